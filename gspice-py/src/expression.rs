@@ -1,11 +1,245 @@
-use core::fmt;
+use std::collections::HashMap;
+use std::sync::RwLockReadGuard;
 
-use pyo3::{exceptions::PyException, prelude::*, types::PyType};
+use pyo3::{
+    exceptions::{
+        PyArithmeticError, PyAttributeError, PyIOError, PyIndexError, PyKeyError, PyRuntimeError,
+        PyTypeError, PyValueError,
+    },
+    prelude::*,
+    types::{PyDict, PySlice, PyType},
+};
 
-use super::{autograd::Grad, impls::fmt_vec, Expression, ScalarTensor, TensorRef};
+#[pyclass]
+pub struct Tensor(gspice::Tensor);
+
+/// Typed Python exception of a [`gspice::Error`]:
+/// `ValueError` for the lengths and the invalid inputs, `IOError` for the files, `IndexError` for the indices,
+/// `ArithmeticError` for the values out of their domain, and `RuntimeError` for the internal states
+fn py_err(e: gspice::Error) -> PyErr {
+    use gspice::Error;
+    let msg = e.to_string();
+    match e {
+        Error::LengthMismatch { .. }
+        | Error::EmptyTensor
+        | Error::SizeLimit { .. }
+        | Error::InvalidPwl(_)
+        | Error::InvalidSpline(_)
+        | Error::InvalidHistogram(_)
+        | Error::InvalidBounds(_)
+        | Error::InvalidSharpness(_)
+        | Error::CapturedConst { .. }
+        | Error::InvalidGrid(_)
+        | Error::InvalidWindow(_)
+        | Error::InvalidRegistration(_)
+        | Error::InvalidSnapshot(_)
+        | Error::InvalidRaw(_)
+        | Error::InvalidCheckpoint(_)
+        | Error::CheckpointMismatch { .. }
+        | Error::UnknownOp { .. }
+        | Error::NotClose { .. } => PyValueError::new_err(msg),
+        Error::Io(_) => PyIOError::new_err(msg),
+        Error::IndexOutOfBounds { .. } => PyIndexError::new_err(msg),
+        Error::OutOfBounds { .. } | Error::NoCrossing { .. } | Error::AssertViolation { .. } => {
+            PyArithmeticError::new_err(msg)
+        }
+        Error::PoisonedLock
+        | Error::NonDifferentiable { .. }
+        | Error::GraphTooDeep { .. }
+        | Error::Cycle { .. } => PyRuntimeError::new_err(msg),
+    }
+}
+
+/// Read the values, `RuntimeError` when the lock is poisoned
+#[inline]
+fn read_values(tensor: &gspice::Tensor) -> PyResult<RwLockReadGuard<'_, Vec<f64>>> {
+    tensor
+        .values()
+        .read()
+        .map_err(|_| py_err(gspice::Error::PoisonedLock))
+}
+
+/// `ArithmeticError` unless the values of a logic input are within `[0, 1]`,
+/// then [mark](gspice::Expression::mark_logic) it as logic
+fn check_logic(expr: &gspice::Expression, op: &str) -> PyResult<()> {
+    let in_range = |x: &f64| (0.0..=1.0).contains(x);
+    let valid = match expr {
+        gspice::Expression::Const(x) => in_range(x),
+        gspice::Expression::Tensor(tensor) => read_values(tensor)?.iter().all(in_range),
+    };
+    if valid {
+        expr.mark_logic();
+        Ok(())
+    } else {
+        Err(PyArithmeticError::new_err(format!(
+            "{op} input out of the logic range [0, 1]"
+        )))
+    }
+}
+
+/// Index of a Python sequence, negative from the end
+#[inline]
+fn normalize_index(index: isize, len: usize) -> PyResult<usize> {
+    let normalized = if index < 0 {
+        index + len as isize
+    } else {
+        index
+    };
+    if (0..len as isize).contains(&normalized) {
+        Ok(normalized as usize)
+    } else {
+        Err(PyIndexError::new_err(format!(
+            "index {index} out of range for length-{len} tensor"
+        )))
+    }
+}
+
+#[pymethods]
+impl Tensor {
+    #[inline]
+    fn __len__(&self) -> PyResult<usize> {
+        Ok(read_values(&self.0)?.len())
+    }
+    /// `tensor[i]` as a float, `tensor[start:stop:step]` as a list
+    fn __getitem__(&self, py: Python<'_>, index: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let values = read_values(&self.0)?;
+        if let Ok(slice) = index.downcast::<PySlice>() {
+            let indices = slice.indices(values.len() as isize)?;
+            let sliced: Vec<f64> = (0..indices.slicelength)
+                .map(|i| values[(indices.start + i as isize * indices.step) as usize])
+                .collect();
+            Ok(sliced.into_py(py))
+        } else {
+            let index = normalize_index(index.extract()?, values.len())?;
+            Ok(values[index].into_py(py))
+        }
+    }
+    /// Iterate over a snapshot of the values
+    #[inline]
+    fn __iter__(&self) -> PyResult<TensorIter> {
+        Ok(TensorIter {
+            values: read_values(&self.0)?.clone(),
+            index: 0,
+        })
+    }
+    #[inline]
+    fn __contains__(&self, value: f64) -> PyResult<bool> {
+        Ok(read_values(&self.0)?.contains(&value))
+    }
+    /// `min`, `max`, `mean`, `l2`, `n_nan` and `n_inf` of the values in one pass,
+    /// cached until the next update, see [`gspice::Tensor::stats`]
+    #[inline]
+    fn stats(&self) -> TensorStats {
+        TensorStats(self.0.stats())
+    }
+    /// Need [`before_update`] before calling this
+    ///
+    /// Need [`Expression::value`](Expression::value) after calling this
+    ///
+    /// `tensor[indices[i]] = values[i]`, see [`update_with`](gspice::Tensor::update_with).
+    /// Raise `IndexError` for an out-of-range index and `ValueError` for different lengths,
+    /// the values are untouched then
+    fn update_at(&self, indices: Vec<isize>, values: Vec<f64>) -> PyResult<()> {
+        if indices.len() != values.len() {
+            return Err(PyValueError::new_err(format!(
+                "{} indices for {} values",
+                indices.len(),
+                values.len()
+            )));
+        }
+        let len = self.__len__()?;
+        let indices = indices
+            .into_iter()
+            .map(|index| normalize_index(index, len))
+            .collect::<PyResult<Vec<usize>>>()?;
+        self.0.update_with(|tensor| {
+            for (index, value) in indices.into_iter().zip(values) {
+                tensor[index] = value;
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Summary of the values of a [`Tensor`], see [`gspice::TensorStats`]
+#[pyclass(frozen)]
+#[derive(Clone, Copy)]
+pub struct TensorStats(gspice::TensorStats);
+
+#[pymethods]
+impl TensorStats {
+    #[getter]
+    fn min(&self) -> f64 {
+        self.0.min
+    }
+    #[getter]
+    fn max(&self) -> f64 {
+        self.0.max
+    }
+    #[getter]
+    fn mean(&self) -> f64 {
+        self.0.mean
+    }
+    #[getter]
+    fn l2(&self) -> f64 {
+        self.0.l2
+    }
+    #[getter]
+    fn n_nan(&self) -> usize {
+        self.0.n_nan
+    }
+    #[getter]
+    fn n_inf(&self) -> usize {
+        self.0.n_inf
+    }
+    /// `{field: value}`, like `dataclasses.asdict`
+    fn asdict(&self, py: Python<'_>) -> PyResult<Py<PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("min", self.0.min)?;
+        dict.set_item("max", self.0.max)?;
+        dict.set_item("mean", self.0.mean)?;
+        dict.set_item("l2", self.0.l2)?;
+        dict.set_item("n_nan", self.0.n_nan)?;
+        dict.set_item("n_inf", self.0.n_inf)?;
+        Ok(dict.unbind())
+    }
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+    fn __repr__(&self) -> String {
+        let gspice::TensorStats {
+            min,
+            max,
+            mean,
+            l2,
+            n_nan,
+            n_inf,
+        } = self.0;
+        format!(
+            "TensorStats(min={min}, max={max}, mean={mean}, l2={l2}, n_nan={n_nan}, n_inf={n_inf})"
+        )
+    }
+}
 
 #[pyclass]
-struct Tensor(gspice::Tensor);
+struct TensorIter {
+    values: Vec<f64>,
+    index: usize,
+}
+
+#[pymethods]
+impl TensorIter {
+    #[inline]
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    #[inline]
+    fn __next__(&mut self) -> Option<f64> {
+        let value = self.values.get(self.index).copied();
+        self.index += 1;
+        value
+    }
+}
 
 #[pyclass]
 pub struct TensorRef(gspice::TensorRef);
@@ -21,35 +255,38 @@ impl TensorRef {
     pub fn assign(&self, values: Vec<f64>) {
         self.0.assign(values);
     }
+    /// The underlying tensor
+    #[inline]
+    fn tensor(&self) -> Tensor {
+        Tensor(self.0.tensor().clone())
+    }
+    /// Need [`before_update`] before calling this
+    ///
+    /// Need [`Expression::value`](Expression::value) after calling this
+    ///
+    /// Tensor\[i\] += call_back(grad\[i\])
+    ///
+    /// Raise `TypeError` when `call_back` is not callable, the first exception of `call_back`,
+    /// or `ValueError` for a length mismatch, the tensor is untouched then
     fn update(&self, grad: &Grad, call_back: Bound<'_, PyAny>) -> PyResult<()> {
-        if call_back.is_callable() {
-            let f = move |x: &f64| -> PyResult<f64> {
-                // Acquire the GIL
-                Python::with_gil(|py| {
-                    // Convert the Rust f64 to a Python object
-                    let arg = x.to_object(py);
-                    // Call the Python function with the argument
-                    let result = call_back.call1((arg,))?;
-                    // Try to extract the result as f64
-                    let output: f64 = result.extract()?;
-                    Ok(output)
-                })
-            };
-            f(&0.0)?;
-            self.update_callback(&grad, |x| f(x).unwrap());
-            Ok(())
-        } else {
-            Err(PyException::new_err("Provided object is not callable"))
+        if !call_back.is_callable() {
+            return Err(PyTypeError::new_err("Provided object is not callable"));
         }
+        let delta = grad
+            .0
+            .iter()
+            .map(|x| call_back.call1((*x,))?.extract())
+            .collect::<PyResult<Vec<f64>>>()?;
+        self.0.try_update(&delta).map_err(py_err)
     }
 }
 
 #[pyclass]
-struct Grad(gspice::Grad);
+pub struct Grad(gspice::Grad);
 #[pymethods]
 impl Grad {
     fn value(&self) -> Vec<f64> {
-        self.0.clone()
+        self.0.to_vec()
     }
     fn __repr__(&self) -> String {
         self.0.to_string()
@@ -58,16 +295,18 @@ impl Grad {
 
 #[pyclass]
 #[derive(Debug)]
-struct GradStore(gspice::GradStore);
+pub struct GradStore(gspice::GradStore);
 
 #[pymethods]
 impl GradStore {
     /// Remove & take the gradient tensor associated with the given tensor-reference
-    pub fn take(&mut self, tensor_ref: &TensorRef) -> Option<Grad> {
-        if let Some(grad_id) = tensor_ref.0.grad_id() {
-            self.0.remove(grad_id)
+    ///
+    /// Raise `ValueError` when the tensor is not with gradient
+    pub fn take(&mut self, tensor_ref: &TensorRef) -> PyResult<Option<Grad>> {
+        if tensor_ref.0.grad_id().is_some() {
+            Ok(self.0.remove(&tensor_ref.0).map(Grad))
         } else {
-            panic!("The tensor is not with gradient")
+            Err(PyValueError::new_err("The tensor is not with gradient"))
         }
     }
 }
@@ -78,12 +317,12 @@ impl Expression {
     /// You need [self.value](Expression::value) before
     /// run [self.backward](Expression::backward) to update its compute graph's value
     fn backward(&self) -> GradStore {
-        GradStore(self.backward())
+        GradStore(self.0.backward())
     }
 }
 
 #[pyclass]
-struct Expression(gspice::Expression);
+pub struct Expression(gspice::Expression);
 
 #[pymethods]
 impl Expression {
@@ -97,19 +336,22 @@ impl Expression {
     #[classmethod]
     #[inline]
     fn py_tensor(_cls: &Bound<'_, PyType>, values: Vec<f64>, need_grad: bool) -> (Self, TensorRef) {
-        Self::tensor(values, need_grad)
+        let (expr, tensor_ref) = gspice::Expression::tensor(values, need_grad);
+        (Self(expr), TensorRef(tensor_ref))
     }
     #[pyo3(name = "zeros")]
     #[classmethod]
     #[inline]
     fn py_zeros(_cls: &Bound<'_, PyType>, len: usize, need_grad: bool) -> (Self, TensorRef) {
-        Self::zeros(len, need_grad)
+        let (expr, tensor_ref) = gspice::Expression::zeros(len, need_grad);
+        (Self(expr), TensorRef(tensor_ref))
     }
     #[pyo3(name = "ones")]
     #[classmethod]
     #[inline]
     fn py_ones(_cls: &Bound<'_, PyType>, len: usize, need_grad: bool) -> (Self, TensorRef) {
-        Self::ones(len, need_grad)
+        let (expr, tensor_ref) = gspice::Expression::ones(len, need_grad);
+        (Self(expr), TensorRef(tensor_ref))
     }
     #[pyo3(name = "rand_uniform")]
     #[classmethod]
@@ -120,8 +362,14 @@ impl Expression {
         lower: f64,
         upper: f64,
         need_grad: bool,
-    ) -> (Self, TensorRef) {
-        Self::rand_uniform(len, lower, upper, need_grad)
+    ) -> PyResult<(Self, TensorRef)> {
+        if !(lower < upper && lower.is_finite() && upper.is_finite()) {
+            return Err(PyArithmeticError::new_err(format!(
+                "empty uniform range [{lower}, {upper})"
+            )));
+        }
+        let (expr, tensor_ref) = gspice::Expression::rand_uniform(len, lower, upper, need_grad);
+        Ok((Self(expr), TensorRef(tensor_ref)))
     }
     #[pyo3(name = "rand_bernoulli")]
     #[classmethod]
@@ -132,19 +380,35 @@ impl Expression {
         p: f64,
         need_grad: bool,
     ) -> (Self, TensorRef) {
-        Self::rand_bernoulli(len, p, need_grad)
+        let (expr, tensor_ref) = gspice::Expression::rand_bernoulli(len, p, need_grad);
+        (Self(expr), TensorRef(tensor_ref))
     }
     #[pyo3(name = "value")]
     #[inline]
-    fn py_value<'a>(&'a self) -> PyScalarTensor {
-        match self.recompute().into() {
-            ScalarTensor::Scalar(x) => PyScalarTensor::Scalar(*x),
-            ScalarTensor::Tensor(tensor) => PyScalarTensor::Tensor(tensor.read().unwrap().clone()),
-        }
+    fn py_value<'a>(&'a self) -> PyResult<PyScalarTensor> {
+        Ok(match self.0.value() {
+            gspice::ScalarTensor::Scalar(x) => PyScalarTensor::Scalar(*x),
+            gspice::ScalarTensor::Tensor(tensor) => PyScalarTensor::Tensor(
+                tensor
+                    .read()
+                    .map_err(|_| py_err(gspice::Error::PoisonedLock))?
+                    .clone(),
+            ),
+        })
     }
     #[inline]
     fn __repr__(&self) -> String {
-        self.to_string()
+        self.0.to_string()
+    }
+    /// Length of a tensor expression, raise `TypeError` for a constant
+    #[inline]
+    fn __len__(&self) -> PyResult<usize> {
+        match &self.0 {
+            gspice::Expression::Tensor(tensor) => Ok(read_values(tensor)?.len()),
+            gspice::Expression::Const(_) => {
+                Err(PyTypeError::new_err("a constant expression has no len()"))
+            }
+        }
     }
 }
 
@@ -154,16 +418,7 @@ impl PyScalarTensor {
     fn __repr__(&self) -> String {
         match self {
             Self::Scalar(x) => format!("Const({x})"),
-            Self::Tensor(tensor) => {
-                struct T<'a>(&'a [f64]);
-                impl<'a> fmt::Display for T<'a> {
-                    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                        write!(f, "Tensor")?;
-                        fmt_vec(self.0, f)
-                    }
-                }
-                T(&tensor).to_string()
-            }
+            Self::Tensor(tensor) => format!("Tensor({tensor:?})"),
         }
     }
 }
@@ -171,44 +426,47 @@ impl PyScalarTensor {
 #[pymethods]
 impl Expression {
     #[inline]
-    fn __add__(&self, rhs: &Self) -> Self {
+    fn __add__(&self, rhs: &Self) -> PyResult<Self> {
         self.add(rhs)
     }
     #[inline]
-    fn __sub__(&self, rhs: &Self) -> Self {
+    fn __sub__(&self, rhs: &Self) -> PyResult<Self> {
         self.sub(rhs)
     }
     #[inline]
-    fn __mul__(&self, rhs: &Self) -> Self {
+    fn __mul__(&self, rhs: &Self) -> PyResult<Self> {
         self.mul(rhs)
     }
     #[inline]
-    fn __div__(&self, rhs: &Self) -> Self {
+    fn __truediv__(&self, rhs: &Self) -> PyResult<Self> {
         self.div(rhs)
     }
-    // TODO: why it needs 2 args
+    /// `self ** rhs`, the modulo of the ternary `pow` is not supported
     #[inline]
-    fn __pow__(&self, rhs: &Self, _mod: bool) -> Self {
+    fn __pow__(&self, rhs: &Self, modulo: Option<&Bound<'_, PyAny>>) -> PyResult<Self> {
+        if modulo.is_some() {
+            return Err(PyTypeError::new_err("pow() with a modulo is not supported"));
+        }
         self.pow(rhs)
     }
     #[inline]
-    fn __max__(&self, rhs: &Self) -> Self {
+    fn __max__(&self, rhs: &Self) -> PyResult<Self> {
         self.max(rhs)
     }
     #[inline]
-    fn __min__(&self, rhs: &Self) -> Self {
+    fn __min__(&self, rhs: &Self) -> PyResult<Self> {
         self.min(rhs)
     }
     #[inline]
-    fn __and__(&self, rhs: &Self) -> Self {
+    fn __and__(&self, rhs: &Self) -> PyResult<Self> {
         self.logic_and(rhs)
     }
     #[inline]
-    fn __or__(&self, rhs: &Self) -> Self {
+    fn __or__(&self, rhs: &Self) -> PyResult<Self> {
         self.logic_or(rhs)
     }
     #[inline]
-    fn __not__(&self) -> Self {
+    fn __not__(&self) -> PyResult<Self> {
         self.logic_not()
     }
     #[inline]
@@ -220,212 +478,299 @@ impl Expression {
         self.abs()
     }
     #[inline]
-    fn __eq__(&self, rhs: &Self) -> Self {
+    fn __eq__(&self, rhs: &Self) -> PyResult<Self> {
         self.eq(rhs)
     }
     #[inline]
-    fn __ne__(&self, rhs: &Self) -> Self {
+    fn __ne__(&self, rhs: &Self) -> PyResult<Self> {
         self.ne(rhs)
     }
     #[inline]
-    fn __le__(&self, rhs: &Self) -> Self {
+    fn __le__(&self, rhs: &Self) -> PyResult<Self> {
         self.le(rhs)
     }
     #[inline]
-    fn __lt__(&self, rhs: &Self) -> Self {
-        self.le(rhs)
+    fn __lt__(&self, rhs: &Self) -> PyResult<Self> {
+        self.lt(rhs)
     }
     #[inline]
-    fn __ge__(&self, rhs: &Self) -> Self {
+    fn __ge__(&self, rhs: &Self) -> PyResult<Self> {
         self.ge(rhs)
     }
     #[inline]
-    fn __gt__(&self, rhs: &Self) -> Self {
+    fn __gt__(&self, rhs: &Self) -> PyResult<Self> {
         self.gt(rhs)
     }
+    /// `on_true` where `self` is `1`, `on_false` where `0`
+    ///
+    /// Raise `ArithmeticError` when `self` is out of the logic range `[0, 1]`,
+    /// and `ValueError` for a length mismatch
     #[inline]
-    pub fn cond(&self, on_true: &Self, on_false: &Self) -> Self {}
+    pub fn cond(&self, on_true: &Self, on_false: &Self) -> PyResult<Self> {
+        check_logic(&self.0, "cond")?;
+        self.0
+            .try_cond(&on_true.0, &on_false.0)
+            .map(Self)
+            .map_err(py_err)
+    }
 }
 
 #[pymethods]
 impl Expression {
     #[inline]
     pub fn neg(&self) -> Self {
-        Self::unary_op::<Neg>(&self)
+        Self(self.0.neg())
     }
     #[inline]
     pub fn sin(&self) -> Self {
-        Self::unary_op::<Sin>(&self)
+        Self(self.0.sin())
     }
     #[inline]
     pub fn cos(&self) -> Self {
-        Self::unary_op::<Cos>(&self)
+        Self(self.0.cos())
     }
     #[inline]
     pub fn tanh(&self) -> Self {
-        Self::unary_op::<Tanh>(&self)
+        Self(self.0.tanh())
     }
     #[inline]
     pub fn tan(&self) -> Self {
-        Self::unary_op::<Tan>(&self)
+        Self(self.0.tan())
     }
     #[inline]
     pub fn ceil(&self) -> Self {
-        Self::unary_op::<Ceil>(&self)
+        Self(self.0.ceil())
     }
     #[inline]
     pub fn floor(&self) -> Self {
-        Self::unary_op::<Floor>(&self)
+        Self(self.0.floor())
     }
     #[inline]
     pub fn round(&self) -> Self {
-        Self::unary_op::<Round>(&self)
+        Self(self.0.round())
     }
     #[inline]
     pub fn sign(&self) -> Self {
-        Self::unary_op::<Sign>(&self)
+        Self(self.0.sign())
     }
     #[inline]
     pub fn sqrt(&self) -> Self {
-        Self::unary_op::<Sqrt>(&self)
+        Self(self.0.sqrt())
     }
     #[inline]
     pub fn sqr(&self) -> Self {
-        Self::unary_op::<Sqr>(&self)
+        Self(self.0.sqr())
     }
     #[inline]
     pub fn cubic(&self) -> Self {
-        Self::unary_op::<Cubic>(&self)
+        Self(self.0.cubic())
     }
     #[inline]
     pub fn log(&self) -> Self {
-        Self::unary_op::<Log>(&self)
+        Self(self.0.log())
     }
     #[inline]
     pub fn exp(&self) -> Self {
-        Self::unary_op::<Exp>(&self)
+        Self(self.0.exp())
     }
     #[inline]
     pub fn abs(&self) -> Self {
-        Self::unary_op::<Abs>(&self)
+        Self(self.0.abs())
     }
     #[inline]
     pub fn erf(&self) -> Self {
-        Self::unary_op::<Erf>(&self)
+        Self(self.0.erf())
     }
     #[inline]
-    pub fn logic_not(&self) -> Self {
-        Self::unary_op::<LogicNot>(&self)
+    pub fn logic_not(&self) -> PyResult<Self> {
+        check_logic(&self.0, "LogicNot")?;
+        Ok(Self(self.0.logic_not()))
     }
 }
 
 #[pymethods]
 impl Expression {
+    /// `0` below `x0`, `1` above `x1`, and the C1 ramp `3t² - 2t³` between
+    #[inline]
+    ///
+    /// Raise `ArithmeticError` unless `x0 < x1`
+    pub fn smoothstep_range(&self, x0: f64, x1: f64) -> PyResult<Self> {
+        // NaN bounds are rejected as well
+        if x0.is_nan() || x1.is_nan() || x0 >= x1 {
+            return Err(PyArithmeticError::new_err(format!(
+                "smoothstep range needs x0 < x1, got [{x0}, {x1}]"
+            )));
+        }
+        Ok(Self(self.0.smoothstep_range(x0, x1)))
+    }
+    /// Gaussian pulse `exp(-(x - center)²/(2σ²))`
+    #[inline]
+    ///
+    /// Raise `ArithmeticError` unless `σ > 0`
+    pub fn gauss_pulse(&self, center: f64, sigma: f64) -> PyResult<Self> {
+        // a NaN sigma is rejected as well
+        if sigma.is_nan() || sigma <= 0.0 {
+            return Err(PyArithmeticError::new_err(format!(
+                "gauss pulse needs sigma > 0, got {sigma}"
+            )));
+        }
+        Ok(Self(self.0.gauss_pulse(center, sigma)))
+    }
+}
+
+#[pymethods]
+impl Expression {
+    #[inline]
+    pub fn add(&self, rhs: &Self) -> PyResult<Self> {
+        self.0.try_add(&rhs.0).map(Self).map_err(py_err)
+    }
     #[inline]
-    pub fn add(&self, rhs: &Self) -> Self {
-        self.binary_op::<Add>(rhs)
+    pub fn sub(&self, rhs: &Self) -> PyResult<Self> {
+        self.0.try_sub(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn sub(&self, rhs: &Self) -> Self {
-        self.binary_op::<Sub>(rhs)
+    pub fn mul(&self, rhs: &Self) -> PyResult<Self> {
+        self.0.try_mul(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn mul(&self, rhs: &Self) -> Self {
-        self.binary_op::<Mul>(rhs)
+    pub fn div(&self, rhs: &Self) -> PyResult<Self> {
+        self.0.try_div(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn div(&self, rhs: &Self) -> Self {
-        self.binary_op::<Div>(rhs)
+    pub fn pow(&self, rhs: &Self) -> PyResult<Self> {
+        self.0.try_pow(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn pow(&self, rhs: &Self) -> Self {
-        self.binary_op::<Pow>(rhs)
+    pub fn min(&self, rhs: &Self) -> PyResult<Self> {
+        self.0.try_min(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn min(&self, rhs: &Self) -> Self {
-        self.binary_op::<Min>(rhs)
+    pub fn max(&self, rhs: &Self) -> PyResult<Self> {
+        self.0.try_max(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn max(&self, rhs: &Self) -> Self {
-        self.binary_op::<Max>(rhs)
+    pub fn logic_and(&self, rhs: &Self) -> PyResult<Self> {
+        check_logic(&self.0, "LogicAnd")?;
+        check_logic(&rhs.0, "LogicAnd")?;
+        self.0.try_logic_and(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn logic_and(&self, rhs: &Self) -> Self {
-        self.binary_op::<LogicAnd>(rhs)
+    pub fn logic_or(&self, rhs: &Self) -> PyResult<Self> {
+        check_logic(&self.0, "LogicOr")?;
+        check_logic(&rhs.0, "LogicOr")?;
+        self.0.try_logic_or(&rhs.0).map(Self).map_err(py_err)
     }
     #[inline]
-    pub fn logic_or(&self, rhs: &Self) -> Self {
-        self.binary_op::<LogicOr>(rhs)
+    pub fn logic_xor(&self, rhs: &Self) -> PyResult<Self> {
+        check_logic(&self.0, "LogicXor")?;
+        check_logic(&rhs.0, "LogicXor")?;
+        self.0.try_logic_xor(&rhs.0).map(Self).map_err(py_err)
+    }
+    #[inline]
+    pub fn logic_nand(&self, rhs: &Self) -> PyResult<Self> {
+        check_logic(&self.0, "LogicNand")?;
+        check_logic(&rhs.0, "LogicNand")?;
+        self.0.try_logic_nand(&rhs.0).map(Self).map_err(py_err)
+    }
+    #[inline]
+    pub fn logic_nor(&self, rhs: &Self) -> PyResult<Self> {
+        check_logic(&self.0, "LogicNor")?;
+        check_logic(&rhs.0, "LogicNor")?;
+        self.0.try_logic_nor(&rhs.0).map(Self).map_err(py_err)
     }
 }
 
 #[pymethods]
 impl Expression {
     #[inline]
-    pub fn eq(&self, rhs: &Self) -> Self {
-        self.discrete_binary_op::<Eq>(rhs, GradMethod::Discrete)
+    pub fn eq(&self, rhs: &Self) -> PyResult<Self> {
+        self.try_cmp(
+            rhs,
+            gspice::DiscreteBinaryOp::Eq,
+            gspice::GradMethod::Discrete,
+        )
     }
     #[inline]
-    pub fn ne(&self, rhs: &Self) -> Self {
-        self.discrete_binary_op::<Ne>(rhs, GradMethod::Discrete)
+    pub fn ne(&self, rhs: &Self) -> PyResult<Self> {
+        self.try_cmp(
+            rhs,
+            gspice::DiscreteBinaryOp::Ne,
+            gspice::GradMethod::Discrete,
+        )
     }
     #[inline]
-    pub fn le(&self, rhs: &Self) -> Self {
-        self.discrete_binary_op::<Le>(rhs, GradMethod::Discrete)
+    pub fn le(&self, rhs: &Self) -> PyResult<Self> {
+        self.try_cmp(
+            rhs,
+            gspice::DiscreteBinaryOp::Le,
+            gspice::GradMethod::Discrete,
+        )
     }
     #[inline]
-    pub fn ge(&self, rhs: &Self) -> Self {
-        self.discrete_binary_op::<Ge>(rhs, GradMethod::Discrete)
+    pub fn ge(&self, rhs: &Self) -> PyResult<Self> {
+        self.try_cmp(
+            rhs,
+            gspice::DiscreteBinaryOp::Ge,
+            gspice::GradMethod::Discrete,
+        )
     }
     #[inline]
-    pub fn lt(&self, rhs: &Self) -> Self {
-        self.discrete_binary_op::<Lt>(rhs, GradMethod::Discrete)
+    pub fn lt(&self, rhs: &Self) -> PyResult<Self> {
+        self.try_cmp(
+            rhs,
+            gspice::DiscreteBinaryOp::Lt,
+            gspice::GradMethod::Discrete,
+        )
     }
     #[inline]
-    pub fn gt(&self, rhs: &Self) -> Self {
-        self.discrete_binary_op::<Gt>(rhs, GradMethod::Discrete)
+    pub fn gt(&self, rhs: &Self) -> PyResult<Self> {
+        self.try_cmp(
+            rhs,
+            gspice::DiscreteBinaryOp::Gt,
+            gspice::GradMethod::Discrete,
+        )
     }
     /// `eq(a,b) = sigmoid(a, b, k) = e^(-k (a - b)^2)`
     ///
     /// **only activate when graident is required!**
     #[inline]
-    pub fn eq_sigmoid(&self, rhs: &Self, k: f64) -> Self {
-        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_sigmoid(k))
+    pub fn eq_sigmoid(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Eq, sigmoid(k)?)
     }
     /// `ne(a,b) = 1- sigmoid(a, b, k) = 1-e^(-k (a - b)^2)`
     ///
     /// **only activate when graident is required!**
     #[inline]
-    pub fn ne_sigmoid(&self, rhs: &Self, k: f64) -> Self {
-        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_sigmoid(k))
+    pub fn ne_sigmoid(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ne, sigmoid(k)?)
     }
     /// `le(a,b) = 1 / (1 + e^(k(a - b)))`
     ///
     /// **only activate when graident is required!**
     #[inline]
-    pub fn le_sigmoid(&self, rhs: &Self, k: f64) -> Self {
-        self.discrete_binary_op::<Le>(rhs, GradMethod::new_sigmoid(k))
+    pub fn le_sigmoid(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Le, sigmoid(k)?)
     }
     /// `ge(a,b) = 1 / (1 + e^(-k(a - b)))`
     ///
     /// **only activate when graident is required!**
     #[inline]
-    pub fn ge_sigmoid(&self, rhs: &Self, k: f64) -> Self {
-        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_sigmoid(k))
+    pub fn ge_sigmoid(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ge, sigmoid(k)?)
     }
     /// `lt(a,b) = 1 / (1 + e^(k(a - b)))`
     ///
     /// **only activate when graident is required!**
     #[inline]
-    pub fn lt_sigmoid(&self, rhs: &Self, k: f64) -> Self {
-        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_sigmoid(k))
+    pub fn lt_sigmoid(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Lt, sigmoid(k)?)
     }
     /// `gt(a,b) = 1 / (1 + e^(-k(a - b)))`
     ///
     /// **only activate when graident is required!**
     #[inline]
-    pub fn gt_sigmoid(&self, rhs: &Self, k: f64) -> Self {
-        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_sigmoid(k))
+    pub fn gt_sigmoid(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Gt, sigmoid(k)?)
     }
     /// `1 - |a - b|/ε`    when  `|a - b| < ε`
     /// ``` text
@@ -438,8 +783,8 @@ impl Expression {
     /// ```
     /// **only activate when graident is required!**
     #[inline]
-    pub fn eq_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_linear(epsilon))
+    pub fn eq_linear(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Eq, linear(epsilon)?)
     }
     /// |`a - b|/ε`    when  `|a - b| < ε`
     /// ``` text
@@ -452,8 +797,8 @@ impl Expression {
     /// ```
     /// **only activate when graident is required!**
     #[inline]
-    pub fn ne_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_linear(epsilon))
+    pub fn ne_linear(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ne, linear(epsilon)?)
     }
     /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
     /// ``` text
@@ -466,8 +811,8 @@ impl Expression {
     /// ```
     /// **only activate when graident is required!**
     #[inline]
-    pub fn le_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Le>(rhs, GradMethod::new_linear(epsilon))
+    pub fn le_linear(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Le, linear(epsilon)?)
     }
     /// `1/2 + (a-b)/2ε`    when  `|a - b| < ε`
     /// ``` text
@@ -480,8 +825,8 @@ impl Expression {
     /// ```
     /// **only activate when graident is required!**
     #[inline]
-    pub fn ge_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_linear(epsilon))
+    pub fn ge_linear(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ge, linear(epsilon)?)
     }
     /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
     /// ``` text
@@ -494,8 +839,8 @@ impl Expression {
     /// ```
     /// **only activate when graident is required!**
     #[inline]
-    pub fn lt_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_linear(epsilon))
+    pub fn lt_linear(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Lt, linear(epsilon)?)
     }
     /// `1/2 + (a-b)/2ε`    when  `|a - b| < ε`
     /// ``` text
@@ -508,13 +853,352 @@ impl Expression {
     /// ```
     /// **only activate when graident is required!**
     #[inline]
-    pub fn gt_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_linear(epsilon))
+    pub fn gt_linear(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Gt, linear(epsilon)?)
+    }
+    /// `eq(a,b) = 1 - tanh²(k(a - b))`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn eq_tanh(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Eq, tanh(k)?)
+    }
+    /// `ne(a,b) = tanh²(k(a - b))`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ne_tanh(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ne, tanh(k)?)
+    }
+    /// `le(a,b) = (1 - tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn le_tanh(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Le, tanh(k)?)
+    }
+    /// `ge(a,b) = (1 + tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ge_tanh(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ge, tanh(k)?)
+    }
+    /// `lt(a,b) = (1 - tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn lt_tanh(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Lt, tanh(k)?)
+    }
+    /// `gt(a,b) = (1 + tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn gt_tanh(&self, rhs: &Self, k: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Gt, tanh(k)?)
+    }
+    /// `1 - (3u² - 2u³)`, `u = |a - b|/ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn eq_smoothstep(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Eq, smoothstep(epsilon)?)
+    }
+    /// `3u² - 2u³`, `u = |a - b|/ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ne_smoothstep(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ne, smoothstep(epsilon)?)
+    }
+    /// `1 - (3t² - 2t³)`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn le_smoothstep(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Le, smoothstep(epsilon)?)
+    }
+    /// `3t² - 2t³`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ge_smoothstep(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Ge, smoothstep(epsilon)?)
+    }
+    /// `1 - (3t² - 2t³)`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn lt_smoothstep(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Lt, smoothstep(epsilon)?)
+    }
+    /// `3t² - 2t³`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn gt_smoothstep(&self, rhs: &Self, epsilon: f64) -> PyResult<Self> {
+        self.try_cmp(rhs, gspice::DiscreteBinaryOp::Gt, smoothstep(epsilon)?)
+    }
+}
+
+#[inline]
+fn sigmoid(k: f64) -> PyResult<gspice::GradMethod> {
+    gspice::GradMethod::try_sigmoid(k).map_err(py_err)
+}
+#[inline]
+fn linear(epsilon: f64) -> PyResult<gspice::GradMethod> {
+    gspice::GradMethod::try_linear(epsilon).map_err(py_err)
+}
+#[inline]
+fn tanh(k: f64) -> PyResult<gspice::GradMethod> {
+    gspice::GradMethod::try_tanh(k).map_err(py_err)
+}
+#[inline]
+fn smoothstep(epsilon: f64) -> PyResult<gspice::GradMethod> {
+    gspice::GradMethod::try_smoothstep(epsilon).map_err(py_err)
+}
+
+impl Expression {
+    /// Comparison with the length check, `ValueError` for a length mismatch
+    #[inline]
+    fn try_cmp(
+        &self,
+        rhs: &Self,
+        op: gspice::DiscreteBinaryOp,
+        method: gspice::GradMethod,
+    ) -> PyResult<Self> {
+        self.0.try_cmp(&rhs.0, op, method).map(Self).map_err(py_err)
+    }
+}
+
+/// Smoothing of the comparisons' gradient, see [`Expression::cmp`]
+///
+/// The constructors raise `ValueError` unless the sharpness is positive and finite
+///
+/// **only activate when graident is required!**
+#[pyclass(name = "CmpMethod")]
+#[derive(Clone, Debug)]
+pub struct CmpMethod(gspice::GradMethod);
+
+#[pymethods]
+impl CmpMethod {
+    /// No gradient
+    #[classmethod]
+    #[inline]
+    fn discrete(_cls: &Bound<'_, PyType>) -> Self {
+        Self(gspice::GradMethod::Discrete)
+    }
+    /// See [`Expression::eq_sigmoid`] and its family
+    #[classmethod]
+    #[inline]
+    fn sigmoid(_cls: &Bound<'_, PyType>, k: f64) -> PyResult<Self> {
+        sigmoid(k).map(Self)
+    }
+    /// See [`Expression::eq_linear`] and its family
+    #[classmethod]
+    #[inline]
+    fn linear(_cls: &Bound<'_, PyType>, epsilon: f64) -> PyResult<Self> {
+        linear(epsilon).map(Self)
+    }
+    /// See [`Expression::eq_tanh`] and its family
+    #[classmethod]
+    #[inline]
+    fn tanh(_cls: &Bound<'_, PyType>, k: f64) -> PyResult<Self> {
+        tanh(k).map(Self)
+    }
+    /// See [`Expression::eq_smoothstep`] and its family
+    #[classmethod]
+    #[inline]
+    fn smoothstep(_cls: &Bound<'_, PyType>, epsilon: f64) -> PyResult<Self> {
+        smoothstep(epsilon).map(Self)
+    }
+    #[inline]
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+#[pymethods]
+impl Expression {
+    /// Generic comparison, `op` is one of `"eq"`, `"ne"`, `"lt"`, `"le"`, `"gt"`, `"ge"`
+    ///
+    /// Raise `ValueError` for any other `op`
+    #[pyo3(name = "cmp")]
+    #[inline]
+    fn py_cmp(&self, rhs: &Self, op: &str, method: &CmpMethod) -> PyResult<Self> {
+        let op = match op {
+            "eq" => gspice::DiscreteBinaryOp::Eq,
+            "ne" => gspice::DiscreteBinaryOp::Ne,
+            "lt" => gspice::DiscreteBinaryOp::Lt,
+            "le" => gspice::DiscreteBinaryOp::Le,
+            "gt" => gspice::DiscreteBinaryOp::Gt,
+            "ge" => gspice::DiscreteBinaryOp::Ge,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "invalid comparison `{op}`, expected one of eq/ne/lt/le/gt/ge"
+                )))
+            }
+        };
+        self.try_cmp(rhs, op, method.0.clone())
     }
 }
 
+/// Named parameters, see [`parameters`]
+#[pyclass]
+pub struct ParameterSet {
+    /// In the order of the definition
+    names: Vec<String>,
+    params: HashMap<String, (gspice::Expression, gspice::TensorRef)>,
+}
+
+/// Attributes of [`ParameterSet`], which cannot be parameter names
+const PARAMETER_SET_ATTRS: [&str; 5] = ["names", "tensor", "update", "grads", "values"];
+
+/// Define the parameters from `{name: values}` as tensors, in one call
+///
+/// Raise `ValueError` for an empty vector or a name colliding with a `ParameterSet` attribute,
+/// and `TypeError` for a non-string name
 #[pyfunction]
-pub fn before_update() {}
+#[pyo3(signature = (values, need_grad = true))]
+pub fn parameters(values: &Bound<'_, PyDict>, need_grad: bool) -> PyResult<ParameterSet> {
+    let mut names = Vec::with_capacity(values.len());
+    let mut params = HashMap::with_capacity(values.len());
+    for (name, value) in values.iter() {
+        let name: String = name
+            .extract()
+            .map_err(|_| PyTypeError::new_err(format!("parameter name {name} is not a string")))?;
+        let value: Vec<f64> = value.extract()?;
+        if value.is_empty() {
+            return Err(PyValueError::new_err(format!(
+                "parameter `{name}` is empty"
+            )));
+        }
+        if PARAMETER_SET_ATTRS.contains(&name.as_str()) {
+            return Err(PyValueError::new_err(format!(
+                "parameter `{name}` collides with a ParameterSet attribute"
+            )));
+        }
+        params.insert(name.clone(), gspice::Expression::tensor(value, need_grad));
+        names.push(name);
+    }
+    Ok(ParameterSet { names, params })
+}
+
+impl ParameterSet {
+    #[inline]
+    fn get(&self, name: &str) -> PyResult<&(gspice::Expression, gspice::TensorRef)> {
+        self.params
+            .get(name)
+            .ok_or_else(|| PyKeyError::new_err(format!("no parameter `{name}`")))
+    }
+}
+
+#[pymethods]
+impl ParameterSet {
+    /// The names in the order of the definition
+    #[inline]
+    fn names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+    #[inline]
+    fn __len__(&self) -> usize {
+        self.names.len()
+    }
+    #[inline]
+    fn __contains__(&self, name: &str) -> bool {
+        self.params.contains_key(name)
+    }
+    /// `params["name"]`, the expression of the parameter
+    #[inline]
+    fn __getitem__(&self, name: &str) -> PyResult<Expression> {
+        Ok(Expression(self.get(name)?.0.clone()))
+    }
+    /// `params.name`, the expression of the parameter
+    #[inline]
+    fn __getattr__(&self, name: &str) -> PyResult<Expression> {
+        self.get(name)
+            .map(|(expr, _)| Expression(expr.clone()))
+            .map_err(|_| PyAttributeError::new_err(format!("no parameter `{name}`")))
+    }
+    /// The tensor reference of the parameter
+    #[inline]
+    fn tensor(&self, name: &str) -> PyResult<TensorRef> {
+        Ok(TensorRef(self.get(name)?.1.clone()))
+    }
+    /// The current values of all the parameters
+    fn values(&self) -> PyResult<HashMap<String, Vec<f64>>> {
+        self.params
+            .iter()
+            .map(|(name, (_, tensor_ref))| {
+                Ok((name.clone(), read_values(tensor_ref.tensor())?.clone()))
+            })
+            .collect()
+    }
+    /// Add the deltas `{name: delta}` to the parameters in one update,
+    /// i.e., a single [`before_update`] for the batch
+    ///
+    /// Raise `KeyError` for an unknown name and `ValueError` for a length mismatch,
+    /// before any parameter is updated
+    fn update(&self, deltas: HashMap<String, Vec<f64>>) -> PyResult<()> {
+        for (name, delta) in &deltas {
+            let len = read_values(self.get(name)?.1.tensor())?.len();
+            if delta.len() != len {
+                return Err(PyValueError::new_err(format!(
+                    "parameter `{name}` has length {len}, got {} deltas",
+                    delta.len()
+                )));
+            }
+        }
+        gspice::expression::before_update();
+        for (name, delta) in &deltas {
+            self.params[name].1.try_update(delta).map_err(py_err)?;
+        }
+        Ok(())
+    }
+    /// Evaluate `loss`, run the backward once, and return the gradients `{name: np.ndarray}`,
+    /// zeros for the parameters `loss` does not depend on
+    ///
+    /// Raise `ValueError` when the parameters are defined without gradient
+    fn grads(&self, py: Python<'_>, loss: &Expression) -> PyResult<HashMap<String, PyObject>> {
+        let numpy = py.import_bound("numpy")?;
+        _ = loss.0.value();
+        let grads = loss.0.backward();
+        self.params
+            .iter()
+            .map(|(name, (_, tensor_ref))| {
+                if !tensor_ref.requires_grad() {
+                    return Err(PyValueError::new_err(format!(
+                        "parameter `{name}` is defined without gradient"
+                    )));
+                }
+                let grad = match grads.get(tensor_ref) {
+                    Some(grad) => grad.to_vec(),
+                    None => vec![0.0; read_values(tensor_ref.tensor())?.len()],
+                };
+                Ok((name.clone(), numpy.call_method1("array", (grad,))?.unbind()))
+            })
+            .collect()
+    }
+}
+
+/// Need before updating the tensors, see [`gspice::before_update`]
+#[pyfunction]
+pub fn before_update() {
+    gspice::before_update();
+}
 
 #[pyclass(name = "ScalarTensor")]
 #[derive(Clone, Debug)]
@@ -1,4 +1,9 @@
-// mod expression;
+#![allow(
+    clippy::useless_conversion,
+    reason = "the pyo3 0.22 macros convert every PyResult error into a PyErr"
+)]
+
+mod expression;
 
 use pyo3::prelude::*;
 
@@ -16,8 +21,16 @@ struct Ckt {}
 /// import the module.
 #[pymodule(name = "gspice")]
 fn pymodule(m: &Bound<'_, PyModule>) -> PyResult<()> {
-    // m.add_function(wrap_pyfunction!(expression::before_update, m)?)?;
-    // m.add_class::<expression::Expression>()?;
+    m.add_function(wrap_pyfunction!(expression::before_update, m)?)?;
+    m.add_class::<expression::Expression>()?;
+    m.add_class::<expression::CmpMethod>()?;
+    m.add_class::<expression::Tensor>()?;
+    m.add_class::<expression::TensorRef>()?;
+    m.add_class::<expression::TensorStats>()?;
+    m.add_class::<expression::Grad>()?;
+    m.add_class::<expression::GradStore>()?;
+    m.add_class::<expression::ParameterSet>()?;
+    m.add_function(wrap_pyfunction!(expression::parameters, m)?)?;
     m.add_class::<Ckt>()?;
     Ok(())
 }
@@ -0,0 +1,53 @@
+import pytest
+
+from gspice import CmpMethod, Expression
+
+OPS = ["eq", "ne", "lt", "le", "gt", "ge"]
+METHODS = [
+    ("sigmoid", 3.0, CmpMethod.sigmoid),
+    ("linear", 0.5, CmpMethod.linear),
+    ("tanh", 2.0, CmpMethod.tanh),
+    ("smoothstep", 0.5, CmpMethod.smoothstep),
+]
+
+
+def values_and_grads(build):
+    x, x_ref = Expression.tensor([-1.0, -0.2, 0.0, 0.3, 1.5], True)
+    y, y_ref = Expression.tensor([0.0, 0.0, 0.0, 0.1, 1.0], True)
+    f = build(x, y)
+    value = repr(f.value())
+    grads = f.backward()
+    return value, grads.take(x_ref).value(), grads.take(y_ref).value()
+
+
+@pytest.mark.parametrize("op", OPS)
+@pytest.mark.parametrize("suffix,param,method", METHODS)
+def test_cmp_matches_suffix_methods(op, suffix, param, method):
+    generic = values_and_grads(lambda x, y: x.cmp(y, op, method(param)))
+    fixed = values_and_grads(lambda x, y: getattr(x, f"{op}_{suffix}")(y, param))
+    assert generic == fixed
+
+
+@pytest.mark.parametrize("op", OPS)
+def test_cmp_discrete_matches_operator_methods(op):
+    generic = values_and_grads(lambda x, y: x.cmp(y, op, CmpMethod.discrete()))
+    fixed = values_and_grads(lambda x, y: getattr(x, op)(y))
+    assert generic == fixed
+
+
+@pytest.mark.parametrize("op", ["", "Lt", "<", "lte"])
+def test_cmp_invalid_op(op):
+    x, _ = Expression.tensor([0.0, 1.0], True)
+    with pytest.raises(ValueError):
+        x.cmp(x, op, CmpMethod.sigmoid(1.0))
+
+
+@pytest.mark.parametrize("param", [0.0, -0.0, -1.0, float("nan"), float("inf")])
+@pytest.mark.parametrize("suffix,method", [(s, m) for s, _, m in METHODS])
+def test_invalid_sharpness(suffix, method, param):
+    x, _ = Expression.tensor([0.0, 1.0], True)
+    with pytest.raises(ValueError):
+        method(param)
+    for op in OPS:
+        with pytest.raises(ValueError):
+            getattr(x, f"{op}_{suffix}")(x, param)
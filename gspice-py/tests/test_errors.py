@@ -0,0 +1,78 @@
+import pytest
+
+from gspice import CmpMethod, Expression, before_update
+
+
+def mismatched():
+    x, _ = Expression.tensor([1.0, 2.0, 3.0], True)
+    y, _ = Expression.tensor([1.0, 2.0], True)
+    return x, y
+
+
+@pytest.mark.parametrize(
+    "build",
+    [
+        lambda x, y: x + y,
+        lambda x, y: x - y,
+        lambda x, y: x * y,
+        lambda x, y: x.div(y),
+        lambda x, y: x.max(y),
+        lambda x, y: x.lt_sigmoid(y, 1.0),
+        lambda x, y: x.cmp(y, "ge", CmpMethod.linear(0.5)),
+        lambda x, y: x <= y,
+    ],
+)
+def test_length_mismatch_raises_value_error(build):
+    x, y = mismatched()
+    with pytest.raises(ValueError):
+        build(x, y)
+
+
+def test_update_length_mismatch_raises_value_error():
+    x, x_ref = Expression.tensor([1.0, 2.0], True)
+    grads = (x * x).backward()
+    y, y_ref = Expression.tensor([1.0, 2.0, 3.0], True)
+    before_update()
+    with pytest.raises(ValueError):
+        y_ref.update(grads.take(x_ref), lambda grad: -grad)
+    assert list(y_ref.tensor()) == [1.0, 2.0, 3.0]
+
+
+@pytest.mark.parametrize("k", [-1.0, -1e-9, float("nan")])
+def test_invalid_sigmoid_k_raises_value_error(k):
+    x, y = Expression.tensor([0.0, 1.0], True)[0], Expression.constant(0.5)
+    with pytest.raises(ValueError):
+        x.lt_sigmoid(y, k)
+    with pytest.raises(ValueError):
+        CmpMethod.sigmoid(k)
+
+
+@pytest.mark.parametrize(
+    "build",
+    [
+        lambda a, b: a & b,
+        lambda a, b: a | b,
+        lambda a, b: b.logic_xor(a),
+        lambda a, b: a.logic_not(),
+        lambda a, b: a.cond(b, b),
+    ],
+)
+def test_logic_input_out_of_range_raises_arithmetic_error(build):
+    a, _ = Expression.tensor([0.0, 1.5], True)
+    b, _ = Expression.tensor([0.0, 1.0], True)
+    with pytest.raises(ArithmeticError):
+        build(a, b)
+
+
+def test_valid_logic_inputs():
+    a, _ = Expression.tensor([0.0, 1.0], True)
+    b, _ = Expression.tensor([1.0, 1.0], True)
+    (a & b).value()
+    (a | Expression.constant(0.0)).value()
+
+
+def test_take_without_gradient_raises_value_error():
+    x, x_ref = Expression.tensor([1.0], False)
+    grads = (x * x).backward()
+    with pytest.raises(ValueError):
+        grads.take(x_ref)
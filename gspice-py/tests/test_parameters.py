@@ -0,0 +1,54 @@
+import numpy as np
+import pytest
+
+import gspice
+from gspice import Expression
+
+
+def test_two_parameter_optimization():
+    params = gspice.parameters({"a": [0.0, 0.5], "b": [3.0]})
+    assert params.names() == ["a", "b"]
+    assert len(params) == 2 and "a" in params and "c" not in params
+    one, two = Expression.constant(1.0), Expression.constant(2.0)
+    # minimum at a = 1, b = -2
+    loss = (params.a - one) * (params.a - one) + (params["b"] + two) * (params["b"] + two)
+    step = 0.1
+    for _ in range(200):
+        grads = params.grads(loss)
+        assert isinstance(grads["a"], np.ndarray) and grads["a"].shape == (2,)
+        params.update({name: -step * grad for name, grad in grads.items()})
+    values = params.values()
+    np.testing.assert_allclose(values["a"], [1.0, 1.0], atol=1e-9)
+    np.testing.assert_allclose(values["b"], [-2.0], atol=1e-9)
+    assert list(params.tensor("b").tensor()) == values["b"]
+
+
+def test_unused_parameter_has_zero_grad():
+    params = gspice.parameters({"a": [1.0, 2.0], "unused": [5.0, 6.0, 7.0]})
+    grads = params.grads(params.a * params.a)
+    np.testing.assert_allclose(grads["a"], [2.0, 4.0])
+    np.testing.assert_allclose(grads["unused"], np.zeros(3))
+
+
+def test_invalid_definitions():
+    with pytest.raises(ValueError):
+        gspice.parameters({"a": []})
+    with pytest.raises(ValueError):
+        gspice.parameters({"update": [1.0]})
+    with pytest.raises(TypeError):
+        gspice.parameters({1: [1.0]})
+
+
+def test_invalid_access_and_update():
+    params = gspice.parameters({"a": [1.0, 2.0]})
+    with pytest.raises(KeyError):
+        params["b"]
+    with pytest.raises(AttributeError):
+        params.b
+    with pytest.raises(KeyError):
+        params.update({"b": [1.0]})
+    with pytest.raises(ValueError):
+        params.update({"a": [1.0]})
+    assert params.values() == {"a": [1.0, 2.0]}
+    with pytest.raises(ValueError):
+        gspice.parameters({"a": [1.0]}, need_grad=False).grads(params.a)
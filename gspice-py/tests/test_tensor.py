@@ -0,0 +1,102 @@
+import pytest
+
+from gspice import Expression, before_update
+
+VALUES = [0.5, -1.0, 2.0, 3.5, -4.0, 6.0, 7.25]
+
+
+def make_tensor():
+    x, x_ref = Expression.tensor(VALUES, True)
+    return x, x_ref, x_ref.tensor()
+
+
+def test_len_and_iter():
+    x, _, tensor = make_tensor()
+    assert len(tensor) == len(VALUES)
+    assert len(x) == len(VALUES)
+    assert list(tensor) == VALUES
+    assert [v for v in tensor] == VALUES
+
+
+def test_len_of_constant():
+    with pytest.raises(TypeError):
+        len(Expression.constant(1.0))
+
+
+@pytest.mark.parametrize("index", range(-len(VALUES), len(VALUES)))
+def test_getitem_int(index):
+    _, _, tensor = make_tensor()
+    assert tensor[index] == VALUES[index]
+
+
+@pytest.mark.parametrize("index", [len(VALUES), -len(VALUES) - 1, 100])
+def test_getitem_out_of_range(index):
+    _, _, tensor = make_tensor()
+    with pytest.raises(IndexError):
+        tensor[index]
+
+
+@pytest.mark.parametrize(
+    "key",
+    [
+        slice(None),
+        slice(1, 4),
+        slice(-3, None),
+        slice(None, -2),
+        slice(None, None, 2),
+        slice(1, None, 3),
+        slice(None, None, -1),
+        slice(-1, 0, -2),
+        slice(5, 1),
+        slice(-100, 100),
+    ],
+)
+def test_getitem_slice(key):
+    _, _, tensor = make_tensor()
+    assert tensor[key] == VALUES[key]
+
+
+def test_contains():
+    _, _, tensor = make_tensor()
+    assert 3.5 in tensor
+    assert 3.0 not in tensor
+
+
+def test_update_at():
+    _, _, tensor = make_tensor()
+    before_update()
+    tensor.update_at([0, -1], [1.0, 2.0])
+    assert tensor[0] == 1.0 and tensor[-1] == 2.0
+    expected = list(VALUES)
+    expected[0], expected[-1] = 1.0, 2.0
+    assert list(tensor) == expected
+
+
+def test_update_at_invalid():
+    _, _, tensor = make_tensor()
+    with pytest.raises(IndexError):
+        tensor.update_at([0, len(VALUES)], [1.0, 2.0])
+    with pytest.raises(ValueError):
+        tensor.update_at([0, 1], [1.0])
+    assert list(tensor) == VALUES
+
+
+def test_stats():
+    _, _, tensor = make_tensor()
+    stats = tensor.stats()
+    assert stats.min == min(VALUES) and stats.max == max(VALUES)
+    assert stats.mean == pytest.approx(sum(VALUES) / len(VALUES))
+    assert stats.l2 == pytest.approx(sum(v * v for v in VALUES) ** 0.5)
+    assert (stats.n_nan, stats.n_inf) == (0, 0)
+    assert tensor.stats() == stats
+    assert stats.asdict()["max"] == max(VALUES)
+
+
+def test_stats_nan_and_update():
+    _, _, tensor = make_tensor()
+    before_update()
+    tensor.update_at([0, 1, 2], [float("nan"), float("inf"), float("nan")])
+    stats = tensor.stats()
+    assert (stats.n_nan, stats.n_inf) == (2, 1)
+    finite = VALUES[3:]
+    assert stats.min == min(finite) and stats.max == max(finite)
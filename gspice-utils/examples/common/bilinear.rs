@@ -0,0 +1,65 @@
+// The table model of the `custom_op_bilinear` example, also used by the tests,
+// included where `CustomOp` is in scope
+
+/// Table model `z(x, y)` on the grid `xs × ys` (row-major in `x`),
+/// bilinear in each cell and extrapolated from the border cells
+struct Bilinear {
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    z: Vec<f64>,
+}
+
+impl Bilinear {
+    fn new(xs: Vec<f64>, ys: Vec<f64>, f: impl Fn(f64, f64) -> f64) -> Self {
+        let z = xs
+            .iter()
+            .flat_map(|x| ys.iter().map(|y| f(*x, *y)))
+            .collect();
+        Self { xs, ys, z }
+    }
+    /// Cell index and the position in it
+    fn locate(grid: &[f64], x: f64) -> (usize, f64) {
+        let i = grid.partition_point(|g| *g <= x).clamp(1, grid.len() - 1) - 1;
+        (i, (x - grid[i]) / (grid[i + 1] - grid[i]))
+    }
+    /// `(z, dz/dx, dz/dy)`
+    fn eval(&self, x: f64, y: f64) -> (f64, f64, f64) {
+        let ((i, tx), (j, ty)) = (Self::locate(&self.xs, x), Self::locate(&self.ys, y));
+        let n = self.ys.len();
+        let z00 = self.z[i * n + j];
+        let z01 = self.z[i * n + j + 1];
+        let z10 = self.z[(i + 1) * n + j];
+        let z11 = self.z[(i + 1) * n + j + 1];
+        let z = (1.0 - tx) * (1.0 - ty) * z00
+            + (1.0 - tx) * ty * z01
+            + tx * (1.0 - ty) * z10
+            + tx * ty * z11;
+        let dz_dx = ((1.0 - ty) * (z10 - z00) + ty * (z11 - z01)) / (self.xs[i + 1] - self.xs[i]);
+        let dz_dy = ((1.0 - tx) * (z01 - z00) + tx * (z11 - z10)) / (self.ys[j + 1] - self.ys[j]);
+        (z, dz_dx, dz_dy)
+    }
+}
+
+/// The inputs are `[x, y]`, a length-1 input is broadcasted
+impl CustomOp for Bilinear {
+    fn forward(&self, inputs: &[&[f64]], out: &mut Vec<f64>) {
+        let [x, y] = inputs else {
+            panic!("bilinear takes 2 inputs")
+        };
+        let len = x.len().max(y.len());
+        out.extend((0..len).map(|i| self.eval(x[i % x.len()], y[i % y.len()]).0));
+    }
+    fn backward(&self, inputs: &[&[f64]], _res: &[f64], grad: &[f64], grads_out: &mut [Vec<f64>]) {
+        let [x, y] = inputs else {
+            panic!("bilinear takes 2 inputs")
+        };
+        for (i, g) in grad.iter().enumerate() {
+            let (_, dz_dx, dz_dy) = self.eval(x[i % x.len()], y[i % y.len()]);
+            grads_out[0][i % x.len()] += g * dz_dx;
+            grads_out[1][i % y.len()] += g * dz_dy;
+        }
+    }
+    fn name(&self) -> &str {
+        "bilinear"
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use gspice_utils::expression::{before_update, CustomOp, Expression};
+
+include!("common/bilinear.rs");
+
+fn main() {
+    let iter = 200;
+    let step = 0.02;
+    // a tabulated drain current `id(vgs, vds)`
+    let grid = |n: usize| {
+        (0..=n)
+            .map(|i| i as f64 * 2.0 / n as f64)
+            .collect::<Vec<_>>()
+    };
+    let table: Arc<dyn CustomOp> = Arc::new(Bilinear::new(grid(20), grid(20), |vgs, vds| {
+        (vgs - 0.4).max(0.0).powi(2) * (2.0 * vds).tanh()
+    }));
+    let (vgs, vgs_ref) = Expression::tensor(vec![0.75], true);
+    let (vds, _) = Expression::tensor(vec![0.2, 1.0], false);
+    let id = Expression::apply_custom(table, &[vgs.clone(), vds.clone()]);
+    let target = Expression::tensor(vec![0.24, 0.62], false).0;
+    let loss = id.sub(&target).sqr();
+    println!("To fit id(vgs, vds) = [0.24, 0.62] at two vds biases, by the shared vgs");
+    println!("BEGIN\n  vgs {vgs}\n  vds {vds}");
+    // the analytic gradient against a finite difference
+    let grads = loss.backward();
+    let df_dvgs = grads.get(&vgs_ref).unwrap()[0];
+    let h = 1e-6;
+    let loss_at = |x: f64| {
+        before_update();
+        vgs_ref.assign(vec![x]);
+        loss.value().overall_sum()
+    };
+    let fd = (loss_at(0.75 + h) - loss_at(0.75 - h)) / (2.0 * h);
+    _ = loss_at(0.75);
+    println!("d loss / d vgs = {df_dvgs:.6e}, finite difference {fd:.6e}");
+    assert!((df_dvgs - fd).abs() < 1e-6);
+    let mut last = f64::MAX;
+    for i in 0..iter {
+        let new_loss = loss.value().overall_sum();
+        if i % 40 == 0 {
+            assert!(new_loss < last);
+            last = new_loss;
+            println!("iter {i}; loss = {last:5e}");
+        }
+        let grads = loss.backward();
+        let df_dvgs = grads.get(&vgs_ref).unwrap();
+        before_update();
+        vgs_ref.update_iter(df_dvgs.iter().map(|d| -step * d));
+    }
+    let loss = loss.value().overall_sum();
+    println!("iter {iter}; loss = {loss:5e}");
+    println!("END\n  vgs {vgs}\n  id {id}");
+}
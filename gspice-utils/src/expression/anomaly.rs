@@ -0,0 +1,256 @@
+use core::fmt;
+use std::sync::{
+    atomic::{AtomicBool, Ordering::Relaxed},
+    Mutex,
+};
+
+use super::{
+    op::{CondMethod, RobustLoss, Spectrum, Transition},
+    Expression, Op,
+};
+
+/// Max depth of the subtree printed in [`AnomalyReport`]
+const SUBTREE_DEPTH: usize = 3;
+
+static ANOMALY_DETECTION: AtomicBool = AtomicBool::new(false);
+static ANOMALY_REPORT: Mutex<Option<AnomalyReport>> = Mutex::new(None);
+
+/// Enable / disable the NaN/Inf detection of every forward kernel
+///
+/// Only the first detection is recorded, use [`take_anomaly_report`] to retrieve it
+#[inline]
+pub fn set_anomaly_detection(enable: bool) {
+    ANOMALY_DETECTION.store(enable, Relaxed);
+}
+
+/// Take the first recorded anomaly (if any), and re-arm the detection
+#[inline]
+pub fn take_anomaly_report() -> Option<AnomalyReport> {
+    ANOMALY_REPORT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+}
+
+/// The first non-finite value produced by a forward kernel,
+/// or the first undefined gradient of a backward kernel
+#[derive(Clone, Debug)]
+pub struct AnomalyReport {
+    /// Op kind, e.g., `Log`, or the gradient, e.g., `d Pow / d rhs`
+    pub op: String,
+    /// Pretty-printed subtree of the node, bounded depth
+    pub subtree: String,
+    /// Index of the bad element
+    pub index: usize,
+    /// The bad value
+    pub value: f64,
+    /// Input values at that index
+    pub inputs: Vec<f64>,
+}
+
+impl fmt::Display for AnomalyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "non-finite value {} at index {} of {}, inputs {:?}\n  {}",
+            self.value, self.index, self.op, self.inputs, self.subtree
+        )
+    }
+}
+
+impl Op {
+    pub(super) fn name(&self) -> String {
+        match self {
+            Op::Assign => "Assign".to_owned(),
+            Op::AssignFrom(_) => "AssignFrom".to_owned(),
+            Op::Powf(_, n) => format!("Powf({n})"),
+            Op::Pwl(_, _) => "Pwl".to_owned(),
+            Op::Spline(_, _) => "Spline".to_owned(),
+            Op::Diode(_, _) => "Diode".to_owned(),
+            Op::Transition(_, Transition::SmoothStepRange { x0, x1 }) => {
+                format!("SmoothStepRange({x0}, {x1})")
+            }
+            Op::Transition(_, Transition::GaussPulse { center, sigma }) => {
+                format!("GaussPulse({center}, {sigma})")
+            }
+            Op::UnaryParam(_, param, unary_param_op) => format!("{unary_param_op:?}({param})"),
+            Op::DivEps(_, _, eps) => format!("DivEps({eps})"),
+            Op::RobustLoss(_, _, RobustLoss::Huber(delta)) => format!("Huber({delta})"),
+            Op::RobustLoss(_, _, RobustLoss::LogCosh) => "LogCosh".to_owned(),
+            Op::Custom(_, custom) => custom.name().to_owned(),
+            Op::ExternalUnary(_, id) => id.name(),
+            Op::CustomBinary(_, _, custom) => custom.name().to_owned(),
+            Op::CustomNary(_, custom) => custom.name().to_owned(),
+            Op::Cond(_, _, _, CondMethod::Smooth) => "Cond".to_owned(),
+            Op::Cond(_, _, _, CondMethod::Ste) => "CondSte".to_owned(),
+            Op::Select(_, _, _, CondMethod::Smooth) => "SelectSmooth".to_owned(),
+            Op::Select(_, _, _, CondMethod::Ste) => "Select".to_owned(),
+            Op::LogicNary(_, op) => format!("Logic{op:?}Many"),
+            Op::Concat(_) => "Concat".to_owned(),
+            Op::Narrow(_, offset, len) => format!("Narrow({offset}, {len})"),
+            Op::Gather(_, _) => "Gather".to_owned(),
+            Op::ScatterAdd(_, _, len) => format!("ScatterAdd({len})"),
+            Op::Reverse(_) => "Reverse".to_owned(),
+            Op::Assert(_, range) => format!("Assert({})", range.label),
+            Op::Shift(_, offset, fill) => format!("Shift({offset}, {fill})"),
+            Op::Ddt(_, dt) => format!("Ddt({dt})"),
+            Op::Idt(_, dt, initial) => format!("Idt({dt}, {initial})"),
+            Op::Spectrum(_, Spectrum::Magnitude) => "FftMag".to_owned(),
+            Op::Spectrum(_, Spectrum::Power) => "FftPower".to_owned(),
+            Op::CrossTime(_, cross) => format!("CrossTime({}, {:?})", cross.threshold, cross.edge),
+            Op::Integrate(_, integral) => format!("Integrate({:?})", integral.rule),
+            Op::WindowReduce(_, reduce) => {
+                format!(
+                    "Window{:?}({}, {})",
+                    reduce.kind, reduce.window, reduce.stride
+                )
+            }
+            Op::Reduce(_, reduce) => format!("{reduce:?}"),
+            Op::CountGe(_, count) if count.mean => "MeanIndicator".to_owned(),
+            Op::CountGe(_, count) => format!("CountGe({})", count.threshold),
+            Op::SoftHistogram(_, histogram) => format!(
+                "SoftHistogram({} bins, {})",
+                histogram.edges.len() - 1,
+                histogram.sigma
+            ),
+            Op::Conv1d(_, _, padding) => format!("Conv1d({padding:?})"),
+            Op::MatVec(_, _, rows, cols) => format!("MatVec({rows}x{cols})"),
+            Op::Polyval(_, _) => "Polyval".to_owned(),
+            Op::Ema(_, alpha, _) => format!("Ema({alpha})"),
+            Op::Weighted(_, _, weighted) => format!("Weighted{weighted:?}"),
+            Op::Complex(_, complex) => complex.name().to_owned(),
+            Op::Unary(_, unary_op) => format!("{unary_op:?}"),
+            Op::Binary(_, _, binary_op) => format!("{binary_op:?}"),
+            Op::DiscreteBinary(_, _, discrete_binary_op, _) => format!("{discrete_binary_op:?}"),
+        }
+    }
+    pub(super) fn inputs(&self) -> Vec<&Expression> {
+        match self {
+            Op::Assign => vec![],
+            Op::AssignFrom(node)
+            | Op::Powf(node, _)
+            | Op::Pwl(node, _)
+            | Op::Spline(node, _)
+            | Op::Diode(node, _)
+            | Op::Transition(node, _)
+            | Op::Narrow(node, _, _)
+            | Op::Gather(node, _)
+            | Op::ScatterAdd(node, _, _)
+            | Op::Reverse(node)
+            | Op::Assert(node, _)
+            | Op::Shift(node, _, _)
+            | Op::Ddt(node, _)
+            | Op::Idt(node, _, _)
+            | Op::Spectrum(node, _)
+            | Op::CrossTime(node, _)
+            | Op::Integrate(node, _)
+            | Op::WindowReduce(node, _)
+            | Op::Reduce(node, _)
+            | Op::Ema(node, _, _)
+            | Op::CountGe(node, _)
+            | Op::SoftHistogram(node, _)
+            | Op::UnaryParam(node, _, _)
+            | Op::Custom(node, _)
+            | Op::ExternalUnary(node, _)
+            | Op::Unary(node, _) => {
+                vec![node]
+            }
+            Op::Cond(cond, on_true, on_false, _) => vec![cond, on_true, on_false],
+            Op::Concat(parts)
+            | Op::CustomNary(parts, _)
+            | Op::Complex(parts, _)
+            | Op::LogicNary(parts, _) => parts.iter().collect(),
+            Op::Select(conds, values, default, _) => {
+                conds.iter().chain(values).chain([default]).collect()
+            }
+            Op::Binary(lhs, rhs, _)
+            | Op::DivEps(lhs, rhs, _)
+            | Op::RobustLoss(lhs, rhs, _)
+            | Op::CustomBinary(lhs, rhs, _)
+            | Op::Conv1d(lhs, rhs, _)
+            | Op::MatVec(lhs, rhs, _, _)
+            | Op::Polyval(lhs, rhs)
+            | Op::Weighted(lhs, rhs, _)
+            | Op::DiscreteBinary(lhs, rhs, _, _) => {
+                vec![lhs, rhs]
+            }
+        }
+    }
+    /// Check the output of a forward kernel, only when anomaly detection is enabled
+    ///
+    /// The values of `inputs` should be readable (not locked for write)
+    #[inline]
+    pub(super) fn check_anomaly(&self, values: &[f64]) {
+        if ANOMALY_DETECTION.load(Relaxed) {
+            self.check_anomaly_slow(values);
+        }
+    }
+    #[cold]
+    fn check_anomaly_slow(&self, values: &[f64]) {
+        if let Op::Assign = self {
+            return;
+        }
+        if let Some(index) = values.iter().position(|x| !x.is_finite()) {
+            self.report_anomaly(self.name(), index, values[index]);
+        }
+    }
+    /// Check a gradient left undefined by the backward of the op, which passes `0` instead,
+    /// only when anomaly detection is enabled
+    ///
+    /// `index` finds the first undefined element, reported as a NaN of `op`
+    #[inline]
+    pub(super) fn check_gradient_anomaly(&self, op: &str, index: impl FnOnce() -> Option<usize>) {
+        if ANOMALY_DETECTION.load(Relaxed) {
+            if let Some(index) = index() {
+                self.report_anomaly(op.to_owned(), index, f64::NAN);
+            }
+        }
+    }
+    /// Record the first anomaly
+    #[cold]
+    fn report_anomaly(&self, op: String, index: usize, value: f64) {
+        let mut report = ANOMALY_REPORT.lock().unwrap_or_else(|e| e.into_inner());
+        if report.is_none() {
+            let mut subtree = String::new();
+            _ = self.fmt_subtree(&mut subtree, SUBTREE_DEPTH);
+            let new_report = AnomalyReport {
+                op,
+                subtree,
+                index,
+                value,
+                inputs: self
+                    .inputs()
+                    .into_iter()
+                    .map(|input| match input {
+                        Expression::Const(x) => *x,
+                        Expression::Tensor(tensor) => {
+                            let values = tensor.read();
+                            // a length-1 input is broadcast to every index
+                            let index = if values.len() == 1 { 0 } else { index };
+                            values.get(index).copied().unwrap_or(f64::NAN)
+                        }
+                    })
+                    .collect(),
+            };
+            log::error!(target: "gspice::anomaly", "Anomaly detected: {new_report}");
+            *report = Some(new_report);
+        }
+    }
+    fn fmt_subtree(&self, f: &mut impl fmt::Write, depth: usize) -> fmt::Result {
+        write!(f, "{}(", self.name())?;
+        for (i, input) in self.inputs().into_iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            match input {
+                Expression::Const(x) => write!(f, "Const({x})")?,
+                Expression::Tensor(tensor) => match tensor.op() {
+                    Op::Assign => write!(f, "Tensor{tensor}")?,
+                    op if depth > 1 => op.fmt_subtree(f, depth - 1)?,
+                    _ => write!(f, "..")?,
+                },
+            }
+        }
+        write!(f, ")")
+    }
+}
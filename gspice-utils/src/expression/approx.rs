@@ -0,0 +1,165 @@
+use std::{collections::BTreeSet, sync::PoisonError};
+
+use super::{Error, Expression, GradStore, ScalarTensor};
+
+/// Tolerance of the approximate comparisons, `a` and `b` are close when
+/// `|a - b| <= atol + rtol * |b|`, as `numpy.isclose`
+///
+/// Equal infinities are close, NaN is only close to NaN with [`nan_equal`](Tolerance::nan_equal)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    pub rtol: f64,
+    pub atol: f64,
+    pub nan_equal: bool,
+}
+
+impl Tolerance {
+    /// NaN is never close
+    #[inline]
+    pub fn new(rtol: f64, atol: f64) -> Self {
+        Self {
+            rtol,
+            atol,
+            nan_equal: false,
+        }
+    }
+    /// Whether NaN is close to NaN
+    #[inline]
+    pub fn nan_equal(self, nan_equal: bool) -> Self {
+        Self { nan_equal, ..self }
+    }
+    /// How much `|a - b|` exceeds the tolerance, `None` when close, infinite on NaN
+    #[inline]
+    fn excess(&self, a: f64, b: f64) -> Option<f64> {
+        if a == b || (self.nan_equal && a.is_nan() && b.is_nan()) {
+            return None;
+        }
+        let excess = (a - b).abs() - (self.atol + self.rtol * b.abs());
+        if excess <= 0.0 {
+            None
+        } else if excess.is_nan() {
+            Some(f64::INFINITY)
+        } else {
+            Some(excess)
+        }
+    }
+    /// Whether `a` and `b` are close
+    #[inline]
+    pub fn is_close(&self, a: f64, b: f64) -> bool {
+        self.excess(a, b).is_none()
+    }
+    /// [`Error::NotClose`] with the worst index when the values are not element-wise close,
+    /// or their lengths differ
+    pub fn check(&self, lhs: &[f64], rhs: &[f64]) -> Result<(), Error> {
+        if lhs.len() != rhs.len() {
+            return Err(Error::NotClose(format!(
+                "length {} vs {}",
+                lhs.len(),
+                rhs.len()
+            )));
+        }
+        let mut worst: Option<(usize, f64)> = None;
+        let mut count = 0;
+        for (index, (a, b)) in lhs.iter().zip(rhs).enumerate() {
+            if let Some(excess) = self.excess(*a, *b) {
+                count += 1;
+                if worst.is_none_or(|(_, worst)| excess > worst) {
+                    worst = Some((index, excess));
+                }
+            }
+        }
+        match worst {
+            None => Ok(()),
+            Some((index, _)) => Err(Error::NotClose(format!(
+                "{count} of {} elements differ, worst at index {index}: {} vs {} \
+                 (|diff| {}, rtol {}, atol {})",
+                lhs.len(),
+                lhs[index],
+                rhs[index],
+                (lhs[index] - rhs[index]).abs(),
+                self.rtol,
+                self.atol,
+            ))),
+        }
+    }
+}
+
+impl<'a> ScalarTensor<'a> {
+    /// Whether the values are element-wise close, see [`Tolerance`],
+    /// a scalar is compared as a length-1 tensor
+    #[inline]
+    pub fn approx_eq(&self, other: &ScalarTensor, rtol: f64, atol: f64) -> bool {
+        self.check_close(other, Tolerance::new(rtol, atol)).is_ok()
+    }
+    /// [`Error::NotClose`] describing the worst element when the values are not close,
+    /// see [`approx_eq`](ScalarTensor::approx_eq)
+    pub fn check_close(&self, other: &ScalarTensor, tolerance: Tolerance) -> Result<(), Error> {
+        self.with_slice(|lhs| other.with_slice(|rhs| tolerance.check(lhs, rhs)))
+    }
+    fn with_slice<R>(&self, f: impl FnOnce(&[f64]) -> R) -> R {
+        match self {
+            ScalarTensor::Scalar(x) => f(std::slice::from_ref(*x)),
+            ScalarTensor::Tensor(tensor) => {
+                f(&tensor.read().unwrap_or_else(PoisonError::into_inner))
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// Compare the values of both expressions, see [`ScalarTensor::check_close`]
+    #[inline]
+    pub fn check_close(&self, other: &Self, tolerance: Tolerance) -> Result<(), Error> {
+        self.value().check_close(&other.value(), tolerance)
+    }
+    /// ## Panics
+    ///
+    /// When the values are not element-wise close, see [`Tolerance`],
+    /// the message shows the worst index and its values
+    #[inline]
+    #[track_caller]
+    pub fn assert_close(&self, other: &Self, rtol: f64, atol: f64) {
+        if let Err(e) = self.check_close(other, Tolerance::new(rtol, atol)) {
+            panic!("assertion `left ≈ right` failed: {e}\n left: {self}\nright: {other}");
+        }
+    }
+}
+
+impl GradStore {
+    /// Compare the gradients of the same [`GradId`](super::GradId)s,
+    /// [`Error::NotClose`] when a gradient is only in one of the stores
+    pub fn check_close(&self, other: &Self, tolerance: Tolerance) -> Result<(), Error> {
+        let ids: BTreeSet<_> = self.grads.keys().chain(other.grads.keys()).collect();
+        // in creation order
+        for id in ids.into_iter().rev() {
+            match (self.grads.get(id), other.grads.get(id)) {
+                (Some(lhs), Some(rhs)) => tolerance.check(lhs, rhs).map_err(|e| match e {
+                    Error::NotClose(msg) => Error::NotClose(format!("gradient of {id:?}: {msg}")),
+                    e => e,
+                })?,
+                (lhs, _) => {
+                    return Err(Error::NotClose(format!(
+                        "gradient of {id:?} only in the {}",
+                        if lhs.is_some() { "left" } else { "right" }
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Whether the gradients are element-wise close, see [`check_close`](GradStore::check_close)
+    #[inline]
+    pub fn approx_eq(&self, other: &Self, rtol: f64, atol: f64) -> bool {
+        self.check_close(other, Tolerance::new(rtol, atol)).is_ok()
+    }
+    /// ## Panics
+    ///
+    /// When the gradients are not close, see [`check_close`](GradStore::check_close)
+    #[inline]
+    #[track_caller]
+    pub fn assert_close(&self, other: &Self, rtol: f64, atol: f64) {
+        if let Err(e) = self.check_close(other, Tolerance::new(rtol, atol)) {
+            panic!("assertion `left ≈ right` failed: {e}");
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering::Relaxed},
+    Mutex,
+};
+
+use super::{Error, Expression, Op, Tensor};
+
+static ASSERT_POLICY: AtomicU8 = AtomicU8::new(AssertPolicy::Log as u8);
+static ASSERT_VIOLATION: Mutex<Option<Error>> = Mutex::new(None);
+
+/// What [`Expression::assert_in_range`] does on a violation, see [`set_assert_policy`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AssertPolicy {
+    /// Skip the check, one atomic load per node and no per-element branch
+    Ignore,
+    /// Log a warning for each violating node, the default
+    Log,
+    /// Log an error and record the first violation,
+    /// use [`take_assert_violation`] to retrieve it
+    Error,
+}
+
+/// Set the policy of all [`Expression::assert_in_range`] nodes, [`AssertPolicy::Log`] by default
+#[inline]
+pub fn set_assert_policy(policy: AssertPolicy) {
+    ASSERT_POLICY.store(policy as u8, Relaxed);
+}
+
+/// See [`set_assert_policy`]
+#[inline]
+pub fn assert_policy() -> AssertPolicy {
+    match ASSERT_POLICY.load(Relaxed) {
+        0 => AssertPolicy::Ignore,
+        1 => AssertPolicy::Log,
+        _ => AssertPolicy::Error,
+    }
+}
+
+/// Take the first violation recorded under [`AssertPolicy::Error`]
+/// as an [`Error::AssertViolation`], and re-arm the recording
+#[inline]
+pub fn take_assert_violation() -> Result<(), Error> {
+    match ASSERT_VIOLATION
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// The valid range of [`Op::Assert`]
+#[derive(Clone, Debug)]
+pub struct AssertRange {
+    pub(super) lo: f64,
+    pub(super) hi: f64,
+    pub(super) label: String,
+}
+
+impl AssertRange {
+    /// Check the values passing through, gated per node by the policy
+    #[inline]
+    pub(super) fn check(&self, values: &[f64]) {
+        let policy = assert_policy();
+        if policy != AssertPolicy::Ignore {
+            self.check_slow(values, policy);
+        }
+    }
+    #[cold]
+    fn check_slow(&self, values: &[f64], policy: AssertPolicy) {
+        // NaN is out of any range
+        if let Some(index) = values.iter().position(|x| !(self.lo..=self.hi).contains(x)) {
+            let e = Error::AssertViolation {
+                label: self.label.clone(),
+                index,
+                value: values[index].to_string(),
+                range: format!("[{}, {}]", self.lo, self.hi),
+            };
+            if policy == AssertPolicy::Log {
+                log::warn!(target: "gspice::assert", "{e}");
+            } else {
+                log::error!(target: "gspice::assert", "{e}");
+                let mut violation = ASSERT_VIOLATION.lock().unwrap_or_else(|e| e.into_inner());
+                if violation.is_none() {
+                    *violation = Some(e);
+                }
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// Tripwire that checks the values within `[lo, hi]` at each evaluation,
+    /// e.g., the fitted range of a surrogate model
+    ///
+    /// The forward and backward are identities. A violation is reported with the label,
+    /// the first violating index and value, according to [`set_assert_policy`]
+    #[inline]
+    pub fn assert_in_range(&self, lo: f64, hi: f64, label: &str) -> Self {
+        let range = AssertRange {
+            lo,
+            hi,
+            label: label.to_owned(),
+        };
+        match self {
+            Self::Const(x) => {
+                range.check(&[*x]);
+                Self::Const(*x)
+            }
+            Self::Tensor(tensor) => {
+                let values = tensor.read().clone();
+                range.check(&values);
+                Self::Tensor(Tensor::new(
+                    tensor.with_grad(),
+                    values,
+                    Op::Assert(self.clone(), range),
+                ))
+            }
+        }
+    }
+}
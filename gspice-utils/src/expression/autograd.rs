@@ -1,13 +1,27 @@
 use itertools::izip;
 use std::{
-    collections::{BTreeMap, HashMap},
-    ops::{Deref, DerefMut},
+    collections::{HashMap, HashSet},
+    iter::repeat,
+    ops::{Deref, DerefMut, Index},
     sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    time::Instant,
 };
 
 use super::{
-    op::{BinaryOp, Cond, DiscreteBinaryOp, GradMethod, Powf, UnaryOp},
-    Expression, Op, Tensor, TensorRef,
+    assertion::AssertRange,
+    complex::ComplexOp,
+    ema::EmaState,
+    op::{
+        broadcast, AssignFrom, BinaryOp, Concat, Cond, CondMethod, Conv1d, ConvPadding, CountGe,
+        CrossTime, CustomBinary, CustomNary, CustomUnary, Ddt, Dft, Diode, DiscreteBinaryOp,
+        DiscreteBinaryOpT, DivEps, Edge, Gather, Ge, GradMethod, Idt, Inputs, Integral, LogicNary,
+        MatVec, MaxMethod, Narrow, Polyval, Powf, Pwl, Reduce, Reverse, RobustLoss, ScatterAdd,
+        Select, Shift, SoftHistogram, Spectrum, Spline, Transition, UnaryOp, UnaryParamOp,
+        Weighted, WindowKind, WindowReduce,
+    },
+    profile::{self, Phase},
+    reduction::{self, accuracy_mode, AccuracyMode},
+    Error, Expression, Op, Tensor, TensorRef,
 };
 use core::cmp::Ordering;
 
@@ -31,8 +45,9 @@ impl DerefMut for Grad {
     }
 }
 
+/// Unique id of a parameter, i.e., a leaf tensor, the op nodes have none
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub(super) struct GradId(usize);
+pub struct GradId(usize);
 
 impl PartialOrd for GradId {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
@@ -55,40 +70,103 @@ impl GradId {
 
 /// A store for gradients, associating a scalar id to the corresponding gradient scalar, used for back propagation.
 #[derive(Debug)]
-pub struct GradStore(HashMap<GradId, Grad>);
+pub struct GradStore {
+    /// The gradients of the parameters, the result
+    pub(super) grads: HashMap<GradId, Grad>,
+    /// The temporary gradients of the op nodes by their [key](Tensor::key),
+    /// each dropped once its node is back-propagated
+    nodes: HashMap<usize, Grad>,
+    loss: Option<f64>,
+    /// Only in [`AccuracyMode::Compensated`]
+    compensated: Option<Compensated>,
+}
+
+/// The ops accumulate into a scratch, which is folded into the gradient
+/// with compensation after each node, see [`GradStore::fold`]
+#[derive(Debug, Default)]
+struct Compensated {
+    /// `(scratch, compensation)` of the gradients
+    buffers: HashMap<Slot, (Grad, Vec<f64>)>,
+    /// Scratches written by the current node
+    pending: HashSet<Slot>,
+}
+
+/// Where the gradient of a tensor with gradient is held in a [`GradStore`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Slot {
+    Param(GradId),
+    Node(usize),
+}
+
+impl Slot {
+    /// `None` for a tensor without gradient
+    #[inline]
+    fn of(tensor: &Tensor) -> Option<Self> {
+        if !tensor.with_grad() {
+            None
+        } else if let Some(id) = tensor.grad_id() {
+            Some(Self::Param(id))
+        } else {
+            Some(Self::Node(tensor.key()))
+        }
+    }
+}
 
 impl Expression {
-    fn grad_walk<'a>(&'a self, already_seen: &mut BTreeMap<GradId, &'a Tensor>) {
-        if let Expression::Tensor(tensor) = self {
-            if let Some(grad_id) = tensor.grad_id() {
-                if already_seen.get(grad_id).is_none() {
-                    already_seen.insert(*grad_id, &tensor);
-                    match tensor.op() {
-                        Op::Assgin => (),
-                        Op::Powf(node, _) => node.grad_walk(already_seen),
-                        Op::Cond(cond, on_true, on_false) => {
-                            cond.grad_walk(already_seen);
-                            on_true.grad_walk(already_seen);
-                            on_false.grad_walk(already_seen);
-                        }
-                        Op::Unary(node, _) => node.grad_walk(already_seen),
-                        Op::Binary(lhs, rhs, _) | Op::DiscreteBinary(lhs, rhs, _, _) => {
-                            lhs.grad_walk(already_seen);
-                            rhs.grad_walk(already_seen);
-                        }
+    /// Return all the nodes that participate in the gradient of this value, in a topological
+    /// order, the first elements having dependencies on the latter ones, e.g. the first element
+    /// if any is the argument. This assumes that the op graph is a DAG.
+    ///
+    /// The participation of the ops is decided here, since their inputs may be (un)frozen
+    /// after creation. The walk is iterative, so that deep graphs do not overflow the stack,
+    /// and only keeps the nodes, so that its memory is a few words per node.
+    ///
+    /// The graphs of several `roots` are walked together, the shared nodes once
+    fn sorted_nodes<'a>(roots: &[&'a Expression]) -> Vec<&'a Tensor> {
+        // post order, reversed at the end
+        let mut sorted = Vec::new();
+        let mut visited: HashSet<usize> = HashSet::new();
+        let mut stack: Vec<&Tensor> = roots
+            .iter()
+            .rev()
+            .filter_map(|root| match root {
+                Expression::Tensor(root) => Some(root),
+                Expression::Const(_) => None,
+            })
+            .collect();
+        while let Some(tensor) = stack.pop() {
+            if visited.contains(&tensor.key()) {
+                continue;
+            }
+            let op = tensor.op();
+            let with_grad = match op {
+                Op::Assign => tensor.with_grad(),
+                op => {
+                    let inputs = op.inputs();
+                    let pending = stack.len();
+                    stack.extend(inputs.iter().filter_map(|input| match input {
+                        Expression::Tensor(input) if !visited.contains(&input.key()) => Some(input),
+                        _ => None,
+                    }));
+                    if stack.len() > pending {
+                        // again once its inputs are walked
+                        stack.insert(pending, tensor);
+                        continue;
                     }
+                    let with_grad = inputs.into_iter().any(
+                        |input| matches!(input, Expression::Tensor(input) if input.with_grad()),
+                    );
+                    tensor.set_with_grad(with_grad);
+                    with_grad
                 }
+            };
+            visited.insert(tensor.key());
+            if with_grad {
+                sorted.push(tensor);
             }
         }
-    }
-    /// Return all the nodes that lead to this value in a topologically sorted vec, the first
-    /// elements having dependencies on the latter ones, e.g. the first element if any is the
-    /// argument.
-    /// This assumes that the op graph is a DAG.
-    fn sorted_nodes(&self) -> BTreeMap<GradId, &Tensor> {
-        let mut already_seen = BTreeMap::new();
-        self.grad_walk(&mut already_seen);
-        already_seen
+        sorted.reverse();
+        sorted
     }
 }
 
@@ -97,107 +175,1053 @@ impl Expression {
     /// You need [self.value](Expression::value) before
     /// run [self.backward](Expression::backward) to update its compute graph's value
     pub fn backward(&self) -> GradStore {
-        let sorted_nodes = self.sorted_nodes();
-        if let Some((first_id, first_tensor)) = sorted_nodes.first_key_value() {
+        self.backward_impl(false).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`backward`](Expression::backward)
+    ///
+    /// + [`Error::EmptyTensor`] when this expression has no element
+    /// + [`Error::PoisonedLock`] when any tensor of the compute graph is poisoned
+    /// + [`Error::NonDifferentiable`] when an op without gradient, e.g., [`ceil`](Expression::ceil),
+    ///   receives a non-zero gradient
+    /// + [`Error::LengthMismatch`] when a [grad mask](Tensor::set_grad_mask) no longer
+    ///   matches the length of its tensor
+    pub fn try_backward(&self) -> Result<GradStore, Error> {
+        self.backward_impl(true)
+    }
+    /// `strict`: report the errors,
+    /// otherwise recover the poisoned locks and skip non-differentiable ops
+    fn backward_impl(&self, strict: bool) -> Result<GradStore, Error> {
+        Self::backward_seeded(&[(self, 1.0)], strict)
+    }
+    /// Back-propagate `Σ weight * sum(root)` over the union of the graphs of the roots,
+    /// each node once, see [`backward_many`](super::backward_many)
+    pub(super) fn backward_seeded(
+        roots: &[(&Expression, f64)],
+        strict: bool,
+    ) -> Result<GradStore, Error> {
+        let sorted_nodes =
+            Self::sorted_nodes(&roots.iter().map(|(root, _)| *root).collect::<Vec<_>>());
+        if strict {
+            for (root, _) in roots {
+                if let Expression::Tensor(tensor) = root {
+                    if tensor.try_read()?.is_empty() {
+                        return Err(Error::EmptyTensor);
+                    }
+                }
+            }
+            for tensor in &sorted_nodes {
+                drop(tensor.try_read()?);
+            }
+        }
+        if !sorted_nodes.is_empty() {
+            let start = log::log_enabled!(target: "gspice::backward", log::Level::Debug)
+                .then(|| (Instant::now(), sorted_nodes.len()));
             let mut grads = GradStore::new();
-            grads.insert(*first_id, Grad(first_tensor.ones_like()));
-            for (grad_id, tensor) in sorted_nodes {
-                if let Op::Assgin = tensor.op() {
+            let mut loss = 0.0;
+            for (root, weight) in roots {
+                match root {
+                    Expression::Const(x) => loss += weight * x,
+                    Expression::Tensor(tensor) => {
+                        loss += weight * tensor.read().iter().sum::<f64>();
+                        if let Some(seed) = grads.or_insert(tensor) {
+                            seed.0.iter_mut().for_each(|g| *g += weight);
+                        }
+                    }
+                }
+            }
+            grads.fold();
+            grads.loss = Some(loss);
+            for tensor in sorted_nodes {
+                if let Op::Assign = tensor.op() {
+                    if let Some(grad) = tensor.grad_id().and_then(|id| grads.grads.get_mut(&id)) {
+                        tensor.apply_grad_mask(grad)?;
+                    }
                     continue;
                 }
-                let grad = grads
-                    .remove_id(&grad_id)
+                // the intermediate gradient is only needed until this node is back-propagated,
+                // only the parameters' (leaves) gradients are kept in the result
+                let mut grad = grads
+                    .take_node(tensor)
                     .expect("gspice internal error - grad not populated");
+                tensor.apply_grad_mask(&mut grad)?;
+                if strict {
+                    if let Op::Unary(_, unary_op) = tensor.op() {
+                        // a zero incoming gradient loses nothing through the op
+                        if !unary_op.is_differentiable() && grad.iter().any(|g| *g != 0.0) {
+                            return Err(Error::NonDifferentiable {
+                                op: format!("{unary_op:?}"),
+                            });
+                        }
+                    }
+                }
+                tensor.materialize_inputs();
+                let profile_start = profile::is_enabled().then(|| (Instant::now(), grad.len()));
                 match tensor.op() {
-                    Op::Assgin => unreachable!(),
+                    Op::Assign => unreachable!(),
                     Op::Powf(node, n) => Powf::_backward(*n, tensor, node, &mut grads, grad),
-                    Op::Cond(cond, on_true, on_false) => {
+                    Op::Pwl(node, pwl) => pwl._backward(tensor, node, &mut grads, grad),
+                    Op::Spline(node, spline) => spline._backward(tensor, node, &mut grads, grad),
+                    Op::Diode(node, diode) => diode._backward(tensor, node, &mut grads, grad),
+                    Op::Transition(node, transition) => {
+                        transition._backward(tensor, node, &mut grads, grad)
+                    }
+                    Op::UnaryParam(node, param, unary_param_op) => {
+                        unary_param_op._backward(*param, tensor, node, &mut grads, grad)
+                    }
+                    Op::DivEps(lhs, rhs, eps) => {
+                        DivEps::_backward(*eps, tensor, lhs, rhs, &mut grads, grad)
+                    }
+                    Op::RobustLoss(lhs, rhs, loss) => {
+                        loss._backward(tensor, lhs, rhs, &mut grads, grad)
+                    }
+                    Op::Custom(node, custom) => custom._backward(tensor, node, &mut grads, grad),
+                    Op::ExternalUnary(node, id) => {
+                        id.get()._backward(tensor, node, &mut grads, grad)
+                    }
+                    Op::CustomBinary(lhs, rhs, custom) => {
+                        custom._backward(tensor, lhs, rhs, &mut grads, grad)
+                    }
+                    Op::CustomNary(inputs, custom) => {
+                        custom._backward(tensor, inputs, &mut grads, grad)
+                    }
+                    Op::Cond(cond, on_true, on_false, _) => {
                         Cond::_backward(cond, on_true, on_false, &mut grads, grad)
                     }
+                    Op::Select(conds, values, default, method) => {
+                        Select::_backward(conds, values, default, *method, &mut grads, grad)
+                    }
+                    Op::LogicNary(terms, op) => op._backward(terms, &mut grads, grad),
+                    Op::Concat(parts) => Concat::_backward(parts, &mut grads, grad),
+                    Op::Narrow(node, offset, len) => {
+                        Narrow::_backward(node, *offset, *len, &mut grads, grad)
+                    }
+                    Op::Gather(node, indices) => Gather::_backward(node, indices, &mut grads, grad),
+                    Op::ScatterAdd(node, indices, _) => {
+                        ScatterAdd::_backward(node, indices, &mut grads, grad)
+                    }
+                    Op::AssignFrom(node) => AssignFrom::_backward(node, &mut grads, grad),
+                    Op::Reverse(node) => Reverse::_backward(node, &mut grads, grad),
+                    Op::Assert(node, _) => AssertRange::_backward(node, &mut grads, grad),
+                    Op::Shift(node, offset, _) => Shift::_backward(node, *offset, &mut grads, grad),
+                    Op::Ddt(node, dt) => Ddt::_backward(node, *dt, &mut grads, grad),
+                    Op::Idt(node, dt, _) => Idt::_backward(node, *dt, &mut grads, grad),
+                    Op::Spectrum(node, spectrum) => {
+                        Dft::_backward(node, *spectrum, &mut grads, grad)
+                    }
+                    Op::CrossTime(node, cross) => cross._backward(node, &mut grads, grad),
+                    Op::Integrate(node, integral) => integral._backward(node, &mut grads, grad),
+                    Op::WindowReduce(node, reduce) => reduce._backward(node, &mut grads, grad),
+                    Op::Reduce(node, reduce) => reduce._backward(tensor, node, &mut grads, grad),
+                    Op::CountGe(node, count) => count._backward(node, &mut grads, grad),
+                    Op::SoftHistogram(node, histogram) => {
+                        histogram._backward(node, &mut grads, grad)
+                    }
+                    Op::Conv1d(signal, kernel, padding) => {
+                        Conv1d::_backward(signal, kernel, *padding, &mut grads, grad)
+                    }
+                    Op::MatVec(input, weights, rows, cols) => {
+                        MatVec::_backward(input, weights, *rows, *cols, &mut grads, grad)
+                    }
+                    Op::Polyval(input, coeffs) => {
+                        Polyval::_backward(input, coeffs, &mut grads, grad)
+                    }
+                    Op::Ema(node, alpha, _) => EmaState::_backward(node, *alpha, &mut grads, grad),
+                    Op::Weighted(input, weights, weighted) => {
+                        weighted._backward(input, weights, &mut grads, grad)
+                    }
+                    Op::Complex(inputs, complex) => complex._backward(inputs, &mut grads, grad),
                     Op::Unary(node, unary_op) => {
                         unary_op._backward(tensor, node, &mut grads, grad);
                     }
                     Op::Binary(lhs, rhs, binary_op) => {
                         binary_op._backward(tensor, lhs, rhs, &mut grads, grad);
                     }
-                    Op::DiscreteBinary(lhs, rhs, discrete_binary_op, grad_method) => {
-                        discrete_binary_op._backward(
-                            tensor,
-                            lhs,
-                            rhs,
-                            grad_method,
-                            &mut grads,
-                            grad,
-                        )
+                    Op::DiscreteBinary(lhs, rhs, discrete_binary_op, grad_method) => {
+                        discrete_binary_op._backward(
+                            tensor,
+                            lhs,
+                            rhs,
+                            grad_method,
+                            &mut grads,
+                            grad,
+                        )
+                    }
+                }
+                grads.fold();
+                if let Some((start, len)) = profile_start {
+                    profile::record(tensor.op(), Phase::Backward, len, start.elapsed());
+                }
+                tensor.release_if_no_retain();
+            }
+            grads.finalize();
+            if let Some((start, nodes)) = start {
+                log::debug!(
+                    target: "gspice::backward",
+                    "backward over {nodes} nodes in {:?}",
+                    start.elapsed()
+                );
+            }
+            Ok(grads)
+        } else {
+            Ok(GradStore::new())
+        }
+    }
+}
+
+impl GradStore {
+    /// Create a new gradient store
+    fn new() -> Self {
+        GradStore {
+            grads: HashMap::new(),
+            nodes: HashMap::new(),
+            loss: None,
+            compensated: (accuracy_mode() == AccuracyMode::Compensated).then(Compensated::default),
+        }
+    }
+
+    /// Sum of the elements of the expression at its [`backward`](Expression::backward),
+    /// `None` for a constant
+    pub fn loss(&self) -> Option<f64> {
+        self.loss
+    }
+
+    /// Get the gradient tensor associated with the given tensor-reference
+    pub fn get(&self, tensor_ref: &TensorRef) -> Option<&Grad> {
+        if let Some(grad_id) = tensor_ref.0.grad_id() {
+            self.grads.get(&grad_id)
+        } else {
+            panic!("The tensor is not with gradient")
+        }
+    }
+
+    /// Get the gradient tensor associated with the given [`GradId`]
+    pub fn get_by_id(&self, grad_id: GradId) -> Option<&Grad> {
+        self.grads.get(&grad_id)
+    }
+
+    /// Remove & take the gradient tensor associated with the given tensor-reference
+    pub fn remove(&mut self, tensor_ref: &TensorRef) -> Option<Grad> {
+        if let Some(grad_id) = tensor_ref.0.grad_id() {
+            self.grads.remove(&grad_id)
+        } else {
+            panic!("The tensor is not with gradient")
+        }
+    }
+
+    /// Remove the temporary gradient of the op node `tensor`, returning it if it exists
+    fn take_node(&mut self, tensor: &Tensor) -> Option<Grad> {
+        let key = tensor.key();
+        let mut grad = self.nodes.remove(&key)?;
+        if let Some(compensated) = &mut self.compensated {
+            if let Some((_, compensation)) = compensated.buffers.remove(&Slot::Node(key)) {
+                izip!(grad.iter_mut(), compensation).for_each(|(g, c)| *g += c);
+            }
+        }
+        Some(grad)
+    }
+
+    /// Get the gradient tensor associated with the given tensor, or, if it does not exist,
+    /// insert a tensor of zeroes, with the same shape and type as the given tensors and return it
+    ///
+    /// In [`AccuracyMode::Compensated`], it is the scratch of the current node instead
+    fn or_insert(&mut self, tensor: &Tensor) -> Option<&mut Grad> {
+        let slot = Slot::of(tensor)?;
+        let zeros = || Grad(tensor.zeros_like());
+        let grad = match slot {
+            Slot::Param(id) => self.grads.entry(id).or_insert_with(zeros),
+            Slot::Node(key) => self.nodes.entry(key).or_insert_with(zeros),
+        };
+        Some(match &mut self.compensated {
+            None => grad,
+            Some(compensated) => {
+                compensated.pending.insert(slot);
+                let len = grad.len();
+                &mut compensated
+                    .buffers
+                    .entry(slot)
+                    .or_insert_with(|| (Grad(vec![0.0; len]), vec![0.0; len]))
+                    .0
+            }
+        })
+    }
+
+    /// Add the scratches of the current node into the gradients with compensation,
+    /// and clear them
+    fn fold(&mut self) {
+        if let Some(compensated) = &mut self.compensated {
+            for slot in compensated.pending.drain() {
+                let (scratch, compensation) = compensated
+                    .buffers
+                    .get_mut(&slot)
+                    .expect("gspice internal error - scratch not populated");
+                let grad = match slot {
+                    Slot::Param(id) => self.grads.get_mut(&id),
+                    Slot::Node(key) => self.nodes.get_mut(&key),
+                }
+                .expect("gspice internal error - grad not populated");
+                izip!(grad.iter_mut(), compensation.iter_mut(), scratch.iter_mut()).for_each(
+                    |(g, c, x)| {
+                        reduction::neumaier_add(g, c, *x);
+                        *x = 0.0;
+                    },
+                );
+            }
+        }
+    }
+
+    /// Add the remaining compensations into the gradients (of the leaves)
+    fn finalize(&mut self) {
+        if let Some(compensated) = &mut self.compensated {
+            for (slot, (_, compensation)) in compensated.buffers.drain() {
+                if let Some(grad) = match slot {
+                    Slot::Param(id) => self.grads.get_mut(&id),
+                    Slot::Node(_) => None,
+                } {
+                    izip!(grad.iter_mut(), compensation).for_each(|(g, c)| *g += c);
+                }
+            }
+        }
+    }
+}
+
+/// Panics when the tensor is not with gradient or has no gradient
+impl Index<&TensorRef> for GradStore {
+    type Output = Grad;
+    #[inline]
+    fn index(&self, tensor_ref: &TensorRef) -> &Grad {
+        &self[tensor_ref.tensor()]
+    }
+}
+
+/// Panics when the tensor is not with gradient or has no gradient
+impl Index<&Tensor> for GradStore {
+    type Output = Grad;
+    #[inline]
+    fn index(&self, tensor: &Tensor) -> &Grad {
+        match tensor.grad_id() {
+            Some(grad_id) => &self[grad_id],
+            None => panic!("The tensor is not with gradient"),
+        }
+    }
+}
+
+/// Panics when there is no gradient of the id
+impl Index<GradId> for GradStore {
+    type Output = Grad;
+    #[inline]
+    fn index(&self, grad_id: GradId) -> &Grad {
+        self.grads
+            .get(&grad_id)
+            .expect("The tensor has no gradient in this store")
+    }
+}
+
+impl UnaryOp {
+    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if !self.is_differentiable() {
+            log::error!(target: "gspice::backward", "BackwardNotSupported {self:?}");
+        }
+        let backward = self.backward();
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
+                        grad.iter(),
+                    ) {
+                        backward(x, res, grad, sum_grad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Powf {
+    fn _backward(n: f64, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
+                        grad.iter(),
+                    ) {
+                        Self::backward(x, n, res, grad, sum_grad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Pwl {
+    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
+                        grad.iter(),
+                    ) {
+                        self.backward(x, res, grad, sum_grad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Spline {
+    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
+                        grad.iter(),
+                    ) {
+                        self.backward(x, res, grad, sum_grad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Diode {
+    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
+                        grad.iter(),
+                    ) {
+                        self.backward(x, res, grad, sum_grad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Transition {
+    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
+                        grad.iter(),
+                    ) {
+                        self.backward(x, res, grad, sum_grad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl UnaryParamOp {
+    fn _backward(
+        &self,
+        param: f64,
+        tensor: &Tensor,
+        node: &Expression,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        let backward = self.backward();
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
+                        grad.iter(),
+                    ) {
+                        backward(x, param, res, grad, sum_grad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Select {
+    fn _backward(
+        conds: &[Expression],
+        values: &[Expression],
+        default: &Expression,
+        method: CondMethod,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        let nodes: Vec<&Expression> = conds.iter().chain(values).chain([default]).collect();
+        let mut node_grads: Vec<Option<Vec<f64>>> = nodes
+            .iter()
+            .map(|node| match node {
+                Expression::Tensor(tensor) if tensor.with_grad() => Some(vec![0.0; grad.len()]),
+                _ => None,
+            })
+            .collect();
+        {
+            let inputs = Inputs::read(nodes.iter().copied());
+            let mut tails = vec![0.0; conds.len() + 1];
+            for (i, grad) in grad.iter().enumerate() {
+                Self::backward(
+                    method,
+                    &inputs,
+                    conds.len(),
+                    i,
+                    grad,
+                    &mut tails,
+                    |input, g| {
+                        if let Some(node_grad) = &mut node_grads[input] {
+                            node_grad[i] += g;
+                        }
+                    },
+                );
+            }
+        }
+        for (node, node_grad) in nodes.into_iter().zip(node_grads) {
+            if let (Expression::Tensor(node_tensor), Some(node_grad)) = (node, node_grad) {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    broadcast_grad(node_sum_grad, node_grad.len(), |node_sum_grad| {
+                        izip!(node_sum_grad.iter_mut(), node_grad).for_each(|(sum, g)| *sum += g);
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl LogicNary {
+    fn _backward(&self, terms: &[Expression], grads: &mut GradStore, grad: Grad) {
+        let mut term_grads: Vec<Option<Vec<f64>>> = terms
+            .iter()
+            .map(|term| match term {
+                Expression::Tensor(tensor) if tensor.with_grad() => Some(vec![0.0; grad.len()]),
+                _ => None,
+            })
+            .collect();
+        {
+            let inputs = Inputs::read(terms.iter());
+            let mut ln_factors = vec![0.0; terms.len()];
+            for (i, grad) in grad.iter().enumerate() {
+                self.backward(&inputs, i, grad, &mut ln_factors, |term, g| {
+                    if let Some(term_grad) = &mut term_grads[term] {
+                        term_grad[i] += g;
+                    }
+                });
+            }
+        }
+        for (term, term_grad) in terms.iter().zip(term_grads) {
+            if let (Expression::Tensor(term_tensor), Some(term_grad)) = (term, term_grad) {
+                if let Some(term_sum_grad) = grads.or_insert(term_tensor) {
+                    broadcast_grad(term_sum_grad, term_grad.len(), |term_sum_grad| {
+                        izip!(term_sum_grad.iter_mut(), term_grad).for_each(|(sum, g)| *sum += g);
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Concat {
+    fn _backward(parts: &[Expression], grads: &mut GradStore, grad: Grad) {
+        let mut offset = 0;
+        for part in parts {
+            match part {
+                Expression::Const(_) => offset += 1,
+                Expression::Tensor(part_tensor) => {
+                    let len = part_tensor.read().len();
+                    if let Some(part_sum_grad) = grads.or_insert(part_tensor) {
+                        izip!(part_sum_grad.iter_mut(), &grad[offset..offset + len])
+                            .for_each(|(sum, g)| *sum += g);
+                    }
+                    offset += len;
+                }
+            }
+        }
+    }
+}
+
+impl Narrow {
+    fn _backward(node: &Expression, offset: usize, len: usize, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(node_sum_grad[offset..offset + len].iter_mut(), grad.iter())
+                    .for_each(|(sum, g)| *sum += g);
+            }
+        }
+    }
+}
+
+impl Gather {
+    fn _backward(node: &Expression, indices: &[usize], grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(indices, grad.iter()).for_each(|(index, g)| node_sum_grad[*index] += g);
+            }
+        }
+    }
+}
+
+impl ScatterAdd {
+    fn _backward(node: &Expression, indices: &[usize], grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(node_sum_grad.iter_mut(), indices)
+                    .for_each(|(sum, index)| *sum += grad[*index]);
+            }
+        }
+    }
+}
+
+impl AssignFrom {
+    fn _backward(node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(node_sum_grad.iter_mut(), grad.iter()).for_each(|(sum, g)| *sum += g);
+            }
+        }
+    }
+}
+
+impl Reverse {
+    fn _backward(node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(node_sum_grad.iter_mut(), grad.iter().rev()).for_each(|(sum, g)| *sum += g);
+            }
+        }
+    }
+}
+
+impl AssertRange {
+    fn _backward(node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(node_sum_grad.iter_mut(), grad.iter()).for_each(|(sum, g)| *sum += g);
+            }
+        }
+    }
+}
+
+impl Shift {
+    fn _backward(node: &Expression, offset: isize, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                for (i, g) in grad.iter().enumerate() {
+                    if let Some(j) = Self::source(i, offset, grad.len()) {
+                        node_sum_grad[j] += g;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Ddt {
+    /// Scatter each output's stencil back to its inputs
+    fn _backward(node: &Expression, dt: f64, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                let n = grad.len();
+                for (i, g) in grad.iter().enumerate() {
+                    match (i.checked_sub(1), (i + 1 < n).then_some(i + 1)) {
+                        (Some(prev), Some(next)) => {
+                            node_sum_grad[next] += g / (2.0 * dt);
+                            node_sum_grad[prev] -= g / (2.0 * dt);
+                        }
+                        (None, Some(next)) => {
+                            node_sum_grad[next] += g / dt;
+                            node_sum_grad[i] -= g / dt;
+                        }
+                        (Some(prev), None) => {
+                            node_sum_grad[i] += g / dt;
+                            node_sum_grad[prev] -= g / dt;
+                        }
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Idt {
+    /// The trapezoid `[k-1, k]` adds to all the outputs from `k`,
+    /// so both its ends get `dt/2` of the reversed cumulative gradient
+    fn _backward(node: &Expression, dt: f64, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                let mut suffix_sum = 0.0;
+                for k in (1..grad.len()).rev() {
+                    suffix_sum += grad[k];
+                    node_sum_grad[k - 1] += 0.5 * dt * suffix_sum;
+                    node_sum_grad[k] += 0.5 * dt * suffix_sum;
+                }
+            }
+        }
+    }
+}
+
+impl Dft {
+    /// `∂|X_k|²/∂x_n = 2 (Re X_k cos θ - Im X_k sin θ)` with `θ = 2πkn/N`,
+    /// and `∂|X_k|/∂x_n` is it divided by `2|X_k|`
+    fn _backward(node: &Expression, spectrum: Spectrum, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                let input = node_tensor.read();
+                let n = input.len();
+                let twiddles = Self::twiddles(n);
+                for (k, ((re, im), g)) in
+                    izip!(Self::transform(&input, &twiddles), grad.iter()).enumerate()
+                {
+                    let scale = match spectrum {
+                        Spectrum::Power => 2.0 * g,
+                        Spectrum::Magnitude => {
+                            let mag = re.hypot(im);
+                            if mag > 0.0 {
+                                g / mag
+                            } else {
+                                continue;
+                            }
+                        }
+                    };
+                    for (i, sum) in node_sum_grad.iter_mut().enumerate() {
+                        let (cos, sin) = twiddles[k * i % n];
+                        *sum += scale * (re * cos - im * sin);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl CrossTime {
+    /// `t = Σ w_i t_i / Σ w_i` with `w_i = c_i P_i`, `P_i = Π_{j<i} (1 - c_j)`:
+    /// `∂t/∂t_i = w_i / Σ w` and `∂t/∂c_j = P_j (g_j - S_j)`, where `g_i = (t_i - t) / Σ w`
+    /// and the suffix `S_j = g_{j+1} c_{j+1} + (1 - c_{j+1}) S_{j+1}` carries the later segments,
+    /// then `t_i = t0 + (t1 - t0) (th - a) / (b - a)` and `c_i(s_a, s_b)` to the samples
+    fn _backward(&self, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                let input = node_tensor.read();
+                if input.len() != self.times.len() {
+                    return;
+                }
+                let above = self.above(&self.method, &input);
+                let [crossing, reach, instants] = self.segments(&above, &input);
+                let den: f64 = izip!(&crossing, &reach).map(|(c, p)| c * p).sum();
+                if den <= 0.0 || den.is_nan() {
+                    return;
+                }
+                let t = izip!(&crossing, &reach, &instants)
+                    .map(|(c, p, t)| c * p * t)
+                    .sum::<f64>()
+                    / den;
+                // `∂s/∂x` of the indicators
+                let mut above_grad = vec![0.0; input.len()];
+                Ge::backward_lhs_iter_fix_rhs(
+                    &self.method,
+                    &self.threshold,
+                    izip!(
+                        input.iter(),
+                        above.iter(),
+                        repeat(&1.0),
+                        above_grad.iter_mut()
+                    ),
+                );
+                let mut suffix = 0.0;
+                for i in (0..crossing.len()).rev() {
+                    let (c, p) = (crossing[i], reach[i]);
+                    let g = grad[0] * (instants[i] - t) / den;
+                    let d_crossing = p * (g - suffix);
+                    suffix = g * c + (1.0 - c) * suffix;
+                    let (d_above_a, d_above_b) = match self.edge {
+                        Edge::Rising => (-above[i + 1], 1.0 - above[i]),
+                        Edge::Falling => (1.0 - above[i + 1], -above[i]),
+                        Edge::Either => (1.0 - 2.0 * above[i + 1], 1.0 - 2.0 * above[i]),
+                    };
+                    node_sum_grad[i] += d_crossing * d_above_a * above_grad[i];
+                    node_sum_grad[i + 1] += d_crossing * d_above_b * above_grad[i + 1];
+                    let (a, b) = (input[i], input[i + 1]);
+                    let w = c * p;
+                    if w != 0.0 && a != b && (0.0..=1.0).contains(&((self.threshold - a) / (b - a)))
+                    {
+                        let scale = grad[0] * w / den * (self.times[i + 1] - self.times[i])
+                            / ((b - a) * (b - a));
+                        node_sum_grad[i] += scale * (self.threshold - b);
+                        node_sum_grad[i + 1] -= scale * (self.threshold - a);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Integral {
+    /// Each sample gets its quadrature weight
+    fn _backward(&self, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(node_sum_grad.iter_mut(), &self.weights)
+                    .for_each(|(sum, w)| *sum += grad[0] * w);
+            }
+        }
+    }
+}
+
+impl WindowReduce {
+    /// The first extremum of each window gets its gradient, or the mean splits it evenly
+    fn _backward(&self, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                let input = node_tensor.read();
+                match self.kind {
+                    WindowKind::Max | WindowKind::Min => {
+                        for (i, g) in izip!(self.extrema(&input), grad.iter()) {
+                            node_sum_grad[i] += g;
+                        }
+                    }
+                    WindowKind::Mean => {
+                        for ((start, end), g) in izip!(self.windows(input.len()), grad.iter()) {
+                            let share = g / (end - start) as f64;
+                            node_sum_grad[start..end]
+                                .iter_mut()
+                                .for_each(|sum| *sum += share);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Reduce {
+    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                let input = node_tensor.read();
+                let (res, g) = (tensor.read()[0], grad[0]);
+                match self {
+                    Self::Sum => node_sum_grad.iter_mut().for_each(|sum| *sum += g),
+                    Self::Mean => {
+                        let n = input.len() as f64;
+                        node_sum_grad.iter_mut().for_each(|sum| *sum += g / n);
+                    }
+                    Self::Max(MaxMethod::Hard) => {
+                        let ties = input.iter().filter(|x| **x == res).count() as f64;
+                        izip!(node_sum_grad.iter_mut(), input.iter())
+                            .filter(|(_, x)| **x == res)
+                            .for_each(|(sum, _)| *sum += g / ties);
+                    }
+                    Self::Max(MaxMethod::Smooth(temperature)) => {
+                        izip!(node_sum_grad.iter_mut(), input.iter())
+                            .for_each(|(sum, x)| *sum += g * ((x - res) / temperature).exp());
+                    }
+                    Self::Quantile { q, bandwidth } => {
+                        for (i, w) in Self::quantile_weights(&input, *q, *bandwidth) {
+                            node_sum_grad[i] += g * w;
+                        }
+                    }
+                    Self::All => {
+                        let log_product = Self::log_product(input.iter().map(|x| Self::logic(*x)));
+                        izip!(node_sum_grad.iter_mut(), input.iter()).for_each(|(sum, x)| {
+                            *sum += g * Self::product_except(Self::logic(*x), log_product)
+                        });
+                    }
+                    Self::Any => {
+                        let log_product =
+                            Self::log_product(input.iter().map(|x| 1.0 - Self::logic(*x)));
+                        izip!(node_sum_grad.iter_mut(), input.iter()).for_each(|(sum, x)| {
+                            *sum += g * Self::product_except(1.0 - Self::logic(*x), log_product)
+                        });
                     }
                 }
             }
-            grads
-        } else {
-            GradStore::new()
         }
     }
 }
 
-impl GradStore {
-    /// Create a new gradient store
-    fn new() -> Self {
-        GradStore(HashMap::new())
+impl CountGe {
+    /// As the gradient of [`Expression::ge`] with each element
+    fn _backward(&self, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                let input = node_tensor.read();
+                let res = self.indicators(&input);
+                let g = if self.mean {
+                    grad[0] / input.len() as f64
+                } else {
+                    grad[0]
+                };
+                Ge::backward_lhs_iter_fix_rhs(
+                    &self.method,
+                    &self.threshold,
+                    izip!(
+                        input.iter(),
+                        res.iter(),
+                        repeat(&g),
+                        node_sum_grad.iter_mut()
+                    ),
+                );
+            }
+        }
     }
+}
 
-    /// Get the gradient tensor associated with the given tensor-reference
-    pub fn get(&self, tensor_ref: &TensorRef) -> Option<&Grad> {
-        if let Some(grad_id) = tensor_ref.0.grad_id() {
-            self.0.get(grad_id)
-        } else {
-            panic!("The tensor is not with gradient")
+impl SoftHistogram {
+    fn _backward(&self, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                self.backward(&node_tensor.read(), &grad, node_sum_grad);
+            }
         }
     }
+}
 
-    /// Remove & take the gradient tensor associated with the given tensor-reference
-    pub fn remove(&mut self, tensor_ref: &TensorRef) -> Option<Grad> {
-        if let Some(grad_id) = tensor_ref.0.grad_id() {
-            self.0.remove(grad_id)
-        } else {
-            panic!("The tensor is not with gradient")
+impl Conv1d {
+    fn _backward(
+        signal: &Expression,
+        kernel: &Expression,
+        padding: ConvPadding,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        let (signal_x, kernel_x) = (signal.conv_values(), kernel.conv_values());
+        let (n, m) = (signal_x.len(), kernel_x.len());
+        let (_, shift) = padding
+            .shape(n, m)
+            .expect("gspice internal error - conv1d shape");
+        if let Expression::Tensor(signal_tensor) = signal {
+            if let Some(signal_sum_grad) = grads.or_insert(signal_tensor) {
+                for (i, g) in grad.iter().enumerate() {
+                    for (k, j) in Self::taps(i, shift, n, m) {
+                        signal_sum_grad[j] += g * kernel_x[k];
+                    }
+                }
+            }
+        }
+        if let Expression::Tensor(kernel_tensor) = kernel {
+            if let Some(kernel_sum_grad) = grads.or_insert(kernel_tensor) {
+                for (i, g) in grad.iter().enumerate() {
+                    for (k, j) in Self::taps(i, shift, n, m) {
+                        kernel_sum_grad[k] += g * signal_x[j];
+                    }
+                }
+            }
         }
     }
+}
 
-    /// Remove the gradient tensor associated with the given tensor, returning it if it exists
-    fn remove_id(&mut self, id: &GradId) -> Option<Grad> {
-        self.0.remove(id)
+impl MatVec {
+    fn _backward(
+        input: &Expression,
+        weights: &Expression,
+        rows: usize,
+        cols: usize,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        let (input_x, weights_x) = (input.conv_values(), weights.conv_values());
+        if let Expression::Tensor(input_tensor) = input {
+            if let Some(input_sum_grad) = grads.or_insert(input_tensor) {
+                for (i, g) in grad.iter().enumerate().take(rows) {
+                    izip!(
+                        input_sum_grad.iter_mut(),
+                        &weights_x[i * cols..(i + 1) * cols]
+                    )
+                    .for_each(|(sum, w)| *sum += g * w);
+                }
+            }
+        }
+        if let Expression::Tensor(weights_tensor) = weights {
+            if let Some(weights_sum_grad) = grads.or_insert(weights_tensor) {
+                for (i, g) in grad.iter().enumerate().take(rows) {
+                    izip!(&mut weights_sum_grad[i * cols..(i + 1) * cols], &input_x)
+                        .for_each(|(sum, x)| *sum += g * x);
+                }
+            }
+        }
     }
+}
 
-    /// Insert a gradient tensor associated with the given tensor, returning the previous gradient tensor if it existed
-    fn insert(&mut self, id: GradId, grad: Grad) -> Option<Grad> {
-        self.0.insert(id, grad)
+impl Polyval {
+    fn _backward(input: &Expression, coeffs: &Expression, grads: &mut GradStore, grad: Grad) {
+        let (input_x, coeffs_x) = (input.conv_values(), coeffs.conv_values());
+        if let Expression::Tensor(input_tensor) = input {
+            if let Some(input_sum_grad) = grads.or_insert(input_tensor) {
+                izip!(input_sum_grad.iter_mut(), &input_x, grad.iter())
+                    .for_each(|(sum, x, g)| *sum += g * Self::derivative(*x, &coeffs_x));
+            }
+        }
+        if let Expression::Tensor(coeffs_tensor) = coeffs {
+            if let Some(coeffs_sum_grad) = grads.or_insert(coeffs_tensor) {
+                for (x, g) in izip!(&input_x, grad.iter()) {
+                    let mut power = *g;
+                    for sum in coeffs_sum_grad.iter_mut() {
+                        *sum += power;
+                        power *= x;
+                    }
+                }
+            }
+        }
     }
+}
 
-    /// Get the gradient tensor associated with the given tensor, or, if it does not exist,
-    /// insert a tensor of zeroes, with the same shape and type as the given tensors and return it
-    fn or_insert(&mut self, tensor: &Tensor) -> Option<&mut Grad> {
-        use std::collections::hash_map::Entry;
-        tensor.grad_id().map(|id| match self.0.entry(id) {
-            Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => entry.insert(Grad(tensor.zeros_like())),
-        })
+impl EmaState {
+    fn _backward(node: &Expression, alpha: f64, grads: &mut GradStore, grad: Grad) {
+        if let Expression::Tensor(node_tensor) = node {
+            if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                izip!(node_sum_grad.iter_mut(), grad.iter()).for_each(|(sum, g)| *sum += alpha * g);
+            }
+        }
     }
 }
 
-impl UnaryOp {
-    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
-        let backward = self.backward();
-        match node {
-            Expression::Const(_) => unreachable!(),
-            Expression::Tensor(node_tensor) => {
+impl Weighted {
+    fn _backward(
+        &self,
+        input: &Expression,
+        weights: &Expression,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        let (input_x, weights_x) = (input.conv_values(), weights.conv_values());
+        let len = self
+            .len(&input_x, &weights_x)
+            .unwrap_or_else(|e| panic!("{e}"));
+        let g = grad[0] * self.scale(len);
+        for (node, other) in [(input, &weights_x), (weights, &input_x)] {
+            if let Expression::Tensor(node_tensor) = node {
                 if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
-                    for (sum_grad, res, x, grad) in itertools::izip!(
-                        node_sum_grad.iter_mut(),
-                        tensor.values().read().unwrap().iter(),
-                        node_tensor.values().read().unwrap().iter(),
-                        grad.iter(),
-                    ) {
-                        backward(x, res, grad, sum_grad);
+                    let node_len = node_sum_grad.len();
+                    for (i, y) in broadcast(other, len).enumerate() {
+                        node_sum_grad[i % node_len] += g * y;
                     }
                 }
             }
@@ -205,19 +1229,24 @@ impl UnaryOp {
     }
 }
 
-impl Powf {
-    fn _backward(n: f64, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
-        match node {
-            Expression::Const(_) => unreachable!(),
-            Expression::Tensor(node_tensor) => {
-                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
-                    for (sum_grad, res, x, grad) in itertools::izip!(
-                        node_sum_grad.iter_mut(),
-                        tensor.values().read().unwrap().iter(),
-                        node_tensor.values().read().unwrap().iter(),
-                        grad.iter(),
-                    ) {
-                        Self::backward(x, n, res, grad, sum_grad);
+impl ComplexOp {
+    fn _backward(&self, inputs: &[Expression], grads: &mut GradStore, grad: Grad) {
+        let values: Vec<Vec<f64>> = inputs.iter().map(Expression::conv_values).collect();
+        let mut x = vec![0.0; inputs.len()];
+        let partials: Vec<[f64; 4]> = (0..grad.len())
+            .map(|i| {
+                for (x, values) in izip!(x.iter_mut(), &values) {
+                    *x = values[i % values.len()];
+                }
+                self.partials(&x)
+            })
+            .collect();
+        for (n, input) in inputs.iter().enumerate() {
+            if let Expression::Tensor(input_tensor) = input {
+                if let Some(input_sum_grad) = grads.or_insert(input_tensor) {
+                    let len = input_sum_grad.len();
+                    for (i, (g, partial)) in izip!(grad.iter(), &partials).enumerate() {
+                        input_sum_grad[i % len] += g * partial[n];
                     }
                 }
             }
@@ -241,7 +1270,7 @@ impl Cond {
                     for (on_false_grad, grad, on_false_x) in itertools::izip!(
                         on_false_sum_grad.iter_mut(),
                         grad.iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
+                        on_false_tensor.read().iter(),
                     ) {
                         Self::backward_on_false(cond_x, on_true_x, on_false_x, grad, on_false_grad);
                     }
@@ -252,7 +1281,7 @@ impl Cond {
                     for (on_true_grad, grad, on_true_x) in itertools::izip!(
                         on_true_sum_grad.iter_mut(),
                         grad.iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
+                        on_true_tensor.read().iter(),
                     ) {
                         Self::backward_on_true(cond_x, on_true_x, on_false_x, grad, on_true_grad);
                     }
@@ -260,24 +1289,28 @@ impl Cond {
             },
             (Expression::Const(cond_x), Expression::Tensor(on_true_tensor), Expression::Tensor(on_false_tensor)) => {
                 if let Some(on_true_sum_grad) = grads.or_insert(on_true_tensor) {
-                    for (on_true_grad, grad, on_true_x, on_false_x) in itertools::izip!(
-                        on_true_sum_grad.iter_mut(),
-                        grad.iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_on_true(cond_x, on_true_x, on_false_x, grad, on_true_grad);
-                    }
+                    broadcast_grad(on_true_sum_grad, grad.len(), |on_true_sum_grad| {
+                        for (on_true_grad, grad, on_true_x, on_false_x) in itertools::izip!(
+                            on_true_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&on_true_tensor.read(), grad.len()),
+                            broadcast(&on_false_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_on_true(cond_x, on_true_x, on_false_x, grad, on_true_grad);
+                        }
+                    });
                 }
                 if let Some(on_false_sum_grad) = grads.or_insert(on_false_tensor) {
-                    for (on_false_grad, grad, on_true_x, on_false_x) in itertools::izip!(
-                        on_false_sum_grad.iter_mut(),
-                        grad.iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_on_false(cond_x, on_true_x, on_false_x, grad, on_false_grad);
-                    }
+                    broadcast_grad(on_false_sum_grad, grad.len(), |on_false_sum_grad| {
+                        for (on_false_grad, grad, on_true_x, on_false_x) in itertools::izip!(
+                            on_false_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&on_true_tensor.read(), grad.len()),
+                            broadcast(&on_false_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_on_false(cond_x, on_true_x, on_false_x, grad, on_false_grad);
+                        }
+                    });
                 }
             },
             (Expression::Tensor(cond_tensor), Expression::Const(on_true_x), Expression::Const(on_false_x)) => {
@@ -285,7 +1318,7 @@ impl Cond {
                     for (cond_grad, grad, cond_x) in itertools::izip!(
                         cond_sum_grad.iter_mut(),
                         grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
+                        cond_tensor.read().iter(),
                     ) {
                         Self::backward_cond(cond_x, on_true_x, on_false_x, grad, cond_grad);
                     }
@@ -293,81 +1326,95 @@ impl Cond {
             },
             (Expression::Tensor(cond_tensor), Expression::Const(on_true_x), Expression::Tensor(on_false_tensor)) => {
                 if let Some(cond_sum_grad) = grads.or_insert(cond_tensor) {
-                    for (cond_grad, grad, cond_x, on_false_x) in itertools::izip!(
-                        cond_sum_grad.iter_mut(),
-                        grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_cond(cond_x, on_true_x, on_false_x, grad, cond_grad);
-                    }
+                    broadcast_grad(cond_sum_grad, grad.len(), |cond_sum_grad| {
+                        for (cond_grad, grad, cond_x, on_false_x) in itertools::izip!(
+                            cond_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&cond_tensor.read(), grad.len()),
+                            broadcast(&on_false_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_cond(cond_x, on_true_x, on_false_x, grad, cond_grad);
+                        }
+                    });
                 }
                 if let Some(on_false_sum_grad) = grads.or_insert(on_false_tensor) {
-                    for (on_false_grad, grad, cond_x, on_false_x) in itertools::izip!(
-                        on_false_sum_grad.iter_mut(),
-                        grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_on_false(cond_x, on_true_x, on_false_x, grad, on_false_grad);
-                    }
+                    broadcast_grad(on_false_sum_grad, grad.len(), |on_false_sum_grad| {
+                        for (on_false_grad, grad, cond_x, on_false_x) in itertools::izip!(
+                            on_false_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&cond_tensor.read(), grad.len()),
+                            broadcast(&on_false_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_on_false(cond_x, on_true_x, on_false_x, grad, on_false_grad);
+                        }
+                    });
                 }
             },
             (Expression::Tensor(cond_tensor), Expression::Tensor(on_true_tensor), Expression::Const(on_false_x)) => {
                 if let Some(cond_sum_grad) = grads.or_insert(cond_tensor) {
-                    for (cond_grad, grad, cond_x, on_true_x) in itertools::izip!(
-                        cond_sum_grad.iter_mut(),
-                        grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_cond(cond_x, on_true_x, on_false_x, grad, cond_grad);
-                    }
+                    broadcast_grad(cond_sum_grad, grad.len(), |cond_sum_grad| {
+                        for (cond_grad, grad, cond_x, on_true_x) in itertools::izip!(
+                            cond_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&cond_tensor.read(), grad.len()),
+                            broadcast(&on_true_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_cond(cond_x, on_true_x, on_false_x, grad, cond_grad);
+                        }
+                    });
                 }
                 if let Some(on_true_sum_grad) = grads.or_insert(on_true_tensor) {
-                    for (on_true_grad, grad, cond_x, on_true_x) in itertools::izip!(
-                        on_true_sum_grad.iter_mut(),
-                        grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_on_true(cond_x, on_true_x, on_false_x, grad, on_true_grad);
-                    }
+                    broadcast_grad(on_true_sum_grad, grad.len(), |on_true_sum_grad| {
+                        for (on_true_grad, grad, cond_x, on_true_x) in itertools::izip!(
+                            on_true_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&cond_tensor.read(), grad.len()),
+                            broadcast(&on_true_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_on_true(cond_x, on_true_x, on_false_x, grad, on_true_grad);
+                        }
+                    });
                 }
             },
             (Expression::Tensor(cond_tensor), Expression::Tensor(on_true_tensor), Expression::Tensor(on_false_tensor)) => {
                 if let Some(cond_sum_grad) = grads.or_insert(cond_tensor) {
-                    for (cond_grad, grad, cond_x, on_true_x, on_false_x) in itertools::izip!(
-                        cond_sum_grad.iter_mut(),
-                        grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_cond(cond_x, on_true_x, on_false_x, grad, cond_grad);
-                    }
+                    broadcast_grad(cond_sum_grad, grad.len(), |cond_sum_grad| {
+                        for (cond_grad, grad, cond_x, on_true_x, on_false_x) in itertools::izip!(
+                            cond_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&cond_tensor.read(), grad.len()),
+                            broadcast(&on_true_tensor.read(), grad.len()),
+                            broadcast(&on_false_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_cond(cond_x, on_true_x, on_false_x, grad, cond_grad);
+                        }
+                    });
                 }
                 if let Some(on_true_sum_grad) = grads.or_insert(on_true_tensor) {
-                    for (on_true_grad, grad, cond_x, on_true_x, on_false_x) in itertools::izip!(
-                        on_true_sum_grad.iter_mut(),
-                        grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_on_true(cond_x, on_true_x, on_false_x, grad, on_true_grad);
-                    }
+                    broadcast_grad(on_true_sum_grad, grad.len(), |on_true_sum_grad| {
+                        for (on_true_grad, grad, cond_x, on_true_x, on_false_x) in itertools::izip!(
+                            on_true_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&cond_tensor.read(), grad.len()),
+                            broadcast(&on_true_tensor.read(), grad.len()),
+                            broadcast(&on_false_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_on_true(cond_x, on_true_x, on_false_x, grad, on_true_grad);
+                        }
+                    });
                 }
                 if let Some(on_false_sum_grad) = grads.or_insert(on_false_tensor) {
-                    for (on_false_grad, grad, cond_x, on_true_x, on_false_x) in itertools::izip!(
-                        on_false_sum_grad.iter_mut(),
-                        grad.iter(),
-                        cond_tensor.values().read().unwrap().iter(),
-                        on_true_tensor.values().read().unwrap().iter(),
-                        on_false_tensor.values().read().unwrap().iter(),
-                    ) {
-                        Self::backward_on_false(cond_x, on_true_x, on_false_x, grad, on_false_grad);
-                    }
+                    broadcast_grad(on_false_sum_grad, grad.len(), |on_false_sum_grad| {
+                        for (on_false_grad, grad, cond_x, on_true_x, on_false_x) in itertools::izip!(
+                            on_false_sum_grad.iter_mut(),
+                            grad.iter(),
+                            broadcast(&cond_tensor.read(), grad.len()),
+                            broadcast(&on_true_tensor.read(), grad.len()),
+                            broadcast(&on_false_tensor.read(), grad.len()),
+                        ) {
+                            Self::backward_on_false(cond_x, on_true_x, on_false_x, grad, on_false_grad);
+                        }
+                    });
                 }
             },
         }
@@ -397,8 +1444,8 @@ impl DiscreteBinaryOp {
                         grad_method,
                         lhs_x,
                         izip!(
-                            rhs_tensor.values().read().unwrap().iter(),
-                            tensor.values().read().unwrap().iter(),
+                            rhs_tensor.read().iter(),
+                            tensor.read().iter(),
                             grad.iter(),
                             rhs_sum_grad.iter_mut(),
                         ),
@@ -411,8 +1458,8 @@ impl DiscreteBinaryOp {
                         grad_method,
                         rhs_x,
                         izip!(
-                            lhs_tensor.values().read().unwrap().iter(),
-                            tensor.values().read().unwrap().iter(),
+                            lhs_tensor.read().iter(),
+                            tensor.read().iter(),
                             grad.iter(),
                             lhs_sum_grad.iter_mut(),
                         ),
@@ -421,28 +1468,32 @@ impl DiscreteBinaryOp {
             }
             (Expression::Tensor(lhs_tensor), Expression::Tensor(rhs_tensor)) => {
                 if let Some(rhs_sum_grad) = grads.or_insert(rhs_tensor) {
-                    self.backward_rhs_iter(
-                        grad_method,
-                        izip!(
-                            lhs_tensor.values().read().unwrap().iter(),
-                            rhs_tensor.values().read().unwrap().iter(),
-                            tensor.values().read().unwrap().iter(),
-                            grad.iter(),
-                            rhs_sum_grad.iter_mut(),
-                        ),
-                    );
+                    broadcast_grad(rhs_sum_grad, grad.len(), |rhs_sum_grad| {
+                        self.backward_rhs_iter(
+                            grad_method,
+                            izip!(
+                                broadcast(&lhs_tensor.read(), grad.len()),
+                                broadcast(&rhs_tensor.read(), grad.len()),
+                                tensor.read().iter(),
+                                grad.iter(),
+                                rhs_sum_grad.iter_mut(),
+                            ),
+                        );
+                    });
                 }
                 if let Some(lhs_sum_grad) = grads.or_insert(lhs_tensor) {
-                    self.backward_lhs_iter(
-                        grad_method,
-                        izip!(
-                            lhs_tensor.values().read().unwrap().iter(),
-                            rhs_tensor.values().read().unwrap().iter(),
-                            tensor.values().read().unwrap().iter(),
-                            grad.iter(),
-                            lhs_sum_grad.iter_mut(),
-                        ),
-                    );
+                    broadcast_grad(lhs_sum_grad, grad.len(), |lhs_sum_grad| {
+                        self.backward_lhs_iter(
+                            grad_method,
+                            izip!(
+                                broadcast(&lhs_tensor.read(), grad.len()),
+                                broadcast(&rhs_tensor.read(), grad.len()),
+                                tensor.read().iter(),
+                                grad.iter(),
+                                lhs_sum_grad.iter_mut(),
+                            ),
+                        );
+                    });
                 }
             }
         }
@@ -459,56 +1510,206 @@ impl BinaryOp {
         grad: Grad,
     ) {
         let [backward_lhs, backward_rhs] = self.backward();
-        match (lhs, rhs) {
-            (Expression::Const(_), Expression::Const(_)) => unreachable!(),
-            (Expression::Const(lhs_x), Expression::Tensor(rhs_tensor)) => {
-                if let Some(rhs_sum_grad) = grads.or_insert(rhs_tensor) {
-                    for (rhs_grad, res, grad, rhs_x) in itertools::izip!(
-                        rhs_sum_grad.iter_mut(),
-                        tensor.values().read().unwrap().iter(),
+        binary_backward(tensor, lhs, rhs, grads, grad, backward_lhs, backward_rhs);
+        if let (Self::Pow, Expression::Tensor(rhs_tensor)) = (self, rhs) {
+            if rhs_tensor.with_grad() {
+                tensor
+                    .op()
+                    .check_gradient_anomaly("d Pow / d rhs", || match lhs {
+                        Expression::Const(x) => (*x <= 0.0).then_some(0),
+                        Expression::Tensor(lhs_tensor) => {
+                            lhs_tensor.read().iter().position(|x| *x <= 0.0)
+                        }
+                    });
+            }
+        }
+    }
+}
+
+impl DivEps {
+    fn _backward(
+        eps: f64,
+        tensor: &Tensor,
+        lhs: &Expression,
+        rhs: &Expression,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        binary_backward(
+            tensor,
+            lhs,
+            rhs,
+            grads,
+            grad,
+            |lhs_x, rhs_x, res, grad, sum_grad| {
+                Self::backward_lhs(lhs_x, rhs_x, eps, res, grad, sum_grad)
+            },
+            |lhs_x, rhs_x, res, grad, sum_grad| {
+                Self::backward_rhs(lhs_x, rhs_x, eps, res, grad, sum_grad)
+            },
+        );
+    }
+}
+
+impl RobustLoss {
+    fn _backward(
+        &self,
+        tensor: &Tensor,
+        lhs: &Expression,
+        rhs: &Expression,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        binary_backward(
+            tensor,
+            lhs,
+            rhs,
+            grads,
+            grad,
+            |lhs_x, rhs_x, res, grad, sum_grad| {
+                self.backward_lhs(lhs_x, rhs_x, res, grad, sum_grad)
+            },
+            |lhs_x, rhs_x, res, grad, sum_grad| {
+                self.backward_rhs(lhs_x, rhs_x, res, grad, sum_grad)
+            },
+        );
+    }
+}
+
+impl CustomUnary {
+    fn _backward(&self, tensor: &Tensor, node: &Expression, grads: &mut GradStore, grad: Grad) {
+        match node {
+            Expression::Const(_) => unreachable!(),
+            Expression::Tensor(node_tensor) => {
+                if let Some(node_sum_grad) = grads.or_insert(node_tensor) {
+                    for (sum_grad, res, x, grad) in itertools::izip!(
+                        node_sum_grad.iter_mut(),
+                        tensor.read().iter(),
+                        node_tensor.read().iter(),
                         grad.iter(),
-                        rhs_tensor.values().read().unwrap().iter(),
                     ) {
-                        backward_rhs(lhs_x, rhs_x, res, grad, rhs_grad);
+                        self.backward(x, res, grad, sum_grad);
                     }
                 }
             }
-            (Expression::Tensor(lhs_tensor), Expression::Const(rhs_x)) => {
-                if let Some(lhs_sum_grad) = grads.or_insert(lhs_tensor) {
-                    for (lhs_grad, res, grad, lhs_x) in itertools::izip!(
-                        lhs_sum_grad.iter_mut(),
-                        tensor.values().read().unwrap().iter(),
-                        grad.iter(),
-                        lhs_tensor.values().read().unwrap().iter(),
-                    ) {
-                        backward_lhs(lhs_x, rhs_x, res, grad, lhs_grad);
-                    }
+        }
+    }
+}
+
+impl CustomBinary {
+    fn _backward(
+        &self,
+        tensor: &Tensor,
+        lhs: &Expression,
+        rhs: &Expression,
+        grads: &mut GradStore,
+        grad: Grad,
+    ) {
+        binary_backward(
+            tensor,
+            lhs,
+            rhs,
+            grads,
+            grad,
+            |lhs_x, rhs_x, res, grad, sum_grad| {
+                self.backward_lhs(lhs_x, rhs_x, res, grad, sum_grad)
+            },
+            |lhs_x, rhs_x, res, grad, sum_grad| {
+                self.backward_rhs(lhs_x, rhs_x, res, grad, sum_grad)
+            },
+        );
+    }
+}
+
+impl CustomNary {
+    fn _backward(&self, tensor: &Tensor, inputs: &[Expression], grads: &mut GradStore, grad: Grad) {
+        let grads_out = self.backward(inputs, &tensor.read(), &grad);
+        for (input, input_grad) in izip!(inputs, grads_out) {
+            if let Expression::Tensor(input_tensor) = input {
+                if let Some(input_sum_grad) = grads.or_insert(input_tensor) {
+                    izip!(input_sum_grad.iter_mut(), input_grad).for_each(|(sum, g)| *sum += g);
                 }
             }
-            (Expression::Tensor(lhs_tensor), Expression::Tensor(rhs_tensor)) => {
-                if let Some(rhs_sum_grad) = grads.or_insert(rhs_tensor) {
+        }
+    }
+}
+
+fn binary_backward(
+    tensor: &Tensor,
+    lhs: &Expression,
+    rhs: &Expression,
+    grads: &mut GradStore,
+    grad: Grad,
+    backward_lhs: impl Fn(&f64, &f64, &f64, &f64, &mut f64),
+    backward_rhs: impl Fn(&f64, &f64, &f64, &f64, &mut f64),
+) {
+    match (lhs, rhs) {
+        (Expression::Const(_), Expression::Const(_)) => unreachable!(),
+        (Expression::Const(lhs_x), Expression::Tensor(rhs_tensor)) => {
+            if let Some(rhs_sum_grad) = grads.or_insert(rhs_tensor) {
+                for (rhs_grad, res, grad, rhs_x) in itertools::izip!(
+                    rhs_sum_grad.iter_mut(),
+                    tensor.read().iter(),
+                    grad.iter(),
+                    rhs_tensor.read().iter(),
+                ) {
+                    backward_rhs(lhs_x, rhs_x, res, grad, rhs_grad);
+                }
+            }
+        }
+        (Expression::Tensor(lhs_tensor), Expression::Const(rhs_x)) => {
+            if let Some(lhs_sum_grad) = grads.or_insert(lhs_tensor) {
+                for (lhs_grad, res, grad, lhs_x) in itertools::izip!(
+                    lhs_sum_grad.iter_mut(),
+                    tensor.read().iter(),
+                    grad.iter(),
+                    lhs_tensor.read().iter(),
+                ) {
+                    backward_lhs(lhs_x, rhs_x, res, grad, lhs_grad);
+                }
+            }
+        }
+        (Expression::Tensor(lhs_tensor), Expression::Tensor(rhs_tensor)) => {
+            if let Some(rhs_sum_grad) = grads.or_insert(rhs_tensor) {
+                broadcast_grad(rhs_sum_grad, grad.len(), |rhs_sum_grad| {
                     for (rhs_grad, res, grad, lhs_x, rhs_x) in itertools::izip!(
                         rhs_sum_grad.iter_mut(),
-                        tensor.values().read().unwrap().iter(),
+                        tensor.read().iter(),
                         grad.iter(),
-                        lhs_tensor.values().read().unwrap().iter(),
-                        rhs_tensor.values().read().unwrap().iter(),
+                        broadcast(&lhs_tensor.read(), grad.len()),
+                        broadcast(&rhs_tensor.read(), grad.len()),
                     ) {
                         backward_rhs(lhs_x, rhs_x, res, grad, rhs_grad);
                     }
-                }
-                if let Some(lhs_sum_grad) = grads.or_insert(lhs_tensor) {
+                });
+            }
+            if let Some(lhs_sum_grad) = grads.or_insert(lhs_tensor) {
+                broadcast_grad(lhs_sum_grad, grad.len(), |lhs_sum_grad| {
                     for (lhs_grad, res, grad, lhs_x, rhs_x) in itertools::izip!(
                         lhs_sum_grad.iter_mut(),
-                        tensor.values().read().unwrap().iter(),
+                        tensor.read().iter(),
                         grad.iter(),
-                        lhs_tensor.values().read().unwrap().iter(),
-                        rhs_tensor.values().read().unwrap().iter(),
+                        broadcast(&lhs_tensor.read(), grad.len()),
+                        broadcast(&rhs_tensor.read(), grad.len()),
                     ) {
                         backward_lhs(lhs_x, rhs_x, res, grad, lhs_grad);
                     }
-                }
+                });
             }
         }
     }
 }
+
+/// Run `backward` on the gradient of a length-`len` output,
+/// a broadcast length-1 input gets the sum of the `len` gradients
+#[inline]
+fn broadcast_grad(sum_grad: &mut [f64], len: usize, backward: impl FnOnce(&mut [f64])) {
+    if sum_grad.len() == len {
+        backward(sum_grad);
+    } else {
+        debug_assert_eq!(sum_grad.len(), 1, "tensor length mismatch!");
+        let mut local = vec![0.0; len];
+        backward(&mut local);
+        sum_grad[0] += local.iter().sum::<f64>();
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering::Relaxed},
+    PoisonError, RwLock, RwLockReadGuard,
+};
+
+use super::{Error, TensorRef};
+
+/// Lower / upper bound of a parameter, see [`TensorRef::set_bounds`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Bound {
+    #[default]
+    Unbounded,
+    /// The same bound of all the elements
+    Scalar(f64),
+    /// One bound per element
+    PerElement(Vec<f64>),
+}
+
+impl Bound {
+    #[inline]
+    fn get(&self, i: usize) -> Option<f64> {
+        match self {
+            Self::Unbounded => None,
+            Self::Scalar(bound) => Some(*bound),
+            Self::PerElement(bounds) => Some(bounds[i]),
+        }
+    }
+    #[inline]
+    fn check_len(&self, len: usize, op: &str) -> Result<(), Error> {
+        match self {
+            Self::PerElement(bounds) if bounds.len() != len => Err(Error::LengthMismatch {
+                expected: len,
+                got: bounds.len(),
+                op: op.to_owned(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Box constraint of a tensor
+#[derive(Debug, Default)]
+pub(super) struct Bounds {
+    range: RwLock<(Bound, Bound)>,
+    /// Error on violation in [`TensorRef::update`]
+    strict: AtomicBool,
+}
+
+impl Bounds {
+    #[inline]
+    fn range(&self) -> RwLockReadGuard<'_, (Bound, Bound)> {
+        self.range.read().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Copy the range and the strict mode of `other`
+    #[inline]
+    pub(super) fn copy_from(&self, other: &Self) {
+        *self.range.write().unwrap_or_else(PoisonError::into_inner) = other.range().clone();
+        self.strict.store(other.is_strict(), Relaxed);
+    }
+    #[inline]
+    pub(super) fn is_strict(&self) -> bool {
+        self.strict.load(Relaxed)
+    }
+    /// [`Error::OutOfBounds`] of the first element out of bounds
+    #[inline]
+    pub(super) fn check(&self, values: &[f64], op: &str) -> Result<(), Error> {
+        let (lo, hi) = &*self.range();
+        match values.iter().enumerate().position(|(i, x)| {
+            lo.get(i).is_some_and(|lo| *x < lo) || hi.get(i).is_some_and(|hi| *x > hi)
+        }) {
+            Some(index) => Err(Error::OutOfBounds {
+                index,
+                op: op.to_owned(),
+            }),
+            None => Ok(()),
+        }
+    }
+    /// [`check`](Bounds::check) under [strict mode](TensorRef::set_strict_bounds),
+    /// [`Error::LengthMismatch`] when a per-element bound does not match the values
+    #[inline]
+    pub(super) fn check_strict(&self, values: &[f64], op: &str) -> Result<(), Error> {
+        if !self.is_strict() {
+            return Ok(());
+        }
+        {
+            let (lo, hi) = &*self.range();
+            lo.check_len(values.len(), op)?;
+            hi.check_len(values.len(), op)?;
+        }
+        self.check(values, op)
+    }
+    /// Clamp the values into the bounds
+    #[inline]
+    pub(super) fn project(&self, values: &mut [f64]) {
+        let (lo, hi) = &*self.range();
+        values.iter_mut().enumerate().for_each(|(i, x)| {
+            if let Some(lo) = lo.get(i) {
+                *x = x.max(lo);
+            }
+            if let Some(hi) = hi.get(i) {
+                *x = x.min(hi);
+            }
+        });
+    }
+}
+
+impl TensorRef {
+    /// Set the box constraint `lo <= x <= hi`, which is enforced by
+    /// + [`update_projected`](TensorRef::update_projected) and the shipped [optimizers](super::optimizer)
+    /// + [`update`](TensorRef::update) under [strict mode](TensorRef::set_strict_bounds)
+    ///
+    /// The current values are not modified, and the gradients are not affected
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_set_bounds`](TensorRef::try_set_bounds)
+    #[inline]
+    pub fn set_bounds(&self, lo: Bound, hi: Bound) {
+        self.try_set_bounds(lo, hi)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`set_bounds`](TensorRef::set_bounds)
+    ///
+    /// + [`Error::LengthMismatch`] when a per-element bound has a different length
+    /// + [`Error::InvalidBounds`] when `lo > hi` (or NaN) of any element
+    #[inline]
+    pub fn try_set_bounds(&self, lo: Bound, hi: Bound) -> Result<(), Error> {
+        let len = self.0.read().len();
+        lo.check_len(len, "set_bounds")?;
+        hi.check_len(len, "set_bounds")?;
+        for i in 0..len {
+            let (lo, hi) = (lo.get(i), hi.get(i));
+            if lo.is_some_and(f64::is_nan)
+                || hi.is_some_and(f64::is_nan)
+                || lo.zip(hi).is_some_and(|(lo, hi)| lo > hi)
+            {
+                return Err(Error::InvalidBounds(format!(
+                    "lo {lo:?} > hi {hi:?} at index {i}"
+                )));
+            }
+        }
+        *self
+            .0
+            .bounds()
+            .range
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = (lo, hi);
+        Ok(())
+    }
+    /// In strict mode, [`update`](TensorRef::update) / [`assign`](TensorRef::assign) panic
+    /// and [`try_update`](TensorRef::try_update) / [`try_assign`](TensorRef::try_assign)
+    /// return [`Error::OutOfBounds`] when the new values violate the bounds,
+    /// the values are not modified
+    #[inline]
+    pub fn set_strict_bounds(&self, strict: bool) {
+        self.0.bounds().strict.store(strict, Relaxed);
+    }
+    /// Need [`before_update`](super::before_update) before calling this
+    ///
+    /// Need [`Expression::value`](super::Expression::value) after calling this
+    ///
+    /// Tensor\[i\] = clamp(Tensor\[i\] + delta\[i\], lo\[i\], hi\[i\])
+    #[inline]
+    pub fn update_projected(&self, delta: &[f64]) {
+        let mut write = self.0.write();
+        itertools::zip_eq(write.iter_mut(), delta).for_each(|(x, d)| *x += d);
+        self.0.bounds().project(&mut write);
+        self.0.change_marker().mark_searched_change();
+    }
+}
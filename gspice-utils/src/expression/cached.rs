@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+
+use super::{Expression, Op, SharpnessHandle, Tensor};
+
+/// The values and the dependency versions (and sharpness) they were computed at
+#[derive(Debug)]
+struct Snapshot {
+    versions: Vec<usize>,
+    sharpness: Vec<f64>,
+    values: Vec<f64>,
+}
+
+/// Memoized [`Expression::value`], keyed on the [versions](Tensor::version)
+/// of the leaf tensors the expression depends on, and on the values of the
+/// [`SharpnessHandle`] of its comparisons, see [`SmoothForward::Always`](super::SmoothForward::Always)
+///
+/// For the repeated queries of the same values within one step,
+/// e.g., the probes of a line search, which then skip the search of the graph
+///
+/// ``` text
+/// let cached = CachedExpression::new(loss);
+/// let a = cached.value(); // evaluate
+/// let b = cached.value(); // cached
+/// before_update();
+/// x_ref.update(&delta);
+/// let c = cached.value(); // evaluate again
+/// ```
+#[derive(Debug)]
+pub struct CachedExpression {
+    expr: Expression,
+    /// The leaf tensors, discovered once at construction
+    leaves: Vec<Tensor>,
+    /// The distinct sharpness of the comparisons, discovered with the leaves
+    sharpness: Vec<SharpnessHandle>,
+    snapshot: Mutex<Option<Snapshot>>,
+}
+
+impl CachedExpression {
+    #[inline]
+    pub fn new(expr: Expression) -> Self {
+        let (leaves, sharpness) = dependencies(&expr);
+        Self {
+            expr,
+            leaves,
+            sharpness,
+            snapshot: Mutex::new(None),
+        }
+    }
+    #[inline]
+    pub fn expr(&self) -> &Expression {
+        &self.expr
+    }
+    /// The values of the expression, a constant is length-1
+    ///
+    /// Evaluated by [`Expression::value`] only when any dependency has been updated
+    /// (or any sharpness set to another value) since the last evaluation,
+    /// otherwise the cached values
+    pub fn value(&self) -> Vec<f64> {
+        let mut snapshot = self.snapshot.lock().unwrap_or_else(PoisonError::into_inner);
+        let versions: Vec<usize> = self.leaves.iter().map(Tensor::version).collect();
+        let sharpness: Vec<f64> = self.sharpness.iter().map(SharpnessHandle::get).collect();
+        match &*snapshot {
+            Some(cached) if cached.versions == versions && cached.sharpness == sharpness => {
+                cached.values.clone()
+            }
+            _ => {
+                let values = match &self.expr {
+                    Expression::Const(x) => vec![*x],
+                    Expression::Tensor(tensor) => {
+                        _ = self.expr.value();
+                        tensor.read().clone()
+                    }
+                };
+                *snapshot = Some(Snapshot {
+                    versions,
+                    sharpness,
+                    values: values.clone(),
+                });
+                values
+            }
+        }
+    }
+    /// Drop the cached values, the next [`value`](CachedExpression::value) evaluates
+    #[inline]
+    pub fn invalidate(&self) {
+        *self.snapshot.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// The distinct leaf tensors of the expression, and the distinct sharpness of its comparisons
+fn dependencies(root: &Expression) -> (Vec<Tensor>, Vec<SharpnessHandle>) {
+    let mut visited = HashSet::new();
+    let mut leaves = Vec::new();
+    let mut sharpness: Vec<SharpnessHandle> = Vec::new();
+    let mut stack = vec![root];
+    while let Some(expr) = stack.pop() {
+        let Expression::Tensor(tensor) = expr else {
+            continue;
+        };
+        if !visited.insert(tensor.key()) {
+            continue;
+        }
+        match tensor.op() {
+            Op::Assign => leaves.push(tensor.clone()),
+            op => {
+                if let Op::DiscreteBinary(_, _, _, grad_method) = op {
+                    if let Some(handle) = grad_method.sharpness() {
+                        if !sharpness.iter().any(|seen| seen.ptr_eq(handle)) {
+                            sharpness.push(handle.clone());
+                        }
+                    }
+                }
+                stack.extend(op.inputs());
+            }
+        }
+    }
+    (leaves, sharpness)
+}
@@ -0,0 +1,338 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+use super::{Expression, Op, ScalarTensor, Tensor};
+
+thread_local! {
+    /// Recomputing released tensors for the backward or [`Expression::materialize`],
+    /// their inputs are not released after being read
+    static MATERIALIZING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Which values of the graph are kept after a forward pass, see [`Expression::set_retention`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Retention {
+    /// Keep every value, the default
+    #[default]
+    All,
+    /// Keep only the output (and the leaf tensors)
+    OutputsOnly,
+    /// Keep the output and the [checkpoints](Expression::checkpoint)
+    Checkpoints,
+}
+
+/// Value buffers of a graph, see [`Expression::memory_stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Distinct tensors, including the leaves
+    pub nodes: usize,
+    /// Tensors whose values are released
+    pub released_nodes: usize,
+    /// Bytes of the values held by the tensors
+    pub retained_bytes: usize,
+    /// Bytes of the released values, recomputed on demand
+    pub released_bytes: usize,
+}
+
+/// Retention of the values of an op tensor, see [`Expression::checkpoint`]
+#[derive(Debug, Default)]
+pub(super) struct Retain {
+    /// Recomputation boundary, the values are always kept
+    checkpoint: AtomicBool,
+    /// Released by [`Expression::forward_no_retain`], and again once back-propagated
+    no_retain: AtomicBool,
+    /// The values are dropped, only the length is kept to recompute them
+    released: AtomicBool,
+    len: AtomicUsize,
+    /// Consumers within the graph of [`Expression::set_retention`]
+    consumers: AtomicUsize,
+    /// Consumers yet to read the values in the current forward pass
+    pending: AtomicUsize,
+    /// Some inputs are released once all their consumers have read them
+    release_inputs: AtomicBool,
+    /// Built by [`lazy_build`](super::lazy_build), released until its first computation
+    lazy: AtomicBool,
+}
+
+impl Retain {
+    /// Copy the flags and the released length of `other`
+    #[inline]
+    pub(super) fn copy_from(&self, other: &Self) {
+        self.checkpoint
+            .store(other.checkpoint.load(Relaxed), Relaxed);
+        self.no_retain.store(other.no_retain.load(Relaxed), Relaxed);
+        self.released.store(other.released.load(Relaxed), Relaxed);
+        self.len.store(other.len.load(Relaxed), Relaxed);
+        self.consumers.store(other.consumers.load(Relaxed), Relaxed);
+        self.release_inputs
+            .store(other.release_inputs.load(Relaxed), Relaxed);
+        self.lazy.store(other.lazy.load(Relaxed), Relaxed);
+    }
+    /// Released with the length `len`, before the first computation, see [`lazy_build`](super::lazy_build)
+    #[inline]
+    pub(super) fn set_lazy(&self, len: usize) {
+        self.len.store(len, Relaxed);
+        self.released.store(true, Relaxed);
+        self.lazy.store(true, Relaxed);
+    }
+}
+
+impl Expression {
+    /// Mark the node as a recomputation boundary of [`forward_no_retain`](Expression::forward_no_retain):
+    /// its values are kept, and the released nodes above it are recomputed from it
+    /// when [`backward`](Expression::backward) reaches them
+    ///
+    /// No effect on constants and leaf tensors, which are always kept
+    #[inline]
+    pub fn checkpoint(&self) -> Self {
+        if let Self::Tensor(tensor) = self {
+            tensor.retain().checkpoint.store(true, Relaxed);
+        }
+        self.clone()
+    }
+    /// [`value`](Expression::value), then drop the values of the intermediate nodes
+    /// except the [checkpoints](Expression::checkpoint) and `self`
+    ///
+    /// The dropped values are recomputed segment by segment during [`backward`](Expression::backward)
+    /// and dropped again once back-propagated, so the peak memory is about the checkpoints plus
+    /// the longest segment. A later [`value`](Expression::value) recomputes and keeps them
+    pub fn forward_no_retain(&self) -> ScalarTensor<'_> {
+        _ = self.value();
+        let Self::Tensor(root) = self else {
+            return self.value();
+        };
+        let mut visited = HashSet::new();
+        let mut stack: Vec<&Tensor> = vec![root];
+        while let Some(tensor) = stack.pop() {
+            if !visited.insert(tensor.key()) {
+                continue;
+            }
+            let op = tensor.op();
+            if matches!(op, Op::Assign) {
+                continue;
+            }
+            if tensor != root && !tensor.retain().checkpoint.load(Relaxed) {
+                tensor.retain().no_retain.store(true, Relaxed);
+                tensor.release();
+            }
+            stack.extend(op.inputs().into_iter().filter_map(|input| match input {
+                Expression::Tensor(input) => Some(input),
+                Expression::Const(_) => None,
+            }));
+        }
+        self.value()
+    }
+    /// Select which values of the graph are kept after a forward pass
+    ///
+    /// Under [`Retention::OutputsOnly`] and [`Retention::Checkpoints`], the values of the other
+    /// intermediate nodes are released at once, and in the later forward passes, as soon as
+    /// all their consumers have read them. They are recomputed on demand, i.e., by the
+    /// [`backward`](Expression::backward) like [`forward_no_retain`](Expression::forward_no_retain),
+    /// by the consumers outside the graph, or by [`materialize`](Expression::materialize).
+    /// [`Retention::All`] materializes and keeps them again
+    ///
+    /// Leaf tensors are always kept. See [`memory_stats`](Expression::memory_stats) for the savings
+    pub fn set_retention(&self, retention: Retention) {
+        let Self::Tensor(root) = self else {
+            return;
+        };
+        let tensors = self.tensors();
+        let mut consumers: HashMap<usize, usize> = HashMap::new();
+        for tensor in &tensors {
+            for input in tensor.op().inputs() {
+                if let Expression::Tensor(input) = input {
+                    *consumers.entry(input.key()).or_default() += 1;
+                }
+            }
+        }
+        for tensor in &tensors {
+            let retain = tensor.retain();
+            let keep = tensor == root
+                || matches!(tensor.op(), Op::Assign)
+                || match retention {
+                    Retention::All => true,
+                    Retention::OutputsOnly => false,
+                    Retention::Checkpoints => retain.checkpoint.load(Relaxed),
+                };
+            retain.no_retain.store(!keep, Relaxed);
+            retain
+                .consumers
+                .store(consumers.get(&tensor.key()).copied().unwrap_or(0), Relaxed);
+            retain.pending.store(0, Relaxed);
+        }
+        for tensor in &tensors {
+            let release_inputs = tensor.op().inputs().into_iter().any(|input| {
+                matches!(input, Expression::Tensor(input) if input.retain().no_retain.load(Relaxed))
+            });
+            tensor
+                .retain()
+                .release_inputs
+                .store(release_inputs, Relaxed);
+        }
+        if retention == Retention::All {
+            self.materialize();
+        } else {
+            tensors.iter().for_each(Tensor::release_if_no_retain);
+        }
+    }
+    /// Recompute the released values of the graph from the kept ones, without searching
+    /// for updates. They are kept until the next forward pass or back-propagation
+    /// under [`Retention::OutputsOnly`] and [`Retention::Checkpoints`]
+    pub fn materialize(&self) {
+        let mut released = self.tensors();
+        released.retain(Tensor::is_released);
+        Tensor::materialize(&released);
+    }
+    /// Sizes of the held and released value buffers of the graph
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.tensors()
+            .iter()
+            .fold(MemoryStats::default(), |mut stats, tensor| {
+                stats.nodes += 1;
+                if tensor.is_released() {
+                    stats.released_nodes += 1;
+                    stats.released_bytes += tensor.retain().len.load(Relaxed) * size_of::<f64>();
+                } else {
+                    stats.retained_bytes += tensor.read().len() * size_of::<f64>();
+                }
+                stats
+            })
+    }
+    /// Distinct tensors of the graph
+    fn tensors(&self) -> Vec<Tensor> {
+        let Self::Tensor(root) = self else {
+            return Vec::new();
+        };
+        let mut visited = HashSet::new();
+        let mut tensors = Vec::new();
+        let mut stack: Vec<&Tensor> = vec![root];
+        while let Some(tensor) = stack.pop() {
+            if !visited.insert(tensor.key()) {
+                continue;
+            }
+            tensors.push(tensor.clone());
+            stack.extend(
+                tensor
+                    .op()
+                    .inputs()
+                    .into_iter()
+                    .filter_map(|input| match input {
+                        Expression::Tensor(input) => Some(input),
+                        Expression::Const(_) => None,
+                    }),
+            );
+        }
+        tensors
+    }
+}
+
+impl Tensor {
+    #[inline]
+    pub(super) fn is_released(&self) -> bool {
+        self.retain().released.load(Relaxed)
+    }
+    /// The length of the values, also of the released ones
+    #[inline]
+    pub(super) fn values_len(&self) -> usize {
+        if self.is_released() {
+            self.retain().len.load(Relaxed)
+        } else {
+            self.read().len()
+        }
+    }
+    #[inline]
+    fn release(&self) {
+        if !self.is_released() {
+            let mut write = self.write();
+            self.retain().len.store(write.len(), Relaxed);
+            *write = Vec::new();
+            self.retain().released.store(true, Relaxed);
+        }
+    }
+    /// Drop the values again once back-propagated, for [`Expression::forward_no_retain`]
+    #[inline]
+    pub(super) fn release_if_no_retain(&self) {
+        if self.retain().no_retain.load(Relaxed) {
+            self.release();
+        }
+    }
+    /// After a forward computation of the tensor, which has read its inputs:
+    /// release the inputs read by all their consumers, see [`Expression::set_retention`]
+    #[inline]
+    pub(super) fn release_consumed_inputs(&self) {
+        let retain = self.retain();
+        retain
+            .pending
+            .store(retain.consumers.load(Relaxed), Relaxed);
+        if retain.release_inputs.load(Relaxed) && !MATERIALIZING.get() {
+            for input in self.op().inputs() {
+                if let Expression::Tensor(input) = input {
+                    let last = input
+                        .retain()
+                        .pending
+                        .fetch_update(Relaxed, Relaxed, |pending| pending.checked_sub(1))
+                        == Ok(1);
+                    if last {
+                        input.release_if_no_retain();
+                    }
+                }
+            }
+        }
+    }
+    /// Compute the values of a released tensor from the current values of its inputs
+    fn force_compute(&self) {
+        self.retain().lazy.store(false, Relaxed);
+        *self.write() = vec![0.0; self.retain().len.load(Relaxed)];
+        self.retain().released.store(false, Relaxed);
+        self.search_forced();
+    }
+    /// [`recompute`](Expression::recompute) of a released tensor: bring the inputs up to date,
+    /// then compute the values regardless of the change markers
+    pub(super) fn recompute_released(&self) {
+        for input in self.op().inputs() {
+            _ = input.recompute();
+        }
+        self.force_compute();
+    }
+    /// Recompute the released inputs (and the released nodes below them) from the kept ones,
+    /// without searching for updates, for the back-propagation
+    pub(super) fn materialize_inputs(&self) {
+        let inputs = self.released_inputs();
+        if !inputs.is_empty() {
+            Self::materialize(&inputs);
+        }
+    }
+    fn released_inputs(&self) -> Vec<Tensor> {
+        self.op()
+            .inputs()
+            .into_iter()
+            .filter_map(|input| match input {
+                Expression::Tensor(input) if input.is_released() => Some(input.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+    /// Recompute the released tensors, and the released nodes below them, from the kept ones
+    fn materialize(released: &[Tensor]) {
+        // post order, so that the inputs are computed first
+        let mut visited = HashSet::new();
+        let mut order = Vec::new();
+        let mut stack: Vec<(Tensor, bool)> = released
+            .iter()
+            .map(|tensor| (tensor.clone(), false))
+            .collect();
+        while let Some((tensor, expanded)) = stack.pop() {
+            if expanded {
+                order.push(tensor);
+            } else if visited.insert(tensor.key()) {
+                let inputs = tensor.released_inputs();
+                stack.push((tensor, true));
+                stack.extend(inputs.into_iter().map(|input| (input, false)));
+            }
+        }
+        let materializing = MATERIALIZING.replace(true);
+        order.iter().for_each(Tensor::force_compute);
+        MATERIALIZING.set(materializing);
+    }
+}
@@ -0,0 +1,212 @@
+use itertools::izip;
+
+use super::{
+    op::{broadcast_len, Op},
+    Error, Expression, Tensor,
+};
+
+/// Complex number as a pair of real expressions, e.g., an AC phasor
+///
+/// The real and imaginary parts broadcast against each other like a binary op.
+/// [`ComplexExpr::mul`], [`ComplexExpr::div`] and [`ComplexExpr::abs`] are fused
+/// into one node per output part
+#[derive(Clone, Debug)]
+pub struct ComplexExpr {
+    pub re: Expression,
+    pub im: Expression,
+}
+
+/// Fused complex op, one real output part per node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComplexOp {
+    /// `Re(z w)` of the inputs `[z.re, z.im, w.re, w.im]`
+    MulRe,
+    /// `Im(z w)` of the inputs `[z.re, z.im, w.re, w.im]`
+    MulIm,
+    /// `Re(z / w)` of the inputs `[z.re, z.im, w.re, w.im]`
+    DivRe,
+    /// `Im(z / w)` of the inputs `[z.re, z.im, w.re, w.im]`
+    DivIm,
+    /// `|z|` of the inputs `[z.re, z.im]`
+    Abs,
+}
+
+/// `(a + ib) / (c + id)` by Smith's formulation, which divides by the larger
+/// of `|c|` and `|d|` first, so that `c² + d²` never overflows
+#[inline]
+fn smith_div(a: f64, b: f64, c: f64, d: f64) -> (f64, f64) {
+    if d.abs() <= c.abs() {
+        let r = d / c;
+        let den = c + d * r;
+        ((a + b * r) / den, (b - a * r) / den)
+    } else {
+        let r = c / d;
+        let den = c * r + d;
+        ((a * r + b) / den, (b * r - a) / den)
+    }
+}
+
+impl ComplexOp {
+    #[inline]
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Self::MulRe => "ComplexMulRe",
+            Self::MulIm => "ComplexMulIm",
+            Self::DivRe => "ComplexDivRe",
+            Self::DivIm => "ComplexDivIm",
+            Self::Abs => "ComplexAbs",
+        }
+    }
+    #[inline]
+    fn forward(&self, x: &[f64]) -> f64 {
+        match self {
+            Self::MulRe => x[0] * x[2] - x[1] * x[3],
+            Self::MulIm => x[0] * x[3] + x[1] * x[2],
+            Self::DivRe => smith_div(x[0], x[1], x[2], x[3]).0,
+            Self::DivIm => smith_div(x[0], x[1], x[2], x[3]).1,
+            Self::Abs => x[0].hypot(x[1]),
+        }
+    }
+    /// Partial derivatives of the output part w.r.t. each input
+    ///
+    /// `z w` and `z / w` are holomorphic, so with the complex derivatives `∂/∂z` and `∂/∂w`
+    /// the Cauchy-Riemann equations give all the real partials
+    #[inline]
+    pub(super) fn partials(&self, x: &[f64]) -> [f64; 4] {
+        let (dz, dw) = match self {
+            Self::MulRe | Self::MulIm => ((x[2], x[3]), (x[0], x[1])),
+            Self::DivRe | Self::DivIm => {
+                let inv = smith_div(1.0, 0.0, x[2], x[3]);
+                let q = smith_div(x[0], x[1], x[2], x[3]);
+                // ∂(z/w)/∂w = -q/w
+                let dw = (-(q.0 * inv.0 - q.1 * inv.1), -(q.0 * inv.1 + q.1 * inv.0));
+                (inv, dw)
+            }
+            Self::Abs => {
+                let r = x[0].hypot(x[1]);
+                return if r > 0.0 {
+                    [x[0] / r, x[1] / r, 0.0, 0.0]
+                } else {
+                    [0.0; 4]
+                };
+            }
+        };
+        match self {
+            Self::MulRe | Self::DivRe => [dz.0, -dz.1, dw.0, -dw.1],
+            _ => [dz.1, dz.0, dw.1, dw.0],
+        }
+    }
+    /// Element-wise output of the input values, broadcasted
+    #[inline]
+    pub(super) fn iter(&self, inputs: &[Vec<f64>]) -> Result<Vec<f64>, Error> {
+        let len = broadcast_len(self.name(), inputs.iter().map(Vec::len))?;
+        let mut x = vec![0.0; inputs.len()];
+        Ok((0..len)
+            .map(|i| {
+                for (x, values) in izip!(x.iter_mut(), inputs) {
+                    *x = values[i % values.len()];
+                }
+                self.forward(&x)
+            })
+            .collect())
+    }
+    fn try_apply(&self, inputs: Vec<Expression>) -> Result<Expression, Error> {
+        let values: Vec<Vec<f64>> = inputs.iter().map(Expression::conv_values).collect();
+        if inputs
+            .iter()
+            .all(|input| matches!(input, Expression::Const(_)))
+        {
+            let x: Vec<f64> = values.iter().map(|v| v[0]).collect();
+            return Ok(Expression::Const(self.forward(&x)));
+        }
+        let values = self.iter(&values)?;
+        let with_grad = inputs
+            .iter()
+            .any(|expr| matches!(expr, Expression::Tensor(tensor) if tensor.with_grad()));
+        Ok(Expression::Tensor(Tensor::new(
+            with_grad,
+            values,
+            Op::Complex(inputs, *self),
+        )))
+    }
+}
+
+impl ComplexExpr {
+    #[inline]
+    pub fn new(re: Expression, im: Expression) -> Self {
+        Self { re, im }
+    }
+    #[inline]
+    fn pair_inputs(&self, rhs: &Self) -> Vec<Expression> {
+        vec![
+            self.re.clone(),
+            self.im.clone(),
+            rhs.re.clone(),
+            rhs.im.clone(),
+        ]
+    }
+    /// `self * rhs`, see [`ComplexExpr::try_mul`]
+    #[inline]
+    pub fn mul(&self, rhs: &Self) -> Self {
+        self.try_mul(rhs).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// `self * rhs` as two fused nodes
+    ///
+    /// [`Error::LengthMismatch`] when the parts cannot broadcast
+    #[inline]
+    pub fn try_mul(&self, rhs: &Self) -> Result<Self, Error> {
+        Ok(Self {
+            re: ComplexOp::MulRe.try_apply(self.pair_inputs(rhs))?,
+            im: ComplexOp::MulIm.try_apply(self.pair_inputs(rhs))?,
+        })
+    }
+    /// `self / rhs`, see [`ComplexExpr::try_div`]
+    #[inline]
+    pub fn div(&self, rhs: &Self) -> Self {
+        self.try_div(rhs).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// `self / rhs` as two fused nodes, by Smith's formulation, which stays finite
+    /// when `|rhs|²` would overflow or underflow
+    ///
+    /// [`Error::LengthMismatch`] when the parts cannot broadcast
+    #[inline]
+    pub fn try_div(&self, rhs: &Self) -> Result<Self, Error> {
+        Ok(Self {
+            re: ComplexOp::DivRe.try_apply(self.pair_inputs(rhs))?,
+            im: ComplexOp::DivIm.try_apply(self.pair_inputs(rhs))?,
+        })
+    }
+    /// Magnitude `|self|`, see [`ComplexExpr::try_abs`]
+    #[inline]
+    pub fn abs(&self) -> Expression {
+        self.try_abs().unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Magnitude `|self|` as one fused node by `hypot`, the gradient is zero at the origin
+    ///
+    /// [`Error::LengthMismatch`] when the parts cannot broadcast
+    #[inline]
+    pub fn try_abs(&self) -> Result<Expression, Error> {
+        ComplexOp::Abs.try_apply(vec![self.re.clone(), self.im.clone()])
+    }
+    /// Phase angle in `(-π, π]` by [`Expression::atan2`], the gradient is zero at the origin
+    #[inline]
+    pub fn arg(&self) -> Expression {
+        self.im.atan2(&self.re)
+    }
+    /// Complex conjugate
+    #[inline]
+    pub fn conj(&self) -> Self {
+        Self {
+            re: self.re.clone(),
+            im: self.im.neg(),
+        }
+    }
+    /// Scale both parts by the real factor
+    #[inline]
+    pub fn scale(&self, factor: &Expression) -> Self {
+        Self {
+            re: self.re.mul(factor),
+            im: self.im.mul(factor),
+        }
+    }
+}
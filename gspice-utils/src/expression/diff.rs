@@ -0,0 +1,251 @@
+use core::fmt;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
+
+use super::{snapshot::tagged_name, Expression, GradId, Op};
+
+/// Max entries printed by the [`Display`](fmt::Display) of [`GraphDiff`]
+const DISPLAY_LIMIT: usize = 16;
+
+/// Structural difference of one node, see [`Expression::diff`]
+///
+/// The path is the input indices from the root, e.g., `$.1.0`
+/// is the first input of the second input of the root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeDiff {
+    /// Only in `other`
+    Added { path: String, op: String },
+    /// Only in `self`
+    Removed { path: String, op: String },
+    /// Another op (or constant) at the same path
+    Changed {
+        path: String,
+        from: String,
+        to: String,
+    },
+}
+
+impl fmt::Display for NodeDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { path, op } => write!(f, "+ {path}: {op}"),
+            Self::Removed { path, op } => write!(f, "- {path}: {op}"),
+            Self::Changed { path, from, to } => write!(f, "~ {path}: {from} -> {to}"),
+        }
+    }
+}
+
+/// Structural differences of two compute graphs, see [`Expression::diff`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GraphDiff {
+    /// The ops added, removed or changed
+    pub nodes: Vec<NodeDiff>,
+    /// `(path, id)` of the leaf tensors only in `self`
+    pub params_removed: Vec<(String, GradId)>,
+    /// `(path, id)` of the leaf tensors only in `other`
+    pub params_added: Vec<(String, GradId)>,
+}
+
+impl GraphDiff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty() && self.params_removed.is_empty() && self.params_added.is_empty()
+    }
+}
+
+/// At most [`DISPLAY_LIMIT`] entries, then the number of the omitted ones
+impl fmt::Display for GraphDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "identical graphs");
+        }
+        write!(
+            f,
+            "{} node(s) differ, {} parameter(s) removed, {} parameter(s) added",
+            self.nodes.len(),
+            self.params_removed.len(),
+            self.params_added.len()
+        )?;
+        let lines = self
+            .nodes
+            .iter()
+            .map(ToString::to_string)
+            .chain(
+                self.params_removed
+                    .iter()
+                    .map(|(path, id)| format!("- {path}: parameter {id:?}")),
+            )
+            .chain(
+                self.params_added
+                    .iter()
+                    .map(|(path, id)| format!("+ {path}: parameter {id:?}")),
+            );
+        let total = self.nodes.len() + self.params_removed.len() + self.params_added.len();
+        for line in lines.take(DISPLAY_LIMIT) {
+            write!(f, "\n  {line}")?;
+        }
+        if total > DISPLAY_LIMIT {
+            write!(f, "\n  ... and {} more", total - DISPLAY_LIMIT)?;
+        }
+        Ok(())
+    }
+}
+
+/// The op of the node and the hash of its [parameters](Op::param_bits), `Tensor` for a leaf
+fn describe(expr: &Expression) -> String {
+    match expr {
+        Expression::Const(x) => format!("Const({x})"),
+        Expression::Tensor(tensor) => match tensor.op() {
+            Op::Assign => "Tensor".to_owned(),
+            op => tagged_name(&op.name(), &op.param_bits()),
+        },
+    }
+}
+
+/// `(first path, id)` of the leaf tensors, in depth-first order
+fn leaves(root: &Expression) -> Vec<(String, GradId)> {
+    let mut visited = HashSet::new();
+    let mut leaves = Vec::new();
+    let mut stack = vec![("$".to_owned(), root)];
+    while let Some((path, expr)) = stack.pop() {
+        let Expression::Tensor(tensor) = expr else {
+            continue;
+        };
+        if !visited.insert(tensor.key()) {
+            continue;
+        }
+        if let Op::Assign = tensor.op() {
+            leaves.extend(tensor.leaf_id().map(|id| (path, id)));
+        } else {
+            stack.extend(
+                tensor
+                    .op()
+                    .inputs()
+                    .into_iter()
+                    .enumerate()
+                    .rev()
+                    .map(|(i, input)| (format!("{path}.{i}"), input)),
+            );
+        }
+    }
+    leaves
+}
+
+impl Expression {
+    /// Structural differences from `other`, by the path from the roots
+    ///
+    /// The ops are compared by kind and parameters (as the [anomaly report](super::AnomalyReport)
+    /// names them, with a hash of the tables and the comparison methods), the leaf tensors by identity: a rebuilt parameter shows up
+    /// as removed and added. A shared subtree is compared once
+    pub fn diff(&self, other: &Self) -> GraphDiff {
+        let mut nodes = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![("$".to_owned(), self, other)];
+        while let Some((path, lhs, rhs)) = stack.pop() {
+            if let (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) = (lhs, rhs) {
+                if lhs_tensor == rhs_tensor || !visited.insert((lhs_tensor.key(), rhs_tensor.key()))
+                {
+                    continue;
+                }
+            }
+            let (from, to) = (describe(lhs), describe(rhs));
+            if from != to {
+                nodes.push(NodeDiff::Changed {
+                    path: path.clone(),
+                    from,
+                    to,
+                });
+            }
+            let (lhs_inputs, rhs_inputs) = match (lhs, rhs) {
+                (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) => {
+                    (lhs_tensor.op().inputs(), rhs_tensor.op().inputs())
+                }
+                _ => continue,
+            };
+            for (i, input) in lhs_inputs.iter().enumerate().skip(rhs_inputs.len()) {
+                nodes.push(NodeDiff::Removed {
+                    path: format!("{path}.{i}"),
+                    op: describe(input),
+                });
+            }
+            for (i, input) in rhs_inputs.iter().enumerate().skip(lhs_inputs.len()) {
+                nodes.push(NodeDiff::Added {
+                    path: format!("{path}.{i}"),
+                    op: describe(input),
+                });
+            }
+            stack.extend(
+                lhs_inputs
+                    .into_iter()
+                    .zip(rhs_inputs)
+                    .enumerate()
+                    .rev()
+                    .map(|(i, (lhs, rhs))| (format!("{path}.{i}"), lhs, rhs)),
+            );
+        }
+        let (lhs_leaves, rhs_leaves) = (leaves(self), leaves(other));
+        let only_in = |leaves: Vec<(String, GradId)>, others: &[(String, GradId)]| {
+            let others: HashSet<GradId> = others.iter().map(|(_, id)| *id).collect();
+            leaves
+                .into_iter()
+                .filter(|(_, id)| !others.contains(id))
+                .collect()
+        };
+        GraphDiff {
+            nodes,
+            params_removed: only_in(lhs_leaves.clone(), &rhs_leaves),
+            params_added: only_in(rhs_leaves, &lhs_leaves),
+        }
+    }
+    /// Hash of the structure: the ops with their [parameters](Op::param_bits), e.g., the tables
+    /// and the comparison methods, the constants and the identities of the leaf tensors
+    ///
+    /// Stable for the same graph across the iterations (the values are not hashed),
+    /// and changes when any part is rebuilt around a new parameter
+    pub fn fingerprint(&self) -> u64 {
+        let Self::Tensor(root) = self else {
+            return hash_node(&describe(self), ());
+        };
+        let mut memo: HashMap<usize, u64> = HashMap::new();
+        let mut stack = vec![(root, false)];
+        while let Some((tensor, expanded)) = stack.pop() {
+            if memo.contains_key(&tensor.key()) {
+                continue;
+            }
+            let inputs = tensor.op().inputs();
+            if let Op::Assign = tensor.op() {
+                memo.insert(tensor.key(), hash_node("Tensor", tensor.leaf_id()));
+            } else if expanded {
+                let input_hashes: Vec<u64> = inputs
+                    .iter()
+                    .map(|input| match input {
+                        Self::Const(_) => hash_node(&describe(input), ()),
+                        Self::Tensor(input) => memo[&input.key()],
+                    })
+                    .collect();
+                let op = tensor.op();
+                memo.insert(
+                    tensor.key(),
+                    hash_node(&op.name(), (op.param_bits(), &input_hashes)),
+                );
+            } else {
+                stack.push((tensor, true));
+                stack.extend(inputs.into_iter().filter_map(|input| match input {
+                    Self::Tensor(input) => Some((input, false)),
+                    Self::Const(_) => None,
+                }));
+            }
+        }
+        memo[&root.key()]
+    }
+}
+
+#[inline]
+fn hash_node(name: &str, inputs: impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    inputs.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,158 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use super::{recompute::counter, Expression, Op, Tensor, TensorRef};
+
+/// Exponential moving average of the values of an expression, read after each evaluation,
+/// e.g., the smoothed loss of an optimization
+///
+/// `ema = alpha * x + (1 - alpha) * ema`, the first observation is taken as-is
+#[derive(Clone, Debug)]
+pub struct EmaTracker {
+    alpha: f64,
+    ema: Option<Vec<f64>>,
+    count: usize,
+}
+
+impl EmaTracker {
+    /// `0 < alpha <= 1`, the weight of the newest observation
+    #[inline]
+    pub fn new(alpha: f64) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0);
+        Self {
+            alpha,
+            ema: None,
+            count: 0,
+        }
+    }
+    /// Evaluate `expr` (see [`Expression::value`]) and blend its values into the average
+    #[inline]
+    pub fn observe(&mut self, expr: &Expression) -> &[f64] {
+        self.observe_values(&expr.values())
+    }
+    /// Blend `values` into the average, e.g., the norm of the gradients,
+    /// a different length restarts the average
+    #[inline]
+    pub fn observe_values(&mut self, values: &[f64]) -> &[f64] {
+        self.count += 1;
+        let ema = match self.ema.take() {
+            Some(ema) if ema.len() == values.len() => blend(self.alpha, values, &ema),
+            _ => values.to_vec(),
+        };
+        self.ema.insert(ema)
+    }
+    /// The average, `None` before the first observation
+    #[inline]
+    pub fn value(&self) -> Option<&[f64]> {
+        self.ema.as_deref()
+    }
+    /// The observations since the creation or the last [`reset`](EmaTracker::reset)
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    #[inline]
+    pub fn reset(&mut self) {
+        self.ema = None;
+        self.count = 0;
+    }
+}
+
+/// Persistent state of [`Expression::ema_of`], shared by its clones
+///
+/// The average is kept in a companion tensor, outside of the graph
+#[derive(Clone, Debug)]
+pub struct EmaState {
+    /// The average after the latest step, empty before the first one
+    ema: TensorRef,
+    step: Arc<Mutex<EmaStep>>,
+}
+
+/// The latest step of an [`EmaState`]
+#[derive(Debug, Default)]
+struct EmaStep {
+    /// The [`before_update`](super::before_update) epoch of the latest step
+    epoch: Option<usize>,
+    /// The average before the latest step, empty before the first one
+    prev: Vec<f64>,
+}
+
+impl Default for EmaState {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EmaState {
+    /// Without history, the first step takes the input as-is
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            ema: Expression::tensor(Vec::new(), false).1,
+            step: Arc::default(),
+        }
+    }
+    /// The average after the latest step, empty before the first one
+    #[inline]
+    pub fn values(&self) -> Vec<f64> {
+        self.ema.0.read().clone()
+    }
+    /// Forget the history, the next step takes the input as-is
+    #[inline]
+    pub fn reset(&self) {
+        *self.lock() = EmaStep::default();
+        *self.ema.0.write() = Vec::new();
+    }
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, EmaStep> {
+        self.step.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Step the average with `x`, once per epoch: a repeated forward in the same epoch
+    /// blends `x` into the same previous average again
+    pub(super) fn step(&self, alpha: f64, x: &[f64]) -> Vec<f64> {
+        let mut step = self.lock();
+        let epoch = counter();
+        if step.epoch != Some(epoch) {
+            step.epoch = Some(epoch);
+            step.prev = self.values();
+        }
+        let ema = if step.prev.len() == x.len() {
+            blend(alpha, x, &step.prev)
+        } else {
+            x.to_vec()
+        };
+        self.ema.0.write().clone_from(&ema);
+        ema
+    }
+}
+
+#[inline]
+fn blend(alpha: f64, x: &[f64], prev: &[f64]) -> Vec<f64> {
+    x.iter()
+        .zip(prev)
+        .map(|(x, prev)| alpha * x + (1.0 - alpha) * prev)
+        .collect()
+}
+
+impl Expression {
+    /// `ema = alpha * x + (1 - alpha) * ema` of the input `x`, with the previous average
+    /// kept in `state`, e.g., for an iterative relaxation, `0 < alpha <= 1`
+    ///
+    /// + The average steps once per [`before_update`](super::before_update) epoch in which
+    ///   the node is evaluated, even when the inputs are unchanged, so that it keeps
+    ///   converging; evaluating it again in the same epoch does not step it twice
+    /// + The first step (or a step with a different length) takes the input as-is
+    /// + The gradient is `alpha`, the previous average is treated as a constant
+    ///
+    /// The graph construction evaluates it, i.e., is the first step of the current epoch
+    #[inline]
+    pub fn ema_of(&self, alpha: f64, state: &EmaState) -> Self {
+        assert!(alpha > 0.0 && alpha <= 1.0);
+        let with_grad = matches!(self, Self::Tensor(tensor) if tensor.with_grad());
+        Self::Tensor(Tensor::new(
+            with_grad,
+            state.step(alpha, &self.conv_values()),
+            Op::Ema(self.clone(), alpha, state.clone()),
+        ))
+    }
+}
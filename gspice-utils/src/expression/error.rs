@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+/// Error of the fallible (`try_*`) API
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum Error {
+    /// The tensors' lengths are not equal, and neither is length-1
+    #[error("tensor length mismatch in {op}: expected {expected}, got {got}")]
+    LengthMismatch {
+        expected: usize,
+        got: usize,
+        op: String,
+    },
+    /// The index is out of the tensor's bounds
+    #[error("index {index} out of bounds for length {len} in {op}")]
+    IndexOutOfBounds {
+        index: usize,
+        len: usize,
+        op: String,
+    },
+    /// A thread panicked while holding the tensor's lock.
+    ///
+    /// The poison is cleared when this error is returned,
+    /// so the following calls will see the data as-is.
+    #[error("tensor lock poisoned")]
+    PoisonedLock,
+    /// The tensor has no element
+    #[error("empty tensor")]
+    EmptyTensor,
+    /// The table of [`Expression::pwl`](super::Expression::pwl) is invalid
+    #[error("invalid PWL table: {0}")]
+    InvalidPwl(String),
+    /// The knots of [`Expression::spline`](super::Expression::spline) are invalid
+    #[error("invalid spline knots: {0}")]
+    InvalidSpline(String),
+    /// The bins of [`Expression::soft_histogram`](super::Expression::soft_histogram) are invalid
+    #[error("invalid histogram: {0}")]
+    InvalidHistogram(String),
+    /// The time grid of [`Expression::integrate`](super::Expression::integrate) is invalid
+    #[error("invalid time grid: {0}")]
+    InvalidGrid(String),
+    /// The window of [`Expression::window_reduce`](super::Expression::window_reduce) is invalid
+    #[error("invalid window: {0}")]
+    InvalidWindow(String),
+    /// The sharpness (`k` / `ε`) of a smoothed comparison is not positive and finite,
+    /// see [`GradMethod::try_sigmoid`](super::GradMethod::try_sigmoid) and its family
+    #[error("invalid sharpness: {0}")]
+    InvalidSharpness(String),
+    /// The bounds of [`TensorRef::set_bounds`](super::TensorRef::set_bounds) are invalid
+    #[error("invalid bounds: {0}")]
+    InvalidBounds(String),
+    /// The updated value violates the bounds, see [`TensorRef::set_strict_bounds`](super::TensorRef::set_strict_bounds)
+    #[error("value out of bounds at index {index} in {op}")]
+    OutOfBounds { index: usize, op: String },
+    /// The waveform does not cross the threshold, see [`Expression::cross_time`](super::Expression::cross_time)
+    #[error("no crossing in {op}")]
+    NoCrossing { op: String },
+    /// The constant is held by the ops as their own parameters, by path,
+    /// see [`Expression::promote_const_to_parameter`](super::Expression::promote_const_to_parameter)
+    #[error("constant {value} captured by {ops:?}")]
+    CapturedConst {
+        value: String,
+        ops: BTreeMap<String, String>,
+    },
+    /// The output is longer than the limit, see [`set_pairwise_limit`](super::set_pairwise_limit)
+    #[error("{op} output of {size} elements exceeds the limit {limit}")]
+    SizeLimit {
+        size: usize,
+        limit: usize,
+        op: String,
+    },
+    /// The values leave the range of [`Expression::assert_in_range`](super::Expression::assert_in_range),
+    /// see [`take_assert_violation`](super::take_assert_violation)
+    #[error("assertion `{label}` failed: {value} at index {index} out of {range}")]
+    AssertViolation {
+        label: String,
+        index: usize,
+        value: String,
+        range: String,
+    },
+    /// The op cannot be registered, see [`register_unary`](super::register_unary)
+    #[error("invalid registration: {0}")]
+    InvalidRegistration(String),
+    /// The input is not a snapshot of this version, see [`Expression::verify_snapshot`](super::Expression::verify_snapshot)
+    #[error("invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    /// A file could not be read or written, see [`io`](super::io)
+    #[error("I/O error: {0}")]
+    Io(String),
+    /// The input is not a raw table of this version, see [`io::read_raw`](super::io::read_raw)
+    #[error("invalid raw table: {0}")]
+    InvalidRaw(String),
+    /// The input is not a checkpoint of this version, see [`Checkpoint::load`](super::optimizer::Checkpoint::load)
+    #[error("invalid checkpoint: {0}")]
+    InvalidCheckpoint(String),
+    /// The parameters of a checkpoint are not the ones restored by name,
+    /// see [`Checkpoint::restore`](super::optimizer::Checkpoint::restore)
+    ///
+    /// `missing` from the checkpoint, `extra` in the checkpoint only,
+    /// `len_changed` as `(name, saved length, length)`
+    #[error(
+        "checkpoint mismatch: missing {missing:?}, extra {extra:?}, length changed {len_changed:?}"
+    )]
+    CheckpointMismatch {
+        missing: Vec<String>,
+        extra: Vec<String>,
+        len_changed: Vec<(String, usize, usize)>,
+    },
+    /// No op is registered under the name, see [`UnaryId`](super::UnaryId)
+    #[error("unknown op {0}")]
+    UnknownOp(String),
+    /// The values are not approximately equal, see [`Tolerance`](super::Tolerance)
+    #[error("not close: {0}")]
+    NotClose(String),
+    /// The compute graph contains an op without gradient
+    #[error("{op} is not differentiable")]
+    NonDifferentiable { op: String },
+    /// The graph is deeper than [`max_graph_depth`](super::max_graph_depth)
+    #[error("graph depth {depth} exceeds the limit {limit}")]
+    GraphTooDeep { depth: usize, limit: usize },
+    /// The graph is not acyclic, see [`Expression::check_acyclic`](super::Expression::check_acyclic)
+    #[error("cycle: {0}")]
+    Cycle(String),
+}
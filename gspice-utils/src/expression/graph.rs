@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+
+use super::{Error, Expression, ScalarTensor, Tensor};
+
+/// Default of [`set_max_graph_depth`]
+const DEFAULT_MAX_GRAPH_DEPTH: usize = usize::MAX;
+
+static MAX_GRAPH_DEPTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_GRAPH_DEPTH);
+
+/// Set the max [depth](Expression::depth) checked by [`try_value`](Expression::try_value),
+/// no limit by default
+///
+/// A deeper graph is an [`Error::GraphTooDeep`], e.g., a runaway unrolled loop.
+/// [`value`](Expression::value) does not check it: the recompute recurses over a bounded
+/// number of levels at a time, so any depth fits the 2 MiB stack of a spawned thread
+#[inline]
+pub fn set_max_graph_depth(limit: usize) {
+    MAX_GRAPH_DEPTH.store(limit, Relaxed);
+}
+
+/// See [`set_max_graph_depth`]
+#[inline]
+pub fn max_graph_depth() -> usize {
+    MAX_GRAPH_DEPTH.load(Relaxed)
+}
+
+impl Tensor {
+    /// The longest path to a leaf, from the inputs of `op`
+    #[inline]
+    pub(super) fn depth_of(inputs: Vec<&Expression>) -> usize {
+        inputs
+            .into_iter()
+            .filter_map(|input| match input {
+                Expression::Const(_) => None,
+                Expression::Tensor(tensor) => Some(tensor.depth() + 1),
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl Expression {
+    /// The number of ops on the longest path to a leaf, `0` for a leaf or a constant
+    #[inline]
+    pub fn depth(&self) -> usize {
+        match self {
+            Self::Const(_) => 0,
+            Self::Tensor(tensor) => tensor.depth(),
+        }
+    }
+    /// [`Error::GraphTooDeep`] beyond [`max_graph_depth`]
+    #[inline]
+    pub(super) fn check_depth(&self) -> Result<(), Error> {
+        let (depth, limit) = (self.depth(), max_graph_depth());
+        if depth > limit {
+            Err(Error::GraphTooDeep { depth, limit })
+        } else {
+            Ok(())
+        }
+    }
+    /// Fallible [`value`](Expression::value),
+    /// [`Error::GraphTooDeep`] beyond [`max_graph_depth`]
+    #[inline]
+    pub fn try_value(&self) -> Result<ScalarTensor<'_>, Error> {
+        self.check_depth()?;
+        Ok(self.value())
+    }
+    /// [`Error::Cycle`] naming the ops along a cycle, if any
+    ///
+    /// The ops hold their inputs, which exist before them, so the graph built through
+    /// this API is acyclic, the structural rewrites (e.g., [`substitute`](Expression::substitute))
+    /// check their results in debug builds
+    #[inline]
+    pub fn check_acyclic(&self) -> Result<(), Error> {
+        match self {
+            Self::Const(_) => Ok(()),
+            Self::Tensor(root) => find_cycle(root, |tensor| {
+                tensor
+                    .op()
+                    .inputs()
+                    .into_iter()
+                    .filter_map(|input| match input {
+                        Self::Const(_) => None,
+                        Self::Tensor(input) => Some(input.clone()),
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Iterative depth-first search with the set of the tensors on the current path
+///
+/// `inputs` lists the tensor inputs of a tensor, e.g., of its [`Op`](super::Op)
+pub(super) fn find_cycle(
+    root: &Tensor,
+    inputs: impl Fn(&Tensor) -> Vec<Tensor>,
+) -> Result<(), Error> {
+    // `true` while on the current path, `false` once all its inputs are searched
+    let mut on_path: HashMap<usize, bool> = HashMap::new();
+    // the current path, with the inputs left to search of each tensor
+    let mut path: Vec<(Tensor, Vec<Tensor>)> = Vec::new();
+    on_path.insert(root.key(), true);
+    path.push((root.clone(), inputs(root)));
+    while let Some((_, pending)) = path.last_mut() {
+        let Some(input) = pending.pop() else {
+            let (tensor, _) = path.pop().expect("non-empty path");
+            on_path.insert(tensor.key(), false);
+            continue;
+        };
+        match on_path.get(&input.key()) {
+            Some(false) => {}
+            Some(true) => {
+                let start = path
+                    .iter()
+                    .position(|(tensor, _)| *tensor == input)
+                    .expect("on the path");
+                let names: Vec<String> = path[start..]
+                    .iter()
+                    .map(|(tensor, _)| tensor)
+                    .chain([&input])
+                    .map(|tensor| tensor.op().name())
+                    .collect();
+                return Err(Error::Cycle(names.join(" -> ")));
+            }
+            None => {
+                on_path.insert(input.key(), true);
+                let pending = inputs(&input);
+                path.push((input, pending));
+            }
+        }
+    }
+    Ok(())
+}
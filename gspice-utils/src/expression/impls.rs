@@ -4,11 +4,12 @@ use super::{
     autograd::Grad,
     op::{
         DiscreteBinaryOp, DiscreteBinaryOpT, GradMethod, GradMethodDiscrete, GradMethodLinear,
-        GradMethodSigmoid, GradMethodT,
+        GradMethodSigmoid, GradMethodSmoothStep, GradMethodT, GradMethodTanh,
     },
     Expression, ScalarTensor, Tensor,
 };
-use core::fmt::{self, Write};
+use core::fmt;
+use std::sync::PoisonError;
 
 pub(crate) fn fmt_vec(vec: &[f64], f: &mut fmt::Formatter<'_>) -> fmt::Result {
     let mut buffer = ryu::Buffer::new();
@@ -22,8 +23,8 @@ pub(crate) fn fmt_vec(vec: &[f64], f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}]) [{len}x1]", buffer.format(vec[len - 1]))
     } else {
         let mut iter = vec.iter();
+        f.write_str("([")?;
         if let Some(first) = iter.next() {
-            f.write_char('[')?;
             f.write_str(buffer.format(*first))?;
             for x in iter {
                 write!(f, ", {}", buffer.format(*x))?;
@@ -34,7 +35,7 @@ pub(crate) fn fmt_vec(vec: &[f64], f: &mut fmt::Formatter<'_>) -> fmt::Result {
 }
 impl fmt::Display for Tensor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt_vec(&self.values().read().unwrap(), f)
+        fmt_vec(&self.read(), f)
     }
 }
 
@@ -65,28 +66,82 @@ impl<'a> ScalarTensor<'a> {
     }
     pub fn to_tensor(&self) -> Option<Vec<f64>> {
         if let ScalarTensor::Tensor(tensor) = self {
-            Some(tensor.read().unwrap().clone())
+            Some(
+                tensor
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone(),
+            )
         } else {
             None
         }
     }
+    /// The values as a new vector, length-1 for a scalar
+    #[inline]
+    pub fn to_owned_values(&self) -> Vec<f64> {
+        match self {
+            ScalarTensor::Scalar(x) => vec![**x],
+            ScalarTensor::Tensor(tensor) => tensor
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        }
+    }
+    /// The single value of a scalar or a length-1 tensor, `None` for the other lengths
+    #[inline]
+    pub fn as_scalar(&self) -> Option<f64> {
+        match self {
+            ScalarTensor::Scalar(x) => Some(**x),
+            ScalarTensor::Tensor(tensor) => match tensor
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_slice()
+            {
+                [x] => Some(*x),
+                _ => None,
+            },
+        }
+    }
+    /// Number of the values, `1` for a scalar
+    #[inline]
+    pub fn len(&self) -> usize {
+        match self {
+            ScalarTensor::Scalar(_) => 1,
+            ScalarTensor::Tensor(tensor) => {
+                tensor.read().unwrap_or_else(PoisonError::into_inner).len()
+            }
+        }
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     // #[cfg(test)]
     pub fn overall_sum(&self) -> f64 {
         match self {
             ScalarTensor::Scalar(x) => **x,
-            ScalarTensor::Tensor(tensor) => {
-                tensor.read().unwrap().iter().fold(0.0, |sum, x| sum + x)
-            }
+            ScalarTensor::Tensor(tensor) => tensor
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .fold(0.0, |sum, x| sum + x),
         }
     }
 }
+/// Equal when it is a scalar, or a length-1 tensor, of the value, see [`ScalarTensor::as_scalar`]
+impl PartialEq<f64> for ScalarTensor<'_> {
+    #[inline]
+    fn eq(&self, other: &f64) -> bool {
+        self.as_scalar() == Some(*other)
+    }
+}
 impl<'a> fmt::Display for ScalarTensor<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ScalarTensor::Scalar(v) => write!(f, "Scalar({})", v),
             ScalarTensor::Tensor(tensor) => {
                 write!(f, "Tensor")?;
-                fmt_vec(&tensor.read().unwrap(), f)
+                fmt_vec(&tensor.read().unwrap_or_else(PoisonError::into_inner), f)
             }
         }
     }
@@ -326,6 +381,8 @@ pub(super) trait DiscreteBinaryIter: GradMethodT {
 impl DiscreteBinaryIter for GradMethodDiscrete {}
 impl DiscreteBinaryIter for GradMethodLinear {}
 impl DiscreteBinaryIter for GradMethodSigmoid {}
+impl DiscreteBinaryIter for GradMethodTanh {}
+impl DiscreteBinaryIter for GradMethodSmoothStep {}
 
 const CMP_METHOD_DISCRET: GradMethodDiscrete = GradMethodDiscrete;
 
@@ -350,6 +407,10 @@ impl DiscreteBinaryOpT for super::op::Eq {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.eq_backward_lhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.eq_backward_lhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.eq_backward_lhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -366,6 +427,12 @@ impl DiscreteBinaryOpT for super::op::Eq {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.eq_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.eq_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.eq_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
         }
     }
     #[inline]
@@ -379,6 +446,10 @@ impl DiscreteBinaryOpT for super::op::Eq {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.eq_backward_rhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.eq_backward_rhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.eq_backward_rhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -395,6 +466,12 @@ impl DiscreteBinaryOpT for super::op::Eq {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.eq_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.eq_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.eq_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
         }
     }
 }
@@ -420,6 +497,10 @@ impl DiscreteBinaryOpT for super::op::Ne {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ne_backward_lhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.ne_backward_lhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ne_backward_lhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -436,6 +517,12 @@ impl DiscreteBinaryOpT for super::op::Ne {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ne_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.ne_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ne_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
         }
     }
     #[inline]
@@ -449,6 +536,10 @@ impl DiscreteBinaryOpT for super::op::Ne {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ne_backward_rhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.ne_backward_rhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ne_backward_rhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -465,6 +556,12 @@ impl DiscreteBinaryOpT for super::op::Ne {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ne_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.ne_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ne_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
         }
     }
 }
@@ -490,6 +587,10 @@ impl DiscreteBinaryOpT for super::op::Le {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.le_backward_lhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.le_backward_lhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.le_backward_lhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -506,6 +607,12 @@ impl DiscreteBinaryOpT for super::op::Le {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.le_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.le_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.le_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
         }
     }
     #[inline]
@@ -519,6 +626,10 @@ impl DiscreteBinaryOpT for super::op::Le {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.le_backward_rhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.le_backward_rhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.le_backward_rhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -535,6 +646,12 @@ impl DiscreteBinaryOpT for super::op::Le {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.le_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.le_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.le_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
         }
     }
 }
@@ -560,6 +677,10 @@ impl DiscreteBinaryOpT for super::op::Ge {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ge_backward_lhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.ge_backward_lhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ge_backward_lhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -576,6 +697,12 @@ impl DiscreteBinaryOpT for super::op::Ge {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ge_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.ge_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ge_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
         }
     }
     #[inline]
@@ -589,6 +716,10 @@ impl DiscreteBinaryOpT for super::op::Ge {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ge_backward_rhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.ge_backward_rhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ge_backward_rhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -605,6 +736,12 @@ impl DiscreteBinaryOpT for super::op::Ge {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.ge_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.ge_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.ge_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
         }
     }
 }
@@ -630,6 +767,10 @@ impl DiscreteBinaryOpT for super::op::Lt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.lt_backward_lhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.lt_backward_lhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.lt_backward_lhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -646,6 +787,12 @@ impl DiscreteBinaryOpT for super::op::Lt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.lt_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.lt_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.lt_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
         }
     }
     #[inline]
@@ -659,6 +806,10 @@ impl DiscreteBinaryOpT for super::op::Lt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.lt_backward_rhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.lt_backward_rhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.lt_backward_rhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -675,6 +826,12 @@ impl DiscreteBinaryOpT for super::op::Lt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.lt_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.lt_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.lt_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
         }
     }
 }
@@ -700,6 +857,10 @@ impl DiscreteBinaryOpT for super::op::Gt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.gt_backward_lhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.gt_backward_lhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.gt_backward_lhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -716,6 +877,12 @@ impl DiscreteBinaryOpT for super::op::Gt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.gt_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.gt_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.gt_backward_lhs_iter_fix_rhs(rhs, lhs_iter)
+            }
         }
     }
     #[inline]
@@ -729,6 +896,10 @@ impl DiscreteBinaryOpT for super::op::Gt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.gt_backward_rhs_iter(iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => grad_method_tanh.gt_backward_rhs_iter(iter),
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.gt_backward_rhs_iter(iter)
+            }
         }
     }
     #[inline]
@@ -745,6 +916,12 @@ impl DiscreteBinaryOpT for super::op::Gt {
             GradMethod::Sigmoid(grad_method_sigmoid) => {
                 grad_method_sigmoid.gt_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
             }
+            GradMethod::Tanh(grad_method_tanh) => {
+                grad_method_tanh.gt_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
+            GradMethod::SmoothStep(grad_method_smoothstep) => {
+                grad_method_smoothstep.gt_backward_rhs_iter_fix_lhs(lhs, rhs_iter)
+            }
         }
     }
 }
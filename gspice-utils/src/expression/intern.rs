@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use ordered_float::OrderedFloat;
+
+use super::{Expression, Op, SharpnessHandle, Tensor};
+
+/// Hash-consing context of the graph construction: the op nodes passed through
+/// [`GraphBuilder::intern`] are merged with the earlier ones of the same op,
+/// the same scalar parameters and the same inputs
+///
+/// Only the ops whose parameters are all scalars or enum tags are merged
+/// (e.g., [`powf`](Expression::powf), the unary and binary ops including the
+/// [registered](super::register_unary) ones, the reductions),
+/// and the comparisons of the same method on the same [sharpness](super::SharpnessHandle),
+/// the table-driven and custom ops are kept as is. Leaf tensors, including the
+/// [tunable constants](Expression::tunable_constant), are never merged since they can be
+/// assigned later. The builder keeps the nodes it has seen alive
+#[derive(Debug, Default)]
+pub struct GraphBuilder {
+    nodes: HashMap<InternKey, Expression>,
+    /// Original tensors (kept alive, so that their keys are not reused) to the interned ones
+    memo: HashMap<usize, (Tensor, Expression)>,
+    merged: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum InputKey {
+    Const(OrderedFloat<f64>),
+    Tensor(usize),
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct InternKey {
+    op: String,
+    /// The kind of the comparison method, and the identity of its sharpness,
+    /// which can be [set](super::SharpnessHandle::set) independently of the equal ones
+    method: Option<(u64, Option<usize>)>,
+    params: Vec<OrderedFloat<f64>>,
+    inputs: Vec<InputKey>,
+}
+
+impl InternKey {
+    /// `None` for the ops that are never merged
+    fn new(op: &Op) -> Option<Self> {
+        let name = match op {
+            Op::Powf(..)
+            | Op::Transition(..)
+            | Op::UnaryParam(..)
+            | Op::DivEps(..)
+            | Op::RobustLoss(..)
+            | Op::Narrow(..)
+            | Op::Reverse(..)
+            | Op::Shift(..)
+            | Op::Ddt(..)
+            | Op::Idt(..)
+            | Op::Reduce(..)
+            | Op::Spectrum(..)
+            | Op::Unary(..)
+            | Op::ExternalUnary(..)
+            | Op::Binary(..)
+            | Op::DiscreteBinary(..) => op.name(),
+            _ => return None,
+        };
+        let method = match op {
+            Op::DiscreteBinary(_, _, _, grad_method) => Some((
+                grad_method.tag(),
+                grad_method.sharpness().map(SharpnessHandle::key),
+            )),
+            _ => None,
+        };
+        Some(Self {
+            op: name,
+            method,
+            params: op.captured_consts().into_iter().map(OrderedFloat).collect(),
+            inputs: op
+                .inputs()
+                .into_iter()
+                .map(|input| match input {
+                    Expression::Const(x) => InputKey::Const(OrderedFloat(*x)),
+                    Expression::Tensor(tensor) => InputKey::Tensor(tensor.key()),
+                })
+                .collect(),
+        })
+    }
+}
+
+impl GraphBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The graph of `expr` with its op nodes merged into the earlier ones
+    ///
+    /// The nodes depending on a merged one are rebuilt and recomputed,
+    /// the graph of `expr` itself is untouched
+    pub fn intern(&mut self, expr: &Expression) -> Expression {
+        let Expression::Tensor(root) = expr else {
+            return expr.clone();
+        };
+        // post order, so that the inputs are interned first
+        let mut stack: Vec<(&Tensor, bool)> = vec![(root, false)];
+        while let Some((tensor, expanded)) = stack.pop() {
+            if self.memo.contains_key(&tensor.key()) {
+                continue;
+            }
+            if !expanded {
+                stack.push((tensor, true));
+                stack.extend(
+                    tensor
+                        .op()
+                        .inputs()
+                        .into_iter()
+                        .filter_map(|input| match input {
+                            Expression::Tensor(input) => Some((input, false)),
+                            Expression::Const(_) => None,
+                        }),
+                );
+                continue;
+            }
+            let interned = self.intern_node(tensor);
+            self.memo.insert(tensor.key(), (tensor.clone(), interned));
+        }
+        self.memo[&root.key()].1.clone()
+    }
+    /// Distinct op nodes in the table
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    /// Op nodes merged into an earlier one so far
+    #[inline]
+    pub fn merged(&self) -> usize {
+        self.merged
+    }
+    /// The inputs of `tensor` are interned
+    fn intern_node(&mut self, tensor: &Tensor) -> Expression {
+        if let Op::Assign = tensor.op() {
+            return Expression::Tensor(tensor.clone());
+        }
+        let mut rebuilt = false;
+        let op = tensor.op().map_inputs(|input| match input {
+            Expression::Const(_) => input.clone(),
+            Expression::Tensor(input_tensor) => {
+                let new_input = self.memo[&input_tensor.key()].1.clone();
+                rebuilt |= !matches!(&new_input, Expression::Tensor(new) if new == input_tensor);
+                new_input
+            }
+        });
+        let key = InternKey::new(&op);
+        if let Some(interned) = key.as_ref().and_then(|key| self.nodes.get(key)) {
+            self.merged += 1;
+            return interned.clone();
+        }
+        let interned = if rebuilt {
+            let with_grad = op
+                .inputs()
+                .into_iter()
+                .any(|input| matches!(input, Expression::Tensor(input) if input.with_grad()));
+            let new = Tensor::new(with_grad, Vec::new(), op);
+            new.recompute_released();
+            Expression::Tensor(new)
+        } else {
+            Expression::Tensor(tensor.clone())
+        };
+        if let Some(key) = key {
+            self.nodes.insert(key, interned.clone());
+        }
+        interned
+    }
+}
+
+impl Expression {
+    /// A new graph with the duplicated op nodes merged, see [`GraphBuilder`],
+    /// for the graphs built without it
+    #[inline]
+    pub fn intern_consts(&self) -> Self {
+        GraphBuilder::new().intern(self)
+    }
+}
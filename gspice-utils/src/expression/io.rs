@@ -0,0 +1,194 @@
+//! Tabular writers of evaluated expressions, e.g., a sweep variable and its outputs for plotting
+//!
+//! ``` text
+//! gspice::io::write_csv("sweep.csv", &[("vin", &vin), ("vout", &vout)])?;
+//! gspice::io::write_raw("sweep.raw", &[("vin", &vin), ("vout", &vout)])?;
+//! let columns = gspice::io::read_raw("sweep.raw")?;
+//! ```
+//!
+//! Each column is evaluated by [`Expression::value`]. The tensor columns have the same length,
+//! a [`Error::LengthMismatch`] names the first column of another length otherwise (a length-1
+//! tensor is not broadcast), and the constant columns are repeated to that length.
+//! A table of constants only is one row
+//!
+//! The raw format is little-endian: the magic `GRAW`, the `u32` version, the `u32` number
+//! of columns, the `u64` number of rows, each name as a `u32` length and its UTF-8 bytes,
+//! then the `f64` bits column by column
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use super::{reader::LeReader, Error, Expression};
+
+/// Leading bytes of a raw table
+const MAGIC: [u8; 4] = *b"GRAW";
+/// Bumped on any change of the layout, older tables are rejected
+const VERSION: u32 = 1;
+
+/// Write the columns to a CSV file, with a header of their names, see [`write_csv_to`]
+#[inline]
+pub fn write_csv(path: impl AsRef<Path>, columns: &[(&str, &Expression)]) -> Result<(), Error> {
+    let file = File::create(path).map_err(io_err)?;
+    write_csv_to(BufWriter::new(file), columns)
+}
+
+/// Stream the columns as CSV, with a header of their names, a row per element
+///
+/// The names are quoted when needed (RFC 4180), the values are in their shortest
+/// round-trip form, `NaN`, `inf` and `-inf` for the non-finite ones
+pub fn write_csv_to(mut writer: impl Write, columns: &[(&str, &Expression)]) -> Result<(), Error> {
+    let (names, values, rows) = evaluate(columns)?;
+    let header: Vec<String> = names.iter().map(|name| csv_field(name)).collect();
+    writeln!(writer, "{}", header.join(",")).map_err(io_err)?;
+    let mut buffer = ryu::Buffer::new();
+    for row in 0..rows {
+        for (i, column) in values.iter().enumerate() {
+            if i != 0 {
+                writer.write_all(b",").map_err(io_err)?;
+            }
+            writer
+                .write_all(buffer.format(column.get(row)).as_bytes())
+                .map_err(io_err)?;
+        }
+        writer.write_all(b"\n").map_err(io_err)?;
+    }
+    writer.flush().map_err(io_err)
+}
+
+/// Write the columns to a raw file, see [`write_raw_to`]
+#[inline]
+pub fn write_raw(path: impl AsRef<Path>, columns: &[(&str, &Expression)]) -> Result<(), Error> {
+    let file = File::create(path).map_err(io_err)?;
+    write_raw_to(BufWriter::new(file), columns)
+}
+
+/// Stream the columns in the raw format (see the [module](self)), for [`read_raw_from`]
+///
+/// The values are kept bit by bit, including the `NaN` payloads
+pub fn write_raw_to(mut writer: impl Write, columns: &[(&str, &Expression)]) -> Result<(), Error> {
+    let (names, values, rows) = evaluate(columns)?;
+    let mut write = |bytes: &[u8]| writer.write_all(bytes).map_err(io_err);
+    write(&MAGIC)?;
+    write(&VERSION.to_le_bytes())?;
+    write(&(names.len() as u32).to_le_bytes())?;
+    write(&(rows as u64).to_le_bytes())?;
+    for name in &names {
+        write(&(name.len() as u32).to_le_bytes())?;
+        write(name.as_bytes())?;
+    }
+    for column in &values {
+        for row in 0..rows {
+            write(&column.get(row).to_bits().to_le_bytes())?;
+        }
+    }
+    writer.flush().map_err(io_err)
+}
+
+/// Read a raw file, see [`read_raw_from`]
+#[inline]
+pub fn read_raw(path: impl AsRef<Path>) -> Result<Vec<(String, Vec<f64>)>, Error> {
+    let file = File::open(path).map_err(io_err)?;
+    read_raw_from(BufReader::new(file))
+}
+
+/// The `(name, values)` of the columns written by [`write_raw_to`]
+///
+/// [`Error::InvalidRaw`] when the input is not a raw table of this version,
+/// is truncated, or has trailing bytes
+pub fn read_raw_from(reader: impl Read) -> Result<Vec<(String, Vec<f64>)>, Error> {
+    let mut input = LeReader::new(reader, Error::InvalidRaw, "table");
+    if input.bytes::<4>()? != MAGIC {
+        return Err(Error::InvalidRaw("not a raw table".to_owned()));
+    }
+    let version = input.u32()?;
+    if version != VERSION {
+        return Err(Error::InvalidRaw(format!(
+            "version {version}, expected {VERSION}"
+        )));
+    }
+    let count = input.u32()?;
+    let rows = input.u64()?;
+    let names = (0..count)
+        .map(|index| input.string(|| format!("name of column #{index}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let columns = names
+        .into_iter()
+        .map(|name| Ok((name, input.values(rows)?)))
+        .collect::<Result<_, Error>>()?;
+    if input.at_end()? {
+        Ok(columns)
+    } else {
+        Err(Error::InvalidRaw("trailing bytes".to_owned()))
+    }
+}
+
+/// A column, evaluated
+enum Column {
+    Const(f64),
+    Values(Vec<f64>),
+}
+
+impl Column {
+    #[inline]
+    fn get(&self, row: usize) -> f64 {
+        match self {
+            Self::Const(x) => *x,
+            Self::Values(values) => values[row],
+        }
+    }
+}
+
+/// The names, the evaluated columns and the number of rows
+fn evaluate<'a>(
+    columns: &[(&'a str, &Expression)],
+) -> Result<(Vec<&'a str>, Vec<Column>, usize), Error> {
+    let mut rows = None;
+    let mut names = Vec::with_capacity(columns.len());
+    let mut values = Vec::with_capacity(columns.len());
+    for (name, expr) in columns {
+        let column = match expr {
+            Expression::Const(x) => Column::Const(*x),
+            Expression::Tensor(_) => {
+                let column = expr.values();
+                match rows {
+                    None => rows = Some(column.len()),
+                    Some(expected) if expected != column.len() => {
+                        return Err(Error::LengthMismatch {
+                            expected,
+                            got: column.len(),
+                            op: format!("column `{name}`"),
+                        })
+                    }
+                    Some(_) => {}
+                }
+                Column::Values(column)
+            }
+        };
+        names.push(*name);
+        values.push(column);
+    }
+    let rows = match rows {
+        Some(rows) => rows,
+        None if columns.is_empty() => 0,
+        None => 1,
+    };
+    Ok((names, values, rows))
+}
+
+/// The name as a CSV field, quoted when it holds a separator, a quote or a line break
+#[inline]
+fn csv_field(name: &str) -> String {
+    if name.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    } else {
+        name.to_owned()
+    }
+}
+
+#[inline]
+fn io_err(e: std::io::Error) -> Error {
+    Error::Io(e.to_string())
+}
@@ -0,0 +1,66 @@
+use std::cell::Cell;
+
+use super::{Expression, Op, Tensor};
+
+thread_local! {
+    /// Inside [`lazy_build`] on this thread
+    static LAZY_BUILD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Restore the outer mode, also when the build panics
+struct LazyBuildGuard(bool);
+
+impl Drop for LazyBuildGuard {
+    #[inline]
+    fn drop(&mut self) {
+        LAZY_BUILD.set(self.0);
+    }
+}
+
+/// Build the graphs in `f` without computing their values, e.g., for a large graph
+/// whose parameters are updated before the first evaluation anyway
+///
+/// + The element-wise ops (unary, binary, comparisons, [`cond`](Expression::cond),
+///   [`map_custom`](Expression::map_custom), ...) only infer and check their length
+///   from the lengths of their inputs, their values are computed at the first read,
+///   e.g., by [`eval`](Expression::eval) or [`value`](Expression::value)
+/// + The other ops are computed at the construction as usual,
+///   which computes their lazy inputs first
+/// + Before the first evaluation, the lazy nodes are [released](super::MemoryStats::released_nodes),
+///   reading [`Tensor::values`] computes them
+///
+/// The first evaluation gives the same values as an eager construction. The mode is
+/// per thread, nested calls are lazy as well
+#[inline]
+pub fn lazy_build<T>(f: impl FnOnce() -> T) -> T {
+    let _outer = LazyBuildGuard(LAZY_BUILD.replace(true));
+    f()
+}
+
+/// Inside [`lazy_build`] on this thread
+#[inline]
+pub fn is_lazy_build() -> bool {
+    LAZY_BUILD.get()
+}
+
+impl Tensor {
+    /// A tensor of `op` with the values of length `len` yet to compute, see [`lazy_build`]
+    #[inline]
+    pub(super) fn new_lazy(with_grad: bool, len: usize, op: Op) -> Self {
+        let tensor = Self::new(with_grad, Vec::new(), op);
+        tensor.retain().set_lazy(len);
+        tensor
+    }
+}
+
+impl Expression {
+    /// A lazy tensor of `op`, with gradient when any of its inputs is with gradient
+    #[inline]
+    pub(super) fn new_lazy(len: usize, op: Op) -> Self {
+        let with_grad = op
+            .inputs()
+            .into_iter()
+            .any(|input| matches!(input, Self::Tensor(tensor) if tensor.with_grad()));
+        Self::Tensor(Tensor::new_lazy(with_grad, len, op))
+    }
+}
@@ -0,0 +1,106 @@
+use super::{Expression, TensorRef};
+
+/// Named term of [`LossBuilder`]
+#[derive(Clone, Debug)]
+struct Term {
+    name: String,
+    /// Length-1 tensor, adjustable without rebuilding
+    weight: TensorRef,
+    /// The summed term, its value is read back by [`LossBuilder::terms`]
+    value: Expression,
+    weighted: Expression,
+}
+
+/// Weighted sum of named terms, e.g., `w1*delay + w2*power + w3*area_penalty`
+///
+/// ``` text
+/// let loss = LossBuilder::new()
+///     .term("delay", 1.0, delay)
+///     .term("power", 0.1, power);
+/// let f = loss.build();
+/// _ = f.value();
+/// for (name, value) in loss.terms() { .. }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct LossBuilder {
+    terms: Vec<Term>,
+}
+
+impl LossBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add the term `weight * sum(value)`
+    ///
+    /// ## Panics
+    ///
+    /// When the name is already used
+    #[inline]
+    pub fn term(mut self, name: &str, weight: f64, value: Expression) -> Self {
+        assert!(
+            self.terms.iter().all(|term| term.name != name),
+            "duplicated loss term {name}"
+        );
+        let (weight_expr, weight) = Expression::tensor(vec![weight], false);
+        let value = value.sum();
+        let weighted = weight_expr.mul(&value);
+        self.terms.push(Term {
+            name: name.to_owned(),
+            weight,
+            value,
+            weighted,
+        });
+        self
+    }
+    /// The combined scalar loss, `Σ weight * sum(value)`, a constant zero without any term
+    ///
+    /// The built expressions share the terms, call it once and keep it
+    #[inline]
+    pub fn build(&self) -> Expression {
+        self.terms
+            .iter()
+            .map(|term| term.weighted.clone())
+            .reduce(|sum, weighted| sum.add(&weighted))
+            .unwrap_or(Expression::Const(0.0))
+    }
+    /// `(name, unweighted value)` of each term, in the order of addition
+    ///
+    /// Read as-is, call [`Expression::value`] of the [built](LossBuilder::build) loss before
+    #[inline]
+    pub fn terms(&self) -> Vec<(String, f64)> {
+        self.terms
+            .iter()
+            .map(|term| {
+                let value = match &term.value {
+                    Expression::Const(x) => *x,
+                    Expression::Tensor(tensor) => tensor.read()[0],
+                };
+                (term.name.clone(), value)
+            })
+            .collect()
+    }
+    /// The current weight of the term
+    #[inline]
+    pub fn weight(&self, name: &str) -> Option<f64> {
+        self.find(name).map(|term| term.weight.0.read()[0])
+    }
+    /// Need [`before_update`](super::before_update) before calling this
+    ///
+    /// Need [`Expression::value`] after calling this, the built loss is not rebuilt
+    ///
+    /// ## Panics
+    ///
+    /// When there is no such term
+    #[inline]
+    pub fn set_weight(&self, name: &str, weight: f64) {
+        self.find(name)
+            .unwrap_or_else(|| panic!("no loss term {name}"))
+            .weight
+            .assign(vec![weight]);
+    }
+    #[inline]
+    fn find(&self, name: &str) -> Option<&Term> {
+        self.terms.iter().find(|term| term.name == name)
+    }
+}
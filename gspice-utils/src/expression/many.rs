@@ -0,0 +1,35 @@
+use super::{Error, Expression, GradStore};
+
+/// The values of several outputs sharing one graph, in the order of `outputs`
+///
+/// The shared nodes are computed once: an evaluated node is up to date until the next
+/// [`before_update`](super::before_update), the later outputs reuse its values
+///
+/// ## Panics
+///
+/// See [`Expression::value`]
+#[inline]
+pub fn eval_many(outputs: &[&Expression]) -> Vec<Vec<f64>> {
+    outputs.iter().map(|output| output.values()).collect()
+}
+
+/// The gradients of `Σ weight * sum(output)`, without building the weighted sum,
+/// in a single backward over the union of the graphs: each shared node is
+/// back-propagated once, with the gradients of all its consumers
+///
+/// [`GradStore::loss`] is the weighted sum. Need [`eval_many`] (or the values of
+/// each output) before, like [`Expression::backward`]
+///
+/// ## Panics
+///
+/// See [`try_backward_many`]
+#[inline]
+pub fn backward_many(outputs: &[(&Expression, f64)]) -> GradStore {
+    Expression::backward_seeded(outputs, false).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Fallible [`backward_many`], the errors of [`Expression::try_backward`] for any output
+#[inline]
+pub fn try_backward_many(outputs: &[(&Expression, f64)]) -> Result<GradStore, Error> {
+    Expression::backward_seeded(outputs, true)
+}
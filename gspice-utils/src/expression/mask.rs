@@ -0,0 +1,84 @@
+use std::sync::{PoisonError, RwLock};
+
+use super::{Error, Grad, Tensor};
+
+/// Elements of a tensor excluded from the back-propagation, see [`Tensor::set_grad_mask`]
+#[derive(Debug, Default)]
+pub(super) struct GradMask(RwLock<Option<Vec<bool>>>);
+
+impl GradMask {
+    #[inline]
+    fn get(&self) -> Option<Vec<bool>> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+    #[inline]
+    fn set(&self, mask: Option<Vec<bool>>) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = mask;
+    }
+    /// Copy the mask of `other`
+    #[inline]
+    pub(super) fn copy_from(&self, other: &Self) {
+        self.set(other.get());
+    }
+}
+
+impl Tensor {
+    /// Exclude the elements where `mask` is `true` from the back-propagation:
+    /// their gradients are zeroed when [`backward`](super::Expression::backward) reaches
+    /// this tensor, so they contribute nothing to the gradients of its inputs
+    /// (nor to its own gradient, for a leaf)
+    ///
+    /// The forward values are not affected
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_set_grad_mask`](Tensor::try_set_grad_mask)
+    #[inline]
+    pub fn set_grad_mask(&self, mask: Vec<bool>) {
+        self.try_set_grad_mask(mask)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`set_grad_mask`](Tensor::set_grad_mask)
+    ///
+    /// [`Error::LengthMismatch`] when the lengths of `mask` and the tensor differ,
+    /// checked again by the backward in case the tensor is updated to another length
+    #[inline]
+    pub fn try_set_grad_mask(&self, mask: Vec<bool>) -> Result<(), Error> {
+        let len = self.values_len();
+        if mask.len() != len {
+            return Err(Error::LengthMismatch {
+                expected: len,
+                got: mask.len(),
+                op: "set_grad_mask".to_owned(),
+            });
+        }
+        self.grad_mask().set(Some(mask));
+        Ok(())
+    }
+    /// Remove the mask of [`set_grad_mask`](Tensor::set_grad_mask)
+    #[inline]
+    pub fn clear_grad_mask(&self) {
+        self.grad_mask().set(None);
+    }
+    /// Zero the masked elements of the gradient of this tensor
+    pub(super) fn apply_grad_mask(&self, grad: &mut Grad) -> Result<(), Error> {
+        if let Some(mask) = self.grad_mask().get() {
+            if mask.len() != grad.0.len() {
+                return Err(Error::LengthMismatch {
+                    expected: grad.0.len(),
+                    got: mask.len(),
+                    op: "grad mask".to_owned(),
+                });
+            }
+            grad.0
+                .iter_mut()
+                .zip(mask)
+                .filter(|(_, masked)| *masked)
+                .for_each(|(g, _)| *g = 0.0);
+        }
+        Ok(())
+    }
+}
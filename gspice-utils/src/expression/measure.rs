@@ -0,0 +1,36 @@
+use super::{Expression, MaxMethod};
+
+/// Waveform measurements, assembled from the reductions
+impl Expression {
+    /// Peak excursion above `target`, `max(x - target)` by `method`
+    ///
+    /// Negative when the waveform stays below `target`
+    #[inline]
+    pub fn overshoot(&self, target: f64, method: MaxMethod) -> Self {
+        self.sub(&Self::Const(target)).amax(method)
+    }
+    /// Worst deviation from `target` over the last `tail_fraction` of the samples,
+    /// `max |x - target|` by `method`
+    ///
+    /// The tail is rounded up to whole samples, at least one and at most all of them,
+    /// and fixed by the current length. A constant is a single sample
+    #[inline]
+    pub fn settling_error(&self, target: f64, tail_fraction: f64, method: MaxMethod) -> Self {
+        let tail = match self {
+            Self::Const(_) => self.clone(),
+            Self::Tensor(tensor) => {
+                let len = tensor.read().len();
+                let tail_len = ((len as f64 * tail_fraction).ceil() as usize)
+                    .max(1)
+                    .min(len);
+                self.narrow(len - tail_len, tail_len)
+            }
+        };
+        tail.sub(&Self::Const(target)).abs().amax(method)
+    }
+    /// Root mean square of the elements, `sqrt(mean(x²))`
+    #[inline]
+    pub fn rms(&self) -> Self {
+        self.sqr().mean().sqrt()
+    }
+}
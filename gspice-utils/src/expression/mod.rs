@@ -1,19 +1,93 @@
+mod anomaly;
+mod approx;
+mod assertion;
 mod autograd;
+mod bound;
+mod cached;
+mod checkpoint;
+mod complex;
+mod diff;
+mod ema;
+mod error;
+mod graph;
 mod impls;
+mod intern;
+pub mod io;
+mod lazy;
+mod loss;
+mod many;
+mod mask;
+mod measure;
 mod op;
-mod optimizer;
+pub mod optimizer;
+mod pairwise;
+pub mod profile;
+mod property;
+mod prune;
+mod reader;
 mod recompute;
+mod reduction;
+mod registry;
+mod rollback;
+mod snapshot;
+mod stats;
+mod subgraph;
+mod sweep;
 mod test;
-use itertools::zip_eq;
+mod tunable;
+pub use anomaly::{set_anomaly_detection, take_anomaly_report, AnomalyReport};
+pub use approx::Tolerance;
+pub use assertion::{
+    assert_policy, set_assert_policy, take_assert_violation, AssertPolicy, AssertRange,
+};
+pub use autograd::{Grad, GradId, GradStore};
+pub use bound::Bound;
+pub use cached::CachedExpression;
+pub use checkpoint::{MemoryStats, Retention};
+pub use complex::{ComplexExpr, ComplexOp};
+pub use diff::{GraphDiff, NodeDiff};
+pub use ema::{EmaState, EmaTracker};
+pub use error::Error;
+pub use graph::{max_graph_depth, set_max_graph_depth};
+pub use intern::GraphBuilder;
+pub use lazy::{is_lazy_build, lazy_build};
+pub use loss::LossBuilder;
+pub use many::{backward_many, eval_many, try_backward_many};
+pub use op::{set_smooth_forward, smooth_forward};
+pub use op::{
+    BinaryOp, CondMethod, ConvPadding, CountGe, CrossTime, CustomBinary, CustomBinaryBackward,
+    CustomBinaryForward, CustomNary, CustomOp, CustomUnary, CustomUnaryBackward,
+    CustomUnaryForward, Diode, DiscreteBinaryOp, Edge, GradMethod, GradMethodLinear,
+    GradMethodSigmoid, GradMethodSmoothStep, GradMethodTanh, Integral, LogicNary, MaxMethod, Op,
+    Pwl, PwlExtrapolation, Quadrature, Reduce, RobustLoss, SharpnessHandle, SmoothForward,
+    SoftHistogram, Spectrum, Spline, SplineBoundary, TiePolicy, Transition, UnaryOp, UnaryParamOp,
+    Weighted, WindowKind, WindowReduce, WindowTail, DB_FLOOR, LIMEXP_X0,
+};
+pub use pairwise::{pairwise_limit, set_pairwise_limit};
+pub use prune::{prune_dead_branches, set_prune_dead_branches};
 pub use recompute::before_update;
+pub use reduction::{
+    accuracy_mode, is_deterministic, set_accuracy_mode, set_deterministic, AccuracyMode,
+};
+pub use registry::{register_unary, UnaryId};
+pub use rollback::ParameterSnapshot;
+pub use snapshot::{SnapshotDiff, SnapshotMismatch};
+pub use stats::TensorStats;
+pub use sweep::sweep;
+#[cfg(feature = "rayon")]
+pub use sweep::sweep_par;
+pub use tunable::ConstHandle;
 
-use autograd::GradId;
+use bound::Bounds;
+use checkpoint::Retain;
+use mask::GradMask;
 use num_traits::identities::{One, Zero};
-use op::Op;
 use recompute::ChangeMarker;
+use stats::StatsCache;
+use std::hash::{Hash, Hasher};
 use std::sync::{
     atomic::{AtomicBool, Ordering::Relaxed},
-    Arc, RwLock,
+    Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
 };
 
 #[derive(Clone, Debug)]
@@ -21,42 +95,190 @@ pub struct Tensor(Arc<_Tensor>);
 
 #[derive(Debug)]
 struct _Tensor {
+    /// Only the leaves (the parameters) have one, also when frozen
     grad_id: Option<GradId>,
+    /// Leaf: set by the user, op: refreshed at each backward
+    with_grad: AtomicBool,
     values: RwLock<Vec<f64>>,
     change_marker: ChangeMarker,
+    bounds: Bounds,
+    retain: Retain,
+    grad_mask: GradMask,
+    stats_cache: StatsCache,
+    /// The longest path to a leaf, see [`Expression::depth`]
+    depth: usize,
     op: Op,
     #[cfg(debug_assertions)]
     is_logic: AtomicBool,
 }
+/// Iterative drop, so that dropping a deep graph does not overflow the stack
+impl Drop for _Tensor {
+    fn drop(&mut self) {
+        if let Op::Assign = self.op {
+            return;
+        }
+        let mut stack: Vec<Expression> = self.op.inputs().into_iter().cloned().collect();
+        self.op = Op::Assign;
+        while let Some(expr) = stack.pop() {
+            if let Expression::Tensor(tensor) = expr {
+                // the last owner unlinks the inputs before dropping
+                if let Some(mut inner) = Arc::into_inner(tensor.0) {
+                    stack.extend(inner.op.inputs().into_iter().cloned());
+                    inner.op = Op::Assign;
+                }
+            }
+        }
+    }
+}
+
 impl Tensor {
+    /// Writing the values through this lock (or a raw pointer into them) bypasses
+    /// the change tracking, call [`mark_changed`](Tensor::mark_changed) after that,
+    /// or use [`update_with`](Tensor::update_with)
+    ///
+    /// A released (or [lazy](lazy_build)) tensor is recomputed first, see [`Expression::set_retention`]
     #[inline]
     pub fn values(&self) -> &RwLock<Vec<f64>> {
+        if self.is_released() {
+            self.recompute_released();
+        }
         &self.0.values
     }
+    /// Need [`before_update`] before calling this
+    ///
+    /// Need [`Expression::value`](Expression::value) after calling this
+    ///
+    /// Mark the values as changed in the current update, so that the dependent expressions
+    /// recompute from them, e.g., after the values are mutated through a raw pointer
+    /// by an external simulator
+    ///
+    /// Intended for leaf tensors, the values of an op tensor are overwritten
+    /// at its next recompute
+    #[inline]
+    pub fn mark_changed(&self) {
+        self.change_marker().mark_searched_change();
+    }
+    /// Whether the tensor has not been searched since the last [`before_update`],
+    /// i.e., its values may be out of date until the next [`Expression::value`]
+    ///
+    /// A tensor [marked as changed](Tensor::mark_changed) is not stale
+    #[inline]
+    pub fn is_stale(&self) -> bool {
+        self.change_marker().is_stale()
+    }
+    /// Need [`before_update`] before calling this
+    ///
+    /// Need [`Expression::value`](Expression::value) after calling this
+    ///
+    /// Mutate the values in place and [mark them as changed](Tensor::mark_changed)
+    /// under the same write lock, so that no evaluator observes the new values unmarked
+    #[inline]
+    pub fn update_with(&self, f: impl FnOnce(&mut Vec<f64>)) {
+        let mut write = self.write();
+        f(&mut write);
+        self.change_marker().mark_searched_change();
+    }
+    /// Counter of the changes, bumped by every update (or [`mark_changed`](Tensor::mark_changed))
+    /// of a leaf tensor and every recompute of an op tensor
+    ///
+    /// Equal versions mean equal values, the converse is not guaranteed
+    #[inline]
+    pub fn version(&self) -> usize {
+        self.change_marker().version()
+    }
     #[inline]
     pub fn with_grad(&self) -> bool {
-        self.0.grad_id.is_some()
+        self.0.with_grad.load(Relaxed)
     }
+    /// Alias of [`with_grad`](Tensor::with_grad)
     #[inline]
-    fn zeros_like(&self) -> Vec<f64> {
-        vec![f64::zero(); self.values().read().unwrap().len()]
+    pub fn requires_grad(&self) -> bool {
+        self.with_grad()
     }
+    /// Read the values, recover the data when the lock is poisoned
+    ///
+    /// A released (or [lazy](lazy_build)) tensor is recomputed first
+    #[inline]
+    fn read(&self) -> RwLockReadGuard<'_, Vec<f64>> {
+        if self.is_released() {
+            self.recompute_released();
+        }
+        self.0.values.read().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Write the values, recover the data when the lock is poisoned
     #[inline]
-    fn ones_like(&self) -> Vec<f64> {
-        vec![f64::one(); self.values().read().unwrap().len()]
+    fn write(&self) -> RwLockWriteGuard<'_, Vec<f64>> {
+        self.0
+            .values
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
     }
+    /// Read the values, [`Error::PoisonedLock`] when the lock is poisoned
     #[inline]
-    fn op(&self) -> &Op {
+    fn try_read(&self) -> Result<RwLockReadGuard<'_, Vec<f64>>, Error> {
+        if self.is_released() {
+            self.recompute_released();
+        }
+        self.0.values.read().map_err(|_| {
+            self.0.values.clear_poison();
+            Error::PoisonedLock
+        })
+    }
+    /// Write the values, [`Error::PoisonedLock`] when the lock is poisoned
+    #[inline]
+    fn try_write(&self) -> Result<RwLockWriteGuard<'_, Vec<f64>>, Error> {
+        self.0.values.write().map_err(|_| {
+            self.0.values.clear_poison();
+            Error::PoisonedLock
+        })
+    }
+    #[inline]
+    fn zeros_like(&self) -> Vec<f64> {
+        vec![f64::zero(); self.read().len()]
+    }
+    /// The op producing the tensor, [`Op::Assign`] for a leaf tensor
+    #[inline]
+    pub fn op(&self) -> &Op {
         &self.0.op
     }
+    /// `None` when the tensor does not participate in the gradient
     #[inline]
-    fn grad_id(&self) -> &Option<GradId> {
-        &self.0.grad_id
+    fn grad_id(&self) -> Option<GradId> {
+        self.0.grad_id.filter(|_| self.with_grad())
+    }
+    /// The id of a leaf regardless of [`with_grad`](Tensor::with_grad)
+    #[inline]
+    fn leaf_id(&self) -> Option<GradId> {
+        self.0.grad_id
+    }
+    #[inline]
+    fn set_with_grad(&self, with_grad: bool) {
+        self.0.with_grad.store(with_grad, Relaxed)
     }
     #[inline]
     fn change_marker(&self) -> &ChangeMarker {
         &self.0.change_marker
     }
+    #[inline]
+    fn bounds(&self) -> &Bounds {
+        &self.0.bounds
+    }
+    #[inline]
+    fn retain(&self) -> &Retain {
+        &self.0.retain
+    }
+    #[inline]
+    fn grad_mask(&self) -> &GradMask {
+        &self.0.grad_mask
+    }
+    #[inline]
+    fn stats_cache(&self) -> &StatsCache {
+        &self.0.stats_cache
+    }
+    #[inline]
+    fn depth(&self) -> usize {
+        self.0.depth
+    }
     #[cfg(debug_assertions)]
     #[inline]
     fn is_logic(&self) -> bool {
@@ -68,33 +290,98 @@ impl Tensor {
         self.0.is_logic.store(true, Relaxed)
     }
     #[inline]
-    fn new(grad_id: Option<GradId>, values: Vec<f64>, op: Op) -> Self {
-        Self(Arc::new(_Tensor {
-            grad_id,
+    /// A leaf is given a [`GradId`], an op node is not
+    fn new(with_grad: bool, values: Vec<f64>, op: Op) -> Self {
+        op.check_anomaly(&values);
+        profile::count_allocation();
+        recompute::CONSTRUCTED.fetch_add(1, Relaxed);
+        let tensor = Self(Arc::new(_Tensor {
+            with_grad: AtomicBool::new(with_grad),
+            grad_id: matches!(op, Op::Assign).then(GradId::new),
             values: RwLock::new(values),
             change_marker: ChangeMarker::new(),
+            bounds: Bounds::default(),
+            retain: Retain::default(),
+            grad_mask: GradMask::default(),
+            stats_cache: StatsCache::default(),
+            depth: Self::depth_of(op.inputs()),
             op,
             #[cfg(debug_assertions)]
             is_logic: AtomicBool::new(false),
-        }))
+        }));
+        if let Op::DiscreteBinary(_, _, _, grad_method) = tensor.op() {
+            grad_method.register(&tensor);
+        }
+        tensor
     }
 }
 
-#[derive(Clone, Debug)]
+/// Identity of the shared tensor, the clones are equal
+impl PartialEq for Tensor {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for Tensor {}
+impl Hash for Tensor {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct TensorRef(Tensor);
 
 impl TensorRef {
+    /// `None` when the tensor is not with gradient
+    #[inline]
+    pub fn grad_id(&self) -> Option<GradId> {
+        self.0.grad_id()
+    }
+    /// Freeze (`false`) / unfreeze (`true`) the parameter, without rebuilding the graph
+    ///
+    /// The ops decide whether to propagate the gradient at each [`backward`](Expression::backward),
+    /// so the ops built when the parameter was frozen get its gradient after unfreezing
+    #[inline]
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        self.0.set_with_grad(requires_grad)
+    }
+    #[inline]
+    pub fn requires_grad(&self) -> bool {
+        self.0.with_grad()
+    }
+    #[inline]
+    pub fn tensor(&self) -> &Tensor {
+        &self.0
+    }
     /// Need [`before_update`] before calling this
     ///
     /// Need [`Expression::value`](Expression::value) after calling this
     ///
     /// Tensor = values
+    ///
+    /// Panics when the values violate the bounds under [strict mode](TensorRef::set_strict_bounds),
+    /// the tensor is not modified
     #[inline]
     pub fn assign(&self, values: Vec<f64>) {
-        let mut write = self.0.values().write().unwrap();
-        *write = values;
+        if let Err(e) = self.0.bounds().check_strict(&values, "assign") {
+            panic!("{e}");
+        }
+        *self.0.write() = values;
         self.0.change_marker().mark_searched_change();
     }
+    /// Fallible [`assign`](TensorRef::assign)
+    ///
+    /// The tensor is not modified when an error is returned
+    #[inline]
+    pub fn try_assign(&self, values: Vec<f64>) -> Result<(), Error> {
+        self.0.bounds().check_strict(&values, "assign")?;
+        *self.0.try_write()? = values;
+        self.0.change_marker().mark_searched_change();
+        Ok(())
+    }
     /// Need [`before_update`] before calling this
     ///
     /// Need [`Expression::value`](Expression::value) after calling this
@@ -102,21 +389,66 @@ impl TensorRef {
     /// Tensor\[i\] += delta\[i\]
     #[inline]
     pub fn update(&self, delta: &[f64]) {
-        self.update_iter(delta.into_iter().map(|d|*d))
+        self.update_iter(delta.iter().copied())
     }
     /// Need [`before_update`] before calling this
     ///
     /// Need [`Expression::value`](Expression::value) after calling this
     ///
     /// Tensor\[i\] += delta_iter\[i\]
+    ///
+    /// Panics when the lengths mismatch, or the values violate the bounds under
+    /// [strict mode](TensorRef::set_strict_bounds), the tensor is not modified
     #[inline]
     pub fn update_iter(&self, delta_iter: impl Iterator<Item = f64>) {
-        let mut write = self.0.values().write().unwrap();
-        zip_eq(write.iter_mut(), delta_iter).for_each(|(x, d)| *x += d);
+        let delta: Vec<f64> = delta_iter.collect();
+        let mut write = self.0.write();
+        match self.updated(&write, &delta) {
+            Ok(values) => {
+                *write = values;
+                self.0.change_marker().mark_searched_change();
+            }
+            Err(e) => {
+                // release the lock before panicking, so that it is not poisoned
+                drop(write);
+                panic!("{e}");
+            }
+        }
+    }
+    /// Fallible [`update`](TensorRef::update)
+    ///
+    /// The tensor is not modified when an error is returned
+    #[inline]
+    pub fn try_update(&self, delta: &[f64]) -> Result<(), Error> {
+        let mut write = self.0.try_write()?;
+        *write = self.updated(&write, delta)?;
         self.0.change_marker().mark_searched_change();
+        Ok(())
+    }
+    /// `values + delta`, validated against the length and the strict bounds
+    #[inline]
+    fn updated(&self, values: &[f64], delta: &[f64]) -> Result<Vec<f64>, Error> {
+        if values.len() != delta.len() {
+            return Err(Error::LengthMismatch {
+                expected: values.len(),
+                got: delta.len(),
+                op: "update".to_owned(),
+            });
+        }
+        let values: Vec<f64> = values.iter().zip(delta).map(|(x, d)| x + d).collect();
+        self.0.bounds().check_strict(&values, "update")?;
+        Ok(values)
     }
 }
 
+/// ## Lengths
+///
+/// + The element-wise ops (unary, binary, [`cond`](Expression::cond), comparison, ...)
+///   broadcast a length-1 tensor to the length of the others, the constants act as length-1
+/// + The other lengths must be equal, otherwise the op panics with [`Error::LengthMismatch`]
+///   (or returns it from the `try_*` variant), at the construction and at the recompute
+/// + Empty tensors are supported: the element-wise ops produce empty outputs,
+///   and an empty tensor broadcasts a length-1 tensor to empty
 #[derive(Clone, Debug)]
 pub enum Expression {
     Const(f64),
@@ -138,11 +470,7 @@ impl Expression {
     }
     #[inline]
     pub fn tensor(values: Vec<f64>, need_grad: bool) -> (Self, TensorRef) {
-        let tensor = Tensor::new(
-            if need_grad { Some(GradId::new()) } else { None },
-            values,
-            Op::Assgin,
-        );
+        let tensor = Tensor::new(need_grad, values, Op::Assign);
         (Self::Tensor(tensor.clone()), TensorRef(tensor))
     }
     #[inline]
@@ -185,17 +513,28 @@ impl Expression {
     /// get the value / recompute and get the value
     #[inline]
     pub fn value<'a>(&'a self) -> ScalarTensor<'a> {
-        self.recompute().into()
+        self.recompute_root().into()
     }
-    /// Mark the expression as logic for debug-mode-only logic check
+    /// The values as a new vector, length-1 for a constant,
+    /// see [`ScalarTensor::to_owned_values`]
+    #[inline]
+    pub fn values(&self) -> Vec<f64> {
+        self.value().to_owned_values()
+    }
+    /// The single value of a constant or a length-1 tensor, see [`ScalarTensor::as_scalar`]
+    #[inline]
+    pub fn scalar(&self) -> Option<f64> {
+        self.value().as_scalar()
+    }
+    /// Mark the expression as logic for debug-mode-only logic check,
+    /// e.g., after the bindings have checked its values are within `[0, 1]`
     ///
-    /// `#[cfg(test)]` This requirement seems only happend in test
-    #[cfg(test)]
+    /// A constant is checked by its value, no effect on it
+    #[inline]
     pub fn mark_logic(&self) {
         #[cfg(debug_assertions)]
-        match self {
-            Expression::Const(_) => todo!(),
-            Expression::Tensor(tensor) => tensor.mark_logic(),
+        if let Expression::Tensor(tensor) = self {
+            tensor.mark_logic();
         }
     }
 }
@@ -1,45 +1,476 @@
 use itertools::izip;
 use num_traits::{One, Zero};
 use ordered_float::OrderedFloat;
-use std::{cmp::Ordering, fmt::Debug};
+use std::{
+    cmp::Ordering,
+    collections::VecDeque,
+    fmt::{self, Debug},
+    panic::{RefUnwindSafe, UnwindSafe},
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering::Relaxed},
+        Arc, Mutex, MutexGuard, PoisonError, RwLockReadGuard, Weak,
+    },
+};
 
-use super::{Expression, GradId, Tensor};
+use super::{
+    _Tensor, assertion::AssertRange, complex::ComplexOp, ema::EmaState, lazy::is_lazy_build,
+    reduction, registry::UnaryId, Error, Expression, Tensor,
+};
 
+/// The operation of a tensor node, to inspect the graph, see [`Expression::find`]
 #[derive(Debug)]
 pub enum Op {
-    /// new assign
-    Assgin,
+    /// A leaf tensor, its values are assigned by [`TensorRef::assign`](super::TensorRef::assign)
+    /// and its family rather than computed
+    Assign,
+    /// Pass-through copy of the input, see [`Expression::assign_from`]
+    AssignFrom(Expression),
     Powf(Expression, f64),
+    /// Piecewise-linear table lookup
+    Pwl(Expression, Pwl),
+    /// Cubic spline interpolation
+    Spline(Expression, Spline),
+    /// Shockley diode current
+    Diode(Expression, Diode),
+    /// Smooth step / pulse
+    Transition(Expression, Transition),
+    /// Concatenation of the parts
+    Concat(Vec<Expression>),
+    /// Sub-range `[offset, offset + len)`
+    Narrow(Expression, usize, usize),
+    /// `output[i] = input[indices[i]]`
+    Gather(Expression, Vec<usize>),
+    /// `output[indices[i]] += input[i]`, output length
+    ScatterAdd(Expression, Vec<usize>, usize),
+    /// Reversed elements
+    Reverse(Expression),
+    /// `output[i] = input[i - offset]`, filled out of range
+    Shift(Expression, isize, f64),
+    /// Time derivative, sampled by `dt`
+    Ddt(Expression, f64),
+    /// Trapezoidal time integral, sampled by `dt`, from `initial`
+    Idt(Expression, f64, f64),
+    /// One-sided DFT magnitude or power
+    Spectrum(Expression, Spectrum),
+    /// First crossing time of the threshold, sampled at the times
+    CrossTime(Expression, CrossTime),
+    /// Quadrature over the times
+    Integrate(Expression, Integral),
+    /// Reduction of each window
+    WindowReduce(Expression, WindowReduce),
+    /// Reduction into one element
+    Reduce(Expression, Reduce),
+    /// Smoothed count of the elements above a threshold
+    CountGe(Expression, CountGe),
+    /// Soft bin counts of the samples
+    SoftHistogram(Expression, SoftHistogram),
+    /// 1-D convolution of the signal and the kernel
+    Conv1d(Expression, Expression, ConvPadding),
+    /// Product of the input and the row-major `rows × cols` weights
+    MatVec(Expression, Expression, usize, usize),
+    /// Polynomial of the input with the coefficients
+    Polyval(Expression, Expression),
+    /// Exponential moving average of the input, stepped once per epoch
+    Ema(Expression, f64, EmaState),
+    /// Sum or mean of the products of the input and the weights
+    Weighted(Expression, Expression, Weighted),
+    /// One real part of a fused complex op
+    Complex(Vec<Expression>, ComplexOp),
+    /// Identity that checks the values within the range
+    Assert(Expression, AssertRange),
+    UnaryParam(Expression, f64, UnaryParamOp),
+    /// `lhs / rhs`, `|rhs|` is clamped to `eps`
+    DivEps(Expression, Expression, f64),
+    /// Robust loss of the input to the target
+    RobustLoss(Expression, Expression, RobustLoss),
+    /// Element-wise user function
+    Custom(Expression, CustomUnary),
+    /// Element-wise function registered by [`register_unary`](super::register_unary)
+    ExternalUnary(Expression, UnaryId),
+    /// Element-wise user function of two inputs
+    CustomBinary(Expression, Expression, CustomBinary),
+    /// User op of any number of inputs
+    CustomNary(Vec<Expression>, CustomNary),
     /// `(cond)? on_true : on_false`
     ///
     /// smoothing method:
     /// `cond*on_true + (1-cond)*on_false`
-    Cond(Expression, Expression, Expression),
+    Cond(Expression, Expression, Expression, CondMethod),
+    /// `conds[0]? values[0] : conds[1]? values[1] : ... : default`
+    Select(Vec<Expression>, Vec<Expression>, Expression, CondMethod),
+    /// Logic op of all the terms, accumulated in log-space
+    LogicNary(Vec<Expression>, LogicNary),
     Unary(Expression, UnaryOp),
     Binary(Expression, Expression, BinaryOp),
     DiscreteBinary(Expression, Expression, DiscreteBinaryOp, GradMethod),
     // DiscreteUnary(Expression, DiscreteUnaryOp, GradMethod),
 }
 
+impl Op {
+    /// Former misspelled name of [`Op::Assign`], only usable as a value, match on [`Op::Assign`]
+    #[deprecated(note = "renamed to `Op::Assign`")]
+    #[allow(non_upper_case_globals)]
+    pub const Assgin: Op = Op::Assign;
+}
+
 /// GradMethod only activate in gradient mode
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum GradMethod {
     Discrete,
     Linear(GradMethodLinear),
     Sigmoid(GradMethodSigmoid),
+    Tanh(GradMethodTanh),
+    SmoothStep(GradMethodSmoothStep),
 }
 
 impl GradMethod {
+    /// See [`Expression::eq_sigmoid`] and its family
+    ///
+    /// ## Panics
+    ///
+    /// Unless `k` is positive and finite, see [`try_sigmoid`](GradMethod::try_sigmoid)
     #[inline]
-    fn new_sigmoid(k: f64) -> Self {
-        assert!(k.is_sign_positive());
-        Self::Sigmoid(GradMethodSigmoid { k })
+    pub fn new_sigmoid(k: f64) -> Self {
+        Self::try_sigmoid(k).unwrap_or_else(|e| panic!("{e}"))
     }
+    /// See [`Expression::eq_linear`] and its family
+    ///
+    /// ## Panics
+    ///
+    /// Unless `epsilon` is positive and finite, see [`try_linear`](GradMethod::try_linear)
     #[inline]
-    fn new_linear(epsilon: f64) -> Self {
-        assert!(epsilon.is_sign_positive());
-        Self::Linear(GradMethodLinear { epsilon })
+    pub fn new_linear(epsilon: f64) -> Self {
+        Self::try_linear(epsilon).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// See [`Expression::eq_tanh`] and its family
+    ///
+    /// ## Panics
+    ///
+    /// Unless `k` is positive and finite, see [`try_tanh`](GradMethod::try_tanh)
+    #[inline]
+    pub fn new_tanh(k: f64) -> Self {
+        Self::try_tanh(k).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// See [`Expression::eq_smoothstep`] and its family
+    ///
+    /// ## Panics
+    ///
+    /// Unless `epsilon` is positive and finite, see [`try_smoothstep`](GradMethod::try_smoothstep)
+    #[inline]
+    pub fn new_smoothstep(epsilon: f64) -> Self {
+        Self::try_smoothstep(epsilon).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`new_sigmoid`](GradMethod::new_sigmoid),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite
+    #[inline]
+    pub fn try_sigmoid(k: f64) -> Result<Self, Error> {
+        SharpnessHandle::checked("k", k).map(|k| Self::new_sigmoid_annealed(&k))
+    }
+    /// Fallible [`new_linear`](GradMethod::new_linear),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite
+    #[inline]
+    pub fn try_linear(epsilon: f64) -> Result<Self, Error> {
+        SharpnessHandle::checked("epsilon", epsilon).map(|e| Self::new_linear_annealed(&e))
+    }
+    /// Fallible [`new_tanh`](GradMethod::new_tanh),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite
+    #[inline]
+    pub fn try_tanh(k: f64) -> Result<Self, Error> {
+        SharpnessHandle::checked("k", k).map(|k| Self::new_tanh_annealed(&k))
+    }
+    /// Fallible [`new_smoothstep`](GradMethod::new_smoothstep),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite
+    #[inline]
+    pub fn try_smoothstep(epsilon: f64) -> Result<Self, Error> {
+        SharpnessHandle::checked("epsilon", epsilon).map(|e| Self::new_smoothstep_annealed(&e))
+    }
+    #[inline]
+    pub fn new_sigmoid_annealed(k: &SharpnessHandle) -> Self {
+        Self::Sigmoid(GradMethodSigmoid { k: k.clone() })
+    }
+    #[inline]
+    pub fn new_linear_annealed(epsilon: &SharpnessHandle) -> Self {
+        Self::Linear(GradMethodLinear {
+            epsilon: epsilon.clone(),
+        })
+    }
+    #[inline]
+    pub fn new_tanh_annealed(k: &SharpnessHandle) -> Self {
+        Self::Tanh(GradMethodTanh { k: k.clone() })
+    }
+    #[inline]
+    pub fn new_smoothstep_annealed(epsilon: &SharpnessHandle) -> Self {
+        Self::SmoothStep(GradMethodSmoothStep {
+            epsilon: epsilon.clone(),
+        })
+    }
+}
+
+static SMOOTH_FORWARD: AtomicU8 = AtomicU8::new(SmoothForward::OnlyWithGrad as u8);
+
+/// Forward value of the comparisons with a smooth [`GradMethod`], see [`set_smooth_forward`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SmoothForward {
+    /// The hard `0`/`1`, the method only shapes the gradient, the default
+    OnlyWithGrad,
+    /// The smooth function whose derivative is the gradient of the method,
+    /// e.g., `1 / (1 + e^(k(a - b)))` for [`le_sigmoid`](Expression::le_sigmoid),
+    /// whether or not any side requires the gradient,
+    /// for the logic composed of the comparisons
+    Always,
+}
+
+/// Select the forward value of the comparisons, [`SmoothForward::OnlyWithGrad`] by default
+///
+/// Read when a comparison is computed or recomputed, so switching it (or changing
+/// the [`SharpnessHandle`] under [`SmoothForward::Always`]) takes effect on the nodes
+/// built or recomputed afterwards. [`GradMethod::Discrete`] is always hard
+#[inline]
+pub fn set_smooth_forward(policy: SmoothForward) {
+    SMOOTH_FORWARD.store(policy as u8, Relaxed);
+}
+
+/// See [`set_smooth_forward`]
+#[inline]
+pub fn smooth_forward() -> SmoothForward {
+    match SMOOTH_FORWARD.load(Relaxed) {
+        0 => SmoothForward::OnlyWithGrad,
+        _ => SmoothForward::Always,
+    }
+}
+
+impl GradMethod {
+    /// Whether the forward value is [`smooth`](GradMethod::smooth), see [`set_smooth_forward`]
+    #[inline]
+    pub(super) fn is_smooth_forward(&self) -> bool {
+        !matches!(self, Self::Discrete) && smooth_forward() == SmoothForward::Always
+    }
+    /// The sharpness (`k` / `ε`) of this method, `None` for [`GradMethod::Discrete`]
+    #[inline]
+    pub(super) fn sharpness(&self) -> Option<&SharpnessHandle> {
+        match self {
+            Self::Discrete => None,
+            Self::Linear(GradMethodLinear { epsilon })
+            | Self::SmoothStep(GradMethodSmoothStep { epsilon }) => Some(epsilon),
+            Self::Sigmoid(GradMethodSigmoid { k }) | Self::Tanh(GradMethodTanh { k }) => Some(k),
+        }
+    }
+    /// The kind of this method, regardless of its sharpness
+    #[inline]
+    pub(super) fn tag(&self) -> u64 {
+        match self {
+            Self::Discrete => 0,
+            Self::Linear(_) => 1,
+            Self::Sigmoid(_) => 2,
+            Self::Tanh(_) => 3,
+            Self::SmoothStep(_) => 4,
+        }
+    }
+    /// Track the comparison `tensor` in the sharpness of this method, see [`SharpnessHandle::set`]
+    pub(super) fn register(&self, tensor: &Tensor) {
+        if let Some(sharpness) = self.sharpness() {
+            sharpness.register(tensor);
+        }
+    }
+    /// The smooth `op(lhs, rhs)` whose derivative is the gradient of this method,
+    /// the hard one for [`GradMethod::Discrete`]
+    ///
+    /// The output is clamped to `[0, 1]` against rounding drift, so that it composes
+    /// with the logic ops
+    pub(super) fn smooth(&self, op: DiscreteBinaryOp, lhs: f64, rhs: f64) -> f64 {
+        let out = match op {
+            DiscreteBinaryOp::Eq => self.smooth_eq(lhs - rhs),
+            DiscreteBinaryOp::Ne => 1.0 - self.smooth_eq(lhs - rhs),
+            DiscreteBinaryOp::Le => self.smooth_le(lhs - rhs, Le::forward),
+            DiscreteBinaryOp::Lt => self.smooth_le(lhs - rhs, Lt::forward),
+            DiscreteBinaryOp::Ge => self.smooth_le(rhs - lhs, Le::forward),
+            DiscreteBinaryOp::Gt => self.smooth_le(rhs - lhs, Lt::forward),
+        };
+        out.clamp(0.0, 1.0)
+    }
+    /// `eq` of `diff = a - b`
+    #[inline]
+    fn smooth_eq(&self, diff: f64) -> f64 {
+        match self {
+            Self::Discrete => Eq::forward(diff, 0.0),
+            Self::Linear(GradMethodLinear { epsilon }) => {
+                (1.0 - diff.abs() / epsilon.get()).max(0.0)
+            }
+            Self::Sigmoid(GradMethodSigmoid { k }) => (-k.get() * diff * diff).exp(),
+            Self::Tanh(GradMethodTanh { k }) => 1.0 - (k.get() * diff).tanh().powi(2),
+            Self::SmoothStep(GradMethodSmoothStep { epsilon }) => {
+                let u = (diff.abs() / epsilon.get()).min(1.0);
+                1.0 - u * u * (3.0 - 2.0 * u)
+            }
+        }
+    }
+    /// `le` of `diff = a - b`, `hard` for [`GradMethod::Discrete`]
+    #[inline]
+    fn smooth_le(&self, diff: f64, hard: fn(f64, f64) -> f64) -> f64 {
+        match self {
+            Self::Discrete => hard(diff, 0.0),
+            Self::Linear(GradMethodLinear { epsilon }) => {
+                (0.5 - diff / (2.0 * epsilon.get())).clamp(0.0, 1.0)
+            }
+            Self::Sigmoid(GradMethodSigmoid { k }) => 1.0 / (1.0 + (k.get() * diff).exp()),
+            Self::Tanh(GradMethodTanh { k }) => 0.5 * (1.0 - (k.get() * diff).tanh()),
+            Self::SmoothStep(GradMethodSmoothStep { epsilon }) => {
+                let t = ((diff + epsilon.get()) / (2.0 * epsilon.get())).clamp(0.0, 1.0);
+                1.0 - t * t * (3.0 - 2.0 * t)
+            }
+        }
+    }
+}
+
+/// Shared sharpness (`k` / `ε`) of the smoothed comparisons, adjustable at runtime
+///
+/// Used for annealing, e.g., start with a soft comparison and sharpen it over iterations
+/// without rebuilding the graph. The forward value of a comparison is discrete and does not
+/// depend on the sharpness, so changing it does not need [`before_update`](super::before_update),
+/// the next `backward` reads the current value.
+///
+/// Under [`SmoothForward::Always`] the forward value depends on the sharpness:
+/// [`set`](SharpnessHandle::set) flags the comparisons built with this handle, which are
+/// recomputed (with their dependents) by the next evaluation after [`before_update`](super::before_update)
+#[derive(Clone, Debug)]
+pub struct SharpnessHandle(Arc<Sharpness>);
+
+#[derive(Debug)]
+struct Sharpness {
+    value: AtomicU64,
+    /// The comparisons built with this sharpness
+    nodes: Mutex<Vec<Weak<_Tensor>>>,
+}
+
+impl SharpnessHandle {
+    /// ## Panics
+    ///
+    /// Unless `value` is positive and finite, see [`try_new`](SharpnessHandle::try_new)
+    #[inline]
+    pub fn new(value: f64) -> Self {
+        Self::try_new(value).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// [`Error::InvalidSharpness`] unless `value` is positive and finite
+    #[inline]
+    pub fn try_new(value: f64) -> Result<Self, Error> {
+        Self::checked("sharpness", value)
+    }
+    #[inline]
+    fn checked(name: &str, value: f64) -> Result<Self, Error> {
+        check_sharpness(name, value)?;
+        Ok(Self(Arc::new(Sharpness {
+            value: AtomicU64::new(value.to_bits()),
+            nodes: Mutex::new(Vec::new()),
+        })))
+    }
+    #[inline]
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.value.load(Relaxed))
+    }
+    /// ## Panics
+    ///
+    /// Unless `value` is positive and finite, see [`try_set`](SharpnessHandle::try_set)
+    #[inline]
+    pub fn set(&self, value: f64) {
+        self.try_set(value).unwrap_or_else(|e| panic!("{e}"));
+    }
+    /// [`Error::InvalidSharpness`] unless `value` is positive and finite,
+    /// the current value is kept on error
+    ///
+    /// Under [`SmoothForward::Always`] the comparisons built with this handle are flagged
+    /// for recomputation, see [`SharpnessHandle`]
+    #[inline]
+    pub fn try_set(&self, value: f64) -> Result<(), Error> {
+        check_sharpness("sharpness", value)?;
+        if self.0.value.swap(value.to_bits(), Relaxed) != value.to_bits()
+            && smooth_forward() == SmoothForward::Always
+        {
+            self.nodes().retain(|node| match node.upgrade() {
+                Some(node) => {
+                    node.change_marker.mark_forced();
+                    true
+                }
+                None => false,
+            });
+        }
+        Ok(())
+    }
+    #[inline]
+    fn nodes(&self) -> MutexGuard<'_, Vec<Weak<_Tensor>>> {
+        self.0.nodes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Whether both handles share the same sharpness
+    #[inline]
+    pub(super) fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+    /// The identity of the shared sharpness, equal for the handles that [`ptr_eq`](Self::ptr_eq)
+    #[inline]
+    pub(super) fn key(&self) -> usize {
+        Arc::as_ptr(&self.0) as usize
+    }
+    /// Track the comparison `tensor` built with this sharpness
+    fn register(&self, tensor: &Tensor) {
+        let mut nodes = self.nodes();
+        // drop the dead nodes before growing
+        if nodes.len() == nodes.capacity() {
+            nodes.retain(|node| node.strong_count() != 0);
+        }
+        nodes.push(Arc::downgrade(&tensor.0));
+    }
+}
+
+/// `k` / `ε` must be positive and finite, `0` and `inf` degenerate to the discrete step
+/// and NaN poisons the gradient
+#[inline]
+fn check_sharpness(name: &str, value: f64) -> Result<(), Error> {
+    if value > 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::InvalidSharpness(format!(
+            "{name} must be positive and finite, got {value}"
+        )))
+    }
+}
+
+/// Broadcast length of the tensors' lengths `lens`,
+/// a length-1 tensor broadcasts to the length of the others
+///
+/// [`Error::LengthMismatch`] when two lengths differ and neither is `1`
+#[inline]
+pub(super) fn broadcast_len(
+    op: &str,
+    lens: impl IntoIterator<Item = usize>,
+) -> Result<usize, Error> {
+    let mut len = None;
+    for l in lens {
+        match len {
+            None | Some(1) => len = Some(l),
+            Some(expected) if l != 1 && l != expected => {
+                return Err(Error::LengthMismatch {
+                    expected,
+                    got: l,
+                    op: op.to_owned(),
+                })
+            }
+            _ => (),
+        }
     }
+    Ok(len.unwrap_or(1))
+}
+
+/// Iterate `values` as length `len`, repeating a length-1 tensor
+///
+/// `len` must come from [`broadcast_len`], so that the values are never truncated
+#[inline]
+pub(super) fn broadcast(values: &[f64], len: usize) -> impl Iterator<Item = &f64> {
+    assert!(
+        values.len() == len || values.len() == 1,
+        "gspice internal error - broadcast length-{} tensor to length {len}",
+        values.len()
+    );
+    values.iter().cycle().take(len)
 }
 
 macro_rules! assert_logic {
@@ -94,208 +525,3628 @@ impl Expression {
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////
-///////////////////////////////////   Cond   ///////////////////////////////////////////////
+///////////////////////////////////   Pwl   ////////////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////////////////
 
-pub(super) struct Cond;
-impl Cond {
-    /// `(cond)? on_true : on_false`
+/// `xs` and `ys` have the same length of at least `min_len`, and `xs` is strictly increasing
+#[inline]
+fn check_table(xs: &[f64], ys: &[f64], min_len: usize) -> Result<(), String> {
+    if xs.len() < min_len {
+        return Err(format!("{} points, need at least {min_len}", xs.len()));
+    }
+    if xs.len() != ys.len() {
+        return Err(format!("{} xs but {} ys", xs.len(), ys.len()));
+    }
+    if let Some(i) = xs
+        .windows(2)
+        .position(|w| w[0].partial_cmp(&w[1]) != Some(std::cmp::Ordering::Less))
+    {
+        return Err(format!("xs is not strictly increasing at index {}", i + 1));
+    }
+    Ok(())
+}
+
+/// The segment `k` of `[xs[k], xs[k+1])` containing `x`, O(log n)
+///
+/// The queries outside the table fall into the end segments, `xs.len() >= 2`
+#[inline]
+fn table_segment(xs: &[f64], x: f64) -> usize {
+    xs.partition_point(|xi| *xi <= x)
+        .saturating_sub(1)
+        .min(xs.len() - 2)
+}
+
+/// Extrapolation rule of [`Expression::pwl`] outside the breakpoints
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PwlExtrapolation {
+    /// Hold the end values, zero gradient
+    Clamp,
+    /// Extend the end segments
+    Extend,
+}
+
+/// Piecewise-linear table, `xs` is strictly increasing
+#[derive(Clone, Debug)]
+pub struct Pwl {
+    pub(super) xs: Vec<f64>,
+    pub(super) ys: Vec<f64>,
+    pub(super) extrapolation: PwlExtrapolation,
+}
+
+impl Pwl {
+    /// [`Error::InvalidPwl`] when the table is empty, `xs` and `ys` have different lengths,
+    /// or `xs` is not strictly increasing
+    #[inline]
+    pub fn new(xs: &[f64], ys: &[f64], extrapolation: PwlExtrapolation) -> Result<Self, Error> {
+        check_table(xs, ys, 1).map_err(Error::InvalidPwl)?;
+        Ok(Self {
+            xs: xs.to_vec(),
+            ys: ys.to_vec(),
+            extrapolation,
+        })
+    }
+    /// The segment `[xs[k], xs[k+1])` of `x` and its slope, O(log n)
     ///
-    /// smoothing method:
-    /// `cond*on_true + (1-cond)*on_false`
+    /// A breakpoint takes the slope of its right segment, except the last one
     #[inline]
-    pub(super) fn forward(cond: &f64, on_true: f64, on_false: f64) -> f64 {
-        assert_logic!(*cond);
-        cond * on_true + (1.0 - cond) * on_false
+    fn segment(&self, x: f64) -> (usize, f64) {
+        if self.xs.len() == 1 {
+            return (0, 0.0);
+        }
+        let k = table_segment(&self.xs, x);
+        let slope = (self.ys[k + 1] - self.ys[k]) / (self.xs[k + 1] - self.xs[k]);
+        (k, slope)
     }
-    /// $\frac{\partial L}{\partial a} = \frac{\partial L}{\partial e} \cdot \frac{\partial e}{\partial a} = \text{grad\_output} \times (b - c)$
     #[inline]
-    pub(super) fn backward_cond(
-        _cond: &f64,
-        on_true: &f64,
-        on_false: &f64,
-        grad: &f64,
-        cond_grad: &mut f64,
-    ) {
-        *cond_grad += grad * (on_true - on_false);
+    fn is_outside(&self, x: f64) -> bool {
+        x < self.xs[0] || x > self.xs[self.xs.len() - 1]
     }
     #[inline]
-    pub(super) fn backward_on_true(
-        cond: &f64,
-        _on_true: &f64,
-        _on_false: &f64,
-        grad: &f64,
-        on_true_grad: &mut f64,
-    ) {
-        *on_true_grad += cond * grad;
+    pub(super) fn forward(&self, x: f64) -> f64 {
+        if self.extrapolation == PwlExtrapolation::Clamp && self.is_outside(x) {
+            if x < self.xs[0] {
+                self.ys[0]
+            } else {
+                self.ys[self.ys.len() - 1]
+            }
+        } else {
+            let (k, slope) = self.segment(x);
+            self.ys[k] + slope * (x - self.xs[k])
+        }
     }
     #[inline]
-    pub(super) fn backward_on_false(
-        cond: &f64,
-        _on_true: &f64,
-        _on_false: &f64,
-        grad: &f64,
-        on_false_grad: &mut f64,
-    ) {
-        *on_false_grad += (1.0 - cond) * grad;
+    pub(super) fn backward(&self, x: &f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
+        if self.extrapolation == PwlExtrapolation::Clamp && self.is_outside(*x) {
+            return;
+        }
+        *sum_grad += grad * self.segment(*x).1;
     }
+}
+
+impl Expression {
+    /// Piecewise-linear interpolation of the table `(xs, ys)`,
+    /// the gradient is the local segment slope
+    ///
+    /// ## Panics
+    ///
+    /// See [`Pwl::new`]
     #[inline]
-    pub(super) fn iter_tensor_x_x(
-        cond_tensor: &Tensor,
-        on_true_x: f64,
-        on_false_x: f64,
-    ) -> Vec<f64> {
-        cond_tensor
-            .values()
-            .read()
-            .unwrap()
-            .iter()
-            .map(|cond_x| Cond::forward(cond_x, on_true_x, on_false_x))
-            .collect()
+    pub fn pwl(&self, xs: &[f64], ys: &[f64], extrapolation: PwlExtrapolation) -> Self {
+        self.try_pwl(xs, ys, extrapolation)
+            .unwrap_or_else(|e| panic!("{e}"))
     }
+    /// Fallible [`pwl`](Expression::pwl)
     #[inline]
-    pub(super) fn iter_tensor_x_tensor(
-        cond_tensor: &Tensor,
-        on_true_x: f64,
-        on_false_tensor: &Tensor,
-    ) -> Vec<f64> {
-        izip!(
-            cond_tensor.values().read().unwrap().iter(),
-            on_false_tensor.values().read().unwrap().iter()
-        )
-        .map(|(cond_x, on_false_x)| Cond::forward(cond_x, on_true_x, *on_false_x))
-        .collect()
+    pub fn try_pwl(
+        &self,
+        xs: &[f64],
+        ys: &[f64],
+        extrapolation: PwlExtrapolation,
+    ) -> Result<Self, Error> {
+        let pwl = Pwl::new(xs, ys, extrapolation)?;
+        Ok(match self {
+            Self::Const(x) => Self::Const(pwl.forward(*x)),
+            Self::Tensor(tensor) => Self::Tensor(tensor.unary_op(
+                |x| pwl.forward(x),
+                Op::Pwl(Self::Tensor(tensor.clone()), pwl.clone()),
+            )),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Spline   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Boundary condition of [`Expression::spline`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplineBoundary {
+    /// Zero second derivative at both ends
+    Natural,
+    /// Given first derivative `d0` at the first knot and `dn` at the last knot
+    Clamped { d0: f64, dn: f64 },
+}
+
+/// Cubic spline, `xs` is strictly increasing
+///
+/// Outside the knots, the spline is extended linearly with its end slopes,
+/// so the value and the first derivative stay continuous
+#[derive(Clone, Debug)]
+pub struct Spline {
+    pub(super) xs: Vec<f64>,
+    pub(super) ys: Vec<f64>,
+    /// Second derivatives at the knots
+    pub(super) ms: Vec<f64>,
+}
+
+impl Spline {
+    /// Solve the second derivatives at the knots
+    ///
+    /// [`Error::InvalidSpline`] when there are less than two knots, `xs` and `ys` have
+    /// different lengths, or `xs` is not strictly increasing
+    pub fn new(xs: &[f64], ys: &[f64], bc: SplineBoundary) -> Result<Self, Error> {
+        check_table(xs, ys, 2).map_err(Error::InvalidSpline)?;
+        let n = xs.len();
+        let h: Vec<f64> = xs.windows(2).map(|w| w[1] - w[0]).collect();
+        let slope: Vec<f64> = (0..n - 1).map(|i| (ys[i + 1] - ys[i]) / h[i]).collect();
+        // tridiagonal system: sub[i] m[i-1] + diag[i] m[i] + sup[i] m[i+1] = rhs[i]
+        let mut sub = vec![0.0; n];
+        let mut diag = vec![1.0; n];
+        let mut sup = vec![0.0; n];
+        let mut rhs = vec![0.0; n];
+        for i in 1..n - 1 {
+            sub[i] = h[i - 1];
+            diag[i] = 2.0 * (h[i - 1] + h[i]);
+            sup[i] = h[i];
+            rhs[i] = 6.0 * (slope[i] - slope[i - 1]);
+        }
+        if let SplineBoundary::Clamped { d0, dn } = bc {
+            diag[0] = 2.0 * h[0];
+            sup[0] = h[0];
+            rhs[0] = 6.0 * (slope[0] - d0);
+            sub[n - 1] = h[n - 2];
+            diag[n - 1] = 2.0 * h[n - 2];
+            rhs[n - 1] = 6.0 * (dn - slope[n - 2]);
+        }
+        // Thomas algorithm
+        for i in 1..n {
+            let w = sub[i] / diag[i - 1];
+            diag[i] -= w * sup[i - 1];
+            rhs[i] -= w * rhs[i - 1];
+        }
+        let mut ms = rhs;
+        ms[n - 1] /= diag[n - 1];
+        for i in (0..n - 1).rev() {
+            ms[i] = (ms[i] - sup[i] * ms[i + 1]) / diag[i];
+        }
+        Ok(Self {
+            xs: xs.to_vec(),
+            ys: ys.to_vec(),
+            ms,
+        })
+    }
+    /// Value and first derivative of the cubic of segment `k` at `x`
+    #[inline]
+    fn eval(&self, k: usize, x: f64) -> (f64, f64) {
+        let h = self.xs[k + 1] - self.xs[k];
+        let a = (self.xs[k + 1] - x) / h;
+        let b = (x - self.xs[k]) / h;
+        let (m0, m1) = (self.ms[k], self.ms[k + 1]);
+        let y = a * self.ys[k]
+            + b * self.ys[k + 1]
+            + ((a * a * a - a) * m0 + (b * b * b - b) * m1) * h * h / 6.0;
+        let dy = (self.ys[k + 1] - self.ys[k]) / h
+            + ((3.0 * b * b - 1.0) * m1 - (3.0 * a * a - 1.0) * m0) * h / 6.0;
+        (y, dy)
+    }
+    /// Value and first derivative at `x`, linear outside the knots
+    #[inline]
+    fn eval_extended(&self, x: f64) -> (f64, f64) {
+        let n = self.xs.len();
+        let end = if x < self.xs[0] {
+            0
+        } else if x > self.xs[n - 1] {
+            n - 1
+        } else {
+            return self.eval(table_segment(&self.xs, x), x);
+        };
+        let (y, dy) = self.eval(end.min(n - 2), self.xs[end]);
+        (y + dy * (x - self.xs[end]), dy)
     }
     #[inline]
-    pub(super) fn iter_tensor_tensor_x(
-        cond_tensor: &Tensor,
-        on_true_tensor: &Tensor,
-        on_false_x: f64,
-    ) -> Vec<f64> {
-        izip!(
-            cond_tensor.values().read().unwrap().iter(),
-            on_true_tensor.values().read().unwrap().iter(),
-        )
-        .map(|(cond_x, on_true_x)| Cond::forward(cond_x, *on_true_x, on_false_x))
-        .collect()
+    pub(super) fn forward(&self, x: f64) -> f64 {
+        self.eval_extended(x).0
     }
     #[inline]
-    pub(super) fn iter_tensor_tensor_tensor(
-        cond_tensor: &Tensor,
-        on_true_tensor: &Tensor,
-        on_false_tensor: &Tensor,
-    ) -> Vec<f64> {
-        izip!(
-            cond_tensor.values().read().unwrap().iter(),
-            on_true_tensor.values().read().unwrap().iter(),
-            on_false_tensor.values().read().unwrap().iter()
-        )
-        .map(|(cond_x, on_true_x, on_false_x)| Cond::forward(cond_x, *on_true_x, *on_false_x))
-        .collect()
+    pub(super) fn backward(&self, x: &f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
+        *sum_grad += grad * self.eval_extended(*x).1;
     }
 }
 
 impl Expression {
-    /// smoothing method
-    /// `cond*on_true + (1-cond)*on_false`
+    /// Cubic spline interpolation of the knots `(xs, ys)` with the boundary condition `bc`,
+    /// the gradient is the analytic spline derivative
+    ///
+    /// Outside the knots, the spline is extended linearly with its end slopes
+    ///
+    /// ## Panics
+    ///
+    /// See [`Spline::new`]
     #[inline]
-    pub fn cond(&self, on_true: &Self, on_false: &Self) -> Self {
-        #[cfg(debug_assertions)]
-        if let Self::Tensor(cond_tensor) = self {
-            assert_logic_tensor!(cond_tensor);
+    pub fn spline(&self, xs: &[f64], ys: &[f64], bc: SplineBoundary) -> Self {
+        self.try_spline(xs, ys, bc)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`spline`](Expression::spline)
+    #[inline]
+    pub fn try_spline(&self, xs: &[f64], ys: &[f64], bc: SplineBoundary) -> Result<Self, Error> {
+        let spline = Spline::new(xs, ys, bc)?;
+        Ok(match self {
+            Self::Const(x) => Self::Const(spline.forward(*x)),
+            Self::Tensor(tensor) => Self::Tensor(tensor.unary_op(
+                |x| spline.forward(x),
+                Op::Spline(Self::Tensor(tensor.clone()), spline.clone()),
+            )),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Diode   //////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Shockley diode current with [`Expression::limexp`], see [`Expression::diode`]
+#[derive(Clone, Copy, Debug)]
+pub struct Diode {
+    is: f64,
+    n_vt: f64,
+}
+
+impl Diode {
+    /// `[Is, nVt]`
+    #[inline]
+    pub(super) fn params(&self) -> [f64; 2] {
+        [self.is, self.n_vt]
+    }
+    #[inline]
+    pub(super) fn forward(&self, v: f64) -> f64 {
+        let x = v / self.n_vt;
+        if x <= LIMEXP_X0 {
+            self.is * x.exp_m1()
+        } else {
+            self.is * (LimExp::forward(x) - 1.0)
         }
-        match (self, on_true, on_false) {
-            (Self::Const(cond_x), Self::Const(on_true_x), Self::Const(on_false_x)) => {
-                Self::Const(Cond::forward(cond_x, *on_true_x, *on_false_x))
-            }
-            (Self::Const(cond_x), Self::Const(on_true_x), Self::Tensor(on_false_tensor)) => {
-                if cond_x.is_zero() {
-                    Self::Tensor(on_false_tensor.clone())
-                } else {
-                    Self::Const(*on_true_x)
-                }
-            }
-            (Self::Const(cond_x), Self::Tensor(on_true_tensor), Self::Const(on_false_x)) => {
-                if cond_x.is_zero() {
-                    Self::Const(*on_false_x)
-                } else {
-                    Self::Tensor(on_true_tensor.clone())
-                }
-            }
-            (Self::Const(cond_x), Self::Tensor(on_true_tensor), Self::Tensor(on_false_tensor)) => {
-                if cond_x.is_zero() {
-                    Self::Tensor(on_false_tensor.clone())
-                } else {
-                    Self::Tensor(on_true_tensor.clone())
-                }
-            }
-            (Self::Tensor(cond_tensor), Self::Const(on_true_x), Self::Const(on_false_x)) => {
-                Self::Tensor(Tensor::new(
-                    if cond_tensor.with_grad() {
-                        Some(GradId::new())
-                    } else {
-                        None
-                    },
-                    Cond::iter_tensor_x_x(cond_tensor, *on_true_x, *on_false_x),
-                    Op::Cond(
-                        Self::Tensor(cond_tensor.clone()),
-                        Self::Const(*on_true_x),
-                        Self::Const(*on_false_x),
-                    ),
-                ))
+    }
+    /// `Is/(nVt) * limexp'(v/(nVt))`
+    #[inline]
+    pub(super) fn backward(&self, v: &f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
+        *sum_grad += grad * self.is / self.n_vt * LimExp::derivative(v / self.n_vt);
+    }
+}
+
+impl Expression {
+    /// Shockley diode current `Is*(limexp(v/(nVt))-1)` of the voltage `self`,
+    /// `is > 0` and `n_vt > 0`
+    #[inline]
+    pub fn diode(&self, is: f64, n_vt: f64) -> Self {
+        assert!(is > 0.0 && n_vt > 0.0);
+        let diode = Diode { is, n_vt };
+        match self {
+            Self::Const(v) => Self::Const(diode.forward(*v)),
+            Self::Tensor(tensor) => Self::Tensor(tensor.unary_op(
+                |v| diode.forward(v),
+                Op::Diode(Self::Tensor(tensor.clone()), diode),
+            )),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Transition   /////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Smooth step / pulse shapes for behavioral sources
+#[derive(Clone, Copy, Debug)]
+pub enum Transition {
+    /// `0` below `x0`, `1` above `x1`, `3t² - 2t³` between, `t = (x - x0)/(x1 - x0)`
+    SmoothStepRange { x0: f64, x1: f64 },
+    /// `exp(-(x - center)²/(2σ²))`
+    GaussPulse { center: f64, sigma: f64 },
+}
+
+impl Transition {
+    #[inline]
+    pub(super) fn forward(&self, x: f64) -> f64 {
+        match *self {
+            Self::SmoothStepRange { x0, x1 } => {
+                if x <= x0 {
+                    0.0
+                } else if x >= x1 {
+                    1.0
+                } else {
+                    let t = (x - x0) / (x1 - x0);
+                    t * t * (3.0 - 2.0 * t)
+                }
             }
-            (Self::Tensor(cond_tensor), Self::Const(on_true_x), Self::Tensor(on_false_tensor)) => {
-                Self::Tensor(Tensor::new(
-                    if cond_tensor.with_grad() || on_false_tensor.with_grad() {
-                        Some(GradId::new())
-                    } else {
-                        None
-                    },
-                    Cond::iter_tensor_x_tensor(cond_tensor, *on_true_x, on_false_tensor),
-                    Op::Cond(
-                        Self::Tensor(cond_tensor.clone()),
-                        Self::Const(*on_true_x),
-                        Self::Tensor(on_false_tensor.clone()),
-                    ),
+            Self::GaussPulse { center, sigma } => {
+                let u = (x - center) / sigma;
+                (-0.5 * u * u).exp()
+            }
+        }
+    }
+    #[inline]
+    pub(super) fn backward(&self, x: &f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
+        match *self {
+            Self::SmoothStepRange { x0, x1 } => {
+                let t = (x - x0) / (x1 - x0);
+                *sum_grad += grad * GradMethodSmoothStep::ramp_derivative(t) / (x1 - x0);
+            }
+            Self::GaussPulse { center, sigma } => {
+                *sum_grad -= grad * res * (x - center) / (sigma * sigma);
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// `0` below `x0`, `1` above `x1`, and the C1 ramp `3t² - 2t³` between,
+    /// `t = (x - x0)/(x1 - x0)`, `x0 < x1`
+    ///
+    /// The output saturates exactly outside the ramp, so it is a valid logic input
+    ///
+    /// ``` text
+    ///              ______ 1
+    ///            /
+    /// 0 ______ /
+    /// ---------------->
+    ///        x0  x1    x
+    /// ```
+    #[inline]
+    pub fn smoothstep_range(&self, x0: f64, x1: f64) -> Self {
+        assert!(x0 < x1);
+        let out = self.transition_op(Transition::SmoothStepRange { x0, x1 });
+        if let Self::Tensor(tensor) = &out {
+            mark_logic_tensor!(tensor);
+        }
+        out
+    }
+    /// Gaussian pulse `exp(-(x - center)²/(2σ²))`, `sigma > 0`
+    #[inline]
+    pub fn gauss_pulse(&self, center: f64, sigma: f64) -> Self {
+        assert!(sigma > 0.0);
+        self.transition_op(Transition::GaussPulse { center, sigma })
+    }
+    #[inline]
+    fn transition_op(&self, transition: Transition) -> Self {
+        match self {
+            Self::Const(x) => Self::Const(transition.forward(*x)),
+            Self::Tensor(tensor) => Self::Tensor(tensor.unary_op(
+                |x| transition.forward(x),
+                Op::Transition(Self::Tensor(tensor.clone()), transition),
+            )),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   DivEps   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+pub(super) struct DivEps;
+impl DivEps {
+    /// `sign(rhs) * max(|rhs|, eps)`, `rhs = 0` is treated as positive
+    #[inline]
+    fn clamp(rhs: f64, eps: f64) -> f64 {
+        if rhs.abs() >= eps {
+            rhs
+        } else if rhs < 0.0 {
+            -eps
+        } else {
+            eps
+        }
+    }
+    #[inline]
+    pub(super) fn forward(lhs: f64, rhs: f64, eps: f64) -> f64 {
+        lhs / Self::clamp(rhs, eps)
+    }
+    #[inline]
+    pub(super) fn backward_lhs(
+        _lhs: &f64,
+        rhs: &f64,
+        eps: f64,
+        _res: &f64,
+        grad: &f64,
+        sum_grad: &mut f64,
+    ) {
+        *sum_grad += grad / Self::clamp(*rhs, eps);
+    }
+    /// `-lhs/rhs²` when `|rhs| >= eps`, otherwise `0`
+    #[inline]
+    pub(super) fn backward_rhs(
+        _lhs: &f64,
+        rhs: &f64,
+        eps: f64,
+        res: &f64,
+        grad: &f64,
+        sum_grad: &mut f64,
+    ) {
+        if rhs.abs() >= eps {
+            *sum_grad -= grad * res / rhs;
+        }
+    }
+}
+impl Expression {
+    /// `lhs / rhs` with `|rhs|` clamped to `eps`, `eps > 0`
+    ///
+    /// The lhs gradient is `1/rhs'` where `rhs' = sign(rhs) * max(|rhs|, eps)`,
+    /// the rhs gradient is `-lhs/rhs²` when `|rhs| >= eps`,
+    /// and `0` in the clamped region `|rhs| < eps`
+    #[inline]
+    pub fn div_eps(&self, rhs: &Self, eps: f64) -> Self {
+        assert!(eps > 0.0);
+        let forward_lhs_rhs = move |lhs, rhs| DivEps::forward(lhs, rhs, eps);
+        let forward_rhs_lhs = move |rhs, lhs| DivEps::forward(lhs, rhs, eps);
+        match (self, rhs) {
+            (Self::Const(lhs_x), Self::Const(rhs_x)) => {
+                Self::Const(DivEps::forward(*lhs_x, *rhs_x, eps))
+            }
+            (Self::Const(lhs_x), Self::Tensor(rhs_tensor)) => {
+                Self::Tensor(rhs_tensor.broadcast_binary_op(
+                    *lhs_x,
+                    forward_rhs_lhs,
+                    Op::DivEps(self.clone(), rhs.clone(), eps),
                 ))
             }
-            (Self::Tensor(cond_tensor), Self::Tensor(on_true_tensor), Self::Const(on_false_x)) => {
-                Self::Tensor(Tensor::new(
-                    if cond_tensor.with_grad() || on_true_tensor.with_grad() {
-                        Some(GradId::new())
-                    } else {
-                        None
-                    },
-                    Cond::iter_tensor_tensor_x(cond_tensor, on_true_tensor, *on_false_x),
-                    Op::Cond(
-                        Self::Tensor(cond_tensor.clone()),
-                        Self::Tensor(on_true_tensor.clone()),
-                        Self::Const(*on_false_x),
-                    ),
+            (Self::Tensor(lhs_tensor), Self::Const(rhs_x)) => {
+                Self::Tensor(lhs_tensor.broadcast_binary_op(
+                    *rhs_x,
+                    forward_lhs_rhs,
+                    Op::DivEps(self.clone(), rhs.clone(), eps),
                 ))
             }
-            (
-                Self::Tensor(cond_tensor),
-                Self::Tensor(on_true_tensor),
-                Self::Tensor(on_false_tensor),
-            ) => Self::Tensor(Tensor::new(
-                if cond_tensor.with_grad()
-                    || on_true_tensor.with_grad()
-                    || on_false_tensor.with_grad()
+            (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) => {
+                if let Err(e) =
+                    broadcast_len("DivEps", [lhs_tensor.read().len(), rhs_tensor.read().len()])
                 {
-                    Some(GradId::new())
+                    panic!("{e}");
+                }
+                Self::Tensor(lhs_tensor.binary_op(
+                    rhs_tensor,
+                    forward_lhs_rhs,
+                    Op::DivEps(self.clone(), rhs.clone(), eps),
+                ))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   RobustLoss   /////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Element-wise loss of the residual `d = x - target`, less sensitive to the outliers
+/// than the square, see [`Expression::huber`] and [`Expression::log_cosh`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RobustLoss {
+    /// `d²/2` within `±delta`, `delta (|d| - delta/2)` outside
+    Huber(f64),
+    /// `ln cosh(d)`
+    LogCosh,
+}
+
+impl RobustLoss {
+    #[inline]
+    pub(super) fn forward(self, x: f64, target: f64) -> f64 {
+        let d = x - target;
+        match self {
+            Self::Huber(delta) => {
+                if d.abs() <= delta {
+                    0.5 * d * d
                 } else {
-                    None
-                },
-                Cond::iter_tensor_tensor_tensor(cond_tensor, on_true_tensor, on_false_tensor),
-                Op::Cond(
-                    Self::Tensor(cond_tensor.clone()),
-                    Self::Tensor(on_true_tensor.clone()),
-                    Self::Tensor(on_false_tensor.clone()),
-                ),
+                    delta * (d.abs() - 0.5 * delta)
+                }
+            }
+            // |d| + ln((1 + exp(-2|d|)) / 2), without the overflow of cosh
+            Self::LogCosh => d.abs() + (-2.0 * d.abs()).exp().ln_1p() - std::f64::consts::LN_2,
+        }
+    }
+    /// The derivative to `x`, the opposite of the derivative to `target`
+    #[inline]
+    pub(super) fn derivative(self, x: f64, target: f64) -> f64 {
+        let d = x - target;
+        match self {
+            Self::Huber(delta) => d.clamp(-delta, delta),
+            Self::LogCosh => d.tanh(),
+        }
+    }
+    #[inline]
+    pub(super) fn backward_lhs(
+        self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        sum_grad: &mut f64,
+    ) {
+        *sum_grad += grad * self.derivative(*lhs, *rhs);
+    }
+    #[inline]
+    pub(super) fn backward_rhs(
+        self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        sum_grad: &mut f64,
+    ) {
+        *sum_grad -= grad * self.derivative(*lhs, *rhs);
+    }
+}
+
+impl Expression {
+    /// Huber loss of the residual `d = self - target`, element-wise:
+    /// `d²/2` within `±delta`, `delta (|d| - delta/2)` outside, `delta > 0`
+    ///
+    /// The value and the gradient `clamp(d, -delta, delta)` are continuous at `±delta`,
+    /// the target gradient is the opposite. Reduce it separately, e.g., with [`mean`](Expression::mean)
+    #[inline]
+    pub fn huber(&self, target: &Self, delta: f64) -> Self {
+        assert!(delta > 0.0);
+        self.robust_loss(target, RobustLoss::Huber(delta))
+    }
+    /// `ln cosh(d)` of the residual `d = self - target`, element-wise,
+    /// about `d²/2` when small and `|d| - ln 2` when large
+    ///
+    /// Computed as `|d| + ln((1 + exp(-2|d|)) / 2)`, which does not overflow.
+    /// The gradient is `tanh(d)`, the target gradient is the opposite
+    #[inline]
+    pub fn log_cosh(&self, target: &Self) -> Self {
+        self.robust_loss(target, RobustLoss::LogCosh)
+    }
+    #[inline]
+    fn robust_loss(&self, target: &Self, loss: RobustLoss) -> Self {
+        let forward_lhs_rhs = move |lhs, rhs| loss.forward(lhs, rhs);
+        let forward_rhs_lhs = move |rhs, lhs| loss.forward(lhs, rhs);
+        match (self, target) {
+            (Self::Const(lhs_x), Self::Const(rhs_x)) => Self::Const(loss.forward(*lhs_x, *rhs_x)),
+            (Self::Const(lhs_x), Self::Tensor(rhs_tensor)) => {
+                Self::Tensor(rhs_tensor.broadcast_binary_op(
+                    *lhs_x,
+                    forward_rhs_lhs,
+                    Op::RobustLoss(self.clone(), target.clone(), loss),
+                ))
+            }
+            (Self::Tensor(lhs_tensor), Self::Const(rhs_x)) => {
+                Self::Tensor(lhs_tensor.broadcast_binary_op(
+                    *rhs_x,
+                    forward_lhs_rhs,
+                    Op::RobustLoss(self.clone(), target.clone(), loss),
+                ))
+            }
+            (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) => {
+                if let Err(e) = broadcast_len(
+                    "RobustLoss",
+                    [lhs_tensor.read().len(), rhs_tensor.read().len()],
+                ) {
+                    panic!("{e}");
+                }
+                Self::Tensor(lhs_tensor.binary_op(
+                    rhs_tensor,
+                    forward_lhs_rhs,
+                    Op::RobustLoss(self.clone(), target.clone(), loss),
+                ))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Custom   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// `x -> res` of [`Expression::map_custom`]
+pub type CustomUnaryForward = Arc<dyn Fn(f64) -> f64 + Send + Sync>;
+/// `(x, res, grad) -> contribution` of [`Expression::map_custom`]
+pub type CustomUnaryBackward = Arc<dyn Fn(f64, f64, f64) -> f64 + Send + Sync>;
+/// `(lhs, rhs) -> res` of [`Expression::zip_custom`]
+pub type CustomBinaryForward = Arc<dyn Fn(f64, f64) -> f64 + Send + Sync>;
+/// `(lhs, rhs, res, grad) -> (lhs contribution, rhs contribution)` of [`Expression::zip_custom`]
+pub type CustomBinaryBackward = Arc<dyn Fn(f64, f64, f64, f64) -> (f64, f64) + Send + Sync>;
+
+/// User element-wise function, see [`Expression::map_custom`]
+#[derive(Clone)]
+pub struct CustomUnary {
+    name: Arc<str>,
+    forward: CustomUnaryForward,
+    /// `(x, res, grad) -> local contribution`
+    backward: CustomUnaryBackward,
+}
+
+impl CustomUnary {
+    #[inline]
+    pub(super) fn new(
+        name: &str,
+        forward: CustomUnaryForward,
+        backward: CustomUnaryBackward,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            forward,
+            backward,
+        }
+    }
+    #[inline]
+    pub(super) fn forward(&self, x: f64) -> f64 {
+        (self.forward)(x)
+    }
+    #[inline]
+    pub(super) fn backward(&self, x: &f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
+        *sum_grad += (self.backward)(*x, *res, *grad);
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// User element-wise function of two inputs, see [`Expression::zip_custom`]
+#[derive(Clone)]
+pub struct CustomBinary {
+    name: Arc<str>,
+    forward: CustomBinaryForward,
+    /// `(lhs, rhs, res, grad) -> (lhs contribution, rhs contribution)`
+    backward: CustomBinaryBackward,
+}
+
+impl CustomBinary {
+    #[inline]
+    pub(super) fn forward(&self, lhs: f64, rhs: f64) -> f64 {
+        (self.forward)(lhs, rhs)
+    }
+    #[inline]
+    pub(super) fn backward_lhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        res: &f64,
+        grad: &f64,
+        sum_grad: &mut f64,
+    ) {
+        *sum_grad += (self.backward)(*lhs, *rhs, *res, *grad).0;
+    }
+    #[inline]
+    pub(super) fn backward_rhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        res: &f64,
+        grad: &f64,
+        sum_grad: &mut f64,
+    ) {
+        *sum_grad += (self.backward)(*lhs, *rhs, *res, *grad).1;
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// The graph stays unwind safe: the closures are only called (`Fn`),
+// and a panic inside them is handled like the one of a built-in kernel
+impl UnwindSafe for CustomUnary {}
+impl RefUnwindSafe for CustomUnary {}
+impl UnwindSafe for CustomBinary {}
+impl RefUnwindSafe for CustomBinary {}
+
+/// Shows the provided name
+impl fmt::Debug for CustomUnary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+impl fmt::Display for CustomUnary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+/// Shows the provided name
+impl fmt::Debug for CustomBinary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+impl fmt::Display for CustomBinary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+impl Expression {
+    /// Element-wise user function, an escape hatch for the functions GSPICE does not provide
+    ///
+    /// `backward(x, res, grad)` returns the local contribution to the gradient of `x`,
+    /// i.e., `grad * f'(x)`, where `res = forward(x)`
+    ///
+    /// `name` is shown by [`Debug`] and the [anomaly report](super::AnomalyReport)
+    #[inline]
+    pub fn map_custom(
+        &self,
+        forward: CustomUnaryForward,
+        backward: CustomUnaryBackward,
+        name: &str,
+    ) -> Self {
+        let custom = CustomUnary::new(name, forward, backward);
+        match self {
+            Self::Const(x) => Self::Const(custom.forward(*x)),
+            Self::Tensor(tensor) => Self::Tensor(tensor.unary_op(
+                |x| custom.forward(x),
+                Op::Custom(self.clone(), custom.clone()),
             )),
         }
     }
+    /// Element-wise user function of `self` and `rhs`, see [`map_custom`](Expression::map_custom)
+    ///
+    /// `backward(lhs, rhs, res, grad)` returns the local contributions to the gradients of
+    /// `lhs` and `rhs`, a length-1 side is broadcasted like the built-in binary ops
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_zip_custom`](Expression::try_zip_custom)
+    #[inline]
+    pub fn zip_custom(
+        &self,
+        rhs: &Self,
+        forward: CustomBinaryForward,
+        backward: CustomBinaryBackward,
+        name: &str,
+    ) -> Self {
+        self.try_zip_custom(rhs, forward, backward, name)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`zip_custom`](Expression::zip_custom)
+    ///
+    /// [`Error::LengthMismatch`] when both sides are tensors with different lengths,
+    /// and neither is length-1
+    #[inline]
+    pub fn try_zip_custom(
+        &self,
+        rhs: &Self,
+        forward: CustomBinaryForward,
+        backward: CustomBinaryBackward,
+        name: &str,
+    ) -> Result<Self, Error> {
+        let custom = CustomBinary {
+            name: name.into(),
+            forward,
+            backward,
+        };
+        let op = || Op::CustomBinary(self.clone(), rhs.clone(), custom.clone());
+        Ok(match (self, rhs) {
+            (Self::Const(lhs_x), Self::Const(rhs_x)) => Self::Const(custom.forward(*lhs_x, *rhs_x)),
+            (Self::Const(lhs_x), Self::Tensor(rhs_tensor)) => Self::Tensor(
+                rhs_tensor.broadcast_binary_op(*lhs_x, |rhs, lhs| custom.forward(lhs, rhs), op()),
+            ),
+            (Self::Tensor(lhs_tensor), Self::Const(rhs_x)) => Self::Tensor(
+                lhs_tensor.broadcast_binary_op(*rhs_x, |lhs, rhs| custom.forward(lhs, rhs), op()),
+            ),
+            (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) => {
+                broadcast_len(name, [lhs_tensor.values_len(), rhs_tensor.values_len()])?;
+                Self::Tensor(lhs_tensor.binary_op(
+                    rhs_tensor,
+                    |lhs, rhs| custom.forward(lhs, rhs),
+                    op(),
+                ))
+            }
+        })
+    }
+}
+
+/// User op of any number of inputs with vector-level access, see [`Expression::apply_custom`]
+///
+/// Unlike [`map_custom`](Expression::map_custom), the whole input vectors are given at once,
+/// e.g., for an external table model or an FFI call. The methods take `&self`,
+/// an internal cache needs interior mutability
+pub trait CustomOp: Send + Sync {
+    /// Write the result into the empty `out`, its length is the one of the output tensor
+    ///
+    /// `inputs[k]` are the values of the `k`-th input, a constant is a one-element slice
+    fn forward(&self, inputs: &[&[f64]], out: &mut Vec<f64>);
+    /// Accumulate the gradient of each input into `grads_out[k]`,
+    /// which is zero-initialized with the length of `inputs[k]`
+    ///
+    /// `res` is the output of [`forward`](CustomOp::forward) and `grad` its gradient,
+    /// the gradients of the constant inputs are dropped
+    fn backward(&self, inputs: &[&[f64]], res: &[f64], grad: &[f64], grads_out: &mut [Vec<f64>]);
+    /// Shown by [`Debug`] and the [anomaly report](super::AnomalyReport)
+    fn name(&self) -> &str;
+}
+
+/// [`CustomOp`] node of [`Expression::apply_custom`]
+#[derive(Clone)]
+pub struct CustomNary(Arc<dyn CustomOp>);
+
+impl CustomNary {
+    /// Call `f` with the values of `inputs`, a constant is a one-element slice
+    #[inline]
+    fn with_slices<R>(inputs: &[Expression], f: impl FnOnce(&[&[f64]]) -> R) -> R {
+        let consts: Vec<[f64; 1]> = inputs
+            .iter()
+            .map(|input| match input {
+                Expression::Const(x) => [*x],
+                Expression::Tensor(_) => [0.0],
+            })
+            .collect();
+        let guards: Vec<Option<RwLockReadGuard<'_, Vec<f64>>>> = inputs
+            .iter()
+            .map(|input| match input {
+                Expression::Const(_) => None,
+                Expression::Tensor(tensor) => Some(tensor.read()),
+            })
+            .collect();
+        let slices: Vec<&[f64]> = izip!(&consts, &guards)
+            .map(|(x, guard)| match guard {
+                Some(values) => values.as_slice(),
+                None => x.as_slice(),
+            })
+            .collect();
+        f(&slices)
+    }
+    #[inline]
+    pub(super) fn forward(&self, inputs: &[Expression]) -> Vec<f64> {
+        let mut out = Vec::new();
+        Self::with_slices(inputs, |slices| self.0.forward(slices, &mut out));
+        out
+    }
+    /// The gradients of all the inputs, see [`CustomOp::backward`]
+    #[inline]
+    pub(super) fn backward(
+        &self,
+        inputs: &[Expression],
+        res: &[f64],
+        grad: &[f64],
+    ) -> Vec<Vec<f64>> {
+        let mut grads_out: Vec<Vec<f64>> = inputs
+            .iter()
+            .map(|input| match input {
+                Expression::Const(_) => vec![0.0],
+                Expression::Tensor(tensor) => vec![0.0; tensor.read().len()],
+            })
+            .collect();
+        Self::with_slices(inputs, |slices| {
+            self.0.backward(slices, res, grad, &mut grads_out);
+        });
+        grads_out
+    }
+    #[inline]
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+}
+
+// Unwind safe like `CustomUnary`
+impl UnwindSafe for CustomNary {}
+impl RefUnwindSafe for CustomNary {}
+
+/// Shows the provided name
+impl fmt::Debug for CustomNary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+impl fmt::Display for CustomNary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Expression {
+    /// Apply the user op to the `inputs`, see [`CustomOp`]
+    ///
+    /// The node is recomputed when any input changes,
+    /// and has a gradient when any input has
+    #[inline]
+    pub fn apply_custom(op: Arc<dyn CustomOp>, inputs: &[Self]) -> Self {
+        let custom = CustomNary(op);
+        let with_grad = inputs
+            .iter()
+            .any(|input| matches!(input, Self::Tensor(tensor) if tensor.with_grad()));
+        Self::Tensor(Tensor::new(
+            with_grad,
+            custom.forward(inputs),
+            Op::CustomNary(inputs.to_vec(), custom),
+        ))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////   UnaryParamOp   /////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Unary op with a scalar parameter
+#[derive(Clone, Copy, Debug)]
+pub enum UnaryParamOp {
+    /// `sqrt(x² + ε²)`
+    AbsSmooth,
+    /// `ln(max(x, min_arg))`
+    LogClamped,
+    /// `sqrt(max(x, min_arg))`
+    SqrtClamped,
+    /// `max(20 log10|x|, floor_db)`
+    Db20,
+    /// `max(10 log10|x|, floor_db)`
+    Db10,
+    /// `10^(x / divisor)`, the inverse of the dB with the divisor `20` or `10`
+    FromDb,
+}
+
+trait UnaryParamOpT {
+    const OP: UnaryParamOp;
+    fn forward(x: f64, param: f64) -> f64;
+    fn backward(x: &f64, param: f64, res: &f64, grad: &f64, sum_grad: &mut f64);
+}
+
+struct AbsSmooth;
+impl UnaryParamOpT for AbsSmooth {
+    const OP: UnaryParamOp = UnaryParamOp::AbsSmooth;
+    /// `sqrt(x² + ε²)`
+    #[inline]
+    fn forward(x: f64, epsilon: f64) -> f64 {
+        x.hypot(epsilon)
+    }
+    /// $\frac{\partial f}{\partial x} = \frac{x}{\sqrt{x^2 + \epsilon^2}}$
+    #[inline]
+    fn backward(x: &f64, _epsilon: f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
+        if !res.is_zero() {
+            *sum_grad += grad * x / res;
+        }
+    }
+}
+
+struct LogClamped;
+impl UnaryParamOpT for LogClamped {
+    const OP: UnaryParamOp = UnaryParamOp::LogClamped;
+    #[inline]
+    fn forward(x: f64, min_arg: f64) -> f64 {
+        x.max(min_arg).ln()
+    }
+    /// `1/x` when `x > min_arg`, otherwise `0`
+    #[inline]
+    fn backward(x: &f64, min_arg: f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
+        if *x > min_arg {
+            *sum_grad += grad / x;
+        }
+    }
+}
+
+struct SqrtClamped;
+impl UnaryParamOpT for SqrtClamped {
+    const OP: UnaryParamOp = UnaryParamOp::SqrtClamped;
+    #[inline]
+    fn forward(x: f64, min_arg: f64) -> f64 {
+        x.max(min_arg).sqrt()
+    }
+    /// `1/(2 sqrt(x))` when `x > min_arg`, otherwise `0`
+    #[inline]
+    fn backward(x: &f64, min_arg: f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
+        if *x > min_arg {
+            *sum_grad += grad * 0.5 / res;
+        }
+    }
+}
+
+/// Default floor of [`Expression::db20`] and [`Expression::db10`], in dB
+pub const DB_FLOOR: f64 = -400.0;
+
+struct Db20;
+impl UnaryParamOpT for Db20 {
+    const OP: UnaryParamOp = UnaryParamOp::Db20;
+    #[inline]
+    fn forward(x: f64, floor_db: f64) -> f64 {
+        (20.0 * x.abs().log10()).max(floor_db)
+    }
+    /// $\frac{\partial f}{\partial x} = \frac{20}{\ln 10 \cdot x}$ above the floor, otherwise `0`
+    #[inline]
+    fn backward(x: &f64, floor_db: f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
+        if *res > floor_db {
+            *sum_grad += grad * 20.0 / (std::f64::consts::LN_10 * x);
+        }
+    }
+}
+
+struct Db10;
+impl UnaryParamOpT for Db10 {
+    const OP: UnaryParamOp = UnaryParamOp::Db10;
+    #[inline]
+    fn forward(x: f64, floor_db: f64) -> f64 {
+        (10.0 * x.abs().log10()).max(floor_db)
+    }
+    /// $\frac{\partial f}{\partial x} = \frac{10}{\ln 10 \cdot x}$ above the floor, otherwise `0`
+    #[inline]
+    fn backward(x: &f64, floor_db: f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
+        if *res > floor_db {
+            *sum_grad += grad * 10.0 / (std::f64::consts::LN_10 * x);
+        }
+    }
+}
+
+struct FromDb;
+impl UnaryParamOpT for FromDb {
+    const OP: UnaryParamOp = UnaryParamOp::FromDb;
+    #[inline]
+    fn forward(x: f64, divisor: f64) -> f64 {
+        10.0_f64.powf(x / divisor)
+    }
+    /// $\frac{\partial f}{\partial x} = \frac{\ln 10}{d} \cdot f$
+    #[inline]
+    fn backward(_x: &f64, divisor: f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
+        *sum_grad += grad * std::f64::consts::LN_10 / divisor * res;
+    }
+}
+
+impl UnaryParamOp {
+    #[inline]
+    pub(super) const fn forward(&self) -> fn(f64, f64) -> f64 {
+        match self {
+            Self::AbsSmooth => AbsSmooth::forward,
+            Self::LogClamped => LogClamped::forward,
+            Self::SqrtClamped => SqrtClamped::forward,
+            Self::Db20 => Db20::forward,
+            Self::Db10 => Db10::forward,
+            Self::FromDb => FromDb::forward,
+        }
+    }
+    #[inline]
+    pub(super) const fn backward(&self) -> fn(&f64, f64, &f64, &f64, &mut f64) {
+        match self {
+            Self::AbsSmooth => AbsSmooth::backward,
+            Self::LogClamped => LogClamped::backward,
+            Self::SqrtClamped => SqrtClamped::backward,
+            Self::Db20 => Db20::backward,
+            Self::Db10 => Db10::backward,
+            Self::FromDb => FromDb::backward,
+        }
+    }
+}
+
+impl Expression {
+    /// Smoothed absolute value `sqrt(x² + ε²)`, converges to [`abs`](Expression::abs) as `ε → 0`
+    ///
+    /// ``` text
+    /// \            /
+    ///   \        /
+    ///     \____/     ε
+    /// --------------->
+    ///        0       x
+    /// ```
+    #[inline]
+    pub fn abs_smooth(&self, epsilon: f64) -> Self {
+        assert!(epsilon.is_sign_positive());
+        self.unary_param_op::<AbsSmooth>(epsilon)
+    }
+    /// `ln(max(x, min_arg))`, `min_arg > 0`
+    ///
+    /// The gradient is `1/x` when `x > min_arg`,
+    /// and `0` in the clamped region `x <= min_arg`
+    #[inline]
+    pub fn log_clamped(&self, min_arg: f64) -> Self {
+        assert!(min_arg > 0.0);
+        self.unary_param_op::<LogClamped>(min_arg)
+    }
+    /// `sqrt(max(x, min_arg))`, `min_arg >= 0`
+    ///
+    /// The gradient is `1/(2 sqrt(x))` when `x > min_arg`,
+    /// and `0` in the clamped region `x <= min_arg`
+    #[inline]
+    pub fn sqrt_clamped(&self, min_arg: f64) -> Self {
+        assert!(min_arg >= 0.0);
+        self.unary_param_op::<SqrtClamped>(min_arg)
+    }
+    /// Amplitude in dB, `20 log10|x|` floored at [`DB_FLOOR`], see [`Expression::db20_floor`]
+    #[inline]
+    pub fn db20(&self) -> Self {
+        self.db20_floor(DB_FLOOR)
+    }
+    /// `max(20 log10|x|, floor_db)` as one node
+    ///
+    /// The gradient is `20/(ln10 x)` above the floor, whose sign follows `x`,
+    /// and `0` at the floor, so `x = 0` gives `floor_db` instead of `-inf`
+    #[inline]
+    pub fn db20_floor(&self, floor_db: f64) -> Self {
+        self.unary_param_op::<Db20>(floor_db)
+    }
+    /// Power in dB, `10 log10|x|` floored at [`DB_FLOOR`], see [`Expression::db10_floor`]
+    #[inline]
+    pub fn db10(&self) -> Self {
+        self.db10_floor(DB_FLOOR)
+    }
+    /// `max(10 log10|x|, floor_db)` as one node
+    ///
+    /// The gradient is `10/(ln10 x)` above the floor, and `0` at the floor
+    #[inline]
+    pub fn db10_floor(&self, floor_db: f64) -> Self {
+        self.unary_param_op::<Db10>(floor_db)
+    }
+    /// `10^(x/20)`, the amplitude of the dB, so `from_db20(db20(x)) = |x|` above the floor
+    #[inline]
+    pub fn from_db20(&self) -> Self {
+        self.unary_param_op::<FromDb>(20.0)
+    }
+    /// `10^(x/10)`, the power of the dB
+    #[inline]
+    pub fn from_db10(&self) -> Self {
+        self.unary_param_op::<FromDb>(10.0)
+    }
+    /// `x * factor`, an engineering-unit scaling like `1e-3` for milli,
+    /// as one scalar multiplication
+    #[inline]
+    pub fn scale_unit(&self, factor: f64) -> Self {
+        self.mul(&Self::Const(factor))
+    }
+    #[inline]
+    fn unary_param_op<T: UnaryParamOpT>(&self, param: f64) -> Self {
+        match self {
+            Self::Const(x) => Self::Const(T::forward(*x, param)),
+            Self::Tensor(tensor) => Self::Tensor(tensor.broadcast_binary_op(
+                param,
+                T::forward,
+                Op::UnaryParam(Self::Tensor(tensor.clone()), param, T::OP),
+            )),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Cond   ///////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Forward method of [`Op::Cond`], the backward is always the smoothing one
+#[derive(Clone, Copy, Debug)]
+pub enum CondMethod {
+    /// `cond*on_true + (1-cond)*on_false`
+    Smooth,
+    /// Straight-through, `(cond >= 0.5)? on_true : on_false`
+    Ste,
+}
+
+impl CondMethod {
+    #[inline]
+    pub(super) const fn forward(&self) -> fn(&f64, f64, f64) -> f64 {
+        match self {
+            Self::Smooth => Cond::forward,
+            Self::Ste => Cond::forward_ste,
+        }
+    }
+}
+
+pub(super) struct Cond;
+impl Cond {
+    /// `(cond)? on_true : on_false`
+    ///
+    /// smoothing method:
+    /// `cond*on_true + (1-cond)*on_false`
+    #[inline]
+    pub(super) fn forward(cond: &f64, on_true: f64, on_false: f64) -> f64 {
+        assert_logic!(*cond);
+        cond * on_true + (1.0 - cond) * on_false
+    }
+    /// `(cond >= 0.5)? on_true : on_false`
+    #[inline]
+    pub(super) fn forward_ste(cond: &f64, on_true: f64, on_false: f64) -> f64 {
+        assert_logic!(*cond);
+        if *cond >= 0.5 {
+            on_true
+        } else {
+            on_false
+        }
+    }
+    /// $\frac{\partial L}{\partial a} = \frac{\partial L}{\partial e} \cdot \frac{\partial e}{\partial a} = \text{grad\_output} \times (b - c)$
+    #[inline]
+    pub(super) fn backward_cond(
+        _cond: &f64,
+        on_true: &f64,
+        on_false: &f64,
+        grad: &f64,
+        cond_grad: &mut f64,
+    ) {
+        *cond_grad += grad * (on_true - on_false);
+    }
+    #[inline]
+    pub(super) fn backward_on_true(
+        cond: &f64,
+        _on_true: &f64,
+        _on_false: &f64,
+        grad: &f64,
+        on_true_grad: &mut f64,
+    ) {
+        *on_true_grad += cond * grad;
+    }
+    #[inline]
+    pub(super) fn backward_on_false(
+        cond: &f64,
+        _on_true: &f64,
+        _on_false: &f64,
+        grad: &f64,
+        on_false_grad: &mut f64,
+    ) {
+        *on_false_grad += (1.0 - cond) * grad;
+    }
+    /// The broadcast length of `[cond, on_true, on_false]`, `None` for a constant
+    ///
+    /// The [`Error::LengthMismatch`] names the three lengths
+    pub(super) fn len(lens: [Option<usize>; 3]) -> Result<usize, Error> {
+        broadcast_len("Cond", lens.into_iter().flatten()).map_err(|e| match e {
+            Error::LengthMismatch { expected, got, .. } => {
+                let [cond, on_true, on_false] =
+                    lens.map(|len| len.map_or_else(|| "scalar".to_owned(), |len| len.to_string()));
+                Error::LengthMismatch {
+                    expected,
+                    got,
+                    op: format!("Cond (cond {cond}, on_true {on_true}, on_false {on_false})"),
+                }
+            }
+            e => e,
+        })
+    }
+    /// The length of the expression, `None` for a constant
+    #[inline]
+    fn expr_len(expr: &Expression) -> Option<usize> {
+        match expr {
+            Expression::Const(_) => None,
+            Expression::Tensor(tensor) => Some(tensor.values_len()),
+        }
+    }
+    /// The constant `cond_x` blending the branches, at least one of which is a tensor,
+    /// always by [`forward`](Cond::forward)
+    #[inline]
+    pub(super) fn iter_x(cond_x: f64, on_true: &Expression, on_false: &Expression) -> Vec<f64> {
+        let len = Self::len([None, Self::expr_len(on_true), Self::expr_len(on_false)])
+            .unwrap_or_else(|e| panic!("{e}"));
+        let inputs = Inputs::read([on_true, on_false].into_iter());
+        (0..len)
+            .map(|i| Self::forward(&cond_x, inputs.get(0, i), inputs.get(1, i)))
+            .collect()
+    }
+    #[inline]
+    pub(super) fn iter_tensor_x_x(
+        cond_tensor: &Tensor,
+        on_true_x: f64,
+        on_false_x: f64,
+        forward: fn(&f64, f64, f64) -> f64,
+    ) -> Vec<f64> {
+        cond_tensor
+            .read()
+            .iter()
+            .map(|cond_x| forward(cond_x, on_true_x, on_false_x))
+            .collect()
+    }
+    #[inline]
+    pub(super) fn iter_tensor_x_tensor(
+        cond_tensor: &Tensor,
+        on_true_x: f64,
+        on_false_tensor: &Tensor,
+        forward: fn(&f64, f64, f64) -> f64,
+    ) -> Vec<f64> {
+        let (cond_vec, on_false_vec) = (cond_tensor.read(), on_false_tensor.read());
+        let len = Self::len([Some(cond_vec.len()), None, Some(on_false_vec.len())])
+            .unwrap_or_else(|e| panic!("{e}"));
+        izip!(broadcast(&cond_vec, len), broadcast(&on_false_vec, len))
+            .map(|(cond_x, on_false_x)| forward(cond_x, on_true_x, *on_false_x))
+            .collect()
+    }
+    #[inline]
+    pub(super) fn iter_tensor_tensor_x(
+        cond_tensor: &Tensor,
+        on_true_tensor: &Tensor,
+        on_false_x: f64,
+        forward: fn(&f64, f64, f64) -> f64,
+    ) -> Vec<f64> {
+        let (cond_vec, on_true_vec) = (cond_tensor.read(), on_true_tensor.read());
+        let len = Self::len([Some(cond_vec.len()), Some(on_true_vec.len()), None])
+            .unwrap_or_else(|e| panic!("{e}"));
+        izip!(broadcast(&cond_vec, len), broadcast(&on_true_vec, len))
+            .map(|(cond_x, on_true_x)| forward(cond_x, *on_true_x, on_false_x))
+            .collect()
+    }
+    #[inline]
+    pub(super) fn iter_tensor_tensor_tensor(
+        cond_tensor: &Tensor,
+        on_true_tensor: &Tensor,
+        on_false_tensor: &Tensor,
+        forward: fn(&f64, f64, f64) -> f64,
+    ) -> Vec<f64> {
+        let (cond_vec, on_true_vec, on_false_vec) = (
+            cond_tensor.read(),
+            on_true_tensor.read(),
+            on_false_tensor.read(),
+        );
+        let len = Self::len([
+            Some(cond_vec.len()),
+            Some(on_true_vec.len()),
+            Some(on_false_vec.len()),
+        ])
+        .unwrap_or_else(|e| panic!("{e}"));
+        izip!(
+            broadcast(&cond_vec, len),
+            broadcast(&on_true_vec, len),
+            broadcast(&on_false_vec, len)
+        )
+        .map(|(cond_x, on_true_x, on_false_x)| forward(cond_x, *on_true_x, *on_false_x))
+        .collect()
+    }
+}
+
+impl Expression {
+    /// smoothing method
+    /// `cond*on_true + (1-cond)*on_false`
+    ///
+    /// A constant `cond` of `0` or `1` is the branch itself, another one blends
+    /// the branches the same way, with their gradients scaled by `cond` and `1-cond`
+    ///
+    /// The gradient of `cond` is `on_true - on_false` even at the bounds,
+    /// so a raw parameter as `cond` can be optimized out of `[0, 1]`,
+    /// see [`cond_guarded`](Expression::cond_guarded)
+    #[inline]
+    pub fn cond(&self, on_true: &Self, on_false: &Self) -> Self {
+        self.cond_op(on_true, on_false, CondMethod::Smooth)
+    }
+    /// [`cond`](Expression::cond) of `cond` [saturated](Expression::saturate01) to `[0, 1]`,
+    /// for a `cond` not produced by the logic ops, e.g., a raw parameter
+    ///
+    /// The gradient of `cond` passes through inside `[0, 1]` and is zero outside,
+    /// so the effective condition never leaves the interval
+    #[inline]
+    pub fn cond_guarded(&self, on_true: &Self, on_false: &Self) -> Self {
+        self.saturate01().cond(on_true, on_false)
+    }
+    /// Straight-through conditional,
+    /// the forward picks the branch by `cond >= 0.5`,
+    /// and the backward is the same as [`cond`](Expression::cond)
+    ///
+    /// Constant `cond` behaves the same as [`cond`](Expression::cond), blending the branches
+    #[inline]
+    pub fn cond_ste(&self, on_true: &Self, on_false: &Self) -> Self {
+        self.cond_op(on_true, on_false, CondMethod::Ste)
+    }
+    /// Fallible [`cond`](Expression::cond)
+    ///
+    /// The inputs broadcast as the binary ops, the tensors have the same length
+    /// or are length-1, and a constant is repeated. [`Error::LengthMismatch`] names the lengths
+    /// of the three inputs otherwise, unless `cond` is the constant `0` or `1`.
+    /// A later mismatch after a [`TensorRef::assign`](super::TensorRef::assign)
+    /// panics with the same error at the recompute
+    #[inline]
+    pub fn try_cond(&self, on_true: &Self, on_false: &Self) -> Result<Self, Error> {
+        self.cond_len(on_true, on_false)?;
+        Ok(self.cond(on_true, on_false))
+    }
+    /// Fallible [`cond_ste`](Expression::cond_ste), see [`try_cond`](Expression::try_cond)
+    #[inline]
+    pub fn try_cond_ste(&self, on_true: &Self, on_false: &Self) -> Result<Self, Error> {
+        self.cond_len(on_true, on_false)?;
+        Ok(self.cond_ste(on_true, on_false))
+    }
+    /// Check the broadcast length of the tensors, see [`Cond::len`]
+    #[inline]
+    fn cond_len(&self, on_true: &Self, on_false: &Self) -> Result<(), Error> {
+        // the constant `cond` of `0` or `1` picks a branch without broadcasting
+        if !matches!(self, Self::Const(x) if x.is_zero() || x.is_one()) {
+            Cond::len([self, on_true, on_false].map(Cond::expr_len))?;
+        }
+        Ok(())
+    }
+    #[inline]
+    fn cond_op(&self, on_true: &Self, on_false: &Self, method: CondMethod) -> Self {
+        #[cfg(debug_assertions)]
+        if let Self::Tensor(cond_tensor) = self {
+            assert_logic_tensor!(cond_tensor);
+        }
+        if let Err(e) = self.cond_len(on_true, on_false) {
+            panic!("{e}");
+        }
+        let picks_branch = matches!(self, Self::Const(x) if x.is_zero() || x.is_one());
+        let all_const = [self, on_true, on_false]
+            .iter()
+            .all(|expr| matches!(expr, Self::Const(_)));
+        if is_lazy_build() && !picks_branch && !all_const {
+            let len = Cond::len([self, on_true, on_false].map(Cond::expr_len))
+                .unwrap_or_else(|e| panic!("{e}"));
+            // a constant `cond` blends the branches, whatever the method
+            let method = if matches!(self, Self::Const(_)) {
+                CondMethod::Smooth
+            } else {
+                method
+            };
+            return Self::new_lazy(
+                len,
+                Op::Cond(self.clone(), on_true.clone(), on_false.clone(), method),
+            );
+        }
+        match (self, on_true, on_false) {
+            (Self::Const(cond_x), Self::Const(on_true_x), Self::Const(on_false_x)) => {
+                Self::Const(Cond::forward(cond_x, *on_true_x, *on_false_x))
+            }
+            (Self::Const(cond_x), _, _) if cond_x.is_zero() => on_false.clone(),
+            (Self::Const(cond_x), _, _) if cond_x.is_one() => on_true.clone(),
+            // the same blend as a tensor `cond`, whatever the method
+            (Self::Const(cond_x), _, _) => {
+                assert_logic!(*cond_x);
+                let with_grad = [on_true, on_false]
+                    .into_iter()
+                    .any(|expr| matches!(expr, Self::Tensor(tensor) if tensor.with_grad()));
+                Self::Tensor(Tensor::new(
+                    with_grad,
+                    Cond::iter_x(*cond_x, on_true, on_false),
+                    Op::Cond(
+                        self.clone(),
+                        on_true.clone(),
+                        on_false.clone(),
+                        CondMethod::Smooth,
+                    ),
+                ))
+            }
+            (Self::Tensor(cond_tensor), Self::Const(on_true_x), Self::Const(on_false_x)) => {
+                Self::Tensor(Tensor::new(
+                    cond_tensor.with_grad(),
+                    Cond::iter_tensor_x_x(cond_tensor, *on_true_x, *on_false_x, method.forward()),
+                    Op::Cond(
+                        Self::Tensor(cond_tensor.clone()),
+                        Self::Const(*on_true_x),
+                        Self::Const(*on_false_x),
+                        method,
+                    ),
+                ))
+            }
+            (Self::Tensor(cond_tensor), Self::Const(on_true_x), Self::Tensor(on_false_tensor)) => {
+                Self::Tensor(Tensor::new(
+                    cond_tensor.with_grad() || on_false_tensor.with_grad(),
+                    Cond::iter_tensor_x_tensor(
+                        cond_tensor,
+                        *on_true_x,
+                        on_false_tensor,
+                        method.forward(),
+                    ),
+                    Op::Cond(
+                        Self::Tensor(cond_tensor.clone()),
+                        Self::Const(*on_true_x),
+                        Self::Tensor(on_false_tensor.clone()),
+                        method,
+                    ),
+                ))
+            }
+            (Self::Tensor(cond_tensor), Self::Tensor(on_true_tensor), Self::Const(on_false_x)) => {
+                Self::Tensor(Tensor::new(
+                    cond_tensor.with_grad() || on_true_tensor.with_grad(),
+                    Cond::iter_tensor_tensor_x(
+                        cond_tensor,
+                        on_true_tensor,
+                        *on_false_x,
+                        method.forward(),
+                    ),
+                    Op::Cond(
+                        Self::Tensor(cond_tensor.clone()),
+                        Self::Tensor(on_true_tensor.clone()),
+                        Self::Const(*on_false_x),
+                        method,
+                    ),
+                ))
+            }
+            (
+                Self::Tensor(cond_tensor),
+                Self::Tensor(on_true_tensor),
+                Self::Tensor(on_false_tensor),
+            ) => Self::Tensor(Tensor::new(
+                cond_tensor.with_grad()
+                    || on_true_tensor.with_grad()
+                    || on_false_tensor.with_grad(),
+                Cond::iter_tensor_tensor_tensor(
+                    cond_tensor,
+                    on_true_tensor,
+                    on_false_tensor,
+                    method.forward(),
+                ),
+                Op::Cond(
+                    Self::Tensor(cond_tensor.clone()),
+                    Self::Tensor(on_true_tensor.clone()),
+                    Self::Tensor(on_false_tensor.clone()),
+                    method,
+                ),
+            )),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Select   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Read-locked inputs of a multi-input op, the constants and length-1 tensors are broadcast
+pub(super) struct Inputs<'a>(Vec<Input<'a>>);
+
+enum Input<'a> {
+    Const(f64),
+    Tensor(RwLockReadGuard<'a, Vec<f64>>),
+}
+
+impl<'a> Inputs<'a> {
+    #[inline]
+    pub(super) fn read(exprs: impl Iterator<Item = &'a Expression>) -> Self {
+        Self(
+            exprs
+                .map(|expr| match expr {
+                    Expression::Const(x) => Input::Const(*x),
+                    Expression::Tensor(tensor) => Input::Tensor(tensor.read()),
+                })
+                .collect(),
+        )
+    }
+    #[inline]
+    pub(super) fn get(&self, input: usize, i: usize) -> f64 {
+        match &self.0[input] {
+            Input::Const(x) => *x,
+            Input::Tensor(values) if values.len() == 1 => values[0],
+            Input::Tensor(values) => values[i],
+        }
+    }
+}
+
+pub(super) struct Select;
+impl Select {
+    /// The inputs are `[conds.., values.., default]`
+    #[inline]
+    pub(super) fn forward(method: CondMethod, inputs: &Inputs, n: usize, i: usize) -> f64 {
+        match method {
+            CondMethod::Ste => {
+                for k in 0..n {
+                    let cond = inputs.get(k, i);
+                    assert_logic!(cond);
+                    if cond >= 0.5 {
+                        return inputs.get(n + k, i);
+                    }
+                }
+                inputs.get(2 * n, i)
+            }
+            CondMethod::Smooth => (0..n).rev().fold(inputs.get(2 * n, i), |res, k| {
+                Cond::forward(&inputs.get(k, i), inputs.get(n + k, i), res)
+            }),
+        }
+    }
+    /// The nested smoothing method
+    ///
+    /// the branch `k` has weight `w_k = c_k ∏_{j<k}(1-c_j)`, the default has `∏_j(1-c_j)`,
+    /// and the gradient of `c_k` is `∏_{j<k}(1-c_j) (v_k - r_{k+1})`,
+    /// where `r_{k+1}` is the blend of the following branches
+    ///
+    /// [`CondMethod::Ste`] evaluates it at the hard conds `c_k >= 0.5`, i.e., the values
+    /// get the gradient of the chosen branch only, and the conds the straight-through one
+    #[inline]
+    pub(super) fn backward(
+        method: CondMethod,
+        inputs: &Inputs,
+        n: usize,
+        i: usize,
+        grad: &f64,
+        tails: &mut [f64],
+        mut sum_grad: impl FnMut(usize, f64),
+    ) {
+        let cond = |k: usize| {
+            let cond = inputs.get(k, i);
+            match method {
+                CondMethod::Smooth => cond,
+                CondMethod::Ste if cond >= 0.5 => 1.0,
+                CondMethod::Ste => 0.0,
+            }
+        };
+        tails[n] = inputs.get(2 * n, i);
+        for k in (0..n).rev() {
+            tails[k] = Cond::forward(&cond(k), inputs.get(n + k, i), tails[k + 1]);
+        }
+        let mut prefix = 1.0;
+        for k in 0..n {
+            let cond = cond(k);
+            sum_grad(k, grad * prefix * (inputs.get(n + k, i) - tails[k + 1]));
+            sum_grad(n + k, grad * prefix * cond);
+            prefix *= 1.0 - cond;
+        }
+        sum_grad(2 * n, grad * prefix);
+    }
+    #[inline]
+    pub(super) fn iter(
+        method: CondMethod,
+        conds: &[Expression],
+        values: &[Expression],
+        default: &Expression,
+        len: usize,
+    ) -> Vec<f64> {
+        let inputs = Inputs::read(conds.iter().chain(values).chain([default]));
+        (0..len)
+            .map(|i| Self::forward(method, &inputs, conds.len(), i))
+            .collect()
+    }
+}
+
+impl Expression {
+    /// Multi-way select, the first `conds[k] >= 0.5` picks `values[k]`, otherwise `default`
+    ///
+    /// The backward is the straight-through one: the chosen value gets the gradient,
+    /// and the conds get the gradient of the nested [`cond`](Expression::cond) at the hard conds
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_select`](Expression::try_select)
+    #[inline]
+    pub fn select(conds: &[Self], values: &[Self], default: &Self) -> Self {
+        Self::try_select(conds, values, default).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Same as nested [`cond`](Expression::cond), but in a single node
+    ///
+    /// `c_0 v_0 + (1-c_0) (c_1 v_1 + (1-c_1) (... + (1-c_{n-1}) default))`
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_select`](Expression::try_select)
+    #[inline]
+    pub fn select_smooth(conds: &[Self], values: &[Self], default: &Self) -> Self {
+        Self::select_op(conds, values, default, CondMethod::Smooth)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`select`](Expression::select)
+    ///
+    /// [`Error::LengthMismatch`] when the counts of `conds` and `values` differ,
+    /// or the tensors have different lengths and are not length-1
+    #[inline]
+    pub fn try_select(conds: &[Self], values: &[Self], default: &Self) -> Result<Self, Error> {
+        Self::select_op(conds, values, default, CondMethod::Ste)
+    }
+    #[inline]
+    fn select_op(
+        conds: &[Self],
+        values: &[Self],
+        default: &Self,
+        method: CondMethod,
+    ) -> Result<Self, Error> {
+        if conds.len() != values.len() {
+            return Err(Error::LengthMismatch {
+                expected: conds.len(),
+                got: values.len(),
+                op: "Select".to_owned(),
+            });
+        }
+        #[cfg(debug_assertions)]
+        for cond in conds {
+            if let Self::Tensor(cond_tensor) = cond {
+                assert_logic_tensor!(cond_tensor);
+            }
+        }
+        let mut lens = Vec::new();
+        let mut with_grad = false;
+        for expr in conds.iter().chain(values).chain([default]) {
+            if let Self::Tensor(tensor) = expr {
+                lens.push(tensor.read().len());
+                with_grad |= tensor.with_grad();
+            }
+        }
+        let len = broadcast_len("Select", lens.iter().copied())?;
+        Ok(if lens.is_empty() {
+            Self::Const(Select::iter(method, conds, values, default, 1)[0])
+        } else {
+            Self::Tensor(Tensor::new(
+                with_grad,
+                Select::iter(method, conds, values, default, len),
+                Op::Select(conds.to_vec(), values.to_vec(), default.clone(), method),
+            ))
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////   LogicNary   ////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Logic op of many terms in a single node, see [`Expression::logic_or_many`]
+///
+/// The product is accumulated as a sum of logarithms, instead of folding the binary ops
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LogicNary {
+    /// `1 - ∏(1 - x_i)`
+    Or,
+    /// `∏ x_i`
+    And,
+}
+
+impl LogicNary {
+    /// Logarithm of the factor of `x`, `-∞` for the absorbing `x`,
+    /// i.e., `1` of [`Or`](LogicNary::Or) and `0` of [`And`](LogicNary::And)
+    #[inline]
+    fn ln_factor(self, x: f64) -> f64 {
+        assert_logic!(x);
+        match self {
+            Self::Or => (-x).ln_1p(),
+            Self::And => x.ln(),
+        }
+    }
+    /// `0` and `1` without terms
+    #[inline]
+    fn of_ln_product(self, ln_product: f64) -> f64 {
+        match self {
+            Self::Or => -ln_product.exp_m1(),
+            Self::And => ln_product.exp(),
+        }
+    }
+    #[inline]
+    pub(super) fn forward(self, inputs: &Inputs, n: usize, i: usize) -> f64 {
+        let ln_product = reduction::sum_iter((0..n).map(|k| self.ln_factor(inputs.get(k, i))));
+        self.of_ln_product(ln_product)
+    }
+    /// The gradient of `x_k` is the product of the other factors for both ops,
+    /// an absorbing `x_k` gets the product of the rest when it is the only one
+    #[inline]
+    pub(super) fn backward(
+        self,
+        inputs: &Inputs,
+        i: usize,
+        grad: &f64,
+        ln_factors: &mut [f64],
+        mut sum_grad: impl FnMut(usize, f64),
+    ) {
+        let mut absorbing = 0;
+        for (k, ln_factor) in ln_factors.iter_mut().enumerate() {
+            *ln_factor = self.ln_factor(inputs.get(k, i));
+            if *ln_factor == f64::NEG_INFINITY {
+                absorbing += 1;
+            }
+        }
+        let ln_finite = reduction::sum_iter(
+            ln_factors
+                .iter()
+                .copied()
+                .filter(|ln_factor| *ln_factor != f64::NEG_INFINITY),
+        );
+        for (k, ln_factor) in ln_factors.iter().enumerate() {
+            let others = match (absorbing, *ln_factor == f64::NEG_INFINITY) {
+                (0, _) => (ln_finite - ln_factor).exp(),
+                (1, true) => ln_finite.exp(),
+                _ => 0.0,
+            };
+            sum_grad(k, grad * others);
+        }
+    }
+    #[inline]
+    pub(super) fn iter(self, terms: &[Expression], len: usize) -> Vec<f64> {
+        let inputs = Inputs::read(terms.iter());
+        (0..len)
+            .map(|i| self.forward(&inputs, terms.len(), i))
+            .collect()
+    }
+}
+
+impl Expression {
+    /// `1 - ∏(1 - x_i)` of the logic terms, the same as folding [`logic_or`](Expression::logic_or)
+    /// but in a single node, accumulated as `∑ ln(1 - x_i)` for the precision of many terms,
+    /// `0` without terms
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_logic_or_many`](Expression::try_logic_or_many)
+    #[inline]
+    pub fn logic_or_many(terms: &[Self]) -> Self {
+        Self::try_logic_or_many(terms).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// `∏ x_i` of the logic terms, the same as folding [`logic_and`](Expression::logic_and)
+    /// but in a single node, accumulated as `∑ ln(x_i)`, `1` without terms
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_logic_and_many`](Expression::try_logic_and_many)
+    #[inline]
+    pub fn logic_and_many(terms: &[Self]) -> Self {
+        Self::try_logic_and_many(terms).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`logic_or_many`](Expression::logic_or_many)
+    ///
+    /// [`Error::LengthMismatch`] when the tensors have different lengths and are not length-1
+    #[inline]
+    pub fn try_logic_or_many(terms: &[Self]) -> Result<Self, Error> {
+        Self::logic_nary_op(terms, LogicNary::Or)
+    }
+    /// Fallible [`logic_and_many`](Expression::logic_and_many),
+    /// see [`try_logic_or_many`](Expression::try_logic_or_many)
+    #[inline]
+    pub fn try_logic_and_many(terms: &[Self]) -> Result<Self, Error> {
+        Self::logic_nary_op(terms, LogicNary::And)
+    }
+    #[inline]
+    fn logic_nary_op(terms: &[Self], op: LogicNary) -> Result<Self, Error> {
+        let mut lens = Vec::new();
+        let mut with_grad = false;
+        for term in terms {
+            if let Self::Tensor(tensor) = term {
+                assert_logic_tensor!(tensor);
+                lens.push(tensor.read().len());
+                with_grad |= tensor.with_grad();
+            }
+        }
+        let len = broadcast_len(&format!("Logic{op:?}Many"), lens.iter().copied())?;
+        Ok(if lens.is_empty() {
+            Self::Const(op.iter(terms, 1)[0])
+        } else {
+            Self::Tensor(mark_logic_tensor!(Tensor::new(
+                with_grad,
+                op.iter(terms, len),
+                Op::LogicNary(terms.to_vec(), op),
+            )))
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Concat   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+pub(super) struct Concat;
+impl Concat {
+    /// Concatenate the values of `parts`, a constant part is one element
+    #[inline]
+    pub(super) fn iter(parts: &[Expression]) -> Vec<f64> {
+        let mut values = Vec::new();
+        for part in parts {
+            match part {
+                Expression::Const(x) => values.push(*x),
+                Expression::Tensor(tensor) => values.extend_from_slice(&tensor.read()),
+            }
+        }
+        values
+    }
+}
+
+pub(super) struct Narrow;
+impl Narrow {
+    /// [`Error::IndexOutOfBounds`] when `offset + len` exceeds `tensor_len`
+    #[inline]
+    pub(super) fn check(offset: usize, len: usize, tensor_len: usize) -> Result<(), Error> {
+        match offset.checked_add(len) {
+            Some(end) if end <= tensor_len => Ok(()),
+            _ => Err(Error::IndexOutOfBounds {
+                index: offset.saturating_add(len),
+                len: tensor_len,
+                op: "Narrow".to_owned(),
+            }),
+        }
+    }
+}
+
+impl Expression {
+    /// Concatenate the `parts` into one tensor, a constant part is one element
+    ///
+    /// The gradient is sliced back to each part
+    #[inline]
+    pub fn concat(parts: &[Self]) -> Self {
+        let with_grad = parts
+            .iter()
+            .any(|part| matches!(part, Self::Tensor(tensor) if tensor.with_grad()));
+        Self::Tensor(Tensor::new(
+            with_grad,
+            Concat::iter(parts),
+            Op::Concat(parts.to_vec()),
+        ))
+    }
+    /// The sub-range `[offset, offset + len)`,
+    /// the gradient is scattered back into the same positions
+    ///
+    /// A constant is broadcast to `len` elements
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_narrow`](Expression::try_narrow)
+    #[inline]
+    pub fn narrow(&self, offset: usize, len: usize) -> Self {
+        self.try_narrow(offset, len)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`narrow`](Expression::narrow)
+    ///
+    /// [`Error::IndexOutOfBounds`] when `offset + len` exceeds the tensor's length
+    #[inline]
+    pub fn try_narrow(&self, offset: usize, len: usize) -> Result<Self, Error> {
+        match self {
+            Self::Const(_) => Ok(self.clone()),
+            Self::Tensor(tensor) => {
+                let values = {
+                    let read = tensor.read();
+                    Narrow::check(offset, len, read.len())?;
+                    read[offset..offset + len].to_vec()
+                };
+                Ok(Self::Tensor(Tensor::new(
+                    tensor.with_grad(),
+                    values,
+                    Op::Narrow(self.clone(), offset, len),
+                )))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Gather   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// [`Error::IndexOutOfBounds`] when any index is not less than `len`
+#[inline]
+pub(super) fn check_indices(indices: &[usize], len: usize, op: &str) -> Result<(), Error> {
+    match indices.iter().find(|index| **index >= len) {
+        Some(index) => Err(Error::IndexOutOfBounds {
+            index: *index,
+            len,
+            op: op.to_owned(),
+        }),
+        None => Ok(()),
+    }
+}
+
+pub(super) struct Gather;
+impl Gather {
+    /// `output[i] = input[indices[i]]`
+    #[inline]
+    pub(super) fn iter(input: &[f64], indices: &[usize]) -> Vec<f64> {
+        indices.iter().map(|index| input[*index]).collect()
+    }
+}
+
+pub(super) struct ScatterAdd;
+impl ScatterAdd {
+    /// `output[indices[i]] += input[i]`, a constant input is broadcast
+    #[inline]
+    pub(super) fn iter(input: &Expression, indices: &[usize], len: usize) -> Vec<f64> {
+        let mut values = vec![0.0; len];
+        match input {
+            Expression::Const(x) => indices.iter().for_each(|index| values[*index] += x),
+            Expression::Tensor(tensor) => {
+                izip!(indices, tensor.read().iter()).for_each(|(index, x)| values[*index] += x)
+            }
+        }
+        values
+    }
+}
+
+impl Expression {
+    /// `output[i] = self[indices[i]]`,
+    /// the gradient is scatter-added back, so the repeated indices accumulate
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_gather`](Expression::try_gather)
+    #[inline]
+    pub fn gather(&self, indices: &[usize]) -> Self {
+        self.try_gather(indices).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`gather`](Expression::gather)
+    ///
+    /// [`Error::IndexOutOfBounds`] when any index is not less than the tensor's length
+    #[inline]
+    pub fn try_gather(&self, indices: &[usize]) -> Result<Self, Error> {
+        match self {
+            Self::Const(_) => Ok(self.clone()),
+            Self::Tensor(tensor) => {
+                let values = {
+                    let read = tensor.read();
+                    check_indices(indices, read.len(), "Gather")?;
+                    Gather::iter(&read, indices)
+                };
+                Ok(Self::Tensor(Tensor::new(
+                    tensor.with_grad(),
+                    values,
+                    Op::Gather(self.clone(), indices.to_vec()),
+                )))
+            }
+        }
+    }
+    /// The reverse of [`gather`](Expression::gather), a length-`len` tensor with
+    /// `output[indices[i]] += self[i]`, the gradient is gathered back
+    ///
+    /// A constant is broadcast to every index
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_scatter_add`](Expression::try_scatter_add)
+    #[inline]
+    pub fn scatter_add(&self, indices: &[usize], len: usize) -> Self {
+        self.try_scatter_add(indices, len)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`scatter_add`](Expression::scatter_add)
+    ///
+    /// + [`Error::LengthMismatch`] when `indices` and the tensor have different lengths
+    /// + [`Error::IndexOutOfBounds`] when any index is not less than `len`
+    #[inline]
+    pub fn try_scatter_add(&self, indices: &[usize], len: usize) -> Result<Self, Error> {
+        check_indices(indices, len, "ScatterAdd")?;
+        let with_grad = match self {
+            Self::Const(_) => false,
+            Self::Tensor(tensor) => {
+                let tensor_len = tensor.read().len();
+                if tensor_len != indices.len() {
+                    return Err(Error::LengthMismatch {
+                        expected: indices.len(),
+                        got: tensor_len,
+                        op: "ScatterAdd".to_owned(),
+                    });
+                }
+                tensor.with_grad()
+            }
+        };
+        Ok(Self::Tensor(Tensor::new(
+            with_grad,
+            ScatterAdd::iter(self, indices, len),
+            Op::ScatterAdd(self.clone(), indices.to_vec(), len),
+        )))
+    }
+}
+
+pub(super) struct AssignFrom;
+
+pub(super) struct Reverse;
+impl Reverse {
+    #[inline]
+    pub(super) fn iter(input: &[f64]) -> Vec<f64> {
+        input.iter().rev().copied().collect()
+    }
+}
+
+pub(super) struct Shift;
+impl Shift {
+    /// The source index of `output[i]`, `None` for a filled position
+    #[inline]
+    pub(super) fn source(i: usize, offset: isize, len: usize) -> Option<usize> {
+        // the magnitude, `-offset` overflows at `isize::MIN`
+        let magnitude = offset.unsigned_abs();
+        if offset >= 0 {
+            i.checked_sub(magnitude)
+        } else {
+            i.checked_add(magnitude)
+        }
+        .filter(|j| *j < len)
+    }
+    /// `output[i] = input[i - offset]`, `fill` when out of range
+    #[inline]
+    pub(super) fn iter(input: &[f64], offset: isize, fill: f64) -> Vec<f64> {
+        (0..input.len())
+            .map(|i| Self::source(i, offset, input.len()).map_or(fill, |j| input[j]))
+            .collect()
+    }
+}
+
+impl Expression {
+    /// Pass-through node copying the values of `source`, again on each recompute
+    /// after `source` changes, and passing the gradient to it as-is
+    ///
+    /// Unlike `source` itself, the node is distinct from the other consumers of `source`,
+    /// e.g., a probe point to [find](Expression::find) or [snapshot](Expression::snapshot),
+    /// and it is never merged by [`GraphBuilder`](super::GraphBuilder).
+    /// A constant is returned as-is
+    #[inline]
+    pub fn assign_from(source: &Expression) -> Self {
+        match source {
+            Self::Const(_) => source.clone(),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                tensor.read().clone(),
+                Op::AssignFrom(source.clone()),
+            )),
+        }
+    }
+    /// Reverse the elements, a constant is returned as-is
+    #[inline]
+    pub fn reverse(&self) -> Self {
+        match self {
+            Self::Const(_) => self.clone(),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                Reverse::iter(&tensor.read()),
+                Op::Reverse(self.clone()),
+            )),
+        }
+    }
+    /// Move the elements by `offset`, `output[i] = self[i - offset]`,
+    /// the vacated positions are filled with `fill`
+    ///
+    /// A positive `offset` delays the waveform. The gradient is shifted back,
+    /// and dropped for the elements shifted out. A constant is returned as-is
+    #[inline]
+    pub fn shift(&self, offset: isize, fill: f64) -> Self {
+        match self {
+            Self::Const(_) => self.clone(),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                Shift::iter(&tensor.read(), offset, fill),
+                Op::Shift(self.clone(), offset, fill),
+            )),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////   Ddt / Idt   ///////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+pub(super) struct Ddt;
+impl Ddt {
+    /// Central differences inside, one-sided at the ends, zero for a single sample
+    #[inline]
+    pub(super) fn iter(input: &[f64], dt: f64) -> Vec<f64> {
+        let n = input.len();
+        (0..n)
+            .map(|i| match (i.checked_sub(1), (i + 1 < n).then_some(i + 1)) {
+                (Some(prev), Some(next)) => (input[next] - input[prev]) / (2.0 * dt),
+                (None, Some(next)) => (input[next] - input[i]) / dt,
+                (Some(prev), None) => (input[i] - input[prev]) / dt,
+                (None, None) => 0.0,
+            })
+            .collect()
+    }
+}
+
+pub(super) struct Idt;
+impl Idt {
+    /// Trapezoidal cumulative integral starting from `initial`
+    #[inline]
+    pub(super) fn iter(input: &[f64], dt: f64, initial: f64) -> Vec<f64> {
+        let mut sum = initial;
+        let mut values = Vec::with_capacity(input.len());
+        for (i, x) in input.iter().enumerate() {
+            if i > 0 {
+                sum += 0.5 * dt * (input[i - 1] + x);
+            }
+            values.push(sum);
+        }
+        values
+    }
+}
+
+impl Expression {
+    /// Time derivative of the waveform uniformly sampled by `dt`,
+    /// central differences inside and one-sided at the ends
+    ///
+    /// The gradient is the transposed stencil. A constant's derivative is zero
+    #[inline]
+    pub fn ddt(&self, dt: f64) -> Self {
+        match self {
+            Self::Const(_) => Self::Const(0.0),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                Ddt::iter(&tensor.read(), dt),
+                Op::Ddt(self.clone(), dt),
+            )),
+        }
+    }
+    /// Trapezoidal time integral of the waveform uniformly sampled by `dt`,
+    /// `output[0] = initial`
+    ///
+    /// The gradient is the reversed cumulative sum.
+    /// A constant is a single sample, its integral is `initial`
+    #[inline]
+    pub fn idt(&self, dt: f64, initial: f64) -> Self {
+        match self {
+            Self::Const(_) => Self::Const(initial),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                Idt::iter(&tensor.read(), dt, initial),
+                Op::Idt(self.clone(), dt, initial),
+            )),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////   Spectrum   ////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Output of the one-sided DFT, see [`Expression::fft_mag`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Spectrum {
+    /// `|X_k|`
+    Magnitude,
+    /// `|X_k|²`
+    Power,
+}
+
+/// Direct `O(N²)` real-input DFT, the bins `0..=N/2`
+pub(super) struct Dft;
+impl Dft {
+    /// `(cos, sin)` of `2πm/N` for `m` in `0..N`
+    #[inline]
+    pub(super) fn twiddles(n: usize) -> Vec<(f64, f64)> {
+        (0..n)
+            .map(|m| (std::f64::consts::TAU * m as f64 / n as f64).sin_cos())
+            .map(|(sin, cos)| (cos, sin))
+            .collect()
+    }
+    /// `(Re X_k, Im X_k)` of `X_k = Σ_n x_n e^{-2πikn/N}`
+    #[inline]
+    pub(super) fn transform(input: &[f64], twiddles: &[(f64, f64)]) -> Vec<(f64, f64)> {
+        let n = input.len();
+        let bins = if n == 0 { 0 } else { n / 2 + 1 };
+        (0..bins)
+            .map(|k| {
+                input
+                    .iter()
+                    .enumerate()
+                    .fold((0.0, 0.0), |(re, im), (i, x)| {
+                        let (cos, sin) = twiddles[k * i % n];
+                        (re + x * cos, im - x * sin)
+                    })
+            })
+            .collect()
+    }
+    #[inline]
+    pub(super) fn iter(input: &[f64], spectrum: Spectrum) -> Vec<f64> {
+        Self::transform(input, &Self::twiddles(input.len()))
+            .into_iter()
+            .map(|(re, im)| match spectrum {
+                Spectrum::Magnitude => re.hypot(im),
+                Spectrum::Power => re * re + im * im,
+            })
+            .collect()
+    }
+}
+
+impl Expression {
+    /// Magnitude of the one-sided DFT, a length-`N` tensor (uniformly sampled in time)
+    /// gives the `N/2+1` bins `|X_k|` of `X_k = Σ_n x_n e^{-2πikn/N}`
+    ///
+    /// The gradient is the adjoint DFT of `grad · X/|X|`, zero for a zero-magnitude bin.
+    /// A constant is a single sample. The DFT is direct, `O(N²)`
+    #[inline]
+    pub fn fft_mag(&self) -> Self {
+        self.spectrum(Spectrum::Magnitude)
+    }
+    /// Power `|X_k|²` of the one-sided DFT, see [`fft_mag`](Expression::fft_mag)
+    ///
+    /// Smooth everywhere, without the square root
+    #[inline]
+    pub fn fft_power(&self) -> Self {
+        self.spectrum(Spectrum::Power)
+    }
+    #[inline]
+    fn spectrum(&self, spectrum: Spectrum) -> Self {
+        match self {
+            Self::Const(x) => Self::Const(Dft::iter(&[*x], spectrum)[0]),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                Dft::iter(&tensor.read(), spectrum),
+                Op::Spectrum(self.clone(), spectrum),
+            )),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+//////////////////////////////////   CrossTime   ///////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Direction of the threshold crossing, see [`Expression::cross_time`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    /// From below to at or above the threshold
+    Rising,
+    /// From at or above to below the threshold
+    Falling,
+    /// Either direction
+    Either,
+}
+
+/// First crossing of a threshold, see [`Expression::cross_time`]
+#[derive(Clone, Debug)]
+pub struct CrossTime {
+    pub(super) threshold: f64,
+    pub(super) times: Vec<f64>,
+    pub(super) edge: Edge,
+    /// Smooths the indicators `x ≥ threshold` of the samples
+    pub(super) method: GradMethod,
+}
+
+impl CrossTime {
+    /// The indicators `x ≥ threshold` of the samples with `method`
+    #[inline]
+    pub(super) fn above(&self, method: &GradMethod, input: &[f64]) -> Vec<f64> {
+        input
+            .iter()
+            .map(|x| method.ge_indicator(*x, self.threshold))
+            .collect()
+    }
+    /// The crossing indicator of a segment on the edge, from the indicators of its ends
+    #[inline]
+    pub(super) fn crossing(&self, above_a: f64, above_b: f64) -> f64 {
+        match self.edge {
+            Edge::Rising => (1.0 - above_a) * above_b,
+            Edge::Falling => above_a * (1.0 - above_b),
+            Edge::Either => above_a + above_b - 2.0 * above_a * above_b,
+        }
+    }
+    /// The position of the threshold in the segment `[a, b]`, the middle when flat
+    #[inline]
+    pub(super) fn frac(&self, a: f64, b: f64) -> f64 {
+        if a == b {
+            0.5
+        } else {
+            ((self.threshold - a) / (b - a)).clamp(0.0, 1.0)
+        }
+    }
+    /// For each segment `[i, i+1]`: the crossing indicator `c_i`, the probability
+    /// `Π_{j<i} (1 - c_j)` that no earlier segment crosses, and the interpolated instant `t_i`
+    pub(super) fn segments(&self, above: &[f64], input: &[f64]) -> [Vec<f64>; 3] {
+        let n = input.len().saturating_sub(1);
+        let (mut crossing, mut reach, mut instants) = (
+            Vec::with_capacity(n),
+            Vec::with_capacity(n),
+            Vec::with_capacity(n),
+        );
+        let mut none_before = 1.0;
+        for i in 0..n {
+            let c = self.crossing(above[i], above[i + 1]);
+            let (t0, t1) = (self.times[i], self.times[i + 1]);
+            crossing.push(c);
+            reach.push(none_before);
+            instants.push(t0 + self.frac(input[i], input[i + 1]) * (t1 - t0));
+            none_before *= 1.0 - c;
+        }
+        [crossing, reach, instants]
+    }
+    /// The soft argmin `Σ w_i t_i / Σ w_i` of the first crossing, `w_i = c_i Π_{j<i} (1 - c_j)`,
+    /// `NaN` when not crossed or when the length differs from the times
+    pub(super) fn iter(&self, input: &[f64]) -> Vec<f64> {
+        if input.len() != self.times.len() {
+            return vec![f64::NAN];
+        }
+        let above = self.above(&self.method, input);
+        let [crossing, reach, instants] = self.segments(&above, input);
+        let (num, den) = izip!(&crossing, &reach, &instants)
+            .fold((0.0, 0.0), |(num, den), (c, p, t)| {
+                (num + c * p * t, den + c * p)
+            });
+        vec![if den > 0.0 { num / den } else { f64::NAN }]
+    }
+    /// Whether any segment crosses with the hard indicators
+    #[inline]
+    fn crossed(&self, input: &[f64]) -> bool {
+        let above = self.above(&GradMethod::Discrete, input);
+        above
+            .windows(2)
+            .any(|pair| self.crossing(pair[0], pair[1]) > 0.0)
+    }
+}
+
+impl Expression {
+    /// The first time the waveform sampled at `times` crosses `threshold` on `edge`,
+    /// a length-1 tensor
+    ///
+    /// Each segment `[i, i+1]` has a crossing indicator `c_i` built from the indicators
+    /// `x ≥ threshold` of its ends, smoothed by `method` as [`count_ge`](Expression::count_ge),
+    /// e.g., `(1 - s_i) s_{i+1}` on [`Edge::Rising`], and an instant `t_i` linearly
+    /// interpolated between the bracketing samples. The result is the soft argmin
+    /// `Σ w_i t_i / Σ w_i` of the first crossing, weighted by `w_i = c_i Π_{j<i} (1 - c_j)`,
+    /// so the gradient flows to the samples through both the interpolation and the weights
+    ///
+    /// With [`GradMethod::Discrete`], the hard first crossing, whose gradient is only
+    /// the interpolation, which the smoothed methods approach as the sharpness grows
+    ///
+    /// The value becomes `NaN` (without gradient) when an update removes the crossing
+    /// or changes the length
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_cross_time`](Expression::try_cross_time)
+    #[inline]
+    pub fn cross_time(
+        &self,
+        threshold: f64,
+        times: &[f64],
+        edge: Edge,
+        method: GradMethod,
+    ) -> Self {
+        self.try_cross_time(threshold, times, edge, method)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`cross_time`](Expression::cross_time)
+    ///
+    /// + [`Error::LengthMismatch`] when the length differs from `times`
+    /// + [`Error::NoCrossing`] when the waveform does not cross with the hard indicators,
+    ///   e.g., a constant
+    #[inline]
+    pub fn try_cross_time(
+        &self,
+        threshold: f64,
+        times: &[f64],
+        edge: Edge,
+        method: GradMethod,
+    ) -> Result<Self, Error> {
+        let op = || format!("CrossTime({threshold}, {edge:?})");
+        match self {
+            Self::Const(_) => Err(Error::NoCrossing { op: op() }),
+            Self::Tensor(tensor) => {
+                let input = tensor.read();
+                if input.len() != times.len() {
+                    return Err(Error::LengthMismatch {
+                        expected: times.len(),
+                        got: input.len(),
+                        op: op(),
+                    });
+                }
+                let cross = CrossTime {
+                    threshold,
+                    times: times.to_vec(),
+                    edge,
+                    method,
+                };
+                if !cross.crossed(&input) {
+                    return Err(Error::NoCrossing { op: op() });
+                }
+                Ok(Self::Tensor(Tensor::new(
+                    tensor.with_grad(),
+                    cross.iter(&input),
+                    Op::CrossTime(self.clone(), cross),
+                )))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////   Integrate   ////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Quadrature rule of [`Expression::integrate`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quadrature {
+    /// Linear between the samples, exact for a linear waveform
+    Trapezoid,
+    /// Quadratic through each pair of intervals, exact for a quadratic waveform,
+    /// the last interval is a trapezoid when the number of samples is even
+    Simpson,
+}
+
+/// Integral over a fixed time grid, see [`Expression::integrate`]
+#[derive(Clone, Debug)]
+pub struct Integral {
+    pub(super) times: Vec<f64>,
+    pub(super) rule: Quadrature,
+    /// The weight of each sample, `∫ = Σ w_i x_i`
+    pub(super) weights: Vec<f64>,
+}
+
+impl Integral {
+    #[inline]
+    fn new(times: &[f64], rule: Quadrature) -> Result<Self, Error> {
+        if times.len() < 2 {
+            return Err(Error::InvalidGrid(format!(
+                "need at least 2 times, got {}",
+                times.len()
+            )));
+        }
+        if let Some(i) = times.iter().position(|t| !t.is_finite()) {
+            return Err(Error::InvalidGrid(format!("time {} at {i}", times[i])));
+        }
+        if let Some(i) = times.windows(2).position(|w| w[0] >= w[1]) {
+            return Err(Error::InvalidGrid(format!(
+                "times not strictly increasing at {}",
+                i + 1
+            )));
+        }
+        Ok(Self {
+            weights: Self::weights(times, rule),
+            times: times.to_vec(),
+            rule,
+        })
+    }
+    /// The trapezoid `[i, i+1]` gives `h/2` to both ends, the Simpson pair
+    /// `[i, i+2]` of the steps `h0, h1` gives `(h0+h1)/6 · (2 - h1/h0, (h0+h1)²/(h0 h1), 2 - h0/h1)`
+    fn weights(times: &[f64], rule: Quadrature) -> Vec<f64> {
+        let n = times.len();
+        let mut weights = vec![0.0; n];
+        let trapezoid = |weights: &mut [f64], i: usize| {
+            let h = times[i + 1] - times[i];
+            weights[i] += 0.5 * h;
+            weights[i + 1] += 0.5 * h;
+        };
+        match rule {
+            Quadrature::Trapezoid => (0..n - 1).for_each(|i| trapezoid(&mut weights, i)),
+            Quadrature::Simpson => {
+                let pairs = (n - 1) / 2;
+                for i in (0..2 * pairs).step_by(2) {
+                    let (h0, h1) = (times[i + 1] - times[i], times[i + 2] - times[i + 1]);
+                    let scale = (h0 + h1) / 6.0;
+                    weights[i] += scale * (2.0 - h1 / h0);
+                    weights[i + 1] += scale * (h0 + h1) * (h0 + h1) / (h0 * h1);
+                    weights[i + 2] += scale * (2.0 - h0 / h1);
+                }
+                if 2 * pairs < n - 1 {
+                    trapezoid(&mut weights, n - 2);
+                }
+            }
+        }
+        weights
+    }
+    /// The fixed time grid
+    #[inline]
+    pub fn times(&self) -> &[f64] {
+        &self.times
+    }
+    #[inline]
+    pub fn rule(&self) -> Quadrature {
+        self.rule
+    }
+    /// `Σ w_i x_i`, compensated per [`set_accuracy_mode`](super::set_accuracy_mode),
+    /// panics when the length differs from the times
+    #[inline]
+    pub(super) fn iter(&self, input: &[f64]) -> Vec<f64> {
+        assert_eq!(
+            input.len(),
+            self.weights.len(),
+            "tensor length mismatch in Integrate: expected {}, got {}",
+            self.weights.len(),
+            input.len()
+        );
+        vec![reduction::sum_iter(
+            izip!(input, &self.weights).map(|(x, w)| w * x),
+        )]
+    }
+}
+
+impl Expression {
+    /// `∫ x dt` of the waveform sampled at the non-uniform `times`, a length-1 tensor,
+    /// e.g., the energy `i.mul(&v).integrate(&times, Quadrature::Simpson)`
+    ///
+    /// The times are fixed in the op, so the integral is the weighted sum of the samples
+    /// by the quadrature weights, which are also the gradient.
+    /// A constant is integrated over the whole grid.
+    /// Recomputing panics when the updated length differs from `times`
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_integrate`](Expression::try_integrate)
+    #[inline]
+    pub fn integrate(&self, times: &[f64], rule: Quadrature) -> Self {
+        self.try_integrate(times, rule)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`integrate`](Expression::integrate)
+    ///
+    /// + [`Error::InvalidGrid`] when there are less than 2 times, or the times
+    ///   are not finite and strictly increasing
+    /// + [`Error::LengthMismatch`] when the length differs from `times`
+    #[inline]
+    pub fn try_integrate(&self, times: &[f64], rule: Quadrature) -> Result<Self, Error> {
+        let integral = Integral::new(times, rule)?;
+        match self {
+            Self::Const(x) => Ok(Self::Const(x * (times[times.len() - 1] - times[0]))),
+            Self::Tensor(tensor) => {
+                let input = tensor.read();
+                if input.len() != times.len() {
+                    return Err(Error::LengthMismatch {
+                        expected: times.len(),
+                        got: input.len(),
+                        op: format!("Integrate({rule:?})"),
+                    });
+                }
+                Ok(Self::Tensor(Tensor::new(
+                    tensor.with_grad(),
+                    integral.iter(&input),
+                    Op::Integrate(self.clone(), integral),
+                )))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////   WindowReduce   /////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Reduction of each window of [`Expression::window_reduce`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowKind {
+    /// The gradient goes to the first maximum of the window
+    Max,
+    /// The gradient goes to the first minimum of the window
+    Min,
+    /// The gradient is split evenly in the window
+    Mean,
+}
+
+/// The samples after the last full window of [`Expression::window_reduce`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowTail {
+    /// Only the full windows
+    Drop,
+    /// One more window from the next start to the end, when it has samples
+    /// not covered by the full windows
+    Keep,
+}
+
+/// Windows of `window` samples every `stride` samples, see [`Expression::window_reduce`]
+#[derive(Clone, Copy, Debug)]
+pub struct WindowReduce {
+    pub(super) window: usize,
+    pub(super) stride: usize,
+    pub(super) kind: WindowKind,
+    pub(super) tail: WindowTail,
+}
+
+impl WindowReduce {
+    #[inline]
+    fn new(
+        window: usize,
+        stride: usize,
+        kind: WindowKind,
+        tail: WindowTail,
+    ) -> Result<Self, Error> {
+        if window == 0 || stride == 0 {
+            return Err(Error::InvalidWindow(format!(
+                "window {window} and stride {stride} must be positive"
+            )));
+        }
+        Ok(Self {
+            window,
+            stride,
+            kind,
+            tail,
+        })
+    }
+    /// The `[start, end)` of each window of `len` samples
+    pub(super) fn windows(&self, len: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let full = if len >= self.window {
+            (len - self.window) / self.stride + 1
+        } else {
+            0
+        };
+        let covered = full
+            .checked_sub(1)
+            .map_or(0, |last| last * self.stride + self.window);
+        let partial = self.tail == WindowTail::Keep && covered < len && full * self.stride < len;
+        (0..full + usize::from(partial)).map(move |k| {
+            let start = k * self.stride;
+            (start, (start + self.window).min(len))
+        })
+    }
+    /// The first extremum of each window, by a monotonic deque in `O(n)`
+    ///
+    /// The deque holds the candidates of the current window, whose values are strictly
+    /// worse from the front, so a tie keeps the earlier sample in front
+    pub(super) fn extrema(&self, input: &[f64]) -> Vec<usize> {
+        let better = match self.kind {
+            WindowKind::Max => |x: f64, y: f64| x > y,
+            WindowKind::Min => |x: f64, y: f64| x < y,
+            WindowKind::Mean => unreachable!("extrema of WindowMean"),
+        };
+        let mut deque = VecDeque::<usize>::new();
+        let mut next = 0;
+        self.windows(input.len())
+            .map(|(start, end)| {
+                while deque.front().is_some_and(|i| *i < start) {
+                    deque.pop_front();
+                }
+                // the samples between the windows are skipped when `stride > window`
+                next = next.max(start);
+                for i in next..end {
+                    while deque.back().is_some_and(|j| better(input[i], input[*j])) {
+                        deque.pop_back();
+                    }
+                    deque.push_back(i);
+                }
+                next = end;
+                deque[0]
+            })
+            .collect()
+    }
+    #[inline]
+    pub(super) fn iter(&self, input: &[f64]) -> Vec<f64> {
+        match self.kind {
+            WindowKind::Max | WindowKind::Min => {
+                self.extrema(input).into_iter().map(|i| input[i]).collect()
+            }
+            WindowKind::Mean => self
+                .windows(input.len())
+                .map(|(start, end)| reduction::sum(&input[start..end]) / (end - start) as f64)
+                .collect(),
+        }
+    }
+}
+
+impl Expression {
+    /// The max / min / mean of each window of `window` samples, starting every `stride` samples,
+    /// e.g., the peak of each bit of an eye diagram
+    ///
+    /// The windows overlap when `stride < window`, and skip samples when `stride > window`.
+    /// `tail` drops or keeps the shorter window at the end. The gradient of a max / min goes to
+    /// the first extremum of its window, unlike [`amax`](Expression::amax) which splits it among the ties.
+    /// A constant is its own window. The length follows the updated input,
+    /// an update leaving no window gives an empty tensor
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_window_reduce`](Expression::try_window_reduce)
+    #[inline]
+    pub fn window_reduce(
+        &self,
+        window: usize,
+        stride: usize,
+        kind: WindowKind,
+        tail: WindowTail,
+    ) -> Self {
+        self.try_window_reduce(window, stride, kind, tail)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`window_reduce`](Expression::window_reduce)
+    ///
+    /// + [`Error::InvalidWindow`] when `window` or `stride` is zero
+    /// + [`Error::EmptyTensor`] when there is no window, e.g., the input is shorter
+    ///   than `window` under [`WindowTail::Drop`]
+    #[inline]
+    pub fn try_window_reduce(
+        &self,
+        window: usize,
+        stride: usize,
+        kind: WindowKind,
+        tail: WindowTail,
+    ) -> Result<Self, Error> {
+        let reduce = WindowReduce::new(window, stride, kind, tail)?;
+        match self {
+            Self::Const(x) => Ok(Self::Const(*x)),
+            Self::Tensor(tensor) => {
+                let values = reduce.iter(&tensor.read());
+                if values.is_empty() {
+                    return Err(Error::EmptyTensor);
+                }
+                Ok(Self::Tensor(Tensor::new(
+                    tensor.with_grad(),
+                    values,
+                    Op::WindowReduce(self.clone(), reduce),
+                )))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Reduce   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Maximum of [`Expression::amax`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MaxMethod {
+    /// The exact maximum, the gradient is split evenly among the tied samples
+    Hard,
+    /// `τ ln Σ exp(x/τ)` with the temperature `τ`, an upper bound within `τ ln N`,
+    /// the gradient is the softmax
+    Smooth(f64),
+}
+
+/// Reduction of all the elements into one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reduce {
+    /// See [`Expression::sum`]
+    Sum,
+    /// See [`Expression::mean`]
+    Mean,
+    /// See [`Expression::amax`]
+    Max(MaxMethod),
+    /// See [`Expression::all`]
+    All,
+    /// See [`Expression::any`]
+    Any,
+    /// See [`Expression::quantile`] and [`Expression::quantile_smooth`]
+    Quantile { q: f64, bandwidth: Option<f64> },
+}
+
+impl Reduce {
+    #[inline]
+    pub(super) fn iter(&self, input: &[f64]) -> Vec<f64> {
+        let n = input.len() as f64;
+        let max = || input.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        vec![match self {
+            Self::Sum => reduction::sum(input),
+            Self::Mean => reduction::sum(input) / n,
+            Self::Max(MaxMethod::Hard) => max(),
+            Self::Max(MaxMethod::Smooth(temperature)) => {
+                // single pass, rescaling the sum whenever the running maximum grows
+                let (max, sum) = input
+                    .iter()
+                    .fold((f64::NEG_INFINITY, 0.0), |(max, sum), x| {
+                        if *x > max {
+                            (*x, sum * ((max - x) / temperature).exp() + 1.0)
+                        } else {
+                            (max, sum + ((x - max) / temperature).exp())
+                        }
+                    });
+                if max.is_finite() {
+                    max + temperature * sum.ln()
+                } else {
+                    max
+                }
+            }
+            Self::All => Self::product(input.iter().map(|x| Self::logic(*x))),
+            Self::Any => 1.0 - Self::product(input.iter().map(|x| 1.0 - Self::logic(*x))),
+            Self::Quantile { .. } if input.is_empty() => f64::NAN,
+            Self::Quantile { q, bandwidth } => Self::quantile_weights(input, *q, *bandwidth)
+                .into_iter()
+                .map(|(i, w)| w * input[i])
+                .sum(),
+        }]
+    }
+    /// `(index, weight)` of the samples defining the quantile, none when empty
+    ///
+    /// The order statistics at the rank `q(n - 1)`, linearly interpolated between
+    /// the two neighbors, each spread by a normalized Gaussian kernel over the ranks
+    /// of the width `bandwidth (n - 1)` when smoothed.
+    /// The input is not sorted in place, only an index permutation
+    pub(super) fn quantile_weights(
+        input: &[f64],
+        q: f64,
+        bandwidth: Option<f64>,
+    ) -> Vec<(usize, f64)> {
+        let n = input.len();
+        if n == 0 {
+            return vec![];
+        }
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|i, j| input[*i].total_cmp(&input[*j]));
+        let pos = q * (n - 1) as f64;
+        let lo = pos.floor() as usize;
+        let hi = (lo + 1).min(n - 1);
+        let frac = pos - lo as f64;
+        let width = bandwidth.map_or(0.0, |bandwidth| bandwidth * (n - 1) as f64);
+        if width == 0.0 {
+            return vec![(order[lo], 1.0 - frac), (order[hi], frac)];
+        }
+        let kernel = |center: usize| {
+            let weights: Vec<f64> = (0..n)
+                .map(|k| {
+                    let d = (k as f64 - center as f64) / width;
+                    (-0.5 * d * d).exp()
+                })
+                .collect();
+            let sum: f64 = weights.iter().sum();
+            weights.into_iter().map(move |w| w / sum)
+        };
+        kernel(lo)
+            .zip(kernel(hi))
+            .enumerate()
+            .map(|(k, (w_lo, w_hi))| (order[k], (1.0 - frac) * w_lo + frac * w_hi))
+            .collect()
+    }
+    /// The input of [`All`](Reduce::All) / [`Any`](Reduce::Any), clamped to `[0, 1]`
+    #[inline]
+    pub(super) fn logic(x: f64) -> f64 {
+        assert_logic!(x);
+        x.clamp(0.0, 1.0)
+    }
+    /// `(number of zeros, Σ ln y of the nonzero y)` of the non-negative factors
+    #[inline]
+    pub(super) fn log_product(factors: impl Iterator<Item = f64>) -> (usize, f64) {
+        factors.fold((0, 0.0), |(zeros, log_sum), y| {
+            if y == 0.0 {
+                (zeros + 1, log_sum)
+            } else {
+                (zeros, log_sum + y.ln())
+            }
+        })
+    }
+    /// `Π y` accumulated in the log space, so that a long product does not underflow early
+    #[inline]
+    fn product(factors: impl Iterator<Item = f64>) -> f64 {
+        match Self::log_product(factors) {
+            (0, log_sum) => log_sum.exp(),
+            _ => 0.0,
+        }
+    }
+    /// `Π_{j≠i} y_j` of the [`log_product`](Reduce::log_product) of all the factors
+    #[inline]
+    pub(super) fn product_except(y: f64, (zeros, log_sum): (usize, f64)) -> f64 {
+        match zeros {
+            0 => (log_sum - y.ln()).exp(),
+            1 if y == 0.0 => log_sum.exp(),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Expression {
+    /// Reduce the elements into a length-1 tensor, a constant is returned as-is
+    #[inline]
+    fn reduce(&self, reduce: Reduce) -> Self {
+        match self {
+            Self::Const(_) => self.clone(),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                reduce.iter(&tensor.read()),
+                Op::Reduce(self.clone(), reduce),
+            )),
+        }
+    }
+    /// Sum of the elements, a length-1 tensor
+    ///
+    /// A constant is returned as-is, a single sample
+    #[inline]
+    pub fn sum(&self) -> Self {
+        self.reduce(Reduce::Sum)
+    }
+    /// Mean of the elements, a length-1 tensor, `NaN` when empty
+    ///
+    /// A constant is returned as-is
+    #[inline]
+    pub fn mean(&self) -> Self {
+        self.reduce(Reduce::Mean)
+    }
+    /// Maximum of the elements, a length-1 tensor, `-inf` when empty
+    ///
+    /// A constant is returned as-is.
+    /// Unlike [`max`](Expression::max), not element-wise between two expressions
+    #[inline]
+    pub fn amax(&self, method: MaxMethod) -> Self {
+        self.reduce(Reduce::Max(method))
+    }
+    /// `ln Σ exp(βx) / β` of all the elements, a length-1 tensor, `-inf` when empty
+    ///
+    /// Stabilized by the running maximum in one pass, an upper bound of the maximum
+    /// within `ln N / β`, converging to it as `β → ∞`. The gradient is the softmax `exp(βx)/Σ exp(βx)`,
+    /// spread over all the elements rather than only the maximum, see [`amax`](Expression::amax)
+    ///
+    /// ## Panics
+    ///
+    /// When `beta` is not positive
+    #[inline]
+    pub fn smooth_max(&self, beta: f64) -> Self {
+        assert!(beta > 0.0, "non-positive smooth max beta {beta}");
+        self.amax(MaxMethod::Smooth(beta.recip()))
+    }
+    /// `-ln Σ exp(-βx) / β` of all the elements, see [`smooth_max`](Expression::smooth_max)
+    ///
+    /// ## Panics
+    ///
+    /// When `beta` is not positive
+    #[inline]
+    pub fn smooth_min(&self, beta: f64) -> Self {
+        assert!(beta > 0.0, "non-positive smooth min beta {beta}");
+        self.neg().smooth_max(beta).neg()
+    }
+    /// Smooth AND of all the elements `Π x`, a length-1 logic tensor, `1` when empty
+    ///
+    /// Accumulated in the log space against the underflow of long tensors,
+    /// the gradient of each element is the product of the others.
+    /// The input should be a logic tensor, see [`logic_and`](Expression::logic_and),
+    /// the values are clamped to `[0, 1]`
+    #[inline]
+    pub fn all(&self) -> Self {
+        self.logic_reduce(Reduce::All)
+    }
+    /// Smooth OR of all the elements `1 - Π (1 - x)`, a length-1 logic tensor, `0` when empty
+    ///
+    /// See [`all`](Expression::all)
+    #[inline]
+    pub fn any(&self) -> Self {
+        self.logic_reduce(Reduce::Any)
+    }
+    /// The `q`-quantile of the elements, a length-1 tensor, `NaN` when empty
+    ///
+    /// Linearly interpolated between the order statistics around the rank `q(n - 1)`,
+    /// e.g., `quantile(0.5)` is the median and `quantile(0.99)` the 99th percentile.
+    /// The gradient is split between the (one or two) samples defining it, by the
+    /// interpolation weights. A constant is returned as-is
+    ///
+    /// ## Panics
+    ///
+    /// When `q` is out of `[0, 1]`
+    #[inline]
+    pub fn quantile(&self, q: f64) -> Self {
+        assert!((0.0..=1.0).contains(&q), "quantile {q} out of [0, 1]");
+        self.reduce(Reduce::Quantile { q, bandwidth: None })
+    }
+    /// Smoothed [`quantile`](Expression::quantile), a weighted mean of the order statistics
+    /// in a soft neighborhood of the rank `q(n - 1)`, the Gaussian width is `bandwidth`
+    /// in the units of `q`. The gradient flows to all the neighborhood by the weights,
+    /// and it converges to [`quantile`](Expression::quantile) as `bandwidth → 0`
+    ///
+    /// ## Panics
+    ///
+    /// When `q` is out of `[0, 1]` or `bandwidth` is negative
+    #[inline]
+    pub fn quantile_smooth(&self, q: f64, bandwidth: f64) -> Self {
+        assert!((0.0..=1.0).contains(&q), "quantile {q} out of [0, 1]");
+        assert!(bandwidth >= 0.0, "negative quantile bandwidth {bandwidth}");
+        self.reduce(Reduce::Quantile {
+            q,
+            bandwidth: Some(bandwidth),
+        })
+    }
+    #[inline]
+    fn logic_reduce(&self, reduce: Reduce) -> Self {
+        match self {
+            Self::Const(x) => Self::Const(Reduce::logic(*x)),
+            Self::Tensor(tensor) => {
+                assert_logic_tensor!(tensor);
+                let Self::Tensor(out) = self.reduce(reduce) else {
+                    unreachable!()
+                };
+                Self::Tensor(mark_logic_tensor!(out))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   CountGe   ////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Count (or fraction) of the elements `≥ threshold`,
+/// see [`Expression::count_ge`] and [`Expression::mean_indicator`]
+#[derive(Clone, Debug)]
+pub struct CountGe {
+    pub(super) threshold: f64,
+    pub(super) method: GradMethod,
+    /// Divided by the number of elements
+    pub(super) mean: bool,
+}
+
+impl GradMethod {
+    /// The indicator `x ≥ threshold` whose derivative is the gradient of [`Expression::ge`]
+    /// with this method, the hard one for [`GradMethod::Discrete`]
+    #[inline]
+    pub(super) fn ge_indicator(&self, x: f64, threshold: f64) -> f64 {
+        let diff = x - threshold;
+        match self {
+            Self::Discrete => Ge::forward(x, threshold),
+            Self::Linear(GradMethodLinear { epsilon }) => {
+                (0.5 + diff / (2.0 * epsilon.get())).clamp(0.0, 1.0)
+            }
+            Self::Sigmoid(GradMethodSigmoid { k }) => 1.0 / (1.0 + (-k.get() * diff).exp()),
+            Self::Tanh(GradMethodTanh { k }) => 0.5 * (1.0 + (k.get() * diff).tanh()),
+            Self::SmoothStep(GradMethodSmoothStep { epsilon }) => {
+                let t = ((diff + epsilon.get()) / (2.0 * epsilon.get())).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            }
+        }
+    }
+}
+
+impl CountGe {
+    /// The indicators of the elements
+    #[inline]
+    pub(super) fn indicators(&self, input: &[f64]) -> Vec<f64> {
+        input
+            .iter()
+            .map(|x| self.method.ge_indicator(*x, self.threshold))
+            .collect()
+    }
+    #[inline]
+    pub(super) fn iter(&self, input: &[f64]) -> Vec<f64> {
+        let count: f64 = self.indicators(input).iter().sum();
+        vec![if self.mean {
+            count / input.len() as f64
+        } else {
+            count
+        }]
+    }
+}
+
+impl Expression {
+    #[inline]
+    fn count(&self, count: CountGe) -> Self {
+        match self {
+            Self::Const(x) => Self::Const(count.method.ge_indicator(*x, count.threshold)),
+            Self::Tensor(tensor) => Self::Tensor(Tensor::new(
+                tensor.with_grad(),
+                count.iter(&tensor.read()),
+                Op::CountGe(self.clone(), count),
+            )),
+        }
+    }
+    /// Number of the elements `≥ threshold`, a length-1 tensor, e.g., the passing samples
+    ///
+    /// With [`GradMethod::Discrete`], the exact count (the gradient is as [`ge`](Expression::ge)).
+    /// With a smoothed method, the sum of the smooth indicators whose derivative
+    /// is the gradient of [`ge`](Expression::ge) with that method, so that the count
+    /// is differentiable w.r.t. the upstream parameters,
+    /// e.g., `1/(1 + e^(-k(x - threshold)))` for [`GradMethod::new_sigmoid`]
+    ///
+    /// A constant is its indicator
+    #[inline]
+    pub fn count_ge(&self, threshold: f64, method: GradMethod) -> Self {
+        self.count(CountGe {
+            threshold,
+            method,
+            mean: false,
+        })
+    }
+    /// Fraction of the pass indicators `≥ 0.5`, a length-1 tensor, `NaN` when empty,
+    /// e.g., the Monte-Carlo yield
+    ///
+    /// See [`count_ge`](Expression::count_ge)
+    #[inline]
+    pub fn mean_indicator(&self, method: GradMethod) -> Self {
+        self.count(CountGe {
+            threshold: 0.5,
+            method,
+            mean: true,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+/////////////////////////////////   SoftHistogram   ////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Soft bins of [`Expression::soft_histogram`]
+#[derive(Clone, Debug)]
+pub struct SoftHistogram {
+    pub(super) edges: Vec<f64>,
+    pub(super) sigma: f64,
+}
+
+impl SoftHistogram {
+    /// Beyond `RANGE` σ, the kernel is negligible (`Φ(-8) ≈ 6e-16`)
+    const RANGE: f64 = 8.0;
+    #[inline]
+    fn new(edges: &[f64], sigma: f64) -> Result<Self, Error> {
+        if edges.len() < 2 {
+            return Err(Error::InvalidHistogram(format!(
+                "need at least 2 edges, got {}",
+                edges.len()
+            )));
+        }
+        if let Some(i) = edges.windows(2).position(|w| w[0] >= w[1]) {
+            return Err(Error::InvalidHistogram(format!(
+                "edges not strictly increasing at {}",
+                i + 1
+            )));
+        }
+        if !(sigma > 0.0 && sigma.is_finite()) {
+            return Err(Error::InvalidHistogram(format!(
+                "sigma {sigma} is not positive"
+            )));
+        }
+        Ok(Self {
+            edges: edges.to_vec(),
+            sigma,
+        })
+    }
+    /// The edges `[lo, hi]` whose kernel CDF is not saturated for the sample `x`,
+    /// the ones below are `0`, the ones above are `1`, the outer edges are `∓∞`
+    #[inline]
+    fn window(&self, x: f64) -> (usize, usize) {
+        let bins = self.edges.len() - 1;
+        let reach = Self::RANGE * self.sigma;
+        let lo = self
+            .edges
+            .partition_point(|e| *e < x - reach)
+            .clamp(1, bins);
+        let hi = self
+            .edges
+            .partition_point(|e| *e <= x + reach)
+            .clamp(lo, bins);
+        (lo, hi)
+    }
+    /// `Φ((e - x)/σ)` of an edge `e`
+    #[inline]
+    fn cdf(&self, k: usize, x: f64) -> f64 {
+        0.5 * (1.0
+            + candle_core::cpu::erf::erf(
+                (self.edges[k] - x) / (self.sigma * std::f64::consts::SQRT_2),
+            ))
+    }
+    /// `dΦ((e - x)/σ)/dx` of an edge `e`
+    #[inline]
+    fn cdf_derivative(&self, k: usize, x: f64) -> f64 {
+        let z = (self.edges[k] - x) / self.sigma;
+        -(-0.5 * z * z).exp() / (self.sigma * (2.0 * std::f64::consts::PI).sqrt())
+    }
+    /// The bin `j` gets `Φ((e[j+1] - x)/σ) - Φ((e[j] - x)/σ)` of each sample `x`,
+    /// the outer bins take the tails, so that each sample counts once
+    #[inline]
+    pub(super) fn iter(&self, input: &[f64]) -> Vec<f64> {
+        let mut counts = vec![0.0; self.edges.len() - 1];
+        for x in input {
+            let (lo, hi) = self.window(*x);
+            let mut prev = 0.0;
+            for k in lo..=hi {
+                let cdf = if k == hi { 1.0 } else { self.cdf(k, *x) };
+                counts[k - 1] += cdf - prev;
+                prev = cdf;
+            }
+        }
+        counts
+    }
+    /// The gradient of each sample from its nearby bins
+    #[inline]
+    pub(super) fn backward(&self, input: &[f64], grad: &[f64], sum_grad: &mut [f64]) {
+        for (x, sum) in izip!(input, sum_grad) {
+            let (lo, hi) = self.window(*x);
+            for k in lo..hi {
+                *sum += self.cdf_derivative(k, *x) * (grad[k - 1] - grad[k]);
+            }
+        }
+    }
+}
+
+impl Expression {
+    /// Soft bin counts of the samples, a tensor of `edges.len() - 1` elements
+    ///
+    /// Each sample is spread by a Gaussian kernel of the width `sigma`, the bin `j` gets
+    /// the kernel mass in `[edges[j], edges[j+1])` and the outer bins take the tails,
+    /// so the counts sum to the number of samples, and converge to the hard histogram
+    /// (with the outliers in the outer bins) as `sigma → 0`.
+    /// Differentiable w.r.t. the samples, each gradient comes from the nearby bins.
+    /// A constant is one sample
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_soft_histogram`](Expression::try_soft_histogram)
+    #[inline]
+    pub fn soft_histogram(&self, edges: &[f64], sigma: f64) -> Self {
+        self.try_soft_histogram(edges, sigma)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`soft_histogram`](Expression::soft_histogram)
+    ///
+    /// [`Error::InvalidHistogram`] when there are less than 2 edges,
+    /// the edges are not strictly increasing, or `sigma` is not positive
+    #[inline]
+    pub fn try_soft_histogram(&self, edges: &[f64], sigma: f64) -> Result<Self, Error> {
+        let histogram = SoftHistogram::new(edges, sigma)?;
+        let (values, with_grad) = match self {
+            Self::Const(x) => (histogram.iter(&[*x]), false),
+            Self::Tensor(tensor) => (histogram.iter(&tensor.read()), tensor.with_grad()),
+        };
+        Ok(Self::Tensor(Tensor::new(
+            with_grad,
+            values,
+            Op::SoftHistogram(self.clone(), histogram),
+        )))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Conv1d   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Padding of [`Expression::conv1d`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConvPadding {
+    /// No padding, the output has `n - m + 1` elements
+    Valid,
+    /// Zero padding, the output has `n` elements, centered on the kernel
+    Same,
+}
+
+impl ConvPadding {
+    /// `(output length, shift)` of a length-`n` signal and a length-`m` kernel,
+    /// `output[i] = Σ_k kernel[k] * signal[i + shift - k]`
+    #[inline]
+    pub(super) fn shape(&self, n: usize, m: usize) -> Result<(usize, usize), Error> {
+        match self {
+            _ if m == 0 => Err(Error::LengthMismatch {
+                expected: 1,
+                got: 0,
+                op: "Conv1d".to_owned(),
+            }),
+            Self::Valid if n < m => Err(Error::LengthMismatch {
+                expected: m,
+                got: n,
+                op: "Conv1d".to_owned(),
+            }),
+            Self::Valid => Ok((n - m + 1, m - 1)),
+            Self::Same => Ok((n, (m - 1) / 2)),
+        }
+    }
+}
+
+pub(super) struct Conv1d;
+impl Conv1d {
+    /// The pairs `(k, j)` contributing `kernel[k] * signal[j]` to `output[i]`
+    #[inline]
+    pub(super) fn taps(
+        i: usize,
+        shift: usize,
+        n: usize,
+        m: usize,
+    ) -> impl Iterator<Item = (usize, usize)> {
+        (0..m).filter_map(move |k| {
+            (i + shift)
+                .checked_sub(k)
+                .filter(|j| *j < n)
+                .map(|j| (k, j))
+        })
+    }
+    #[inline]
+    pub(super) fn forward(
+        signal: &[f64],
+        kernel: &[f64],
+        padding: ConvPadding,
+    ) -> Result<Vec<f64>, Error> {
+        let (n, m) = (signal.len(), kernel.len());
+        let (len, shift) = padding.shape(n, m)?;
+        Ok((0..len)
+            .map(|i| {
+                Self::taps(i, shift, n, m)
+                    .map(|(k, j)| kernel[k] * signal[j])
+                    .sum()
+            })
+            .collect())
+    }
+}
+
+impl Expression {
+    /// Values of a [`Conv1d`] input, a constant is a length-1 tensor
+    #[inline]
+    pub(super) fn conv_values(&self) -> Vec<f64> {
+        match self {
+            Self::Const(x) => vec![*x],
+            Self::Tensor(tensor) => tensor.read().clone(),
+        }
+    }
+    /// 1-D convolution `output[i] = Σ_k kernel[k] * self[i + shift - k]`,
+    /// where `shift` is `m - 1` for [`ConvPadding::Valid`] and `(m - 1)/2` for [`ConvPadding::Same`]
+    ///
+    /// The signal gradient is the correlation with the kernel,
+    /// and the kernel gradient is the correlation with the signal
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_conv1d`](Expression::try_conv1d)
+    #[inline]
+    pub fn conv1d(&self, kernel: &Self, padding: ConvPadding) -> Self {
+        self.try_conv1d(kernel, padding)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`conv1d`](Expression::conv1d)
+    ///
+    /// [`Error::LengthMismatch`] when the kernel is empty,
+    /// or longer than the signal with [`ConvPadding::Valid`]
+    #[inline]
+    pub fn try_conv1d(&self, kernel: &Self, padding: ConvPadding) -> Result<Self, Error> {
+        let values = Conv1d::forward(&self.conv_values(), &kernel.conv_values(), padding)?;
+        let with_grad = [self, kernel]
+            .iter()
+            .any(|expr| matches!(expr, Self::Tensor(tensor) if tensor.with_grad()));
+        Ok(match (self, kernel) {
+            (Self::Const(_), Self::Const(_)) => Self::Const(values[0]),
+            _ => Self::Tensor(Tensor::new(
+                with_grad,
+                values,
+                Op::Conv1d(self.clone(), kernel.clone(), padding),
+            )),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   MatVec   /////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+pub(super) struct MatVec;
+impl MatVec {
+    /// [`Error::LengthMismatch`] when the lengths are not `cols` and `rows * cols`
+    #[inline]
+    pub(super) fn check(
+        input: &[f64],
+        weights: &[f64],
+        rows: usize,
+        cols: usize,
+    ) -> Result<(), Error> {
+        if input.len() != cols {
+            return Err(Error::LengthMismatch {
+                expected: cols,
+                got: input.len(),
+                op: "MatVec input".to_owned(),
+            });
+        }
+        if weights.len() != rows * cols {
+            return Err(Error::LengthMismatch {
+                expected: rows * cols,
+                got: weights.len(),
+                op: "MatVec weights".to_owned(),
+            });
+        }
+        Ok(())
+    }
+    #[inline]
+    pub(super) fn forward(
+        input: &[f64],
+        weights: &[f64],
+        rows: usize,
+        cols: usize,
+    ) -> Result<Vec<f64>, Error> {
+        Self::check(input, weights, rows, cols)?;
+        Ok((0..rows)
+            .map(|i| {
+                reduction::sum_iter(
+                    izip!(&weights[i * cols..(i + 1) * cols], input).map(|(w, x)| w * x),
+                )
+            })
+            .collect())
+    }
+}
+
+impl Expression {
+    /// `y[i] = Σ_j W[i, j] x[j]` of the input `x` (length-`cols`, `self`)
+    /// and the row-major `rows × cols` weights `W`, a length-`rows` tensor
+    ///
+    /// The input gradient is `Wᵀ g`, the weights gradient is the outer product `g ⊗ x`.
+    /// With the element-wise ops, e.g., `x.matvec(&w, rows, cols).add(&b).tanh()` is a dense layer.
+    /// A constant is length-1
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_matvec`](Expression::try_matvec), also when the updated lengths mismatch at recompute
+    #[inline]
+    pub fn matvec(&self, weights: &Self, rows: usize, cols: usize) -> Self {
+        self.try_matvec(weights, rows, cols)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`matvec`](Expression::matvec)
+    ///
+    /// [`Error::LengthMismatch`] when the input is not length-`cols`
+    /// or the weights are not length-`rows * cols`
+    #[inline]
+    pub fn try_matvec(&self, weights: &Self, rows: usize, cols: usize) -> Result<Self, Error> {
+        let values = MatVec::forward(&self.conv_values(), &weights.conv_values(), rows, cols)?;
+        let with_grad = [self, weights]
+            .iter()
+            .any(|expr| matches!(expr, Self::Tensor(tensor) if tensor.with_grad()));
+        Ok(match (self, weights) {
+            (Self::Const(_), Self::Const(_)) => Self::Const(values[0]),
+            _ => Self::Tensor(Tensor::new(
+                with_grad,
+                values,
+                Op::MatVec(self.clone(), weights.clone(), rows, cols),
+            )),
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Polyval   ////////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+pub(super) struct Polyval;
+impl Polyval {
+    /// `Σ c[k] x^k` by Horner's scheme
+    #[inline]
+    pub(super) fn forward(x: f64, coeffs: &[f64]) -> f64 {
+        coeffs.iter().rev().fold(0.0, |acc, c| acc * x + c)
+    }
+    /// `Σ k c[k] x^(k-1)` by Horner's scheme
+    #[inline]
+    pub(super) fn derivative(x: f64, coeffs: &[f64]) -> f64 {
+        coeffs
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .fold(0.0, |acc, (k, c)| acc * x + k as f64 * c)
+    }
+    #[inline]
+    pub(super) fn iter(input: &[f64], coeffs: &[f64]) -> Vec<f64> {
+        input.iter().map(|x| Self::forward(*x, coeffs)).collect()
+    }
+}
+
+impl Expression {
+    /// `Σ c[k] x^k` of each element `x`, with the coefficients `c` from the constant term,
+    /// fused into one node by Horner's scheme
+    ///
+    /// The input gradient is the derivative polynomial, the coefficient gradient `x^k` is
+    /// accumulated over the elements. A constant coefficient is degree 0.
+    /// The powers of a high degree are the user's care when `|x| > 1`
+    #[inline]
+    pub fn polyval(&self, coeffs: &Self) -> Self {
+        let coeffs_values = coeffs.conv_values();
+        match (self, coeffs) {
+            (Self::Const(x), Self::Const(_)) => Self::Const(Polyval::forward(*x, &coeffs_values)),
+            _ => {
+                let with_grad = [self, coeffs]
+                    .iter()
+                    .any(|expr| matches!(expr, Self::Tensor(tensor) if tensor.with_grad()));
+                Self::Tensor(Tensor::new(
+                    with_grad,
+                    Polyval::iter(&self.conv_values(), &coeffs_values),
+                    Op::Polyval(self.clone(), coeffs.clone()),
+                ))
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////
+///////////////////////////////////   Weighted   ///////////////////////////////////////////
+////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Reduction of the element-wise products in a single node, see [`Expression::weighted_sum`]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Weighted {
+    /// `Σ w_i x_i`
+    Sum,
+    /// `Σ w_i x_i / n`
+    Mean,
+}
+
+impl Weighted {
+    /// The broadcast length, see [`broadcast_len`]
+    #[inline]
+    pub(super) fn len(self, input: &[f64], weights: &[f64]) -> Result<usize, Error> {
+        broadcast_len(&format!("Weighted{self:?}"), [input.len(), weights.len()])
+    }
+    /// `1` or `1 / n`
+    #[inline]
+    pub(super) fn scale(self, len: usize) -> f64 {
+        match self {
+            Self::Sum => 1.0,
+            Self::Mean => 1.0 / len as f64,
+        }
+    }
+    /// One pass over the data, compensated per [`set_accuracy_mode`](super::set_accuracy_mode)
+    #[inline]
+    pub(super) fn forward(self, input: &[f64], weights: &[f64]) -> Result<f64, Error> {
+        let len = self.len(input, weights)?;
+        let sum = reduction::sum_iter(
+            izip!(broadcast(input, len), broadcast(weights, len)).map(|(x, w)| w * x),
+        );
+        Ok(sum * self.scale(len))
+    }
+}
+
+impl Expression {
+    /// `Σ w_i x_i` of the elements `x` (`self`) and the weights `w`, a length-1 tensor
+    ///
+    /// The same as `self.mul(weights).sum()` without the intermediate product tensor,
+    /// e.g., the weighted loss `err.sqr().weighted_sum(&w)`. The weights can be a parameter,
+    /// the input gradient is `w`, the weights gradient is `x`. A length-1 input broadcasts
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_weighted_sum`](Expression::try_weighted_sum), also when the updated lengths mismatch at recompute
+    #[inline]
+    pub fn weighted_sum(&self, weights: &Self) -> Self {
+        self.try_weighted_sum(weights)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`weighted_sum`](Expression::weighted_sum)
+    ///
+    /// [`Error::LengthMismatch`] when the lengths differ and none is length-1
+    #[inline]
+    pub fn try_weighted_sum(&self, weights: &Self) -> Result<Self, Error> {
+        self.weighted(weights, Weighted::Sum)
+    }
+    /// `Σ w_i x_i / n`, the same as `self.mul(weights).mean()` fused as
+    /// [`weighted_sum`](Expression::weighted_sum), `NaN` when empty
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_weighted_mean`](Expression::try_weighted_mean), also when the updated lengths mismatch at recompute
+    #[inline]
+    pub fn weighted_mean(&self, weights: &Self) -> Self {
+        self.try_weighted_mean(weights)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`weighted_mean`](Expression::weighted_mean)
+    ///
+    /// [`Error::LengthMismatch`] when the lengths differ and none is length-1
+    #[inline]
+    pub fn try_weighted_mean(&self, weights: &Self) -> Result<Self, Error> {
+        self.weighted(weights, Weighted::Mean)
+    }
+    #[inline]
+    fn weighted(&self, weights: &Self, weighted: Weighted) -> Result<Self, Error> {
+        let value = weighted.forward(&self.conv_values(), &weights.conv_values())?;
+        let with_grad = [self, weights]
+            .iter()
+            .any(|expr| matches!(expr, Self::Tensor(tensor) if tensor.with_grad()));
+        Ok(match (self, weights) {
+            (Self::Const(_), Self::Const(_)) => Self::Const(value),
+            _ => Self::Tensor(Tensor::new(
+                with_grad,
+                vec![value],
+                Op::Weighted(self.clone(), weights.clone(), weighted),
+            )),
+        })
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////
@@ -389,6 +4240,7 @@ pub struct Constraint {
 #[derive(Clone, Copy, Debug)]
 pub enum UnaryOp {
     LogicNot,
+    Saturate01,
     Neg,
     Sin,
     Cos,
@@ -403,6 +4255,7 @@ pub enum UnaryOp {
     Cubic,
     Log,
     Exp,
+    LimExp,
     Abs,
     Erf,
 }
@@ -491,7 +4344,7 @@ impl UnaryOpT for Tan {
     #[inline]
     fn backward(_x: &f64, res: &f64, grad: &f64, sum_grad: &mut f64) {
         let dtan = res * res + 1.;
-        *sum_grad -= grad * dtan;
+        *sum_grad += grad * dtan;
     }
 }
 struct Ceil;
@@ -504,7 +4357,6 @@ impl UnaryOpT for Ceil {
     // FIXME: No gradient for compare
     #[inline]
     fn backward(_x: &f64, _res: &f64, _grad: &f64, _sum_grad: &mut f64) {
-        log::error!("BackwardNotSupported Ceil");
         // *sum_grad += grad;
     }
 }
@@ -517,7 +4369,6 @@ impl UnaryOpT for Floor {
     }
     #[inline]
     fn backward(_x: &f64, _res: &f64, _grad: &f64, _sum_grad: &mut f64) {
-        log::error!("BackwardNotSupported Floor");
         // *sum_grad += grad;
     }
 }
@@ -531,7 +4382,6 @@ impl UnaryOpT for Round {
     }
     #[inline]
     fn backward(_x: &f64, _res: &f64, _grad: &f64, _sum_grad: &mut f64) {
-        log::error!("BackwardNotSupported Round");
         // *sum_grad += grad;
     }
 }
@@ -544,7 +4394,6 @@ impl UnaryOpT for Sign {
     }
     #[inline]
     fn backward(_x: &f64, _res: &f64, _grad: &f64, _sum_grad: &mut f64) {
-        log::error!("BackwardNotSupported Sign");
         // let epsilon = 1e-10;
         // if (x.abs() - epsilon).is_sign_negative() {
         //     *sum_grad += grad;
@@ -612,6 +4461,34 @@ impl UnaryOpT for Exp {
         *sum_grad += grad * res;
     }
 }
+
+/// Crossover of [`Expression::limexp`], `exp(80) ≈ 5.5e34`
+pub const LIMEXP_X0: f64 = 80.0;
+
+struct LimExp;
+impl LimExp {
+    /// `exp(min(x, x0))`
+    #[inline]
+    fn derivative(x: f64) -> f64 {
+        x.min(LIMEXP_X0).exp()
+    }
+}
+impl UnaryOpT for LimExp {
+    const OP: UnaryOp = UnaryOp::LimExp;
+    /// `exp(x)` below [`LIMEXP_X0`], `exp(x0)*(1+x-x0)` above
+    #[inline]
+    fn forward(x: f64) -> f64 {
+        if x <= LIMEXP_X0 {
+            x.exp()
+        } else {
+            LIMEXP_X0.exp() * (1.0 + x - LIMEXP_X0)
+        }
+    }
+    #[inline]
+    fn backward(x: &f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
+        *sum_grad += grad * Self::derivative(*x);
+    }
+}
 struct Abs;
 impl UnaryOpT for Abs {
     const OP: UnaryOp = UnaryOp::Abs;
@@ -619,12 +4496,13 @@ impl UnaryOpT for Abs {
     fn forward(x: f64) -> f64 {
         x.abs()
     }
+    /// The subgradient at `x = 0` (both `+0.0` and `-0.0`) is `0`
     #[inline]
     fn backward(x: &f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
-        if x.is_sign_positive() {
-            *sum_grad += grad;
-        } else {
-            *sum_grad -= grad;
+        match OrderedFloat(*x).cmp(&OrderedFloat(0.0)) {
+            Ordering::Less => *sum_grad -= grad,
+            Ordering::Equal => (),
+            Ordering::Greater => *sum_grad += grad,
         }
     }
 }
@@ -660,14 +4538,39 @@ impl UnaryOpT for LogicNot {
         1.0 - x
     }
     #[inline]
+    fn backward(_x: &f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
+        *sum_grad -= grad;
+    }
+}
+
+/// `clamp(x, 0, 1)`, the output is a logic tensor
+///
+/// The gradient passes through inside `[0, 1]`, and is `0` outside
+struct Saturate01;
+impl UnaryOpT for Saturate01 {
+    const OP: UnaryOp = UnaryOp::Saturate01;
+    #[inline]
+    fn debug_mark(tensor: Tensor) -> Tensor {
+        mark_logic_tensor!(tensor)
+    }
+    #[inline]
+    fn forward(x: f64) -> f64 {
+        x.clamp(0.0, 1.0)
+    }
+    #[inline]
     fn backward(x: &f64, _res: &f64, grad: &f64, sum_grad: &mut f64) {
-        // d/dx erf(x) = 2/sqrt(pi) * e^(-x^2)
-        let erf_grad = (2. / std::f64::consts::PI.sqrt()) * (-x * x).exp();
-        *sum_grad += grad * erf_grad;
+        if (0.0..=1.0).contains(x) {
+            *sum_grad += grad;
+        }
     }
 }
 
 impl UnaryOp {
+    /// Ceil, Floor, Round and Sign have no gradient
+    #[inline]
+    pub(super) const fn is_differentiable(&self) -> bool {
+        !matches!(self, Self::Ceil | Self::Floor | Self::Round | Self::Sign)
+    }
     pub(super) const fn forward(&self) -> fn(f64) -> f64 {
         match self {
             Self::Neg => Neg::forward,
@@ -684,9 +4587,11 @@ impl UnaryOp {
             Self::Cubic => Cubic::forward,
             Self::Log => Log::forward,
             Self::Exp => Exp::forward,
+            Self::LimExp => LimExp::forward,
             Self::Abs => Abs::forward,
             Self::Erf => Erf::forward,
             Self::LogicNot => LogicNot::forward,
+            Self::Saturate01 => Saturate01::forward,
         }
     }
     #[inline]
@@ -706,34 +4611,27 @@ impl UnaryOp {
             Self::Cubic => Cubic::backward,
             Self::Log => Log::backward,
             Self::Exp => Exp::backward,
+            Self::LimExp => LimExp::backward,
             Self::Abs => Abs::backward,
             Self::Erf => Erf::backward,
             Self::LogicNot => LogicNot::backward,
+            Self::Saturate01 => Saturate01::backward,
         }
     }
 }
 
 impl Tensor {
     #[inline]
-    pub(super) fn iter_unary_op(&self, forward: fn(f64) -> f64) -> Vec<f64> {
-        self.values()
-            .read()
-            .unwrap()
-            .iter()
-            .map(|x| forward(*x))
-            .collect()
+    pub(super) fn iter_unary_op(&self, forward: impl Fn(f64) -> f64) -> Vec<f64> {
+        self.read().iter().map(|x| forward(*x)).collect()
     }
     #[inline]
-    pub(super) fn unary_op(&self, forward: fn(f64) -> f64, op: Op) -> Self {
-        Self::new(
-            if self.with_grad() {
-                Some(GradId::new())
-            } else {
-                None
-            },
-            self.iter_unary_op(forward),
-            op,
-        )
+    pub(super) fn unary_op(&self, forward: impl Fn(f64) -> f64, op: Op) -> Self {
+        if is_lazy_build() {
+            let with_grad = self.with_grad();
+            return Self::new_lazy(with_grad, self.values_len(), op);
+        }
+        Self::new(self.with_grad(), self.iter_unary_op(forward), op)
     }
 }
 
@@ -794,6 +4692,12 @@ impl Expression {
     pub fn exp(&self) -> Self {
         Self::unary_op::<Exp>(&self)
     }
+    /// Verilog-A `limexp`: `exp(x)` below [`LIMEXP_X0`],
+    /// and linearized `exp(x0)*(1+x-x0)` above, so it is C1 and never overflows
+    #[inline]
+    pub fn limexp(&self) -> Self {
+        Self::unary_op::<LimExp>(self)
+    }
     #[inline]
     pub fn abs(&self) -> Self {
         Self::unary_op::<Abs>(&self)
@@ -806,6 +4710,12 @@ impl Expression {
     pub fn logic_not(&self) -> Self {
         Self::unary_op::<LogicNot>(&self)
     }
+    /// Clamp to `[0, 1]` and mark the result as logic,
+    /// use it before feeding a blended value into the logic ops
+    #[inline]
+    pub fn saturate01(&self) -> Self {
+        Self::unary_op::<Saturate01>(self)
+    }
 }
 
 impl Expression {
@@ -839,11 +4749,18 @@ pub enum DiscreteBinaryOp {
 }
 
 impl DiscreteBinaryOp {
+    /// The hard or the [`smooth`](GradMethod::smooth) values, see [`set_smooth_forward`]
     #[inline]
     pub(super) fn forward_iter<'a>(
         &self,
+        grad_method: &GradMethod,
         iter: impl Iterator<Item = (&'a f64, &'a f64)>,
     ) -> Vec<f64> {
+        if grad_method.is_smooth_forward() {
+            return iter
+                .map(|(lhs, rhs)| grad_method.smooth(*self, *lhs, *rhs))
+                .collect();
+        }
         match self {
             DiscreteBinaryOp::Eq => Eq::forward_iter(iter),
             DiscreteBinaryOp::Ne => Ne::forward_iter(iter),
@@ -856,9 +4773,15 @@ impl DiscreteBinaryOp {
     #[inline]
     pub(super) fn forward_iter_fix_lhs<'a>(
         &self,
+        grad_method: &GradMethod,
         lhs: f64,
         rhs_iter: impl Iterator<Item = &'a f64>,
     ) -> Vec<f64> {
+        if grad_method.is_smooth_forward() {
+            return rhs_iter
+                .map(|rhs| grad_method.smooth(*self, lhs, *rhs))
+                .collect();
+        }
         match self {
             DiscreteBinaryOp::Eq => Eq::forward_iter_fix_lhs(lhs, rhs_iter),
             DiscreteBinaryOp::Ne => Ne::forward_iter_fix_lhs(lhs, rhs_iter),
@@ -871,9 +4794,15 @@ impl DiscreteBinaryOp {
     #[inline]
     pub(super) fn forward_iter_fix_rhs<'a>(
         &self,
+        grad_method: &GradMethod,
         rhs: f64,
         lhs_iter: impl Iterator<Item = &'a f64>,
     ) -> Vec<f64> {
+        if grad_method.is_smooth_forward() {
+            return lhs_iter
+                .map(|lhs| grad_method.smooth(*self, *lhs, rhs))
+                .collect();
+        }
         match self {
             DiscreteBinaryOp::Eq => Eq::forward_iter_fix_rhs(rhs, lhs_iter),
             DiscreteBinaryOp::Ne => Ne::forward_iter_fix_rhs(rhs, lhs_iter),
@@ -1028,9 +4957,9 @@ impl GradMethodT for GradMethodDiscrete {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct GradMethodLinear {
-    epsilon: f64,
+    epsilon: SharpnessHandle,
 }
 
 impl GradMethodT for GradMethodLinear {
@@ -1054,7 +4983,7 @@ impl GradMethodT for GradMethodLinear {
     #[inline]
     fn eq_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
         if !res.is_zero() {
-            *lhs_sum_grad -= grad * (lhs - rhs).signum() / self.epsilon;
+            *lhs_sum_grad -= grad * (lhs - rhs).signum() / self.epsilon.get();
         }
     }
     /// `1 - |a - b|/ε`    when  `|a - b| < ε`
@@ -1077,7 +5006,7 @@ impl GradMethodT for GradMethodLinear {
     #[inline]
     fn eq_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
         if !res.is_zero() {
-            *rhs_sum_grad += grad * (lhs - rhs).signum() / self.epsilon;
+            *rhs_sum_grad += grad * (lhs - rhs).signum() / self.epsilon.get();
         }
     }
     /// |`a - b|/ε`    when  `|a - b| < ε`
@@ -1095,7 +5024,7 @@ impl GradMethodT for GradMethodLinear {
     #[inline]
     fn ne_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
         if !res.is_one() {
-            *lhs_sum_grad += grad * (lhs - rhs).signum() / self.epsilon;
+            *lhs_sum_grad += grad * (lhs - rhs).signum() / self.epsilon.get();
         }
     }
     /// |`a - b|/ε`    when  `|a - b| < ε`
@@ -1113,7 +5042,7 @@ impl GradMethodT for GradMethodLinear {
     #[inline]
     fn ne_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
         if !res.is_one() {
-            *rhs_sum_grad -= grad * (lhs - rhs).signum() / self.epsilon;
+            *rhs_sum_grad -= grad * (lhs - rhs).signum() / self.epsilon.get();
         }
     }
     /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
@@ -1142,8 +5071,8 @@ impl GradMethodT for GradMethodLinear {
         grad: &f64,
         lhs_sum_grad: &mut f64,
     ) {
-        if OrderedFloat((lhs - rhs).abs()) <= OrderedFloat(self.epsilon) {
-            *lhs_sum_grad -= grad / (2.0 * self.epsilon);
+        if OrderedFloat((lhs - rhs).abs()) <= OrderedFloat(self.epsilon.get()) {
+            *lhs_sum_grad -= grad / (2.0 * self.epsilon.get());
         }
     }
     /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
@@ -1172,8 +5101,8 @@ impl GradMethodT for GradMethodLinear {
         grad: &f64,
         rhs_sum_grad: &mut f64,
     ) {
-        if OrderedFloat((lhs - rhs).abs()) <= OrderedFloat(self.epsilon) {
-            *rhs_sum_grad += grad / (2.0 * self.epsilon);
+        if OrderedFloat((lhs - rhs).abs()) <= OrderedFloat(self.epsilon.get()) {
+            *rhs_sum_grad += grad / (2.0 * self.epsilon.get());
         }
     }
     fn lt_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
@@ -1183,9 +5112,9 @@ impl GradMethodT for GradMethodLinear {
         self.le_backward_rhs(lhs, rhs, res, grad, rhs_sum_grad);
     }
 }
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct GradMethodSigmoid {
-    k: f64,
+    k: SharpnessHandle,
 }
 impl GradMethodT for GradMethodSigmoid {
     /// `eq(a,b) = sigmoid(a, b, k) = e^(-k (a - b)^2)`
@@ -1201,12 +5130,224 @@ impl GradMethodT for GradMethodSigmoid {
         lhs_sum_grad: &mut f64,
     ) {
         let diff = lhs - rhs;
-        let kdiff = self.k * diff;
+        let kdiff = self.k.get() * diff;
         *lhs_sum_grad -= grad * 2.0 * kdiff * ((-kdiff * diff).exp());
     }
     /// `eq(a,b) = sigmoid(a, b, k) = e^(-k (a - b)^2)`
     ///
-    /// $$\frac{\partial \text{Eq}_{\text{sigmoid}}}{\partial b} = 2k (a - b) e^{-k (a - b)^2}$$
+    /// $$\frac{\partial \text{Eq}_{\text{sigmoid}}}{\partial b} = 2k (a - b) e^{-k (a - b)^2}$$
+    #[inline]
+    fn eq_backward_rhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        rhs_sum_grad: &mut f64,
+    ) {
+        let diff = lhs - rhs;
+        let kdiff = self.k.get() * diff;
+        *rhs_sum_grad += grad * 2.0 * kdiff * ((-kdiff * diff).exp());
+    }
+    /// `ne(a,b) = 1- sigmoid(a, b, k) = 1-e^(-k (a - b)^2)`
+    ///
+    /// -eq
+    #[inline]
+    fn ne_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        self.eq_backward_rhs(lhs, rhs, res, grad, lhs_sum_grad);
+    }
+    /// `ne(a,b) = 1- sigmoid(a, b, k) = 1-e^(-k (a - b)^2)`
+    ///
+    /// -eq
+    #[inline]
+    fn ne_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        self.eq_backward_lhs(lhs, rhs, res, grad, rhs_sum_grad);
+    }
+    /// `le(a,b) = 1 / (1 + e^(k(a - b)))`
+    ///
+    /// $$\frac{\partial \text{Lt}_{\text{sigmoid}}}{\partial a} = -k \cdot \sigma(-k(a - b))(1 - \sigma(-k(a - b)))$$
+    #[inline]
+    fn le_backward_lhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        lhs_sum_grad: &mut f64,
+    ) {
+        let sigma = 1.0 / (1.0 + (self.k.get() * (lhs - rhs)).exp());
+        *lhs_sum_grad -= grad * self.k.get() * sigma * (1.0 - sigma);
+    }
+    /// `le(a,b) = 1 / (1 + e^(k(a - b)))`
+    ///
+    /// $$\frac{\partial \text{Lt}_{\text{sigmoid}}}{\partial b} = k \cdot \sigma(-k(a - b))(1 - \sigma(-k(a - b)))$$
+    #[inline]
+    fn le_backward_rhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        rhs_sum_grad: &mut f64,
+    ) {
+        let sigma = 1.0 / (1.0 + (self.k.get() * (lhs - rhs)).exp());
+        *rhs_sum_grad += grad * self.k.get() * sigma * (1.0 - sigma);
+    }
+    /// `lt(a,b) = 1 / (1 + e^(k(a - b)))`
+    fn lt_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        self.le_backward_lhs(lhs, rhs, res, grad, lhs_sum_grad);
+    }
+    /// `lt(a,b) = 1 / (1 + e^(k(a - b)))`
+    fn lt_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        self.le_backward_rhs(lhs, rhs, res, grad, rhs_sum_grad);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GradMethodTanh {
+    k: SharpnessHandle,
+}
+impl GradMethodT for GradMethodTanh {
+    /// `eq(a,b) = 1 - tanh²(k(a - b))`
+    ///
+    /// $$ \frac{\partial \text{Eq}_{\text{tanh}}}{\partial a} = -2k \tanh(k(a - b)) (1 - \tanh^2(k(a - b))) $$
+    #[inline]
+    fn eq_backward_lhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        lhs_sum_grad: &mut f64,
+    ) {
+        let t = (self.k.get() * (lhs - rhs)).tanh();
+        *lhs_sum_grad -= grad * 2.0 * self.k.get() * t * (1.0 - t * t);
+    }
+    /// `eq(a,b) = 1 - tanh²(k(a - b))`
+    ///
+    /// $$ \frac{\partial \text{Eq}_{\text{tanh}}}{\partial b} = 2k \tanh(k(a - b)) (1 - \tanh^2(k(a - b))) $$
+    #[inline]
+    fn eq_backward_rhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        rhs_sum_grad: &mut f64,
+    ) {
+        let t = (self.k.get() * (lhs - rhs)).tanh();
+        *rhs_sum_grad += grad * 2.0 * self.k.get() * t * (1.0 - t * t);
+    }
+    /// `ne(a,b) = tanh²(k(a - b))`
+    ///
+    /// -eq
+    #[inline]
+    fn ne_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        self.eq_backward_rhs(lhs, rhs, res, grad, lhs_sum_grad);
+    }
+    /// `ne(a,b) = tanh²(k(a - b))`
+    ///
+    /// -eq
+    #[inline]
+    fn ne_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        self.eq_backward_lhs(lhs, rhs, res, grad, rhs_sum_grad);
+    }
+    /// `le(a,b) = (1 - tanh(k(a - b))) / 2`
+    ///
+    /// $$ \frac{\partial \text{Lt}_{\text{tanh}}}{\partial a} = -\frac{k}{2} (1 - \tanh^2(k(a - b))) $$
+    #[inline]
+    fn le_backward_lhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        lhs_sum_grad: &mut f64,
+    ) {
+        let t = (self.k.get() * (lhs - rhs)).tanh();
+        *lhs_sum_grad -= grad * 0.5 * self.k.get() * (1.0 - t * t);
+    }
+    /// `le(a,b) = (1 - tanh(k(a - b))) / 2`
+    ///
+    /// $$ \frac{\partial \text{Lt}_{\text{tanh}}}{\partial b} = \frac{k}{2} (1 - \tanh^2(k(a - b))) $$
+    #[inline]
+    fn le_backward_rhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        rhs_sum_grad: &mut f64,
+    ) {
+        let t = (self.k.get() * (lhs - rhs)).tanh();
+        *rhs_sum_grad += grad * 0.5 * self.k.get() * (1.0 - t * t);
+    }
+    /// `lt(a,b) = (1 - tanh(k(a - b))) / 2`
+    fn lt_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        self.le_backward_lhs(lhs, rhs, res, grad, lhs_sum_grad);
+    }
+    /// `lt(a,b) = (1 - tanh(k(a - b))) / 2`
+    fn lt_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        self.le_backward_rhs(lhs, rhs, res, grad, rhs_sum_grad);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GradMethodSmoothStep {
+    epsilon: SharpnessHandle,
+}
+impl GradMethodSmoothStep {
+    /// `d/dx (3t² - 2t³) = 6t(1 - t)`, `t = clamp(x, 0, 1)`
+    #[inline]
+    fn ramp_derivative(t: f64) -> f64 {
+        if t > 0.0 && t < 1.0 {
+            6.0 * t * (1.0 - t)
+        } else {
+            0.0
+        }
+    }
+}
+impl GradMethodT for GradMethodSmoothStep {
+    /// `1 - (3u² - 2u³)`, `u = |a - b|/ε`    when  `|a - b| < ε`
+    /// ``` text
+    ///                1
+    ///       _-_       
+    ///      /   \
+    /// ___-'     '-___  0
+    /// --------------->
+    ///   -ε  0  ε     a-b
+    /// ```
+    /// **only activate when graident is required!**
+    ///
+    /// $$
+    /// \frac{\partial \text{Eq}_{\text{smoothstep}}}{\partial a} = \begin{cases}
+    /// -\frac{6u(1-u)\,\text{sign}(a - b)}{\epsilon} & \text{if } |a - b| < \epsilon \\
+    /// 0 & \text{otherwise}
+    /// \end{cases}
+    /// $$
+    #[inline]
+    fn eq_backward_lhs(
+        &self,
+        lhs: &f64,
+        rhs: &f64,
+        _res: &f64,
+        grad: &f64,
+        lhs_sum_grad: &mut f64,
+    ) {
+        let diff = lhs - rhs;
+        let u = diff.abs() / self.epsilon.get();
+        *lhs_sum_grad -= grad * diff.signum() * Self::ramp_derivative(u) / self.epsilon.get();
+    }
+    /// `1 - (3u² - 2u³)`, `u = |a - b|/ε`    when  `|a - b| < ε`
+    ///
+    /// **only activate when graident is required!**
+    ///
+    /// $$
+    /// \frac{\partial \text{Eq}_{\text{smoothstep}}}{\partial b} = \begin{cases}
+    /// \frac{6u(1-u)\,\text{sign}(a - b)}{\epsilon} & \text{if } |a - b| < \epsilon \\
+    /// 0 & \text{otherwise}
+    /// \end{cases}
+    /// $$
     #[inline]
     fn eq_backward_rhs(
         &self,
@@ -1217,26 +5358,40 @@ impl GradMethodT for GradMethodSigmoid {
         rhs_sum_grad: &mut f64,
     ) {
         let diff = lhs - rhs;
-        let kdiff = self.k * diff;
-        *rhs_sum_grad += grad * 2.0 * kdiff * ((-kdiff * diff).exp());
+        let u = diff.abs() / self.epsilon.get();
+        *rhs_sum_grad += grad * diff.signum() * Self::ramp_derivative(u) / self.epsilon.get();
     }
-    /// `ne(a,b) = 1- sigmoid(a, b, k) = 1-e^(-k (a - b)^2)`
+    /// `3u² - 2u³`, `u = |a - b|/ε`    when  `|a - b| < ε`
     ///
     /// -eq
     #[inline]
     fn ne_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
         self.eq_backward_rhs(lhs, rhs, res, grad, lhs_sum_grad);
     }
-    /// `ne(a,b) = 1- sigmoid(a, b, k) = 1-e^(-k (a - b)^2)`
+    /// `3u² - 2u³`, `u = |a - b|/ε`    when  `|a - b| < ε`
     ///
     /// -eq
     #[inline]
     fn ne_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
         self.eq_backward_lhs(lhs, rhs, res, grad, rhs_sum_grad);
     }
-    /// `le(a,b) = 1 / (1 + e^(k(a - b)))`
+    /// `1 - (3t² - 2t³)`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    /// ``` text
+    /// ___            1
+    ///    '-.          
+    ///       \
+    ///        '-.___  0
+    /// --------------->
+    ///   -ε  0  ε     a-b
+    /// ```
+    /// **only activate when graident is required!**
     ///
-    /// $$\frac{\partial \text{Lt}_{\text{sigmoid}}}{\partial a} = -k \cdot \sigma(-k(a - b))(1 - \sigma(-k(a - b)))$$
+    /// $$
+    /// \frac{\partial \text{Lt}_{\text{smoothstep}}}{\partial a} = \begin{cases}
+    /// -\frac{3t(1-t)}{\epsilon} & \text{if } |a - b| < \epsilon \\
+    /// 0 & \text{otherwise}
+    /// \end{cases}
+    /// $$
     #[inline]
     fn le_backward_lhs(
         &self,
@@ -1246,12 +5401,19 @@ impl GradMethodT for GradMethodSigmoid {
         grad: &f64,
         lhs_sum_grad: &mut f64,
     ) {
-        let sigma = 1.0 / (1.0 + (self.k * (lhs - rhs)).exp());
-        *lhs_sum_grad -= grad * self.k * sigma * (1.0 - sigma);
+        let t = (lhs - rhs + self.epsilon.get()) / (2.0 * self.epsilon.get());
+        *lhs_sum_grad -= grad * Self::ramp_derivative(t) / (2.0 * self.epsilon.get());
     }
-    /// `le(a,b) = 1 / (1 + e^(k(a - b)))`
+    /// `1 - (3t² - 2t³)`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
     ///
-    /// $$\frac{\partial \text{Lt}_{\text{sigmoid}}}{\partial b} = k \cdot \sigma(-k(a - b))(1 - \sigma(-k(a - b)))$$
+    /// **only activate when graident is required!**
+    ///
+    /// $$
+    /// \frac{\partial \text{Lt}_{\text{smoothstep}}}{\partial b} = \begin{cases}
+    /// \frac{3t(1-t)}{\epsilon} & \text{if } |a - b| < \epsilon \\
+    /// 0 & \text{otherwise}
+    /// \end{cases}
+    /// $$
     #[inline]
     fn le_backward_rhs(
         &self,
@@ -1261,14 +5423,12 @@ impl GradMethodT for GradMethodSigmoid {
         grad: &f64,
         rhs_sum_grad: &mut f64,
     ) {
-        let sigma = 1.0 / (1.0 + (self.k * (lhs - rhs)).exp());
-        *rhs_sum_grad += grad * self.k * sigma * (1.0 - sigma);
+        let t = (lhs - rhs + self.epsilon.get()) / (2.0 * self.epsilon.get());
+        *rhs_sum_grad += grad * Self::ramp_derivative(t) / (2.0 * self.epsilon.get());
     }
-    /// `lt(a,b) = 1 / (1 + e^(k(a - b)))`
     fn lt_backward_lhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
         self.le_backward_lhs(lhs, rhs, res, grad, lhs_sum_grad);
     }
-    /// `lt(a,b) = 1 / (1 + e^(k(a - b)))`
     fn lt_backward_rhs(&self, lhs: &f64, rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
         self.le_backward_rhs(lhs, rhs, res, grad, rhs_sum_grad);
     }
@@ -1324,6 +5484,43 @@ pub(super) struct Lt;
 pub(super) struct Gt;
 
 impl Expression {
+    /// Generic comparison, with the [`GradMethod`] chosen at runtime
+    ///
+    /// **GradMethod only activate when graident is required!**
+    ///
+    /// The forward value follows [`set_smooth_forward`] whether the sides are constants or
+    /// tensors, so folding the constants gives the same value: two constants fold to the
+    /// smooth value of the method only under [`SmoothForward::Always`], to the hard `0`/`1` otherwise
+    #[inline]
+    pub fn cmp(&self, rhs: &Self, op: DiscreteBinaryOp, grad_method: GradMethod) -> Self {
+        match op {
+            DiscreteBinaryOp::Eq => self.discrete_binary_op::<Eq>(rhs, grad_method),
+            DiscreteBinaryOp::Ne => self.discrete_binary_op::<Ne>(rhs, grad_method),
+            DiscreteBinaryOp::Le => self.discrete_binary_op::<Le>(rhs, grad_method),
+            DiscreteBinaryOp::Ge => self.discrete_binary_op::<Ge>(rhs, grad_method),
+            DiscreteBinaryOp::Lt => self.discrete_binary_op::<Lt>(rhs, grad_method),
+            DiscreteBinaryOp::Gt => self.discrete_binary_op::<Gt>(rhs, grad_method),
+        }
+    }
+    /// Fallible [`cmp`](Expression::cmp)
+    ///
+    /// [`Error::LengthMismatch`] when both sides are tensors with different lengths,
+    /// and neither is length-1
+    #[inline]
+    pub fn try_cmp(
+        &self,
+        rhs: &Self,
+        op: DiscreteBinaryOp,
+        grad_method: GradMethod,
+    ) -> Result<Self, Error> {
+        if let (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) = (self, rhs) {
+            broadcast_len(
+                &format!("{op:?}"),
+                [lhs_tensor.read().len(), rhs_tensor.read().len()],
+            )?;
+        }
+        Ok(self.cmp(rhs, op, grad_method))
+    }
     #[inline]
     pub fn eq(&self, rhs: &Self) -> Self {
         self.discrete_binary_op::<Eq>(rhs, GradMethod::Discrete)
@@ -1390,6 +5587,78 @@ impl Expression {
     pub fn gt_sigmoid(&self, rhs: &Self, k: f64) -> Self {
         self.discrete_binary_op::<Gt>(rhs, GradMethod::new_sigmoid(k))
     }
+    /// Fallible [`eq_sigmoid`](Expression::eq_sigmoid),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_eq_sigmoid(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Eq, GradMethod::try_sigmoid(k)?)
+    }
+    /// Fallible [`ne_sigmoid`](Expression::ne_sigmoid),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ne_sigmoid(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Ne, GradMethod::try_sigmoid(k)?)
+    }
+    /// Fallible [`le_sigmoid`](Expression::le_sigmoid),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_le_sigmoid(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Le, GradMethod::try_sigmoid(k)?)
+    }
+    /// Fallible [`ge_sigmoid`](Expression::ge_sigmoid),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ge_sigmoid(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Ge, GradMethod::try_sigmoid(k)?)
+    }
+    /// Fallible [`lt_sigmoid`](Expression::lt_sigmoid),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_lt_sigmoid(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Lt, GradMethod::try_sigmoid(k)?)
+    }
+    /// Fallible [`gt_sigmoid`](Expression::gt_sigmoid),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_gt_sigmoid(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Gt, GradMethod::try_sigmoid(k)?)
+    }
+    /// [`eq_sigmoid`](Expression::eq_sigmoid) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn eq_sigmoid_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_sigmoid_annealed(k))
+    }
+    /// [`ne_sigmoid`](Expression::ne_sigmoid) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn ne_sigmoid_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_sigmoid_annealed(k))
+    }
+    /// [`le_sigmoid`](Expression::le_sigmoid) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn le_sigmoid_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Le>(rhs, GradMethod::new_sigmoid_annealed(k))
+    }
+    /// [`ge_sigmoid`](Expression::ge_sigmoid) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn ge_sigmoid_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_sigmoid_annealed(k))
+    }
+    /// [`lt_sigmoid`](Expression::lt_sigmoid) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn lt_sigmoid_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_sigmoid_annealed(k))
+    }
+    /// [`gt_sigmoid`](Expression::gt_sigmoid) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn gt_sigmoid_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_sigmoid_annealed(k))
+    }
     /// `1 - |a - b|/ε`    when  `|a - b| < ε`
     /// ``` text
     ///                1
@@ -1401,78 +5670,414 @@ impl Expression {
     /// ```
     /// **only activate when graident is required!**
     #[inline]
-    pub fn eq_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_linear(epsilon))
+    pub fn eq_linear(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_linear(epsilon))
+    }
+    /// |`a - b|/ε`    when  `|a - b| < ε`
+    /// ``` text
+    /// ___      ____    1
+    ///    \    /        
+    ///     \  /
+    ///      \/          0
+    /// --------------->
+    ///   -ε  0  ε     a-b
+    /// ```
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ne_linear(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_linear(epsilon))
+    }
+    /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
+    /// ``` text
+    /// ____           1
+    ///     \          
+    ///       \
+    ///         \___   0
+    /// --------------->
+    ///   -ε  0  ε     a-b
+    /// ```
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn le_linear(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Le>(rhs, GradMethod::new_linear(epsilon))
+    }
+    /// `1/2 + (a-b)/2ε`    when  `|a - b| < ε`
+    /// ``` text
+    ///          ____  1
+    ///         /      
+    ///       /
+    /// ____/          0
+    /// --------------->
+    ///   -ε  0  ε     a-b
+    /// ```
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ge_linear(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_linear(epsilon))
+    }
+    /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
+    /// ``` text
+    /// ____           1
+    ///     \          
+    ///       \
+    ///         \___   0
+    /// --------------->
+    ///   -ε  0  ε     a-b
+    /// ```
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn lt_linear(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_linear(epsilon))
+    }
+    /// `1/2 + (a-b)/2ε`    when  `|a - b| < ε`
+    /// ``` text
+    ///          ____  1
+    ///         /      
+    ///       /
+    /// ____/          0
+    /// --------------->
+    ///   -ε  0  ε     a-b
+    /// ```
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn gt_linear(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_linear(epsilon))
+    }
+    /// Fallible [`eq_linear`](Expression::eq_linear),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_eq_linear(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Eq, GradMethod::try_linear(epsilon)?)
+    }
+    /// Fallible [`ne_linear`](Expression::ne_linear),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ne_linear(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Ne, GradMethod::try_linear(epsilon)?)
+    }
+    /// Fallible [`le_linear`](Expression::le_linear),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_le_linear(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Le, GradMethod::try_linear(epsilon)?)
+    }
+    /// Fallible [`ge_linear`](Expression::ge_linear),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ge_linear(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Ge, GradMethod::try_linear(epsilon)?)
+    }
+    /// Fallible [`lt_linear`](Expression::lt_linear),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_lt_linear(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Lt, GradMethod::try_linear(epsilon)?)
+    }
+    /// Fallible [`gt_linear`](Expression::gt_linear),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_gt_linear(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Gt, GradMethod::try_linear(epsilon)?)
+    }
+    /// [`eq_linear`](Expression::eq_linear) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn eq_linear_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_linear_annealed(epsilon))
+    }
+    /// [`ne_linear`](Expression::ne_linear) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn ne_linear_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_linear_annealed(epsilon))
+    }
+    /// [`le_linear`](Expression::le_linear) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn le_linear_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Le>(rhs, GradMethod::new_linear_annealed(epsilon))
+    }
+    /// [`ge_linear`](Expression::ge_linear) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn ge_linear_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_linear_annealed(epsilon))
+    }
+    /// [`lt_linear`](Expression::lt_linear) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn lt_linear_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_linear_annealed(epsilon))
+    }
+    /// [`gt_linear`](Expression::gt_linear) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn gt_linear_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_linear_annealed(epsilon))
+    }
+    /// `eq(a,b) = 1 - tanh²(k(a - b))`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn eq_tanh(&self, rhs: &Self, k: f64) -> Self {
+        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_tanh(k))
+    }
+    /// `ne(a,b) = tanh²(k(a - b))`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ne_tanh(&self, rhs: &Self, k: f64) -> Self {
+        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_tanh(k))
+    }
+    /// `le(a,b) = (1 - tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn le_tanh(&self, rhs: &Self, k: f64) -> Self {
+        self.discrete_binary_op::<Le>(rhs, GradMethod::new_tanh(k))
+    }
+    /// `ge(a,b) = (1 + tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ge_tanh(&self, rhs: &Self, k: f64) -> Self {
+        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_tanh(k))
+    }
+    /// `lt(a,b) = (1 - tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn lt_tanh(&self, rhs: &Self, k: f64) -> Self {
+        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_tanh(k))
+    }
+    /// `gt(a,b) = (1 + tanh(k(a - b))) / 2`
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn gt_tanh(&self, rhs: &Self, k: f64) -> Self {
+        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_tanh(k))
+    }
+    /// Fallible [`eq_tanh`](Expression::eq_tanh),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_eq_tanh(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Eq, GradMethod::try_tanh(k)?)
+    }
+    /// Fallible [`ne_tanh`](Expression::ne_tanh),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ne_tanh(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Ne, GradMethod::try_tanh(k)?)
+    }
+    /// Fallible [`le_tanh`](Expression::le_tanh),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_le_tanh(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Le, GradMethod::try_tanh(k)?)
+    }
+    /// Fallible [`ge_tanh`](Expression::ge_tanh),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ge_tanh(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Ge, GradMethod::try_tanh(k)?)
+    }
+    /// Fallible [`lt_tanh`](Expression::lt_tanh),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_lt_tanh(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Lt, GradMethod::try_tanh(k)?)
+    }
+    /// Fallible [`gt_tanh`](Expression::gt_tanh),
+    /// [`Error::InvalidSharpness`] unless `k` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_gt_tanh(&self, rhs: &Self, k: f64) -> Result<Self, Error> {
+        self.try_cmp(rhs, DiscreteBinaryOp::Gt, GradMethod::try_tanh(k)?)
+    }
+    /// [`eq_tanh`](Expression::eq_tanh) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn eq_tanh_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_tanh_annealed(k))
+    }
+    /// [`ne_tanh`](Expression::ne_tanh) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn ne_tanh_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_tanh_annealed(k))
+    }
+    /// [`le_tanh`](Expression::le_tanh) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn le_tanh_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Le>(rhs, GradMethod::new_tanh_annealed(k))
+    }
+    /// [`ge_tanh`](Expression::ge_tanh) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn ge_tanh_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_tanh_annealed(k))
+    }
+    /// [`lt_tanh`](Expression::lt_tanh) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn lt_tanh_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_tanh_annealed(k))
+    }
+    /// [`gt_tanh`](Expression::gt_tanh) with a shared `k`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn gt_tanh_annealed(&self, rhs: &Self, k: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_tanh_annealed(k))
+    }
+    /// `1 - (3u² - 2u³)`, `u = |a - b|/ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn eq_smoothstep(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_smoothstep(epsilon))
+    }
+    /// `3u² - 2u³`, `u = |a - b|/ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ne_smoothstep(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_smoothstep(epsilon))
+    }
+    /// `1 - (3t² - 2t³)`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn le_smoothstep(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Le>(rhs, GradMethod::new_smoothstep(epsilon))
+    }
+    /// `3t² - 2t³`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn ge_smoothstep(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_smoothstep(epsilon))
+    }
+    /// `1 - (3t² - 2t³)`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn lt_smoothstep(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_smoothstep(epsilon))
+    }
+    /// `3t² - 2t³`, `t = (a - b + ε)/2ε`    when  `|a - b| < ε`
+    ///
+    /// C1 continuous, exactly `0`/`1` outside the band
+    ///
+    /// **only activate when graident is required!**
+    #[inline]
+    pub fn gt_smoothstep(&self, rhs: &Self, epsilon: f64) -> Self {
+        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_smoothstep(epsilon))
+    }
+    /// Fallible [`eq_smoothstep`](Expression::eq_smoothstep),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_eq_smoothstep(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(
+            rhs,
+            DiscreteBinaryOp::Eq,
+            GradMethod::try_smoothstep(epsilon)?,
+        )
+    }
+    /// Fallible [`ne_smoothstep`](Expression::ne_smoothstep),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ne_smoothstep(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(
+            rhs,
+            DiscreteBinaryOp::Ne,
+            GradMethod::try_smoothstep(epsilon)?,
+        )
+    }
+    /// Fallible [`le_smoothstep`](Expression::le_smoothstep),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_le_smoothstep(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(
+            rhs,
+            DiscreteBinaryOp::Le,
+            GradMethod::try_smoothstep(epsilon)?,
+        )
+    }
+    /// Fallible [`ge_smoothstep`](Expression::ge_smoothstep),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_ge_smoothstep(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(
+            rhs,
+            DiscreteBinaryOp::Ge,
+            GradMethod::try_smoothstep(epsilon)?,
+        )
+    }
+    /// Fallible [`lt_smoothstep`](Expression::lt_smoothstep),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_lt_smoothstep(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(
+            rhs,
+            DiscreteBinaryOp::Lt,
+            GradMethod::try_smoothstep(epsilon)?,
+        )
+    }
+    /// Fallible [`gt_smoothstep`](Expression::gt_smoothstep),
+    /// [`Error::InvalidSharpness`] unless `epsilon` is positive and finite, see [`try_cmp`](Expression::try_cmp)
+    #[inline]
+    pub fn try_gt_smoothstep(&self, rhs: &Self, epsilon: f64) -> Result<Self, Error> {
+        self.try_cmp(
+            rhs,
+            DiscreteBinaryOp::Gt,
+            GradMethod::try_smoothstep(epsilon)?,
+        )
     }
-    /// |`a - b|/ε`    when  `|a - b| < ε`
-    /// ``` text
-    /// ___      ____    1
-    ///    \    /        
-    ///     \  /
-    ///      \/          0
-    /// --------------->
-    ///   -ε  0  ε     a-b
-    /// ```
-    /// **only activate when graident is required!**
+    /// [`eq_smoothstep`](Expression::eq_smoothstep) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
     #[inline]
-    pub fn ne_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_linear(epsilon))
+    pub fn eq_smoothstep_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Eq>(rhs, GradMethod::new_smoothstep_annealed(epsilon))
     }
-    /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
-    /// ``` text
-    /// ____           1
-    ///     \          
-    ///       \
-    ///         \___   0
-    /// --------------->
-    ///   -ε  0  ε     a-b
-    /// ```
-    /// **only activate when graident is required!**
+    /// [`ne_smoothstep`](Expression::ne_smoothstep) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
     #[inline]
-    pub fn le_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Le>(rhs, GradMethod::new_linear(epsilon))
+    pub fn ne_smoothstep_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ne>(rhs, GradMethod::new_smoothstep_annealed(epsilon))
     }
-    /// `1/2 + (a-b)/2ε`    when  `|a - b| < ε`
-    /// ``` text
-    ///          ____  1
-    ///         /      
-    ///       /
-    /// ____/          0
-    /// --------------->
-    ///   -ε  0  ε     a-b
-    /// ```
-    /// **only activate when graident is required!**
+    /// [`le_smoothstep`](Expression::le_smoothstep) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
     #[inline]
-    pub fn ge_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_linear(epsilon))
+    pub fn le_smoothstep_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Le>(rhs, GradMethod::new_smoothstep_annealed(epsilon))
     }
-    /// `1/2 - (a-b)/2ε`    when  `|a - b| < ε`
-    /// ``` text
-    /// ____           1
-    ///     \          
-    ///       \
-    ///         \___   0
-    /// --------------->
-    ///   -ε  0  ε     a-b
-    /// ```
-    /// **only activate when graident is required!**
+    /// [`ge_smoothstep`](Expression::ge_smoothstep) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
     #[inline]
-    pub fn lt_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_linear(epsilon))
+    pub fn ge_smoothstep_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Ge>(rhs, GradMethod::new_smoothstep_annealed(epsilon))
     }
-    /// `1/2 + (a-b)/2ε`    when  `|a - b| < ε`
-    /// ``` text
-    ///          ____  1
-    ///         /      
-    ///       /
-    /// ____/          0
-    /// --------------->
-    ///   -ε  0  ε     a-b
-    /// ```
-    /// **only activate when graident is required!**
+    /// [`lt_smoothstep`](Expression::lt_smoothstep) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
     #[inline]
-    pub fn gt_linear(&self, rhs: &Self, epsilon: f64) -> Self {
-        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_linear(epsilon))
+    pub fn lt_smoothstep_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Lt>(rhs, GradMethod::new_smoothstep_annealed(epsilon))
+    }
+    /// [`gt_smoothstep`](Expression::gt_smoothstep) with a shared `epsilon`,
+    /// which can be changed later by [`SharpnessHandle::set`]
+    #[inline]
+    pub fn gt_smoothstep_annealed(&self, rhs: &Self, epsilon: &SharpnessHandle) -> Self {
+        self.discrete_binary_op::<Gt>(rhs, GradMethod::new_smoothstep_annealed(epsilon))
     }
 }
 
@@ -1484,18 +6089,43 @@ impl Expression {
         rhs: &Self,
         grad_method: GradMethod,
     ) -> Self {
+        if is_lazy_build()
+            && [self, rhs]
+                .iter()
+                .any(|expr| matches!(expr, Self::Tensor(_)))
+        {
+            let mut lens = Vec::with_capacity(2);
+            let mut with_grad = false;
+            for tensor in [self, rhs].into_iter().filter_map(|expr| match expr {
+                Self::Tensor(tensor) => Some(tensor),
+                Self::Const(_) => None,
+            }) {
+                T::debug_assertions(tensor);
+                lens.push(tensor.values_len());
+                with_grad |= tensor.with_grad();
+            }
+            let len =
+                broadcast_len(&format!("{:?}", T::OP), lens).unwrap_or_else(|e| panic!("{e}"));
+            return Self::Tensor(T::debug_mark(Tensor::new_lazy(
+                with_grad,
+                len,
+                Op::DiscreteBinary(self.clone(), rhs.clone(), T::OP, grad_method),
+            )));
+        }
         match (self, rhs) {
-            (Self::Const(lhs_x), Self::Const(rhs_x)) => Self::Const(T::forward(*lhs_x, *rhs_x)),
+            (Self::Const(lhs_x), Self::Const(rhs_x)) => {
+                Self::Const(if grad_method.is_smooth_forward() {
+                    grad_method.smooth(T::OP, *lhs_x, *rhs_x)
+                } else {
+                    T::forward(*lhs_x, *rhs_x)
+                })
+            }
             (Self::Const(lhs_x), Self::Tensor(rhs_tensor)) => {
                 T::debug_assertions(rhs_tensor);
-                let grad_id = if rhs_tensor.with_grad() {
-                    Some(GradId::new())
-                } else {
-                    None
-                };
+                let with_grad = rhs_tensor.with_grad();
                 Self::Tensor(T::debug_mark(Tensor::new(
-                    grad_id,
-                    T::forward_iter_fix_lhs(*lhs_x, rhs_tensor.values().read().unwrap().iter()),
+                    with_grad,
+                    T::OP.forward_iter_fix_lhs(&grad_method, *lhs_x, rhs_tensor.read().iter()),
                     Op::DiscreteBinary(
                         Self::Const(*lhs_x),
                         Self::Tensor(rhs_tensor.clone()),
@@ -1506,14 +6136,10 @@ impl Expression {
             }
             (Self::Tensor(lhs_tensor), Self::Const(rhs_x)) => {
                 T::debug_assertions(lhs_tensor);
-                let grad_id = if lhs_tensor.with_grad() {
-                    Some(GradId::new())
-                } else {
-                    None
-                };
+                let with_grad = lhs_tensor.with_grad();
                 Self::Tensor(T::debug_mark(Tensor::new(
-                    grad_id,
-                    T::forward_iter_fix_rhs(*rhs_x, lhs_tensor.values().read().unwrap().iter()),
+                    with_grad,
+                    T::OP.forward_iter_fix_rhs(&grad_method, *rhs_x, lhs_tensor.read().iter()),
                     Op::DiscreteBinary(
                         Self::Tensor(lhs_tensor.clone()),
                         Self::Const(*rhs_x),
@@ -1525,17 +6151,18 @@ impl Expression {
             (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) => {
                 T::debug_assertions(lhs_tensor);
                 T::debug_assertions(rhs_tensor);
-                let grad_id = if lhs_tensor.with_grad() || rhs_tensor.with_grad() {
-                    Some(GradId::new())
-                } else {
-                    None
-                };
+                let with_grad = lhs_tensor.with_grad() || rhs_tensor.with_grad();
+                let (lhs_vec, rhs_vec) = (lhs_tensor.read(), rhs_tensor.read());
+                let len = broadcast_len(&format!("{:?}", T::OP), [lhs_vec.len(), rhs_vec.len()])
+                    .unwrap_or_else(|e| panic!("{e}"));
+                let values = T::OP.forward_iter(
+                    &grad_method,
+                    izip!(broadcast(&lhs_vec, len), broadcast(&rhs_vec, len)),
+                );
+                drop((lhs_vec, rhs_vec));
                 Self::Tensor(T::debug_mark(Tensor::new(
-                    grad_id,
-                    T::forward_iter(izip!(
-                        lhs_tensor.values().read().unwrap().iter(),
-                        rhs_tensor.values().read().unwrap().iter()
-                    )),
+                    with_grad,
+                    values,
                     Op::DiscreteBinary(
                         Self::Tensor(lhs_tensor.clone()),
                         Self::Tensor(rhs_tensor.clone()),
@@ -1552,6 +6179,46 @@ impl Expression {
 ///////////////////////////////////   BinaryOp   ///////////////////////////////////////////
 ////////////////////////////////////////////////////////////////////////////////////////////
 
+/// Gradient of [`min`](Expression::min_tie) / [`max`](Expression::max_tie) at the exact ties
+///
+/// A node tied with itself, e.g., `x.min(&x)` or both sides merged by
+/// [`GraphBuilder`](super::GraphBuilder), gets the whole gradient under every policy
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum TiePolicy {
+    /// Half to each side, the default
+    #[default]
+    Split,
+    /// All to the left-hand side, winner-takes-all
+    Lhs,
+    /// All to the right-hand side, winner-takes-all
+    Rhs,
+}
+
+const TIE_SPLIT: u8 = 0;
+const TIE_LHS: u8 = 1;
+const TIE_RHS: u8 = 2;
+
+impl TiePolicy {
+    /// The policy of the const parameter of `Min` and `Max`
+    #[inline]
+    const fn from_tag(tag: u8) -> Self {
+        match tag {
+            TIE_LHS => Self::Lhs,
+            TIE_RHS => Self::Rhs,
+            _ => Self::Split,
+        }
+    }
+    /// The share of the left-hand side at a tie, the right-hand side gets the rest
+    #[inline]
+    const fn lhs_share(self) -> f64 {
+        match self {
+            Self::Split => 0.5,
+            Self::Lhs => 1.0,
+            Self::Rhs => 0.0,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum BinaryOp {
     Add,
@@ -1559,10 +6226,14 @@ pub enum BinaryOp {
     Mul,
     Div,
     Pow,
-    Min,
-    Max,
+    Atan2,
+    Min(TiePolicy),
+    Max(TiePolicy),
     LogicAnd,
     LogicOr,
+    LogicXor,
+    LogicNand,
+    LogicNor,
 }
 
 trait BinaryOpT {
@@ -1648,6 +6319,108 @@ impl BinaryOpT for LogicOr {
     }
 }
 
+/// xor(a,b) = a+b - 2 * a * b
+struct LogicXor;
+impl BinaryOpT for LogicXor {
+    const OP: BinaryOp = BinaryOp::LogicXor;
+    #[inline]
+    fn debug_assertions(tensor: &Tensor) {
+        assert_logic_tensor!(tensor);
+    }
+    #[inline]
+    fn debug_mark(tensor: Tensor) -> Tensor {
+        mark_logic_tensor!(tensor)
+    }
+    #[inline]
+    fn forward_lhs_rhs(lhs: f64, rhs: f64) -> f64 {
+        assert_logic!(lhs);
+        assert_logic!(rhs);
+        lhs + rhs - 2.0 * lhs * rhs
+    }
+    #[inline]
+    fn forward_rhs_lhs(rhs: f64, lhs: f64) -> f64 {
+        assert_logic!(lhs);
+        assert_logic!(rhs);
+        lhs + rhs - 2.0 * lhs * rhs
+    }
+    #[inline]
+    fn backward_lhs(_lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        *lhs_sum_grad += grad * (1.0 - 2.0 * rhs);
+    }
+    #[inline]
+    fn backward_rhs(lhs: &f64, _rhs: &f64, _res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        *rhs_sum_grad += grad * (1.0 - 2.0 * lhs);
+    }
+}
+
+/// nand(a,b) = 1 - a * b
+struct LogicNand;
+impl BinaryOpT for LogicNand {
+    const OP: BinaryOp = BinaryOp::LogicNand;
+    #[inline]
+    fn debug_assertions(tensor: &Tensor) {
+        assert_logic_tensor!(tensor);
+    }
+    #[inline]
+    fn debug_mark(tensor: Tensor) -> Tensor {
+        mark_logic_tensor!(tensor)
+    }
+    #[inline]
+    fn forward_lhs_rhs(lhs: f64, rhs: f64) -> f64 {
+        assert_logic!(lhs);
+        assert_logic!(rhs);
+        1.0 - lhs * rhs
+    }
+    #[inline]
+    fn forward_rhs_lhs(rhs: f64, lhs: f64) -> f64 {
+        assert_logic!(lhs);
+        assert_logic!(rhs);
+        1.0 - lhs * rhs
+    }
+    #[inline]
+    fn backward_lhs(_lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        *lhs_sum_grad -= grad * rhs;
+    }
+    #[inline]
+    fn backward_rhs(lhs: &f64, _rhs: &f64, _res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        *rhs_sum_grad -= grad * lhs;
+    }
+}
+
+/// nor(a,b) = (1 - a) * (1 - b)
+struct LogicNor;
+impl BinaryOpT for LogicNor {
+    const OP: BinaryOp = BinaryOp::LogicNor;
+    #[inline]
+    fn debug_assertions(tensor: &Tensor) {
+        assert_logic_tensor!(tensor);
+    }
+    #[inline]
+    fn debug_mark(tensor: Tensor) -> Tensor {
+        mark_logic_tensor!(tensor)
+    }
+    #[inline]
+    fn forward_lhs_rhs(lhs: f64, rhs: f64) -> f64 {
+        assert_logic!(lhs);
+        assert_logic!(rhs);
+        (1.0 - lhs) * (1.0 - rhs)
+    }
+    #[inline]
+    fn forward_rhs_lhs(rhs: f64, lhs: f64) -> f64 {
+        assert_logic!(lhs);
+        assert_logic!(rhs);
+        (1.0 - lhs) * (1.0 - rhs)
+    }
+    #[inline]
+    fn backward_lhs(_lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        *lhs_sum_grad -= grad * (1.0 - rhs);
+    }
+    #[inline]
+    fn backward_rhs(lhs: &f64, _rhs: &f64, _res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        *rhs_sum_grad -= grad * (1.0 - lhs);
+    }
+}
+
 struct Add;
 impl BinaryOpT for Add {
     const OP: BinaryOp = BinaryOp::Add;
@@ -1775,21 +6548,61 @@ impl BinaryOpT for Pow {
     ///
     /// $\frac{\partial f}{\partial a} = \frac{\partial f}{\partial c} \cdot \frac{\partial c}{\partial a} = \frac{\partial f}{\partial c} \cdot b \cdot a^{b - 1}$
     ///
+    /// At $a = 0$: `0` when $b > 1$ or $b = 0$, `1` when $b = 1$,
+    /// and `inf` with the sign of $b$ otherwise
     #[inline]
-    fn backward_lhs(lhs: &f64, rhs: &f64, res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
-        *lhs_sum_grad += grad * rhs * res / lhs;
-        // *lhs_sum_grad += grad * rhs * lhs.powf(rhs - 1.0);
+    fn backward_lhs(lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        // `res / lhs` is NaN at `lhs = 0`, and `0 * 0^-1` for a constant `a^0`
+        if *rhs != 0.0 {
+            *lhs_sum_grad += grad * rhs * lhs.powf(rhs - 1.0);
+        }
     }
     /// $\frac{\partial f}{\partial b} = \frac{\partial f}{\partial c} \cdot \frac{\partial c}{\partial b} = \frac{\partial f}{\partial c} \cdot c \cdot \ln(a)$
+    ///
+    /// `0` where $a \le 0$, whose $\ln(a)$ is undefined,
+    /// reported by the [anomaly detection](super::set_anomaly_detection)
     #[inline]
     fn backward_rhs(lhs: &f64, _rhs: &f64, res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
-        *rhs_sum_grad += grad * res * (lhs.ln());
+        if *lhs > 0.0 {
+            *rhs_sum_grad += grad * res * lhs.ln();
+        }
+    }
+}
+
+struct Atan2;
+impl BinaryOpT for Atan2 {
+    const OP: BinaryOp = BinaryOp::Atan2;
+    #[inline]
+    fn forward_lhs_rhs(lhs: f64, rhs: f64) -> f64 {
+        lhs.atan2(rhs)
+    }
+    #[inline]
+    fn forward_rhs_lhs(rhs: f64, lhs: f64) -> f64 {
+        lhs.atan2(rhs)
+    }
+    /// $ c = \operatorname{atan2}(a, b) $
+    ///
+    /// $\frac{\partial c}{\partial a} = \frac{b}{a^2 + b^2}$, zero at the origin
+    #[inline]
+    fn backward_lhs(lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
+        let norm2 = lhs * lhs + rhs * rhs;
+        if norm2 > 0.0 {
+            *lhs_sum_grad += grad * rhs / norm2;
+        }
+    }
+    /// $\frac{\partial c}{\partial b} = \frac{-a}{a^2 + b^2}$, zero at the origin
+    #[inline]
+    fn backward_rhs(lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
+        let norm2 = lhs * lhs + rhs * rhs;
+        if norm2 > 0.0 {
+            *rhs_sum_grad -= grad * lhs / norm2;
+        }
     }
 }
 
-struct Min;
-impl BinaryOpT for Min {
-    const OP: BinaryOp = BinaryOp::Min;
+struct Min<const TIE: u8>;
+impl<const TIE: u8> BinaryOpT for Min<TIE> {
+    const OP: BinaryOp = BinaryOp::Min(TiePolicy::from_tag(TIE));
     #[inline]
     fn forward_lhs_rhs(lhs: f64, rhs: f64) -> f64 {
         lhs.min(rhs)
@@ -1800,28 +6613,26 @@ impl BinaryOpT for Min {
     }
     #[inline]
     fn backward_lhs(lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
-        // If both masks are 1 one the same point, we want to scale the
-        // gradient by 0.5 rather than 1.
+        // a tie is shared by the policy rather than given to both sides
         match OrderedFloat(*lhs).cmp(&OrderedFloat(*rhs)) {
             Ordering::Less => *lhs_sum_grad += grad,
-            Ordering::Equal => *lhs_sum_grad += grad / 2.0,
+            Ordering::Equal => *lhs_sum_grad += grad * TiePolicy::from_tag(TIE).lhs_share(),
             Ordering::Greater => (),
         }
     }
     #[inline]
     fn backward_rhs(lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
-        // If both masks are 1 one the same point, we want to scale the
-        // gradient by 0.5 rather than 1.
+        // a tie is shared by the policy rather than given to both sides
         match OrderedFloat(*rhs).cmp(&OrderedFloat(*lhs)) {
             Ordering::Less => *rhs_sum_grad += grad,
-            Ordering::Equal => *rhs_sum_grad += grad / 2.0,
+            Ordering::Equal => *rhs_sum_grad += grad * (1.0 - TiePolicy::from_tag(TIE).lhs_share()),
             Ordering::Greater => (),
         }
     }
 }
-struct Max;
-impl BinaryOpT for Max {
-    const OP: BinaryOp = BinaryOp::Max;
+struct Max<const TIE: u8>;
+impl<const TIE: u8> BinaryOpT for Max<TIE> {
+    const OP: BinaryOp = BinaryOp::Max(TiePolicy::from_tag(TIE));
     #[inline]
     fn forward_lhs_rhs(lhs: f64, rhs: f64) -> f64 {
         lhs.max(rhs)
@@ -1832,21 +6643,19 @@ impl BinaryOpT for Max {
     }
     #[inline]
     fn backward_lhs(lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, lhs_sum_grad: &mut f64) {
-        // If both masks are 1 one the same point, we want to scale the
-        // gradient by 0.5 rather than 1.
+        // a tie is shared by the policy rather than given to both sides
         match OrderedFloat(*lhs).cmp(&OrderedFloat(*rhs)) {
             Ordering::Less => (),
-            Ordering::Equal => *lhs_sum_grad += grad / 2.0,
+            Ordering::Equal => *lhs_sum_grad += grad * TiePolicy::from_tag(TIE).lhs_share(),
             Ordering::Greater => *lhs_sum_grad += grad,
         }
     }
     #[inline]
     fn backward_rhs(lhs: &f64, rhs: &f64, _res: &f64, grad: &f64, rhs_sum_grad: &mut f64) {
-        // If both masks are 1 one the same point, we want to scale the
-        // gradient by 0.5 rather than 1.
+        // a tie is shared by the policy rather than given to both sides
         match OrderedFloat(*rhs).cmp(&OrderedFloat(*lhs)) {
             Ordering::Less => (),
-            Ordering::Equal => *rhs_sum_grad += grad / 2.0,
+            Ordering::Equal => *rhs_sum_grad += grad * (1.0 - TiePolicy::from_tag(TIE).lhs_share()),
             Ordering::Greater => *rhs_sum_grad += grad,
         }
     }
@@ -1861,10 +6670,21 @@ impl BinaryOp {
             Self::Mul => [Mul::forward_lhs_rhs, Mul::forward_rhs_lhs],
             Self::Div => [Div::forward_lhs_rhs, Div::forward_rhs_lhs],
             Self::Pow => [Pow::forward_lhs_rhs, Pow::forward_rhs_lhs],
-            Self::Min => [Min::forward_lhs_rhs, Min::forward_rhs_lhs],
-            Self::Max => [Max::forward_lhs_rhs, Max::forward_rhs_lhs],
+            Self::Atan2 => [Atan2::forward_lhs_rhs, Atan2::forward_rhs_lhs],
+            // the forward does not depend on the policy
+            Self::Min(_) => [
+                Min::<TIE_SPLIT>::forward_lhs_rhs,
+                Min::<TIE_SPLIT>::forward_rhs_lhs,
+            ],
+            Self::Max(_) => [
+                Max::<TIE_SPLIT>::forward_lhs_rhs,
+                Max::<TIE_SPLIT>::forward_rhs_lhs,
+            ],
             Self::LogicAnd => [LogicAnd::forward_lhs_rhs, LogicAnd::forward_rhs_lhs],
             Self::LogicOr => [LogicOr::forward_lhs_rhs, LogicOr::forward_rhs_lhs],
+            Self::LogicXor => [LogicXor::forward_lhs_rhs, LogicXor::forward_rhs_lhs],
+            Self::LogicNand => [LogicNand::forward_lhs_rhs, LogicNand::forward_rhs_lhs],
+            Self::LogicNor => [LogicNor::forward_lhs_rhs, LogicNor::forward_rhs_lhs],
         }
     }
     #[inline]
@@ -1875,23 +6695,44 @@ impl BinaryOp {
             Self::Mul => [Mul::backward_lhs, Mul::backward_rhs],
             Self::Div => [Div::backward_lhs, Div::backward_rhs],
             Self::Pow => [Pow::backward_lhs, Pow::backward_rhs],
-            Self::Min => [Min::backward_lhs, Min::backward_rhs],
-            Self::Max => [Max::backward_lhs, Max::backward_rhs],
+            Self::Atan2 => [Atan2::backward_lhs, Atan2::backward_rhs],
+            Self::Min(TiePolicy::Split) => [
+                Min::<TIE_SPLIT>::backward_lhs,
+                Min::<TIE_SPLIT>::backward_rhs,
+            ],
+            Self::Min(TiePolicy::Lhs) => {
+                [Min::<TIE_LHS>::backward_lhs, Min::<TIE_LHS>::backward_rhs]
+            }
+            Self::Min(TiePolicy::Rhs) => {
+                [Min::<TIE_RHS>::backward_lhs, Min::<TIE_RHS>::backward_rhs]
+            }
+            Self::Max(TiePolicy::Split) => [
+                Max::<TIE_SPLIT>::backward_lhs,
+                Max::<TIE_SPLIT>::backward_rhs,
+            ],
+            Self::Max(TiePolicy::Lhs) => {
+                [Max::<TIE_LHS>::backward_lhs, Max::<TIE_LHS>::backward_rhs]
+            }
+            Self::Max(TiePolicy::Rhs) => {
+                [Max::<TIE_RHS>::backward_lhs, Max::<TIE_RHS>::backward_rhs]
+            }
             Self::LogicAnd => [LogicAnd::backward_lhs, LogicAnd::backward_rhs],
             Self::LogicOr => [LogicOr::backward_lhs, LogicOr::backward_rhs],
+            Self::LogicXor => [LogicXor::backward_lhs, LogicXor::backward_rhs],
+            Self::LogicNand => [LogicNand::backward_lhs, LogicNand::backward_rhs],
+            Self::LogicNor => [LogicNor::backward_lhs, LogicNor::backward_rhs],
         }
     }
 }
 
 impl Tensor {
     #[inline]
-    pub(super) fn iter_binary_op(&self, rhs: &Self, forward: fn(f64, f64) -> f64) -> Vec<f64> {
-        let self_vec = self.values().read().unwrap();
-        let rhs_vec = rhs.values().read().unwrap();
-        debug_assert_eq!(rhs_vec.len(), self_vec.len(), "tensor length mismatch!");
-        self_vec
-            .iter()
-            .zip(rhs_vec.iter())
+    pub(super) fn iter_binary_op(&self, rhs: &Self, forward: impl Fn(f64, f64) -> f64) -> Vec<f64> {
+        let self_vec = self.read();
+        let rhs_vec = rhs.read();
+        let len = broadcast_len("Binary", [self_vec.len(), rhs_vec.len()])
+            .unwrap_or_else(|e| panic!("{e}"));
+        izip!(broadcast(&self_vec, len), broadcast(&rhs_vec, len))
             .map(|(v1, v2)| forward(*v1, *v2))
             .collect()
     }
@@ -1899,23 +6740,20 @@ impl Tensor {
     pub(super) fn broadcast_iter_binary_op(
         &self,
         rhs: f64,
-        forward: fn(f64, f64) -> f64,
+        forward: impl Fn(f64, f64) -> f64,
     ) -> Vec<f64> {
-        self.values()
-            .read()
-            .unwrap()
-            .iter()
-            .map(|v| forward(*v, rhs))
-            .collect()
+        self.read().iter().map(|v| forward(*v, rhs)).collect()
     }
     #[inline]
-    pub(super) fn binary_op(&self, rhs: &Self, forward: fn(f64, f64) -> f64, op: Op) -> Self {
+    pub(super) fn binary_op(&self, rhs: &Self, forward: impl Fn(f64, f64) -> f64, op: Op) -> Self {
+        if is_lazy_build() {
+            let len = broadcast_len("Binary", [self.values_len(), rhs.values_len()])
+                .unwrap_or_else(|e| panic!("{e}"));
+            let with_grad = self.with_grad() || rhs.with_grad();
+            return Self::new_lazy(with_grad, len, op);
+        }
         Self::new(
-            if self.with_grad() || rhs.with_grad() {
-                Some(GradId::new())
-            } else {
-                None
-            },
+            self.with_grad() || rhs.with_grad(),
             self.iter_binary_op(rhs, forward),
             op,
         )
@@ -1924,15 +6762,15 @@ impl Tensor {
     pub(super) fn broadcast_binary_op(
         &self,
         rhs: f64,
-        forward: fn(f64, f64) -> f64,
+        forward: impl Fn(f64, f64) -> f64,
         op: Op,
     ) -> Self {
+        if is_lazy_build() {
+            let with_grad = self.with_grad();
+            return Self::new_lazy(with_grad, self.values_len(), op);
+        }
         Self::new(
-            if self.with_grad() {
-                Some(GradId::new())
-            } else {
-                None
-            },
+            self.with_grad(),
             self.broadcast_iter_binary_op(rhs, forward),
             op,
         )
@@ -1960,13 +6798,42 @@ impl Expression {
     pub fn pow(&self, rhs: &Self) -> Self {
         self.binary_op::<Pow>(rhs)
     }
+    /// Four-quadrant `atan2(self, rhs)`, the angle of the point `(rhs, self)` in `(-π, π]`
+    ///
+    /// The gradient is zero at the origin
+    #[inline]
+    pub fn atan2(&self, rhs: &Self) -> Self {
+        self.binary_op::<Atan2>(rhs)
+    }
+    /// [`min_tie`](Expression::min_tie) splitting the gradient of a tie
     #[inline]
     pub fn min(&self, rhs: &Self) -> Self {
-        self.binary_op::<Min>(rhs)
+        self.binary_op::<Min<TIE_SPLIT>>(rhs)
     }
+    /// [`max_tie`](Expression::max_tie) splitting the gradient of a tie
     #[inline]
     pub fn max(&self, rhs: &Self) -> Self {
-        self.binary_op::<Max>(rhs)
+        self.binary_op::<Max<TIE_SPLIT>>(rhs)
+    }
+    /// Element-wise minimum, the gradient goes to the smaller side,
+    /// and is shared by `tie` where both sides are equal
+    #[inline]
+    pub fn min_tie(&self, rhs: &Self, tie: TiePolicy) -> Self {
+        match tie {
+            TiePolicy::Split => self.binary_op::<Min<TIE_SPLIT>>(rhs),
+            TiePolicy::Lhs => self.binary_op::<Min<TIE_LHS>>(rhs),
+            TiePolicy::Rhs => self.binary_op::<Min<TIE_RHS>>(rhs),
+        }
+    }
+    /// Element-wise maximum, the gradient goes to the larger side,
+    /// and is shared by `tie` where both sides are equal
+    #[inline]
+    pub fn max_tie(&self, rhs: &Self, tie: TiePolicy) -> Self {
+        match tie {
+            TiePolicy::Split => self.binary_op::<Max<TIE_SPLIT>>(rhs),
+            TiePolicy::Lhs => self.binary_op::<Max<TIE_LHS>>(rhs),
+            TiePolicy::Rhs => self.binary_op::<Max<TIE_RHS>>(rhs),
+        }
     }
     #[inline]
     pub fn logic_and(&self, rhs: &Self) -> Self {
@@ -1976,11 +6843,111 @@ impl Expression {
     pub fn logic_or(&self, rhs: &Self) -> Self {
         self.binary_op::<LogicOr>(rhs)
     }
+    #[inline]
+    pub fn logic_xor(&self, rhs: &Self) -> Self {
+        self.binary_op::<LogicXor>(rhs)
+    }
+    #[inline]
+    pub fn logic_nand(&self, rhs: &Self) -> Self {
+        self.binary_op::<LogicNand>(rhs)
+    }
+    #[inline]
+    pub fn logic_nor(&self, rhs: &Self) -> Self {
+        self.binary_op::<LogicNor>(rhs)
+    }
+}
+
+impl Expression {
+    #[inline]
+    pub fn try_add(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Add>(rhs)
+    }
+    #[inline]
+    pub fn try_sub(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Sub>(rhs)
+    }
+    #[inline]
+    pub fn try_mul(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Mul>(rhs)
+    }
+    #[inline]
+    pub fn try_div(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Div>(rhs)
+    }
+    #[inline]
+    pub fn try_pow(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Pow>(rhs)
+    }
+    #[inline]
+    pub fn try_atan2(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Atan2>(rhs)
+    }
+    #[inline]
+    pub fn try_min(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Min<TIE_SPLIT>>(rhs)
+    }
+    #[inline]
+    pub fn try_max(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<Max<TIE_SPLIT>>(rhs)
+    }
+    #[inline]
+    pub fn try_logic_and(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<LogicAnd>(rhs)
+    }
+    #[inline]
+    pub fn try_logic_or(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<LogicOr>(rhs)
+    }
+    #[inline]
+    pub fn try_logic_xor(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<LogicXor>(rhs)
+    }
+    #[inline]
+    pub fn try_logic_nand(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<LogicNand>(rhs)
+    }
+    #[inline]
+    pub fn try_logic_nor(&self, rhs: &Self) -> Result<Self, Error> {
+        self.try_binary_op_impl::<LogicNor>(rhs)
+    }
+    /// Fallible binary op selected at runtime, e.g., `x.try_binary_op(&y, BinaryOp::Add)`
+    ///
+    /// [`Error::LengthMismatch`] when both sides are tensors with different lengths,
+    /// and neither is length-1
+    pub fn try_binary_op(&self, rhs: &Self, op: BinaryOp) -> Result<Self, Error> {
+        match op {
+            BinaryOp::Add => self.try_add(rhs),
+            BinaryOp::Sub => self.try_sub(rhs),
+            BinaryOp::Mul => self.try_mul(rhs),
+            BinaryOp::Div => self.try_div(rhs),
+            BinaryOp::Pow => self.try_pow(rhs),
+            BinaryOp::Atan2 => self.try_atan2(rhs),
+            BinaryOp::Min(TiePolicy::Split) => self.try_binary_op_impl::<Min<TIE_SPLIT>>(rhs),
+            BinaryOp::Min(TiePolicy::Lhs) => self.try_binary_op_impl::<Min<TIE_LHS>>(rhs),
+            BinaryOp::Min(TiePolicy::Rhs) => self.try_binary_op_impl::<Min<TIE_RHS>>(rhs),
+            BinaryOp::Max(TiePolicy::Split) => self.try_binary_op_impl::<Max<TIE_SPLIT>>(rhs),
+            BinaryOp::Max(TiePolicy::Lhs) => self.try_binary_op_impl::<Max<TIE_LHS>>(rhs),
+            BinaryOp::Max(TiePolicy::Rhs) => self.try_binary_op_impl::<Max<TIE_RHS>>(rhs),
+            BinaryOp::LogicAnd => self.try_logic_and(rhs),
+            BinaryOp::LogicOr => self.try_logic_or(rhs),
+            BinaryOp::LogicXor => self.try_logic_xor(rhs),
+            BinaryOp::LogicNand => self.try_logic_nand(rhs),
+            BinaryOp::LogicNor => self.try_logic_nor(rhs),
+        }
+    }
 }
+
 impl Expression {
     #[inline]
     fn binary_op<T: BinaryOpT>(&self, rhs: &Self) -> Self {
-        match (self, rhs) {
+        self.try_binary_op_impl::<T>(rhs)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// [`Error::LengthMismatch`] when both sides are tensors with different lengths,
+    /// and neither is length-1
+    #[inline]
+    fn try_binary_op_impl<T: BinaryOpT>(&self, rhs: &Self) -> Result<Self, Error> {
+        Ok(match (self, rhs) {
             (Self::Const(lhs_x), Self::Const(rhs_x)) => {
                 Self::Const(T::forward_lhs_rhs(*lhs_x, *rhs_x))
             }
@@ -2003,6 +6970,10 @@ impl Expression {
             (Self::Tensor(lhs_tensor), Self::Tensor(rhs_tensor)) => {
                 T::debug_assertions(lhs_tensor);
                 T::debug_assertions(rhs_tensor);
+                broadcast_len(
+                    &format!("{:?}", T::OP),
+                    [lhs_tensor.values_len(), rhs_tensor.values_len()],
+                )?;
                 Self::Tensor(T::debug_mark(lhs_tensor.binary_op(
                     rhs_tensor,
                     T::forward_lhs_rhs,
@@ -2013,6 +6984,6 @@ impl Expression {
                     ),
                 )))
             }
-        }
+        })
     }
 }
@@ -1,11 +1,758 @@
-enum Optimizer {
-    Linear { step: f64 },
-    Adam { step: f64 },
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
+
+use super::{autograd::GradId, before_update, Error, Expression, GradStore, TensorRef};
+
+mod resume;
+pub use resume::{Checkpoint, ParameterMap};
+
+static LOG_SUMMARY_EVERY: AtomicUsize = AtomicUsize::new(0);
+
+/// Log a summary every `n_iters` steps of the optimizers, `0` (by default) disables it
+///
+/// The summary is an info log of the target `gspice::optim`, with the step count,
+/// the loss (see [`GradStore::loss`]) and the L2 norm of the gradients
+#[inline]
+pub fn log_summary_every(n_iters: usize) {
+    LOG_SUMMARY_EVERY.store(n_iters, Relaxed);
+}
+
+/// Log the summary of the `step`-th step (starting from 1) when it is due,
+/// `grad_norm` is only evaluated then
+fn log_summary(optimizer: &str, step: usize, loss: Option<f64>, grad_norm: impl FnOnce() -> f64) {
+    let every = LOG_SUMMARY_EVERY.load(Relaxed);
+    if every != 0
+        && step.is_multiple_of(every)
+        && log::log_enabled!(target: "gspice::optim", log::Level::Info)
+    {
+        let loss = loss.map_or_else(|| "-".to_owned(), |loss| format!("{loss:.6e}"));
+        log::info!(
+            target: "gspice::optim",
+            "{optimizer} step {step}: loss {loss}, grad norm {:.6e}",
+            grad_norm()
+        );
+    }
+}
+
+/// L2 norm of the gradients of the groups' parameters in `grads`
+fn grad_norm(groups: &[ParamGroup], grads: &GradStore) -> f64 {
+    groups
+        .iter()
+        .flat_map(|group| &group.tensors)
+        .filter_map(|param| grads.get_by_id(param.grad_id()?))
+        .flat_map(|grad| grad.iter())
+        .map(|g| g * g)
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Gradient-descent optimizer over groups of parameters
+///
+/// The parameters are updated in place via [`TensorRef::update_projected`],
+/// i.e., projected into their [bounds](TensorRef::set_bounds) after each step,
+/// call [`Expression::value`](super::Expression::value) after [`step`](Optimizer::step)
+pub trait Optimizer {
+    /// Apply one update step with the gradients of [`Expression::backward`](super::Expression::backward)
+    ///
+    /// Calls [`before_update`] once, the parameters without gradient in `grads`
+    /// (or [frozen](TensorRef::set_requires_grad)) are skipped
+    fn step(&mut self, grads: &GradStore);
+    /// Reset the internal state (e.g., momentum buffers), keep the parameters
+    fn zero_state(&mut self);
+    /// Add a group of parameters with its own options
+    ///
+    /// ## Panics
+    ///
+    /// When any parameter is not with gradient
+    fn add_group(&mut self, group: ParamGroup);
+    fn groups(&self) -> &[ParamGroup];
+    /// Mutable groups, e.g., to change the learning rates
+    fn groups_mut(&mut self) -> &mut [ParamGroup];
+}
+
+/// Panics when a parameter is not with gradient
+fn check_with_grad(params: &[TensorRef]) {
+    if params.iter().any(|param| param.0.grad_id().is_none()) {
+        panic!("The tensor is not with gradient")
+    }
+}
+
+/// Parameters sharing the same optimizer options
+#[derive(Clone, Debug)]
+pub struct ParamGroup {
+    pub tensors: Vec<TensorRef>,
+    pub lr: f64,
+    /// L2 penalty, `grad += weight_decay * x`
+    pub weight_decay: f64,
+}
+
+impl ParamGroup {
+    #[inline]
+    pub fn new(tensors: Vec<TensorRef>, lr: f64) -> Self {
+        Self {
+            tensors,
+            lr,
+            weight_decay: 0.0,
+        }
+    }
+    #[inline]
+    pub fn with_weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+    /// Panics when a parameter is not with gradient
+    fn check(&self) {
+        check_with_grad(&self.tensors);
+    }
+    /// `(grad id, parameter, gradient with weight decay)` of the parameters with gradient in `grads`
+    fn grads<'a>(
+        &'a self,
+        grads: &'a GradStore,
+    ) -> impl Iterator<Item = (GradId, &'a TensorRef, Vec<f64>)> + 'a {
+        self.tensors.iter().filter_map(move |param| {
+            // frozen by `set_requires_grad(false)`
+            let grad_id = param.grad_id()?;
+            let grad = grads.get_by_id(grad_id)?;
+            let values = param.0.read();
+            let grad = grad
+                .iter()
+                .zip(values.iter())
+                .map(|(g, x)| g + self.weight_decay * x)
+                .collect();
+            Some((grad_id, param, grad))
+        })
+    }
+}
+
+/// Stochastic gradient descent, with optional momentum
+///
+/// `v = momentum * v + grad`, `x -= lr * v`
+#[derive(Clone, Debug)]
+pub struct Sgd {
+    groups: Vec<ParamGroup>,
+    momentum: f64,
+    velocity: HashMap<GradId, Vec<f64>>,
+    steps: usize,
+}
+
+impl Sgd {
+    /// One group of `params`
+    ///
+    /// ## Panics
+    ///
+    /// When any parameter is not with gradient
+    #[inline]
+    pub fn new(params: Vec<TensorRef>, lr: f64) -> Self {
+        let mut sgd = Self {
+            groups: Vec::new(),
+            momentum: 0.0,
+            velocity: HashMap::new(),
+            steps: 0,
+        };
+        sgd.add_group(ParamGroup::new(params, lr));
+        sgd
+    }
+    #[inline]
+    pub fn with_momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self
+    }
+    /// Set the weight decay of all the current groups
+    #[inline]
+    pub fn with_weight_decay(mut self, weight_decay: f64) -> Self {
+        self.groups
+            .iter_mut()
+            .for_each(|group| group.weight_decay = weight_decay);
+        self
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self, grads: &GradStore) {
+        self.steps += 1;
+        log_summary("Sgd", self.steps, grads.loss(), || {
+            grad_norm(&self.groups, grads)
+        });
+        before_update();
+        for group in &self.groups {
+            for (grad_id, param, mut delta) in group.grads(grads) {
+                if self.momentum != 0.0 {
+                    let velocity = self
+                        .velocity
+                        .entry(grad_id)
+                        .or_insert_with(|| vec![0.0; delta.len()]);
+                    velocity
+                        .iter_mut()
+                        .zip(delta.iter_mut())
+                        .for_each(|(v, d)| {
+                            *v = self.momentum * *v + *d;
+                            *d = *v;
+                        });
+                }
+                delta.iter_mut().for_each(|d| *d *= -group.lr);
+                param.update_projected(&delta);
+            }
+        }
+    }
+    fn zero_state(&mut self) {
+        self.velocity.clear();
+    }
+    fn add_group(&mut self, group: ParamGroup) {
+        group.check();
+        self.groups.push(group);
+    }
+    fn groups(&self) -> &[ParamGroup] {
+        &self.groups
+    }
+    fn groups_mut(&mut self) -> &mut [ParamGroup] {
+        &mut self.groups
+    }
+}
+
+/// Adam, with bias correction
+///
+/// The step count is kept per parameter, so a parameter without gradient
+/// in one step keeps its moments and does not advance its step count
+#[derive(Clone, Debug)]
+pub struct Adam {
+    groups: Vec<ParamGroup>,
+    betas: (f64, f64),
+    eps: f64,
+    state: HashMap<GradId, AdamState>,
+    steps: usize,
+}
+
+/// First / second moments and the step count of one parameter
+#[derive(Clone, Debug)]
+struct AdamState {
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: i32,
+}
+
+impl Adam {
+    /// One group of `params`, default `betas = (0.9, 0.999)`, `eps = 1e-8`
+    ///
+    /// ## Panics
+    ///
+    /// When any parameter is not with gradient
+    #[inline]
+    pub fn new(params: Vec<TensorRef>, lr: f64) -> Self {
+        let mut adam = Self {
+            groups: Vec::new(),
+            betas: (0.9, 0.999),
+            eps: 1e-8,
+            state: HashMap::new(),
+            steps: 0,
+        };
+        adam.add_group(ParamGroup::new(params, lr));
+        adam
+    }
+    #[inline]
+    pub fn with_betas(mut self, beta1: f64, beta2: f64) -> Self {
+        self.betas = (beta1, beta2);
+        self
+    }
+    #[inline]
+    pub fn with_eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+    /// Set the weight decay of all the current groups
+    #[inline]
+    pub fn with_weight_decay(mut self, weight_decay: f64) -> Self {
+        self.groups
+            .iter_mut()
+            .for_each(|group| group.weight_decay = weight_decay);
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self, grads: &GradStore) {
+        self.steps += 1;
+        log_summary("Adam", self.steps, grads.loss(), || {
+            grad_norm(&self.groups, grads)
+        });
+        before_update();
+        let (beta1, beta2) = self.betas;
+        for group in &self.groups {
+            for (grad_id, param, grad) in group.grads(grads) {
+                let state = self.state.entry(grad_id).or_insert_with(|| AdamState {
+                    m: vec![0.0; grad.len()],
+                    v: vec![0.0; grad.len()],
+                    t: 0,
+                });
+                state.t += 1;
+                let bias1 = 1.0 - beta1.powi(state.t);
+                let bias2 = 1.0 - beta2.powi(state.t);
+                let delta: Vec<f64> =
+                    itertools::izip!(state.m.iter_mut(), state.v.iter_mut(), grad)
+                        .map(|(m, v, g)| {
+                            *m = beta1 * *m + (1.0 - beta1) * g;
+                            *v = beta2 * *v + (1.0 - beta2) * g * g;
+                            -group.lr * (*m / bias1) / ((*v / bias2).sqrt() + self.eps)
+                        })
+                        .collect();
+                param.update_projected(&delta);
+            }
+        }
+    }
+    fn zero_state(&mut self) {
+        self.state.clear();
+    }
+    fn add_group(&mut self, group: ParamGroup) {
+        group.check();
+        self.groups.push(group);
+    }
+    fn groups(&self) -> &[ParamGroup] {
+        &self.groups
+    }
+    fn groups_mut(&mut self) -> &mut [ParamGroup] {
+        &mut self.groups
+    }
+}
+
+/// Learning-rate schedule, scales the learning rates of all the groups of the optimizer
+///
+/// The scheduler owns the optimizer, use [`optimizer_mut`](Scheduler::optimizer_mut) to step it
+pub trait Scheduler {
+    type Optimizer: Optimizer;
+    /// Update the learning rates after `epoch` (starting from 0) with its `loss`,
+    /// return the new scale of the initial learning rates
+    fn step(&mut self, epoch: usize, loss: f64) -> f64;
+    fn optimizer(&self) -> &Self::Optimizer;
+    fn optimizer_mut(&mut self) -> &mut Self::Optimizer;
+}
+
+/// Initial learning rates of the groups, the groups added later are recorded lazily
+#[derive(Clone, Debug)]
+struct BaseLrs<O> {
+    optimizer: O,
+    lrs: Vec<f64>,
+}
+
+impl<O: Optimizer> BaseLrs<O> {
+    fn new(optimizer: O) -> Self {
+        Self {
+            optimizer,
+            lrs: Vec::new(),
+        }
+    }
+    fn scale(&mut self, scale: f64) -> f64 {
+        let groups = self.optimizer.groups_mut();
+        self.lrs
+            .extend(groups[self.lrs.len()..].iter().map(|group| group.lr));
+        groups
+            .iter_mut()
+            .zip(&self.lrs)
+            .for_each(|(group, lr)| group.lr = lr * scale);
+        scale
+    }
+}
+
+/// `lr = lr0 * gamma^(epoch+1)`
+#[derive(Clone, Debug)]
+pub struct ExponentialDecay<O> {
+    base: BaseLrs<O>,
+    gamma: f64,
+}
+
+impl<O: Optimizer> ExponentialDecay<O> {
+    #[inline]
+    pub fn new(optimizer: O, gamma: f64) -> Self {
+        Self {
+            base: BaseLrs::new(optimizer),
+            gamma,
+        }
+    }
+}
+
+impl<O: Optimizer> Scheduler for ExponentialDecay<O> {
+    type Optimizer = O;
+    fn step(&mut self, epoch: usize, _loss: f64) -> f64 {
+        self.base.scale(self.gamma.powf((epoch + 1) as f64))
+    }
+    fn optimizer(&self) -> &O {
+        &self.base.optimizer
+    }
+    fn optimizer_mut(&mut self) -> &mut O {
+        &mut self.base.optimizer
+    }
+}
+
+/// Cosine annealing from `lr0` to `lr0 * min_scale` in `t_max` epochs, then stay at `lr0 * min_scale`
+///
+/// `lr = lr0 * (min_scale + (1 - min_scale) * (1 + cos(π (epoch+1) / t_max)) / 2)`
+#[derive(Clone, Debug)]
+pub struct Cosine<O> {
+    base: BaseLrs<O>,
+    t_max: usize,
+    min_scale: f64,
+}
+
+impl<O: Optimizer> Cosine<O> {
+    /// ## Panics
+    ///
+    /// When `t_max` is 0
+    #[inline]
+    pub fn new(optimizer: O, t_max: usize, min_scale: f64) -> Self {
+        assert!(t_max > 0, "t_max should be positive");
+        Self {
+            base: BaseLrs::new(optimizer),
+            t_max,
+            min_scale,
+        }
+    }
+}
+
+impl<O: Optimizer> Scheduler for Cosine<O> {
+    type Optimizer = O;
+    fn step(&mut self, epoch: usize, _loss: f64) -> f64 {
+        let progress = (epoch + 1).min(self.t_max) as f64 / self.t_max as f64;
+        let cos = (1.0 + (std::f64::consts::PI * progress).cos()) / 2.0;
+        self.base
+            .scale(self.min_scale + (1.0 - self.min_scale) * cos)
+    }
+    fn optimizer(&self) -> &O {
+        &self.base.optimizer
+    }
+    fn optimizer_mut(&mut self) -> &mut O {
+        &mut self.base.optimizer
+    }
+}
+
+/// Scale the learning rates by `factor` when the loss has not improved for more than `patience` epochs
+///
+/// The loss improves when `loss < best * (1 - threshold)`, a NaN loss never improves.
+/// The scale is bounded by `min_scale`
+#[derive(Clone, Debug)]
+pub struct ReduceOnPlateau<O> {
+    base: BaseLrs<O>,
+    factor: f64,
+    patience: usize,
+    threshold: f64,
+    min_scale: f64,
+    best: f64,
+    num_bad_epochs: usize,
+    scale: f64,
+}
+
+impl<O: Optimizer> ReduceOnPlateau<O> {
+    /// Default `factor = 0.1`, `patience = 10`, `threshold = 1e-4`, `min_scale = 0`
+    #[inline]
+    pub fn new(optimizer: O) -> Self {
+        Self {
+            base: BaseLrs::new(optimizer),
+            factor: 0.1,
+            patience: 10,
+            threshold: 1e-4,
+            min_scale: 0.0,
+            best: f64::INFINITY,
+            num_bad_epochs: 0,
+            scale: 1.0,
+        }
+    }
+    #[inline]
+    pub fn with_factor(mut self, factor: f64) -> Self {
+        self.factor = factor;
+        self
+    }
+    #[inline]
+    pub fn with_patience(mut self, patience: usize) -> Self {
+        self.patience = patience;
+        self
+    }
+    #[inline]
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+    #[inline]
+    pub fn with_min_scale(mut self, min_scale: f64) -> Self {
+        self.min_scale = min_scale;
+        self
+    }
 }
 
-impl Optimizer {
-    fn next_epoch(&mut self) {}
-    fn gradient_decent(&self) -> impl Fn(f64) -> f64 {
-        |x| x
+impl<O: Optimizer> Scheduler for ReduceOnPlateau<O> {
+    type Optimizer = O;
+    fn step(&mut self, _epoch: usize, loss: f64) -> f64 {
+        if loss < self.best * (1.0 - self.threshold) {
+            self.best = loss;
+            self.num_bad_epochs = 0;
+        } else {
+            self.num_bad_epochs += 1;
+            if self.num_bad_epochs > self.patience {
+                self.scale = (self.scale * self.factor).max(self.min_scale);
+                self.num_bad_epochs = 0;
+            }
+        }
+        self.base.scale(self.scale)
     }
+    fn optimizer(&self) -> &O {
+        &self.base.optimizer
+    }
+    fn optimizer_mut(&mut self) -> &mut O {
+        &mut self.base.optimizer
+    }
+}
+
+/// Flattened view of parameters, the global vector is the concatenation of all the tensors
+#[derive(Clone, Debug)]
+pub(super) struct FlatParams {
+    params: Vec<TensorRef>,
+    /// `offsets[i]..offsets[i+1]` is the range of `params[i]`
+    offsets: Vec<usize>,
+}
+
+impl FlatParams {
+    /// Panics when a parameter is not with gradient
+    pub(super) fn new(params: Vec<TensorRef>) -> Self {
+        check_with_grad(&params);
+        let mut offsets = vec![0];
+        for param in &params {
+            offsets.push(offsets[offsets.len() - 1] + param.0.read().len());
+        }
+        Self { params, offsets }
+    }
+    #[inline]
+    pub(super) fn len(&self) -> usize {
+        self.offsets[self.offsets.len() - 1]
+    }
+    #[inline]
+    fn ranges(&self) -> impl Iterator<Item = (&TensorRef, core::ops::Range<usize>)> {
+        self.params
+            .iter()
+            .zip(self.offsets.windows(2))
+            .map(|(param, w)| (param, w[0]..w[1]))
+    }
+    #[cfg(test)]
+    pub(super) fn values(&self) -> Vec<f64> {
+        let mut flat = vec![0.0; self.len()];
+        for (param, range) in self.ranges() {
+            flat[range].copy_from_slice(&param.0.read());
+        }
+        flat
+    }
+    /// Zeros for the parameters without gradient in `grads`, or frozen
+    pub(super) fn grads(&self, grads: &GradStore) -> Vec<f64> {
+        let mut flat = vec![0.0; self.len()];
+        for (param, range) in self.ranges() {
+            if let Some(grad) = param.grad_id().and_then(|grad_id| grads.get_by_id(grad_id)) {
+                flat[range].copy_from_slice(grad);
+            }
+        }
+        flat
+    }
+    /// Calls [`before_update`] once, and then [`TensorRef::update_projected`] of each parameter
+    pub(super) fn update(&self, delta: &[f64]) {
+        assert_eq!(delta.len(), self.len(), "flat delta length mismatch");
+        before_update();
+        for (param, range) in self.ranges() {
+            param.update_projected(&delta[range]);
+        }
+    }
+}
+
+#[inline]
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// Limited-memory BFGS with strong-Wolfe line search
+///
+/// Unlike [`Optimizer`], each [`step`](Lbfgs::step) evaluates the loss (the sum of its elements)
+/// and its gradient several times, by updating the parameters and re-running the forward
+#[derive(Clone, Debug)]
+pub struct Lbfgs {
+    params: FlatParams,
+    lr: f64,
+    history_size: usize,
+    max_line_search: usize,
+    tolerance_grad: f64,
+    /// `(s, y, 1 / y·s)`, oldest first
+    history: std::collections::VecDeque<(Vec<f64>, Vec<f64>, f64)>,
+    steps: usize,
+}
+
+/// Sufficient decrease and curvature constants of the strong-Wolfe conditions
+const WOLFE_C1: f64 = 1e-4;
+const WOLFE_C2: f64 = 0.9;
+
+impl Lbfgs {
+    /// Default `history_size = 10`, `max_line_search = 25`, `tolerance_grad = 1e-12`,
+    /// `lr` is the initial step length of the line search
+    ///
+    /// ## Panics
+    ///
+    /// When any parameter is not with gradient
+    #[inline]
+    pub fn new(params: Vec<TensorRef>, lr: f64) -> Self {
+        Self {
+            params: FlatParams::new(params),
+            lr,
+            history_size: 10,
+            max_line_search: 25,
+            tolerance_grad: 1e-12,
+            history: std::collections::VecDeque::new(),
+            steps: 0,
+        }
+    }
+    #[inline]
+    pub fn with_history_size(mut self, history_size: usize) -> Self {
+        self.history_size = history_size;
+        self
+    }
+    #[inline]
+    pub fn with_max_line_search(mut self, max_line_search: usize) -> Self {
+        self.max_line_search = max_line_search;
+        self
+    }
+    /// Stop when `max |grad| <= tolerance_grad`
+    #[inline]
+    pub fn with_tolerance_grad(mut self, tolerance_grad: f64) -> Self {
+        self.tolerance_grad = tolerance_grad;
+        self
+    }
+    /// Reset the curvature history, keep the parameters
+    #[inline]
+    pub fn zero_state(&mut self) {
+        self.history.clear();
+    }
+    /// Loss and flat gradient at the current parameters
+    fn evaluate(&self, loss: &Expression) -> (f64, Vec<f64>) {
+        let f = loss.value().overall_sum();
+        (f, self.params.grads(&loss.backward()))
+    }
+    /// `-H g` by the two-loop recursion
+    fn direction(&self, g: &[f64]) -> Vec<f64> {
+        let mut q = g.to_vec();
+        let mut alphas = vec![0.0; self.history.len()];
+        for ((s, y, rho), alpha) in self.history.iter().zip(alphas.iter_mut()).rev() {
+            *alpha = rho * dot(s, &q);
+            q.iter_mut().zip(y).for_each(|(q, y)| *q -= *alpha * y);
+        }
+        let gamma = match self.history.back() {
+            Some((_, y, rho)) => 1.0 / (rho * dot(y, y)),
+            // the first step is scaled by the gradient
+            None => 1.0_f64.min(1.0 / g.iter().map(|g| g.abs()).sum::<f64>()),
+        };
+        q.iter_mut().for_each(|q| *q *= gamma);
+        for ((s, y, rho), alpha) in self.history.iter().zip(alphas) {
+            let beta = rho * dot(y, &q);
+            q.iter_mut()
+                .zip(s)
+                .for_each(|(q, s)| *q += (alpha - beta) * s);
+        }
+        q.iter_mut().for_each(|q| *q = -*q);
+        q
+    }
+    /// One L-BFGS iteration, return the loss after the step
+    ///
+    /// The parameters are left at the accepted point, whose forward is already computed
+    pub fn step(&mut self, loss: &Expression) -> f64 {
+        let (f0, g0) = self.evaluate(loss);
+        self.steps += 1;
+        log_summary("Lbfgs", self.steps, Some(f0), || dot(&g0, &g0).sqrt());
+        if g0.iter().all(|g| g.abs() <= self.tolerance_grad) {
+            return f0;
+        }
+        let mut d = self.direction(&g0);
+        let mut dg0 = dot(&d, &g0);
+        if dg0.partial_cmp(&0.0) != Some(core::cmp::Ordering::Less) {
+            // not a descent direction, restart from the steepest descent
+            self.history.clear();
+            d = self.direction(&g0);
+            dg0 = dot(&d, &g0);
+        }
+        let (t, f, g) = self.line_search(loss, f0, dg0, &d);
+        if t == 0.0 {
+            self.history.clear();
+            return f;
+        }
+        let s: Vec<f64> = d.iter().map(|d| t * d).collect();
+        let y: Vec<f64> = g.iter().zip(&g0).map(|(g, g0)| g - g0).collect();
+        let ys = dot(&y, &s);
+        if ys > 1e-10 {
+            if self.history.len() == self.history_size {
+                self.history.pop_front();
+            }
+            self.history.push_back((s, y, 1.0 / ys));
+        }
+        f
+    }
+    /// Strong-Wolfe line search along `d` (bracketing and zoom),
+    /// return `(t, f(t), g(t))` with the parameters at `x0 + t d`
+    ///
+    /// Fall back to the best Armijo point, or `t = 0` when none is found
+    fn line_search(&self, loss: &Expression, f0: f64, dg0: f64, d: &[f64]) -> (f64, f64, Vec<f64>) {
+        // move the parameters from `x0 + t_now d` to `x0 + t d`
+        let mut t_now = 0.0;
+        let mut eval = |t: f64| {
+            let delta: Vec<f64> = d.iter().map(|d| (t - t_now) * d).collect();
+            self.params.update(&delta);
+            t_now = t;
+            let (f, g) = self.evaluate(loss);
+            (f, dot(&g, d), g)
+        };
+        let armijo = |t: f64, f: f64| f <= f0 + WOLFE_C1 * t * dg0;
+        let curvature = |dg: f64| dg.abs() <= -WOLFE_C2 * dg0;
+        // `(t, f, dg)` of the bracket ends, `lo` is the best Armijo point
+        let mut lo = (0.0, f0, dg0);
+        let mut best: Option<(f64, f64, Vec<f64>)> = None;
+        let mut hi = None;
+        let mut t = self.lr;
+        for _ in 0..self.max_line_search {
+            let (f, dg, g) = eval(t);
+            let decreased =
+                f.is_finite() && armijo(t, f) && !(f >= lo.1 && (hi.is_some() || lo.0 > 0.0));
+            if decreased {
+                if curvature(dg) {
+                    return (t, f, g);
+                }
+                best = Some((t, f, g));
+                let last_lo = lo;
+                lo = (t, f, dg);
+                match hi {
+                    None if dg >= 0.0 => hi = Some(last_lo),
+                    Some((t_hi, _, _)) if (t_hi - t) * dg >= 0.0 => hi = Some(last_lo),
+                    _ => {}
+                }
+            } else {
+                hi = Some((t, f, dg));
+            }
+            t = match hi {
+                // extrapolate
+                None => 2.0 * t,
+                // zoom by the minimizer of the cubic interpolation, safeguarded into the bracket
+                Some(hi) => {
+                    let (a, b) = (lo.0.min(hi.0), lo.0.max(hi.0));
+                    let t_cubic = cubic_minimizer(lo, hi);
+                    let margin = 0.1 * (b - a);
+                    if t_cubic.is_finite() && t_cubic > a + margin && t_cubic < b - margin {
+                        t_cubic
+                    } else {
+                        0.5 * (a + b)
+                    }
+                }
+            };
+        }
+        let (t, f, g) = best.unwrap_or((0.0, f0, Vec::new()));
+        if t != t_now {
+            let delta: Vec<f64> = d.iter().map(|d| (t - t_now) * d).collect();
+            self.params.update(&delta);
+            loss.value();
+        }
+        (t, f, g)
+    }
+}
+
+/// Minimizer of the cubic interpolating `f` and `f'` at the two points `(t, f, f')`
+fn cubic_minimizer((t1, f1, g1): (f64, f64, f64), (t2, f2, g2): (f64, f64, f64)) -> f64 {
+    let d1 = g1 + g2 - 3.0 * (f1 - f2) / (t1 - t2);
+    let d2 = (d1 * d1 - g1 * g2).sqrt() * (t2 - t1).signum();
+    t2 - (t2 - t1) * (g2 + d2 - d1) / (g2 - g1 + 2.0 * d2)
 }
@@ -0,0 +1,329 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use super::{
+    super::{before_update, reader::LeReader},
+    Adam, AdamState, Error, TensorRef,
+};
+
+/// Leading bytes of a checkpoint
+const MAGIC: [u8; 4] = *b"GCKP";
+/// Bumped on any change of the layout, older checkpoints are rejected
+const VERSION: u32 = 1;
+
+const TAG_NO_MOMENTS: u8 = 0;
+const TAG_MOMENTS: u8 = 1;
+
+/// The parameters by name, the key of a [`Checkpoint`]
+///
+/// The names stay the same when the graph is rebuilt, unlike the tensors
+#[derive(Clone, Debug, Default)]
+pub struct ParameterMap(BTreeMap<String, TensorRef>);
+
+impl ParameterMap {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The previous parameter of `name`, if any
+    #[inline]
+    pub fn insert(&mut self, name: impl Into<String>, param: TensorRef) -> Option<TensorRef> {
+        self.0.insert(name.into(), param)
+    }
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<&TensorRef> {
+        self.0.get(name)
+    }
+    /// By name
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &TensorRef)> {
+        self.0.iter().map(|(name, param)| (name.as_str(), param))
+    }
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<S: Into<String>> FromIterator<(S, TensorRef)> for ParameterMap {
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (S, TensorRef)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(name, param)| (name.into(), param))
+                .collect(),
+        )
+    }
+}
+
+/// One parameter of a checkpoint
+#[derive(Clone, Debug)]
+struct Saved {
+    values: Vec<f64>,
+    /// `None` before the first step with its gradient
+    moments: Option<AdamState>,
+}
+
+/// The state of an [`Adam`] run, to resume it after a restart
+///
+/// ``` text
+/// Checkpoint::save("run.ckpt", &adam, &params, iter, &extra)?;
+/// // a new process, the same graph built again
+/// let checkpoint = Checkpoint::load("run.ckpt")?;
+/// checkpoint.restore(&mut adam, &params, true)?;
+/// for iter in checkpoint.iter()..n_iters { ... }
+/// ```
+///
+/// The parameters and their moments are keyed by their names in the [`ParameterMap`],
+/// the options of the optimizer (learning rates, betas, ...) are the ones it is built with.
+/// The values are kept bit by bit, so the resumed steps are identical to an uninterrupted run
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    iter: u64,
+    /// Steps of the optimizer
+    steps: u64,
+    extra: HashMap<String, f64>,
+    params: BTreeMap<String, Saved>,
+}
+
+impl Checkpoint {
+    /// Write the checkpoint to a file, see [`save_to`](Checkpoint::save_to)
+    #[inline]
+    pub fn save(
+        path: impl AsRef<Path>,
+        optimizer: &Adam,
+        params: &ParameterMap,
+        iter: u64,
+        extra: &HashMap<String, f64>,
+    ) -> Result<(), Error> {
+        let file = File::create(path).map_err(io_err)?;
+        Self::save_to(BufWriter::new(file), optimizer, params, iter, extra)
+    }
+    /// Write the values of `params`, their moments in `optimizer`, the iteration
+    /// counter and the `extra` scalars (e.g., the best loss so far)
+    pub fn save_to(
+        mut writer: impl Write,
+        optimizer: &Adam,
+        params: &ParameterMap,
+        iter: u64,
+        extra: &HashMap<String, f64>,
+    ) -> Result<(), Error> {
+        let checkpoint = Self {
+            iter,
+            steps: optimizer.steps as u64,
+            extra: extra.clone(),
+            params: params
+                .iter()
+                .map(|(name, param)| {
+                    let saved = Saved {
+                        values: param.0.read().clone(),
+                        moments: param
+                            .0
+                            .leaf_id()
+                            .and_then(|id| optimizer.state.get(&id))
+                            .cloned(),
+                    };
+                    (name.to_owned(), saved)
+                })
+                .collect(),
+        };
+        checkpoint.write(&mut writer).map_err(io_err)?;
+        writer.flush().map_err(io_err)
+    }
+    /// Read a checkpoint file, see [`load_from`](Checkpoint::load_from)
+    #[inline]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path).map_err(io_err)?;
+        Self::load_from(BufReader::new(file))
+    }
+    /// Read a checkpoint written by [`save_to`](Checkpoint::save_to), apply it by [`restore`](Checkpoint::restore)
+    ///
+    /// [`Error::InvalidCheckpoint`] when the input is not a checkpoint of this version
+    pub fn load_from(reader: impl Read) -> Result<Self, Error> {
+        let mut input = LeReader::new(reader, Error::InvalidCheckpoint, "checkpoint");
+        if input.bytes::<4>()? != MAGIC {
+            return Err(Error::InvalidCheckpoint("not a checkpoint".to_owned()));
+        }
+        let version = input.u32()?;
+        if version != VERSION {
+            return Err(Error::InvalidCheckpoint(format!(
+                "version {version}, expected {VERSION}"
+            )));
+        }
+        let iter = input.u64()?;
+        let steps = input.u64()?;
+        let extra = (0..input.u32()?)
+            .map(|_| {
+                Ok((
+                    input.string(|| "name".to_owned())?,
+                    f64::from_bits(input.u64()?),
+                ))
+            })
+            .collect::<Result<_, Error>>()?;
+        let mut params = BTreeMap::new();
+        for _ in 0..input.u32()? {
+            let name = input.string(|| "name".to_owned())?;
+            let len = input.u64()?;
+            let values = input.values(len)?;
+            let moments = match input.bytes::<1>()?[0] {
+                TAG_NO_MOMENTS => None,
+                TAG_MOMENTS => Some(AdamState {
+                    t: i32::from_le_bytes(input.bytes()?),
+                    m: input.values(len)?,
+                    v: input.values(len)?,
+                }),
+                tag => {
+                    return Err(Error::InvalidCheckpoint(format!(
+                        "unknown tag {tag} of `{name}`"
+                    )))
+                }
+            };
+            if params
+                .insert(name.clone(), Saved { values, moments })
+                .is_some()
+            {
+                return Err(Error::InvalidCheckpoint(format!("duplicate `{name}`")));
+            }
+        }
+        Ok(Self {
+            iter,
+            steps,
+            extra,
+            params,
+        })
+    }
+    /// Write the saved values to `params` and the saved moments to `optimizer`,
+    /// and its step count. The moments of the other parameters of `optimizer` are kept
+    ///
+    /// Strict, the parameters of the checkpoint and of `params` are the same names of
+    /// the same lengths, or an [`Error::CheckpointMismatch`] lists all the differences
+    /// and nothing is restored. Otherwise the differing parameters are skipped, with a
+    /// warning of the target `gspice::optim`.
+    ///
+    /// [`Error::OutOfBounds`] when a saved value violates the bounds of its parameter
+    /// in [strict mode](TensorRef::set_strict_bounds), nothing is restored
+    ///
+    /// Calls [`before_update`] once, need [`Expression::value`](super::Expression::value) after calling this
+    pub fn restore(
+        &self,
+        optimizer: &mut Adam,
+        params: &ParameterMap,
+        strict: bool,
+    ) -> Result<(), Error> {
+        let mut missing = Vec::new();
+        let mut len_changed = Vec::new();
+        let mut matched = Vec::new();
+        for (name, param) in params.iter() {
+            match self.params.get(name) {
+                None => missing.push(name.to_owned()),
+                Some(saved) => {
+                    let len = param.0.read().len();
+                    if saved.values.len() == len {
+                        matched.push((param, saved));
+                    } else {
+                        len_changed.push((name.to_owned(), saved.values.len(), len));
+                    }
+                }
+            }
+        }
+        let extra: Vec<String> = self
+            .params
+            .keys()
+            .filter(|name| params.get(name).is_none())
+            .cloned()
+            .collect();
+        if !(missing.is_empty() && extra.is_empty() && len_changed.is_empty()) {
+            let e = Error::CheckpointMismatch {
+                missing,
+                extra,
+                len_changed,
+            };
+            if strict {
+                return Err(e);
+            }
+            log::warn!(target: "gspice::optim", "partial restore, {e}");
+        }
+        // all checked first, so that nothing is restored on error
+        for (param, saved) in &matched {
+            param.0.bounds().check_strict(&saved.values, "restore")?;
+        }
+        before_update();
+        for (param, saved) in matched {
+            param.try_assign(saved.values.clone())?;
+            if let Some(id) = param.0.leaf_id() {
+                match &saved.moments {
+                    Some(moments) => optimizer.state.insert(id, moments.clone()),
+                    None => optimizer.state.remove(&id),
+                };
+            }
+        }
+        optimizer.steps = self.steps as usize;
+        Ok(())
+    }
+    /// The iteration counter given to [`save`](Checkpoint::save)
+    #[inline]
+    pub fn iter(&self) -> u64 {
+        self.iter
+    }
+    /// The scalars given to [`save`](Checkpoint::save)
+    #[inline]
+    pub fn extra(&self) -> &HashMap<String, f64> {
+        &self.extra
+    }
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&self.iter.to_le_bytes())?;
+        out.write_all(&self.steps.to_le_bytes())?;
+        // sorted, so the same state gives the same file
+        let mut extra: Vec<_> = self.extra.iter().collect();
+        extra.sort_unstable_by_key(|(name, _)| *name);
+        out.write_all(&(extra.len() as u32).to_le_bytes())?;
+        for (name, value) in extra {
+            write_name(out, name)?;
+            out.write_all(&value.to_bits().to_le_bytes())?;
+        }
+        out.write_all(&(self.params.len() as u32).to_le_bytes())?;
+        for (name, saved) in &self.params {
+            write_name(out, name)?;
+            out.write_all(&(saved.values.len() as u64).to_le_bytes())?;
+            write_values(out, &saved.values)?;
+            match &saved.moments {
+                None => out.write_all(&[TAG_NO_MOMENTS])?,
+                Some(AdamState { m, v, t }) => {
+                    out.write_all(&[TAG_MOMENTS])?;
+                    out.write_all(&t.to_le_bytes())?;
+                    write_values(out, m)?;
+                    write_values(out, v)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[inline]
+fn write_name(out: &mut impl Write, name: &str) -> io::Result<()> {
+    out.write_all(&(name.len() as u32).to_le_bytes())?;
+    out.write_all(name.as_bytes())
+}
+
+#[inline]
+fn write_values(out: &mut impl Write, values: &[f64]) -> io::Result<()> {
+    values
+        .iter()
+        .try_for_each(|x| out.write_all(&x.to_bits().to_le_bytes()))
+}
+
+#[inline]
+fn io_err(e: io::Error) -> Error {
+    Error::Io(e.to_string())
+}
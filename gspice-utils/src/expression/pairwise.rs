@@ -0,0 +1,96 @@
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+use super::{Error, Expression};
+
+/// Default of [`set_pairwise_limit`]
+const DEFAULT_PAIRWISE_LIMIT: usize = 1 << 20;
+
+static PAIRWISE_LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_PAIRWISE_LIMIT);
+
+/// Set the max output length of [`Expression::pairwise_diff`] and [`Expression::outer_sub`],
+/// against the quadratic blowup, `2^20` by default
+#[inline]
+pub fn set_pairwise_limit(limit: usize) {
+    PAIRWISE_LIMIT.store(limit, Relaxed);
+}
+
+/// See [`set_pairwise_limit`]
+#[inline]
+pub fn pairwise_limit() -> usize {
+    PAIRWISE_LIMIT.load(Relaxed)
+}
+
+#[inline]
+fn check_size(size: usize, op: &str) -> Result<(), Error> {
+    let limit = pairwise_limit();
+    if size > limit {
+        Err(Error::SizeLimit {
+            size,
+            limit,
+            op: op.to_owned(),
+        })
+    } else {
+        Ok(())
+    }
+}
+
+impl Expression {
+    /// Length of the tensor, a constant is length-1
+    #[inline]
+    fn pairwise_len(&self) -> usize {
+        match self {
+            Self::Const(_) => 1,
+            Self::Tensor(tensor) => tensor.read().len(),
+        }
+    }
+    /// `x[i] - x[j]` of all the pairs `i < j`, a length `N(N-1)/2` tensor
+    /// ordered as `(0,1), (0,2), .., (0,N-1), (1,2), ..`
+    ///
+    /// The gradient scatters `+g` to `i` and `-g` to `j`.
+    /// The pairs are of the length when built
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_pairwise_diff`](Expression::try_pairwise_diff)
+    #[inline]
+    pub fn pairwise_diff(&self) -> Self {
+        self.try_pairwise_diff().unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`pairwise_diff`](Expression::pairwise_diff)
+    ///
+    /// [`Error::SizeLimit`] when the output is longer than the [limit](set_pairwise_limit)
+    #[inline]
+    pub fn try_pairwise_diff(&self) -> Result<Self, Error> {
+        let n = self.pairwise_len();
+        check_size(n * n.saturating_sub(1) / 2, "PairwiseDiff")?;
+        if let Self::Const(_) = self {
+            return Ok(Self::tensor(Vec::new(), false).0);
+        }
+        let (lhs, rhs): (Vec<usize>, Vec<usize>) =
+            (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))).unzip();
+        self.try_gather(&lhs)?.try_sub(&self.try_gather(&rhs)?)
+    }
+    /// `self[i] - rhs[j]` at `i * M + j`, the flattened `N × M` difference matrix
+    ///
+    /// The pairs are of the lengths when built, a constant is length-1
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_outer_sub`](Expression::try_outer_sub)
+    #[inline]
+    pub fn outer_sub(&self, rhs: &Self) -> Self {
+        self.try_outer_sub(rhs).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`outer_sub`](Expression::outer_sub)
+    ///
+    /// [`Error::SizeLimit`] when the output is longer than the [limit](set_pairwise_limit)
+    #[inline]
+    pub fn try_outer_sub(&self, rhs: &Self) -> Result<Self, Error> {
+        let (n, m) = (self.pairwise_len(), rhs.pairwise_len());
+        check_size(n * m, "OuterSub")?;
+        let lhs_indices: Vec<usize> = (0..n).flat_map(|i| std::iter::repeat_n(i, m)).collect();
+        let rhs_indices: Vec<usize> = (0..n).flat_map(|_| 0..m).collect();
+        self.try_gather(&lhs_indices)?
+            .try_sub(&rhs.try_gather(&rhs_indices)?)
+    }
+}
@@ -0,0 +1,194 @@
+//! Opt-in profiler of the forward/backward kernels, per op kind and tensor length bucket
+//!
+//! ``` text
+//! gspice::profile::enable();
+//! f.value();
+//! f.backward();
+//! println!("{}", gspice::profile::report());
+//! ```
+//!
+//! When disabled, each kernel pays one atomic load.
+//! The forward timing covers the recomputation ([`Expression::value`](super::Expression::value)
+//! after [`before_update`](super::before_update)), excluding the inputs' own time,
+//! the eager forward at the graph construction is only counted as allocations
+
+use core::fmt;
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::{Op, Tensor};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static STATS: Mutex<BTreeMap<(String, Phase, usize), Stat>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// Time spent in the inputs of the node being recomputed
+    static CHILD_TIME: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Start recording, the records are kept until [`reset`]
+#[inline]
+pub fn enable() {
+    ENABLED.store(true, Relaxed);
+}
+
+/// Stop recording, the records are kept
+#[inline]
+pub fn disable() {
+    ENABLED.store(false, Relaxed);
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Relaxed)
+}
+
+/// Clear the records
+#[inline]
+pub fn reset() {
+    STATS.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    ALLOCATIONS.store(0, Relaxed);
+}
+
+/// Snapshot of the records, sorted by the time, the largest first
+pub fn report() -> ProfileReport {
+    let mut entries: Vec<ProfileEntry> = STATS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|((op, phase, len_bucket), stat)| ProfileEntry {
+            op: op.clone(),
+            phase: *phase,
+            len_bucket: *len_bucket,
+            calls: stat.calls,
+            elements: stat.elements,
+            time: stat.time,
+        })
+        .collect();
+    entries.sort_by_key(|entry| core::cmp::Reverse(entry.time));
+    ProfileReport {
+        entries,
+        allocations: ALLOCATIONS.load(Relaxed),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    Forward,
+    Backward,
+}
+
+#[derive(Default)]
+struct Stat {
+    calls: usize,
+    elements: usize,
+    time: Duration,
+}
+
+/// Records of one (op kind, phase, length bucket)
+#[derive(Clone, Debug)]
+pub struct ProfileEntry {
+    /// Op kind without the parameters, e.g., `Exp`, `Powf`
+    pub op: String,
+    pub phase: Phase,
+    /// The output lengths are within `(len_bucket/2, len_bucket]`
+    pub len_bucket: usize,
+    pub calls: usize,
+    /// Total output elements
+    pub elements: usize,
+    /// Total time, excluding the inputs' own time
+    pub time: Duration,
+}
+
+/// See [`report`]
+#[derive(Clone, Debug)]
+pub struct ProfileReport {
+    /// Sorted by the time, the largest first
+    pub entries: Vec<ProfileEntry>,
+    /// Output vectors allocated by the forward kernels, including the graph construction
+    pub allocations: usize,
+}
+
+impl ProfileReport {
+    /// Total time of the op kind over the phases and buckets
+    pub fn time_of(&self, op: &str) -> Duration {
+        self.entries
+            .iter()
+            .filter(|entry| entry.op == op)
+            .map(|entry| entry.time)
+            .sum()
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: Duration = self.entries.iter().map(|entry| entry.time).sum();
+        writeln!(
+            f,
+            "{:<20} {:<8} {:>10} {:>10} {:>12} {:>12} {:>7}",
+            "op", "phase", "len<=", "calls", "elements", "time(us)", "share"
+        )?;
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{:<20} {:<8} {:>10} {:>10} {:>12} {:>12.1} {:>6.1}%",
+                entry.op,
+                format!("{:?}", entry.phase),
+                entry.len_bucket,
+                entry.calls,
+                entry.elements,
+                entry.time.as_secs_f64() * 1e6,
+                100.0 * entry.time.as_secs_f64() / total.as_secs_f64().max(f64::MIN_POSITIVE),
+            )?;
+        }
+        write!(f, "output allocations: {}", self.allocations)
+    }
+}
+
+#[inline]
+pub(super) fn count_allocation() {
+    if is_enabled() {
+        ALLOCATIONS.fetch_add(1, Relaxed);
+    }
+}
+
+/// Run the recomputation of the tensor, and record its own time
+#[inline]
+pub(super) fn forward<T>(tensor: &Tensor, f: impl FnOnce() -> T) -> T {
+    if !is_enabled() {
+        return f();
+    }
+    let parent_child_time = CHILD_TIME.replace(Duration::ZERO);
+    let start = Instant::now();
+    let out = f();
+    let total = start.elapsed();
+    let own = total.saturating_sub(CHILD_TIME.get());
+    CHILD_TIME.set(parent_child_time + total);
+    if !matches!(tensor.op(), Op::Assign) {
+        record(tensor.op(), Phase::Forward, tensor.read().len(), own);
+    }
+    out
+}
+
+#[cold]
+pub(super) fn record(op: &Op, phase: Phase, len: usize, time: Duration) {
+    let mut name = op.name();
+    if let Some(i) = name.find('(') {
+        name.truncate(i);
+    }
+    let mut stats = STATS.lock().unwrap_or_else(|e| e.into_inner());
+    let stat = stats
+        .entry((name, phase, len.next_power_of_two()))
+        .or_default();
+    stat.calls += 1;
+    stat.elements += len;
+    stat.time += time;
+}
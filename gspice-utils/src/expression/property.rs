@@ -0,0 +1,707 @@
+#![cfg(test)]
+//! Property-based testing of the ops: random inputs and random element-wise graphs,
+//! shrunk to a minimal counterexample on failure
+//!
+//! The runs are reproducible, `GSPICE_PROPTEST_SEED` and `GSPICE_PROPTEST_CASES`
+//! override the seed and the number of cases of each property
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
+
+use itertools::izip;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{BinaryOp, Expression, GradStore, TensorRef, TiePolicy, Tolerance, UnaryOp, LIMEXP_X0};
+
+const DEFAULT_SEED: u64 = 0x6753_5049_4345;
+const DEFAULT_CASES: usize = 64;
+/// Longest generated input
+const MAX_LEN: usize = 16;
+/// Property runs spent on simplifying the values of a counterexample
+const SHRINK_RUNS: usize = 512;
+/// Probability of an element equal to the one of the first input, for the ties
+const TIE_PROBABILITY: f64 = 0.125;
+/// Probability of an edge case of the domain, see [`Domain::specials`]
+const SPECIAL_PROBABILITY: f64 = 0.2;
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// The seed of each property is derived from its name,
+/// so that adding a property does not change the cases of the others
+fn rng(name: &str) -> (u64, StdRng) {
+    let seed = env_or("GSPICE_PROPTEST_SEED", DEFAULT_SEED);
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    (seed, StdRng::seed_from_u64(seed ^ hasher.finish()))
+}
+
+fn cases() -> usize {
+    env_or("GSPICE_PROPTEST_CASES", DEFAULT_CASES)
+}
+
+/// Range of the generated elements of an input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Domain {
+    /// `[lo, hi]`
+    Range(f64, f64),
+    /// `±[lo, hi]`, away from zero
+    Magnitude(f64, f64),
+}
+
+impl Domain {
+    /// The inputs of the logic ops, marked as logic tensors by [`leaves`]
+    pub(super) const LOGIC: Self = Self::Range(0.0, 1.0);
+    fn contains(&self, x: f64) -> bool {
+        match *self {
+            Self::Range(lo, hi) => (lo..=hi).contains(&x),
+            Self::Magnitude(lo, hi) => (lo..=hi).contains(&x.abs()),
+        }
+    }
+    /// The bounds and the simple values of the domain
+    fn specials(&self) -> Vec<f64> {
+        let candidates = match *self {
+            Self::Range(lo, hi) => vec![lo, hi, 0.0, 1.0, -1.0, 0.5],
+            Self::Magnitude(lo, hi) => vec![lo, -lo, hi, -hi, 1.0, -1.0],
+        };
+        candidates
+            .into_iter()
+            .filter(|x| self.contains(*x))
+            .collect()
+    }
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        if rng.gen_bool(SPECIAL_PROBABILITY) {
+            if let Some(x) = self.specials().choose(rng) {
+                return *x;
+            }
+        }
+        match *self {
+            Self::Range(lo, hi) => rng.gen_range(lo..=hi),
+            Self::Magnitude(lo, hi) => {
+                let x = rng.gen_range(lo..=hi);
+                if rng.gen() {
+                    x
+                } else {
+                    -x
+                }
+            }
+        }
+    }
+    /// Simpler values than `x` in the domain, simplest first
+    fn shrink(&self, x: f64) -> Vec<f64> {
+        let mut candidates = self.specials();
+        candidates.extend([x.trunc(), x.round()]);
+        // towards zero, by halves
+        candidates.extend((1..=8).map(|k| (x - x / f64::from(1 << k)).trunc()));
+        candidates.extend((1..=3).map(|digits| {
+            let scale = 10f64.powi(digits);
+            (x * scale).round() / scale
+        }));
+        candidates.retain(|candidate| {
+            self.contains(*candidate) && complexity(*candidate) < complexity(x)
+        });
+        candidates.sort_by(|a, b| complexity(*a).partial_cmp(&complexity(*b)).unwrap());
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// `(decimal digits, magnitude)`, a simpler value has fewer digits, then a smaller magnitude
+fn complexity(x: f64) -> (u32, f64) {
+    let digits = (0..=17)
+        .find(|digits| {
+            let scaled = x * 10f64.powi(*digits as i32);
+            scaled.is_finite() && scaled.fract() == 0.0
+        })
+        .unwrap_or(18);
+    (digits, x.abs())
+}
+
+/// The element and the reason of a failed property
+#[derive(Debug)]
+pub(super) struct Counterexample {
+    pub(super) index: usize,
+    pub(super) detail: String,
+}
+
+pub(super) type Outcome = Result<(), Counterexample>;
+
+/// The first element where `got` and `want` are not close
+pub(super) fn check_close(what: &str, got: &[f64], want: &[f64], tolerance: Tolerance) -> Outcome {
+    if got.len() != want.len() {
+        return Err(Counterexample {
+            index: 0,
+            detail: format!("{what}: length {} vs {}", got.len(), want.len()),
+        });
+    }
+    match izip!(got, want).position(|(g, w)| !tolerance.is_close(*g, *w)) {
+        None => Ok(()),
+        Some(index) => Err(Counterexample {
+            index,
+            detail: format!("{what}: {} vs {}", got[index], want[index]),
+        }),
+    }
+}
+
+/// The first element violating `predicate(index, value)`
+pub(super) fn check(what: &str, values: &[f64], predicate: impl Fn(usize, f64) -> bool) -> Outcome {
+    match values
+        .iter()
+        .enumerate()
+        .position(|(index, x)| !predicate(index, *x))
+    {
+        None => Ok(()),
+        Some(index) => Err(Counterexample {
+            index,
+            detail: format!("{what}: {}", values[index]),
+        }),
+    }
+}
+
+/// Leaf tensors with gradient holding the inputs, the logic ones are marked as such
+pub(super) fn leaves(inputs: &[Vec<f64>], domains: &[Domain]) -> Vec<(Expression, TensorRef)> {
+    izip!(inputs, domains)
+        .map(|(values, domain)| {
+            let (x, x_ref) = Expression::tensor(values.clone(), true);
+            if *domain == Domain::LOGIC {
+                x.mark_logic();
+            }
+            (x, x_ref)
+        })
+        .collect()
+}
+
+/// The values of the expression, a constant is broadcasted to `len`
+pub(super) fn values(expr: &Expression, len: usize) -> Vec<f64> {
+    match expr {
+        Expression::Const(x) => vec![*x; len],
+        Expression::Tensor(_) => expr.value().to_tensor().unwrap(),
+    }
+}
+
+/// The gradient w.r.t. the leaf, zeros when it does not participate
+pub(super) fn grad(grads: &GradStore, leaf: &TensorRef, len: usize) -> Vec<f64> {
+    grads
+        .get(leaf)
+        .map_or_else(|| vec![0.0; len], |grad| grad.to_vec())
+}
+
+/// Both expressions have close values and gradients w.r.t. the `leaves`
+pub(super) fn check_exprs_close(
+    what: &str,
+    got: &Expression,
+    want: &Expression,
+    leaves: &[(Expression, TensorRef)],
+    tolerance: Tolerance,
+) -> Outcome {
+    let len = leaves.first().map_or(1, |(x, _)| values(x, 1).len());
+    check_close(what, &values(got, len), &values(want, len), tolerance)?;
+    let (got_grads, want_grads) = (got.backward(), want.backward());
+    for (k, (_, leaf)) in leaves.iter().enumerate() {
+        check_close(
+            &format!("d {what} / dx{k}"),
+            &grad(&got_grads, leaf, len),
+            &grad(&want_grads, leaf, len),
+            tolerance,
+        )?;
+    }
+    Ok(())
+}
+
+fn random_inputs(rng: &mut StdRng, domains: &[Domain]) -> Vec<Vec<f64>> {
+    let len = rng.gen_range(1..=MAX_LEN);
+    let mut inputs: Vec<Vec<f64>> = Vec::with_capacity(domains.len());
+    for domain in domains {
+        let input = (0..len)
+            .map(|i| match inputs.first() {
+                Some(first) if domain.contains(first[i]) && rng.gen_bool(TIE_PROBABILITY) => {
+                    first[i]
+                }
+                _ => domain.sample(rng),
+            })
+            .collect();
+        inputs.push(input);
+    }
+    inputs
+}
+
+/// Check `property` on the random inputs drawn from the `domains`, all of the same length
+///
+/// ## Panics
+///
+/// On the first failure, with the shrunk counterexample
+#[track_caller]
+pub(super) fn for_all(name: &str, domains: &[Domain], property: impl Fn(&[Vec<f64>]) -> Outcome) {
+    let (seed, mut rng) = rng(name);
+    for case in 0..cases() {
+        let inputs = random_inputs(&mut rng, domains);
+        if let Err(failure) = property(&inputs) {
+            let (inputs, failure) = shrink_inputs(domains, inputs, failure, &property);
+            panic!(
+                "property `{name}` failed (seed {seed:#x}, case {case}) at element {}: {}\ninputs: {inputs:?}",
+                failure.index, failure.detail
+            );
+        }
+    }
+}
+
+/// Check `property` on `count` random element-wise graphs of at most `depth` ops
+/// over the inputs drawn from the `domains`, see [`for_all`]
+#[track_caller]
+pub(super) fn for_all_graphs(
+    name: &str,
+    domains: &[Domain],
+    count: usize,
+    depth: usize,
+    property: impl Fn(&[Graph], &[Vec<f64>]) -> Outcome,
+) {
+    let (seed, mut rng) = rng(name);
+    for case in 0..cases() {
+        let mut graphs: Vec<Graph> = (0..count)
+            .map(|_| Graph::random(&mut rng, domains.len(), depth))
+            .collect();
+        let inputs = random_inputs(&mut rng, domains);
+        if let Err(mut failure) = property(&graphs, &inputs) {
+            // the smallest failing subgraphs, then the inputs
+            'progress: loop {
+                for k in 0..count {
+                    let mut candidates = graphs[k].subgraphs();
+                    candidates.sort_by_key(Graph::size);
+                    for candidate in candidates {
+                        let mut shrunk = graphs.clone();
+                        shrunk[k] = candidate;
+                        if let Err(f) = property(&shrunk, &inputs) {
+                            (graphs, failure) = (shrunk, f);
+                            continue 'progress;
+                        }
+                    }
+                }
+                break;
+            }
+            let (inputs, failure) = shrink_inputs(domains, inputs, failure, &|inputs| {
+                property(&graphs, inputs)
+            });
+            let graphs: Vec<String> = graphs.iter().map(Graph::to_string).collect();
+            panic!(
+                "property `{name}` failed (seed {seed:#x}, case {case}) at element {}: {}\ngraphs: {graphs:?}\ninputs: {inputs:?}",
+                failure.index, failure.detail
+            );
+        }
+    }
+}
+
+/// The offending element alone if it still fails, otherwise the fewest elements,
+/// then its simplest values
+fn shrink_inputs(
+    domains: &[Domain],
+    mut inputs: Vec<Vec<f64>>,
+    mut failure: Counterexample,
+    property: &impl Fn(&[Vec<f64>]) -> Outcome,
+) -> (Vec<Vec<f64>>, Counterexample) {
+    let column: Vec<Vec<f64>> = inputs
+        .iter()
+        .map(|input| vec![input[failure.index]])
+        .collect();
+    if let Err(f) = property(&column) {
+        (inputs, failure) = (column, f);
+    }
+    let mut i = 0;
+    while i < inputs[0].len() && inputs[0].len() > 1 {
+        if i == failure.index {
+            i += 1;
+            continue;
+        }
+        let candidate: Vec<Vec<f64>> = inputs
+            .iter()
+            .map(|input| {
+                let mut input = input.clone();
+                input.remove(i);
+                input
+            })
+            .collect();
+        match property(&candidate) {
+            Err(f) => (inputs, failure) = (candidate, f),
+            Ok(()) => i += 1,
+        }
+    }
+    let mut runs = 0;
+    'progress: loop {
+        for (k, domain) in domains.iter().enumerate() {
+            for simpler in domain.shrink(inputs[k][failure.index]) {
+                runs += 1;
+                if runs > SHRINK_RUNS {
+                    break 'progress;
+                }
+                let mut candidate = inputs.clone();
+                candidate[k][failure.index] = simpler;
+                if let Err(f) = property(&candidate) {
+                    (inputs, failure) = (candidate, f);
+                    continue 'progress;
+                }
+            }
+        }
+        break;
+    }
+    (inputs, failure)
+}
+
+/// Value and derivative along a direction, the forward-mode reference of the backward
+#[derive(Clone, Copy, Debug)]
+pub(super) struct Dual {
+    pub(super) value: f64,
+    pub(super) derivative: f64,
+}
+
+impl Dual {
+    #[inline]
+    pub(super) fn new(value: f64, derivative: f64) -> Self {
+        Self { value, derivative }
+    }
+    /// Every unary op, with the same conventions as the backward:
+    /// a zero slope for the steps, and at the kinks of `abs` and `saturate01`
+    pub(super) fn unary(op: UnaryOp, x: Self) -> Self {
+        let (value, slope) = match op {
+            UnaryOp::LogicNot => (1.0 - x.value, -1.0),
+            UnaryOp::Saturate01 => (
+                x.value.clamp(0.0, 1.0),
+                if (0.0..=1.0).contains(&x.value) {
+                    1.0
+                } else {
+                    0.0
+                },
+            ),
+            UnaryOp::Neg => (-x.value, -1.0),
+            UnaryOp::Sin => (x.value.sin(), x.value.cos()),
+            UnaryOp::Cos => (x.value.cos(), -x.value.sin()),
+            UnaryOp::Tanh => {
+                let tanh = x.value.tanh();
+                (tanh, 1.0 - tanh * tanh)
+            }
+            UnaryOp::Tan => {
+                let tan = x.value.tan();
+                (tan, 1.0 + tan * tan)
+            }
+            UnaryOp::Ceil => (x.value.ceil(), 0.0),
+            UnaryOp::Floor => (x.value.floor(), 0.0),
+            UnaryOp::Round => (x.value.round(), 0.0),
+            UnaryOp::Sign => (x.value.signum(), 0.0),
+            UnaryOp::Sqrt => (x.value.sqrt(), 0.5 / x.value.sqrt()),
+            UnaryOp::Sqr => (x.value * x.value, 2.0 * x.value),
+            UnaryOp::Cubic => (x.value.powi(3), 3.0 * x.value * x.value),
+            UnaryOp::Log => (x.value.ln(), 1.0 / x.value),
+            UnaryOp::Exp => (x.value.exp(), x.value.exp()),
+            UnaryOp::LimExp if x.value <= LIMEXP_X0 => (x.value.exp(), x.value.exp()),
+            UnaryOp::LimExp => (
+                LIMEXP_X0.exp() * (1.0 + x.value - LIMEXP_X0),
+                LIMEXP_X0.exp(),
+            ),
+            UnaryOp::Abs if x.value == 0.0 => (0.0, 0.0),
+            UnaryOp::Abs => (x.value.abs(), x.value.signum()),
+            UnaryOp::Erf => (
+                candle_core::cpu::erf::erf(x.value),
+                2.0 / std::f64::consts::PI.sqrt() * (-x.value * x.value).exp(),
+            ),
+        };
+        Self::new(value, slope * x.derivative)
+    }
+    /// Every binary op, with the same tie conventions as the backward
+    pub(super) fn binary(op: BinaryOp, lhs: Self, rhs: Self) -> Self {
+        let (a, b) = (lhs.value, rhs.value);
+        let (value, slope_lhs, slope_rhs) = match op {
+            BinaryOp::Add => (a + b, 1.0, 1.0),
+            BinaryOp::Sub => (a - b, 1.0, -1.0),
+            BinaryOp::Mul => (a * b, b, a),
+            BinaryOp::Div => (a / b, 1.0 / b, -a / (b * b)),
+            BinaryOp::Pow => {
+                let value = a.powf(b);
+                (value, b * value / a, value * a.ln())
+            }
+            BinaryOp::Atan2 => {
+                let norm2 = a * a + b * b;
+                if norm2 > 0.0 {
+                    (a.atan2(b), b / norm2, -a / norm2)
+                } else {
+                    (a.atan2(b), 0.0, 0.0)
+                }
+            }
+            BinaryOp::Min(tie) | BinaryOp::Max(tie) if a == b => {
+                let share = match tie {
+                    TiePolicy::Split => 0.5,
+                    TiePolicy::Lhs => 1.0,
+                    TiePolicy::Rhs => 0.0,
+                };
+                (a, share, 1.0 - share)
+            }
+            BinaryOp::Min(_) if a < b => (a, 1.0, 0.0),
+            BinaryOp::Min(_) => (b, 0.0, 1.0),
+            BinaryOp::Max(_) if a > b => (a, 1.0, 0.0),
+            BinaryOp::Max(_) => (b, 0.0, 1.0),
+            BinaryOp::LogicAnd => (a * b, b, a),
+            BinaryOp::LogicOr => ((a + b - a * b).clamp(0.0, 1.0), 1.0 - b, 1.0 - a),
+            BinaryOp::LogicXor => (
+                (a + b - 2.0 * a * b).clamp(0.0, 1.0),
+                1.0 - 2.0 * b,
+                1.0 - 2.0 * a,
+            ),
+            BinaryOp::LogicNand => (1.0 - a * b, -b, -a),
+            BinaryOp::LogicNor => ((1.0 - a) * (1.0 - b), b - 1.0, a - 1.0),
+        };
+        Self::new(
+            value,
+            slope_lhs * lhs.derivative + slope_rhs * rhs.derivative,
+        )
+    }
+}
+
+/// The smooth unary ops of the random graphs
+const GRAPH_UNARY: [UnaryOp; 5] = [
+    UnaryOp::Neg,
+    UnaryOp::Sin,
+    UnaryOp::Cos,
+    UnaryOp::Tanh,
+    UnaryOp::Sqr,
+];
+/// Every unary op, see [`unary_domain`]
+pub(super) const EVERY_UNARY: [UnaryOp; 19] = [
+    UnaryOp::LogicNot,
+    UnaryOp::Saturate01,
+    UnaryOp::Neg,
+    UnaryOp::Sin,
+    UnaryOp::Cos,
+    UnaryOp::Tanh,
+    UnaryOp::Tan,
+    UnaryOp::Ceil,
+    UnaryOp::Floor,
+    UnaryOp::Round,
+    UnaryOp::Sign,
+    UnaryOp::Sqrt,
+    UnaryOp::Sqr,
+    UnaryOp::Cubic,
+    UnaryOp::Log,
+    UnaryOp::Exp,
+    UnaryOp::LimExp,
+    UnaryOp::Abs,
+    UnaryOp::Erf,
+];
+
+/// The domain where the op is compared with the [`Dual`] reference,
+/// the match is exhaustive so that a new op gets one
+pub(super) fn unary_domain(op: UnaryOp) -> Domain {
+    match op {
+        UnaryOp::LogicNot => Domain::LOGIC,
+        UnaryOp::Saturate01 => Domain::Range(-2.0, 3.0),
+        UnaryOp::Neg
+        | UnaryOp::Sin
+        | UnaryOp::Cos
+        | UnaryOp::Tanh
+        | UnaryOp::Ceil
+        | UnaryOp::Floor
+        | UnaryOp::Round
+        | UnaryOp::Sign
+        | UnaryOp::Sqr
+        | UnaryOp::Cubic
+        | UnaryOp::Exp
+        | UnaryOp::Abs
+        | UnaryOp::Erf => Domain::Range(-10.0, 10.0),
+        UnaryOp::Tan => Domain::Range(-1.5, 1.5),
+        UnaryOp::Sqrt | UnaryOp::Log => Domain::Range(0.1, 10.0),
+        UnaryOp::LimExp => Domain::Range(-10.0, 100.0),
+    }
+}
+
+/// Every binary op, see [`binary_domains`]
+pub(super) const EVERY_BINARY: [BinaryOp; 17] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Pow,
+    BinaryOp::Atan2,
+    BinaryOp::Min(TiePolicy::Split),
+    BinaryOp::Min(TiePolicy::Lhs),
+    BinaryOp::Min(TiePolicy::Rhs),
+    BinaryOp::Max(TiePolicy::Split),
+    BinaryOp::Max(TiePolicy::Lhs),
+    BinaryOp::Max(TiePolicy::Rhs),
+    BinaryOp::LogicAnd,
+    BinaryOp::LogicOr,
+    BinaryOp::LogicXor,
+    BinaryOp::LogicNand,
+    BinaryOp::LogicNor,
+];
+
+/// The domains of the `(lhs, rhs)` where the op is smooth enough to be compared
+/// with the [`Dual`] reference, the match is exhaustive so that a new op gets one
+pub(super) fn binary_domains(op: BinaryOp) -> [Domain; 2] {
+    match op {
+        BinaryOp::Add
+        | BinaryOp::Sub
+        | BinaryOp::Mul
+        | BinaryOp::Atan2
+        | BinaryOp::Min(_)
+        | BinaryOp::Max(_) => [Domain::Range(-10.0, 10.0); 2],
+        BinaryOp::Div => [Domain::Range(-10.0, 10.0), Domain::Magnitude(0.1, 10.0)],
+        BinaryOp::Pow => [Domain::Range(0.1, 10.0), Domain::Range(-3.0, 3.0)],
+        BinaryOp::LogicAnd
+        | BinaryOp::LogicOr
+        | BinaryOp::LogicXor
+        | BinaryOp::LogicNand
+        | BinaryOp::LogicNor => [Domain::LOGIC; 2],
+    }
+}
+
+/// The binary ops of the random graphs, defined everywhere
+const GRAPH_BINARY: [BinaryOp; 6] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Min(TiePolicy::Split),
+    BinaryOp::Max(TiePolicy::Split),
+    BinaryOp::Atan2,
+];
+
+/// The expression method of the unary op
+pub(super) fn apply_unary(op: UnaryOp, x: &Expression) -> Expression {
+    match op {
+        UnaryOp::LogicNot => x.logic_not(),
+        UnaryOp::Saturate01 => x.saturate01(),
+        UnaryOp::Neg => x.neg(),
+        UnaryOp::Sin => x.sin(),
+        UnaryOp::Cos => x.cos(),
+        UnaryOp::Tanh => x.tanh(),
+        UnaryOp::Tan => x.tan(),
+        UnaryOp::Ceil => x.ceil(),
+        UnaryOp::Floor => x.floor(),
+        UnaryOp::Round => x.round(),
+        UnaryOp::Sign => x.sign(),
+        UnaryOp::Sqrt => x.sqrt(),
+        UnaryOp::Sqr => x.sqr(),
+        UnaryOp::Cubic => x.cubic(),
+        UnaryOp::Log => x.log(),
+        UnaryOp::Exp => x.exp(),
+        UnaryOp::LimExp => x.limexp(),
+        UnaryOp::Abs => x.abs(),
+        UnaryOp::Erf => x.erf(),
+    }
+}
+
+/// The expression method of the binary op
+pub(super) fn apply_binary(op: BinaryOp, lhs: &Expression, rhs: &Expression) -> Expression {
+    match op {
+        BinaryOp::Add => lhs.add(rhs),
+        BinaryOp::Sub => lhs.sub(rhs),
+        BinaryOp::Mul => lhs.mul(rhs),
+        BinaryOp::Div => lhs.div(rhs),
+        BinaryOp::Pow => lhs.pow(rhs),
+        BinaryOp::Atan2 => lhs.atan2(rhs),
+        BinaryOp::Min(tie) => lhs.min_tie(rhs, tie),
+        BinaryOp::Max(tie) => lhs.max_tie(rhs, tie),
+        BinaryOp::LogicAnd => lhs.logic_and(rhs),
+        BinaryOp::LogicOr => lhs.logic_or(rhs),
+        BinaryOp::LogicXor => lhs.logic_xor(rhs),
+        BinaryOp::LogicNand => lhs.logic_nand(rhs),
+        BinaryOp::LogicNor => lhs.logic_nor(rhs),
+    }
+}
+
+/// Random element-wise graph over the leaves,
+/// built into an [`Expression`] and evaluated by the [`Dual`] reference
+#[derive(Clone, Debug)]
+pub(super) enum Graph {
+    Leaf(usize),
+    Const(f64),
+    Unary(UnaryOp, Box<Graph>),
+    Binary(BinaryOp, Box<Graph>, Box<Graph>),
+}
+
+impl Graph {
+    /// At most `depth` ops from the root to the leaves
+    pub(super) fn random(rng: &mut StdRng, leaves: usize, depth: usize) -> Self {
+        match rng.gen_range(0..if depth == 0 { 2 } else { 5 }) {
+            0 if rng.gen_bool(0.2) => {
+                Self::Const((rng.gen_range(-2.0..2.0) * 4.0_f64).round() / 4.0)
+            }
+            0 | 1 => Self::Leaf(rng.gen_range(0..leaves)),
+            2 => Self::Unary(
+                *GRAPH_UNARY.choose(rng).unwrap(),
+                Box::new(Self::random(rng, leaves, depth - 1)),
+            ),
+            _ => Self::Binary(
+                *GRAPH_BINARY.choose(rng).unwrap(),
+                Box::new(Self::random(rng, leaves, depth - 1)),
+                Box::new(Self::random(rng, leaves, depth - 1)),
+            ),
+        }
+    }
+    pub(super) fn build(&self, leaves: &[Expression]) -> Expression {
+        match self {
+            Self::Leaf(k) => leaves[*k].clone(),
+            Self::Const(x) => Expression::constant(*x),
+            Self::Unary(op, x) => apply_unary(*op, &x.build(leaves)),
+            Self::Binary(op, lhs, rhs) => apply_binary(*op, &lhs.build(leaves), &rhs.build(leaves)),
+        }
+    }
+    /// One element, differentiated w.r.t. the `wrt` leaf
+    pub(super) fn eval(&self, xs: &[f64], wrt: usize) -> Dual {
+        match self {
+            Self::Leaf(k) => Dual::new(xs[*k], if *k == wrt { 1.0 } else { 0.0 }),
+            Self::Const(x) => Dual::new(*x, 0.0),
+            Self::Unary(op, x) => Dual::unary(*op, x.eval(xs, wrt)),
+            Self::Binary(op, lhs, rhs) => Dual::binary(*op, lhs.eval(xs, wrt), rhs.eval(xs, wrt)),
+        }
+    }
+    /// The reference values, and the derivatives w.r.t. each leaf
+    pub(super) fn reference(&self, inputs: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+        let len = inputs.first().map_or(0, Vec::len);
+        let column = |i: usize| inputs.iter().map(|input| input[i]).collect::<Vec<_>>();
+        let values = (0..len).map(|i| self.eval(&column(i), 0).value).collect();
+        let derivatives = (0..inputs.len())
+            .map(|wrt| {
+                (0..len)
+                    .map(|i| self.eval(&column(i), wrt).derivative)
+                    .collect()
+            })
+            .collect();
+        (values, derivatives)
+    }
+    fn size(&self) -> usize {
+        match self {
+            Self::Leaf(_) | Self::Const(_) => 1,
+            Self::Unary(_, x) => 1 + x.size(),
+            Self::Binary(_, lhs, rhs) => 1 + lhs.size() + rhs.size(),
+        }
+    }
+    /// The proper subgraphs
+    fn subgraphs(&self) -> Vec<Self> {
+        match self {
+            Self::Leaf(_) | Self::Const(_) => Vec::new(),
+            Self::Unary(_, x) => [x.as_ref().clone()]
+                .into_iter()
+                .chain(x.subgraphs())
+                .collect(),
+            Self::Binary(_, lhs, rhs) => [lhs.as_ref().clone(), rhs.as_ref().clone()]
+                .into_iter()
+                .chain(lhs.subgraphs())
+                .chain(rhs.subgraphs())
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Leaf(k) => write!(f, "x{k}"),
+            Self::Const(x) => write!(f, "{x}"),
+            Self::Unary(op, x) => write!(f, "{op:?}({x})"),
+            Self::Binary(op, lhs, rhs) => write!(f, "{op:?}({lhs}, {rhs})"),
+        }
+    }
+}
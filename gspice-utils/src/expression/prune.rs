@@ -0,0 +1,124 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering::Relaxed},
+};
+
+use num_traits::identities::{One, Zero};
+
+use super::{op::Cond, Expression, Op, Tensor};
+
+static PRUNE_DEAD_BRANCHES: AtomicBool = AtomicBool::new(false);
+
+/// The [`on_true`](Expression::cond) branch of a [`Cond`] is skipped
+pub(super) const PRUNED_ON_TRUE: u8 = 0b01;
+/// The [`on_false`](Expression::cond) branch of a [`Cond`] is skipped
+pub(super) const PRUNED_ON_FALSE: u8 = 0b10;
+
+/// Skip the dead branch of the [`cond`](Expression::cond) ops whose condition
+/// is provably constant, disabled by default since it changes the output of the blend
+///
+/// At each recompute, a `cond` tensor whose values are all `1` (all `0`) and whose
+/// leaves are all frozen (see [`TensorRef::set_requires_grad`](super::TensorRef::set_requires_grad))
+/// picks `on_true` (`on_false`) without evaluating the other branch, nor its subtree.
+/// The pruning is decided again at every recompute, so updating the condition
+/// to other values, or unfreezing its leaves, evaluates both branches again
+///
+/// + The skipped nodes are flagged, and fully recomputed when they are read again,
+///   so that the updates missed in the meantime are not lost
+/// + The output is the live branch itself, as if the dead one was finite:
+///   the smooth blend would give `NaN` for an infinite dead branch
+/// + The dead branch gets zero gradients, computed from its last values
+/// + The dead branch is not pruned when it sets (or mismatches) the broadcast length
+///   of the output, by its last length
+#[inline]
+pub fn set_prune_dead_branches(enabled: bool) {
+    PRUNE_DEAD_BRANCHES.store(enabled, Relaxed);
+}
+
+/// See [`set_prune_dead_branches`]
+#[inline]
+pub fn prune_dead_branches() -> bool {
+    PRUNE_DEAD_BRANCHES.load(Relaxed)
+}
+
+impl Cond {
+    /// The branch picked by a `cond` whose values are all `1` (`Some(true)`)
+    /// or all `0` (`Some(false)`), and which gets no gradient, i.e., all its leaves are frozen
+    ///
+    /// `None` when both branches are needed, or the dead one sets (or mismatches)
+    /// the output length
+    pub(super) fn live_branch(
+        cond: &Tensor,
+        on_true: &Expression,
+        on_false: &Expression,
+    ) -> Option<bool> {
+        if !prune_dead_branches() {
+            return None;
+        }
+        let (live_on_true, cond_len) = {
+            let values = cond.read();
+            match values.first() {
+                Some(x) if x.is_one() && values.iter().all(One::is_one) => (true, values.len()),
+                Some(x) if x.is_zero() && values.iter().all(Zero::is_zero) => (false, values.len()),
+                _ => return None,
+            }
+        };
+        let (live, dead) = if live_on_true {
+            (on_true, on_false)
+        } else {
+            (on_false, on_true)
+        };
+        let len = |expr: &Expression| match expr {
+            Expression::Const(_) => None,
+            Expression::Tensor(tensor) => Some(tensor.values_len()),
+        };
+        // a mismatch is reported by the full recompute
+        let full_len = Self::len([Some(cond_len), len(live), len(dead)]).ok()?;
+        let live_len = Self::len([Some(cond_len), len(live), None]).ok()?;
+        (full_len == live_len && !cond.requires_grad_upstream()).then_some(live_on_true)
+    }
+}
+
+impl Tensor {
+    /// Whether a leaf with gradient is upstream, i.e., `self` is not frozen
+    fn requires_grad_upstream(&self) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![self];
+        while let Some(tensor) = stack.pop() {
+            if !visited.insert(tensor.key()) {
+                continue;
+            }
+            match tensor.op() {
+                Op::Assign if tensor.with_grad() => return true,
+                Op::Assign => {}
+                op => stack.extend(op.inputs().into_iter().filter_map(|input| match input {
+                    Expression::Const(_) => None,
+                    Expression::Tensor(input) => Some(input),
+                })),
+            }
+        }
+        false
+    }
+}
+
+/// Flag the stale op nodes of the skipped `branch`, see [`set_prune_dead_branches`]
+///
+/// The walk stops at the leaves and at the nodes already searched in this epoch,
+/// whose inputs are up to date
+pub(super) fn mark_skipped(branch: &Expression) {
+    let mut visited = HashSet::new();
+    let mut stack = vec![branch];
+    while let Some(expr) = stack.pop() {
+        let Expression::Tensor(tensor) = expr else {
+            continue;
+        };
+        if matches!(tensor.op(), Op::Assign)
+            || !tensor.change_marker().is_stale()
+            || !visited.insert(tensor.key())
+        {
+            continue;
+        }
+        tensor.change_marker().mark_forced();
+        stack.extend(tensor.op().inputs());
+    }
+}
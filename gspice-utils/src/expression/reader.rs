@@ -0,0 +1,83 @@
+use std::io::Read;
+
+use super::Error;
+
+/// Little-endian reads of the binary formats: the [raw tables](super::io), the snapshots
+/// and the [checkpoints](super::optimizer::Checkpoint), the error of the format on a short
+/// or failed read
+pub(super) struct LeReader<R> {
+    inner: R,
+    /// The variant of [`Error`] of the format
+    error: fn(String) -> Error,
+    /// The format, e.g., `table`, named by the error of a truncated input
+    format: &'static str,
+}
+
+impl<R: Read> LeReader<R> {
+    #[inline]
+    pub(super) fn new(inner: R, error: fn(String) -> Error, format: &'static str) -> Self {
+        Self {
+            inner,
+            error,
+            format,
+        }
+    }
+    #[inline]
+    pub(super) fn bytes<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut buf = [0; N];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|e| (self.error)(e.to_string()))?;
+        Ok(buf)
+    }
+    #[inline]
+    pub(super) fn u32(&mut self) -> Result<u32, Error> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+    #[inline]
+    pub(super) fn u64(&mut self) -> Result<u64, Error> {
+        self.bytes().map(u64::from_le_bytes)
+    }
+    /// Read in place, so that a corrupted length fails at the end of the input
+    /// instead of allocating it upfront
+    pub(super) fn vec(&mut self, len: u64) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        let read = (&mut self.inner)
+            .take(len)
+            .read_to_end(&mut buf)
+            .map_err(|e| (self.error)(e.to_string()))?;
+        if read as u64 == len {
+            Ok(buf)
+        } else {
+            Err((self.error)(format!(
+                "unexpected end of the {}",
+                self.format
+            )))
+        }
+    }
+    /// A `u32` length and the UTF-8 bytes, `what` names it on error
+    #[inline]
+    pub(super) fn string(&mut self, what: impl FnOnce() -> String) -> Result<String, Error> {
+        let len = self.u32()?;
+        String::from_utf8(self.vec(len as u64)?)
+            .map_err(|_| (self.error)(format!("{} is not UTF-8", what())))
+    }
+    #[inline]
+    pub(super) fn values(&mut self, len: u64) -> Result<Vec<f64>, Error> {
+        let bytes = self.vec(len.saturating_mul(8))?;
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|chunk| f64::from_bits(u64::from_le_bytes(chunk.try_into().unwrap())))
+            .collect())
+    }
+    /// Whether the input is exhausted, to reject the trailing bytes
+    #[inline]
+    pub(super) fn at_end(&mut self) -> Result<bool, Error> {
+        let mut buf = Vec::new();
+        (&mut self.inner)
+            .take(1)
+            .read_to_end(&mut buf)
+            .map_err(|e| (self.error)(e.to_string()))?;
+        Ok(buf.is_empty())
+    }
+}
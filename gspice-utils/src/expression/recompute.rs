@@ -1,15 +1,148 @@
 use super::{
-    op::{BinaryOp, Cond, DiscreteBinaryOp, Powf, UnaryOp},
-    Expression, Op, ScalarTensor, Tensor,
+    assertion::AssertRange,
+    complex::ComplexOp,
+    ema::EmaState,
+    op::{
+        broadcast, broadcast_len, check_indices, AssignFrom, BinaryOp, Concat, Cond, CondMethod,
+        Conv1d, ConvPadding, CountGe, CrossTime, CustomBinary, CustomNary, CustomUnary, Ddt, Dft,
+        Diode, DiscreteBinaryOp, DivEps, Gather, GradMethod, Idt, Integral, LogicNary, MatVec,
+        Narrow, Polyval, Powf, Pwl, Reduce, Reverse, RobustLoss, ScatterAdd, Select, Shift,
+        SoftHistogram, Spectrum, Spline, Transition, UnaryOp, UnaryParamOp, Weighted, WindowReduce,
+    },
+    profile,
+    prune::{mark_skipped, PRUNED_ON_FALSE, PRUNED_ON_TRUE},
+    Error, Expression, Op, ScalarTensor, Tensor,
 };
 use itertools::izip;
-use num_traits::Zero;
-use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    sync::{
+        atomic::{
+            AtomicBool, AtomicU8, AtomicUsize,
+            Ordering::{Acquire, Relaxed, Release},
+        },
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::Instant,
+};
+
+/// Tensors constructed since the last evaluation, see [`Expression::recompute_root`]
+pub(super) static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// `(changed, unchanged)` nodes searched by this thread, see [`Expression::recompute_root`]
+    static SEARCHED: Cell<(usize, usize)> = const { Cell::new((0, 0)) };
+}
 
 #[cfg(test)]
 pub(crate) static TEST_RECOMPUTE_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// The tensors whose forward ran, in order
+#[cfg(test)]
+pub(crate) static TEST_FORWARD_LOG: Mutex<Vec<usize>> = Mutex::new(Vec::new());
 
 impl Expression {
+    /// Bring the value up to date now, and get it
+    ///
+    /// + The stale nodes are recomputed in topological order (the inputs before the node),
+    ///   each node at most once, even when it is shared by several paths
+    /// + The nodes without updated inputs since the last [`before_update`] are not recomputed
+    /// + Thread safe: the first evaluator reaching a stale node computes it, a concurrent
+    ///   evaluator (of this or another expression sharing the node) waits for it,
+    ///   then reuses the fresh value without recomputing it
+    #[inline]
+    pub fn eval(&self) -> ScalarTensor<'_> {
+        self.recompute_root().into()
+    }
+    /// [`eval`](Expression::eval), and whether the value changed since the last [`before_update`]
+    ///
+    /// Always `false` for a constant
+    #[inline]
+    pub fn refresh(&self) -> bool {
+        matches!(
+            self.recompute_root(),
+            RecomputeScalarTensor::TensorChanged(_)
+        )
+    }
+    /// [`recompute`](Expression::recompute) of an evaluation entry, with the debug logs
+    /// of the graph construction and the recomputation, once per evaluation
+    pub(super) fn recompute_root(&self) -> RecomputeScalarTensor<'_> {
+        if log::log_enabled!(target: "gspice::graph", log::Level::Debug) {
+            let constructed = CONSTRUCTED.swap(0, Relaxed);
+            if constructed != 0 {
+                log::debug!(
+                    target: "gspice::graph",
+                    "{constructed} nodes constructed since the last evaluation"
+                );
+            }
+        }
+        if !log::log_enabled!(target: "gspice::recompute", log::Level::Debug) {
+            return self.recompute_staged();
+        }
+        let (changed, unchanged) = SEARCHED.get();
+        let start = Instant::now();
+        let out = self.recompute_staged();
+        let elapsed = start.elapsed();
+        let (changed_after, unchanged_after) = SEARCHED.get();
+        log::debug!(
+            target: "gspice::recompute",
+            "{} nodes recomputed, {} unchanged, in {elapsed:?}",
+            changed_after - changed,
+            unchanged_after - unchanged,
+        );
+        out
+    }
+}
+
+/// Levels of the graph recomputed by one recursion, see [`Expression::recompute_staged`]
+const STAGE: usize = 256;
+
+impl Expression {
+    /// [`recompute`](Expression::recompute) with a bounded recursion: in a graph deeper than
+    /// [`STAGE`], the stale (or released) nodes ending a stage, i.e., with an input in a lower
+    /// stage of `depth / STAGE`, are recomputed first, bottom-up, so that each recursion stops
+    /// at the recomputed nodes of the stage below, within `2 * STAGE` levels
+    fn recompute_staged(&self) -> RecomputeScalarTensor<'_> {
+        let Self::Tensor(root) = self else {
+            return self.recompute();
+        };
+        if root.depth() <= STAGE {
+            return self.recompute();
+        }
+        let needs_recompute =
+            |tensor: &Tensor| tensor.is_released() || tensor.change_marker().is_stale();
+        // post order, so that the inputs are recomputed first
+        let mut visited = HashSet::new();
+        let mut order = Vec::new();
+        let mut stack: Vec<(&Tensor, bool)> = vec![(root, false)];
+        while let Some((tensor, expanded)) = stack.pop() {
+            if expanded {
+                let stage = tensor.depth() / STAGE;
+                let ends_stage = tensor.op().inputs().into_iter().any(
+                    |input| matches!(input, Self::Tensor(input) if input.depth() / STAGE < stage),
+                );
+                if ends_stage && tensor != root {
+                    order.push(tensor.clone());
+                }
+            } else if visited.insert(tensor.key()) {
+                stack.push((tensor, true));
+                stack.extend(
+                    tensor
+                        .op()
+                        .inputs()
+                        .into_iter()
+                        .filter_map(|input| match input {
+                            Self::Tensor(input) if needs_recompute(input) => Some((input, false)),
+                            _ => None,
+                        }),
+                );
+            }
+        }
+        for tensor in order {
+            _ = Self::Tensor(tensor).recompute();
+        }
+        self.recompute()
+    }
     pub(super) fn recompute<'a>(&'a self) -> RecomputeScalarTensor<'a> {
         #[cfg(test)]
         {
@@ -17,24 +150,124 @@ impl Expression {
         }
         match self {
             Expression::Const(f) => RecomputeScalarTensor::Scalar(f),
+            Expression::Tensor(tensor) if tensor.is_released() => {
+                tensor.recompute_released();
+                RecomputeScalarTensor::TensorChanged(tensor)
+            }
             Expression::Tensor(tensor) => match tensor.change_marker().change_state() {
                 ChangeState::Changed => RecomputeScalarTensor::TensorChanged(tensor),
-                ChangeState::NoChange => RecomputeScalarTensor::TensorNoChange(tensor),
-                ChangeState::NeedSearch => match tensor.op() {
-                    Op::Assgin => RecomputeScalarTensor::nochange(tensor),
-                    Op::Powf(node, n) => Powf::recompute(*n, node, tensor),
-                    Op::Cond(cond, on_true, on_false) => {
-                        Cond::recompute(cond, on_true, on_false, tensor)
+                ChangeState::NoChange => Self::nochange_or_forced(tensor),
+                ChangeState::NeedSearch => {
+                    // the first evaluator reaching the stale node computes it,
+                    // the others wait and reuse the result
+                    let _claim = tensor.change_marker().claim();
+                    match tensor.change_marker().change_state() {
+                        ChangeState::Changed => RecomputeScalarTensor::TensorChanged(tensor),
+                        ChangeState::NoChange => Self::nochange_or_forced(tensor),
+                        ChangeState::NeedSearch => {
+                            // a node skipped in a pruned branch may have missed the updates
+                            let forced = FORCED.replace(tensor.change_marker().take_forced());
+                            let out = profile::forward(tensor, || Self::search(tensor));
+                            FORCED.set(forced);
+                            tensor.release_consumed_inputs();
+                            match out {
+                                RecomputeScalarTensor::TensorNoChange(tensor) => {
+                                    Self::nochange_or_forced(tensor)
+                                }
+                                out => out,
+                            }
+                        }
                     }
-                    Op::Unary(node, unary_op) => unary_op.recompute(node, tensor),
-                    Op::Binary(lhs, rhs, binary_op) => binary_op.recompute(lhs, rhs, tensor),
-                    Op::DiscreteBinary(lhs, rhs, discrete_binary_op, _) => {
-                        discrete_binary_op.recompute(lhs, rhs, tensor)
-                    }
-                },
+                }
             },
         }
     }
+    /// An unchanged input is read as changed by a [forced search](Tensor::search_forced)
+    #[inline]
+    fn nochange_or_forced(tensor: &Tensor) -> RecomputeScalarTensor<'_> {
+        if FORCED.get() {
+            RecomputeScalarTensor::TensorChanged(tensor)
+        } else {
+            RecomputeScalarTensor::TensorNoChange(tensor)
+        }
+    }
+}
+
+impl Tensor {
+    /// Recompute the values from the current values of the inputs, regardless of
+    /// the change markers: the inputs are read as changed, without marking them,
+    /// so the other consumers of the inputs are not recomputed
+    pub(super) fn search_forced(&self) {
+        let _claim = self.change_marker().claim();
+        let forced = FORCED.replace(true);
+        _ = profile::forward(self, || Expression::search(self));
+        FORCED.set(forced);
+        self.release_consumed_inputs();
+    }
+}
+
+impl Expression {
+    /// Recompute the inputs, then the stale tensor when any input changed
+    fn search(tensor: &Tensor) -> RecomputeScalarTensor<'_> {
+        match tensor.op() {
+            Op::Assign => RecomputeScalarTensor::nochange(tensor),
+            Op::AssignFrom(node) => AssignFrom::recompute(node, tensor),
+            Op::Powf(node, n) => Powf::recompute(*n, node, tensor),
+            Op::Pwl(node, pwl) => pwl.recompute(node, tensor),
+            Op::Spline(node, spline) => spline.recompute(node, tensor),
+            Op::Diode(node, diode) => diode.recompute(node, tensor),
+            Op::Transition(node, transition) => transition.recompute(node, tensor),
+            Op::UnaryParam(node, param, unary_param_op) => {
+                unary_param_op.recompute(*param, node, tensor)
+            }
+            Op::DivEps(lhs, rhs, eps) => DivEps::recompute(*eps, lhs, rhs, tensor),
+            Op::RobustLoss(lhs, rhs, loss) => loss.recompute(lhs, rhs, tensor),
+            Op::Custom(node, custom) => custom.recompute(node, tensor),
+            Op::ExternalUnary(node, id) => id.get().recompute(node, tensor),
+            Op::CustomBinary(lhs, rhs, custom) => custom.recompute(lhs, rhs, tensor),
+            Op::CustomNary(inputs, custom) => custom.recompute(inputs, tensor),
+            Op::Select(conds, values, default, method) => {
+                Select::recompute(conds, values, default, *method, tensor)
+            }
+            Op::Cond(cond, on_true, on_false, method) => {
+                Cond::recompute(cond, on_true, on_false, method, tensor)
+            }
+            Op::LogicNary(terms, op) => op.recompute(terms, tensor),
+            Op::Concat(parts) => Concat::recompute(parts, tensor),
+            Op::Narrow(node, offset, len) => Narrow::recompute(node, *offset, *len, tensor),
+            Op::Gather(node, indices) => Gather::recompute(node, indices, tensor),
+            Op::ScatterAdd(node, indices, len) => {
+                ScatterAdd::recompute(node, indices, *len, tensor)
+            }
+            Op::Reverse(node) => Reverse::recompute(node, tensor),
+            Op::Assert(node, range) => range.recompute(node, tensor),
+            Op::Shift(node, offset, fill) => Shift::recompute(node, *offset, *fill, tensor),
+            Op::Ddt(node, dt) => Ddt::recompute(node, *dt, tensor),
+            Op::Idt(node, dt, initial) => Idt::recompute(node, *dt, *initial, tensor),
+            Op::Spectrum(node, spectrum) => Dft::recompute(node, *spectrum, tensor),
+            Op::CrossTime(node, cross) => cross.recompute(node, tensor),
+            Op::Integrate(node, integral) => integral.recompute(node, tensor),
+            Op::WindowReduce(node, reduce) => reduce.recompute(node, tensor),
+            Op::Reduce(node, reduce) => reduce.recompute(node, tensor),
+            Op::CountGe(node, count) => count.recompute(node, tensor),
+            Op::SoftHistogram(node, histogram) => histogram.recompute(node, tensor),
+            Op::Conv1d(signal, kernel, padding) => {
+                Conv1d::recompute(signal, kernel, *padding, tensor)
+            }
+            Op::MatVec(input, weights, rows, cols) => {
+                MatVec::recompute(input, weights, *rows, *cols, tensor)
+            }
+            Op::Polyval(input, coeffs) => Polyval::recompute(input, coeffs, tensor),
+            Op::Ema(node, alpha, state) => state.recompute(node, *alpha, tensor),
+            Op::Weighted(input, weights, weighted) => weighted.recompute(input, weights, tensor),
+            Op::Complex(inputs, complex) => complex.recompute(inputs, tensor),
+            Op::Unary(node, unary_op) => unary_op.recompute(node, tensor),
+            Op::Binary(lhs, rhs, binary_op) => binary_op.recompute(lhs, rhs, tensor),
+            Op::DiscreteBinary(lhs, rhs, discrete_binary_op, grad_method) => {
+                discrete_binary_op.recompute(lhs, rhs, grad_method, tensor)
+            }
+        }
+    }
 }
 
 enum ChangeState {
@@ -61,21 +294,59 @@ impl<'a> From<RecomputeScalarTensor<'a>> for ScalarTensor<'a> {
 
 impl<'a> RecomputeScalarTensor<'a> {
     fn change(tensor: &'a Tensor, values: Vec<f64>) -> Self {
-        let mut write = tensor.values().write().unwrap();
+        tensor.op().check_anomaly(&values);
+        profile::count_allocation();
+        #[cfg(test)]
+        TEST_FORWARD_LOG
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(tensor.key());
+        let mut write = tensor.write();
         *write = values;
         tensor.change_marker().mark_searched_change();
+        SEARCHED.set({
+            let (changed, unchanged) = SEARCHED.get();
+            (changed + 1, unchanged)
+        });
         RecomputeScalarTensor::TensorChanged(tensor)
     }
     fn nochange(tensor: &'a Tensor) -> Self {
         tensor.change_marker().mark_searched_nochange();
+        SEARCHED.set({
+            let (changed, unchanged) = SEARCHED.get();
+            (changed, unchanged + 1)
+        });
         Self::TensorNoChange(tensor)
     }
 }
 
 static COUNTER: AtomicUsize = AtomicUsize::new(0);
+thread_local! {
+    /// The thread's own counter inside [`with_local_epoch`]
+    static LOCAL_COUNTER: Cell<Option<usize>> = const { Cell::new(None) };
+    /// The search of this thread reads its unchanged inputs as changed,
+    /// see [`Tensor::search_forced`]
+    static FORCED: Cell<bool> = const { Cell::new(false) };
+}
 pub fn before_update() {
-    // No need async, use Relaxed
-    COUNTER.fetch_add(2, Relaxed);
+    match LOCAL_COUNTER.get() {
+        Some(counter) => LOCAL_COUNTER.set(Some(counter + 2)),
+        // No need async, use Relaxed
+        None => _ = COUNTER.fetch_add(2, Relaxed),
+    }
+}
+#[inline]
+pub(super) fn counter() -> usize {
+    LOCAL_COUNTER.get().unwrap_or_else(|| COUNTER.load(Relaxed))
+}
+/// Run `f` with a thread-local counter, so that [`before_update`] of other threads
+/// does not invalidate the change markers, only for a graph used by this thread alone
+#[cfg_attr(not(feature = "rayon"), allow(dead_code))]
+pub(super) fn with_local_epoch<T>(f: impl FnOnce() -> T) -> T {
+    let outer = LOCAL_COUNTER.replace(Some(COUNTER.load(Relaxed)));
+    let out = f();
+    LOCAL_COUNTER.set(outer);
+    out
 }
 
 /// When ChangeMarker::COUNTER is 2n,
@@ -87,21 +358,75 @@ pub fn before_update() {
 /// 2n+2 : searched, no change inside
 ///
 /// update tensor makes its marker become 2n+1
+///
+/// Protocol:
+/// + [`before_update`] bumps the counter, so that all the markers become "not searched" (stale)
+/// + the updates of leaf tensors ([`TensorRef::update`](super::TensorRef::update),
+///   [`Tensor::mark_changed`], ...) mark them as "searched, change"
+/// + [`Expression::value`] searches the stale tensors from the output to the leaves,
+///   recomputes the ones with any changed input and marks them "searched, change",
+///   the others are marked "searched, no change inside"
+/// + the searched markers are only reset by the next [`before_update`]
+///
+/// The marker is published (`Release`) after the values are written, so an evaluator
+/// observing a searched marker (`Acquire`) never reads a partially written buffer
 #[derive(Debug)]
-pub(crate) struct ChangeMarker(AtomicUsize);
+pub(crate) struct ChangeMarker {
+    marker: AtomicUsize,
+    /// Number of the changes marked, see [`Tensor::version`]
+    version: AtomicUsize,
+    /// Held while the stale tensor is searched / recomputed
+    claim: Mutex<()>,
+    /// Branches skipped by the latest recompute of a [`Cond`], see [`Cond::live_branch`]
+    pruned: AtomicU8,
+    /// Skipped in a pruned branch while stale, see [`mark_skipped`](super::prune::mark_skipped),
+    /// or its [sharpness](super::SharpnessHandle::set) changed
+    forced: AtomicBool,
+}
 impl ChangeMarker {
     pub(super) const fn new() -> Self {
-        Self(AtomicUsize::new(2))
+        Self {
+            marker: AtomicUsize::new(2),
+            version: AtomicUsize::new(0),
+            claim: Mutex::new(()),
+            pruned: AtomicU8::new(0),
+            forced: AtomicBool::new(false),
+        }
     }
     pub(super) fn mark_searched_change(&self) {
-        self.0.store(COUNTER.load(Relaxed) + 1, Relaxed);
+        _ = self.version.fetch_add(1, Relaxed);
+        self.marker.store(counter() + 1, Release);
+    }
+    #[inline]
+    pub(super) fn version(&self) -> usize {
+        self.version.load(Relaxed)
     }
     fn mark_searched_nochange(&self) {
-        self.0.store(COUNTER.load(Relaxed) + 2, Relaxed);
+        self.marker.store(counter() + 2, Release);
+    }
+    /// Set the pruned branches, and get the previous ones
+    fn swap_pruned(&self, pruned: u8) -> u8 {
+        self.pruned.swap(pruned, Relaxed)
+    }
+    /// Force the next search to recompute the values regardless of the inputs,
+    /// the updates of the inputs may have been missed
+    pub(super) fn mark_forced(&self) {
+        self.forced.store(true, Relaxed);
+    }
+    fn take_forced(&self) -> bool {
+        self.forced.swap(false, Relaxed)
+    }
+    /// The claims are taken from the output to the inputs, so the evaluators never deadlock
+    fn claim(&self) -> MutexGuard<'_, ()> {
+        self.claim.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    #[inline]
+    pub(super) fn is_stale(&self) -> bool {
+        matches!(self.change_state(), ChangeState::NeedSearch)
     }
     fn change_state(&self) -> ChangeState {
-        let counter = COUNTER.load(Relaxed);
-        match counter + 2 - self.0.load(Relaxed) {
+        let counter = counter();
+        match counter + 2 - self.marker.load(Acquire) {
             1 => ChangeState::Changed,
             0 => ChangeState::NoChange,
             _ => ChangeState::NeedSearch,
@@ -110,7 +435,6 @@ impl ChangeMarker {
 }
 
 impl BinaryOp {
-    #[rustfmt::skip]
     fn recompute<'a>(
         &self,
         lhs: &Expression,
@@ -118,31 +442,127 @@ impl BinaryOp {
         tensor: &'a Tensor,
     ) -> RecomputeScalarTensor<'a> {
         let [fn_forward_lhs_rhs, fn_forward_rhs_lhs] = self.forward();
-        match (lhs.recompute(), rhs.recompute()) {
-            (RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::Scalar(_))
-                => unreachable!(),
-            (RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::TensorNoChange(_))
-            | (RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::Scalar(_))
-            | (RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::TensorNoChange(_))
-                => RecomputeScalarTensor::nochange(tensor),
-            (RecomputeScalarTensor::Scalar(lhs_x), RecomputeScalarTensor::TensorChanged(rhs_tensor))
-                => RecomputeScalarTensor::change(
-                    tensor,
-                    rhs_tensor.broadcast_iter_binary_op(*lhs_x, fn_forward_rhs_lhs),
-                ),
-            (RecomputeScalarTensor::TensorChanged(lhs_tensor), RecomputeScalarTensor::Scalar(rhs_x))
-                => RecomputeScalarTensor::change(
-                    tensor,
-                    lhs_tensor.broadcast_iter_binary_op(*rhs_x, fn_forward_lhs_rhs),
-                ),
-            (RecomputeScalarTensor::TensorChanged(lhs_tensor), RecomputeScalarTensor::TensorNoChange(rhs_tensor))
-            | (RecomputeScalarTensor::TensorChanged(lhs_tensor), RecomputeScalarTensor::TensorChanged(rhs_tensor))
-            | (RecomputeScalarTensor::TensorNoChange(lhs_tensor), RecomputeScalarTensor::TensorChanged(rhs_tensor))
-                => RecomputeScalarTensor::change(
-                    tensor,
-                    lhs_tensor.iter_binary_op(rhs_tensor, fn_forward_lhs_rhs),
-                ),
+        binary_recompute(lhs, rhs, tensor, fn_forward_lhs_rhs, fn_forward_rhs_lhs)
+    }
+}
+
+impl DivEps {
+    fn recompute<'a>(
+        eps: f64,
+        lhs: &Expression,
+        rhs: &Expression,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        binary_recompute(
+            lhs,
+            rhs,
+            tensor,
+            |lhs_x, rhs_x| Self::forward(lhs_x, rhs_x, eps),
+            |rhs_x, lhs_x| Self::forward(lhs_x, rhs_x, eps),
+        )
+    }
+}
+
+impl RobustLoss {
+    fn recompute<'a>(
+        &self,
+        lhs: &Expression,
+        rhs: &Expression,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        binary_recompute(
+            lhs,
+            rhs,
+            tensor,
+            |lhs_x, rhs_x| self.forward(lhs_x, rhs_x),
+            |rhs_x, lhs_x| self.forward(lhs_x, rhs_x),
+        )
+    }
+}
+
+impl CustomUnary {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => RecomputeScalarTensor::change(
+                tensor,
+                node_tensor.iter_unary_op(|x| self.forward(x)),
+            ),
+        }
+    }
+}
+
+impl CustomBinary {
+    fn recompute<'a>(
+        &self,
+        lhs: &Expression,
+        rhs: &Expression,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        binary_recompute(
+            lhs,
+            rhs,
+            tensor,
+            |lhs_x, rhs_x| self.forward(lhs_x, rhs_x),
+            |rhs_x, lhs_x| self.forward(lhs_x, rhs_x),
+        )
+    }
+}
+
+impl CustomNary {
+    /// Like [`Concat`], the length follows the op's output
+    fn recompute<'a>(
+        &self,
+        inputs: &[Expression],
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        let mut changed = false;
+        for input in inputs {
+            if let RecomputeScalarTensor::TensorChanged(_) = input.recompute() {
+                changed = true;
+            }
         }
+        if changed {
+            RecomputeScalarTensor::change(tensor, self.forward(inputs))
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+#[rustfmt::skip]
+fn binary_recompute<'a>(
+    lhs: &Expression,
+    rhs: &Expression,
+    tensor: &'a Tensor,
+    fn_forward_lhs_rhs: impl Fn(f64, f64) -> f64,
+    fn_forward_rhs_lhs: impl Fn(f64, f64) -> f64,
+) -> RecomputeScalarTensor<'a> {
+    match (lhs.recompute(), rhs.recompute()) {
+        (RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::Scalar(_))
+            => unreachable!(),
+        (RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::TensorNoChange(_))
+        | (RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::Scalar(_))
+        | (RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::TensorNoChange(_))
+            => RecomputeScalarTensor::nochange(tensor),
+        (RecomputeScalarTensor::Scalar(lhs_x), RecomputeScalarTensor::TensorChanged(rhs_tensor))
+            => RecomputeScalarTensor::change(
+                tensor,
+                rhs_tensor.broadcast_iter_binary_op(*lhs_x, fn_forward_rhs_lhs),
+            ),
+        (RecomputeScalarTensor::TensorChanged(lhs_tensor), RecomputeScalarTensor::Scalar(rhs_x))
+            => RecomputeScalarTensor::change(
+                tensor,
+                lhs_tensor.broadcast_iter_binary_op(*rhs_x, fn_forward_lhs_rhs),
+            ),
+        (RecomputeScalarTensor::TensorChanged(lhs_tensor), RecomputeScalarTensor::TensorNoChange(rhs_tensor))
+        | (RecomputeScalarTensor::TensorChanged(lhs_tensor), RecomputeScalarTensor::TensorChanged(rhs_tensor))
+        | (RecomputeScalarTensor::TensorNoChange(lhs_tensor), RecomputeScalarTensor::TensorChanged(rhs_tensor))
+            => RecomputeScalarTensor::change(
+                tensor,
+                lhs_tensor.iter_binary_op(rhs_tensor, fn_forward_lhs_rhs),
+            ),
     }
 }
 
@@ -151,6 +571,7 @@ impl DiscreteBinaryOp {
         &self,
         lhs: &Expression,
         rhs: &Expression,
+        grad_method: &GradMethod,
         tensor: &'a Tensor,
     ) -> RecomputeScalarTensor<'a> {
         match (lhs.recompute(), rhs.recompute()) {
@@ -166,14 +587,14 @@ impl DiscreteBinaryOp {
                 RecomputeScalarTensor::TensorChanged(rhs_tensor),
             ) => RecomputeScalarTensor::change(
                 tensor,
-                self.forward_iter_fix_lhs(*lhs_x, rhs_tensor.values().read().unwrap().iter()),
+                self.forward_iter_fix_lhs(grad_method, *lhs_x, rhs_tensor.read().iter()),
             ),
             (
                 RecomputeScalarTensor::TensorChanged(lhs_tensor),
                 RecomputeScalarTensor::Scalar(rhs_x),
             ) => RecomputeScalarTensor::change(
                 tensor,
-                self.forward_iter_fix_rhs(*rhs_x, lhs_tensor.values().read().unwrap().iter()),
+                self.forward_iter_fix_rhs(grad_method, *rhs_x, lhs_tensor.read().iter()),
             ),
             (
                 RecomputeScalarTensor::TensorChanged(lhs_tensor),
@@ -186,13 +607,19 @@ impl DiscreteBinaryOp {
             | (
                 RecomputeScalarTensor::TensorNoChange(lhs_tensor),
                 RecomputeScalarTensor::TensorChanged(rhs_tensor),
-            ) => RecomputeScalarTensor::change(
-                tensor,
-                self.forward_iter(izip!(
-                    lhs_tensor.values().read().unwrap().iter(),
-                    rhs_tensor.values().read().unwrap().iter()
-                )),
-            ),
+            ) => {
+                let (lhs_vec, rhs_vec) = (lhs_tensor.read(), rhs_tensor.read());
+                // re-derived, the inputs may be resized
+                let len = broadcast_len("DiscreteBinary", [lhs_vec.len(), rhs_vec.len()])
+                    .unwrap_or_else(|e| panic!("{e}"));
+                RecomputeScalarTensor::change(
+                    tensor,
+                    self.forward_iter(
+                        grad_method,
+                        izip!(broadcast(&lhs_vec, len), broadcast(&rhs_vec, len)),
+                    ),
+                )
+            }
         }
     }
 }
@@ -210,15 +637,571 @@ impl Powf {
     }
 }
 
+impl Pwl {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => RecomputeScalarTensor::change(
+                tensor,
+                node_tensor.iter_unary_op(|x| self.forward(x)),
+            ),
+        }
+    }
+}
+
+impl Spline {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => RecomputeScalarTensor::change(
+                tensor,
+                node_tensor.iter_unary_op(|x| self.forward(x)),
+            ),
+        }
+    }
+}
+
+impl Diode {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => RecomputeScalarTensor::change(
+                tensor,
+                node_tensor.iter_unary_op(|x| self.forward(x)),
+            ),
+        }
+    }
+}
+
+impl Transition {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => RecomputeScalarTensor::change(
+                tensor,
+                node_tensor.iter_unary_op(|x| self.forward(x)),
+            ),
+        }
+    }
+}
+
+impl UnaryParamOp {
+    fn recompute<'a>(
+        &self,
+        param: f64,
+        node: &Expression,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => RecomputeScalarTensor::change(
+                tensor,
+                node_tensor.broadcast_iter_binary_op(param, self.forward()),
+            ),
+        }
+    }
+}
+
+impl Select {
+    fn recompute<'a>(
+        conds: &[Expression],
+        values: &[Expression],
+        default: &Expression,
+        method: CondMethod,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        let mut changed = false;
+        for node in conds.iter().chain(values).chain([default]) {
+            if let RecomputeScalarTensor::TensorChanged(_) = node.recompute() {
+                changed = true;
+            }
+        }
+        if changed {
+            // re-derived, the inputs may be resized
+            let len = broadcast_len(
+                "Select",
+                conds
+                    .iter()
+                    .chain(values)
+                    .chain([default])
+                    .filter_map(|node| match node {
+                        Expression::Const(_) => None,
+                        Expression::Tensor(tensor) => Some(tensor.read().len()),
+                    }),
+            )
+            .unwrap_or_else(|e| panic!("{e}"));
+            RecomputeScalarTensor::change(tensor, Self::iter(method, conds, values, default, len))
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+impl LogicNary {
+    fn recompute<'a>(&self, terms: &[Expression], tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        let mut changed = false;
+        for term in terms {
+            if let RecomputeScalarTensor::TensorChanged(_) = term.recompute() {
+                changed = true;
+            }
+        }
+        if changed {
+            // re-derived, the inputs may be resized
+            let len = broadcast_len(
+                &format!("Logic{self:?}Many"),
+                terms.iter().filter_map(|term| match term {
+                    Expression::Const(_) => None,
+                    Expression::Tensor(tensor) => Some(tensor.read().len()),
+                }),
+            )
+            .unwrap_or_else(|e| panic!("{e}"));
+            RecomputeScalarTensor::change(tensor, self.iter(terms, len))
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+impl Concat {
+    /// The length follows the updated parts
+    fn recompute<'a>(parts: &[Expression], tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        let mut changed = false;
+        for part in parts {
+            if let RecomputeScalarTensor::TensorChanged(_) = part.recompute() {
+                changed = true;
+            }
+        }
+        if changed {
+            RecomputeScalarTensor::change(tensor, Self::iter(parts))
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+impl Narrow {
+    /// Panics when the updated input is shorter than `offset + len`
+    fn recompute<'a>(
+        node: &Expression,
+        offset: usize,
+        len: usize,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                let values = {
+                    let read = node_tensor.read();
+                    if let Err(e) = Self::check(offset, len, read.len()) {
+                        panic!("{e}");
+                    }
+                    read[offset..offset + len].to_vec()
+                };
+                RecomputeScalarTensor::change(tensor, values)
+            }
+        }
+    }
+}
+
+impl Gather {
+    /// Panics when an index is out of the updated input's bounds
+    fn recompute<'a>(
+        node: &Expression,
+        indices: &[usize],
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                let values = {
+                    let read = node_tensor.read();
+                    if let Err(e) = check_indices(indices, read.len(), "Gather") {
+                        panic!("{e}");
+                    }
+                    Self::iter(&read, indices)
+                };
+                RecomputeScalarTensor::change(tensor, values)
+            }
+        }
+    }
+}
+
+impl ScatterAdd {
+    /// Panics when the updated input's length differs from the indices'
+    fn recompute<'a>(
+        node: &Expression,
+        indices: &[usize],
+        len: usize,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) | RecomputeScalarTensor::TensorNoChange(_) => {
+                RecomputeScalarTensor::nochange(tensor)
+            }
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                let node_len = node_tensor.read().len();
+                if node_len != indices.len() {
+                    panic!(
+                        "{}",
+                        Error::LengthMismatch {
+                            expected: indices.len(),
+                            got: node_len,
+                            op: "ScatterAdd".to_owned(),
+                        }
+                    );
+                }
+                RecomputeScalarTensor::change(tensor, Self::iter(node, indices, len))
+            }
+        }
+    }
+}
+
+impl AssignFrom {
+    fn recompute<'a>(node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, node_tensor.read().clone())
+            }
+        }
+    }
+}
+
+impl Reverse {
+    fn recompute<'a>(node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, Self::iter(&node_tensor.read()))
+            }
+        }
+    }
+}
+
+impl AssertRange {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                let values = node_tensor.read().clone();
+                self.check(&values);
+                RecomputeScalarTensor::change(tensor, values)
+            }
+        }
+    }
+}
+
+impl Shift {
+    fn recompute<'a>(
+        node: &Expression,
+        offset: isize,
+        fill: f64,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, Self::iter(&node_tensor.read(), offset, fill))
+            }
+        }
+    }
+}
+
+impl Ddt {
+    fn recompute<'a>(node: &Expression, dt: f64, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, Self::iter(&node_tensor.read(), dt))
+            }
+        }
+    }
+}
+
+impl Idt {
+    fn recompute<'a>(
+        node: &Expression,
+        dt: f64,
+        initial: f64,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, Self::iter(&node_tensor.read(), dt, initial))
+            }
+        }
+    }
+}
+
+impl Dft {
+    /// The length follows the updated input
+    fn recompute<'a>(
+        node: &Expression,
+        spectrum: Spectrum,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, Self::iter(&node_tensor.read(), spectrum))
+            }
+        }
+    }
+}
+
+impl CrossTime {
+    /// `NaN` when the crossing is lost or the updated length differs from the times
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, self.iter(&node_tensor.read()))
+            }
+        }
+    }
+}
+
+impl Integral {
+    /// Panics when the updated length differs from the times
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, self.iter(&node_tensor.read()))
+            }
+        }
+    }
+}
+
+impl WindowReduce {
+    /// The length follows the updated input
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, self.iter(&node_tensor.read()))
+            }
+        }
+    }
+}
+
+impl Reduce {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, self.iter(&node_tensor.read()))
+            }
+        }
+    }
+}
+
+impl CountGe {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) => unreachable!(),
+            RecomputeScalarTensor::TensorNoChange(_) => RecomputeScalarTensor::nochange(tensor),
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, self.iter(&node_tensor.read()))
+            }
+        }
+    }
+}
+
+impl SoftHistogram {
+    fn recompute<'a>(&self, node: &Expression, tensor: &'a Tensor) -> RecomputeScalarTensor<'a> {
+        match node.recompute() {
+            RecomputeScalarTensor::Scalar(_) | RecomputeScalarTensor::TensorNoChange(_) => {
+                RecomputeScalarTensor::nochange(tensor)
+            }
+            RecomputeScalarTensor::TensorChanged(node_tensor) => {
+                RecomputeScalarTensor::change(tensor, self.iter(&node_tensor.read()))
+            }
+        }
+    }
+}
+
+impl Conv1d {
+    /// The length follows the updated inputs, panics when the kernel becomes empty
+    /// or longer than the signal under [`ConvPadding::Valid`]
+    fn recompute<'a>(
+        signal: &Expression,
+        kernel: &Expression,
+        padding: ConvPadding,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        let signal_changed = matches!(signal.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        let kernel_changed = matches!(kernel.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        if signal_changed || kernel_changed {
+            let values = Self::forward(&signal.conv_values(), &kernel.conv_values(), padding)
+                .unwrap_or_else(|e| panic!("{e}"));
+            RecomputeScalarTensor::change(tensor, values)
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+impl MatVec {
+    /// Panics when the updated lengths mismatch
+    fn recompute<'a>(
+        input: &Expression,
+        weights: &Expression,
+        rows: usize,
+        cols: usize,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        let input_changed = matches!(input.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        let weights_changed =
+            matches!(weights.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        if input_changed || weights_changed {
+            let values = Self::forward(&input.conv_values(), &weights.conv_values(), rows, cols)
+                .unwrap_or_else(|e| panic!("{e}"));
+            RecomputeScalarTensor::change(tensor, values)
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+impl Polyval {
+    fn recompute<'a>(
+        input: &Expression,
+        coeffs: &Expression,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        let input_changed = matches!(input.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        let coeffs_changed = matches!(coeffs.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        if input_changed || coeffs_changed {
+            RecomputeScalarTensor::change(
+                tensor,
+                Self::iter(&input.conv_values(), &coeffs.conv_values()),
+            )
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+impl EmaState {
+    /// Always steps, also with an unchanged input
+    fn recompute<'a>(
+        &self,
+        node: &Expression,
+        alpha: f64,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        _ = node.recompute();
+        let values = self.step(alpha, &node.conv_values());
+        if *tensor.read() == values {
+            RecomputeScalarTensor::nochange(tensor)
+        } else {
+            RecomputeScalarTensor::change(tensor, values)
+        }
+    }
+}
+
+impl Weighted {
+    /// Panics when the updated lengths mismatch
+    fn recompute<'a>(
+        &self,
+        input: &Expression,
+        weights: &Expression,
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        let input_changed = matches!(input.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        let weights_changed =
+            matches!(weights.recompute(), RecomputeScalarTensor::TensorChanged(_));
+        if input_changed || weights_changed {
+            let value = self
+                .forward(&input.conv_values(), &weights.conv_values())
+                .unwrap_or_else(|e| panic!("{e}"));
+            RecomputeScalarTensor::change(tensor, vec![value])
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
+impl ComplexOp {
+    fn recompute<'a>(
+        &self,
+        inputs: &[Expression],
+        tensor: &'a Tensor,
+    ) -> RecomputeScalarTensor<'a> {
+        let mut changed = false;
+        for input in inputs {
+            if let RecomputeScalarTensor::TensorChanged(_) = input.recompute() {
+                changed = true;
+            }
+        }
+        if changed {
+            let values: Vec<Vec<f64>> = inputs.iter().map(Expression::conv_values).collect();
+            RecomputeScalarTensor::change(
+                tensor,
+                self.iter(&values).unwrap_or_else(|e| panic!("{e}")),
+            )
+        } else {
+            RecomputeScalarTensor::nochange(tensor)
+        }
+    }
+}
+
 impl Cond {
     #[rustfmt::skip]
     fn recompute<'a>(
         cond: &Expression,
         on_true: &Expression,
         on_false: &Expression,
+        method: &CondMethod,
         tensor: &'a Tensor,
     ) -> RecomputeScalarTensor<'a> {
-        match (cond.recompute(), on_true.recompute(), on_false.recompute()){
+        let forward = method.forward();
+        let cond_out = cond.recompute();
+        let live_on_true = match cond_out {
+            RecomputeScalarTensor::Scalar(_) => None,
+            RecomputeScalarTensor::TensorNoChange(cond_tensor)
+            | RecomputeScalarTensor::TensorChanged(cond_tensor)
+                => Self::live_branch(cond_tensor, on_true, on_false),
+        };
+        // the skipped branch blends as `0`, i.e., the output is the live branch
+        let (on_true_out, on_false_out, pruned) = match live_on_true {
+            Some(true) => {
+                mark_skipped(on_false);
+                (on_true.recompute(), RecomputeScalarTensor::Scalar(&0.0), PRUNED_ON_FALSE)
+            }
+            Some(false) => {
+                mark_skipped(on_true);
+                (RecomputeScalarTensor::Scalar(&0.0), on_false.recompute(), PRUNED_ON_TRUE)
+            }
+            None => (on_true.recompute(), on_false.recompute(), 0),
+        };
+        // switching a branch on or off changes the output, even when no input changed
+        let switched = tensor.change_marker().swap_pruned(pruned) != pruned;
+        let promote = |out| match out {
+            RecomputeScalarTensor::TensorNoChange(input) if switched => RecomputeScalarTensor::TensorChanged(input),
+            out => out,
+        };
+        match (promote(cond_out), promote(on_true_out), promote(on_false_out)){
             (RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::Scalar(_))
                 => unreachable!(),
             (RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::TensorNoChange(_))
@@ -229,36 +1212,19 @@ impl Cond {
             | (RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::Scalar(_))
             | (RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::TensorNoChange(_))
                 => RecomputeScalarTensor::nochange(tensor),
-            (RecomputeScalarTensor::Scalar(cond_x), RecomputeScalarTensor::Scalar(_), RecomputeScalarTensor::TensorChanged(on_false_tensor))
-            | (RecomputeScalarTensor::Scalar(cond_x), RecomputeScalarTensor::TensorNoChange(_), RecomputeScalarTensor::TensorChanged(on_false_tensor))
-                => if cond_x.is_zero() {
-                    RecomputeScalarTensor::change(tensor, on_false_tensor.values().read().unwrap().clone())
-                } else {
-                    RecomputeScalarTensor::nochange(tensor)
-                },
-            (RecomputeScalarTensor::Scalar(cond_x), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::Scalar(_))
-            | (RecomputeScalarTensor::Scalar(cond_x), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::TensorNoChange(_))
-                => if cond_x.is_zero() {
-                    RecomputeScalarTensor::nochange(tensor)
-                } else {
-                    RecomputeScalarTensor::change(tensor, on_true_tensor.values().read().unwrap().clone())
-                },
-            (RecomputeScalarTensor::Scalar(cond_x), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::TensorChanged(on_false_tensor))
-                => if cond_x.is_zero() {
-                    RecomputeScalarTensor::change(tensor, on_false_tensor.values().read().unwrap().clone())
-                } else {
-                    RecomputeScalarTensor::change(tensor, on_true_tensor.values().read().unwrap().clone())
-                },
+            // a changed branch of the constant `cond`, blended as `Expression::cond`
+            (RecomputeScalarTensor::Scalar(cond_x), _, _)
+                => RecomputeScalarTensor::change(tensor, Self::iter_x(*cond_x, on_true, on_false)),
             (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::Scalar(on_true_x), RecomputeScalarTensor::Scalar(on_false_x))
-                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_x_x(cond_tensor, *on_true_x, *on_false_x)),
+                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_x_x(cond_tensor, *on_true_x, *on_false_x, forward)),
             (RecomputeScalarTensor::TensorNoChange(cond_tensor), RecomputeScalarTensor::Scalar(on_true_x), RecomputeScalarTensor::TensorChanged(on_false_tensor))
             | (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::Scalar(on_true_x), RecomputeScalarTensor::TensorNoChange(on_false_tensor))
             | (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::Scalar(on_true_x), RecomputeScalarTensor::TensorChanged(on_false_tensor))
-                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_x_tensor(cond_tensor, *on_true_x, on_false_tensor)),
+                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_x_tensor(cond_tensor, *on_true_x, on_false_tensor, forward)),
             (RecomputeScalarTensor::TensorNoChange(cond_tensor), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::Scalar(on_false_x))
             | (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::TensorNoChange(on_true_tensor), RecomputeScalarTensor::Scalar(on_false_x))
             | (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::Scalar(on_false_x))
-                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_tensor_x(cond_tensor, on_true_tensor, *on_false_x)),
+                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_tensor_x(cond_tensor, on_true_tensor, *on_false_x, forward)),
             (RecomputeScalarTensor::TensorNoChange(cond_tensor), RecomputeScalarTensor::TensorNoChange(on_true_tensor), RecomputeScalarTensor::TensorChanged(on_false_tensor))
             | (RecomputeScalarTensor::TensorNoChange(cond_tensor), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::TensorNoChange(on_false_tensor))
             | (RecomputeScalarTensor::TensorNoChange(cond_tensor), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::TensorChanged(on_false_tensor))
@@ -266,7 +1232,7 @@ impl Cond {
             | (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::TensorNoChange(on_true_tensor), RecomputeScalarTensor::TensorChanged(on_false_tensor))
             | (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::TensorNoChange(on_false_tensor))
             | (RecomputeScalarTensor::TensorChanged(cond_tensor), RecomputeScalarTensor::TensorChanged(on_true_tensor), RecomputeScalarTensor::TensorChanged(on_false_tensor))
-                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_tensor_tensor(cond_tensor, on_true_tensor, on_false_tensor)),
+                => RecomputeScalarTensor::change(tensor, Self::iter_tensor_tensor_tensor(cond_tensor, on_true_tensor, on_false_tensor, forward)),
         }
     }
 }
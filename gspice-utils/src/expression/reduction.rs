@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering::Relaxed};
+
+static DETERMINISTIC: AtomicBool = AtomicBool::new(false);
+static ACCURACY_MODE: AtomicU8 = AtomicU8::new(AccuracyMode::Fast as u8);
+
+/// Leaves of the pairwise tree are summed sequentially
+const PAIRWISE_BLOCK: usize = 256;
+/// Shorter inputs are never summed in parallel
+#[cfg(feature = "rayon")]
+const PARALLEL_MIN_LEN: usize = 1 << 15;
+
+/// Force the reductions to a fixed order, independent of the thread count,
+/// for bit-identical results between runs
+///
+/// + [`sum`](super::Expression::sum) and [`mean`](super::Expression::mean) use a pairwise tree
+///   whose shape only depends on the length, the subtrees may still run in parallel
+///   with the `rayon` feature
+/// + Otherwise (by default), a long input may be summed by `rayon` in an order
+///   depending on the scheduling
+///
+/// The other reductions (e.g., the smooth max, i.e., the log-sum-exp, and the dot products
+/// of [`matvec`](super::Expression::matvec)), the gradient accumulation of the backward,
+/// and all the reductions of [`AccuracyMode::Compensated`] are always sequential,
+/// so they are deterministic in both modes
+#[inline]
+pub fn set_deterministic(enable: bool) {
+    DETERMINISTIC.store(enable, Relaxed);
+}
+
+/// See [`set_deterministic`]
+#[inline]
+pub fn is_deterministic() -> bool {
+    DETERMINISTIC.load(Relaxed)
+}
+
+/// Summation of the reductions, see [`set_accuracy_mode`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AccuracyMode {
+    /// Plain floating-point additions, the default
+    Fast,
+    /// Kahan–Babuška (Neumaier) compensated summation, sequential,
+    /// exact unless the compensation itself overflows its precision
+    Compensated,
+}
+
+/// Select the summation of [`sum`](super::Expression::sum), [`mean`](super::Expression::mean),
+/// the dot products of [`matvec`](super::Expression::matvec),
+/// and the gradient accumulation of the backward, [`AccuracyMode::Fast`] by default
+///
+/// [`AccuracyMode::Compensated`] costs about four times the additions,
+/// and one more buffer per gradient during the backward
+#[inline]
+pub fn set_accuracy_mode(mode: AccuracyMode) {
+    ACCURACY_MODE.store(mode as u8, Relaxed);
+}
+
+/// See [`set_accuracy_mode`]
+#[inline]
+pub fn accuracy_mode() -> AccuracyMode {
+    match ACCURACY_MODE.load(Relaxed) {
+        0 => AccuracyMode::Fast,
+        _ => AccuracyMode::Compensated,
+    }
+}
+
+/// Add `x` into `sum`, accumulating the lost low-order bits into `compensation`,
+/// the result is `sum + compensation`
+#[inline]
+pub(super) fn neumaier_add(sum: &mut f64, compensation: &mut f64, x: f64) {
+    let t = *sum + x;
+    if sum.abs() >= x.abs() {
+        *compensation += (*sum - t) + x;
+    } else {
+        *compensation += (x - t) + *sum;
+    }
+    *sum = t;
+}
+
+/// Sum of a reduction, in the order of [`set_deterministic`] and [`set_accuracy_mode`]
+#[inline]
+pub(super) fn sum(values: &[f64]) -> f64 {
+    if accuracy_mode() == AccuracyMode::Compensated {
+        sum_iter(values.iter().copied())
+    } else if is_deterministic() {
+        pairwise_sum(values)
+    } else {
+        #[cfg(feature = "rayon")]
+        if values.len() >= PARALLEL_MIN_LEN {
+            use rayon::prelude::*;
+            return values.par_iter().sum();
+        }
+        values.iter().sum()
+    }
+}
+
+/// Sequential sum of the terms, compensated in [`AccuracyMode::Compensated`]
+#[inline]
+pub(super) fn sum_iter(terms: impl Iterator<Item = f64>) -> f64 {
+    match accuracy_mode() {
+        AccuracyMode::Fast => terms.sum(),
+        AccuracyMode::Compensated => {
+            let (mut sum, mut compensation) = (0.0, 0.0);
+            terms.for_each(|x| neumaier_add(&mut sum, &mut compensation, x));
+            sum + compensation
+        }
+    }
+}
+
+/// Split at the middle until [`PAIRWISE_BLOCK`], the tree only depends on the length
+fn pairwise_sum(values: &[f64]) -> f64 {
+    if values.len() <= PAIRWISE_BLOCK {
+        return values.iter().sum();
+    }
+    let (lhs, rhs) = values.split_at(values.len() / 2);
+    #[cfg(feature = "rayon")]
+    if values.len() >= PARALLEL_MIN_LEN {
+        let (lhs, rhs) = rayon::join(|| pairwise_sum(lhs), || pairwise_sum(rhs));
+        return lhs + rhs;
+    }
+    pairwise_sum(lhs) + pairwise_sum(rhs)
+}
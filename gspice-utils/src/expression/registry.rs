@@ -0,0 +1,105 @@
+use std::{fmt, str::FromStr, sync::RwLock};
+
+use super::{
+    op::{CustomUnary, CustomUnaryBackward, CustomUnaryForward},
+    Error, Expression, Op,
+};
+
+/// The registered element-wise functions, indexed by [`UnaryId`], append-only
+static UNARY_REGISTRY: RwLock<Vec<CustomUnary>> = RwLock::new(Vec::new());
+
+/// Handle of an element-wise function registered by [`register_unary`]
+///
+/// The id is only valid in the process that registered it,
+/// a graph is saved and restored by the name, see [`Display`](fmt::Display) and [`FromStr`]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UnaryId(u16);
+
+/// Register the element-wise function `name` once at startup,
+/// for the plugins of device-specific functions, applied by [`Expression::apply_unary`]
+///
+/// `backward(x, res, grad)` returns the local contribution to the gradient of `x` as
+/// [`map_custom`](Expression::map_custom). Unlike the latter, the node holds the id instead
+/// of the closures, so it is merged by [`GraphBuilder`](super::GraphBuilder)
+/// and can be looked up by the name
+///
+/// [`Error::InvalidRegistration`] when `name` is empty or already registered,
+/// or the registry is full
+pub fn register_unary(
+    name: &str,
+    forward: CustomUnaryForward,
+    backward: CustomUnaryBackward,
+) -> Result<UnaryId, Error> {
+    if name.is_empty() {
+        return Err(Error::InvalidRegistration("empty name".to_owned()));
+    }
+    let mut registry = UNARY_REGISTRY.write().unwrap_or_else(|e| e.into_inner());
+    if registry.iter().any(|custom| custom.name() == name) {
+        return Err(Error::InvalidRegistration(format!(
+            "{name} is already registered"
+        )));
+    }
+    let id = u16::try_from(registry.len())
+        .map_err(|_| Error::InvalidRegistration(format!("too many ops to register {name}")))?;
+    registry.push(CustomUnary::new(name, forward, backward));
+    Ok(UnaryId(id))
+}
+
+impl UnaryId {
+    /// The function registered as `name`, if any
+    #[inline]
+    pub fn from_name(name: &str) -> Option<Self> {
+        UNARY_REGISTRY
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .position(|custom| custom.name() == name)
+            .map(|id| Self(id as u16))
+    }
+    /// The registered name
+    #[inline]
+    pub fn name(&self) -> String {
+        self.get().name().to_owned()
+    }
+    /// The registered function, the entries are never removed
+    #[inline]
+    pub(super) fn get(&self) -> CustomUnary {
+        UNARY_REGISTRY.read().unwrap_or_else(|e| e.into_inner())[self.0 as usize].clone()
+    }
+}
+
+/// Shows the registered name
+impl fmt::Debug for UnaryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+/// The registered name, parsed back by [`FromStr`]
+impl fmt::Display for UnaryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name())
+    }
+}
+
+impl FromStr for UnaryId {
+    type Err = Error;
+    /// [`Error::UnknownOp`] when the name is not registered (yet) in this process
+    #[inline]
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::from_name(name).ok_or_else(|| Error::UnknownOp(name.to_owned()))
+    }
+}
+
+impl Expression {
+    /// Apply the function registered by [`register_unary`]
+    #[inline]
+    pub fn apply_unary(&self, id: UnaryId) -> Self {
+        let custom = id.get();
+        match self {
+            Self::Const(x) => Self::Const(custom.forward(*x)),
+            Self::Tensor(tensor) => Self::Tensor(
+                tensor.unary_op(|x| custom.forward(x), Op::ExternalUnary(self.clone(), id)),
+            ),
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use itertools::izip;
+
+use super::{before_update, Op, Tensor};
+
+/// Copies of the values of leaf tensors, to try a step and roll it back,
+/// e.g., in a line search or a trust region
+///
+/// ``` text
+/// let snapshot = ParameterSnapshot::capture(&params);
+/// optimizer.step(&grads);
+/// if loss.value() > best {
+///     snapshot.restore();
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ParameterSnapshot {
+    /// `(tensor, values, version)`
+    params: Vec<(Tensor, Vec<f64>, usize)>,
+}
+
+impl ParameterSnapshot {
+    /// Copy the current values (and [versions](Tensor::version)) of `tensors`
+    ///
+    /// ## Panics
+    ///
+    /// When a tensor is not a leaf, i.e., its op is not [`Op::Assign`]
+    #[inline]
+    pub fn capture(tensors: &[Tensor]) -> Self {
+        Self {
+            params: tensors
+                .iter()
+                .map(|tensor| {
+                    assert!(
+                        matches!(tensor.op(), Op::Assign),
+                        "capture of a non-leaf tensor"
+                    );
+                    (tensor.clone(), tensor.read().clone(), tensor.version())
+                })
+                .collect(),
+        }
+    }
+    /// Write the captured values back, with their lengths
+    ///
+    /// Calls [`before_update`] once, then marks the restored tensors as changed,
+    /// a tensor not updated since the capture (same version) is left untouched.
+    /// Need [`Expression::value`](super::Expression::value) after calling this
+    pub fn restore(&self) {
+        before_update();
+        for (tensor, values, version) in &self.params {
+            if tensor.version() != *version {
+                tensor.update_with(|write| write.clone_from(values));
+            }
+        }
+    }
+    /// `(index, max |Δ|)` of each captured tensor between `self` and `other`,
+    /// `inf` when its lengths differ, e.g., the step size of a trust region
+    /// in the infinity norm
+    ///
+    /// ## Panics
+    ///
+    /// When the snapshots are not of the same tensors
+    pub fn diff(&self, other: &Self) -> Vec<(usize, f64)> {
+        assert!(
+            self.params.len() == other.params.len()
+                && izip!(&self.params, &other.params).all(|(lhs, rhs)| lhs.0 == rhs.0),
+            "diff of the snapshots of different tensors"
+        );
+        izip!(&self.params, &other.params)
+            .enumerate()
+            .map(|(i, ((_, lhs, _), (_, rhs, _)))| {
+                let max_abs_delta = if lhs.len() == rhs.len() {
+                    izip!(lhs, rhs).fold(0.0, |max, (l, r)| f64::max(max, (l - r).abs()))
+                } else {
+                    f64::INFINITY
+                };
+                (i, max_abs_delta)
+            })
+            .collect()
+    }
+    /// The number of captured tensors
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+}
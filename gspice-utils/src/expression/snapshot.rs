@@ -0,0 +1,501 @@
+use core::fmt;
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    sync::PoisonError,
+};
+
+use super::{
+    op::{GradMethod, PwlExtrapolation, WindowTail},
+    reader::LeReader,
+    Error, Expression, Op, ScalarTensor,
+};
+
+/// Leading bytes of a snapshot
+const MAGIC: [u8; 4] = *b"GSNP";
+/// Bumped on any change of the layout, older snapshots are rejected
+const VERSION: u32 = 2;
+/// Max mismatches kept by [`SnapshotDiff`], the others are only counted
+const MISMATCH_LIMIT: usize = 16;
+
+const TAG_CONST: u8 = 0;
+const TAG_LEAF: u8 = 1;
+const TAG_OP: u8 = 2;
+
+/// One node of the canonical form, the inputs are indices of the earlier nodes
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    /// Bits of the value
+    Const(u64),
+    Leaf(Vec<f64>),
+    /// The name, the bits of the [parameters](Op::param_bits) and the inputs
+    Op(String, Vec<u64>, Vec<u32>),
+}
+
+impl Node {
+    /// Whether the nodes are the same without the values of the leaves
+    #[inline]
+    fn same_structure(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Leaf(lhs), Self::Leaf(rhs)) => lhs.len() == rhs.len(),
+            _ => self == other,
+        }
+    }
+    /// The structural part, the leaf values are written by [`write_values`]
+    fn write_structure(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Self::Const(bits) => {
+                out.write_all(&[TAG_CONST])?;
+                out.write_all(&bits.to_le_bytes())
+            }
+            Self::Leaf(values) => {
+                out.write_all(&[TAG_LEAF])?;
+                out.write_all(&(values.len() as u64).to_le_bytes())
+            }
+            Self::Op(name, params, inputs) => {
+                out.write_all(&[TAG_OP])?;
+                out.write_all(&(name.len() as u32).to_le_bytes())?;
+                out.write_all(name.as_bytes())?;
+                out.write_all(&(params.len() as u32).to_le_bytes())?;
+                params
+                    .iter()
+                    .try_for_each(|bits| out.write_all(&bits.to_le_bytes()))?;
+                out.write_all(&(inputs.len() as u32).to_le_bytes())?;
+                inputs
+                    .iter()
+                    .try_for_each(|input| out.write_all(&input.to_le_bytes()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Const(bits) => write!(f, "Const({})", f64::from_bits(*bits)),
+            Self::Leaf(values) => write!(f, "Tensor[{}]", values.len()),
+            Self::Op(name, params, inputs) => {
+                write!(f, "{}(", tagged_name(name, params))?;
+                for (i, input) in inputs.iter().enumerate() {
+                    let sep = if i == 0 { "" } else { ", " };
+                    write!(f, "{sep}#{input}")?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+/// Canonical form of a graph: the distinct nodes in post order of the inputs,
+/// with the first path from the root of each node
+///
+/// The op nodes are merged by structure (the name, the parameters and the inputs)
+/// and the leaves numbered by their first visit,
+/// so the form does not depend on the construction order, nor on how the subtrees are shared
+struct Canonical {
+    nodes: Vec<Node>,
+    paths: Vec<String>,
+    output: Vec<f64>,
+}
+
+impl Canonical {
+    fn new(root: &Expression) -> Self {
+        let mut canonical = Self {
+            nodes: Vec::new(),
+            paths: Vec::new(),
+            output: Vec::new(),
+        };
+        let mut merged: HashMap<Vec<u8>, u32> = HashMap::new();
+        let mut memo: HashMap<usize, u32> = HashMap::new();
+        let mut stack = vec![(root, "$".to_owned(), false)];
+        while let Some((expr, path, expanded)) = stack.pop() {
+            let tensor = match expr {
+                Expression::Const(x) => {
+                    canonical.merge(&mut merged, Node::Const(x.to_bits()), path);
+                    continue;
+                }
+                Expression::Tensor(tensor) => tensor,
+            };
+            if memo.contains_key(&tensor.key()) {
+                continue;
+            }
+            let inputs = tensor.op().inputs();
+            let index = if let Op::Assign = tensor.op() {
+                // never merged with another leaf of the same length
+                let values = tensor
+                    .values()
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner);
+                canonical.push(Node::Leaf(values.clone()), path)
+            } else if expanded {
+                let inputs = inputs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, input)| match input {
+                        Expression::Const(x) => canonical.merge(
+                            &mut merged,
+                            Node::Const(x.to_bits()),
+                            format!("{path}.{i}"),
+                        ),
+                        Expression::Tensor(input) => memo[&input.key()],
+                    })
+                    .collect();
+                let op = tensor.op();
+                canonical.merge(
+                    &mut merged,
+                    Node::Op(op.name(), op.param_bits(), inputs),
+                    path,
+                )
+            } else {
+                stack.push((expr, path.clone(), true));
+                // reversed, so that the first input is numbered first
+                stack.extend(
+                    inputs
+                        .into_iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(i, input)| (input, format!("{path}.{i}"), false)),
+                );
+                continue;
+            };
+            memo.insert(tensor.key(), index);
+        }
+        canonical.output = match root.value() {
+            ScalarTensor::Scalar(x) => vec![*x],
+            ScalarTensor::Tensor(values) => values
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        };
+        canonical
+    }
+    #[inline]
+    fn push(&mut self, node: Node, path: String) -> u32 {
+        self.nodes.push(node);
+        self.paths.push(path);
+        self.nodes.len() as u32 - 1
+    }
+    /// The index of the earlier node of the same structure, or push it
+    #[inline]
+    fn merge(&mut self, merged: &mut HashMap<Vec<u8>, u32>, node: Node, path: String) -> u32 {
+        let mut key = Vec::new();
+        _ = node.write_structure(&mut key);
+        match merged.get(&key) {
+            Some(index) => *index,
+            None => {
+                let index = self.push(node, path);
+                merged.insert(key, index);
+                index
+            }
+        }
+    }
+    /// FNV-1a of the structure, stable across the processes and the platforms
+    fn hash(&self) -> u64 {
+        let mut bytes = Vec::new();
+        for node in &self.nodes {
+            _ = node.write_structure(&mut bytes);
+        }
+        fnv1a(&bytes)
+    }
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&self.hash().to_le_bytes())?;
+        out.write_all(&(self.nodes.len() as u32).to_le_bytes())?;
+        for node in &self.nodes {
+            node.write_structure(out)?;
+            if let Node::Leaf(values) = node {
+                write_values(out, values)?;
+            }
+        }
+        out.write_all(&(self.output.len() as u64).to_le_bytes())?;
+        write_values(out, &self.output)
+    }
+    /// The nodes and the output of a snapshot, with its structure hash
+    fn read(input: &mut impl Read) -> Result<(u64, Vec<Node>, Vec<f64>), Error> {
+        let mut input = LeReader::new(input, Error::InvalidSnapshot, "snapshot");
+        if input.bytes::<4>()? != MAGIC {
+            return Err(Error::InvalidSnapshot("not a snapshot".to_owned()));
+        }
+        let version = input.u32()?;
+        if version != VERSION {
+            return Err(Error::InvalidSnapshot(format!(
+                "version {version}, expected {VERSION}"
+            )));
+        }
+        let hash = input.u64()?;
+        let count = input.u32()?;
+        let mut nodes = Vec::new();
+        for index in 0..count {
+            let node = match input.bytes::<1>()?[0] {
+                TAG_CONST => Node::Const(input.u64()?),
+                TAG_LEAF => {
+                    let len = input.u64()?;
+                    Node::Leaf(input.values(len)?)
+                }
+                TAG_OP => {
+                    let name = input.string(|| format!("op name of #{index}"))?;
+                    let params = (0..input.u32()?)
+                        .map(|_| input.u64())
+                        .collect::<Result<_, _>>()?;
+                    let inputs = (0..input.u32()?)
+                        .map(|_| match input.u32()? {
+                            i if i < index => Ok(i),
+                            i => Err(Error::InvalidSnapshot(format!(
+                                "input #{i} of #{index} is not an earlier node"
+                            ))),
+                        })
+                        .collect::<Result<_, _>>()?;
+                    Node::Op(name, params, inputs)
+                }
+                tag => {
+                    return Err(Error::InvalidSnapshot(format!(
+                        "unknown tag {tag} of #{index}"
+                    )))
+                }
+            };
+            nodes.push(node);
+        }
+        let len = input.u64()?;
+        let output = input.values(len)?;
+        Ok((hash, nodes, output))
+    }
+}
+
+/// `name[hash of the parameters]`, the name alone without [parameters](Op::param_bits)
+pub(super) fn tagged_name(name: &str, params: &[u64]) -> String {
+    if params.is_empty() {
+        name.to_owned()
+    } else {
+        let bytes: Vec<u8> = params.iter().flat_map(|bits| bits.to_le_bytes()).collect();
+        format!("{name}[{:016x}]", fnv1a(&bytes))
+    }
+}
+
+#[inline]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl Op {
+    /// The bits of the parameters not shown by the name, e.g., the tables, the indices,
+    /// the times and the comparison methods, so that two ops differing only by them
+    /// are not merged. The user ops are known by their names only
+    pub(super) fn param_bits(&self) -> Vec<u64> {
+        let bits = |xs: &[f64]| xs.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        let indices = |indices: &[usize]| indices.iter().map(|i| *i as u64).collect::<Vec<_>>();
+        let method = |method: &GradMethod| {
+            [method.tag()]
+                .into_iter()
+                .chain(
+                    method
+                        .sharpness()
+                        .map(|sharpness| sharpness.get().to_bits()),
+                )
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Op::Pwl(_, pwl) => {
+                let extrapolation = match pwl.extrapolation {
+                    PwlExtrapolation::Clamp => 0,
+                    PwlExtrapolation::Extend => 1,
+                };
+                [vec![extrapolation], bits(&pwl.xs), bits(&pwl.ys)].concat()
+            }
+            Op::Spline(_, spline) => {
+                [bits(&spline.xs), bits(&spline.ys), bits(&spline.ms)].concat()
+            }
+            Op::Diode(_, diode) => bits(&diode.params()),
+            Op::Gather(_, gather) | Op::ScatterAdd(_, gather, _) => indices(gather),
+            Op::CrossTime(_, cross) => [bits(&cross.times), method(&cross.method)].concat(),
+            Op::Integrate(_, integral) => bits(&integral.times),
+            Op::WindowReduce(_, reduce) => vec![match reduce.tail {
+                WindowTail::Drop => 0,
+                WindowTail::Keep => 1,
+            }],
+            Op::CountGe(_, count) => method(&count.method),
+            Op::SoftHistogram(_, histogram) => bits(&histogram.edges),
+            Op::Assert(_, range) => bits(&[range.lo, range.hi]),
+            Op::DiscreteBinary(_, _, _, grad_method) => method(grad_method),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[inline]
+fn write_values(out: &mut impl Write, values: &[f64]) -> io::Result<()> {
+    values
+        .iter()
+        .try_for_each(|x| out.write_all(&x.to_bits().to_le_bytes()))
+}
+
+/// One difference from a snapshot, see [`Expression::verify_snapshot`]
+///
+/// The path is the input indices from the root of the graph being verified, as [`NodeDiff`](super::NodeDiff)
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotMismatch {
+    /// The numbers of the distinct nodes differ
+    NodeCount { expected: usize, got: usize },
+    /// Another node at the same position of the canonical order,
+    /// the inputs are shown as `#index` of that order
+    Node {
+        path: String,
+        expected: String,
+        got: String,
+    },
+    /// The lengths of a leaf (by its path) or the output (`$`) differ
+    Len {
+        path: String,
+        expected: usize,
+        got: usize,
+    },
+    /// The bits of a value of a leaf (by its path) or the output (`$`) differ
+    Value {
+        path: String,
+        index: usize,
+        expected: f64,
+        got: f64,
+    },
+}
+
+impl fmt::Display for SnapshotMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NodeCount { expected, got } => write!(f, "~ nodes: {expected} -> {got}"),
+            Self::Node {
+                path,
+                expected,
+                got,
+            } => write!(f, "~ {path}: {expected} -> {got}"),
+            Self::Len {
+                path,
+                expected,
+                got,
+            } => write!(f, "~ {path}: length {expected} -> {got}"),
+            Self::Value {
+                path,
+                index,
+                expected,
+                got,
+            } => write!(f, "~ {path}[{index}]: {expected:?} -> {got:?}"),
+        }
+    }
+}
+
+/// Differences of a graph from a snapshot, see [`Expression::verify_snapshot`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SnapshotDiff {
+    /// Structure hash of the snapshot
+    pub expected_hash: u64,
+    /// Structure hash of the graph
+    pub hash: u64,
+    /// The first mismatches, in the canonical order of the nodes, then the output
+    pub mismatches: Vec<SnapshotMismatch>,
+    /// Number of the mismatches, including the ones not kept
+    pub total: usize,
+}
+
+impl SnapshotDiff {
+    /// Same structure and bit-identical values
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+    #[inline]
+    fn push(&mut self, mismatch: SnapshotMismatch) {
+        if self.mismatches.len() < MISMATCH_LIMIT {
+            self.mismatches.push(mismatch);
+        }
+        self.total += 1;
+    }
+    fn compare_values(&mut self, path: &str, expected: &[f64], got: &[f64]) {
+        if expected.len() != got.len() {
+            self.push(SnapshotMismatch::Len {
+                path: path.to_owned(),
+                expected: expected.len(),
+                got: got.len(),
+            });
+        }
+        for (index, (expected, got)) in expected.iter().zip(got).enumerate() {
+            if expected.to_bits() != got.to_bits() {
+                self.push(SnapshotMismatch::Value {
+                    path: path.to_owned(),
+                    index,
+                    expected: *expected,
+                    got: *got,
+                });
+            }
+        }
+    }
+}
+
+/// At most [`MISMATCH_LIMIT`] mismatches, then the number of the omitted ones
+impl fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "matches the snapshot");
+        }
+        write!(
+            f,
+            "{} mismatch(es), structure hash {:016x} -> {:016x}",
+            self.total, self.expected_hash, self.hash
+        )?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {mismatch}")?;
+        }
+        if self.total > self.mismatches.len() {
+            write!(f, "\n  ... and {} more", self.total - self.mismatches.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Expression {
+    /// Write the golden file of the graph: its canonical structure, the values of
+    /// its leaves and its output, for [`verify_snapshot`](Expression::verify_snapshot)
+    ///
+    /// The shared subtrees are merged by structure, so a graph rebuilt in another
+    /// construction order, or with another sharing of the same subtrees, has the same snapshot.
+    /// The values are evaluated by [`value`](Expression::value)
+    pub fn snapshot(&self, mut writer: impl Write) -> io::Result<()> {
+        Canonical::new(self).write(&mut writer)
+    }
+    /// Compare the graph with a snapshot written by [`snapshot`](Expression::snapshot),
+    /// the structure node by node and the values bit by bit
+    ///
+    /// [`Error::InvalidSnapshot`] when the input is not a snapshot of this version
+    pub fn verify_snapshot(&self, mut reader: impl Read) -> Result<SnapshotDiff, Error> {
+        let (expected_hash, expected_nodes, expected_output) = Canonical::read(&mut reader)?;
+        let canonical = Canonical::new(self);
+        let mut diff = SnapshotDiff {
+            expected_hash,
+            hash: canonical.hash(),
+            ..Default::default()
+        };
+        if expected_nodes.len() != canonical.nodes.len() {
+            diff.push(SnapshotMismatch::NodeCount {
+                expected: expected_nodes.len(),
+                got: canonical.nodes.len(),
+            });
+        }
+        for ((expected, got), path) in expected_nodes
+            .iter()
+            .zip(&canonical.nodes)
+            .zip(&canonical.paths)
+        {
+            match (expected, got) {
+                (Node::Leaf(expected), Node::Leaf(got)) => diff.compare_values(path, expected, got),
+                _ if !expected.same_structure(got) => diff.push(SnapshotMismatch::Node {
+                    path: path.clone(),
+                    expected: expected.to_string(),
+                    got: got.to_string(),
+                }),
+                _ => (),
+            }
+        }
+        diff.compare_values("$", &expected_output, &canonical.output);
+        Ok(diff)
+    }
+}
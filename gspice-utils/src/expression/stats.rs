@@ -0,0 +1,84 @@
+use std::sync::{Mutex, PoisonError};
+
+use super::Tensor;
+
+/// Summary of the values of a tensor, see [`Tensor::stats`]
+///
+/// `min`, `max`, `mean` and `l2` are of the finite values, the others are counted apart,
+/// `min`, `max` and `mean` are `NaN` without any finite value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TensorStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Euclidean norm
+    pub l2: f64,
+    pub n_nan: usize,
+    pub n_inf: usize,
+}
+
+impl TensorStats {
+    /// One pass over `values`
+    fn of(values: &[f64]) -> Self {
+        let (mut min, mut max, mut sum, mut sqr_sum) = (f64::INFINITY, f64::NEG_INFINITY, 0.0, 0.0);
+        let (mut n_finite, mut n_nan, mut n_inf) = (0, 0, 0);
+        for &x in values {
+            if x.is_nan() {
+                n_nan += 1;
+            } else if x.is_infinite() {
+                n_inf += 1;
+            } else {
+                n_finite += 1;
+                min = min.min(x);
+                max = max.max(x);
+                sum += x;
+                sqr_sum += x * x;
+            }
+        }
+        if n_finite == 0 {
+            (min, max) = (f64::NAN, f64::NAN);
+        }
+        Self {
+            min,
+            max,
+            mean: sum / n_finite as f64,
+            l2: sqr_sum.sqrt(),
+            n_nan,
+            n_inf,
+        }
+    }
+}
+
+/// The stats of a [version](Tensor::version) of the values
+#[derive(Debug, Default)]
+pub(super) struct StatsCache(Mutex<Option<(usize, TensorStats)>>);
+
+impl Tensor {
+    /// [`TensorStats`] of the values, in one pass under the read lock
+    ///
+    /// Cached by [version](Tensor::version), so the repeated queries are free until the next
+    /// update or recompute. A write through [`values`](Tensor::values) is only seen
+    /// after [`mark_changed`](Tensor::mark_changed)
+    pub fn stats(&self) -> TensorStats {
+        // the version before the values, so the cache is never newer than its version
+        let version = self.version();
+        let mut cache = self
+            .stats_cache()
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match *cache {
+            Some((cached, stats)) if cached == version => stats,
+            _ => {
+                let stats = TensorStats::of(&self.read());
+                *cache = Some((version, stats));
+                stats
+            }
+        }
+    }
+    /// The number of values matching `pred`, under the read lock
+    #[inline]
+    pub fn count_where(&self, pred: impl Fn(f64) -> bool) -> usize {
+        self.read().iter().filter(|x| pred(**x)).count()
+    }
+}
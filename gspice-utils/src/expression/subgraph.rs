@@ -0,0 +1,136 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Error, Expression, Op, Tensor};
+
+impl Expression {
+    /// The distinct op nodes matching `pred`, in depth-first order from `self`
+    ///
+    /// The leaf tensors are [`Op::Assign`], the constants are not nodes
+    pub fn find(&self, pred: impl Fn(&Op) -> bool) -> Vec<Self> {
+        let mut visited = HashSet::new();
+        let mut found = Vec::new();
+        let mut stack = vec![self];
+        while let Some(expr) = stack.pop() {
+            let Self::Tensor(tensor) = expr else {
+                continue;
+            };
+            if !visited.insert(tensor.key()) {
+                continue;
+            }
+            if pred(tensor.op()) {
+                found.push(expr.clone());
+            }
+            stack.extend(tensor.op().inputs().into_iter().rev());
+        }
+        found
+    }
+    /// A new graph with every occurrence of `target` (by identity) replaced by `replacement`
+    ///
+    /// Only the nodes depending on `target` are rebuilt and recomputed,
+    /// the rest of the graph (and the sharing in it) is the same tensors.
+    /// The original graph is untouched. A constant `target` matches nothing
+    ///
+    /// ## Panics
+    ///
+    /// See [`try_substitute`](Expression::try_substitute)
+    #[inline]
+    pub fn substitute(&self, target: &Self, replacement: &Self) -> Self {
+        self.try_substitute(target, replacement)
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Fallible [`substitute`](Expression::substitute)
+    ///
+    /// [`Error::LengthMismatch`] when the lengths of `target` and `replacement` differ,
+    /// a constant is length-1
+    pub fn try_substitute(&self, target: &Self, replacement: &Self) -> Result<Self, Error> {
+        let len = |expr: &Self| match expr {
+            Self::Const(_) => 1,
+            Self::Tensor(tensor) => tensor.read().len(),
+        };
+        if len(target) != len(replacement) {
+            return Err(Error::LengthMismatch {
+                expected: len(target),
+                got: len(replacement),
+                op: "Substitute".to_owned(),
+            });
+        }
+        Ok(self.rebuild(&|expr| match (expr, target) {
+            (Self::Tensor(tensor), Self::Tensor(target)) if tensor == target => {
+                Some(replacement.clone())
+            }
+            _ => None,
+        }))
+    }
+    /// A new graph with the nodes (or constant inputs) for which `replace` returns `Some` replaced
+    ///
+    /// The nodes depending on a replaced one are rebuilt and recomputed,
+    /// the others are the same tensors
+    pub(super) fn rebuild(&self, replace: &impl Fn(&Self) -> Option<Self>) -> Self {
+        if let Some(new) = replace(self) {
+            return new;
+        }
+        let Self::Tensor(root) = self else {
+            return self.clone();
+        };
+        // post order, so that the inputs are substituted first
+        let mut memo: HashMap<usize, Self> = HashMap::new();
+        let mut stack: Vec<(&Tensor, bool)> = vec![(root, false)];
+        while let Some((tensor, expanded)) = stack.pop() {
+            if memo.contains_key(&tensor.key()) {
+                continue;
+            }
+            let expr = Self::Tensor(tensor.clone());
+            if let Some(new) = (tensor != root).then(|| replace(&expr)).flatten() {
+                memo.insert(tensor.key(), new);
+            } else if expanded {
+                let mut rebuilt = false;
+                let op = tensor.op().map_inputs(|input| match input {
+                    Self::Const(_) => match replace(input) {
+                        Some(new) => {
+                            rebuilt = true;
+                            new
+                        }
+                        None => input.clone(),
+                    },
+                    Self::Tensor(input_tensor) => {
+                        let new_input = memo[&input_tensor.key()].clone();
+                        rebuilt |= !matches!(&new_input, Self::Tensor(new) if new == input_tensor);
+                        new_input
+                    }
+                });
+                let new = if rebuilt {
+                    let with_grad = op
+                        .inputs()
+                        .into_iter()
+                        .any(|input| matches!(input, Self::Tensor(input) if input.with_grad()));
+                    let new = Tensor::new(with_grad, Vec::new(), op);
+                    new.recompute_released();
+                    new
+                } else {
+                    tensor.clone()
+                };
+                memo.insert(tensor.key(), Self::Tensor(new));
+            } else {
+                stack.push((tensor, true));
+                stack.extend(
+                    tensor
+                        .op()
+                        .inputs()
+                        .into_iter()
+                        .filter_map(|input| match input {
+                            Self::Tensor(input) => Some((input, false)),
+                            Self::Const(_) => None,
+                        }),
+                );
+            }
+        }
+        let new = memo
+            .remove(&root.key())
+            .expect("gspice internal error - root not substituted");
+        #[cfg(debug_assertions)]
+        if let Err(e) = new.check_acyclic() {
+            panic!("gspice internal error - {e}");
+        }
+        new
+    }
+}
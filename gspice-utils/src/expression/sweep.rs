@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use super::{autograd::GradId, before_update, Expression, Op, Tensor, TensorRef};
+
+impl Op {
+    /// The same op with each input replaced by `f(input)`
+    pub(super) fn map_inputs(&self, mut f: impl FnMut(&Expression) -> Expression) -> Self {
+        match self {
+            Op::Assign => Op::Assign,
+            Op::AssignFrom(node) => Op::AssignFrom(f(node)),
+            Op::Powf(node, n) => Op::Powf(f(node), *n),
+            Op::Pwl(node, pwl) => Op::Pwl(f(node), pwl.clone()),
+            Op::Spline(node, spline) => Op::Spline(f(node), spline.clone()),
+            Op::Diode(node, diode) => Op::Diode(f(node), *diode),
+            Op::Transition(node, transition) => Op::Transition(f(node), *transition),
+            Op::Concat(parts) => Op::Concat(parts.iter().map(f).collect()),
+            Op::Narrow(node, offset, len) => Op::Narrow(f(node), *offset, *len),
+            Op::Gather(node, indices) => Op::Gather(f(node), indices.clone()),
+            Op::ScatterAdd(node, indices, len) => Op::ScatterAdd(f(node), indices.clone(), *len),
+            Op::Reverse(node) => Op::Reverse(f(node)),
+            Op::Assert(node, range) => Op::Assert(f(node), range.clone()),
+            Op::Shift(node, offset, fill) => Op::Shift(f(node), *offset, *fill),
+            Op::Ddt(node, dt) => Op::Ddt(f(node), *dt),
+            Op::Idt(node, dt, initial) => Op::Idt(f(node), *dt, *initial),
+            Op::Spectrum(node, spectrum) => Op::Spectrum(f(node), *spectrum),
+            Op::CrossTime(node, cross) => Op::CrossTime(f(node), cross.clone()),
+            Op::Integrate(node, integral) => Op::Integrate(f(node), integral.clone()),
+            Op::WindowReduce(node, reduce) => Op::WindowReduce(f(node), *reduce),
+            Op::Reduce(node, reduce) => Op::Reduce(f(node), *reduce),
+            Op::CountGe(node, count) => Op::CountGe(f(node), count.clone()),
+            Op::SoftHistogram(node, histogram) => Op::SoftHistogram(f(node), histogram.clone()),
+            Op::Conv1d(signal, kernel, padding) => Op::Conv1d(f(signal), f(kernel), *padding),
+            Op::MatVec(input, weights, rows, cols) => {
+                Op::MatVec(f(input), f(weights), *rows, *cols)
+            }
+            Op::Polyval(input, coeffs) => Op::Polyval(f(input), f(coeffs)),
+            Op::Ema(node, alpha, state) => Op::Ema(f(node), *alpha, state.clone()),
+            Op::Weighted(input, weights, weighted) => Op::Weighted(f(input), f(weights), *weighted),
+            Op::Complex(inputs, complex) => Op::Complex(inputs.iter().map(f).collect(), *complex),
+            Op::UnaryParam(node, param, unary_param_op) => {
+                Op::UnaryParam(f(node), *param, *unary_param_op)
+            }
+            Op::DivEps(lhs, rhs, eps) => Op::DivEps(f(lhs), f(rhs), *eps),
+            Op::RobustLoss(lhs, rhs, loss) => Op::RobustLoss(f(lhs), f(rhs), *loss),
+            Op::Custom(node, custom) => Op::Custom(f(node), custom.clone()),
+            Op::ExternalUnary(node, id) => Op::ExternalUnary(f(node), *id),
+            Op::CustomBinary(lhs, rhs, custom) => Op::CustomBinary(f(lhs), f(rhs), custom.clone()),
+            Op::CustomNary(inputs, custom) => {
+                Op::CustomNary(inputs.iter().map(f).collect(), custom.clone())
+            }
+            Op::Cond(cond, on_true, on_false, method) => {
+                Op::Cond(f(cond), f(on_true), f(on_false), *method)
+            }
+            Op::Select(conds, values, default, method) => Op::Select(
+                conds.iter().map(&mut f).collect(),
+                values.iter().map(&mut f).collect(),
+                f(default),
+                *method,
+            ),
+            Op::LogicNary(terms, op) => Op::LogicNary(terms.iter().map(f).collect(), *op),
+            Op::Unary(node, unary_op) => Op::Unary(f(node), *unary_op),
+            Op::Binary(lhs, rhs, binary_op) => Op::Binary(f(lhs), f(rhs), *binary_op),
+            Op::DiscreteBinary(lhs, rhs, discrete_binary_op, grad_method) => {
+                Op::DiscreteBinary(f(lhs), f(rhs), *discrete_binary_op, grad_method.clone())
+            }
+        }
+    }
+}
+
+impl Tensor {
+    /// Identity of the shared tensor
+    #[inline]
+    pub(super) fn key(&self) -> usize {
+        std::sync::Arc::as_ptr(&self.0) as usize
+    }
+}
+
+impl Expression {
+    /// Deep copy of the compute graph, every tensor is a fresh one
+    /// (values, locks, change markers, and gradient ids), so that updating the copy
+    /// does not affect the original, and the two can be used by different threads
+    ///
+    /// The sharing inside the graph is kept, and the annealed sharpness
+    /// of [`GradMethod`](super::GradMethod) is still shared
+    #[inline]
+    pub fn deep_clone_with_fresh_tensors(&self) -> Self {
+        self.deep_clone_memo(&mut HashMap::new())
+    }
+    /// [`deep_clone_with_fresh_tensors`](Expression::deep_clone_with_fresh_tensors),
+    /// and the map from the [`GradId`] of each original parameter (a leaf tensor with gradient) to its copy
+    pub fn deep_clone(&self) -> (Self, HashMap<GradId, TensorRef>) {
+        let mut memo = HashMap::new();
+        let copy = self.deep_clone_memo(&mut memo);
+        let params = memo
+            .into_values()
+            .filter(|(original, _)| matches!(original.op(), Op::Assign))
+            .filter_map(|(original, copy)| {
+                original.grad_id().map(|grad_id| (grad_id, TensorRef(copy)))
+            })
+            .collect();
+        (copy, params)
+    }
+    /// `memo` maps the original tensors to `(original, copy)`
+    fn deep_clone_memo(&self, memo: &mut HashMap<usize, (Tensor, Tensor)>) -> Self {
+        match self {
+            Self::Const(x) => Self::Const(*x),
+            Self::Tensor(tensor) => Self::Tensor(tensor.deep_clone(memo)),
+        }
+    }
+    /// Number of distinct tensors in the graph
+    #[cfg(test)]
+    pub(super) fn node_count(&self) -> usize {
+        fn walk(expr: &Expression, seen: &mut std::collections::HashSet<usize>) {
+            if let Expression::Tensor(tensor) = expr {
+                if seen.insert(tensor.key()) {
+                    tensor
+                        .op()
+                        .inputs()
+                        .into_iter()
+                        .for_each(|input| walk(input, seen));
+                }
+            }
+        }
+        let mut seen = std::collections::HashSet::new();
+        walk(self, &mut seen);
+        seen.len()
+    }
+}
+
+impl Tensor {
+    fn deep_clone(&self, memo: &mut HashMap<usize, (Tensor, Tensor)>) -> Self {
+        if let Some((_, copy)) = memo.get(&self.key()) {
+            return copy.clone();
+        }
+        let op = self.op().map_inputs(|input| input.deep_clone_memo(memo));
+        let copy = Tensor::new(self.with_grad(), self.read().clone(), op);
+        copy.bounds().copy_from(self.bounds());
+        copy.retain().copy_from(self.retain());
+        copy.grad_mask().copy_from(self.grad_mask());
+        #[cfg(debug_assertions)]
+        if self.is_logic() {
+            copy.mark_logic();
+        }
+        memo.insert(self.key(), (self.clone(), copy.clone()));
+        copy
+    }
+}
+
+/// Evaluate `expr` at each values of `param`, reusing the graph
+///
+/// Only the subgraph depending on `param` is recomputed at each point,
+/// and `param` is left at the last values. The values may have different lengths,
+/// the downstream lengths are re-derived at each point
+///
+/// ## Panics
+///
+/// When a recompute panics, e.g., `param` becomes shorter than a
+/// [`narrow`](Expression::narrow) of it
+pub fn sweep(expr: &Expression, param: &TensorRef, values: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    values
+        .iter()
+        .map(|value| {
+            before_update();
+            param.assign(value.clone());
+            match expr.value() {
+                super::ScalarTensor::Scalar(x) => vec![*x],
+                tensor => tensor.to_tensor().expect("tensor"),
+            }
+        })
+        .collect()
+}
+
+/// Parallel [`sweep`], each thread sweeps a chunk of `values`
+/// on its own [deep clone](Expression::deep_clone_with_fresh_tensors) of the graph
+///
+/// The original graph (including `param`) is not modified
+#[cfg(feature = "rayon")]
+pub fn sweep_par(expr: &Expression, param: &TensorRef, values: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    use rayon::prelude::*;
+    let chunk_size = values.len().div_ceil(rayon::current_num_threads()).max(1);
+    values
+        .par_chunks(chunk_size)
+        .map(|chunk| {
+            let mut memo = HashMap::new();
+            let expr = expr.deep_clone_memo(&mut memo);
+            let param = TensorRef(param.0.deep_clone(&mut memo));
+            super::recompute::with_local_epoch(|| sweep(&expr, &param, chunk))
+        })
+        .collect::<Vec<_>>()
+        .concat()
+}
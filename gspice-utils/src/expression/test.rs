@@ -4,7 +4,7 @@ use ordered_float::OrderedFloat;
 use rand::prelude::Distribution;
 use serial_test::serial;
 
-use super::{before_update, Expression, ScalarTensor};
+use super::{before_update, Expression, ScalarTensor, Tolerance};
 use std::ops::*;
 
 macro_rules! assert_eq_vec {
@@ -14,14 +14,9 @@ macro_rules! assert_eq_vec {
     ($lhs:expr, $rhs:expr, $tolerance:expr) => {
         let lhs = $lhs;
         let rhs = $rhs;
-        assert!(
-            lhs.len() == rhs.len()
-                && lhs
-                    .iter()
-                    .zip(rhs.iter())
-                    .all(|(x1, x2)| OrderedFloat(f64::abs(x1 - x2)).le(&OrderedFloat($tolerance))),
-            "left:  {lhs:?}\nright: {rhs:?}"
-        )
+        if let Err(e) = Tolerance::new(0.0, $tolerance).check(&lhs[..], &rhs[..]) {
+            panic!("{e}\nleft:  {lhs:?}\nright: {rhs:?}")
+        }
     };
 }
 
@@ -209,7 +204,7 @@ fn len_mismatch_update() {
     let (y, _) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
     let f = x.add(&y);
     before_update();
-    x_ref.assign(vec![1.0]);
+    x_ref.assign(vec![1.0, 2.0]);
     _ = f.value();
 }
 
@@ -985,3 +980,4915 @@ fn unary_op() {
     assert_scalar!(&const1_abs, f64::abs(x1));
     assert_scalar!(&const1_erf, candle_core::cpu::erf::erf(x1));
 }
+
+/// central finite difference `(f(x+h) - f(x-h)) / 2h`
+fn finite_difference(f: impl Fn(f64) -> f64, x: f64) -> f64 {
+    let h = 1e-6 * x.abs().max(1.0);
+    (f(x + h) - f(x - h)) / (2.0 * h)
+}
+
+macro_rules! assert_unary_finite_difference {
+    ($tensor:expr, $tensor_ref:expr, $values:expr, $f:expr, $op:ident($($arg:expr),*)) => {
+        assert_unary_finite_difference!($tensor, $tensor_ref, $values, $f, $op($($arg),*), 1e-6);
+    };
+    ($tensor:expr, $tensor_ref:expr, $values:expr, $f:expr, $op:ident($($arg:expr),*), $rel_tolerance:expr) => {
+        let grads = $tensor.$op($($arg),*).backward();
+        let got = grads.get($tensor_ref).unwrap();
+        let want: Vec<f64> = $values.iter().map(|x| finite_difference($f, *x)).collect();
+        assert!(
+            got.iter().zip(want.iter()).all(|(g, w)| {
+                OrderedFloat(f64::abs(g - w)).le(&OrderedFloat($rel_tolerance * w.abs().max(1.0)))
+            }),
+            "{}\nleft:  {got:?}\nright: {want:?}",
+            stringify!($op)
+        )
+    };
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_unary_finite_difference() {
+    let values = vec![-2.4, -0.7, 0.3, 1.2, 2.9];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    assert_unary_finite_difference!(x, &x_ref, values, |x| -x, neg());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::sin, sin());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::cos, cos());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::tanh, tanh());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::tan, tan());
+    assert_unary_finite_difference!(x, &x_ref, values, |x| x * x, sqr());
+    assert_unary_finite_difference!(x, &x_ref, values, |x| x * x * x, cubic());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::exp, exp());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::abs, abs());
+    assert_unary_finite_difference!(x, &x_ref, values, candle_core::cpu::erf::erf, erf());
+    // no gradient away from the discontinuities
+    assert_unary_finite_difference!(x, &x_ref, values, f64::ceil, ceil());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::floor, floor());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::round, round());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::signum, sign());
+
+    let values = vec![0.1, 0.5, 1.2, 7.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    assert_unary_finite_difference!(x, &x_ref, values, f64::sqrt, sqrt());
+    assert_unary_finite_difference!(x, &x_ref, values, f64::ln, log());
+
+    let values = vec![0.1, 0.5, 0.9];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    x.mark_logic();
+    assert_unary_finite_difference!(x, &x_ref, values, |x| 1.0 - x, logic_not());
+
+    // tan'(x) = 1 + tan²(x) explodes near π/2
+    let half_pi = std::f64::consts::FRAC_PI_2;
+    let values = vec![half_pi - 1e-2, half_pi - 1e-3, -half_pi + 1e-3, half_pi + 1e-2];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    assert_unary_finite_difference!(x, &x_ref, values, f64::tan, tan(), 1e-5);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_abs_smooth() {
+    // abs: subgradient at ±0 is 0
+    let (x, x_ref) = Expression::tensor(vec![-1.0, -0.0, 0.0, 1.0], true);
+    let grads = x.abs().backward();
+    assert_grad!(grads.get(&x_ref), vec![-1.0, 0.0, 0.0, 1.0]);
+
+    // abs_smooth: gradient is continuous across zero
+    let epsilon = 0.1;
+    let values: Vec<f64> = (-10..=10).map(|i| i as f64 * 1e-3).collect();
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.abs_smooth(epsilon);
+    assert_tensor!(&f, values.iter().map(|x| x.hypot(epsilon)).collect());
+    let grads = f.backward();
+    let grad = grads.get(&x_ref).unwrap();
+    assert!(grad.windows(2).all(|w| (w[1] - w[0]).abs() < 2e-2), "{grad}");
+    assert_eq!(grad[10], 0.0);
+    assert_unary_finite_difference!(x, &x_ref, values, |x| (x * x + epsilon * epsilon).sqrt(), abs_smooth(epsilon));
+
+    // abs_smooth converges to abs
+    let values = vec![-2.0, -1e-3, 0.0, 1e-3, 2.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.abs_smooth(1e-12);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), values.iter().map(|x| x.abs()).collect::<Vec<_>>(), 1e-12);
+    let (grads, abs_grads) = (f.backward(), x.abs().backward());
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), abs_grads.get(&x_ref).unwrap(), 1e-12);
+
+    before_update();
+    x_ref.assign(vec![-3.0, 4.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![3.0, 4.0], 1e-12);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_clamped() {
+    // log_clamped: clamped [-1.0, 0.0, 0.05], unclamped [0.5, 2.0]
+    let min_arg = 0.1;
+    let values = vec![-1.0, 0.0, 0.05, 0.5, 2.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.log_clamped(min_arg);
+    assert_tensor!(&f, values.iter().map(|x| x.max(min_arg).ln()).collect());
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0, 0.0, 0.0, 2.0, 0.5]);
+    assert_unary_finite_difference!(x, &x_ref, values, |x| x.max(min_arg).ln(), log_clamped(min_arg));
+
+    // sqrt_clamped: clamped [-1.0, 0.0, 0.1], unclamped [1.0, 4.0]
+    let min_arg = 0.25;
+    let values = vec![-1.0, 0.0, 0.1, 1.0, 4.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.sqrt_clamped(min_arg);
+    assert_tensor!(&f, values.iter().map(|x| x.max(min_arg).sqrt()).collect());
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0, 0.0, 0.0, 0.5, 0.25]);
+    assert_unary_finite_difference!(x, &x_ref, values, |x| x.max(min_arg).sqrt(), sqrt_clamped(min_arg));
+    // sqrt_clamped(0) has no NaN below zero
+    let (x, _) = Expression::tensor(vec![-1.0, 0.0, 4.0], true);
+    assert_tensor!(&x.sqrt_clamped(0.0), vec![0.0, 0.0, 2.0]);
+
+    // div_eps: clamped [-0.05, 0.05], unclamped [-2.0, 3.0]
+    let eps = 0.1;
+    let div_eps = |lhs: f64, rhs: f64| lhs / if rhs.abs() >= eps { rhs } else { eps.copysign(rhs) };
+    let lhs_values = vec![1.5, -2.0, 0.7, 3.0];
+    let rhs_values = vec![-2.0, -0.05, 0.05, 3.0];
+    let (lhs, lhs_ref) = Expression::tensor(lhs_values.clone(), true);
+    let (rhs, rhs_ref) = Expression::tensor(rhs_values.clone(), true);
+    let f = lhs.div_eps(&rhs, eps);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![-0.75, 20.0, 7.0, 1.0], 1e-12);
+    let grads = f.backward();
+    let lhs_want: Vec<f64> = izip!(&lhs_values, &rhs_values).map(|(l, r)| finite_difference(|l| div_eps(l, *r), *l)).collect();
+    let rhs_want: Vec<f64> = izip!(&lhs_values, &rhs_values).map(|(l, r)| finite_difference(|r| div_eps(*l, r), *r)).collect();
+    assert_eq_vec!(grads.get(&lhs_ref).unwrap(), lhs_want, 1e-6);
+    assert_eq_vec!(grads.get(&rhs_ref).unwrap(), rhs_want, 1e-6);
+    assert_eq_vec!(grads.get(&rhs_ref).unwrap(), vec![-0.375, 0.0, 0.0, -1.0 / 3.0], 1e-12);
+    // rhs = 0 is treated as positive
+    let (rhs, rhs_ref) = Expression::tensor(vec![0.0], true);
+    let f = Expression::constant(2.0).div_eps(&rhs, eps);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![20.0], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&rhs_ref), vec![0.0]);
+    // broadcast and recompute
+    let (x, x_ref) = Expression::tensor(vec![0.0, 4.0], true);
+    let f = x.div_eps(&Expression::constant(0.0), eps);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.0, 40.0], 1e-12);
+    before_update();
+    x_ref.assign(vec![1.0, -1.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![10.0, -10.0], 1e-12);
+}
+
+/// `(name, constructor, reference)` of a comparison with a sharpness
+type CmpCase<'a> = (
+    &'a str,
+    fn(&Expression, &Expression, f64) -> Expression,
+    &'a dyn Fn(f64, f64) -> f64,
+);
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_tanh() {
+    let k = 5.0;
+    let le = move |a: f64, b: f64| 0.5 * (1.0 - (k * (a - b)).tanh());
+    let ge = move |a: f64, b: f64| 0.5 * (1.0 + (k * (a - b)).tanh());
+    let eq = move |a: f64, b: f64| 1.0 - (k * (a - b)).tanh().powi(2);
+    let ne = move |a: f64, b: f64| (k * (a - b)).tanh().powi(2);
+    // near a=b and far from it
+    let lhs_values = vec![0.0, 0.01, -0.02, 0.1, 1.0, -3.0];
+    let rhs_values = vec![0.0, 0.0, 0.01, -0.05, -0.5, 1.0];
+    let (a, a_ref) = Expression::tensor(lhs_values.clone(), true);
+    let (b, b_ref) = Expression::tensor(rhs_values.clone(), true);
+    let ops: [CmpCase; 6] = [
+        ("eq", Expression::eq_tanh, &eq),
+        ("ne", Expression::ne_tanh, &ne),
+        ("le", Expression::le_tanh, &le),
+        ("ge", Expression::ge_tanh, &ge),
+        ("lt", Expression::lt_tanh, &le),
+        ("gt", Expression::gt_tanh, &ge),
+    ];
+    for (name, op, f) in ops {
+        let a_want: Vec<f64> = izip!(&lhs_values, &rhs_values).map(|(x, y)| finite_difference(|x| f(x, *y), *x)).collect();
+        let b_want: Vec<f64> = izip!(&lhs_values, &rhs_values).map(|(x, y)| finite_difference(|y| f(*x, y), *y)).collect();
+        let grads = op(&a, &b, k).backward();
+        let (a_got, b_got) = (grads.get(&a_ref).unwrap(), grads.get(&b_ref).unwrap());
+        assert!(a_got.iter().zip(&a_want).all(|(g, w)| (g - w).abs() < 1e-6), "{name} lhs\nleft:  {a_got:?}\nright: {a_want:?}");
+        assert!(b_got.iter().zip(&b_want).all(|(g, w)| (g - w).abs() < 1e-6), "{name} rhs\nleft:  {b_got:?}\nright: {b_want:?}");
+        // broadcast
+        let grads = op(&a, &Expression::constant(0.0), k).backward();
+        let a_got = grads.get(&a_ref).unwrap();
+        let a_want: Vec<f64> = lhs_values.iter().map(|x| finite_difference(|x| f(x, 0.0), *x)).collect();
+        assert!(a_got.iter().zip(&a_want).all(|(g, w)| (g - w).abs() < 1e-6), "{name} fix rhs\nleft:  {a_got:?}\nright: {a_want:?}");
+        let grads = op(&Expression::constant(0.0), &b, k).backward();
+        let b_got = grads.get(&b_ref).unwrap();
+        let b_want: Vec<f64> = rhs_values.iter().map(|y| finite_difference(|y| f(0.0, y), *y)).collect();
+        assert!(b_got.iter().zip(&b_want).all(|(g, w)| (g - w).abs() < 1e-6), "{name} fix lhs\nleft:  {b_got:?}\nright: {b_want:?}");
+    }
+    // forward stays discrete
+    assert_tensor!(&a.lt_tanh(&b, k), vec![0.0, 0.0, 1.0, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_smoothstep() {
+    let epsilon = 0.5;
+    let ramp = move |a: f64, b: f64| {
+        let t = ((a - b + epsilon) / (2.0 * epsilon)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let bump = move |a: f64, b: f64| {
+        let u = ((a - b).abs() / epsilon).min(1.0);
+        1.0 - u * u * (3.0 - 2.0 * u)
+    };
+    let le = move |a, b| 1.0 - ramp(a, b);
+    let ne = move |a, b| 1.0 - bump(a, b);
+    // inside the band, and outside (|a-b| >= ε) where the gradient is exactly 0
+    let lhs_values = vec![0.1, -0.2, 0.45, 0.5, -0.5, 0.8, -3.0];
+    let rhs_values = vec![0.0; 7];
+    let (a, a_ref) = Expression::tensor(lhs_values.clone(), true);
+    let (b, b_ref) = Expression::tensor(rhs_values.clone(), true);
+    let ops: [CmpCase; 6] = [
+        ("eq", Expression::eq_smoothstep, &bump),
+        ("ne", Expression::ne_smoothstep, &ne),
+        ("le", Expression::le_smoothstep, &le),
+        ("ge", Expression::ge_smoothstep, &ramp),
+        ("lt", Expression::lt_smoothstep, &le),
+        ("gt", Expression::gt_smoothstep, &ramp),
+    ];
+    for (name, op, f) in ops {
+        let a_want: Vec<f64> = izip!(&lhs_values, &rhs_values).map(|(x, y)| finite_difference(|x| f(x, *y), *x)).collect();
+        let b_want: Vec<f64> = izip!(&lhs_values, &rhs_values).map(|(x, y)| finite_difference(|y| f(*x, y), *y)).collect();
+        let grads = op(&a, &b, epsilon).backward();
+        let (a_got, b_got) = (grads.get(&a_ref).unwrap(), grads.get(&b_ref).unwrap());
+        assert!(a_got.iter().zip(&a_want).all(|(g, w)| (g - w).abs() < 1e-5), "{name} lhs\nleft:  {a_got:?}\nright: {a_want:?}");
+        assert!(b_got.iter().zip(&b_want).all(|(g, w)| (g - w).abs() < 1e-5), "{name} rhs\nleft:  {b_got:?}\nright: {b_want:?}");
+        assert!(a_got[3..].iter().all(|g| *g == 0.0), "{name}: {a_got:?}");
+        assert!(b_got[3..].iter().all(|g| *g == 0.0), "{name}: {b_got:?}");
+        // gradient is continuous at ±ε
+        for edge in [-epsilon, epsilon] {
+            let (x, x_ref) = Expression::tensor(vec![edge - 1e-9, edge + 1e-9], true);
+            let grads = op(&x, &Expression::constant(0.0), epsilon).backward();
+            let grad = grads.get(&x_ref).unwrap();
+            assert!(grad.iter().all(|g| g.abs() < 1e-7), "{name} at {edge}: {grad:?}");
+        }
+    }
+    // forward saturates at exactly 0/1
+    let (x, _) = Expression::tensor(vec![-3.0, -0.1, 0.0, 0.1, 3.0], true);
+    let zero = Expression::constant(0.0);
+    assert_tensor!(&x.lt_smoothstep(&zero, epsilon), vec![1.0, 1.0, 0.0, 0.0, 0.0]);
+    assert_tensor!(&x.ge_smoothstep(&zero, epsilon), vec![0.0, 0.0, 1.0, 1.0, 1.0]);
+    assert_tensor!(&x.eq_smoothstep(&zero, epsilon), vec![0.0, 0.0, 1.0, 0.0, 0.0]);
+    assert_tensor!(&x.ne_smoothstep(&zero, epsilon), vec![1.0, 1.0, 0.0, 1.0, 1.0]);
+}
+
+#[test]
+#[serial]
+fn annealed_sharpness() {
+    use super::SharpnessHandle;
+    let (a, a_ref) = Expression::tensor(vec![-0.3, -0.01, 0.0, 0.02, 0.5], true);
+    let (b, b_ref) = Expression::tensor(vec![0.0, 0.0, 0.1, -0.01, 0.3], true);
+    let k = SharpnessHandle::new(1.0);
+    let epsilon = SharpnessHandle::new(1.0);
+    let f = a
+        .le_sigmoid_annealed(&b, &k)
+        .add(&a.eq_tanh_annealed(&b, &k))
+        .add(&a.gt_linear_annealed(&b, &epsilon))
+        .add(&a.ne_smoothstep_annealed(&b, &epsilon));
+    let fresh = |k: f64, epsilon: f64| {
+        a.le_sigmoid(&b, k)
+            .add(&a.eq_tanh(&b, k))
+            .add(&a.gt_linear(&b, epsilon))
+            .add(&a.ne_smoothstep(&b, epsilon))
+    };
+    for (new_k, new_epsilon) in [(1.0, 1.0), (100.0, 0.05), (1.0, 1.0)] {
+        k.set(new_k);
+        epsilon.set(new_epsilon);
+        assert_eq!(k.get(), new_k);
+        let want = fresh(new_k, new_epsilon);
+        assert_eq_vec!(
+            f.value().to_tensor().unwrap(),
+            want.value().to_tensor().unwrap()
+        );
+        let (grads, want_grads) = (f.backward(), want.backward());
+        assert_eq_vec!(grads.get(&a_ref).unwrap(), want_grads.get(&a_ref).unwrap());
+        assert_eq_vec!(grads.get(&b_ref).unwrap(), want_grads.get(&b_ref).unwrap());
+    }
+    k.set(1.0);
+    let soft = f.backward().get(&a_ref).unwrap().to_vec();
+    k.set(100.0);
+    let sharp = f.backward().get(&a_ref).unwrap().to_vec();
+    assert_ne!(soft, sharp);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn invalid_sharpness() {
+    use super::{Error, GradMethod, SharpnessHandle};
+    let (a, _) = Expression::tensor(vec![-0.5, 0.0, 0.5], true);
+    let (b, _) = Expression::tensor(vec![0.0, 0.0], true);
+    for bad in [0.0, -0.0, -1.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+        for method in [GradMethod::try_sigmoid, GradMethod::try_linear, GradMethod::try_tanh, GradMethod::try_smoothstep] {
+            assert!(matches!(method(bad), Err(Error::InvalidSharpness(_))), "{bad}");
+        }
+        assert!(matches!(a.try_lt_sigmoid(&a, bad), Err(Error::InvalidSharpness(_))));
+        assert!(matches!(a.try_eq_linear(&a, bad), Err(Error::InvalidSharpness(_))));
+        assert!(matches!(a.try_ge_tanh(&a, bad), Err(Error::InvalidSharpness(_))));
+        assert!(matches!(a.try_ne_smoothstep(&a, bad), Err(Error::InvalidSharpness(_))));
+        assert!(matches!(SharpnessHandle::try_new(bad), Err(Error::InvalidSharpness(_))));
+        // the handle keeps its value
+        let k = SharpnessHandle::new(2.0);
+        assert!(matches!(k.try_set(bad), Err(Error::InvalidSharpness(_))));
+        assert_eq!(k.get(), 2.0);
+        assert!(std::panic::catch_unwind(|| GradMethod::new_sigmoid(bad)).is_err());
+        assert!(std::panic::catch_unwind(|| SharpnessHandle::new(bad)).is_err());
+    }
+    let Err(Error::InvalidSharpness(msg)) = GradMethod::try_linear(-1.0) else { panic!() };
+    assert_eq!(msg, "epsilon must be positive and finite, got -1");
+    // a valid sharpness is the same as the panicking API
+    a.try_lt_sigmoid(&a, 3.0).unwrap().assert_close(&a.lt_sigmoid(&a, 3.0), 0.0, 0.0);
+    a.try_gt_linear(&a.neg(), 0.5).unwrap().backward().assert_close(&a.gt_linear(&a.neg(), 0.5).backward(), 0.0, 0.0);
+    // the length is still checked
+    assert!(matches!(a.try_lt_sigmoid(&b, 3.0), Err(Error::LengthMismatch { .. })));
+    assert!(SharpnessHandle::try_new(f64::MIN_POSITIVE).is_ok());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cmp_generic() {
+    use super::{DiscreteBinaryOp, GradMethod};
+    type Named = fn(&Expression, &Expression) -> Expression;
+    let (a, a_ref) = Expression::tensor(vec![-0.3, -0.01, 0.0, 0.02, 0.5], true);
+    let (b, b_ref) = Expression::tensor(vec![0.0, 0.0, 0.0, -0.01, 0.3], true);
+    let ops = [DiscreteBinaryOp::Eq, DiscreteBinaryOp::Ne, DiscreteBinaryOp::Le, DiscreteBinaryOp::Ge, DiscreteBinaryOp::Lt, DiscreteBinaryOp::Gt];
+    let methods: [(GradMethod, [Named; 6]); 5] = [
+        (GradMethod::Discrete, [Expression::eq, Expression::ne, Expression::le, Expression::ge, Expression::lt, Expression::gt]),
+        (GradMethod::new_linear(0.1), [|a, b| a.eq_linear(b, 0.1), |a, b| a.ne_linear(b, 0.1), |a, b| a.le_linear(b, 0.1), |a, b| a.ge_linear(b, 0.1), |a, b| a.lt_linear(b, 0.1), |a, b| a.gt_linear(b, 0.1)]),
+        (GradMethod::new_sigmoid(3.0), [|a, b| a.eq_sigmoid(b, 3.0), |a, b| a.ne_sigmoid(b, 3.0), |a, b| a.le_sigmoid(b, 3.0), |a, b| a.ge_sigmoid(b, 3.0), |a, b| a.lt_sigmoid(b, 3.0), |a, b| a.gt_sigmoid(b, 3.0)]),
+        (GradMethod::new_tanh(3.0), [|a, b| a.eq_tanh(b, 3.0), |a, b| a.ne_tanh(b, 3.0), |a, b| a.le_tanh(b, 3.0), |a, b| a.ge_tanh(b, 3.0), |a, b| a.lt_tanh(b, 3.0), |a, b| a.gt_tanh(b, 3.0)]),
+        (GradMethod::new_smoothstep(0.1), [|a, b| a.eq_smoothstep(b, 0.1), |a, b| a.ne_smoothstep(b, 0.1), |a, b| a.le_smoothstep(b, 0.1), |a, b| a.ge_smoothstep(b, 0.1), |a, b| a.lt_smoothstep(b, 0.1), |a, b| a.gt_smoothstep(b, 0.1)]),
+    ];
+    for (method, named) in methods {
+        for (op, named) in ops.into_iter().zip(named) {
+            let (got, want) = (a.cmp(&b, op, method.clone()), named(&a, &b));
+            assert_eq_vec!(got.value().to_tensor().unwrap(), want.value().to_tensor().unwrap());
+            let (grads, want_grads) = (got.backward(), want.backward());
+            assert_eq_vec!(grads.get(&a_ref).unwrap(), want_grads.get(&a_ref).unwrap());
+            assert_eq_vec!(grads.get(&b_ref).unwrap(), want_grads.get(&b_ref).unwrap());
+            let (got, want) = (Expression::constant(0.0).cmp(&b, op, method.clone()), named(&Expression::constant(0.0), &b));
+            assert_eq_vec!(got.value().to_tensor().unwrap(), want.value().to_tensor().unwrap());
+            let (grads, want_grads) = (got.backward(), want.backward());
+            assert_eq_vec!(grads.get(&b_ref).unwrap(), want_grads.get(&b_ref).unwrap());
+        }
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn logic_xor_nand_nor() {
+    let (a, a_ref) = Expression::tensor(vec![0.0, 0.0, 1.0, 1.0, 0.3], true);
+    let (b, b_ref) = Expression::tensor(vec![0.0, 1.0, 0.0, 1.0, 0.6], true);
+    a.mark_logic();
+    b.mark_logic();
+    let a_values = [0.0, 0.0, 1.0, 1.0, 0.3];
+    let b_values = [0.0, 1.0, 0.0, 1.0, 0.6];
+    let xor = |a: f64, b: f64| a + b - 2.0 * a * b;
+    let nand = |a: f64, b: f64| 1.0 - a * b;
+    let nor = |a: f64, b: f64| (1.0 - a) * (1.0 - b);
+    assert_eq_vec!(a.logic_xor(&b).value().to_tensor().unwrap(), vec![0.0, 1.0, 1.0, 0.0, 0.54], 1e-12);
+    assert_tensor!(&a.logic_nand(&b), vec![1.0, 1.0, 1.0, 0.0, 1.0 - 0.3 * 0.6]);
+    assert_tensor!(&a.logic_nor(&b), vec![1.0, 0.0, 0.0, 0.0, 0.7 * 0.4]);
+    // (name, gate, reference)
+    type Case<'a> = (&'a str, Expression, &'a dyn Fn(f64, f64) -> f64);
+    let ops: [Case; 3] = [
+        ("xor", a.logic_xor(&b), &xor),
+        ("nand", a.logic_nand(&b), &nand),
+        ("nor", a.logic_nor(&b), &nor),
+    ];
+    for (name, f, f_ref) in ops {
+        let grads = f.backward();
+        let a_want: Vec<f64> = izip!(&a_values, &b_values).map(|(x, y)| finite_difference(|x| f_ref(x, *y), *x)).collect();
+        let b_want: Vec<f64> = izip!(&a_values, &b_values).map(|(x, y)| finite_difference(|y| f_ref(*x, y), *y)).collect();
+        let (a_got, b_got) = (grads.get(&a_ref).unwrap(), grads.get(&b_ref).unwrap());
+        assert!(a_got.iter().zip(&a_want).all(|(g, w)| (g - w).abs() < 1e-6), "{name} lhs\nleft:  {a_got:?}\nright: {a_want:?}");
+        assert!(b_got.iter().zip(&b_want).all(|(g, w)| (g - w).abs() < 1e-6), "{name} rhs\nleft:  {b_got:?}\nright: {b_want:?}");
+    }
+    // xor(a, b) == or(and(a, not b), and(not a, b)) on logic values
+    let composed = a.logic_and(&b.logic_not()).logic_or(&a.logic_not().logic_and(&b));
+    assert_eq_vec!(&a.logic_xor(&b).value().to_tensor().unwrap()[..4], &composed.value().to_tensor().unwrap()[..4]);
+}
+
+#[test]
+#[serial]
+fn saturate01() {
+    let (x, x_ref) = Expression::tensor(vec![-0.5, 0.0, 0.3, 1.0, 1.0 + f64::EPSILON, 2.0], true);
+    let f = x.saturate01();
+    assert_tensor!(&f, vec![0.0, 0.0, 0.3, 1.0, 1.0, 1.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0, 1.0, 1.0, 1.0, 0.0, 0.0]);
+    // the output is a logic tensor, no `mark_logic` needed
+    _ = f.logic_and(&f);
+
+    // 50 smoothed comparisons composed by logic ops
+    let (x, x_ref) = Expression::rand_uniform(16, -1.0, 1.0, true);
+    let (y, _) = Expression::rand_uniform(16, -1.0, 1.0, true);
+    let mut all = Expression::constant(1.0);
+    let mut any = Expression::constant(0.0);
+    for i in 0..50 {
+        let threshold = Expression::constant(i as f64 / 25.0 - 1.0);
+        let blend = x
+            .le_sigmoid(&threshold, 10.0)
+            .cond(&y, &x)
+            .sqr()
+            .saturate01();
+        all = all.logic_and(&x.gt_sigmoid(&threshold, 10.0).logic_or(&blend));
+        any = any.logic_or(&x.lt_tanh(&threshold, 10.0).logic_xor(&blend));
+    }
+    let f = all.add(&any);
+    let grads = f.backward();
+    assert!(grads.get(&x_ref).unwrap().iter().all(|g| g.is_finite()));
+    for v in all.value().to_tensor().unwrap().iter() {
+        assert!((0.0..=1.0).contains(v), "{v}");
+    }
+    for v in any.value().to_tensor().unwrap().iter() {
+        assert!((0.0..=1.0).contains(v), "{v}");
+    }
+
+    // the smoothed comparisons are clamped, and compose with the logic ops
+    super::set_smooth_forward(super::SmoothForward::Always);
+    let mut all = Expression::constant(1.0);
+    for i in 0..50 {
+        let threshold = Expression::constant(i as f64 / 25.0 - 1.0);
+        all = all.logic_and(
+            &x.gt_tanh(&threshold, 10.0)
+                .logic_or(&x.le_linear(&threshold, 0.1)),
+        );
+    }
+    super::set_smooth_forward(super::SmoothForward::OnlyWithGrad);
+    for v in all.value().to_tensor().unwrap().iter() {
+        assert!((0.0..=1.0).contains(v), "{v}");
+    }
+}
+
+#[test]
+#[serial]
+fn backward_cond_ste() {
+    let (cond, cond_ref) = Expression::tensor(vec![0.0, 0.2, 0.5, 0.7, 1.0], true);
+    cond.mark_logic();
+    let (on_true, on_true_ref) = Expression::tensor(vec![1.0, 2.0, 3.0, 4.0, 5.0], true);
+    let (on_false, on_false_ref) = Expression::tensor(vec![-1.0, -2.0, -3.0, -4.0, -5.0], true);
+    let ste = cond.cond_ste(&on_true, &on_false);
+    let smooth = cond.cond(&on_true, &on_false);
+    // forward picks the branch exactly
+    assert_tensor!(&ste, vec![-1.0, -2.0, 3.0, 4.0, 5.0]);
+    // backward equals the smooth one
+    let (grads, smooth_grads) = (ste.backward(), smooth.backward());
+    for tensor_ref in [&cond_ref, &on_true_ref, &on_false_ref] {
+        assert_eq_vec!(
+            grads.get(tensor_ref).unwrap(),
+            smooth_grads.get(tensor_ref).unwrap()
+        );
+    }
+    // broadcast
+    let ste = cond.cond_ste(&Expression::constant(1.0), &on_false);
+    assert_tensor!(&ste, vec![-1.0, -2.0, 1.0, 1.0, 1.0]);
+    let ste = cond.cond_ste(&on_true, &Expression::constant(0.0));
+    assert_tensor!(&ste, vec![0.0, 0.0, 3.0, 4.0, 5.0]);
+    let ste = cond.cond_ste(&Expression::constant(1.0), &Expression::constant(0.0));
+    assert_tensor!(&ste, vec![0.0, 0.0, 1.0, 1.0, 1.0]);
+    // recompute
+    before_update();
+    cond_ref.assign(vec![1.0, 0.6, 0.4, 0.0, 0.5]);
+    assert_tensor!(&ste, vec![1.0, 1.0, 0.0, 0.0, 1.0]);
+    // constant cond behaves the same as `cond`
+    let (on_true, _) = Expression::tensor(vec![1.0, 2.0], true);
+    let c = Expression::constant(0.3);
+    assert_tensor!(
+        &c.cond_ste(&on_true, &Expression::constant(0.0)),
+        vec![0.3, 0.6]
+    );
+    assert_scalar!(
+        &c.cond_ste(&Expression::constant(1.0), &Expression::constant(0.0)),
+        0.3
+    );
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_select() {
+    use super::Error;
+    // 3 regions: cutoff 0 / triode x² / saturation 2x-1, C1 at the boundaries
+    let values = vec![-1.0, -1e-3, 0.0, 0.5, 1.0 - 1e-3, 1.0, 3.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    // the discrete comparisons also pass gradient, select the regions by a copy without gradient
+    let (region, region_ref) = Expression::tensor(values.clone(), false);
+    let (zero, one) = (Expression::constant(0.0), Expression::constant(1.0));
+    let f = Expression::select(
+        &[region.lt(&zero), region.lt(&one)],
+        &[zero.clone(), x.sqr()],
+        &x.mul(&Expression::constant(2.0)).sub(&one),
+    );
+    let closed_form = |x: f64| if x < 0.0 { 0.0 } else if x < 1.0 { x * x } else { 2.0 * x - 1.0 };
+    let derivative = |x: f64| if x < 0.0 { 0.0 } else if x < 1.0 { 2.0 * x } else { 2.0 };
+    assert_tensor!(&f, values.iter().map(|x| closed_form(*x)).collect());
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), values.iter().map(|x| derivative(*x)).collect());
+    // recompute
+    before_update();
+    x_ref.assign(vec![2.0, 0.25, -3.0, 1.0, 0.0, 0.75, -0.5]);
+    region_ref.assign(vec![2.0, 0.25, -3.0, 1.0, 0.0, 0.75, -0.5]);
+    assert_tensor!(&f, vec![3.0, 0.0625, 0.0, 1.0, 0.0, 0.5625, 0.0]);
+
+    // smooth mode equals the nested `cond`
+    let (c0, c0_ref) = Expression::tensor(vec![0.2, 0.0, 1.0, 0.6], true);
+    let (c1, c1_ref) = Expression::tensor(vec![0.7, 0.3, 0.5, 1.0], true);
+    c0.mark_logic();
+    c1.mark_logic();
+    let (v0, v0_ref) = Expression::tensor(vec![1.0, 2.0, 3.0, 4.0], true);
+    let (d, d_ref) = Expression::tensor(vec![-1.0, -2.0, -3.0, -4.0], true);
+    let v1 = Expression::constant(10.0);
+    let smooth = Expression::select_smooth(&[c0.clone(), c1.clone()], &[v0.clone(), v1.clone()], &d);
+    let nested = c0.cond(&v0, &c1.cond(&v1, &d));
+    assert_eq_vec!(smooth.value().to_tensor().unwrap(), nested.value().to_tensor().unwrap(), 1e-12);
+    let (grads, nested_grads) = (smooth.backward(), nested.backward());
+    for tensor_ref in [&c0_ref, &c1_ref, &v0_ref, &d_ref] {
+        assert_eq_vec!(grads.get(tensor_ref).unwrap(), nested_grads.get(tensor_ref).unwrap(), 1e-12);
+    }
+    // hard mode picks the first cond >= 0.5, the backward is the nested one at the hard conds
+    // `[0, 0, 1, 1]` and `[1, 0, 1, 1]`: the chosen branch gets the gradient
+    let hard = Expression::select(&[c0.clone(), c1.clone()], &[v0.clone(), v1.clone()], &d);
+    assert_tensor!(&hard, vec![10.0, -2.0, 3.0, 4.0]);
+    let grads = hard.backward();
+    assert_grad!(grads.get(&v0_ref), vec![0.0, 0.0, 1.0, 1.0]);
+    assert_grad!(grads.get(&d_ref), vec![0.0, 1.0, 0.0, 0.0]);
+    // `v_0 - r_1`, `r_1 = [10, -2, 10, 10]`
+    assert_grad!(grads.get(&c0_ref), vec![-9.0, 4.0, -7.0, -6.0]);
+    // `(1 - c_0) (v_1 - d)`
+    assert_grad!(grads.get(&c1_ref), vec![11.0, 12.0, 0.0, 0.0]);
+
+    // validation
+    let (short, _) = Expression::tensor(vec![1.0, 2.0], true);
+    assert!(matches!(Expression::try_select(std::slice::from_ref(&c0), &[], &d), Err(Error::LengthMismatch { expected: 1, got: 0, .. })));
+    assert!(matches!(Expression::try_select(std::slice::from_ref(&c0), &[short], &d), Err(Error::LengthMismatch { expected: 4, got: 2, .. })));
+    assert_scalar!(&Expression::select(&[Expression::constant(0.0)], std::slice::from_ref(&one), &Expression::constant(5.0)), 5.0);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_pwl() {
+    use super::{Error, PwlExtrapolation};
+    let xs = [0.0, 1.0, 3.0];
+    let ys = [1.0, 3.0, 2.0];
+    // slopes: 2.0 on [0, 1), -0.5 on [1, 3]
+    let values = vec![-1.0, 0.0, 0.5, 1.0, 2.0, 3.0, 5.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.pwl(&xs, &ys, PwlExtrapolation::Clamp);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0, 1.0, 2.0, 3.0, 2.5, 2.0, 2.0], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0, 2.0, 2.0, -0.5, -0.5, -0.5, 0.0]);
+    let f = x.pwl(&xs, &ys, PwlExtrapolation::Extend);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![-1.0, 1.0, 2.0, 3.0, 2.5, 2.0, 1.0], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![2.0, 2.0, 2.0, -0.5, -0.5, -0.5, -0.5]);
+    // away from breakpoints the gradient is the finite difference
+    let values = vec![-0.5, 0.3, 1.7, 4.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    for extrapolation in [PwlExtrapolation::Clamp, PwlExtrapolation::Extend] {
+        let pwl = super::Pwl::new(&xs, &ys, extrapolation).unwrap();
+        assert_unary_finite_difference!(x, &x_ref, values, |x| pwl.forward(x), pwl(&xs, &ys, extrapolation));
+    }
+    // gradient flows into a parameter feeding self, and recompute
+    let (p, p_ref) = Expression::tensor(vec![0.25], true);
+    let f = p.mul(&Expression::constant(2.0)).pwl(&xs, &ys, PwlExtrapolation::Clamp);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![2.0], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&p_ref), vec![4.0]);
+    before_update();
+    p_ref.assign(vec![1.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![2.5], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&p_ref), vec![-1.0]);
+    // const input
+    assert_scalar!(&Expression::constant(2.0).pwl(&xs, &ys, PwlExtrapolation::Extend), 2.5);
+    // invalid tables
+    assert!(matches!(x.try_pwl(&[], &[], PwlExtrapolation::Clamp), Err(Error::InvalidPwl(_))));
+    assert!(matches!(x.try_pwl(&[0.0, 1.0], &[0.0], PwlExtrapolation::Clamp), Err(Error::InvalidPwl(_))));
+    assert!(matches!(x.try_pwl(&[0.0, 1.0, 1.0], &[0.0, 1.0, 2.0], PwlExtrapolation::Clamp), Err(Error::InvalidPwl(_))));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_spline() {
+    use super::{Error, SplineBoundary};
+    // natural spline of (0, 0), (1, 1), (2, 0) is 1.5x - 0.5x^3 on [0, 1], symmetric on [1, 2]
+    let reference = |x: f64| {
+        let x = if x > 1.0 { 2.0 - x } else { x };
+        (1.5 * x - 0.5 * x.powi(3), 1.5 - 1.5 * x.powi(2))
+    };
+    let xs = [0.0, 1.0, 2.0];
+    let ys = [0.0, 1.0, 0.0];
+    let values = vec![0.0, 0.5, 1.0, 1.25, 2.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.spline(&xs, &ys, SplineBoundary::Natural);
+    let want: Vec<f64> = values.iter().map(|x| reference(*x).0).collect();
+    assert_eq_vec!(f.value().to_tensor().unwrap(), want, 1e-12);
+    let grads = f.backward();
+    let want: Vec<f64> = values[..2].iter().map(|x| reference(*x).1).chain(values[2..].iter().map(|x| -reference(*x).1)).collect();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), want, 1e-12);
+    // linear outside the knots with the end slopes
+    let (x, x_ref) = Expression::tensor(vec![-1.0, 3.0], true);
+    let f = x.spline(&xs, &ys, SplineBoundary::Natural);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![-1.5, -1.5], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![1.5, -1.5]);
+
+    // clamped spline reproduces a cubic with exact end derivatives
+    let cubic = |x: f64| x.powi(3) - 2.0 * x;
+    let d_cubic = |x: f64| 3.0 * x.powi(2) - 2.0;
+    let xs = [-2.0, -0.5, 0.0, 1.0, 2.5];
+    let ys: Vec<f64> = xs.iter().map(|x| cubic(*x)).collect();
+    let bc = SplineBoundary::Clamped { d0: d_cubic(-2.0), dn: d_cubic(2.5) };
+    let values = vec![-2.0, -1.3, -0.5, -0.2, 0.0, 0.7, 1.0, 1.9, 2.5];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.spline(&xs, &ys, bc);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), values.iter().map(|x| cubic(*x)).collect::<Vec<_>>(), 1e-9);
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), values.iter().map(|x| d_cubic(*x)).collect::<Vec<_>>(), 1e-9);
+
+    // C1 at the knots and the ends, gradient is the finite difference
+    // (the second derivative jumps at the clamped ends, so the tolerance is looser)
+    let xs = [0.0, 0.3, 1.0, 1.2, 2.0];
+    let ys = [1.0, -0.5, 0.2, 0.8, 0.0];
+    for bc in [SplineBoundary::Natural, SplineBoundary::Clamped { d0: 0.5, dn: -1.0 }] {
+        let spline = super::Spline::new(&xs, &ys, bc).unwrap();
+        let values: Vec<f64> = vec![-0.5, 0.0, 0.3, 0.6, 1.0, 1.2, 1.5, 2.0, 2.7];
+        let (x, x_ref) = Expression::tensor(values.clone(), true);
+        assert_eq_vec!(x.spline(&xs, &ys, bc).value().to_tensor().unwrap(), values.iter().map(|x| spline.forward(*x)).collect::<Vec<_>>(), 1e-12);
+        assert_unary_finite_difference!(x, &x_ref, values, |x| spline.forward(x), spline(&xs, &ys, bc), 1e-4);
+    }
+
+    // gradient flows into a parameter feeding self, and recompute
+    let (p, p_ref) = Expression::tensor(vec![0.25], true);
+    let f = p.mul(&Expression::constant(2.0)).spline(&[0.0, 1.0, 2.0], &[0.0, 1.0, 0.0], SplineBoundary::Natural);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.6875], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&p_ref), vec![2.25]);
+    before_update();
+    p_ref.assign(vec![0.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.0], 1e-12);
+    // const input
+    assert_scalar!(&Expression::constant(1.0).spline(&[0.0, 1.0, 2.0], &[0.0, 1.0, 0.0], SplineBoundary::Natural), 1.0);
+    // invalid knots
+    assert!(matches!(p.try_spline(&[0.0], &[0.0], SplineBoundary::Natural), Err(Error::InvalidSpline(_))));
+    assert!(matches!(p.try_spline(&[0.0, 1.0], &[0.0], SplineBoundary::Natural), Err(Error::InvalidSpline(_))));
+    assert!(matches!(p.try_spline(&[1.0, 0.0], &[0.0, 1.0], SplineBoundary::Natural), Err(Error::InvalidSpline(_))));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_limexp_diode() {
+    use super::LIMEXP_X0;
+    // exp below the crossover, finite far above it
+    let (x, x_ref) = Expression::tensor(vec![-2.0, 1.0, 1000.0], true);
+    let f = x.limexp();
+    let got = f.value().to_tensor().unwrap();
+    assert_eq_vec!(&got[..2], vec![(-2.0f64).exp(), 1.0f64.exp()], 1e-12);
+    assert!(got[2].is_finite());
+    assert_eq!(got[2], LIMEXP_X0.exp() * (1.0 + 1000.0 - LIMEXP_X0));
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![(-2.0f64).exp(), 1.0f64.exp(), LIMEXP_X0.exp()]);
+    // C1 at the crossover
+    let limexp = |x: f64| Expression::constant(x).limexp().value().to_scalar().unwrap();
+    let h = 1e-6;
+    let (below, above) = (limexp(LIMEXP_X0 - h), limexp(LIMEXP_X0 + h));
+    let at = limexp(LIMEXP_X0);
+    assert!((above - below).abs() <= 3.0 * h * at);
+    let left = (at - below) / h;
+    let right = (above - at) / h;
+    assert!((left - right).abs() <= 1e-5 * at, "{left} {right}");
+    let values = vec![LIMEXP_X0 - 0.5, LIMEXP_X0 + 0.5];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    assert_unary_finite_difference!(x, &x_ref, values, limexp, limexp());
+
+    // diode
+    let (is, n_vt) = (1e-14, 0.02585 * 1.5);
+    let values = vec![-1.0, 0.0, 0.3, 0.6, 0.8];
+    let (v, v_ref) = Expression::tensor(values.clone(), true);
+    let f = v.diode(is, n_vt);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), values.iter().map(|v| is * ((v / n_vt).exp() - 1.0)).collect::<Vec<_>>(), 1e-12);
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&v_ref).unwrap(), values.iter().map(|v| is / n_vt * (v / n_vt).exp()).collect::<Vec<_>>(), 1e-12);
+    // huge forward bias stays finite
+    let (v, v_ref) = Expression::tensor(vec![100.0], true);
+    let f = v.diode(is, n_vt);
+    assert!(f.value().to_tensor().unwrap()[0].is_finite());
+    let grads = f.backward();
+    assert_grad!(grads.get(&v_ref), vec![is / n_vt * LIMEXP_X0.exp()]);
+    // recompute
+    before_update();
+    v_ref.assign(vec![0.3]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![is * (0.3 / n_vt).exp_m1()], 1e-12);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn backward_transition() {
+    let (x0, x1) = (1.0, 3.0);
+    let step = move |x: f64| { let t = ((x - x0) / (x1 - x0)).clamp(0.0, 1.0); 3.0 * t * t - 2.0 * t * t * t };
+    let values = vec![-1.0, 1.0, 1.5, 2.0, 2.9, 3.0, 5.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.smoothstep_range(x0, x1);
+    // exact saturation outside the ramp
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.0, 0.0, 0.15625, 0.5, step(2.9), 1.0, 1.0], 1e-12);
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), vec![0.0, 0.0, 0.5625, 0.75, 0.1425, 0.0, 0.0], 1e-12);
+    // differentiable across x0 / x1
+    let values = vec![x0 - 1e-3, x0, x0 + 1e-3, 1.7, x1 - 1e-3, x1, x1 + 1e-3];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    assert_unary_finite_difference!(x, &x_ref, values, step, smoothstep_range(x0, x1), 1e-5);
+    // downstream logic ops accept it
+    let g = x.smoothstep_range(x0, x1).logic_not();
+    assert!(g.value().to_tensor().unwrap().iter().all(|v| (0.0..=1.0).contains(v)));
+
+    let (center, sigma) = (0.5, 0.2);
+    let pulse = move |x: f64| (-0.5 * ((x - center) / sigma).powi(2)).exp();
+    let values = vec![-1.0, 0.2, 0.5, 0.6, 1.3];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    assert_eq_vec!(x.gauss_pulse(center, sigma).value().to_tensor().unwrap(), values.iter().map(|x| pulse(*x)).collect::<Vec<_>>(), 1e-12);
+    assert_unary_finite_difference!(x, &x_ref, values, pulse, gauss_pulse(center, sigma));
+    assert_scalar!(&Expression::constant(0.5).gauss_pulse(center, sigma), 1.0);
+
+    // gradient into a parameter feeding self, and recompute
+    let (p, p_ref) = Expression::tensor(vec![1.0], true);
+    let f = p.add(&Expression::constant(1.0)).smoothstep_range(x0, x1);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.5], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&p_ref), vec![0.75]);
+    before_update();
+    p_ref.assign(vec![3.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0], 1e-12);
+    let grads = f.backward();
+    assert_grad!(grads.get(&p_ref), vec![0.0]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn broadcast_scalar_tensor() {
+    use super::Error;
+    let sweep_values = vec![1.0, 2.0, 3.0, 4.0];
+    let (sweep, sweep_ref) = Expression::tensor(sweep_values.clone(), true);
+    let (param, param_ref) = Expression::tensor(vec![2.0], true);
+    // binary, both sides
+    let f = param.mul(&sweep);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![2.0, 4.0, 6.0, 8.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&param_ref), vec![10.0]);
+    assert_grad!(grads.get(&sweep_ref), vec![2.0; 4]);
+    let f = sweep.div(&param);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.5, 1.0, 1.5, 2.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&param_ref), vec![-2.5]);
+    assert_grad!(grads.get(&sweep_ref), vec![0.5; 4]);
+    let f = sweep.div_eps(&param, 0.1);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.5, 1.0, 1.5, 2.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&param_ref), vec![-2.5]);
+    // comparison
+    let f = sweep.lt_sigmoid(&param, 1.0);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0, 0.0, 0.0, 0.0]);
+    let grads = f.backward();
+    let want_param: f64 = sweep_values.iter().map(|x| finite_difference(|p| 1.0 / (1.0 + (x - p).exp()), 2.0)).sum();
+    assert_eq_vec!(grads.get(&param_ref).unwrap(), vec![want_param], 1e-6);
+    // cond: the condition and the branches broadcast
+    let (c, c_ref) = Expression::tensor(vec![1.0, 0.0, 1.0, 0.0], true);
+    c.mark_logic();
+    let f = c.cond(&param, &sweep);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![2.0, 2.0, 2.0, 4.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&param_ref), vec![2.0]);
+    assert_grad!(grads.get(&sweep_ref), vec![0.0, 1.0, 0.0, 1.0]);
+    assert_grad!(grads.get(&c_ref), vec![1.0, 0.0, -1.0, -2.0]);
+    let (c, c_ref) = Expression::tensor(vec![1.0], true);
+    c.mark_logic();
+    let f = c.cond(&sweep, &param);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), sweep_values.clone());
+    let grads = f.backward();
+    assert_grad!(grads.get(&c_ref), vec![2.0]);
+    assert_grad!(grads.get(&param_ref), vec![0.0]);
+    // select
+    let (c0, _) = Expression::tensor(vec![0.0, 1.0, 0.0, 0.0], false);
+    c0.mark_logic();
+    let f = Expression::select(&[c0], std::slice::from_ref(&param), &sweep);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0, 2.0, 3.0, 4.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&param_ref), vec![1.0]);
+    assert_grad!(grads.get(&sweep_ref), vec![1.0, 0.0, 1.0, 1.0]);
+    // recompute after updating the scalar
+    let f = param.mul(&sweep).add(&param);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![4.0, 6.0, 8.0, 10.0]);
+    before_update();
+    param_ref.assign(vec![3.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![6.0, 9.0, 12.0, 15.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&param_ref), vec![14.0]);
+    param_ref.assign(vec![2.0]);
+    // recompute a comparison of two tensors
+    let f = sweep.lt(&param);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0, 0.0, 0.0, 0.0]);
+    before_update();
+    param_ref.assign(vec![3.5]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0, 1.0, 1.0, 0.0]);
+    before_update();
+    sweep_ref.assign(vec![4.0, 3.0, 2.0, 1.0]);
+    param_ref.assign(vec![2.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![0.0, 0.0, 0.0, 1.0]);
+    before_update();
+    sweep_ref.assign(sweep_values.clone());
+    // other lengths still mismatch
+    let (short, _) = Expression::tensor(vec![1.0, 2.0], true);
+    assert_eq!(short.try_mul(&sweep).unwrap_err(), Error::LengthMismatch { expected: 2, got: 4, op: "Mul".to_owned() });
+    assert!(param.try_mul(&short).is_ok());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn concat_narrow() {
+    use super::Error;
+    let (a, a_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let (b, b_ref) = Expression::tensor(vec![3.0, 4.0, 5.0], true);
+    let f = Expression::concat(&[a.clone(), Expression::constant(9.0), b.clone()]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0, 2.0, 9.0, 3.0, 4.0, 5.0]);
+    // the backward seeds ones, i.e., the gradient of the sum
+    let grads = f.backward();
+    assert_grad!(grads.get(&a_ref), vec![1.0; 2]);
+    assert_grad!(grads.get(&b_ref), vec![1.0; 3]);
+    // the gradient is sliced back to each part
+    let (w, _) = Expression::tensor(vec![10.0, 20.0, 30.0, 40.0, 50.0], false);
+    let f = Expression::concat(&[a.clone(), b.clone()]).mul(&w);
+    let grads = f.backward();
+    assert_grad!(grads.get(&a_ref), vec![10.0, 20.0]);
+    assert_grad!(grads.get(&b_ref), vec![30.0, 40.0, 50.0]);
+    // the same part twice
+    let grads = Expression::concat(&[a.clone(), a.clone()]).mul(&Expression::tensor(vec![1.0, 2.0, 3.0, 4.0], false).0).backward();
+    assert_grad!(grads.get(&a_ref), vec![4.0, 6.0]);
+
+    // narrow
+    let f = b.narrow(1, 2);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![4.0, 5.0]);
+    let f = f.mul(&Expression::tensor(vec![2.0, 3.0], false).0);
+    let grads = f.backward();
+    assert_grad!(grads.get(&b_ref), vec![0.0, 2.0, 3.0]);
+    // narrow of an updated parameter recomputes
+    before_update();
+    b_ref.assign(vec![-1.0, -2.0, -3.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![-4.0, -9.0]);
+    // corners: max over the narrowed views of a concat
+    let corners = Expression::concat(&[a.clone(), b.clone()]);
+    let f = corners.narrow(0, 2).max(&corners.narrow(2, 2));
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![1.0, 2.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&a_ref), vec![1.0, 1.0]);
+    assert_grad!(grads.get(&b_ref), vec![0.0, 0.0, 0.0]);
+    // validation
+    assert_eq!(b.try_narrow(2, 2).unwrap_err(), Error::IndexOutOfBounds { index: 4, len: 3, op: "Narrow".to_owned() });
+    assert!(b.try_narrow(3, 0).is_ok());
+    assert_scalar!(&Expression::constant(1.0).narrow(5, 2), 1.0);
+}
+
+#[test]
+#[serial]
+#[should_panic]
+fn narrow_recompute_out_of_bounds() {
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let f = x.narrow(1, 2);
+    before_update();
+    x_ref.assign(vec![1.0, 2.0]);
+    _ = f.value();
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn gather_scatter_add() {
+    use super::Error;
+    // per-device parameters onto per-instance sweep, with repeated indices
+    let (device, device_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let indices = [2, 0, 2, 2, 1];
+    let f = device.gather(&indices);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![3.0, 1.0, 3.0, 3.0, 2.0]);
+    // the repeated indices accumulate, the counts are [1, 1, 3]
+    let grads = f.backward();
+    assert_grad!(grads.get(&device_ref), vec![1.0, 1.0, 3.0]);
+    let grads = f.mul(&Expression::tensor(vec![1.0, 10.0, 100.0, 1000.0, 10000.0], false).0).backward();
+    assert_grad!(grads.get(&device_ref), vec![10.0, 10000.0, 1101.0]);
+    // recompute
+    before_update();
+    device_ref.assign(vec![-1.0, -2.0, -3.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![-3.0, -1.0, -3.0, -3.0, -2.0]);
+
+    // scatter_add
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0, 4.0, 5.0], true);
+    let f = x.scatter_add(&indices, 4);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![2.0, 5.0, 8.0, 0.0]);
+    let grads = f.mul(&Expression::tensor(vec![1.0, 10.0, 100.0, 1000.0], false).0).backward();
+    assert_grad!(grads.get(&x_ref), vec![100.0, 1.0, 100.0, 100.0, 10.0]);
+    assert_eq_vec!(Expression::constant(2.0).scatter_add(&indices, 4).value().to_tensor().unwrap(), vec![2.0, 2.0, 6.0, 0.0]);
+    // gather back the scattered values
+    let grads = x.scatter_add(&indices, 3).gather(&[2]).backward();
+    assert_grad!(grads.get(&x_ref), vec![1.0, 0.0, 1.0, 1.0, 0.0]);
+
+    // validation
+    assert_eq!(device.try_gather(&[0, 3]).unwrap_err(), Error::IndexOutOfBounds { index: 3, len: 3, op: "Gather".to_owned() });
+    assert_eq!(x.try_scatter_add(&indices, 2).unwrap_err(), Error::IndexOutOfBounds { index: 2, len: 2, op: "ScatterAdd".to_owned() });
+    assert_eq!(x.try_scatter_add(&[0, 1], 2).unwrap_err(), Error::LengthMismatch { expected: 2, got: 5, op: "ScatterAdd".to_owned() });
+}
+
+#[test]
+#[serial]
+#[should_panic]
+fn gather_recompute_out_of_bounds() {
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let f = x.gather(&[0, 2]);
+    before_update();
+    x_ref.assign(vec![1.0, 2.0]);
+    _ = f.value();
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn reverse_shift() {
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0, 4.0], true);
+    assert_eq_vec!(x.reverse().value().to_tensor().unwrap(), vec![4.0, 3.0, 2.0, 1.0]);
+    assert_eq_vec!(x.shift(1, 0.0).value().to_tensor().unwrap(), vec![0.0, 1.0, 2.0, 3.0]);
+    assert_eq_vec!(x.shift(-2, -1.0).value().to_tensor().unwrap(), vec![3.0, 4.0, -1.0, -1.0]);
+    assert_eq_vec!(x.shift(5, 9.0).value().to_tensor().unwrap(), vec![9.0; 4]);
+    assert_eq_vec!(x.shift(isize::MIN, 9.0).value().to_tensor().unwrap(), vec![9.0; 4]);
+    assert_eq_vec!(x.shift(isize::MAX, 9.0).value().to_tensor().unwrap(), vec![9.0; 4]);
+    let w = Expression::tensor(vec![1.0, 10.0, 100.0, 1000.0], false).0;
+    let grads = x.reverse().mul(&w).backward();
+    assert_grad!(grads.get(&x_ref), vec![1000.0, 100.0, 10.0, 1.0]);
+    // shifted back, dropped for the filled positions
+    let grads = x.shift(1, 0.0).mul(&w).backward();
+    assert_grad!(grads.get(&x_ref), vec![10.0, 100.0, 1000.0, 0.0]);
+    let grads = x.shift(-2, 0.0).mul(&w).backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0, 0.0, 1.0, 10.0]);
+    // recompute
+    let f = x.shift(1, 0.0).reverse();
+    before_update();
+    x_ref.assign(vec![5.0, 6.0, 7.0, 8.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![7.0, 6.0, 5.0, 0.0]);
+    assert_scalar!(&Expression::constant(2.0).shift(1, 0.0).reverse(), 2.0);
+
+    // differentiable delay-matching loss: sum((shift(a*s, 2) - r)²)
+    let signal = [0.0, 0.5, 1.0, 0.8, 0.3, -0.2];
+    let reference = Expression::tensor(vec![0.0, 0.0, 0.1, 0.6, 1.1, 0.7], false).0;
+    let loss = |a: &Expression| a.mul(&Expression::tensor(signal.to_vec(), false).0).shift(2, 0.0).sub(&reference).sqr();
+    let a_values = vec![1.2, 0.9, 1.1, 1.0, 0.8, 1.3];
+    let (a, a_ref) = Expression::tensor(a_values.clone(), true);
+    let grads = loss(&a).backward();
+    let total = |a_values: Vec<f64>| loss(&Expression::tensor(a_values, false).0).value().to_tensor().unwrap().iter().sum::<f64>();
+    let want: Vec<f64> = (0..a_values.len()).map(|j| finite_difference(|aj| {
+        let mut a_values = a_values.clone();
+        a_values[j] = aj;
+        total(a_values)
+    }, a_values[j])).collect();
+    assert_eq_vec!(grads.get(&a_ref).unwrap(), want, 1e-6);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn conv1d() {
+    use super::{ConvPadding, Error};
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0, 4.0], true);
+    let (k, k_ref) = Expression::tensor(vec![1.0, 10.0, 100.0], true);
+    assert_eq_vec!(x.conv1d(&k, ConvPadding::Valid).value().to_tensor().unwrap(), vec![123.0, 234.0]);
+    assert_eq_vec!(x.conv1d(&k, ConvPadding::Same).value().to_tensor().unwrap(), vec![12.0, 123.0, 234.0, 340.0]);
+    let grads = x.conv1d(&k, ConvPadding::Valid).backward();
+    assert_grad!(grads.get(&x_ref), vec![100.0, 110.0, 11.0, 1.0]);
+    assert_grad!(grads.get(&k_ref), vec![7.0, 5.0, 3.0]);
+    // moving average with a constant kernel
+    assert_eq_vec!(x.conv1d(&Expression::constant(0.5), ConvPadding::Same).value().to_tensor().unwrap(), vec![0.5, 1.0, 1.5, 2.0]);
+    assert_eq!(
+        x.try_conv1d(&Expression::tensor(vec![1.0; 5], false).0, ConvPadding::Valid).unwrap_err(),
+        Error::LengthMismatch { expected: 5, got: 4, op: "Conv1d".to_owned() }
+    );
+    // recompute
+    let f = x.conv1d(&k, ConvPadding::Valid);
+    before_update();
+    k_ref.assign(vec![0.0, 1.0, 0.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![2.0, 3.0]);
+
+    // length-5 kernel on a length-100 signal, loss = sum(conv(s, k)²)
+    let signal: Vec<f64> = (0..100).map(|i| (0.1 * i as f64).sin() + 0.01 * i as f64).collect();
+    let kernel = vec![0.3, -0.2, 0.5, 0.1, -0.4];
+    for padding in [ConvPadding::Valid, ConvPadding::Same] {
+        let total = |signal: &[f64], kernel: &[f64]| {
+            Expression::tensor(signal.to_vec(), false).0
+                .conv1d(&Expression::tensor(kernel.to_vec(), false).0, padding)
+                .sqr().value().to_tensor().unwrap().iter().sum::<f64>()
+        };
+        let (s, s_ref) = Expression::tensor(signal.clone(), true);
+        let (k, k_ref) = Expression::tensor(kernel.clone(), true);
+        let grads = s.conv1d(&k, padding).sqr().backward();
+        let want_s: Vec<f64> = (0..signal.len()).map(|j| finite_difference(|sj| {
+            let mut signal = signal.clone();
+            signal[j] = sj;
+            total(&signal, &kernel)
+        }, signal[j])).collect();
+        let want_k: Vec<f64> = (0..kernel.len()).map(|j| finite_difference(|kj| {
+            let mut kernel = kernel.clone();
+            kernel[j] = kj;
+            total(&signal, &kernel)
+        }, kernel[j])).collect();
+        assert_eq_vec!(grads.get(&s_ref).unwrap(), want_s, 1e-5);
+        assert_eq_vec!(grads.get(&k_ref).unwrap(), want_k, 1e-5);
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn optimizer() {
+    use super::optimizer::{Adam, Optimizer, Sgd};
+    let loss = |x: &Expression| x.sub(&Expression::constant(3.0)).sqr();
+    let total = |f: &Expression| f.value().to_tensor().unwrap().iter().sum::<f64>();
+    let minimize = |opt: &mut dyn Optimizer, x: &Expression, max_iter: usize| {
+        let f = loss(x);
+        for _ in 0..max_iter {
+            if total(&f) < 1e-8 {
+                return;
+            }
+            let grads = f.backward();
+            opt.step(&grads);
+        }
+        panic!("not converged in {max_iter} iterations, loss {}", total(&f));
+    };
+    let (x, x_ref) = Expression::tensor(vec![0.0, -1.0, 5.0, 10.0], true);
+    minimize(&mut Sgd::new(vec![x_ref.clone()], 0.1), &x, 200);
+    assert_eq_vec!(x.value().to_tensor().unwrap(), vec![3.0; 4], 1e-4);
+    before_update();
+    x_ref.assign(vec![0.0, -1.0, 5.0, 10.0]);
+    minimize(&mut Sgd::new(vec![x_ref.clone()], 0.05).with_momentum(0.5), &x, 200);
+    before_update();
+    x_ref.assign(vec![0.0, -1.0, 5.0, 10.0]);
+    minimize(&mut Adam::new(vec![x_ref.clone()], 0.5).with_betas(0.5, 0.9), &x, 500);
+    assert_eq_vec!(x.value().to_tensor().unwrap(), vec![3.0; 4], 1e-4);
+
+    // weight decay pulls towards 0: x* = 3 / (1 + wd/2)
+    let (x, x_ref) = Expression::tensor(vec![0.0], true);
+    let mut sgd = Sgd::new(vec![x_ref], 0.1).with_weight_decay(1.0);
+    let f = loss(&x);
+    for _ in 0..200 {
+        f.value();
+        sgd.step(&f.backward());
+    }
+    assert_eq_vec!(x.value().to_tensor().unwrap(), vec![2.0], 1e-8);
+
+    // y has no gradient in the 2nd step, its moments survive
+    let (x, x_ref) = Expression::tensor(vec![0.0], true);
+    let (y, y_ref) = Expression::tensor(vec![0.0], true);
+    let both = loss(&x).add(&loss(&y));
+    let only_x = loss(&x);
+    let mut adam = Adam::new(vec![x_ref.clone(), y_ref.clone()], 0.1);
+    for f in [&both, &only_x, &both] {
+        // every expression should be recomputed after an update
+        both.value();
+        only_x.value();
+        let grads = f.backward();
+        assert_eq!(grads.get(&y_ref).is_some(), std::ptr::eq(f, &both));
+        adam.step(&grads);
+    }
+    let (y_alone, y_alone_ref) = Expression::tensor(vec![0.0], true);
+    let mut adam_alone = Adam::new(vec![y_alone_ref], 0.1);
+    let f = loss(&y_alone);
+    for _ in 0..2 {
+        f.value();
+        adam_alone.step(&f.backward());
+    }
+    assert_eq_vec!(y.value().to_tensor().unwrap(), y_alone.value().to_tensor().unwrap());
+    // reset
+    adam.zero_state();
+    let y0 = y.value().to_tensor().unwrap()[0];
+    both.value();
+    adam.step(&both.backward());
+    // the 1st bias-corrected Adam step is `lr * sign(grad)`
+    assert_eq_vec!(y.value().to_tensor().unwrap(), vec![y0 + 0.1], 1e-6);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn optimizer_group_scheduler() {
+    use super::optimizer::{Cosine, ExponentialDecay, Optimizer, ParamGroup, ReduceOnPlateau, Scheduler, Sgd};
+    // a metres-scale width and a ratio: f = (1e3 a - 3)² + (1e-3 b - 3)²
+    let converged = |group_lrs: Option<(f64, f64)>| {
+        let (a, a_ref) = Expression::tensor(vec![0.0], true);
+        let (b, b_ref) = Expression::tensor(vec![0.0], true);
+        let f = a.mul(&Expression::constant(1e3)).sub(&Expression::constant(3.0)).sqr()
+            .add(&b.mul(&Expression::constant(1e-3)).sub(&Expression::constant(3.0)).sqr());
+        let mut sgd = match group_lrs {
+            Some((a_lr, b_lr)) => {
+                let mut sgd = Sgd::new(vec![a_ref], a_lr);
+                sgd.add_group(ParamGroup::new(vec![b_ref], b_lr));
+                sgd
+            }
+            None => Sgd::new(vec![a_ref, b_ref], 2.5e-7),
+        };
+        (0..100).any(|_| {
+            let loss = f.value().to_tensor().unwrap()[0];
+            sgd.step(&f.backward());
+            loss < 1e-8
+        })
+    };
+    assert!(!converged(None));
+    assert!(converged(Some((2.5e-7, 2.5e5))));
+
+    // schedule traces of two groups
+    let (_, x_ref) = Expression::tensor(vec![0.0], true);
+    let (_, y_ref) = Expression::tensor(vec![0.0], true);
+    let mut sgd = Sgd::new(vec![x_ref], 1.0);
+    sgd.add_group(ParamGroup::new(vec![y_ref], 1e-3));
+    let trace = |scheduler: &mut dyn Scheduler<Optimizer = Sgd>, losses: &[f64]| {
+        losses.iter().enumerate().map(|(epoch, loss)| {
+            let scale = scheduler.step(epoch, *loss);
+            let groups = scheduler.optimizer().groups();
+            assert_eq_vec!(&[groups[1].lr], &[1e-3 * scale], 1e-15);
+            groups[0].lr
+        }).collect::<Vec<_>>()
+    };
+    assert_eq_vec!(trace(&mut ExponentialDecay::new(sgd.clone(), 0.5), &[0.0; 4]), vec![0.5, 0.25, 0.125, 0.0625]);
+    assert_eq_vec!(
+        trace(&mut Cosine::new(sgd.clone(), 4, 0.0), &[0.0; 5]),
+        vec![0.8535533905932737, 0.5, 0.14644660940672627, 0.0, 0.0],
+        1e-15
+    );
+    let mut plateau = ReduceOnPlateau::new(sgd).with_factor(0.5).with_patience(1).with_threshold(0.1).with_min_scale(0.2);
+    assert_eq_vec!(
+        trace(&mut plateau, &[1.0, 0.95, 0.95, 0.5, 0.5, f64::NAN, 0.5, 0.1, 0.1, 0.1]),
+        vec![1.0, 1.0, 0.5, 0.5, 0.5, 0.25, 0.25, 0.25, 0.25, 0.2]
+    );
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn bounded_update() {
+    use super::{optimizer::{Adam, Optimizer, Sgd}, set_anomaly_detection, take_anomaly_report, Bound, Error};
+    _ = take_anomaly_report();
+    set_anomaly_detection(true);
+    // unconstrained minima x = 3, y = e^-5, out of the box
+    let (x, x_ref) = Expression::tensor(vec![0.5, 0.2], true);
+    let (y, y_ref) = Expression::tensor(vec![0.5], true);
+    x_ref.set_bounds(Bound::Scalar(0.1), Bound::PerElement(vec![1.0, 0.5]));
+    y_ref.set_bounds(Bound::Scalar(0.1), Bound::Unbounded);
+    let fx = x.sub(&Expression::constant(3.0)).sqr().sub(&x.sqrt().mul(&Expression::constant(0.1)));
+    let fy = y.log().add(&Expression::constant(5.0)).sqr();
+    let mut adam = Adam::new(vec![x_ref.clone()], 0.5);
+    let mut sgd = Sgd::new(vec![y_ref.clone()], 1.0);
+    for _ in 0..100 {
+        fx.value();
+        fy.value();
+        adam.step(&fx.backward());
+        sgd.step(&fy.backward());
+    }
+    fx.value();
+    fy.value();
+    assert_eq_vec!(x.value().to_tensor().unwrap(), vec![1.0, 0.5]);
+    assert_eq_vec!(y.value().to_tensor().unwrap(), vec![0.1]);
+    // the gradients at the active bounds are still reported
+    let grads = fx.backward();
+    assert!(grads.get(&x_ref).unwrap().iter().all(|g| *g < 0.0));
+    let grads = fy.backward();
+    assert!(grads.get(&y_ref).unwrap()[0] > 0.0);
+    set_anomaly_detection(false);
+    assert!(take_anomaly_report().is_none());
+
+    // strict mode
+    let (z, z_ref) = Expression::tensor(vec![0.5, 0.5], false);
+    z_ref.set_bounds(Bound::Scalar(0.0), Bound::Scalar(1.0));
+    before_update();
+    z_ref.update(&[1.0, 1.0]);
+    assert_eq_vec!(z.value().to_tensor().unwrap(), vec![1.5, 1.5]);
+    z_ref.set_strict_bounds(true);
+    before_update();
+    assert_eq!(z_ref.try_update(&[-1.0, -2.0]), Err(Error::OutOfBounds { index: 1, op: "update".to_owned() }));
+    z_ref.update_projected(&[-1.0, -2.0]);
+    assert_eq_vec!(z.value().to_tensor().unwrap(), vec![0.5, 0.0]);
+    assert!(std::panic::catch_unwind(|| z_ref.update(&[0.0, -0.5])).is_err());
+    // validated before writing: unchanged and not poisoned after the panic
+    assert_eq_vec!(z.value().to_tensor().unwrap(), vec![0.5, 0.0]);
+    assert!(z_ref.try_update(&[0.1, 0.1]).is_ok());
+    assert!(std::panic::catch_unwind(|| z_ref.assign(vec![2.0, 0.5])).is_err());
+    assert_eq!(z_ref.try_assign(vec![0.5, -1.0]), Err(Error::OutOfBounds { index: 1, op: "assign".to_owned() }));
+    assert_eq_vec!(z.value().to_tensor().unwrap(), vec![0.6, 0.1]);
+    z_ref.assign(vec![0.25, 0.75]);
+    assert_eq_vec!(z.value().to_tensor().unwrap(), vec![0.25, 0.75]);
+    assert_eq!(
+        z_ref.try_set_bounds(Bound::PerElement(vec![0.0]), Bound::Unbounded),
+        Err(Error::LengthMismatch { expected: 2, got: 1, op: "set_bounds".to_owned() })
+    );
+    assert!(matches!(z_ref.try_set_bounds(Bound::Scalar(1.0), Bound::Scalar(0.0)), Err(Error::InvalidBounds(_))));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn lbfgs() {
+    use super::optimizer::{FlatParams, Lbfgs};
+    // flatten / unflatten of tensors with different lengths
+    let (a, a_ref) = Expression::tensor(vec![1.0], true);
+    let (b, b_ref) = Expression::tensor(vec![2.0, 3.0, 4.0], true);
+    let (c, c_ref) = Expression::tensor(vec![5.0, 6.0], true);
+    let flat = FlatParams::new(vec![a_ref.clone(), b_ref.clone(), c_ref.clone()]);
+    assert_eq!(flat.len(), 6);
+    assert_eq_vec!(flat.values(), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    // c has no gradient
+    let f = a.mul(&Expression::constant(10.0)).add(&b.sqr());
+    let grads = f.backward();
+    assert_eq_vec!(flat.grads(&grads), vec![30.0, 4.0, 6.0, 8.0, 0.0, 0.0]);
+    flat.update(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+    assert_eq_vec!(a.value().to_tensor().unwrap(), vec![1.1]);
+    assert_eq_vec!(b.value().to_tensor().unwrap(), vec![2.2, 3.3, 4.4]);
+    assert_eq_vec!(c.value().to_tensor().unwrap(), vec![5.5, 6.6]);
+    assert_eq_vec!(flat.values(), vec![1.1, 2.2, 3.3, 4.4, 5.5, 6.6]);
+
+    // Rosenbrock of x = [p, q0, q1]: Σ (1 - x_i)² + 100 (x_{i+1} - x_i²)²
+    let (p, p_ref) = Expression::tensor(vec![-1.2], true);
+    let (q, q_ref) = Expression::tensor(vec![1.0, -0.5], true);
+    let x = Expression::concat(&[p.clone(), q.clone()]);
+    let (xi, xn) = (x.narrow(0, 2), x.narrow(1, 2));
+    let rosenbrock = Expression::constant(1.0).sub(&xi).sqr()
+        .add(&xn.sub(&xi.sqr()).sqr().mul(&Expression::constant(100.0)));
+    let mut lbfgs = Lbfgs::new(vec![p_ref, q_ref], 1.0);
+    let iterations = (1..=100).find(|_| lbfgs.step(&rosenbrock) < 1e-10)
+        .expect("L-BFGS does not converge in 100 iterations");
+    assert!(rosenbrock.value().overall_sum() < 1e-10, "{iterations}");
+    assert_eq_vec!(p.value().to_tensor().unwrap(), vec![1.0], 1e-4);
+    assert_eq_vec!(q.value().to_tensor().unwrap(), vec![1.0, 1.0], 1e-4);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn sweep() {
+    use super::sweep;
+    let build = |vgs: &Expression, w: &Expression| {
+        let vov = vgs.sub(&Expression::constant(0.4)).exp().add(&Expression::constant(1.0)).log();
+        w.mul(&vov.sqr()).add(&w.log())
+    };
+    let (w, w_ref) = Expression::tensor(vec![2.0], true);
+    let (vgs, vgs_ref) = Expression::tensor(vec![0.0, 0.0], false);
+    let f = build(&vgs, &w);
+    let points: Vec<Vec<f64>> = (0..50).map(|i| vec![0.02 * i as f64, 1.0 - 0.01 * i as f64]).collect();
+    let got = sweep(&f, &vgs_ref, &points);
+    assert_eq!(got.len(), points.len());
+    for (point, got) in points.iter().zip(&got) {
+        let naive = build(&Expression::tensor(point.clone(), false).0, &Expression::tensor(vec![2.0], true).0);
+        assert_eq_vec!(got, &naive.value().to_tensor().unwrap());
+    }
+    // the graph is still differentiable at the last point
+    let grads = f.backward();
+    assert!(grads.get(&w_ref).is_some());
+
+    // the deep clone is independent
+    let g = f.deep_clone_with_fresh_tensors();
+    let before = f.value().to_tensor().unwrap();
+    assert_eq_vec!(g.value().to_tensor().unwrap(), &before);
+    before_update();
+    vgs_ref.assign(vec![1.0, 1.0]);
+    w_ref.assign(vec![3.0]);
+    let after = f.value().to_tensor().unwrap();
+    assert!(after != before);
+    assert_eq_vec!(g.value().to_tensor().unwrap(), &before);
+    #[cfg(feature = "rayon")]
+    {
+        let got_par = super::sweep_par(&f, &vgs_ref, &points);
+        assert_eq_vec!(f.value().to_tensor().unwrap(), &after);
+        assert_eq!(got_par, sweep(&f, &vgs_ref, &points));
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn deep_clone() {
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let (y, y_ref) = Expression::tensor(vec![3.0], true);
+    let (z, _) = Expression::tensor(vec![0.5, 0.5], false);
+    // `shared` is referenced twice
+    let shared = x.mul(&y).exp();
+    let f = shared.add(&shared.sin()).mul(&z);
+    let (g, params) = f.deep_clone();
+    assert_eq!(params.len(), 2);
+    assert_eq!(f.node_count(), g.node_count());
+    assert_eq!(f.node_count(), 8);
+    let before = f.value().to_tensor().unwrap();
+    assert_eq_vec!(g.value().to_tensor().unwrap(), &before);
+
+    // drive the clone's parameters
+    let x_clone = &params[&x_ref.grad_id().unwrap()];
+    let y_clone = &params[&y_ref.grad_id().unwrap()];
+    assert!(x_clone.grad_id() != x_ref.grad_id());
+    before_update();
+    x_clone.assign(vec![0.0, 0.0]);
+    y_clone.update(&[1.0]);
+    let x0 = Expression::tensor(vec![0.0, 0.0], false).0;
+    let want = x0.exp().add(&x0.exp().sin()).mul(&Expression::constant(0.5));
+    assert_eq_vec!(g.value().to_tensor().unwrap(), want.value().to_tensor().unwrap());
+    assert_eq_vec!(f.value().to_tensor().unwrap(), &before);
+    assert_eq_vec!(x.value().to_tensor().unwrap(), vec![1.0, 2.0]);
+    assert_eq_vec!(y.value().to_tensor().unwrap(), vec![3.0]);
+    // the gradients of the clone are keyed by its own ids
+    let grads = g.backward();
+    assert!(grads.get(x_clone).is_some());
+    assert!(grads.get(&x_ref).is_none());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn grad_lookup() {
+    use std::collections::HashSet;
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let (_, y_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let (_, z_ref) = Expression::tensor(vec![1.0], false);
+    let x_clone = x_ref.clone();
+    assert_eq!(x_ref, x_clone);
+    assert!(x_ref != y_ref);
+    assert!(x_ref.requires_grad() && !z_ref.requires_grad());
+    assert_eq!(z_ref.grad_id(), None);
+    #[allow(clippy::mutable_key_type, reason = "a TensorRef hashes its pointer, not the interior-mutable values")]
+    let set: HashSet<_> = [x_ref.clone(), x_clone.clone(), y_ref.clone()].into_iter().collect();
+    assert_eq!(set.len(), 2);
+    let ids: HashSet<_> = [x_ref.grad_id(), x_clone.grad_id(), y_ref.grad_id()].into_iter().collect();
+    assert_eq!(ids.len(), 2);
+    // only the parameters have one, an op node with gradient has none
+    let Expression::Tensor(sqr) = x.sqr() else { unreachable!() };
+    assert!(sqr.with_grad() && sqr.grad_id().is_none() && sqr.leaf_id().is_none());
+    let Expression::Tensor(z) = Expression::tensor(vec![1.0], false).0 else { unreachable!() };
+    assert!(z.grad_id().is_none() && z.leaf_id().is_some());
+
+    let grads = x.sqr().backward();
+    assert!(std::ptr::eq(&grads[&x_ref], &grads[&x_clone]));
+    assert!(std::ptr::eq(&grads[x_ref.tensor()], grads.get(&x_clone).unwrap()));
+    assert!(std::ptr::eq(&grads[x_ref.grad_id().unwrap()], grads.get_by_id(x_clone.grad_id().unwrap()).unwrap()));
+    assert_grad!(Some(&grads[&x_ref]), vec![2.0, 4.0]);
+    assert!(grads.get(&y_ref).is_none());
+    assert!(std::panic::catch_unwind(|| grads[&y_ref].len()).is_err());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn toggle_requires_grad() {
+    use super::optimizer::{Optimizer, Sgd};
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], false);
+    let (w, w_ref) = Expression::tensor(vec![3.0], true);
+    let w_id = w_ref.grad_id().unwrap();
+    // built with x frozen
+    let f = x.sqr().mul(&w).add(&x.sin());
+    let grads = f.backward();
+    assert!(grads.get(&w_ref).is_some());
+    assert_eq!(x_ref.grad_id(), None);
+    // unfreeze
+    x_ref.set_requires_grad(true);
+    assert!(x_ref.requires_grad());
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![6.0 * 1.0 + 1.0_f64.cos(), 6.0 * 2.0 + 2.0_f64.cos()]);
+    assert_grad!(grads.get(&w_ref), vec![5.0]);
+    // freeze w, its gradient is gone and x's is unchanged
+    w_ref.set_requires_grad(false);
+    let grads = f.backward();
+    assert_eq!(w_ref.grad_id(), None);
+    assert_grad!(grads.get(&x_ref), vec![6.0 * 1.0 + 1.0_f64.cos(), 6.0 * 2.0 + 2.0_f64.cos()]);
+    // the optimizer skips the frozen parameters
+    w_ref.set_requires_grad(true);
+    let mut sgd = Sgd::new(vec![x_ref.clone(), w_ref.clone()], 0.1);
+    w_ref.set_requires_grad(false);
+    sgd.step(&grads);
+    assert_eq_vec!(w.value().to_tensor().unwrap(), vec![3.0]);
+    assert!(x.value().to_tensor().unwrap()[0] < 1.0);
+    // all frozen
+    x_ref.set_requires_grad(false);
+    assert!(f.backward().get_by_id(w_id).is_none());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn checkpoint() {
+    // the 10k-node chain is recomputed in stages on the default stack
+    std::thread::spawn(checkpoint_chain).join().unwrap();
+
+    // a released value is recomputed before it is read
+    let (x, _) = Expression::tensor(vec![1.0, 2.0], true);
+    let mid = x.sin();
+    let y = mid.exp();
+    y.forward_no_retain();
+    assert_eq!(y.memory_stats().released_nodes, 1);
+    let Expression::Tensor(mid) = &mid else { unreachable!() };
+    assert_eq_vec!(mid.values().read().unwrap().clone(), vec![1.0_f64.sin(), 2.0_f64.sin()], 1e-12);
+    assert_eq!(y.memory_stats().released_nodes, 0);
+}
+
+fn checkpoint_chain() {
+    const LEN: usize = 64;
+    const STEPS: usize = 5_000;
+    const SEGMENT: usize = 100;
+    let (x, x_ref) = Expression::tensor((0..LEN).map(|i| 0.5 + 0.01 * i as f64).collect(), true);
+    let k = Expression::constant(1.0001);
+    // 2 nodes per step
+    let chain = |checkpoint: bool| {
+        let mut y = x.clone();
+        for step in 1..=STEPS {
+            y = y.mul(&k).sin();
+            if checkpoint && step % SEGMENT == 0 {
+                y = y.checkpoint();
+            }
+        }
+        y
+    };
+    let plain = chain(false);
+    plain.value();
+    let expected = plain.backward();
+    drop(plain);
+
+    // the memory is checked in `tests/memory.rs`
+    let y = chain(true);
+    y.forward_no_retain();
+    let grads = y.backward();
+    assert_eq_vec!(
+        grads.get(&x_ref).unwrap(),
+        expected.get(&x_ref).unwrap(),
+        1e-12
+    );
+
+    // recomputed and kept after an update
+    before_update();
+    x_ref.update(&[0.1; LEN]);
+    let y_value = y.value().to_tensor().unwrap();
+    let plain = chain(false);
+    assert_eq_vec!(y_value, plain.value().to_tensor().unwrap(), 1e-12);
+    y.value();
+    let grads = y.backward();
+    plain.value();
+    let expected = plain.backward();
+    assert_eq_vec!(
+        grads.get(&x_ref).unwrap(),
+        expected.get(&x_ref).unwrap(),
+        1e-12
+    );
+}
+
+#[test]
+#[serial]
+fn eval_diamond() {
+    use crate::expression::recompute::TEST_FORWARD_LOG;
+    let forward_log = || std::mem::take(&mut *TEST_FORWARD_LOG.lock().unwrap());
+    let (x, x_ref) = Expression::tensor(vec![0.5, 1.0], true);
+    let (z, z_ref) = Expression::tensor(vec![2.0, 3.0], true);
+    // `shared` is used by both branches of the diamond
+    let shared = x.exp();
+    let f = shared.sin().add(&shared.cos()).mul(&z);
+    let Expression::Tensor(shared_tensor) = &shared else {
+        unreachable!()
+    };
+    f.eval();
+    _ = forward_log();
+
+    before_update();
+    x_ref.assign(vec![1.5, 2.0]);
+    assert!(f.refresh());
+    let log = forward_log();
+    assert_eq!(
+        log.iter().filter(|id| **id == shared_tensor.key()).count(),
+        1
+    );
+    // exp, sin, cos, add, mul
+    assert_eq!(log.len(), 5);
+    // topological order
+    assert_eq!(log[0], shared_tensor.key());
+    let expected: Vec<f64> = [1.5_f64, 2.0]
+        .iter()
+        .zip([2.0, 3.0])
+        .map(|(x, z)| (x.exp().sin() + x.exp().cos()) * z)
+        .collect();
+    assert_eq_vec!(f.eval().to_tensor().unwrap(), expected, 1e-12);
+    assert!(forward_log().is_empty());
+
+    // only the branch after `z`
+    before_update();
+    z_ref.assign(vec![1.0, 1.0]);
+    assert!(f.refresh());
+    assert_eq!(forward_log().len(), 1);
+    // nothing changed
+    before_update();
+    assert!(!f.refresh());
+    assert!(forward_log().is_empty());
+    assert!(!Expression::constant(1.0).refresh());
+
+    // concurrent callers
+    before_update();
+    x_ref.assign(vec![0.1, 0.2]);
+    let changed: Vec<bool> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..4).map(|_| scope.spawn(|| f.refresh())).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    assert_eq!(changed, vec![true; 4]);
+    let log = forward_log();
+    assert_eq!(
+        log.iter().filter(|id| **id == shared_tensor.key()).count(),
+        1
+    );
+    assert_eq!(log.len(), 5);
+}
+
+#[test]
+#[serial]
+fn concurrent_eval_shared_subtree() {
+    use crate::expression::recompute::TEST_FORWARD_LOG;
+    use std::sync::Barrier;
+    const LEN: usize = 100_000;
+    const THREADS: usize = 8;
+    let (x, x_ref) = Expression::tensor(vec![0.5; LEN], true);
+    let (w, _) = Expression::tensor(vec![2.0; LEN], true);
+    let exp = x.exp();
+    let sin = exp.sin();
+    let scaled = sin.mul(&w);
+    let outputs: Vec<Expression> = (0..THREADS)
+        .map(|i| scaled.mul(&Expression::constant(i as f64)).add(&x))
+        .collect();
+    outputs.iter().for_each(|output| {
+        output.value();
+    });
+    let shared = [exp, sin, scaled];
+    for step in 1..=3 {
+        TEST_FORWARD_LOG.lock().unwrap().clear();
+        before_update();
+        x_ref.assign(vec![0.1 * step as f64; LEN]);
+        let barrier = Barrier::new(THREADS);
+        std::thread::scope(|scope| {
+            for output in &outputs {
+                let barrier = &barrier;
+                scope.spawn(move || {
+                    barrier.wait();
+                    assert!(output.refresh());
+                });
+            }
+        });
+        let log = std::mem::take(&mut *TEST_FORWARD_LOG.lock().unwrap());
+        for node in &shared {
+            let Expression::Tensor(tensor) = node else {
+                unreachable!()
+            };
+            assert_eq!(log.iter().filter(|id| **id == tensor.key()).count(), 1);
+        }
+        // the shared subtree, then mul & add of each output
+        assert_eq!(log.len(), shared.len() + 2 * THREADS);
+        let x = 0.1 * step as f64;
+        for (i, output) in outputs.iter().enumerate() {
+            let expected = x.exp().sin() * 2.0 * i as f64 + x;
+            assert_eq_vec!(
+                output.value().to_tensor().unwrap(),
+                vec![expected; LEN],
+                1e-12
+            );
+        }
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn manual_invalidation() {
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], false);
+    let f = x.mul(&Expression::constant(2.0)).add(&Expression::constant(1.0));
+    let Expression::Tensor(f_tensor) = &f else { unreachable!() };
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [3.0, 5.0, 7.0]);
+    // the buffer shared with an external simulator
+    let ptr = x_ref.tensor().values().write().unwrap().as_mut_ptr();
+
+    before_update();
+    assert!(x_ref.tensor().is_stale());
+    assert!(f_tensor.is_stale());
+    unsafe { *ptr.add(1) = 5.0 };
+    // not tracked yet, the cached value is kept
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [3.0, 5.0, 7.0]);
+    assert!(!f_tensor.is_stale());
+
+    before_update();
+    unsafe { *ptr.add(2) = -1.0 };
+    x_ref.tensor().mark_changed();
+    assert!(!x_ref.tensor().is_stale());
+    assert!(f_tensor.is_stale());
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [3.0, 11.0, -1.0]);
+    assert!(!f_tensor.is_stale());
+
+    before_update();
+    x_ref.tensor().update_with(|values| values.iter_mut().for_each(|x| *x *= 10.0));
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [21.0, 101.0, -19.0]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn resize_parameter() {
+    use super::ConvPadding;
+    let build = |x: &Expression, w: &Expression| {
+        let a = x.mul(w).sin().add(&Expression::constant(1.0));
+        let b = x.gt(w).cond(&a, &x.mul(&Expression::constant(2.0)));
+        let c = Expression::select(&[x.lt(&Expression::constant(0.1))], std::slice::from_ref(w), &b);
+        Expression::concat(&[c, w.clone()]).conv1d(&Expression::tensor(vec![0.5, 1.0, -0.5], false).0, ConvPadding::Same)
+    };
+    let values = |len: usize| -> Vec<f64> { (0..len).map(|i| (0.37 * i as f64).sin()).collect() };
+    let (x, x_ref) = Expression::tensor(values(100), true);
+    let (w, w_ref) = Expression::tensor(vec![0.3], true);
+    let f = build(&x, &w);
+    for len in [100, 10_000, 50, 1] {
+        before_update();
+        x_ref.assign(values(len));
+        let f_value = f.value().to_tensor().unwrap();
+        assert_eq!(f_value.len(), len + 1);
+        let grads = f.backward();
+        assert_eq!(grads.get(&x_ref).unwrap().len(), len);
+        assert_eq!(grads.get(&w_ref).unwrap().len(), 1);
+        // the same as the graph built at this length
+        let (fresh_x, fresh_x_ref) = Expression::tensor(values(len), true);
+        let (fresh_w, fresh_w_ref) = Expression::tensor(vec![0.3], true);
+        let fresh = build(&fresh_x, &fresh_w);
+        assert_eq_vec!(f_value, fresh.value().to_tensor().unwrap(), 1e-12);
+        let fresh_grads = fresh.backward();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), fresh_grads.get(&fresh_x_ref).unwrap(), 1e-12);
+        assert_eq_vec!(grads.get(&w_ref).unwrap(), fresh_grads.get(&fresh_w_ref).unwrap(), 1e-12);
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn length_edge_cases() {
+    use super::{DiscreteBinaryOp, Error, GradMethod};
+    let values = |len: usize| -> Vec<f64> { (0..len).map(|i| 0.5 + i as f64).collect() };
+    let logic = |len: usize| -> Vec<f64> { (0..len).map(|i| (i % 2) as f64).collect() };
+    let mismatch = |op: &str, expected, got| Error::LengthMismatch { expected, got, op: op.to_owned() };
+    // (lhs length, rhs length, output length or the mismatch)
+    type Case = (usize, usize, Result<usize, (usize, usize)>);
+    let matrix: [Case; 10] = [
+        (0, 0, Ok(0)), (0, 1, Ok(0)), (1, 0, Ok(0)), (1, 1, Ok(1)), (1, 3, Ok(3)),
+        (3, 1, Ok(3)), (3, 3, Ok(3)), (3, 2, Err((3, 2))), (0, 3, Err((0, 3))), (2, 0, Err((2, 0))),
+    ];
+    for (lhs_len, rhs_len, expected) in matrix {
+        let (lhs, lhs_ref) = Expression::tensor(values(lhs_len), true);
+        let (rhs, rhs_ref) = Expression::tensor(values(rhs_len), true);
+        let (cond, _) = Expression::tensor(logic(lhs_len), false);
+        cond.mark_logic();
+        let case = format!("lhs {lhs_len}, rhs {rhs_len}");
+        // unary keeps the length
+        assert_eq!(lhs.exp().value().to_tensor().unwrap().len(), lhs_len, "{case}");
+        assert_eq!(lhs.mul(&Expression::constant(2.0)).value().to_tensor().unwrap().len(), lhs_len, "{case}");
+        let outputs = [
+            ("Add".to_owned(), lhs.try_add(&rhs)),
+            ("Lt".to_owned(), lhs.try_cmp(&rhs, DiscreteBinaryOp::Lt, GradMethod::new_sigmoid(1.0))),
+            (format!("Cond (cond {lhs_len}, on_true {rhs_len}, on_false {lhs_len})"), cond.try_cond(&rhs, &lhs)),
+            (format!("Cond (cond {lhs_len}, on_true {rhs_len}, on_false scalar)"), cond.try_cond_ste(&rhs, &Expression::constant(-1.0))),
+        ];
+        for (op, output) in outputs {
+            match expected {
+                Ok(len) => {
+                    let output = output.unwrap();
+                    assert_eq!(output.value().to_tensor().unwrap().len(), len, "{op} {case}");
+                    let grads = output.backward();
+                    for (input, input_len) in [(&lhs_ref, lhs_len), (&rhs_ref, rhs_len)] {
+                        if let Some(grad) = grads.get(input) {
+                            assert_eq!(grad.len(), input_len, "{op} {case}");
+                        }
+                    }
+                }
+                Err((expected, got)) => assert_eq!(output.unwrap_err(), mismatch(&op, expected, got), "{case}"),
+            }
+        }
+    }
+
+    // the lengths are validated at the recompute, instead of truncating / cycling the values
+    let (x, x_ref) = Expression::tensor(values(3), true);
+    let (cond, cond_ref) = Expression::tensor(logic(3), false);
+    cond.mark_logic();
+    let f = cond.cond(&x, &Expression::constant(0.0));
+    let g = cond.cond(&x, &x.exp());
+    for (len, ok) in [(1, true), (2, false), (0, false), (3, true)] {
+        before_update();
+        x_ref.assign(values(len));
+        let out = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            (f.value().to_tensor().unwrap(), g.value().to_tensor().unwrap())
+        }));
+        match out {
+            Ok((f_value, g_value)) => {
+                assert!(ok, "length {len}");
+                assert_eq!((f_value.len(), g_value.len()), (3, 3));
+            }
+            Err(e) => {
+                assert!(!ok, "length {len}");
+                let msg = e.downcast_ref::<String>().unwrap();
+                assert_eq!(msg, &mismatch(&format!("Cond (cond 3, on_true {len}, on_false scalar)"), 3, len).to_string());
+            }
+        }
+    }
+    // an empty cond gives an empty output
+    before_update();
+    cond_ref.assign(vec![]);
+    x_ref.assign(values(1));
+    assert!(f.value().to_tensor().unwrap().is_empty());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn custom_op() {
+    use std::sync::Arc;
+    let cubic = |x: &Expression| x.map_custom(
+        Arc::new(|x| x * x * x - x),
+        Arc::new(|x, _res, grad| grad * (3.0 * x * x - 1.0)),
+        "x3_minus_x",
+    );
+    let (x, x_ref) = Expression::tensor(vec![-1.5, -0.2, 0.0, 0.7, 2.0], true);
+    let f = cubic(&x).mul(&x.sin());
+    let builtin = x.powf(3.0).sub(&x).mul(&x.sin());
+    assert_eq_vec!(f.value().to_tensor().unwrap(), builtin.value().to_tensor().unwrap(), 1e-12);
+    let (grads, expected) = (f.backward(), builtin.backward());
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), expected.get(&x_ref).unwrap(), 1e-12);
+    // recompute
+    before_update();
+    x_ref.assign(vec![0.3, 1.1, -2.2, 0.0, 5.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), builtin.value().to_tensor().unwrap(), 1e-12);
+    let (grads, expected) = (f.backward(), builtin.backward());
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), expected.get(&x_ref).unwrap(), 1e-12);
+    assert_scalar!(cubic(&Expression::constant(2.0)), 6.0);
+    // the name is shown
+    let Expression::Tensor(tensor) = cubic(&x) else { unreachable!() };
+    assert!(format!("{:?}", tensor.op()).contains("x3_minus_x"));
+    assert_eq!(tensor.op().name(), "x3_minus_x");
+
+    // `a * b + a²`, the length-1 `b` is broadcasted
+    let zip = |a: &Expression, b: &Expression| a.zip_custom(
+        b,
+        Arc::new(|a, b| a * b + a * a),
+        Arc::new(|a, b, _res, grad| (grad * (b + 2.0 * a), grad * a)),
+        "mul_add_sq",
+    );
+    let (a, a_ref) = Expression::tensor(vec![1.0, -2.0, 3.0], true);
+    let (b, b_ref) = Expression::tensor(vec![0.5], true);
+    for (lhs, rhs) in [(&a, &b), (&a, &Expression::constant(0.5)), (&b, &a), (&Expression::constant(0.5), &a)] {
+        let f = zip(lhs, rhs);
+        let builtin = lhs.mul(rhs).add(&lhs.mul(lhs));
+        assert_eq_vec!(f.value().to_tensor().unwrap(), builtin.value().to_tensor().unwrap(), 1e-12);
+        let (grads, expected) = (f.backward(), builtin.backward());
+        for param in [&a_ref, &b_ref] {
+            assert_eq!(grads.get(param).is_some(), expected.get(param).is_some());
+            if let Some(grad) = grads.get(param) {
+                assert_eq_vec!(grad, expected.get(param).unwrap(), 1e-12);
+            }
+        }
+    }
+    assert_scalar!(zip(&Expression::constant(2.0), &Expression::constant(3.0)), 10.0);
+    assert!(a.try_zip_custom(&Expression::tensor(vec![1.0, 2.0], false).0, Arc::new(|a, _| a), Arc::new(|_, _, _, g| (g, 0.0)), "first").is_err());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn registered_unary() {
+    use std::sync::Arc;
+    use super::{register_unary, Error, GraphBuilder, UnaryId};
+    let softsign = |x: f64| x / (1.0 + x.abs());
+    let id = register_unary(
+        "softsign",
+        Arc::new(softsign),
+        Arc::new(|x, _res, grad| grad / (1.0 + x.abs()).powi(2)),
+    ).unwrap();
+    assert!(matches!(register_unary("softsign", Arc::new(|x| x), Arc::new(|_, _, g| g)), Err(Error::InvalidRegistration(_))));
+    assert!(matches!(register_unary("", Arc::new(|x| x), Arc::new(|_, _, g| g)), Err(Error::InvalidRegistration(_))));
+    let values = vec![-3.0, -0.4, 0.1, 0.9, 2.5];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let f = x.apply_unary(id).mul(&x);
+    let builtin = x.div(&x.abs().add(&Expression::constant(1.0))).mul(&x);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), builtin.value().to_tensor().unwrap(), 1e-12);
+    let (grads, expected) = (f.backward(), builtin.backward());
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), expected.get(&x_ref).unwrap(), 1e-12);
+    assert_unary_finite_difference!(x, &x_ref, values, softsign, apply_unary(id));
+    assert_scalar!(Expression::constant(1.0).apply_unary(id), 0.5);
+    // recompute
+    before_update();
+    x_ref.assign(vec![0.0, 1.0, -1.0, 4.0, -9.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), builtin.value().to_tensor().unwrap(), 1e-12);
+    // the name is shown, saved and restored
+    let Expression::Tensor(tensor) = x.apply_unary(id) else { unreachable!() };
+    assert!(format!("{:?}", tensor.op()).contains("softsign"));
+    assert_eq!(tensor.op().name(), "softsign");
+    let saved = id.to_string();
+    assert_eq!(saved, "softsign");
+    let restored: UnaryId = saved.parse().unwrap();
+    assert_eq!(restored, id);
+    assert_eq!(UnaryId::from_name("softsign"), Some(id));
+    assert!(matches!("softplus".parse::<UnaryId>(), Err(Error::UnknownOp(name)) if name == "softplus"));
+    let rebuilt = x.apply_unary(restored).mul(&x);
+    assert_eq_vec!(rebuilt.value().to_tensor().unwrap(), f.value().to_tensor().unwrap());
+    // merged like the built-in unary ops
+    let mut builder = GraphBuilder::new();
+    let interned = builder.intern(&x.apply_unary(id).add(&x.apply_unary(restored)));
+    assert_eq!(builder.merged(), 1);
+    assert_eq_vec!(interned.value().to_tensor().unwrap(), x.apply_unary(id).mul(&Expression::constant(2.0)).value().to_tensor().unwrap());
+}
+
+use super::CustomOp;
+include!("../../examples/common/bilinear.rs");
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn custom_op_nary() {
+    use std::sync::Arc;
+    // `z = x + 10y + xy` is bilinear, so the table reproduces it exactly
+    let (xs, ys) = (vec![0.0, 1.0, 2.0], vec![0.0, 1.0]);
+    let table: Arc<dyn CustomOp> = Arc::new(Bilinear::new(xs, ys, |x, y| x + 10.0 * y + x * y));
+    let reference = |x: &Expression, y: &Expression| x.add(&y.mul(&Expression::constant(10.0))).add(&x.mul(y));
+    let (x, x_ref) = Expression::tensor(vec![0.5, 1.5, -0.5, 2.5], true);
+    let (y, y_ref) = Expression::tensor(vec![0.25], true);
+    let f = Expression::apply_custom(table.clone(), &[x.clone(), y.clone()]).sin();
+    let builtin = reference(&x, &y).sin();
+    assert_eq_vec!(f.value().to_tensor().unwrap(), builtin.value().to_tensor().unwrap(), 1e-12);
+    let (grads, expected) = (f.backward(), builtin.backward());
+    for param in [&x_ref, &y_ref] {
+        assert_eq_vec!(grads.get(param).unwrap(), expected.get(param).unwrap(), 1e-12);
+    }
+    // recomputed like any other node, also when the length changes
+    before_update();
+    y_ref.assign(vec![0.75, 0.5, -1.0, 3.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), builtin.value().to_tensor().unwrap(), 1e-12);
+    let (grads, expected) = (f.backward(), builtin.backward());
+    for param in [&x_ref, &y_ref] {
+        assert_eq_vec!(grads.get(param).unwrap(), expected.get(param).unwrap(), 1e-12);
+    }
+    // with a gradient when any input has one
+    let (x_const, _) = Expression::tensor(vec![0.5, 1.5], false);
+    let f = Expression::apply_custom(table.clone(), &[x_const.clone(), y.clone()]);
+    let grads = f.backward();
+    assert!(grads.get(&y_ref).is_some() && grads.get(&x_ref).is_none());
+    let f = Expression::apply_custom(table.clone(), &[x_const, Expression::constant(0.25)]);
+    let Expression::Tensor(tensor) = &f else { unreachable!() };
+    assert!(!tensor.with_grad());
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [0.5 + 2.5 + 0.125, 1.5 + 2.5 + 0.375], 1e-12);
+    assert_eq!(tensor.op().name(), "bilinear");
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn ddt_idt() {
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 4.0, 7.0], true);
+    assert_eq_vec!(x.ddt(0.5).value().to_tensor().unwrap(), vec![2.0, 3.0, 5.0, 6.0]);
+    assert_eq_vec!(x.idt(0.5, 1.0).value().to_tensor().unwrap(), vec![1.0, 1.75, 3.25, 6.0]);
+    assert_eq_vec!(Expression::tensor(vec![3.0], false).0.ddt(0.5).value().to_tensor().unwrap(), vec![0.0]);
+    assert_scalar!(&Expression::constant(2.0).ddt(0.5), 0.0);
+    assert_scalar!(&Expression::constant(2.0).idt(0.5, 1.0), 1.0);
+    // the transposed stencils
+    let w = Expression::tensor(vec![1.0, 10.0, 100.0, 1000.0], false).0;
+    let grads = x.ddt(0.5).mul(&w).backward();
+    assert_grad!(grads.get(&x_ref), vec![-2.0 - 10.0, 2.0 - 100.0, 10.0 - 2000.0, 100.0 + 2000.0]);
+    let grads = x.idt(0.5, 1.0).mul(&w).backward();
+    assert_grad!(grads.get(&x_ref), vec![0.25 * 1110.0, 0.25 * (1110.0 + 1100.0), 0.25 * (1100.0 + 1000.0), 0.25 * 1000.0]);
+
+    // ddt(idt(x)) ≈ x away from the boundaries
+    let dt = 1e-3;
+    let t: Vec<f64> = (0..200).map(|i| i as f64 * dt).collect();
+    let (s, s_ref) = Expression::tensor(t.iter().map(|t| (10.0 * t).sin()).collect(), true);
+    let round_trip = s.idt(dt, 0.3).ddt(dt);
+    let (got, want) = (round_trip.value().to_tensor().unwrap().to_vec(), s.value().to_tensor().unwrap().to_vec());
+    assert_eq_vec!(&got[1..199], &want[1..199], 1e-4);
+    // recompute
+    before_update();
+    s_ref.assign(t.iter().map(|t| (20.0 * t).cos()).collect());
+    let (got, want) = (round_trip.value().to_tensor().unwrap().to_vec(), s.value().to_tensor().unwrap().to_vec());
+    assert_eq_vec!(&got[1..199], &want[1..199], 1e-3);
+
+    // gradient of `sum(w * op(x)²)` against the finite differences
+    let values = vec![0.3, -1.2, 0.8, 2.0, 1.1];
+    let w = Expression::tensor(vec![1.0, -2.0, 0.5, 3.0, 1.5], false).0;
+    let ops: [fn(&Expression) -> Expression; 2] = [|x| x.ddt(0.1), |x| x.idt(0.1, -0.4)];
+    for op in ops {
+        let (x, x_ref) = Expression::tensor(values.clone(), true);
+        let f = op(&x).sqr().mul(&w);
+        let grads = f.backward();
+        let sum_at = |j: usize, xj: f64| {
+            let mut values = values.clone();
+            values[j] = xj;
+            before_update();
+            x_ref.assign(values);
+            f.value().to_tensor().unwrap().iter().sum::<f64>()
+        };
+        let want: Vec<f64> = (0..values.len()).map(|j| finite_difference(|xj| sum_at(j, xj), values[j])).collect();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), want, 1e-6);
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn fft_spectrum() {
+    use std::f64::consts::TAU;
+    // two tones at the bins 5 and 12, the amplitude of the first is the parameter
+    let n = 64;
+    let tone = |bin: f64, phase: f64| -> Vec<f64> { (0..n).map(|i| (TAU * bin * i as f64 / n as f64 + phase).cos()).collect() };
+    let (a, a_ref) = Expression::tensor(vec![1.5], true);
+    let signal = a.mul(&Expression::tensor(tone(5.0, 0.3), false).0).add(&Expression::tensor(tone(12.0, 0.0), false).0.mul(&Expression::constant(0.5)));
+    let mag = signal.fft_mag();
+    let values = mag.value().to_tensor().unwrap().to_vec();
+    assert_eq!(values.len(), n / 2 + 1);
+    let mut peaks: Vec<usize> = (0..values.len()).collect();
+    peaks.sort_by(|i, j| values[*j].total_cmp(&values[*i]));
+    assert_eq!(&peaks[..2], &[5, 12]);
+    assert!((values[5] - 1.5 * n as f64 / 2.0).abs() < 1e-9);
+    assert!((values[12] - 0.5 * n as f64 / 2.0).abs() < 1e-9);
+    let power = signal.fft_power().value().to_tensor().unwrap().to_vec();
+    assert_eq_vec!(power, values.iter().map(|m| m * m).collect::<Vec<_>>(), 1e-6);
+    // d|X_5|/da = N/2 against the finite difference
+    for (f, want) in [(signal.fft_mag().narrow(5, 1), 32.0), (signal.fft_power().narrow(5, 1), 2.0 * 1.5 * 32.0 * 32.0)] {
+        let grads = f.backward();
+        let fd = finite_difference(|x| {
+            before_update();
+            a_ref.assign(vec![x]);
+            f.value().to_tensor().unwrap()[0]
+        }, 1.5);
+        before_update();
+        a_ref.assign(vec![1.5]);
+        _ = f.value();
+        assert!((grads.get(&a_ref).unwrap()[0] - want).abs() < 1e-6);
+        assert!((fd - want).abs() < 1e-4 * want);
+    }
+    // the gradient w.r.t. every sample, odd length
+    let values = vec![0.3, -1.2, 0.8, 2.0, 1.1, -0.5, 0.7];
+    let w = Expression::tensor(vec![1.0, -2.0, 0.5, 3.0], false).0;
+    let ops: [fn(&Expression) -> Expression; 2] = [Expression::fft_mag, Expression::fft_power];
+    for op in ops {
+        let (x, x_ref) = Expression::tensor(values.clone(), true);
+        let f = op(&x).mul(&w);
+        let grads = f.backward();
+        let sum_at = |j: usize, xj: f64| {
+            let mut values = values.clone();
+            values[j] = xj;
+            before_update();
+            x_ref.assign(values);
+            f.value().to_tensor().unwrap().iter().sum::<f64>()
+        };
+        let want: Vec<f64> = (0..values.len()).map(|j| finite_difference(|xj| sum_at(j, xj), values[j])).collect();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), want, 1e-6);
+    }
+    // zero-magnitude bins have no gradient instead of NaN
+    let (x, x_ref) = Expression::tensor(vec![0.0; 4], true);
+    let grads = x.fft_mag().backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0; 4]);
+    assert_scalar!(&Expression::constant(-2.0).fft_mag(), 2.0);
+    assert_eq!(Expression::tensor(vec![], false).0.fft_mag().value().to_tensor().unwrap().len(), 0);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cross_time() {
+    use super::{Edge, Error, GradMethod};
+    // the ramp `slope * t - 1` crosses 0.5 at `1.5 / slope`, `d/dslope = -1.5 / slope²`
+    let times: Vec<f64> = (0..=100).map(|i| i as f64 * 0.1).collect();
+    let (slope, slope_ref) = Expression::tensor(vec![0.8], true);
+    let ramp = slope.mul(&Expression::tensor(times.clone(), false).0).sub(&Expression::constant(1.0));
+    let t = ramp.cross_time(0.5, &times, Edge::Rising, GradMethod::Discrete);
+    assert_eq_vec!(t.value().to_tensor().unwrap(), [1.5 / 0.8], 1e-12);
+    let grads = t.backward();
+    assert_eq_vec!(grads.get(&slope_ref).unwrap(), [-1.5 / 0.8 / 0.8], 1e-9);
+    // recompute, in another segment
+    before_update();
+    slope_ref.assign(vec![2.5]);
+    assert_eq_vec!(t.value().to_tensor().unwrap(), [1.5 / 2.5], 1e-12);
+    let grads = t.backward();
+    assert_eq_vec!(grads.get(&slope_ref).unwrap(), [-1.5 / 2.5 / 2.5], 1e-9);
+    // the crossing is lost
+    before_update();
+    slope_ref.assign(vec![-1.0]);
+    assert!(t.value().to_tensor().unwrap()[0].is_nan());
+    let grads = t.backward();
+    assert_eq_vec!(grads.get(&slope_ref).unwrap(), [0.0]);
+
+    // the first crossing on the edge
+    let (x, x_ref) = Expression::tensor(vec![0.0, 1.0, 0.0, 1.0], true);
+    let times = [0.0, 1.0, 2.0, 3.0];
+    assert_eq_vec!(x.cross_time(0.25, &times, Edge::Rising, GradMethod::Discrete).value().to_tensor().unwrap(), [0.25]);
+    assert_eq_vec!(x.cross_time(0.25, &times, Edge::Falling, GradMethod::Discrete).value().to_tensor().unwrap(), [1.75]);
+    assert_eq_vec!(x.cross_time(0.25, &times, Edge::Either, GradMethod::Discrete).value().to_tensor().unwrap(), [0.25]);
+    let grads = x.cross_time(0.25, &times, Edge::Falling, GradMethod::Discrete).backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0, 0.25, 0.75, 0.0]);
+    assert_eq!(
+        x.try_cross_time(2.0, &times, Edge::Either, GradMethod::Discrete).unwrap_err(),
+        Error::NoCrossing { op: "CrossTime(2, Either)".to_owned() }
+    );
+    assert!(matches!(x.try_cross_time(0.5, &times[..3], Edge::Rising, GradMethod::Discrete), Err(Error::LengthMismatch { expected: 3, got: 4, .. })));
+    assert!(matches!(Expression::constant(1.0).try_cross_time(0.5, &[0.0], Edge::Rising, GradMethod::Discrete), Err(Error::NoCrossing { .. })));
+    // an update of another length is `NaN` rather than a panic
+    let t = x.cross_time(0.25, &times, Edge::Rising, GradMethod::Discrete);
+    before_update();
+    x_ref.assign(vec![0.0, 1.0, 0.0]);
+    assert!(t.value().to_tensor().unwrap()[0].is_nan());
+    let grads = t.backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0; 3]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cross_time_soft() {
+    use super::{Edge, GradMethod};
+    // the soft argmin approaches the hard crossing of the ramp as the sharpness grows
+    let times: Vec<f64> = (0..=100).map(|i| i as f64 * 0.1).collect();
+    let (slope, slope_ref) = Expression::tensor(vec![0.8], true);
+    let ramp = slope.mul(&Expression::tensor(times.clone(), false).0).sub(&Expression::constant(1.0));
+    let hard = ramp.cross_time(0.5, &times, Edge::Rising, GradMethod::Discrete).value().to_tensor().unwrap()[0];
+    let mut last = (f64::INFINITY, f64::INFINITY);
+    for k in [2.0, 20.0, 200.0, 2000.0] {
+        let t = ramp.cross_time(0.5, &times, Edge::Rising, GradMethod::new_sigmoid(k));
+        let value = t.value().to_tensor().unwrap()[0];
+        let grad = t.backward().get(&slope_ref).unwrap()[0];
+        let error = ((value - hard).abs(), (grad + 1.5 / 0.8 / 0.8).abs());
+        assert!(error.0 < last.0 && error.1 < last.1, "k = {k}: {error:?} after {last:?}");
+        last = error;
+    }
+    assert!(last.0 < 1e-9 && last.1 < 1e-6, "{last:?}");
+    // the gradient of a soft crossing against a finite difference of the samples
+    let samples = vec![0.1, 0.35, 0.2, 0.6, 0.4, 0.9, 0.7];
+    let times = [0.0, 0.5, 1.0, 2.0, 2.5, 3.5, 4.0];
+    for edge in [Edge::Rising, Edge::Falling, Edge::Either] {
+        let (x, x_ref) = Expression::tensor(samples.clone(), true);
+        let t = x.cross_time(0.5, &times, edge, GradMethod::new_sigmoid(8.0));
+        let grads = t.backward();
+        let grad = grads.get(&x_ref).unwrap();
+        let h = 1e-6;
+        for i in 0..samples.len() {
+            let at = |dx: f64| {
+                let mut samples = samples.clone();
+                samples[i] += dx;
+                before_update();
+                x_ref.assign(samples);
+                t.value().to_tensor().unwrap()[0]
+            };
+            let fd = (at(h) - at(-h)) / (2.0 * h);
+            assert!((grad[i] - fd).abs() < 1e-6, "{edge:?} at {i}: {} vs {fd}", grad[i]);
+        }
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn measurements() {
+    use super::MaxMethod;
+    // the step response `1 - e^{-t} cos(3t)` peaks at `t = (π - atan(1/3)) / 3`
+    let (dt, n) = (1e-3, 8000);
+    let (k, k_ref) = Expression::tensor(vec![1.0], true);
+    let t = Expression::tensor((0..n).map(|i| i as f64 * dt).collect(), false).0;
+    let step = Expression::constant(1.0).sub(&t.neg().exp().mul(&t.mul(&k.mul(&Expression::constant(3.0))).cos()));
+    let t_peak = (std::f64::consts::PI - (1.0f64 / 3.0).atan()) / 3.0;
+    let peak = -(-t_peak).exp() * (3.0 * t_peak).cos();
+    assert_eq_vec!(step.overshoot(1.0, MaxMethod::Hard).value().to_tensor().unwrap(), [peak], 1e-6);
+    let smooth = step.overshoot(1.0, MaxMethod::Smooth(1e-4)).value().to_tensor().unwrap()[0];
+    assert!(smooth >= peak && smooth < peak + 1e-4 * (n as f64).ln());
+    // the tail `t >= 6` is within `e^{-6}`
+    let settling = step.settling_error(1.0, 0.25, MaxMethod::Hard).value().to_tensor().unwrap()[0];
+    assert!(settling <= (-6.0f64).exp() && settling > 0.5 * (-6.5f64).exp());
+    // the rms of a sine over whole periods is `1/√2`
+    let sine = t.mul(&Expression::constant(std::f64::consts::TAU)).sin();
+    assert_eq_vec!(sine.rms().value().to_tensor().unwrap(), [std::f64::consts::FRAC_1_SQRT_2], 1e-9);
+    assert_eq_vec!(sine.sum().value().to_tensor().unwrap(), [0.0], 1e-9);
+    assert_eq_vec!(sine.mean().value().to_tensor().unwrap(), [0.0], 1e-12);
+    // the gradients w.r.t. the frequency factor
+    for method in [MaxMethod::Hard, MaxMethod::Smooth(1e-2)] {
+        for f in [step.overshoot(1.0, method), step.settling_error(1.0, 0.25, method), step.rms()] {
+            let grads = f.backward();
+            let fd = finite_difference(|x| {
+                before_update();
+                k_ref.assign(vec![x]);
+                f.value().to_tensor().unwrap()[0]
+            }, 1.0);
+            before_update();
+            k_ref.assign(vec![1.0]);
+            _ = f.value();
+            assert!((grads.get(&k_ref).unwrap()[0] - fd).abs() < 1e-4 * fd.abs().max(1.0), "{} {fd}", grads.get(&k_ref).unwrap()[0]);
+        }
+    }
+
+    // the maximum attained at two samples, the gradient is split evenly,
+    // as the central finite difference
+    let values = vec![0.5, 2.0, -1.0, 2.0, 1.5];
+    for method in [MaxMethod::Hard, MaxMethod::Smooth(0.3)] {
+        let (x, x_ref) = Expression::tensor(values.clone(), true);
+        let f = x.overshoot(0.5, method);
+        let grads = f.backward();
+        let want: Vec<f64> = (0..values.len()).map(|j| finite_difference(|xj| {
+            let mut values = values.clone();
+            values[j] = xj;
+            before_update();
+            x_ref.assign(values);
+            f.value().to_tensor().unwrap()[0]
+        }, values[j])).collect();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), want, 1e-6);
+    }
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let grads = x.overshoot(0.5, MaxMethod::Hard).backward();
+    assert_grad!(grads.get(&x_ref), vec![0.0, 0.5, 0.0, 0.5, 0.0]);
+    let grads = x.mean().backward();
+    assert_grad!(grads.get(&x_ref), vec![0.2; 5]);
+    assert_eq_vec!(x.settling_error(1.0, 0.0, MaxMethod::Hard).value().to_tensor().unwrap(), [0.5]);
+    assert_eq_vec!(x.settling_error(1.0, 0.4, MaxMethod::Hard).value().to_tensor().unwrap(), [1.0]);
+    assert_scalar!(&Expression::constant(3.0).overshoot(1.0, MaxMethod::Smooth(0.1)), 2.0);
+    assert_scalar!(&Expression::constant(-3.0).rms(), 3.0);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn loss_builder() {
+    use super::LossBuilder;
+    let (x, x_ref) = Expression::tensor(vec![1.0, -2.0, 0.5], true);
+    let (y, y_ref) = Expression::tensor(vec![3.0], true);
+    let delay = x.mul(&y).sqr();
+    let power = x.sqr().add(&y);
+    let area = y.exp();
+    let loss = LossBuilder::new()
+        .term("delay", 2.0, delay.clone())
+        .term("power", 0.5, power.clone())
+        .term("area", 0.1, area.clone());
+    let f = loss.build();
+    let expected = |w: [f64; 3]| {
+        let (d, p, a) = (delay.value().to_tensor().unwrap().iter().sum::<f64>(), power.value().to_tensor().unwrap().iter().sum::<f64>(), area.value().to_tensor().unwrap()[0]);
+        (vec![("delay".to_owned(), d), ("power".to_owned(), p), ("area".to_owned(), a)], w[0] * d + w[1] * p + w[2] * a)
+    };
+    let (terms, sum) = expected([2.0, 0.5, 0.1]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [sum], 1e-12);
+    assert_eq!(loss.terms(), terms);
+    // the weighted sum of the per-term gradients
+    let check_grads = |w: [f64; 3]| {
+        let grads = f.backward();
+        let per_term = [delay.backward(), power.backward(), area.backward()];
+        for param in [&x_ref, &y_ref] {
+            let got = grads.get(param).unwrap();
+            let want: Vec<f64> = (0..got.len()).map(|i| izip!(&w, &per_term).map(|(w, grads)| w * grads.get(param).map_or(0.0, |g| g[i])).sum()).collect();
+            assert_eq_vec!(got, want, 1e-12);
+        }
+    };
+    check_grads([2.0, 0.5, 0.1]);
+    // the weight takes effect on the next evaluation, without rebuilding
+    before_update();
+    loss.set_weight("power", 3.0);
+    assert_eq!(loss.weight("power"), Some(3.0));
+    let (_, sum) = expected([2.0, 3.0, 0.1]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [sum], 1e-12);
+    check_grads([2.0, 3.0, 0.1]);
+    // the terms follow the parameters
+    before_update();
+    x_ref.assign(vec![0.0, 1.0, 2.0]);
+    let (terms, sum) = expected([2.0, 3.0, 0.1]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [sum], 1e-12);
+    assert_eq!(loss.terms(), terms);
+    assert_eq!(loss.weight("missing"), None);
+    assert_scalar!(&LossBuilder::new().build(), 0.0);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn graph_diff() {
+    use super::NodeDiff;
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let (y, y_ref) = Expression::tensor(vec![3.0], true);
+    let (w, w_ref) = Expression::tensor(vec![3.0], true);
+    let z = Expression::tensor(vec![0.5, 0.5], false).0;
+    let build = |op: fn(&Expression, &Expression) -> Expression, y: &Expression| op(&x, y).add(&z).sin().mul(&Expression::constant(2.0));
+    let f = build(Expression::mul, &y);
+    // stable across the iterations, and for a rebuilt identical structure
+    let fingerprint = f.fingerprint();
+    before_update();
+    x_ref.assign(vec![4.0, 5.0]);
+    _ = f.value();
+    assert_eq!(f.fingerprint(), fingerprint);
+    let rebuilt = build(Expression::mul, &y);
+    assert_eq!(rebuilt.fingerprint(), fingerprint);
+    assert!(f.diff(&rebuilt).is_empty());
+    assert_eq!(f.diff(&rebuilt).to_string(), "identical graphs");
+    // one `Mul` became `Add`, and `y` was swapped for `w`
+    let g = build(Expression::add, &w);
+    assert_ne!(g.fingerprint(), fingerprint);
+    assert_ne!(build(Expression::mul, &w).fingerprint(), fingerprint);
+    assert_ne!(build(Expression::add, &y).fingerprint(), fingerprint);
+    let diff = f.diff(&g);
+    assert_eq!(diff.nodes, vec![NodeDiff::Changed { path: "$.0.0.0".to_owned(), from: "Mul".to_owned(), to: "Add".to_owned() }]);
+    assert_eq!(diff.params_removed, vec![("$.0.0.0.1".to_owned(), y_ref.grad_id().unwrap())]);
+    assert_eq!(diff.params_added, vec![("$.0.0.0.1".to_owned(), w_ref.grad_id().unwrap())]);
+    assert_eq!(
+        diff.to_string(),
+        format!("1 node(s) differ, 1 parameter(s) removed, 1 parameter(s) added\n  ~ $.0.0.0: Mul -> Add\n  - $.0.0.0.1: parameter {:?}\n  + $.0.0.0.1: parameter {:?}", y_ref.grad_id().unwrap(), w_ref.grad_id().unwrap())
+    );
+    // added / removed inputs and constants
+    let diff = Expression::concat(&[x.clone(), y.clone()]).diff(&Expression::concat(&[x.clone(), Expression::constant(1.0), z.clone()]));
+    assert_eq!(diff.nodes, vec![
+        NodeDiff::Added { path: "$.2".to_owned(), op: "Tensor".to_owned() },
+        NodeDiff::Changed { path: "$.1".to_owned(), from: "Tensor".to_owned(), to: "Const(1)".to_owned() },
+    ]);
+    // the summary is capped
+    let parts: Vec<Expression> = (0..40).map(|i| Expression::constant(i as f64)).collect();
+    let others: Vec<Expression> = (0..40).map(|i| Expression::constant(-i as f64 - 1.0)).collect();
+    let diff = Expression::concat(&parts).diff(&Expression::concat(&others));
+    assert_eq!(diff.nodes.len(), 40);
+    let summary = diff.to_string();
+    assert_eq!(summary.lines().count(), 1 + 16 + 1);
+    assert!(summary.ends_with("... and 24 more"));
+    assert_eq!(Expression::constant(1.0).fingerprint(), Expression::constant(1.0).fingerprint());
+    assert_ne!(Expression::constant(1.0).fingerprint(), Expression::constant(2.0).fingerprint());
+    // only the comparison method, or only the table, differs
+    let c = Expression::constant(1.5);
+    let (sigmoid, linear) = (x.le_sigmoid(&c, 2.0), x.le_linear(&c, 0.5));
+    assert_eq!(x.le_sigmoid(&c, 2.0).fingerprint(), sigmoid.fingerprint());
+    assert!(sigmoid.diff(&x.le_sigmoid(&c, 2.0)).is_empty());
+    assert_ne!(sigmoid.fingerprint(), linear.fingerprint());
+    assert_ne!(sigmoid.fingerprint(), x.le_sigmoid(&c, 3.0).fingerprint());
+    let diff = sigmoid.diff(&linear);
+    assert!(matches!(&diff.nodes[..], [NodeDiff::Changed { path, from, to }] if path == "$" && from.starts_with("Le[") && to.starts_with("Le[") && from != to));
+    let pwl = |ys: &[f64]| x.pwl(&[0.0, 1.0, 2.0], ys, super::PwlExtrapolation::Clamp);
+    assert_eq!(pwl(&[0.0, 1.0, 4.0]).fingerprint(), pwl(&[0.0, 1.0, 4.0]).fingerprint());
+    assert_ne!(pwl(&[0.0, 1.0, 4.0]).fingerprint(), pwl(&[0.0, 1.0, 5.0]).fingerprint());
+    assert_eq!(pwl(&[0.0, 1.0, 4.0]).diff(&pwl(&[0.0, 1.0, 5.0])).nodes.len(), 1);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn assign_from() {
+    use super::{GraphBuilder, Op};
+    let (x, x_ref) = Expression::tensor(vec![0.5, -1.0, 2.0], true);
+    let probe = Expression::assign_from(&x.sin());
+    let f = probe.mul(&x);
+    f.assert_close(&x.sin().mul(&x), 0.0, 0.0);
+    assert_eq!(f.find(|op| matches!(op, Op::AssignFrom(_))).len(), 1);
+    // the gradient passes through as identity
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.5, -1.0, 2.0].map(|x: f64| x.cos() * x + x.sin()), 1e-15);
+    // copied again after the source parameter is updated
+    before_update();
+    x_ref.assign(vec![1.0, 3.0, -0.25]);
+    assert_eq_vec!(probe.value().to_tensor().unwrap(), [1.0f64.sin(), 3.0f64.sin(), (-0.25f64).sin()]);
+    f.backward().assert_close(&x.sin().mul(&x).backward(), 0.0, 1e-15);
+    // a distinct node, which is not merged with its source
+    let mut builder = GraphBuilder::new();
+    let g = builder.intern(&Expression::assign_from(&x).add(&x));
+    assert_eq!(g.find(|op| matches!(op, Op::AssignFrom(_))).len(), 1);
+    assert!(matches!(Expression::assign_from(&Expression::constant(2.0)), Expression::Const(2.0)));
+    #[allow(deprecated)]
+    let renamed = Op::Assgin;
+    assert!(matches!(renamed, Op::Assign));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn subgraph_substitute() {
+    use super::{Error, Op, PwlExtrapolation, UnaryOp};
+    let (x, x_ref) = Expression::tensor(vec![0.3, 1.0, 2.5], true);
+    let (y, y_ref) = Expression::tensor(vec![2.0, -0.5, 0.5], true);
+    let branch = y.sqr().add(&y);
+    let f = x.sin().mul(&branch).add(&branch.exp());
+    let Expression::Tensor(branch_tensor) = &branch else { unreachable!() };
+    // find
+    let sins = f.find(|op| matches!(op, Op::Unary(_, UnaryOp::Sin)));
+    assert_eq!(sins.len(), 1);
+    assert_eq!(f.find(|op| matches!(op, Op::Assign)).len(), 2);
+    assert_eq!(f.find(|op| matches!(op, Op::Unary(_, UnaryOp::Cos))).len(), 0);
+    // the PWL approximation of `sin` on `[0, π]`
+    let xs: Vec<f64> = (0..=8).map(|i| i as f64 * std::f64::consts::PI / 8.0).collect();
+    let ys: Vec<f64> = xs.iter().map(|x| x.sin()).collect();
+    let surrogate = x.pwl(&xs, &ys, PwlExtrapolation::Extend);
+    let g = f.substitute(&sins[0], &surrogate);
+    let branch_values = branch.value().to_tensor().unwrap().to_vec();
+    let want: Vec<f64> = izip!(surrogate.value().to_tensor().unwrap().iter(), &branch_values).map(|(s, b)| s * b + b.exp()).collect();
+    let got = g.value().to_tensor().unwrap().to_vec();
+    assert_eq_vec!(&got, want, 1e-12);
+    let original = f.value().to_tensor().unwrap().to_vec();
+    assert!(izip!(&got, &original).all(|(g, o)| g != o && (g - o).abs() < 0.05 * o.abs().max(1.0)));
+    assert!(g.find(|op| matches!(op, Op::Unary(_, UnaryOp::Sin))).is_empty());
+    // the untouched branch is literally the same tensors, and the original is untouched
+    for node in [&f, &g] {
+        let branches = node.find(|op| matches!(op, Op::Binary(..)));
+        assert!(branches.iter().any(|b| matches!(b, Expression::Tensor(t) if t == branch_tensor)));
+    }
+    assert_eq!(f.find(|op| matches!(op, Op::Unary(_, UnaryOp::Sin))).len(), 1);
+    assert_eq!(f.fingerprint(), f.fingerprint());
+    assert_eq!(f.diff(&g).nodes.len(), 1);
+    // the gradients flow through the replacement and follow the updates
+    let grads = g.backward();
+    let slope = (ys[3] - ys[2]) / (xs[3] - xs[2]);
+    assert!((grads.get(&x_ref).unwrap()[1] - slope * branch_values[1]).abs() < 1e-12);
+    assert!(grads.get(&y_ref).is_some());
+    before_update();
+    y_ref.assign(vec![1.0, 1.0, 1.0]);
+    let want: Vec<f64> = surrogate.value().to_tensor().unwrap().iter().map(|s| s * 2.0 + 2.0f64.exp()).collect();
+    assert_eq_vec!(g.value().to_tensor().unwrap(), want, 1e-12);
+    // the lengths must match
+    assert!(matches!(f.try_substitute(&sins[0], &Expression::tensor(vec![1.0], false).0), Err(Error::LengthMismatch { expected: 3, got: 1, .. })));
+    let g = f.substitute(&x, &Expression::tensor(vec![0.0; 3], false).0);
+    assert_eq_vec!(g.value().to_tensor().unwrap(), branch.exp().value().to_tensor().unwrap());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cached_expression() {
+    use super::CachedExpression;
+    use crate::expression::recompute::TEST_RECOMPUTE_COUNT;
+    use std::sync::atomic::Ordering::Relaxed;
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let (y, y_ref) = Expression::tensor(vec![3.0], false);
+    let f = x.mul(&y).add(&x.sqr());
+    let cached = CachedExpression::new(f.clone());
+    let evaluations = || TEST_RECOMPUTE_COUNT.load(Relaxed);
+    let before = evaluations();
+    assert_eq_vec!(cached.value(), [4.0, 10.0]);
+    let after_first = evaluations();
+    assert!(after_first > before);
+    // unchanged parameters, no evaluation
+    assert_eq_vec!(cached.value(), [4.0, 10.0]);
+    assert_eq_vec!(cached.value(), [4.0, 10.0]);
+    assert_eq!(evaluations(), after_first);
+    // any dependency updated, evaluated again
+    before_update();
+    y_ref.assign(vec![-1.0]);
+    assert_eq_vec!(cached.value(), [0.0, 2.0]);
+    let after_update = evaluations();
+    assert!(after_update > after_first);
+    assert_eq_vec!(cached.value(), [0.0, 2.0]);
+    assert_eq!(evaluations(), after_update);
+    let version = x_ref.tensor().version();
+    before_update();
+    x_ref.update(&[1.0, 1.0]);
+    assert_eq!(x_ref.tensor().version(), version + 1);
+    assert_eq_vec!(cached.value(), [2.0, 6.0]);
+    assert!(evaluations() > after_update);
+    cached.invalidate();
+    let after_invalidate = evaluations();
+    assert_eq_vec!(cached.value(), [2.0, 6.0]);
+    assert!(evaluations() > after_invalidate);
+    assert_eq_vec!(CachedExpression::new(Expression::constant(2.0)).value(), [2.0]);
+    // a new sharpness of the smooth forward, evaluated again
+    use super::{set_smooth_forward, SharpnessHandle, SmoothForward};
+    set_smooth_forward(SmoothForward::Always);
+    let k = SharpnessHandle::new(2.0);
+    let half = Expression::constant(1.5);
+    let cached = CachedExpression::new(x.le_sigmoid_annealed(&half, &k).add(&x.gt_sigmoid_annealed(&half, &k)));
+    let soft = cached.value();
+    k.set(4.0);
+    before_update();
+    let sharp = cached.value();
+    assert_ne!(soft, sharp);
+    assert_eq_vec!(sharp, x.le_sigmoid(&half, 4.0).add(&x.gt_sigmoid(&half, 4.0)).values(), 1e-15);
+    set_smooth_forward(SmoothForward::OnlyWithGrad);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn tunable_constant() {
+    use super::Error;
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let (temp, temp_handle) = Expression::tunable_constant(300.0);
+    let f = x.mul(&temp).add(&temp.sqr());
+    let fingerprint = f.fingerprint();
+    for temp in [250.0, 300.0, 350.0] {
+        before_update();
+        temp_handle.set(temp);
+        assert_eq!(temp_handle.get(), temp);
+        assert_eq_vec!(f.value().to_tensor().unwrap(), [temp + temp * temp, 2.0 * temp + temp * temp]);
+        let grads = f.backward();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), [temp, temp]);
+    }
+    assert_eq!(f.fingerprint(), fingerprint);
+    // promote the raw constants of a built graph
+    let g = x.mul(&Expression::constant(300.0)).sub(&Expression::constant(300.0)).exp().powf(0.5);
+    let (promoted, handle) = g.promote_const_to_parameter(300.0).unwrap();
+    assert_eq_vec!(promoted.value().to_tensor().unwrap(), g.value().to_tensor().unwrap(), 1e-9);
+    before_update();
+    handle.set(0.5);
+    // sqrt(exp(0.5x - 0.5))
+    assert_eq_vec!(promoted.value().to_tensor().unwrap(), [1.0, 0.25f64.exp()], 1e-12);
+    // held by an op, not an input
+    let Err(Error::CapturedConst { ops, .. }) = g.promote_const_to_parameter(0.5) else { panic!() };
+    assert_eq!(ops.into_iter().collect::<Vec<_>>(), [("$".to_owned(), "Powf(0.5)".to_owned())]);
+    // a constant root is promoted, no match is the same graph
+    let (promoted, handle) = Expression::constant(2.0).promote_const_to_parameter(2.0).unwrap();
+    assert_eq_vec!(promoted.value().to_tensor().unwrap(), [2.0]);
+    before_update();
+    handle.set(3.0);
+    assert_eq_vec!(promoted.value().to_tensor().unwrap(), [3.0]);
+    assert_eq!(g.promote_const_to_parameter(7.0).unwrap().0.fingerprint(), g.fingerprint());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn logic_reduce() {
+    let (x, x_ref) = Expression::tensor(vec![0.5, 0.8, 0.25], true);
+    x.mark_logic();
+    let (all, any) = (x.all(), x.any());
+    assert_eq_vec!(all.value().to_tensor().unwrap(), [0.1], 1e-12);
+    assert_eq_vec!(any.value().to_tensor().unwrap(), [1.0 - 0.5 * 0.2 * 0.75], 1e-12);
+    let grads = all.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.2, 0.125, 0.4], 1e-12);
+    let grads = any.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.15, 0.375, 0.1], 1e-12);
+    // an exact zero (one) factor
+    before_update();
+    x_ref.assign(vec![0.0, 0.8, 1.0]);
+    assert_eq_vec!(all.value().to_tensor().unwrap(), [0.0]);
+    assert_eq_vec!(any.value().to_tensor().unwrap(), [1.0]);
+    let grads = all.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.8, 0.0, 0.0], 1e-12);
+    let grads = any.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.0, 0.0, 0.2], 1e-12);
+    // long tensor, no underflow
+    let n = 10_000;
+    let (x, x_ref) = Expression::tensor(vec![0.999; n], true);
+    x.mark_logic();
+    let all = x.all();
+    let expected = (n as f64 * 0.999f64.ln()).exp();
+    assert!(expected > 4e-5);
+    assert_eq_vec!(all.value().to_tensor().unwrap(), [expected], 1e-12);
+    let grads = all.backward();
+    let grad = grads.get(&x_ref).unwrap();
+    assert_eq_vec!(grad, vec![expected / 0.999; n], 1e-12);
+    let fd = finite_difference(|t| t * ((n - 1) as f64 * 0.999f64.ln()).exp(), 0.999);
+    assert!((grad[0] - fd).abs() < 1e-9);
+    let any = x.any();
+    assert_eq_vec!(any.value().to_tensor().unwrap(), [1.0]);
+    // constants
+    assert_scalar!(&Expression::constant(0.5).all(), 0.5);
+    assert_scalar!(&Expression::constant(0.5).any(), 0.5);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn count_ge_yield() {
+    use super::GradMethod;
+    // normal-ish samples (Irwin-Hall), shifted by the parameter
+    let noise: Vec<f64> = (0..200)
+        .map(|i| (1..=3).map(|j| (i as f64 * 0.618_034 * j as f64 + 0.1 * j as f64).fract()).sum::<f64>() - 1.5)
+        .collect();
+    let (shift, shift_ref) = Expression::tensor(vec![0.2], true);
+    let samples = Expression::tensor(noise.clone(), false).0.add(&shift);
+    let methods = [GradMethod::new_sigmoid(8.0), GradMethod::new_tanh(4.0), GradMethod::new_linear(0.5), GradMethod::new_smoothstep(0.5)];
+    for method in methods {
+        for (f, threshold) in [(samples.count_ge(0.4, method.clone()), 0.4), (samples.mean_indicator(method.clone()), 0.5)] {
+            _ = f.value();
+            let grads = f.backward();
+            let grad = grads.get(&shift_ref).unwrap()[0];
+            assert!(grad > 0.0);
+            let value_at = |x: f64| {
+                before_update();
+                shift_ref.assign(vec![x]);
+                f.value().to_tensor().unwrap()[0]
+            };
+            let fd = finite_difference(value_at, 0.2);
+            _ = value_at(0.2);
+            assert!((grad - fd).abs() < 1e-5 * fd.abs().max(1.0), "{method:?} {threshold}: {grad} vs {fd}");
+        }
+    }
+    // hard counting
+    let count = samples.count_ge(0.4, GradMethod::Discrete);
+    let yield_ = samples.mean_indicator(GradMethod::Discrete);
+    let manual = |threshold: f64| noise.iter().filter(|z| *z + 0.2 >= threshold).count() as f64;
+    assert_eq_vec!(count.value().to_tensor().unwrap(), [manual(0.4)]);
+    assert_eq_vec!(yield_.value().to_tensor().unwrap(), [manual(0.5) / 200.0]);
+    assert!(manual(0.4) > 0.0 && manual(0.4) < 200.0);
+    assert_scalar!(&Expression::constant(0.7).mean_indicator(GradMethod::Discrete), 1.0);
+    assert_scalar!(&Expression::constant(0.5).mean_indicator(GradMethod::new_sigmoid(8.0)), 0.5);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn quantile() {
+    let values = vec![3.0, -1.0, 7.0, 2.0, 5.0, 0.0];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    // numpy.quantile(values, q), the linear method
+    for (q, expected) in [(0.0, -1.0), (0.25, 0.5), (0.5, 2.5), (0.9, 6.0), (0.99, 6.9), (1.0, 7.0)] {
+        assert_eq_vec!(x.quantile(q).value().to_tensor().unwrap(), [expected], 1e-12);
+    }
+    // the stored tensor is not sorted
+    assert_eq_vec!(x.value().to_tensor().unwrap(), &values);
+    // the gradient only flows into the defining samples, halfway between 0.0 and 2.0
+    let f = x.quantile(0.3);
+    _ = f.value();
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.0, 0.0, 0.0, 0.5, 0.0, 0.5], 1e-12);
+    let f = x.quantile(1.0);
+    _ = f.value();
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.0, 0.0, 1.0, 0.0, 0.0, 0.0], 1e-12);
+    // the smoothed one, to the whole neighborhood, and converging to the hard one
+    let smooth = x.quantile_smooth(0.3, 0.2);
+    _ = smooth.value();
+    let grads = smooth.backward();
+    let grad = grads.get(&x_ref).unwrap();
+    assert!(grad.iter().all(|g| *g > 0.0));
+    assert_eq_vec!([grad.iter().sum::<f64>()], [1.0], 1e-12);
+    let hard = x.quantile(0.3).value().to_tensor().unwrap()[0];
+    let error = |bandwidth: f64| (x.quantile_smooth(0.3, bandwidth).value().to_tensor().unwrap()[0] - hard).abs();
+    assert!(error(0.4) > 1e-3);
+    assert!(error(0.05) < 1e-3);
+    assert!(error(0.01) < 1e-9);
+    assert_eq_vec!(x.quantile_smooth(0.3, 0.0).value().to_tensor().unwrap(), [hard]);
+    // recomputed after an update
+    let median = x.quantile(0.5);
+    _ = median.value();
+    before_update();
+    x_ref.assign(vec![1.0, 4.0, 2.0]);
+    assert_eq_vec!(median.value().to_tensor().unwrap(), [2.0]);
+    assert!(Expression::tensor(vec![], false).0.quantile(0.5).value().to_tensor().unwrap()[0].is_nan());
+    assert_scalar!(&Expression::constant(4.0).quantile(0.9), 4.0);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn smooth_max_min() {
+    use super::MaxMethod;
+    // stable with a large beta around large values
+    let values = vec![1000.0, 1000.5, 999.0, 1000.5 - 1e-4];
+    let (x, x_ref) = Expression::tensor(values.clone(), true);
+    let beta = 1e4;
+    let f = x.smooth_max(beta);
+    let value = f.value().to_tensor().unwrap()[0];
+    let expected = 1000.5 + (1.0 + (-1.0f64).exp()).ln() / beta;
+    assert_eq_vec!([value], [expected], 1e-9);
+    let grads = f.backward();
+    let grad = grads.get(&x_ref).unwrap();
+    assert!(grad.iter().all(|g| g.is_finite()));
+    assert_eq_vec!(grad, [0.0, 1.0 / (1.0 + (-1.0f64).exp()), 0.0, (-1.0f64).exp() / (1.0 + (-1.0f64).exp())], 1e-9);
+    let g = x.smooth_min(beta);
+    assert_eq_vec!(g.value().to_tensor().unwrap(), [999.0], 1e-9);
+    // the gradient sums to the incoming one
+    let (x, x_ref) = Expression::tensor(vec![0.3, -1.2, 2.5, 2.4, 0.0], true);
+    for f in [x.smooth_max(2.0).mul(&Expression::constant(3.0)), x.smooth_min(2.0).mul(&Expression::constant(3.0))] {
+        _ = f.value();
+        let grads = f.backward();
+        let grad = grads.get(&x_ref).unwrap();
+        assert!(grad.iter().all(|g| *g > 0.0));
+        assert_eq_vec!([grad.iter().sum::<f64>()], [3.0], 1e-12);
+    }
+    // converges to the hard maximum / minimum
+    let hard_max = x.amax(MaxMethod::Hard).value().to_tensor().unwrap()[0];
+    let hard_min = x.neg().amax(MaxMethod::Hard).neg().value().to_tensor().unwrap()[0];
+    let mut last = (f64::MAX, f64::MAX);
+    for beta in [1.0, 10.0, 100.0, 1e3, 1e4] {
+        let max_error = x.smooth_max(beta).value().to_tensor().unwrap()[0] - hard_max;
+        let min_error = hard_min - x.smooth_min(beta).value().to_tensor().unwrap()[0];
+        assert!(max_error >= 0.0 && (max_error < last.0 || max_error == 0.0) && max_error <= 5f64.ln() / beta);
+        assert!(min_error >= 0.0 && (min_error < last.1 || min_error == 0.0) && min_error <= 5f64.ln() / beta);
+        last = (max_error, min_error);
+    }
+    assert!(last.0 < 1e-12 && last.1 < 1e-12);
+    assert_eq!(Expression::tensor(vec![], false).0.smooth_max(1.0).value().to_tensor().unwrap()[0], f64::NEG_INFINITY);
+    let message = |f: fn() -> Expression| *std::panic::catch_unwind(f).unwrap_err().downcast::<String>().unwrap();
+    assert_eq!(message(|| Expression::constant(1.0).smooth_max(0.0)), "non-positive smooth max beta 0");
+    assert_eq!(message(|| Expression::constant(1.0).smooth_min(-1.0)), "non-positive smooth min beta -1");
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn soft_histogram() {
+    use super::Error;
+    let edges = [0.0, 1.0, 2.0, 3.0, 4.0];
+    let samples = vec![0.3, 1.5, 1.7, 2.2, 3.9, -0.5, 2.9];
+    let (x, x_ref) = Expression::tensor(samples.clone(), true);
+    // each sample counts once, also the outliers
+    for sigma in [0.05, 0.5, 3.0] {
+        let counts = x.soft_histogram(&edges, sigma).value().to_tensor().unwrap();
+        assert_eq!(counts.len(), 4);
+        assert_eq_vec!([counts.iter().sum::<f64>()], [7.0], 1e-12);
+    }
+    // converges to the hard histogram
+    let hard = [2.0, 2.0, 2.0, 1.0];
+    let mut last = f64::MAX;
+    for sigma in [0.3, 0.1, 0.03, 0.01] {
+        let counts = x.soft_histogram(&edges, sigma).value().to_tensor().unwrap();
+        let error: f64 = counts.iter().zip(hard).map(|(c, h)| (c - h).abs()).sum();
+        assert!(error < last);
+        last = error;
+    }
+    assert!(last < 1e-12);
+    // the gradient against finite differences
+    let weights = Expression::tensor(vec![1.0, -2.0, 0.5, 3.0], false).0;
+    let f = x.soft_histogram(&edges, 0.4).mul(&weights).sum();
+    _ = f.value();
+    let grads = f.backward();
+    let grad = grads.get(&x_ref).unwrap();
+    for i in 0..samples.len() {
+        let value_at = |xi: f64| {
+            let mut values = samples.clone();
+            values[i] = xi;
+            before_update();
+            x_ref.assign(values);
+            f.value().to_tensor().unwrap()[0]
+        };
+        let fd = finite_difference(value_at, samples[i]);
+        assert!((grad[i] - fd).abs() < 1e-6, "{i}: {} vs {fd}", grad[i]);
+    }
+    // far from the edges, no gradient
+    let (far, far_ref) = Expression::tensor(vec![-100.0, 100.0], true);
+    let f = far.soft_histogram(&edges, 0.4).mul(&weights).sum();
+    _ = f.value();
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&far_ref).unwrap(), [0.0, 0.0]);
+    assert_eq_vec!(far.soft_histogram(&edges, 0.4).value().to_tensor().unwrap(), [1.0, 0.0, 0.0, 1.0]);
+    assert_eq_vec!(Expression::constant(2.5).soft_histogram(&edges, 1e-3).value().to_tensor().unwrap(), [0.0, 0.0, 1.0, 0.0]);
+    assert!(matches!(x.try_soft_histogram(&[0.0, 1.0, 1.0], 0.1), Err(Error::InvalidHistogram(_))));
+    assert!(matches!(x.try_soft_histogram(&[0.0], 0.1), Err(Error::InvalidHistogram(_))));
+    assert!(matches!(x.try_soft_histogram(&edges, 0.0), Err(Error::InvalidHistogram(_))));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn pairwise_ops() {
+    use super::{pairwise_limit, set_pairwise_limit, Error};
+    let (x, x_ref) = Expression::tensor(vec![1.0, 3.0, 4.0, 8.0, 16.0], true);
+    let diff = x.pairwise_diff();
+    assert_eq_vec!(diff.value().to_tensor().unwrap(), [-2.0, -3.0, -7.0, -15.0, -1.0, -5.0, -13.0, -4.0, -12.0, -8.0]);
+    // weighted by the pair index, +g to i and -g to j
+    let weights = Expression::tensor((1..=10).map(f64::from).collect(), false).0;
+    let f = diff.mul(&weights).sum();
+    _ = f.value();
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [10.0, 17.0, 10.0, -7.0, -30.0]);
+    let (y, y_ref) = Expression::tensor(vec![0.5, -1.0], true);
+    let outer = x.outer_sub(&y);
+    assert_eq_vec!(outer.value().to_tensor().unwrap(), [0.5, 2.0, 2.5, 4.0, 3.5, 5.0, 7.5, 9.0, 15.5, 17.0]);
+    let f = outer.mul(&weights).sum();
+    _ = f.value();
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [3.0, 7.0, 11.0, 15.0, 19.0]);
+    assert_eq_vec!(grads.get(&y_ref).unwrap(), [-25.0, -30.0]);
+    assert_eq_vec!(x.outer_sub(&Expression::constant(1.0)).value().to_tensor().unwrap(), [0.0, 2.0, 3.0, 7.0, 15.0]);
+    assert_eq!(Expression::tensor(vec![2.0], true).0.pairwise_diff().value().to_tensor().unwrap().len(), 0);
+    // the size guard
+    let limit = pairwise_limit();
+    set_pairwise_limit(9);
+    assert_eq!(x.try_pairwise_diff().unwrap_err(), Error::SizeLimit { size: 10, limit: 9, op: "PairwiseDiff".to_owned() });
+    assert!(matches!(x.try_outer_sub(&y), Err(Error::SizeLimit { size: 10, .. })));
+    assert!(x.try_outer_sub(&Expression::constant(1.0)).is_ok());
+    set_pairwise_limit(limit);
+    assert!(x.try_pairwise_diff().is_ok());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn matvec() {
+    use super::Error;
+    for (rows, cols) in [(2, 3), (3, 2), (1, 4), (3, 3)] {
+        let x_values: Vec<f64> = (0..cols).map(|j| 0.5 * j as f64 - 0.7).collect();
+        let w_values: Vec<f64> = (0..rows * cols).map(|k| ((k * 7 % 5) as f64 - 2.0) * 0.3).collect();
+        let c: Vec<f64> = (0..rows).map(|i| i as f64 + 1.0).collect();
+        let (x, x_ref) = Expression::tensor(x_values.clone(), true);
+        let (w, w_ref) = Expression::tensor(w_values.clone(), true);
+        let y = x.matvec(&w, rows, cols);
+        // hand-rolled loops
+        let mut expected = vec![0.0; rows];
+        let mut x_grad = vec![0.0; cols];
+        let mut w_grad = vec![0.0; rows * cols];
+        for i in 0..rows {
+            for j in 0..cols {
+                expected[i] += w_values[i * cols + j] * x_values[j];
+                x_grad[j] += c[i] * w_values[i * cols + j];
+                w_grad[i * cols + j] += c[i] * x_values[j];
+            }
+        }
+        assert_eq_vec!(y.value().to_tensor().unwrap(), &expected, 1e-12);
+        let f = y.mul(&Expression::tensor(c, false).0).sum();
+        _ = f.value();
+        let grads = f.backward();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), &x_grad, 1e-12);
+        assert_eq_vec!(grads.get(&w_ref).unwrap(), &w_grad, 1e-12);
+    }
+    // a tiny layer, recomputed after an update
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], false);
+    let (w, _) = Expression::tensor(vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0], false);
+    let y = x.matvec(&w, 3, 2).add(&Expression::constant(-2.0)).max(&Expression::constant(0.0));
+    assert_eq_vec!(y.value().to_tensor().unwrap(), [0.0, 0.0, 1.0]);
+    before_update();
+    x_ref.assign(vec![3.0, 4.0]);
+    assert_eq_vec!(y.value().to_tensor().unwrap(), [1.0, 2.0, 5.0]);
+    // dimensions
+    assert_eq!(x.try_matvec(&w, 2, 3).unwrap_err(), Error::LengthMismatch { expected: 3, got: 2, op: "MatVec input".to_owned() });
+    assert_eq!(x.try_matvec(&w, 2, 2).unwrap_err(), Error::LengthMismatch { expected: 4, got: 6, op: "MatVec weights".to_owned() });
+    assert_scalar!(&Expression::constant(2.0).matvec(&Expression::constant(3.0), 1, 1), 6.0);
+    let y = x.matvec(&w, 3, 2);
+    before_update();
+    x_ref.assign(vec![1.0, 2.0, 3.0]);
+    assert!(std::panic::catch_unwind(|| y.value().to_tensor().unwrap()).is_err());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn polyval() {
+    let (x, x_ref) = Expression::tensor(vec![-1.3, -0.2, 0.0, 0.7, 1.5], true);
+    let (c, c_ref) = Expression::tensor(vec![0.5, -1.0, 2.0, 0.25, -0.75, 0.1], true);
+    let f = x.polyval(&c);
+    // the composed graph Σ c[k] x^k
+    let reference = (0..6)
+        .map(|k| (0..k).fold(c.gather(&[k]), |term, _| term.mul(&x)))
+        .reduce(|sum, term| sum.add(&term))
+        .unwrap();
+    assert_eq_vec!(f.value().to_tensor().unwrap(), reference.value().to_tensor().unwrap(), 1e-12);
+    let weights = Expression::tensor(vec![1.0, -2.0, 0.5, 3.0, -1.5], false).0;
+    let (loss, expected) = (f.mul(&weights).sum(), reference.mul(&weights).sum());
+    _ = (loss.value(), expected.value());
+    let (grads, expected) = (loss.backward(), expected.backward());
+    for param in [&x_ref, &c_ref] {
+        assert_eq_vec!(grads.get(param).unwrap(), expected.get(param).unwrap(), 1e-12);
+    }
+    // recomputed after an update of the coefficients
+    before_update();
+    c_ref.assign(vec![1.0, 0.0, 1.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [2.69, 1.04, 1.0, 1.49, 3.25], 1e-12);
+    // a high degree stays finite inside |x| <= 1
+    let (y, _) = Expression::tensor(vec![-1.0, 0.5, 0.999], false);
+    let high = y.polyval(&Expression::tensor(vec![1.0; 301], false).0);
+    assert_eq_vec!(high.value().to_tensor().unwrap(), [1.0, 2.0 * (1.0 - 0.5f64.powi(301)), (1.0 - 0.999f64.powi(301)) / 0.001], 1e-9);
+    assert_scalar!(&Expression::constant(2.0).polyval(&Expression::constant(3.0)), 3.0);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn complex_expr() {
+    use super::ComplexExpr;
+    use num_complex::Complex64;
+    let (z_re, z_re_ref) = Expression::tensor(vec![1.5, -0.7, -2.0, 0.3], true);
+    let (z_im, z_im_ref) = Expression::tensor(vec![0.4, 1.2, -0.9, -2.5], true);
+    let (w_re, w_re_ref) = Expression::tensor(vec![0.8, 2.0, -0.3, 1.1], true);
+    let (w_im, w_im_ref) = Expression::tensor(vec![-1.1, 0.6, 0.5, 0.9], true);
+    let refs = [&z_re_ref, &z_im_ref, &w_re_ref, &w_im_ref];
+    let z = ComplexExpr::new(z_re.clone(), z_im.clone());
+    let w = ComplexExpr::new(w_re.clone(), w_im.clone());
+    let points: Vec<[f64; 4]> = (0..4).map(|i| refs.map(|r| r.0.read()[i])).collect();
+    let to_complex = |p: &[f64; 4]| (Complex64::new(p[0], p[1]), Complex64::new(p[2], p[3]));
+    // against num-complex
+    for (got, want) in [
+        (z.mul(&w), points.iter().map(|p| { let (z, w) = to_complex(p); z * w }).collect::<Vec<_>>()),
+        (z.div(&w), points.iter().map(|p| { let (z, w) = to_complex(p); z / w }).collect()),
+        (z.conj(), points.iter().map(|p| to_complex(p).0.conj()).collect()),
+        (z.scale(&Expression::constant(-2.5)), points.iter().map(|p| to_complex(p).0 * -2.5).collect()),
+    ] {
+        assert_eq_vec!(got.re.value().to_tensor().unwrap(), &want.iter().map(|c| c.re).collect::<Vec<_>>(), 1e-12);
+        assert_eq_vec!(got.im.value().to_tensor().unwrap(), &want.iter().map(|c| c.im).collect::<Vec<_>>(), 1e-12);
+    }
+    assert_eq_vec!(z.abs().value().to_tensor().unwrap(), &points.iter().map(|p| to_complex(p).0.norm()).collect::<Vec<_>>(), 1e-12);
+    assert_eq_vec!(z.arg().value().to_tensor().unwrap(), &points.iter().map(|p| to_complex(p).0.arg()).collect::<Vec<_>>(), 1e-12);
+    // one fused node per part
+    assert!(matches!(&z.mul(&w).re, Expression::Tensor(t) if matches!(t.op(), super::Op::Complex(..))));
+    // finite-difference gradients through abs and arg, of (expression, reference)
+    type Case = (ComplexExpr, fn(Complex64, Complex64) -> Complex64);
+    let fs: [Case; 2] = [
+        (z.div(&w), |z, w| z / w),
+        (z.mul(&w.conj()), |z, w| z * w.conj()),
+    ];
+    for (q, reference) in fs {
+        for (loss, part) in [(q.abs(), Complex64::norm as fn(Complex64) -> f64), (q.arg(), Complex64::arg)] {
+            let loss = loss.sum();
+            _ = loss.value();
+            let grads = loss.backward();
+            for (n, param) in refs.iter().enumerate() {
+                let want: Vec<f64> = points.iter().map(|p| finite_difference(|x| {
+                    let mut p = *p;
+                    p[n] = x;
+                    let (z, w) = to_complex(&p);
+                    part(reference(z, w))
+                }, p[n])).collect();
+                assert_eq_vec!(grads.get(param).unwrap(), &want, 1e-6);
+            }
+        }
+    }
+    // Smith's division where |w|² overflows
+    let big = ComplexExpr::new(Expression::constant(1e200), Expression::constant(0.0))
+        .div(&ComplexExpr::new(Expression::constant(1e200), Expression::constant(1e200)));
+    assert_scalar!(&big.re, 0.5);
+    assert_scalar!(&big.im, -0.5);
+    // atan2 covers the four quadrants, the gradient is zero at the origin
+    let (y, y_ref) = Expression::tensor(vec![1.0, 1.0, -1.0, -1.0, 0.0], true);
+    let (x, x_ref) = Expression::tensor(vec![1.0, -1.0, -1.0, 1.0, 0.0], true);
+    let angle = y.atan2(&x);
+    assert_eq_vec!(angle.value().to_tensor().unwrap(), [1.0f64.atan2(1.0), 1.0f64.atan2(-1.0), (-1.0f64).atan2(-1.0), (-1.0f64).atan2(1.0), 0.0], 1e-15);
+    let grads = angle.sum().backward();
+    assert_eq_vec!(grads.get(&y_ref).unwrap(), [0.5, -0.5, -0.5, 0.5, 0.0], 1e-15);
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [-0.5, -0.5, 0.5, 0.5, 0.0], 1e-15);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn db_units() {
+    use super::DB_FLOOR;
+    let values = [-3.0, -0.1, 0.0, 1e-15, 0.5, 10.0];
+    let (x, x_ref) = Expression::tensor(values.to_vec(), true);
+    // x = 0 is floored without NaN
+    let db20 = x.db20();
+    assert_eq_vec!(db20.value().to_tensor().unwrap(), &values.map(|x| (20.0 * f64::log10(f64::abs(x))).max(DB_FLOOR)), 1e-12);
+    let grads = db20.sum().backward();
+    let got = grads.get(&x_ref).unwrap();
+    assert!(got.iter().all(|g| g.is_finite()));
+    assert_eq!(got[2], 0.0);
+    // the sign follows x, -0.1 and 1e-15 are above the floor
+    assert!((got[3] - 20.0 / (std::f64::consts::LN_10 * 1e-15)).abs() <= 1e-12 * got[3]);
+    for i in [0, 1, 4, 5] {
+        let want = finite_difference(|x| 20.0 * x.abs().log10(), values[i]);
+        assert!((got[i] - want).abs() <= 1e-6 * want.abs(), "{i}: {} {want}", got[i]);
+    }
+    assert!(got[0] < 0.0 && got[5] > 0.0);
+    // a custom floor clamps 1e-15 too
+    let floored = x.db20_floor(-200.0);
+    assert_eq!(floored.value().to_tensor().unwrap()[3], -200.0);
+    let grads = floored.sum().backward();
+    assert_eq_vec!(&grads.get(&x_ref).unwrap()[2..4], [0.0, 0.0]);
+    let grads = x.db10().sum().backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), &values.map(|x| if x == 0.0 { 0.0 } else { 10.0 / (std::f64::consts::LN_10 * x) }), 1e-12);
+    // round trip
+    let round_trip = x.db20().from_db20();
+    let want = [3.0, 0.1, 1e-20, 1e-15, 0.5, 10.0];
+    for (got, want) in izip!(round_trip.value().to_tensor().unwrap().iter(), want) {
+        assert!((got - want).abs() <= 1e-12 * want, "{got} {want}");
+    }
+    let grads = round_trip.sum().backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [-1.0, -1.0, 0.0, 1.0, 1.0, 1.0], 1e-9);
+    assert_scalar!(&Expression::constant(30.0).from_db10(), 1000.0);
+    // unit scaling folds into a scalar multiplication
+    let milli = x.scale_unit(1e-3);
+    assert!(matches!(&milli, Expression::Tensor(t) if matches!(t.op(), super::Op::Binary(_, Expression::Const(_), _))));
+    assert_eq_vec!(milli.value().to_tensor().unwrap(), &values.map(|x| x * 1e-3));
+    assert_scalar!(&Expression::constant(2.0).scale_unit(1e3), 2000.0);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn assert_in_range() {
+    use super::{set_assert_policy, take_assert_violation, AssertPolicy, Error};
+    set_assert_policy(AssertPolicy::Error);
+    _ = take_assert_violation();
+    let (vgs, vgs_ref) = Expression::tensor(vec![0.5, 1.0, 1.5], true);
+    let checked = vgs.assert_in_range(0.0, 2.0, "vgs");
+    let f = checked.mul(&checked).sum();
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [3.5]);
+    assert_eq!(take_assert_violation(), Ok(()));
+    // identity backward
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&vgs_ref).unwrap(), [1.0, 2.0, 3.0]);
+    // the bounds are inclusive
+    before_update();
+    vgs_ref.assign(vec![0.0, 1.0, 2.0]);
+    f.value();
+    assert_eq!(take_assert_violation(), Ok(()));
+    // detected at the update that crosses the bound
+    before_update();
+    vgs_ref.assign(vec![0.0, 2.25, -1.0]);
+    f.value();
+    assert_eq!(
+        take_assert_violation(),
+        Err(Error::AssertViolation { label: "vgs".to_owned(), index: 1, value: "2.25".to_owned(), range: "[0, 2]".to_owned() })
+    );
+    // re-armed, and not reported again without a recompute
+    f.value();
+    assert_eq!(take_assert_violation(), Ok(()));
+    // only logged
+    set_assert_policy(AssertPolicy::Log);
+    before_update();
+    vgs_ref.assign(vec![f64::NAN, 0.0, 0.0]);
+    f.value();
+    assert_eq!(take_assert_violation(), Ok(()));
+    // skipped per node
+    set_assert_policy(AssertPolicy::Ignore);
+    before_update();
+    vgs_ref.assign(vec![3.0, 0.0, 0.0]);
+    assert_eq_vec!(f.value().to_tensor().unwrap(), [9.0]);
+    assert_eq!(take_assert_violation(), Ok(()));
+    // a constant is checked once at the construction
+    set_assert_policy(AssertPolicy::Error);
+    assert_scalar!(&Expression::constant(-1.0).assert_in_range(0.0, 1.0, "c"), -1.0);
+    assert!(matches!(take_assert_violation(), Err(Error::AssertViolation { index: 0, .. })));
+    set_assert_policy(AssertPolicy::Log);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn profile_report() {
+    use super::profile::{self, Phase};
+    let n = 200_000;
+    let (x, x_ref) = Expression::tensor(vec![0.5; n], true);
+    let f = x.exp().exp().exp().sum();
+    f.value();
+    profile::reset();
+    profile::enable();
+    before_update();
+    x_ref.assign(vec![0.25; n]);
+    f.value();
+    _ = f.backward();
+    profile::disable();
+    let report = profile::report();
+    let forward = |op: &str| report.entries.iter().find(|e| e.op == op && e.phase == Phase::Forward).unwrap();
+    let exp = forward("Exp");
+    assert_eq!((exp.calls, exp.elements, exp.len_bucket), (3, 3 * n, n.next_power_of_two()));
+    assert_eq!(forward("Sum").len_bucket, 1);
+    assert!(report.time_of("Exp") > report.time_of("Sum"), "{report}");
+    assert!(report.entries.iter().any(|e| e.op == "Exp" && e.phase == Phase::Backward));
+    // one output per recomputed node
+    assert_eq!(report.allocations, 4);
+    assert!(report.to_string().lines().nth(1).unwrap().starts_with("Exp"), "{report}");
+    // nothing recorded when disabled
+    before_update();
+    x_ref.assign(vec![0.5; n]);
+    f.value();
+    assert_eq!(profile::report().allocations, 4);
+    profile::reset();
+    assert!(profile::report().entries.is_empty());
+}
+
+/// `(target, level, message)`
+type LogRecords = Vec<(String, log::Level, String)>;
+
+/// Records of the `gspice::*` targets, installed once as the global logger
+struct CaptureLogger(std::sync::Mutex<LogRecords>);
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("gspice::")
+    }
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push((
+                record.target().to_owned(),
+                record.level(),
+                record.args().to_string(),
+            ));
+        }
+    }
+    fn flush(&self) {}
+}
+
+static CAPTURE_LOGGER: CaptureLogger = CaptureLogger(std::sync::Mutex::new(Vec::new()));
+
+/// Take the captured records
+fn capture_logs() -> LogRecords {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        log::set_logger(&CAPTURE_LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+    });
+    core::mem::take(&mut *CAPTURE_LOGGER.0.lock().unwrap())
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn structured_logging() {
+    use super::optimizer::{log_summary_every, Optimizer, Sgd};
+    let count = |logs: &LogRecords, target: &str| logs.iter().filter(|(t, _, _)| t == target).count();
+    _ = capture_logs();
+    // flush the nodes of the other tests
+    Expression::constant(0.0).value();
+    _ = capture_logs();
+    let (x, x_ref) = Expression::tensor(vec![0.5; 1000], true);
+    let f = x.exp().mul(&Expression::constant(2.0)).sum();
+    f.value();
+    let logs = capture_logs();
+    assert_eq!(logs.iter().find(|(t, _, _)| t == "gspice::graph").unwrap().2, "4 nodes constructed since the last evaluation");
+    // once per evaluation, not per element
+    before_update();
+    x_ref.assign(vec![0.25; 1000]);
+    f.value();
+    let logs = capture_logs();
+    assert_eq!(logs.len(), 1, "{logs:?}");
+    assert_eq!(logs[0].1, log::Level::Debug);
+    assert!(logs[0].2.starts_with("3 nodes recomputed, 0 unchanged"), "{logs:?}");
+    _ = f.backward();
+    let logs = capture_logs();
+    assert_eq!(count(&logs, "gspice::backward"), 1);
+    assert!(logs[0].2.starts_with("backward over 4 nodes"), "{logs:?}");
+    // non-differentiable op, once per node
+    let g = x.ceil().sum();
+    g.value();
+    _ = g.backward();
+    let logs = capture_logs();
+    assert_eq!(logs.iter().filter(|(t, l, m)| t == "gspice::backward" && *l == log::Level::Error && m == "BackwardNotSupported Ceil").count(), 1);
+    // anomaly event
+    super::set_anomaly_detection(true);
+    let h = x.log_clamped(1.0).mul(&Expression::constant(f64::INFINITY));
+    h.value();
+    super::set_anomaly_detection(false);
+    _ = super::take_anomaly_report();
+    assert_eq!(count(&capture_logs(), "gspice::anomaly"), 1);
+    // optimizer summary every 2 steps
+    log_summary_every(2);
+    let mut sgd = Sgd::new(vec![x_ref.clone()], 1e-4);
+    for _ in 0..4 {
+        f.value();
+        sgd.step(&f.backward());
+    }
+    log_summary_every(0);
+    let summaries: Vec<_> = capture_logs().into_iter().filter(|(t, _, _)| t == "gspice::optim").collect();
+    assert_eq!(summaries.len(), 2);
+    assert!(summaries[0].2.starts_with("Sgd step 2: loss 2."), "{summaries:?}");
+    assert!(summaries[1].2.contains("grad norm"));
+    log::set_max_level(log::LevelFilter::Off);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn deterministic_sum() {
+    use super::set_deterministic;
+    // cancelling triples `a, 1, -a`, the exact sum is the count of the triples
+    let mut rng = rand::thread_rng();
+    let uniform = rand::distributions::Uniform::new(1e8, 1e10);
+    let triples = 333_333;
+    let values: Vec<f64> = (0..triples).flat_map(|_| { let a = uniform.sample(&mut rng); [a, 1.0, -a] }).collect();
+    let (x, x_ref) = Expression::tensor(values.clone(), false);
+    let (sum, mean) = (x.sum(), x.mean());
+    let run = |threads: usize| {
+        let eval = || {
+            before_update();
+            x_ref.assign(values.clone());
+            (sum.value().to_tensor().unwrap()[0], mean.value().to_tensor().unwrap()[0])
+        };
+        #[cfg(feature = "rayon")]
+        return rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap().install(eval);
+        #[cfg(not(feature = "rayon"))]
+        {
+            _ = threads;
+            eval()
+        }
+    };
+    set_deterministic(true);
+    let (sum_1, mean_1) = run(1);
+    for threads in [2, 3, 8] {
+        let (sum_n, mean_n) = run(threads);
+        assert_eq!(sum_1.to_bits(), sum_n.to_bits(), "{threads} threads");
+        assert_eq!(mean_1.to_bits(), mean_n.to_bits(), "{threads} threads");
+    }
+    assert!((sum_1 - triples as f64).abs() < 1e-3 * triples as f64, "{sum_1}");
+    set_deterministic(false);
+    for threads in [1, 8] {
+        let (sum_n, _) = run(threads);
+        assert!((sum_n - triples as f64).abs() < 1e-2 * triples as f64, "{sum_n}");
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn compensated_sum() {
+    use super::{set_accuracy_mode, AccuracyMode};
+    // `1e16, 1, -1e16, 1`, the ones are lost by the plain additions, the exact sum is `n/2`
+    let n = 10_000_000;
+    let pattern = [1e16, 1.0, -1e16, 1.0];
+    let values: Vec<f64> = (0..n).map(|i| pattern[i % 4]).collect();
+    let (x, x_ref) = Expression::tensor(values.clone(), false);
+    let (sum, mean) = (x.sum(), x.mean());
+    let eval = |mode| {
+        set_accuracy_mode(mode);
+        before_update();
+        x_ref.assign(values.clone());
+        (sum.value().to_tensor().unwrap()[0], mean.value().to_tensor().unwrap()[0])
+    };
+    let (fast_sum, _) = eval(AccuracyMode::Fast);
+    assert_ne!(fast_sum, (n / 2) as f64);
+    assert_eq!(eval(AccuracyMode::Compensated), ((n / 2) as f64, 0.5));
+    // dot products
+    let (w, _) = Expression::tensor(values[..4000].to_vec(), false);
+    let dot = Expression::tensor(vec![1.0; 1000], false).0.matvec(&w, 4, 1000);
+    assert_eq_vec!(dot.value().to_tensor().unwrap(), [500.0; 4]);
+    // gradient accumulation of a fan-in of 1e5 branches
+    let (p, p_ref) = Expression::tensor(vec![1.0], true);
+    let branches: Vec<Expression> = (0..100_000).map(|i| p.mul(&Expression::constant(pattern[i % 4]))).collect();
+    let f = Expression::concat(&branches).sum();
+    let grad = |mode| {
+        set_accuracy_mode(mode);
+        f.value();
+        f.backward().get(&p_ref).unwrap()[0]
+    };
+    assert_ne!(grad(AccuracyMode::Fast), 50_000.0);
+    assert_eq!(grad(AccuracyMode::Compensated), 50_000.0);
+    set_accuracy_mode(AccuracyMode::Fast);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn retention() {
+    const LEN: usize = 256;
+    const STEPS: usize = 200;
+    const SEGMENT: usize = 20;
+    let (x, x_ref) = Expression::tensor((0..LEN).map(|i| 0.5 + 0.01 * i as f64).collect(), true);
+    let k = Expression::constant(1.0001);
+    // 3 nodes per step, x is read by every step
+    let chain = |checkpoint: bool| {
+        let mut y = x.clone();
+        for step in 1..=STEPS {
+            y = y.mul(&k).sin().add(&x);
+            if checkpoint && step % SEGMENT == 0 {
+                y = y.checkpoint();
+            }
+        }
+        y.sum()
+    };
+    let all_bytes = 3 * STEPS * LEN * size_of::<f64>();
+    let update = |v: f64| {
+        before_update();
+        x_ref.assign(vec![v; LEN]);
+    };
+
+    let plain = chain(false);
+    let stats = plain.memory_stats();
+    assert_eq!(stats.released_nodes, 0);
+    assert!(stats.retained_bytes > all_bytes);
+    update(0.3);
+    let plain_value = plain.value().to_tensor().unwrap();
+    let expected = plain.backward();
+
+    update(0.5);
+    let y = chain(false);
+    y.set_retention(super::Retention::OutputsOnly);
+    let stats = y.memory_stats();
+    assert_eq!(stats.nodes, plain.memory_stats().nodes);
+    // all but the leaf, the output and the sum below it
+    assert_eq!(stats.released_nodes, stats.nodes - 2);
+    assert_eq!(stats.released_bytes, all_bytes);
+    assert!(stats.retained_bytes < 2 * LEN * size_of::<f64>());
+    update(0.3);
+    assert_eq_vec!(y.value().to_tensor().unwrap(), &plain_value, 1e-12);
+    // released as soon as read, the peak is checked in `tests/memory.rs`
+    assert_eq!(y.memory_stats(), stats);
+    let grads = y.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), expected.get(&x_ref).unwrap(), 1e-12);
+    assert_eq!(y.memory_stats().released_nodes, stats.released_nodes);
+    // explicitly requested, then released again by the next forward
+    y.materialize();
+    assert_eq!(y.memory_stats().released_nodes, 0);
+    assert_eq_vec!(y.value().to_tensor().unwrap(), &plain_value, 1e-12);
+    update(0.3);
+    y.value();
+    assert_eq!(y.memory_stats().released_nodes, stats.released_nodes);
+
+    // keep the checkpoints
+    let z = chain(true);
+    z.set_retention(super::Retention::Checkpoints);
+    assert_eq!(z.memory_stats().released_nodes, stats.released_nodes - STEPS / SEGMENT);
+    update(0.3);
+    assert_eq_vec!(z.value().to_tensor().unwrap(), &plain_value, 1e-12);
+    let grads = z.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), expected.get(&x_ref).unwrap(), 1e-12);
+
+    // back to keeping all
+    y.set_retention(super::Retention::All);
+    assert_eq!(y.memory_stats().released_nodes, 0);
+    update(0.3);
+    assert_eq_vec!(y.value().to_tensor().unwrap(), &plain_value, 1e-12);
+    assert_eq!(y.memory_stats().released_nodes, 0);
+    let grads = y.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), expected.get(&x_ref).unwrap(), 1e-12);
+
+    // an op built on a released node reads the recomputed values
+    let (a, _) = Expression::tensor(vec![0.2, 0.4], true);
+    let mid = a.sin();
+    let out = mid.exp();
+    out.set_retention(super::Retention::OutputsOnly);
+    assert_eq!(out.memory_stats().released_nodes, 1);
+    let outside = mid.mul(&k);
+    // computed at the construction, before any evaluation
+    let Expression::Tensor(outside) = &outside else { unreachable!() };
+    assert_eq!(*outside.values().read().unwrap(), [0.2_f64.sin() * 1.0001, 0.4_f64.sin() * 1.0001]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn intern_consts() {
+    const STEPS: usize = 30;
+    let (x, x_ref) = Expression::tensor(vec![0.1, -0.4, 0.7, 1.2], true);
+    let (c1, c1_handle) = Expression::tunable_constant(2.0);
+    let (c2, _c2_handle) = Expression::tunable_constant(2.0);
+    // as generated: the same subexpressions rebuilt at each step
+    let mut f = x.mul(&c1).add(&x.mul(&c2));
+    for step in 0..STEPS {
+        let s = x.powf(2.0).mul(&Expression::constant(0.5)).sin();
+        f = f.add(&s.mul(&Expression::constant(step as f64 + 1.0)));
+    }
+    f = f.add(&x.mul(&c1)).sum();
+    let interned = f.intern_consts();
+    // powf, mul by 0.5 and sin once instead of each step, and x * c1 once,
+    // x * c2 is kept since c1 and c2 are different tunable constants
+    assert_eq!(f.node_count() - interned.node_count(), 3 * (STEPS - 1) + 1);
+    assert_eq_vec!(interned.value().to_tensor().unwrap(), f.value().to_tensor().unwrap(), 0.0);
+    // the gradients of a merged node are accumulated in another order
+    let (grads, expected) = (interned.backward(), f.backward());
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), expected.get(&x_ref).unwrap(), 1e-12);
+    // idempotent, and the tunable constants stay independent
+    assert_eq!(interned.intern_consts().node_count(), interned.node_count());
+    before_update();
+    c1_handle.set(3.0);
+    assert_eq_vec!(interned.value().to_tensor().unwrap(), f.value().to_tensor().unwrap(), 0.0);
+
+    // builder context
+    let mut builder = super::GraphBuilder::new();
+    let a = builder.intern(&x.powf(2.0).mul(&Expression::constant(0.5)));
+    let b = builder.intern(&x.powf(2.0).mul(&Expression::constant(0.5)));
+    let c = builder.intern(&x.powf(3.0).mul(&Expression::constant(0.5)));
+    let d = builder.intern(&x.powf(2.0).mul(&Expression::constant(-0.5)));
+    assert!(matches!((&a, &b), (Expression::Tensor(a), Expression::Tensor(b)) if a == b));
+    assert!(matches!((&a, &c), (Expression::Tensor(a), Expression::Tensor(c)) if a != c));
+    assert!(matches!((&a, &d), (Expression::Tensor(a), Expression::Tensor(d)) if a != d));
+    // b entirely, the powf of d
+    assert_eq!(builder.merged(), 2 + 1);
+    assert_eq!(builder.len(), 5);
+
+    // comparisons on distinct sharpness handles of the same value stay independent
+    use super::{set_smooth_forward, SharpnessHandle, SmoothForward};
+    set_smooth_forward(SmoothForward::Always);
+    let (x, _) = Expression::tensor(vec![0.1, -0.4], true);
+    let y = Expression::tensor(vec![-0.2, -0.1], false).0;
+    let (k1, k2) = (SharpnessHandle::new(2.0), SharpnessHandle::new(2.0));
+    let f = x.le_sigmoid_annealed(&y, &k1).add(&x.le_sigmoid_annealed(&y, &k2).mul(&Expression::constant(10.0)));
+    let interned = f.intern_consts();
+    assert_eq!(interned.node_count(), f.node_count());
+    before_update();
+    k2.set(50.0);
+    assert_eq_vec!(interned.value().to_tensor().unwrap(), f.value().to_tensor().unwrap(), 0.0);
+    // the same handle is merged
+    let g = x.le_sigmoid_annealed(&y, &k1).add(&x.le_sigmoid_annealed(&y, &k1));
+    assert_eq!(g.node_count() - g.intern_consts().node_count(), 1);
+    set_smooth_forward(SmoothForward::OnlyWithGrad);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn approx_eq() {
+    use super::Error;
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let near = Expression::tensor(vec![1.0 + 1e-10, 2.0, 3.0 - 1e-10], false).0;
+    assert!(x.value().approx_eq(&near.value(), 0.0, 1e-9));
+    assert!(!x.value().approx_eq(&near.value(), 0.0, 1e-11));
+    assert!(x.value().approx_eq(&near.value(), 1e-9, 0.0));
+    // a constant is a length-1 tensor
+    let one = Expression::tensor(vec![1.0], false).0;
+    assert!(Expression::constant(1.0).value().approx_eq(&one.value(), 0.0, 0.0));
+    assert!(!Expression::constant(1.0).value().approx_eq(&x.value(), 0.0, 1.0));
+    Expression::constant(1.0 + 1e-13).assert_close(&one, 0.0, 1e-12);
+    // NaN
+    let nan = Expression::tensor(vec![1.0, f64::NAN], false).0;
+    assert!(!nan.value().approx_eq(&nan.value(), 0.0, f64::INFINITY));
+    assert!(nan.check_close(&nan, Tolerance::new(0.0, 0.0).nan_equal(true)).is_ok());
+    assert!(Expression::constant(f64::INFINITY).check_close(&Expression::constant(f64::INFINITY), Tolerance::new(0.0, 0.0)).is_ok());
+    // the worst index is reported
+    let far = Expression::tensor(vec![1.5, 2.0, 0.0], false).0;
+    assert_eq!(
+        x.check_close(&far, Tolerance::new(0.0, 0.1)),
+        Err(Error::NotClose("2 of 3 elements differ, worst at index 2: 3 vs 0 (|diff| 3, rtol 0, atol 0.1)".to_owned())),
+    );
+    assert_eq!(
+        x.check_close(&nan, Tolerance::new(0.0, 0.1)),
+        Err(Error::NotClose("length 3 vs 2".to_owned())),
+    );
+    let e = std::panic::catch_unwind(|| x.assert_close(&far, 0.0, 0.1)).unwrap_err();
+    assert_eq!(
+        e.downcast_ref::<String>().unwrap(),
+        "assertion `left ≈ right` failed: not close: 2 of 3 elements differ, worst at index 2: 3 vs 0 (|diff| 3, rtol 0, atol 0.1)\n left: Tensor([1.0, 2.0, 3.0])\nright: Tensor([1.5, 2.0, 0.0])",
+    );
+    // gradients
+    let y = Expression::tensor(vec![0.5, 0.5, 0.5], true).0;
+    let f = x.mul(&y).sum();
+    let g = x.mul(&y.add(&Expression::constant(1e-13))).sum();
+    let (grads, near_grads) = (f.backward(), g.backward());
+    grads.assert_close(&near_grads, 0.0, 1e-12);
+    assert!(!grads.approx_eq(&near_grads, 0.0, 0.0));
+    assert_eq!(
+        grads.check_close(&x.sum().backward(), Tolerance::new(0.0, 0.0)),
+        Err(Error::NotClose(format!("gradient of {:?}: 3 of 3 elements differ, worst at index 0: 0.5 vs 1 (|diff| 0.5, rtol 0, atol 0)", x_ref.grad_id().unwrap()))),
+    );
+    assert_eq!(
+        grads.check_close(&y.sum().backward(), Tolerance::new(0.0, 1.0)),
+        Err(Error::NotClose(format!("gradient of {:?} only in the left", x_ref.grad_id().unwrap()))),
+    );
+    assert!(y.sum().backward().check_close(&grads, Tolerance::new(0.0, 10.0)).is_err());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_exp_log() {
+    use super::property::{check_close, for_all, grad, leaves, values, Domain};
+    let domains = [Domain::Range(1e-6, 1e6)];
+    for_all("exp_log", &domains, |inputs| {
+        let (x, x_ref) = &leaves(inputs, &domains)[0];
+        let (f, len) = (x.log().exp(), inputs[0].len());
+        check_close("exp(log(x))", &values(&f, len), &inputs[0], Tolerance::new(1e-13, 0.0))?;
+        check_close("d exp(log(x)) / dx", &grad(&f.backward(), x_ref, len), &vec![1.0; len], Tolerance::new(1e-13, 0.0))
+    });
+    let domains = [Domain::Range(-30.0, 30.0)];
+    for_all("log_exp", &domains, |inputs| {
+        let (x, x_ref) = &leaves(inputs, &domains)[0];
+        let (f, len) = (x.exp().log(), inputs[0].len());
+        check_close("log(exp(x))", &values(&f, len), &inputs[0], Tolerance::new(0.0, 1e-13))?;
+        check_close("d log(exp(x)) / dx", &grad(&f.backward(), x_ref, len), &vec![1.0; len], Tolerance::new(1e-13, 0.0))
+    });
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_pythagorean() {
+    use super::property::{check_close, for_all, grad, leaves, values, Domain};
+    let domains = [Domain::Range(-1e3, 1e3)];
+    for_all("pythagorean", &domains, |inputs| {
+        let (x, x_ref) = &leaves(inputs, &domains)[0];
+        let (f, len) = (x.sin().sqr().add(&x.cos().sqr()), inputs[0].len());
+        check_close("sin²(x) + cos²(x)", &values(&f, len), &vec![1.0; len], Tolerance::new(0.0, 1e-15))?;
+        check_close("d (sin²(x) + cos²(x)) / dx", &grad(&f.backward(), x_ref, len), &vec![0.0; len], Tolerance::new(0.0, 1e-15))
+    });
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_min_max() {
+    use super::property::{check_exprs_close, for_all, leaves, Domain};
+    let domains = [Domain::Range(-10.0, 10.0); 2];
+    // also at the ties, where each side gets half of the gradient
+    for_all("min_plus_max", &domains, |inputs| {
+        let leaves = leaves(inputs, &domains);
+        let (a, b) = (&leaves[0].0, &leaves[1].0);
+        check_exprs_close("min(a, b) + max(a, b)", &a.min(b).add(&a.max(b)), &a.add(b), &leaves, Tolerance::new(0.0, 0.0))?;
+        check_exprs_close("min(a, b)", &a.min(b), &b.min(a), &leaves, Tolerance::new(0.0, 0.0))?;
+        check_exprs_close("max(a, b)", &a.max(b), &a.neg().min(&b.neg()).neg(), &leaves, Tolerance::new(0.0, 0.0))
+    });
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_de_morgan() {
+    use super::property::{check_exprs_close, for_all, leaves, Domain};
+    let domains = [Domain::LOGIC; 2];
+    let tolerance = Tolerance::new(0.0, 1e-15);
+    for_all("de_morgan", &domains, |inputs| {
+        let leaves = leaves(inputs, &domains);
+        let (a, b) = (&leaves[0].0, &leaves[1].0);
+        check_exprs_close("not(and(a, b))", &a.logic_and(b).logic_not(), &a.logic_not().logic_or(&b.logic_not()), &leaves, tolerance)?;
+        check_exprs_close("not(or(a, b))", &a.logic_or(b).logic_not(), &a.logic_not().logic_and(&b.logic_not()), &leaves, tolerance)?;
+        check_exprs_close("nand(a, b)", &a.logic_nand(b), &a.logic_and(b).logic_not(), &leaves, tolerance)?;
+        check_exprs_close("nor(a, b)", &a.logic_nor(b), &a.logic_or(b).logic_not(), &leaves, tolerance)?;
+        check_exprs_close("xor(a, b)", &a.logic_xor(b), &b.logic_xor(a), &leaves, tolerance)?;
+        check_exprs_close("xor(a, 1)", &a.logic_xor(&Expression::constant(1.0)), &a.logic_not(), &leaves, tolerance)
+    });
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_unary_ops() {
+    use super::property::{apply_unary, check_close, for_all, grad, leaves, unary_domain, values, Dual, EVERY_UNARY};
+    let tolerance = Tolerance::new(1e-12, 1e-12);
+    for op in EVERY_UNARY {
+        let domains = [unary_domain(op)];
+        for_all(&format!("unary_{op:?}"), &domains, |inputs| {
+            let leaves = leaves(inputs, &domains);
+            let (x, x_ref) = &leaves[0];
+            let len = inputs[0].len();
+            let f = apply_unary(op, x);
+            let duals: Vec<Dual> = inputs[0].iter().map(|x| Dual::unary(op, Dual::new(*x, 1.0))).collect();
+            let want: Vec<f64> = duals.iter().map(|d| d.value).collect();
+            check_close(&format!("{op:?}(x)"), &values(&f, len), &want, tolerance)?;
+            let want: Vec<f64> = duals.iter().map(|d| d.derivative).collect();
+            check_close(&format!("d {op:?}(x) / dx"), &grad(&f.backward(), x_ref, len), &want, tolerance)
+        });
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_binary_ops() {
+    use super::property::{apply_binary, binary_domains, check_close, for_all, grad, leaves, values, Dual, EVERY_BINARY};
+    let tolerance = Tolerance::new(1e-12, 1e-12);
+    for op in EVERY_BINARY {
+        let domains = binary_domains(op);
+        for_all(&format!("binary_{op:?}"), &domains, |inputs| {
+            let leaves = leaves(inputs, &domains);
+            let ((a, a_ref), (b, b_ref)) = (&leaves[0], &leaves[1]);
+            let len = inputs[0].len();
+            let f = apply_binary(op, a, b);
+            let (want, want_a, want_b): (Vec<f64>, Vec<f64>, Vec<f64>) = izip!(&inputs[0], &inputs[1])
+                .map(|(a, b)| {
+                    let da = Dual::binary(op, Dual::new(*a, 1.0), Dual::new(*b, 0.0));
+                    let db = Dual::binary(op, Dual::new(*a, 0.0), Dual::new(*b, 1.0));
+                    (da.value, da.derivative, db.derivative)
+                })
+                .fold((vec![], vec![], vec![]), |(mut v, mut ga, mut gb), (x, dx, dy)| {
+                    v.push(x);
+                    ga.push(dx);
+                    gb.push(dy);
+                    (v, ga, gb)
+                });
+            check_close(&format!("{op:?}(a, b)"), &values(&f, len), &want, tolerance)?;
+            let grads = f.backward();
+            check_close(&format!("d {op:?}(a, b) / da"), &grad(&grads, a_ref, len), &want_a, tolerance)?;
+            check_close(&format!("d {op:?}(a, b) / db"), &grad(&grads, b_ref, len), &want_b, tolerance)?;
+            // a constant side is broadcasted
+            let (b0, a0) = (inputs[1][0], inputs[0][0]);
+            let f = apply_binary(op, a, &Expression::constant(b0));
+            let want: Vec<f64> = inputs[0].iter().map(|a| Dual::binary(op, Dual::new(*a, 1.0), Dual::new(b0, 0.0))).map(|d| d.value).collect();
+            check_close(&format!("{op:?}(a, b[0])"), &values(&f, len), &want, tolerance)?;
+            let want: Vec<f64> = inputs[0].iter().map(|a| Dual::binary(op, Dual::new(*a, 1.0), Dual::new(b0, 0.0)).derivative).collect();
+            check_close(&format!("d {op:?}(a, b[0]) / da"), &grad(&f.backward(), a_ref, len), &want, tolerance)?;
+            let f = apply_binary(op, &Expression::constant(a0), b);
+            let want: Vec<f64> = inputs[1].iter().map(|b| Dual::binary(op, Dual::new(a0, 0.0), Dual::new(*b, 1.0)).derivative).collect();
+            check_close(&format!("d {op:?}(a[0], b) / db"), &grad(&f.backward(), b_ref, len), &want, tolerance)
+        });
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_cmp_methods() {
+    use super::property::{check, check_close, for_all, grad, leaves, values, Domain};
+    use super::{DiscreteBinaryOp, GradMethod};
+    let domains = [Domain::Range(-2.0, 2.0); 2];
+    let methods = [
+        ("sigmoid", GradMethod::new_sigmoid(3.0)),
+        ("linear", GradMethod::new_linear(0.5)),
+        ("tanh", GradMethod::new_tanh(2.0)),
+        ("smoothstep", GradMethod::new_smoothstep(0.5)),
+    ];
+    let ops = [DiscreteBinaryOp::Eq, DiscreteBinaryOp::Ne, DiscreteBinaryOp::Le, DiscreteBinaryOp::Ge, DiscreteBinaryOp::Lt, DiscreteBinaryOp::Gt];
+    let tolerance = Tolerance::new(1e-12, 1e-15);
+    for (name, method) in methods {
+        for_all(&format!("cmp_{name}"), &domains, |inputs| {
+            let leaves = leaves(inputs, &domains);
+            let ((a, a_ref), (b, b_ref)) = (&leaves[0], &leaves[1]);
+            let len = inputs[0].len();
+            // (values, d/da, d/db)
+            let eval = |op: DiscreteBinaryOp, lhs: &Expression, rhs: &Expression| {
+                let f = lhs.cmp(rhs, op, method.clone());
+                let grads = f.backward();
+                (values(&f, len), grad(&grads, a_ref, len), grad(&grads, b_ref, len))
+            };
+            let diff: Vec<f64> = izip!(&inputs[0], &inputs[1]).map(|(a, b)| a - b).collect();
+            for op in ops {
+                let (value, da, db) = eval(op, a, b);
+                // the forward is the discrete comparison
+                let want: Vec<f64> = izip!(&inputs[0], &inputs[1])
+                    .map(|(a, b)| match op {
+                        DiscreteBinaryOp::Eq => a == b,
+                        DiscreteBinaryOp::Ne => a != b,
+                        DiscreteBinaryOp::Le => a <= b,
+                        DiscreteBinaryOp::Ge => a >= b,
+                        DiscreteBinaryOp::Lt => a < b,
+                        DiscreteBinaryOp::Gt => a > b,
+                    } as u8 as f64)
+                    .collect();
+                check_close(&format!("{op:?}(a, b)"), &value, &want, Tolerance::new(0.0, 0.0))?;
+                // only depends on `a - b`
+                check_close(&format!("d {op:?}(a, b) / da"), &da, &db.iter().map(|g| -g).collect::<Vec<_>>(), tolerance)?;
+                // monotonic surrogates
+                let sign = match op {
+                    DiscreteBinaryOp::Eq => -1.0,
+                    DiscreteBinaryOp::Ne => 1.0,
+                    DiscreteBinaryOp::Le | DiscreteBinaryOp::Lt => -1.0,
+                    DiscreteBinaryOp::Ge | DiscreteBinaryOp::Gt => 1.0,
+                };
+                match op {
+                    DiscreteBinaryOp::Eq | DiscreteBinaryOp::Ne => check(&format!("d {op:?}(a, b) / da * (a - b)"), &da, |i, g| sign * g * diff[i] >= 0.0)?,
+                    _ => check(&format!("d {op:?}(a, b) / da"), &da, |_, g| sign * g >= 0.0)?,
+                }
+            }
+            // the mirrored comparisons
+            for (op, mirrored) in [(DiscreteBinaryOp::Gt, DiscreteBinaryOp::Lt), (DiscreteBinaryOp::Ge, DiscreteBinaryOp::Le)] {
+                let ((value, da, db), (want, want_da, want_db)) = (eval(op, a, b), eval(mirrored, b, a));
+                check_close(&format!("{op:?}(a, b)"), &value, &want, Tolerance::new(0.0, 0.0))?;
+                check_close(&format!("d {op:?}(a, b) / da"), &da, &want_da, tolerance)?;
+                check_close(&format!("d {op:?}(a, b) / db"), &db, &want_db, tolerance)?;
+            }
+            let ((_, ne_da, _), (_, eq_da, _)) = (eval(DiscreteBinaryOp::Ne, a, b), eval(DiscreteBinaryOp::Eq, a, b));
+            check_close("d Ne(a, b) / da", &ne_da, &eq_da.iter().map(|g| -g).collect::<Vec<_>>(), tolerance)
+        });
+    }
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_random_graphs() {
+    use super::property::{check_close, for_all_graphs, grad, leaves, values, Domain};
+    let domains = [Domain::Range(-2.0, 2.0); 2];
+    let tolerance = Tolerance::new(1e-10, 1e-10);
+    // the forward and the backward match the forward-mode reference
+    for_all_graphs("graph_reference", &domains, 1, 4, |graphs, inputs| {
+        let leaves = leaves(inputs, &domains);
+        let exprs: Vec<Expression> = leaves.iter().map(|(x, _)| x.clone()).collect();
+        let (f, len) = (graphs[0].build(&exprs), inputs[0].len());
+        let (want, want_grads) = graphs[0].reference(inputs);
+        check_close("f", &values(&f, len), &want, tolerance)?;
+        let grads = f.backward();
+        for (k, ((_, x_ref), want)) in izip!(&leaves, &want_grads).enumerate() {
+            check_close(&format!("d f / dx{k}"), &grad(&grads, x_ref, len), want, tolerance)?;
+        }
+        Ok(())
+    });
+    // the gradient is linear
+    for_all_graphs("gradient_linearity", &domains, 2, 3, |graphs, inputs| {
+        let leaves = leaves(inputs, &domains);
+        let exprs: Vec<Expression> = leaves.iter().map(|(x, _)| x.clone()).collect();
+        let (f, g, len) = (graphs[0].build(&exprs), graphs[1].build(&exprs), inputs[0].len());
+        let (f_grads, g_grads, sum_grads) = (f.backward(), g.backward(), f.add(&g).backward());
+        for (k, (_, x_ref)) in leaves.iter().enumerate() {
+            let want: Vec<f64> = izip!(grad(&f_grads, x_ref, len), grad(&g_grads, x_ref, len)).map(|(f, g)| f + g).collect();
+            check_close(&format!("d (f + g) / dx{k}"), &grad(&sum_grads, x_ref, len), &want, Tolerance::new(1e-12, 1e-12))?;
+        }
+        Ok(())
+    });
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn property_shrinking() {
+    use super::property::{check, for_all, for_all_graphs, grad, leaves, Domain};
+    // a wrong property is reported with its minimal counterexample
+    let e = std::panic::catch_unwind(|| {
+        for_all("shrink_threshold", &[Domain::Range(-100.0, 100.0)], |inputs| check("x", &inputs[0], |_, x| x < 10.0))
+    }).unwrap_err();
+    let message = e.downcast_ref::<String>().unwrap();
+    assert!(message.starts_with("property `shrink_threshold` failed"), "{message}");
+    assert!(message.ends_with("at element 0: x: 10\ninputs: [[10.0]]"), "{message}");
+    let domains = [Domain::Range(-2.0, 2.0)];
+    let e = std::panic::catch_unwind(|| {
+        for_all_graphs("shrink_graph", &domains, 1, 4, |graphs, inputs| {
+            let leaves = leaves(inputs, &domains);
+            let f = graphs[0].build(&[leaves[0].0.clone()]);
+            check("d f / dx0", &grad(&f.backward(), &leaves[0].1, inputs[0].len()), |_, g| g == 0.0)
+        })
+    }).unwrap_err();
+    let message = e.downcast_ref::<String>().unwrap();
+    assert!(message.contains("graphs: [\"x0\"]"), "{message}");
+    assert!(message.ends_with("inputs: [[0.0]]"), "{message}");
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn snapshot() {
+    use super::{Error, PwlExtrapolation, SnapshotMismatch};
+    let (a, _) = Expression::tensor(vec![0.5, -1.0, 2.0], true);
+    let (b, _) = Expression::tensor(vec![1.5, 0.25, -3.0], true);
+    let build = |scale: f64| {
+        let s = a.mul(&b);
+        s.exp().add(&s.sin()).mul(&Expression::constant(scale))
+    };
+    let f = build(2.0);
+    let mut golden = Vec::new();
+    f.snapshot(&mut golden).unwrap();
+    let diff = f.verify_snapshot(&golden[..]).unwrap();
+    assert!(diff.is_empty(), "{diff}");
+    assert_eq!(diff.hash, diff.expected_hash);
+    // the shared node built last, and not shared at all
+    let sin = a.mul(&b).sin();
+    let rebuilt = a.mul(&b).exp().add(&sin).mul(&Expression::constant(2.0));
+    assert!(rebuilt.verify_snapshot(&golden[..]).unwrap().is_empty());
+    // a changed constant is named by its path, and changes every output
+    let diff = build(3.0).verify_snapshot(&golden[..]).unwrap();
+    assert_ne!(diff.hash, diff.expected_hash);
+    assert_eq!(diff.total, 4);
+    assert_eq!(diff.mismatches[0], SnapshotMismatch::Node { path: "$.1".to_owned(), expected: "Const(2)".to_owned(), got: "Const(3)".to_owned() });
+    assert!(matches!(&diff.mismatches[1], SnapshotMismatch::Value { path, index: 0, .. } if path == "$"));
+    assert!(diff.to_string().contains("~ $.1: Const(2) -> Const(3)"), "{diff}");
+    // a changed leaf value
+    let (c, c_ref) = Expression::tensor(vec![1.0, 2.0], true);
+    let g = c.sqr().add(&c);
+    let mut golden = Vec::new();
+    g.snapshot(&mut golden).unwrap();
+    before_update();
+    c_ref.assign(vec![1.0, 2.5]);
+    let diff = g.verify_snapshot(&golden[..]).unwrap();
+    assert_eq!(diff.hash, diff.expected_hash);
+    assert_eq!(diff.mismatches[0], SnapshotMismatch::Value { path: "$.0.0".to_owned(), index: 1, expected: 2.0, got: 2.5 });
+    // not a snapshot, or truncated
+    assert!(matches!(g.verify_snapshot(&b"GSPICE"[..]), Err(Error::InvalidSnapshot(_))));
+    assert!(matches!(g.verify_snapshot(&golden[..golden.len() - 1]), Err(Error::InvalidSnapshot(_))));
+    // two tables on one input are distinct nodes, not merged by the op name
+    let (x, _) = Expression::tensor(vec![0.25, 0.75], true);
+    let xs = [0.0, 1.0];
+    let pwl = |ys: &[f64]| x.pwl(&xs, ys, PwlExtrapolation::Clamp);
+    let f = pwl(&[0.0, 1.0]).add(&pwl(&[1.0, 3.0]));
+    let mut golden = Vec::new();
+    f.snapshot(&mut golden).unwrap();
+    assert!(f.verify_snapshot(&golden[..]).unwrap().is_empty());
+    let diff = pwl(&[0.0, 1.0]).add(&pwl(&[0.0, 1.0])).verify_snapshot(&golden[..]).unwrap();
+    assert_eq!(diff.mismatches[0], SnapshotMismatch::NodeCount { expected: 4, got: 3 });
+    let diff = pwl(&[0.0, 1.0]).add(&pwl(&[1.0, 2.0])).verify_snapshot(&golden[..]).unwrap();
+    assert_ne!(diff.hash, diff.expected_hash);
+    assert!(matches!(&diff.mismatches[0], SnapshotMismatch::Node { path, expected, got } if path == "$.1" && expected.starts_with("Pwl[") && expected != got), "{diff}");
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn owned_values() {
+    let (x, x_ref) = Expression::tensor(vec![1.0, -2.0, 3.5], true);
+    let (y, _) = Expression::tensor(vec![4.0], true);
+    let (empty, _) = Expression::tensor(vec![], false);
+    let c = Expression::constant(2.5);
+    // scalar
+    assert_eq!(c.value().to_owned_values(), [2.5]);
+    assert_eq!(c.value().as_scalar(), Some(2.5));
+    assert_eq!(c.value().len(), 1);
+    assert_eq!(c.values(), [2.5]);
+    assert_eq!(c.scalar(), Some(2.5));
+    assert!(c.value() == 2.5 && c.value() != 2.0);
+    // tensor
+    let f = x.mul(&c);
+    assert_eq!(f.value().to_owned_values(), [2.5, -5.0, 8.75]);
+    assert_eq!(f.value().as_scalar(), None);
+    assert_eq!(f.value().len(), 3);
+    assert_eq!(f.values(), [2.5, -5.0, 8.75]);
+    assert_eq!(f.scalar(), None);
+    assert!(f.value() != 2.5);
+    // length-1 tensor as a scalar
+    assert_eq!(y.value().to_owned_values(), [4.0]);
+    assert_eq!(y.value().as_scalar(), Some(4.0));
+    assert_eq!(y.scalar(), Some(4.0));
+    assert!(y.value() == 4.0);
+    assert!(x.sum().value() == 2.5);
+    // empty tensor
+    assert!(empty.value().is_empty());
+    assert_eq!(empty.values(), Vec::<f64>::new());
+    assert_eq!(empty.scalar(), None);
+    // owned, so that the values outlive the graph and are sent across threads
+    let values = f.values();
+    before_update();
+    x_ref.assign(vec![0.0, 0.0, 0.0]);
+    drop(f);
+    assert_eq!(std::thread::spawn(move || values.iter().sum::<f64>()).join().unwrap(), 6.25);
+    // the read lock is released
+    before_update();
+    x_ref.assign(vec![1.0, 1.0, 1.0]);
+    assert_eq!(x.values(), [1.0, 1.0, 1.0]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn tie_policy() {
+    use super::{BinaryOp, GraphBuilder, Op, TiePolicy};
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let (y, y_ref) = Expression::tensor(vec![1.0, 0.0, 5.0], true);
+    for (tie, lhs_share) in [(TiePolicy::Split, 0.5), (TiePolicy::Lhs, 1.0), (TiePolicy::Rhs, 0.0)] {
+        // min: tie, rhs, lhs; max: tie, lhs, rhs
+        let grads = x.min_tie(&y, tie).backward();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), [lhs_share, 0.0, 1.0]);
+        assert_eq_vec!(grads.get(&y_ref).unwrap(), [1.0 - lhs_share, 1.0, 0.0]);
+        let grads = x.max_tie(&y, tie).backward();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), [lhs_share, 1.0, 0.0]);
+        assert_eq_vec!(grads.get(&y_ref).unwrap(), [1.0 - lhs_share, 0.0, 1.0]);
+        let Expression::Tensor(f) = x.min_tie(&y, tie) else { panic!() };
+        assert!(matches!(f.op(), Op::Binary(_, _, BinaryOp::Min(t)) if *t == tie));
+        // a shared subexpression tied with itself gets the whole gradient
+        let s = x.sqr();
+        let grads = s.min_tie(&s, tie).add(&s.max_tie(&s, tie)).backward();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), [4.0, 8.0, 12.0]);
+        // also when both sides are merged by the builder
+        let mut builder = GraphBuilder::new();
+        let merged = builder.intern(&x.sqr().min_tie(&x.sqr(), tie));
+        let Expression::Tensor(merged_tensor) = &merged else { panic!() };
+        let Op::Binary(Expression::Tensor(lhs), Expression::Tensor(rhs), _) = merged_tensor.op() else { panic!() };
+        assert_eq!(lhs.key(), rhs.key());
+        let grads = merged.backward();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), [2.0, 4.0, 6.0]);
+    }
+    // the policies are not merged with each other
+    let mut builder = GraphBuilder::new();
+    let split = builder.intern(&x.min(&y));
+    let lhs = builder.intern(&x.min_tie(&y, TiePolicy::Lhs));
+    assert_ne!(split.fingerprint(), lhs.fingerprint());
+    assert_eq!(builder.intern(&x.min_tie(&y, TiePolicy::Split)).fingerprint(), split.fingerprint());
+    assert_eq!(TiePolicy::default(), TiePolicy::Split);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn pow_non_positive_base() {
+    use super::{set_anomaly_detection, take_anomaly_report};
+    _ = take_anomaly_report();
+    let values = [0.0, -2.0, 1.5];
+    let (a, a_ref) = Expression::tensor(values.to_vec(), true);
+    let (b, b_ref) = Expression::tensor(vec![3.0; 3], true);
+    set_anomaly_detection(true);
+    let grads = a.pow(&b).backward();
+    set_anomaly_detection(false);
+    let d_a = grads.get(&a_ref).unwrap();
+    let d_b = grads.get(&b_ref).unwrap();
+    assert!(d_a.iter().chain(d_b.iter()).all(|g| g.is_finite()), "{d_a:?} {d_b:?}");
+    let want: Vec<f64> = values.iter().map(|x| finite_difference(|x| x.powf(3.0), *x)).collect();
+    assert_eq_vec!(d_a, want, 1e-6);
+    // undefined `ln(a)` for the exponent, reported
+    assert_eq_vec!(d_b, [0.0, 0.0, 1.5f64.powi(3) * 1.5f64.ln()], 1e-12);
+    let report = take_anomaly_report().expect("no anomaly detected");
+    assert_eq!(report.op, "d Pow / d rhs");
+    assert_eq!(report.index, 0);
+    assert_eq_vec!(&report.inputs, &[0.0, 3.0]);
+    // `a = 0` for the other exponents
+    let (zero, zero_ref) = Expression::tensor(vec![0.0; 5], true);
+    let exponents = Expression::tensor(vec![2.0, 1.0, 0.0, 0.5, -1.0], false).0;
+    let grads = zero.pow(&exponents).backward();
+    assert_eq!(grads.get(&zero_ref).unwrap()[..], [0.0, 1.0, 0.0, f64::INFINITY, f64::NEG_INFINITY]);
+    // a constant exponent or base, and no report without the gradient of the exponent
+    let grads = a.pow(&Expression::constant(2.0)).backward();
+    assert_eq_vec!(grads.get(&a_ref).unwrap(), [0.0, -4.0, 3.0]);
+    let grads = Expression::constant(-2.0).pow(&b).backward();
+    assert_eq_vec!(grads.get(&b_ref).unwrap(), [0.0; 3]);
+    set_anomaly_detection(true);
+    _ = a.pow(&Expression::tensor(vec![3.0; 3], false).0).backward();
+    set_anomaly_detection(false);
+    assert!(take_anomaly_report().is_none());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cond_const_blend() {
+    let (t, t_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let (f, f_ref) = Expression::tensor(vec![-1.0, 4.0, 0.5], true);
+    let blend = Expression::constant(0.3).cond(&t, &f);
+    let want = Expression::constant(0.3).mul(&t).add(&Expression::constant(0.7).mul(&f));
+    blend.assert_close(&want, 1e-12, 0.0);
+    let grads = blend.backward();
+    assert_eq_vec!(grads.get(&t_ref).unwrap(), [0.3; 3], 1e-12);
+    assert_eq_vec!(grads.get(&f_ref).unwrap(), [0.7; 3], 1e-12);
+    // the same blend whatever the method, and with a constant branch
+    Expression::constant(0.3).cond_ste(&t, &f).assert_close(&want, 1e-12, 0.0);
+    let half = Expression::constant(0.3).cond(&t, &Expression::constant(2.0));
+    half.assert_close(&Expression::constant(0.3).mul(&t).add(&Expression::constant(1.4)), 1e-12, 0.0);
+    let grads = half.backward();
+    assert_eq_vec!(grads.get(&t_ref).unwrap(), [0.3; 3], 1e-12);
+    // recomputed as a blend
+    before_update();
+    t_ref.assign(vec![10.0, 20.0, 30.0]);
+    blend.assert_close(&want, 1e-12, 0.0);
+    assert_eq_vec!(blend.values(), [2.3, 8.8, 9.35], 1e-12);
+    // `0` and `1` are the branch itself
+    let (Expression::Tensor(t_tensor), Expression::Tensor(f_tensor)) = (&t, &f) else { unreachable!() };
+    assert!(matches!(Expression::constant(1.0).cond(&t, &f), Expression::Tensor(x) if x == *t_tensor));
+    assert!(matches!(Expression::constant(0.0).cond(&t, &f), Expression::Tensor(x) if x == *f_tensor));
+    assert!(matches!(Expression::constant(1.0).cond(&Expression::constant(2.0), &f), Expression::Const(x) if x == 2.0));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn smooth_forward() {
+    use super::{set_smooth_forward, SmoothForward};
+    let (a, a_ref) = Expression::tensor(vec![-1.0, 0.0, 0.5], false);
+    let b = Expression::tensor(vec![0.0; 3], false).0;
+    let sigmoid = |x: f64| 1.0 / (1.0 + (2.0 * x).exp());
+    // the default, hard without the gradient
+    assert_eq!(super::smooth_forward(), SmoothForward::OnlyWithGrad);
+    assert_eq_vec!(a.le_sigmoid(&b, 2.0).values(), [1.0, 1.0, 0.0]);
+    set_smooth_forward(SmoothForward::Always);
+    let le = a.le_sigmoid(&b, 2.0);
+    assert_eq_vec!(le.values(), [sigmoid(-1.0), 0.5, sigmoid(0.5)], 1e-12);
+    // the constant sides and the others ops, `ge(a, b) = le(b, a)`
+    assert_eq_vec!(a.ge_sigmoid(&Expression::constant(0.0), 2.0).values(), [sigmoid(1.0), 0.5, sigmoid(-0.5)], 1e-12);
+    assert_eq_vec!(a.ne_tanh(&b, 1.0).values(), [1.0f64.tanh().powi(2), 0.0, 0.5f64.tanh().powi(2)], 1e-12);
+    assert_eq_vec!(a.eq_linear(&b, 1.0).values(), [0.0, 1.0, 0.5], 1e-12);
+    assert_eq_vec!(a.lt_smoothstep(&b, 1.0).values(), [1.0, 0.5, 0.15625], 1e-12);
+    assert!(matches!(Expression::constant(0.0).le_sigmoid(&Expression::constant(0.0), 2.0), Expression::Const(x) if x == 0.5));
+    // the hard ones stay hard
+    assert_eq_vec!(a.le(&b).values(), [1.0, 1.0, 0.0]);
+    // the gradient is the derivative of the smooth value
+    let (x, x_ref) = Expression::tensor(vec![0.3], true);
+    let grads = x.le_sigmoid(&Expression::constant(0.0), 2.0).backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [finite_difference(sigmoid, 0.3)], 1e-6);
+    // recomputed with the policy
+    before_update();
+    a_ref.assign(vec![1.0, 0.0, -0.5]);
+    assert_eq_vec!(le.values(), [sigmoid(1.0), 0.5, sigmoid(-0.5)], 1e-12);
+    // a new sharpness recomputes the comparisons and their dependents
+    let k = super::SharpnessHandle::new(2.0);
+    let annealed = a.le_sigmoid_annealed(&b, &k).mul(&Expression::constant(2.0));
+    assert_eq_vec!(annealed.values(), [2.0 * sigmoid(1.0), 1.0, 2.0 * sigmoid(-0.5)], 1e-12);
+    before_update();
+    k.set(4.0);
+    assert_eq_vec!(annealed.values(), [2.0 / (1.0 + 4f64.exp()), 1.0, 2.0 / (1.0 + (-2f64).exp())], 1e-12);
+    set_smooth_forward(SmoothForward::OnlyWithGrad);
+    assert_eq_vec!(a.le_sigmoid(&b, 2.0).values(), [0.0, 1.0, 1.0]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cmp_const_folding() {
+    use super::{set_smooth_forward, DiscreteBinaryOp, GradMethod, SmoothForward};
+    let methods = [
+        GradMethod::Discrete,
+        GradMethod::new_linear(0.5),
+        GradMethod::new_sigmoid(5.0),
+        GradMethod::new_tanh(5.0),
+        GradMethod::new_smoothstep(0.5),
+    ];
+    let ops = [
+        DiscreteBinaryOp::Eq, DiscreteBinaryOp::Ne, DiscreteBinaryOp::Le,
+        DiscreteBinaryOp::Ge, DiscreteBinaryOp::Lt, DiscreteBinaryOp::Gt,
+    ];
+    let tensor = |x: f64| Expression::tensor(vec![x], false).0;
+    for policy in [SmoothForward::OnlyWithGrad, SmoothForward::Always] {
+        set_smooth_forward(policy);
+        for (method, op) in itertools::iproduct!(&methods, ops) {
+            for (lhs, rhs) in [(1.0, 1.1), (1.0, 1.0), (0.2, -0.1)] {
+                let Expression::Const(folded) = Expression::constant(lhs).cmp(&Expression::constant(rhs), op, method.clone()) else {
+                    panic!("not folded");
+                };
+                for (l, r) in [
+                    (Expression::constant(lhs), tensor(rhs)),
+                    (tensor(lhs), Expression::constant(rhs)),
+                    (tensor(lhs), tensor(rhs)),
+                ] {
+                    assert_eq_vec!(l.cmp(&r, op, method.clone()).values(), [folded], 1e-15);
+                }
+            }
+        }
+    }
+    // `1 / (1 + e^(5 × (1 - 1.1)))`
+    let smooth = Expression::constant(1.0).le_sigmoid(&Expression::constant(1.1), 5.0);
+    assert!(matches!(smooth, Expression::Const(x) if (x - 0.6224593312018546).abs() < 1e-12));
+    set_smooth_forward(SmoothForward::OnlyWithGrad);
+    let hard = Expression::constant(1.0).le_sigmoid(&Expression::constant(1.1), 5.0);
+    assert!(matches!(hard, Expression::Const(x) if x == 1.0));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cond_guarded() {
+    use super::optimizer::{Optimizer, Sgd};
+    // the loss prefers `cond = 1.5`, beyond the logic bound
+    let (on_true, on_false) = (Expression::constant(2.0), Expression::constant(0.0));
+    let loss = |f: &Expression| f.sub(&Expression::constant(3.0)).sqr();
+    let (p, p_ref) = Expression::tensor(vec![0.5], true);
+    p.mark_logic();
+    let f = loss(&p.cond(&on_true, &on_false));
+    let mut sgd = Sgd::new(vec![p_ref.clone()], 0.05);
+    let escaped = (0..100).any(|_| {
+        sgd.step(&f.backward());
+        // stop before the next forward, which asserts the logic bounds in debug
+        p.scalar().unwrap() > 1.0
+    });
+    assert!(escaped, "{}", p.scalar().unwrap());
+    // guarded, the same parameter
+    before_update();
+    p_ref.assign(vec![0.5]);
+    let guarded = p.saturate01();
+    let f = loss(&guarded.cond(&on_true, &on_false));
+    assert_eq_vec!(f.values(), loss(&p.cond_guarded(&on_true, &on_false)).values());
+    let mut sgd = Sgd::new(vec![p_ref], 0.05);
+    for _ in 0..100 {
+        sgd.step(&f.backward());
+        f.value();
+        let c = guarded.scalar().unwrap();
+        assert!((0.0..=1.0).contains(&c), "{c}");
+    }
+    // stuck at the bound, the closest to the target
+    assert_eq_vec!(f.values(), [1.0]);
+    assert_eq_vec!(guarded.values(), [1.0]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn logic_nary() {
+    let n = 10_000;
+    let p = 1e-6;
+    let (terms, refs): (Vec<_>, Vec<_>) = (0..n).map(|_| Expression::tensor(vec![p], true)).unzip();
+    terms.iter().for_each(|term| term.mark_logic());
+    // `1 - (1 - p)^n = ∑_k (-1)^(k+1) C(n, k) p^k`, the terms decay by `np = 0.01`
+    let mut term = 1.0;
+    let mut want = 0.0;
+    for k in 1..=20 {
+        term *= -((n - k + 1) as f64) / k as f64 * p;
+        want -= term;
+    }
+    let fused = Expression::logic_or_many(&terms);
+    let fused_err = ((fused.scalar().unwrap() - want) / want).abs();
+    assert!(fused_err < 1e-12, "{fused_err}");
+    // the forward of `logic_or`, a chain of 10k nodes is too deep for the test thread
+    let folded = (0..n).fold(0.0, |acc: f64, _| (acc + p - acc * p).clamp(0.0, 1.0));
+    let folded_err = ((folded - want) / want).abs();
+    assert!(fused_err < folded_err, "{fused_err} vs {folded_err}");
+    // `∂/∂x_k = (1 - p)^(n-1)`
+    let grads = fused.backward();
+    let want_grad = ((n - 1) as f64 * (-p).ln_1p()).exp();
+    for r in [&refs[0], &refs[n / 2], &refs[n - 1]] {
+        assert_eq_vec!(grads.get(r).unwrap(), [want_grad], 1e-15);
+    }
+
+    // the same as the folded ones, with broadcasting and constants
+    let (a, a_ref) = Expression::tensor(vec![0.2, 0.0, 1.0], true);
+    let (b, b_ref) = Expression::tensor(vec![0.5], true);
+    a.mark_logic();
+    b.mark_logic();
+    let c = Expression::constant(0.3);
+    for (fused, folded) in [
+        (Expression::logic_or_many(&[a.clone(), b.clone(), c.clone()]), a.logic_or(&b).logic_or(&c)),
+        (Expression::logic_and_many(&[a.clone(), b.clone(), c.clone()]), a.logic_and(&b).logic_and(&c)),
+    ] {
+        fused.assert_close(&folded, 1e-15, 0.0);
+        let (fused_grads, folded_grads) = (fused.backward(), folded.backward());
+        for r in [&a_ref, &b_ref] {
+            assert_eq_vec!(fused_grads.get(r).unwrap(), folded_grads.get(r).unwrap(), 1e-15);
+        }
+    }
+    // two absorbing terms, no gradient through either
+    let (x, x_ref) = Expression::tensor(vec![0.0, 0.0, 0.5], true);
+    x.mark_logic();
+    let and = Expression::logic_and_many(&[x.clone(), Expression::constant(0.0)]);
+    assert_eq_vec!(and.values(), [0.0; 3]);
+    let grads = and.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), [0.0; 3]);
+    // recomputed
+    before_update();
+    a_ref.assign(vec![0.5, 0.5, 0.5]);
+    assert_eq_vec!(Expression::logic_or_many(&[a.clone(), b.clone()]).values(), [0.75; 3], 1e-15);
+    // constants and no terms
+    assert!(matches!(Expression::logic_or_many(&[c.clone(), c.clone()]), Expression::Const(x) if (x - 0.51).abs() < 1e-15));
+    assert!(matches!(Expression::logic_or_many(&[]), Expression::Const(x) if x == 0.0));
+    assert!(matches!(Expression::logic_and_many(&[]), Expression::Const(x) if x == 1.0));
+    let pair = Expression::tensor(vec![0.5; 2], false).0;
+    pair.mark_logic();
+    assert!(Expression::try_logic_and_many(&[a, pair]).is_err());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn cond_length_mismatch() {
+    use super::Error;
+    let input = |len: usize| {
+        let (x, x_ref) = Expression::tensor(vec![1.0; len], false);
+        x.mark_logic();
+        (x, x_ref)
+    };
+    let mismatch = |op: String| Error::LengthMismatch { expected: 3, got: 2, op };
+    // each input in turn one element short, at the construction
+    for short in 0..3 {
+        let [cond, on_true, on_false] = [0, 1, 2].map(|k| input(if k == short { 2 } else { 3 }).0);
+        let lens = [0, 1, 2].map(|k| if k == short { 2 } else { 3 });
+        let op = format!("Cond (cond {}, on_true {}, on_false {})", lens[0], lens[1], lens[2]);
+        let err = cond.try_cond(&on_true, &on_false).unwrap_err();
+        // the first length is the expected one, unless it is the short one
+        let want = if short == 0 { Error::LengthMismatch { expected: 2, got: 3, op } } else { mismatch(op) };
+        assert_eq!(err, want, "short {short}");
+        assert!(err.to_string().contains("on_false"), "{err}");
+    }
+    // and at the recompute, instead of a truncated output
+    for short in 0..3 {
+        let [(cond, cond_ref), (on_true, on_true_ref), (on_false, on_false_ref)] = [0, 1, 2].map(|_| input(3));
+        let f = cond.cond(&on_true, &on_false);
+        before_update();
+        [&cond_ref, &on_true_ref, &on_false_ref][short].assign(vec![1.0; 2]);
+        let e = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f.value().to_tensor().unwrap()))
+            .expect_err("truncated output");
+        let lens = [0, 1, 2].map(|k| if k == short { 2 } else { 3 });
+        let op = format!("Cond (cond {}, on_true {}, on_false {})", lens[0], lens[1], lens[2]);
+        let want = if short == 0 { Error::LengthMismatch { expected: 2, got: 3, op } } else { mismatch(op) };
+        assert_eq!(e.downcast_ref::<String>().unwrap(), &want.to_string(), "short {short}");
+    }
+    // a length-1 input broadcasts
+    let [(cond, _), (on_true, _), (on_false, _)] = [input(1), input(3), input(3)];
+    assert_eq!(cond.cond(&on_true, &on_false).values().len(), 3);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn graph_guard() {
+    use super::{graph::find_cycle, max_graph_depth, set_max_graph_depth, Error, Tensor};
+    let (x, x_ref) = Expression::tensor(vec![0.5], true);
+    let chain = |n: usize| (0..n).fold(x.clone(), |acc, _| acc.sin());
+    assert_eq!(x.depth(), 0);
+    assert_eq!(Expression::constant(1.0).depth(), 0);
+    assert_eq!(chain(3).depth(), 3);
+    assert_eq!((&chain(3) + &chain(5)).depth(), 6);
+    // no limit by default
+    assert_eq!(max_graph_depth(), usize::MAX);
+    assert!(chain(10_000).try_value().is_ok());
+    // beyond the limit, an error from `try_value`
+    set_max_graph_depth(100);
+    let deep = chain(101);
+    assert_eq!(deep.try_value().err(), Some(Error::GraphTooDeep { depth: 101, limit: 100 }));
+    before_update();
+    x_ref.assign(vec![0.25]);
+    // `value` is not checked
+    assert_eq!(deep.value().to_owned_values(), deep.values());
+    let shallow = chain(100);
+    assert_eq!(shallow.try_value().unwrap().to_owned_values(), shallow.values());
+    set_max_graph_depth(usize::MAX);
+    // the rebuilt graphs stay acyclic
+    let (y, _) = Expression::tensor(vec![0.5], true);
+    let f = &x.sin().cos() * &x;
+    let g = f.substitute(&x, &y);
+    assert_eq!(f.check_acyclic(), Ok(()));
+    assert_eq!(g.check_acyclic(), Ok(()));
+    // a back edge from the sin to the cos, injected in the search
+    let sin = x.sin();
+    let cos = sin.cos();
+    let (Expression::Tensor(sin), Expression::Tensor(cos)) = (sin, cos) else { unreachable!() };
+    let inputs = |tensor: &Tensor| {
+        let mut inputs: Vec<Tensor> = tensor.op().inputs().into_iter().filter_map(|input| match input {
+            Expression::Tensor(input) => Some(input.clone()),
+            Expression::Const(_) => None,
+        }).collect();
+        if *tensor == sin {
+            inputs.push(cos.clone());
+        }
+        inputs
+    };
+    let err = find_cycle(&cos, inputs).unwrap_err();
+    assert_eq!(err, Error::Cycle("Cos -> Sin -> Cos".to_owned()));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn grad_mask() {
+    use super::Error;
+    const LEN: usize = 8;
+    let (p, p_ref) = Expression::tensor(vec![2.0], true);
+    let (x, x_ref) = Expression::tensor(vec![1.0; LEN], true);
+    let y = &x * &p;
+    let Expression::Tensor(y_tensor) = &y else { unreachable!() };
+    // the odd points are "don't care"
+    let mask: Vec<bool> = (0..LEN).map(|i| i % 2 == 1).collect();
+    y_tensor.set_grad_mask(mask.clone());
+    let loss = y.sum();
+    assert_eq!(loss.value().to_owned_values(), vec![2.0 * LEN as f64]);
+    let grads = loss.backward();
+    assert_eq_vec!(grads.get(&p_ref).unwrap(), vec![(LEN / 2) as f64]);
+    let want: Vec<f64> = mask.iter().map(|&masked| if masked { 0.0 } else { 2.0 }).collect();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), want.clone());
+    // a mask on a leaf zeroes its own gradient
+    let Expression::Tensor(x_tensor) = &x else { unreachable!() };
+    y_tensor.clear_grad_mask();
+    x_tensor.set_grad_mask(mask.clone());
+    let grads = loss.backward();
+    assert_eq_vec!(grads.get(&p_ref).unwrap(), vec![LEN as f64]);
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), want);
+    x_tensor.clear_grad_mask();
+    // the lengths must match, at the set and again at the backward
+    assert_eq!(
+        y_tensor.try_set_grad_mask(vec![true; LEN + 1]),
+        Err(Error::LengthMismatch { expected: LEN, got: LEN + 1, op: "set_grad_mask".to_owned() })
+    );
+    y_tensor.set_grad_mask(mask);
+    before_update();
+    x_ref.assign(vec![1.0; LEN / 2]);
+    loss.value();
+    assert_eq!(
+        loss.try_backward().err(),
+        Some(Error::LengthMismatch { expected: LEN / 2, got: LEN, op: "grad mask".to_owned() })
+    );
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn weighted_sum() {
+    use super::profile::{self, Phase};
+    const LEN: usize = 1000;
+    let (err, err_ref) = Expression::tensor((0..LEN).map(|i| (i as f64 * 0.37).sin()).collect(), true);
+    let (w, w_ref) = Expression::tensor((0..LEN).map(|i| 1.0 + (i % 3) as f64).collect(), true);
+    let sqr = err.sqr();
+    for (fused, composed) in [
+        (sqr.weighted_sum(&w), sqr.mul(&w).sum()),
+        (sqr.weighted_mean(&w), sqr.mul(&w).mean()),
+    ] {
+        fused.assert_close(&composed, 1e-12, 0.0);
+        let (fused_grads, composed_grads) = (fused.backward(), composed.backward());
+        for grad_ref in [&err_ref, &w_ref] {
+            assert_eq_vec!(fused_grads.get(grad_ref).unwrap(), composed_grads.get(grad_ref).unwrap(), 1e-12);
+        }
+    }
+    // a no-grad weights tensor, and a broadcast constant weight
+    let (v, _) = Expression::tensor(vec![0.5; LEN], false);
+    assert_eq_vec!(sqr.weighted_sum(&v).values(), vec![0.5 * sqr.sum().values()[0]], 1e-12);
+    let grads = err.weighted_mean(&Expression::constant(2.0)).backward();
+    assert_eq_vec!(grads.get(&err_ref).unwrap(), vec![2.0 / LEN as f64; LEN]);
+    assert_eq!(Expression::constant(3.0).weighted_sum(&Expression::constant(2.0)).value(), 6.0);
+    assert!(err.try_weighted_sum(&Expression::tensor(vec![1.0; 3], false).0).is_err());
+    // one pass over the data, without an intermediate tensor
+    let f = sqr.weighted_sum(&w);
+    f.value();
+    profile::reset();
+    profile::enable();
+    before_update();
+    w_ref.assign(vec![1.0; LEN]);
+    f.value();
+    profile::disable();
+    let report = profile::report();
+    let forward: Vec<_> = report.entries.iter().filter(|e| e.phase == Phase::Forward && e.op != "Sqr").collect();
+    assert_eq!(forward.len(), 1, "{report}");
+    assert_eq!((forward[0].op.as_str(), forward[0].calls, forward[0].elements), ("WeightedSum", 1, 1));
+    assert_eq!(report.allocations, 1, "{report}");
+    profile::reset();
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn robust_loss() {
+    let delta = 0.5;
+    let huber = |d: f64| if d.abs() <= delta { 0.5 * d * d } else { delta * (d.abs() - 0.5 * delta) };
+    let log_cosh = |d: f64| d.cosh().ln();
+    let xs = vec![-3.0, -0.7, -0.2, 0.0, 0.1, 0.45, 2.0];
+    let targets = vec![0.3, -0.1, 0.4, 0.0, -0.2, 0.05, -1.0];
+    let (x, x_ref) = Expression::tensor(xs.clone(), true);
+    let (t, t_ref) = Expression::tensor(targets.clone(), true);
+    // the values, and the gradients to both the input and the target
+    for (loss, f) in [(x.huber(&t, delta), &huber as &dyn Fn(f64) -> f64), (x.log_cosh(&t), &log_cosh)] {
+        let want: Vec<f64> = xs.iter().zip(&targets).map(|(x, t)| f(x - t)).collect();
+        assert_eq_vec!(loss.values(), want, 1e-12);
+        let grads = loss.backward();
+        let want: Vec<f64> = xs.iter().zip(&targets).map(|(x, t)| finite_difference(f, x - t)).collect();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), want.clone(), 1e-6);
+        let want: Vec<f64> = want.iter().map(|g| -g).collect();
+        assert_eq_vec!(grads.get(&t_ref).unwrap(), want, 1e-6);
+    }
+    // continuous value and gradient at ±delta, with a constant target
+    for joint in [-delta, delta] {
+        let eps = 1e-9;
+        let (y, y_ref) = Expression::tensor(vec![joint - eps, joint, joint + eps], true);
+        let loss = y.huber(&Expression::constant(0.0), delta);
+        let values = loss.values();
+        assert!((values[0] - values[2]).abs() < 1e-8, "{values:?}");
+        assert_eq!(values[1], 0.5 * delta * delta);
+        let grads = loss.backward();
+        let grad = grads.get(&y_ref).unwrap();
+        assert!(grad.iter().all(|g| (g - joint).abs() < 1e-8), "{grad:?}");
+    }
+    // no overflow of cosh at large residuals
+    let (y, y_ref) = Expression::tensor(vec![1e4, -1e4, 1e300], true);
+    let loss = y.log_cosh(&Expression::constant(0.0));
+    assert_eq_vec!(loss.values(), vec![1e4 - std::f64::consts::LN_2, 1e4 - std::f64::consts::LN_2, 1e300], 1e-12);
+    let grads = loss.backward();
+    assert_eq_vec!(grads.get(&y_ref).unwrap(), vec![1.0, -1.0, 1.0]);
+    let huber_far = Expression::constant(1e4).huber(&Expression::constant(0.0), delta);
+    assert_eq!(huber_far.value(), delta * (1e4 - 0.5 * delta));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn ema() {
+    use super::{EmaState, EmaTracker};
+    // the tracker, read after each evaluation
+    let (x, x_ref) = Expression::tensor(vec![4.0], true);
+    let loss = x.sqr();
+    let mut tracker = EmaTracker::new(0.5);
+    assert_eq!(tracker.value(), None);
+    assert_eq!(tracker.observe(&loss), [16.0]);
+    for (x, want) in [(2.0, 10.0), (0.0, 5.0)] {
+        before_update();
+        x_ref.assign(vec![x]);
+        assert_eq!(tracker.observe(&loss), [want]);
+    }
+    assert_eq!((tracker.value(), tracker.count()), (Some(&[5.0][..]), 3));
+    assert_eq!(tracker.observe_values(&[1.0, 2.0]), [1.0, 2.0]);
+    tracker.reset();
+    assert_eq!((tracker.value(), tracker.count()), (None, 0));
+
+    // the node, stepped at the construction
+    let state = EmaState::new();
+    x_ref.assign(vec![1.0]);
+    let e = x.ema_of(0.5, &state);
+    assert_eq!(e.values(), [1.0]);
+    assert_eq!(state.values(), [1.0]);
+    // once per epoch, however many times it is evaluated
+    before_update();
+    x_ref.assign(vec![3.0]);
+    assert_eq!(e.values(), [2.0]);
+    assert_eq!(e.values(), [2.0]);
+    assert_eq!(state.values(), [2.0]);
+    // batched updates and several consumers, still one step
+    let (double, sin) = (&e * &Expression::constant(2.0), e.sin());
+    before_update();
+    x_ref.assign(vec![5.0]);
+    x_ref.assign(vec![3.0]);
+    assert_eq!(double.values(), [5.0]);
+    assert_eq!(sin.values(), [2.5_f64.sin()]);
+    assert_eq!(e.values(), [2.5]);
+    // keeps converging with an unchanged input
+    before_update();
+    assert_eq!(sin.values(), [2.75_f64.sin()]);
+    assert_eq!(state.values(), [2.75]);
+    // the recompute of a released node in the backward does not step it again
+    let f = e.sin().sum();
+    before_update();
+    f.forward_no_retain();
+    assert_eq!(state.values(), [2.875]);
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), vec![0.5 * 2.875_f64.cos()]);
+    assert_eq!(state.values(), [2.875]);
+    // forget the history
+    state.reset();
+    before_update();
+    assert_eq!(e.values(), [3.0]);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn parameter_snapshot() {
+    use super::ParameterSnapshot;
+    let (a, a_ref) = Expression::tensor(vec![0.3, -1.7, 2.2], true);
+    let (b, b_ref) = Expression::tensor(vec![0.9], true);
+    let (c, c_ref) = Expression::tensor(vec![1.1], true);
+    let f = (&(&a.sin() * &b).exp() + &c).sum();
+    let g = a.cos().sum();
+    let (f0, g0) = (f.values(), g.values());
+    let params = [a_ref.tensor().clone(), b_ref.tensor().clone(), c_ref.tensor().clone()];
+    let snapshot = ParameterSnapshot::capture(&params);
+    assert_eq!(snapshot.len(), 3);
+    // perturb, evaluate, restore
+    before_update();
+    a_ref.update(&[0.1, 0.2, -0.3]);
+    b_ref.assign(vec![0.5, 0.25]);
+    assert_ne!(g.values(), g0);
+    let perturbed = ParameterSnapshot::capture(&params);
+    let diff = snapshot.diff(&perturbed);
+    assert_eq!(diff.iter().map(|(i, _)| *i).collect::<Vec<_>>(), [0, 1, 2]);
+    assert!((diff[0].1 - 0.3).abs() < 1e-12 && diff[1].1 == f64::INFINITY && diff[2].1 == 0.0, "{diff:?}");
+    let c_version = params[2].version();
+    snapshot.restore();
+    // bit-identical, with the length restored
+    assert_eq!(b_ref.tensor().values().read().unwrap().len(), 1);
+    assert_eq!(f.values().iter().map(|x| x.to_bits()).collect::<Vec<_>>(), f0.iter().map(|x| x.to_bits()).collect::<Vec<_>>());
+    assert_eq!(g.values().iter().map(|x| x.to_bits()).collect::<Vec<_>>(), g0.iter().map(|x| x.to_bits()).collect::<Vec<_>>());
+    // the unchanged tensor is not marked
+    assert_eq!(params[2].version(), c_version);
+    assert!(snapshot.diff(&ParameterSnapshot::capture(&params)).iter().all(|(_, d)| *d == 0.0));
+    let Expression::Tensor(g_tensor) = &g else { unreachable!() };
+    let e = std::panic::catch_unwind(|| ParameterSnapshot::capture(std::slice::from_ref(g_tensor)));
+    assert!(e.is_err());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn eval_backward_many() {
+    use super::{backward_many, eval_many, profile::{self, Phase}};
+    use crate::expression::recompute::TEST_FORWARD_LOG;
+    let forward_log = || std::mem::take(&mut *TEST_FORWARD_LOG.lock().unwrap());
+    let (x, x_ref) = Expression::tensor(vec![0.5, 1.0, 1.5], true);
+    let (p, p_ref) = Expression::tensor(vec![0.3], true);
+    // the shared circuit, and 30 measurements of it
+    let shared = (&x * &p).exp();
+    let Expression::Tensor(shared_tensor) = &shared else { unreachable!() };
+    let outputs: Vec<Expression> = (0..30).map(|k| (&shared * &Expression::constant(k as f64 * 0.1)).sin().sum()).collect();
+    let refs: Vec<&Expression> = outputs.iter().collect();
+    let weights: Vec<f64> = (0..30).map(|k| 1.0 + (k % 4) as f64).collect();
+    _ = eval_many(&refs);
+    _ = forward_log();
+    before_update();
+    x_ref.assign(vec![0.7, 1.1, 1.3]);
+    let values = eval_many(&refs);
+    let log = forward_log();
+    assert_eq!(log.iter().filter(|id| **id == shared_tensor.key()).count(), 1);
+    // mul, exp, then mul, sin, sum of each output
+    assert_eq!(log.len(), 2 + 3 * 30);
+    for (value, output) in values.iter().zip(&outputs) {
+        assert_eq!(value, &output.values());
+    }
+    // the shared nodes are back-propagated once
+    profile::reset();
+    profile::enable();
+    let weighted: Vec<(&Expression, f64)> = refs.iter().copied().zip(weights.iter().copied()).collect();
+    let grads = backward_many(&weighted);
+    profile::disable();
+    let report = profile::report();
+    let backward = |op: &str| report.entries.iter().filter(|e| e.op == op && e.phase == Phase::Backward).map(|e| e.calls).sum::<usize>();
+    assert_eq!((backward("Exp"), backward("Sin")), (1, 30), "{report}");
+    profile::reset();
+    // the same as the explicit weighted sum
+    let sum = outputs.iter().zip(&weights).fold(Expression::constant(0.0), |acc, (output, w)| &acc + &(output * &Expression::constant(*w)));
+    let want = sum.backward();
+    for grad_ref in [&x_ref, &p_ref] {
+        assert_eq_vec!(grads.get(grad_ref).unwrap(), want.get(grad_ref).unwrap(), 1e-12);
+    }
+    assert!((grads.loss().unwrap() - sum.values()[0]).abs() < 1e-12);
+    // an output that is also an input of another one is seeded and back-propagated
+    let grads = backward_many(&[(&shared.sum(), 2.0), (&shared, 1.0), (&Expression::constant(5.0), 1.0)]);
+    let want = (&shared.sum() * &Expression::constant(3.0)).backward();
+    assert_eq_vec!(grads.get(&p_ref).unwrap(), want.get(&p_ref).unwrap(), 1e-12);
+    assert!((grads.loss().unwrap() - 5.0 - want.loss().unwrap()).abs() < 1e-12);
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn prune_dead_branch() {
+    use super::{prune_dead_branches, set_prune_dead_branches};
+    use crate::expression::recompute::TEST_FORWARD_LOG;
+    let forward_log = || std::mem::take(&mut *TEST_FORWARD_LOG.lock().unwrap());
+    let (c, c_ref) = Expression::tensor(vec![1.0; 3], false);
+    c.mark_logic();
+    let (x, x_ref) = Expression::tensor(vec![0.1, 0.2, 0.3], true);
+    let (y, y_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    // the instrumented expensive subtree of the dead branch
+    let expensive = x.exp().sin().exp();
+    let Expression::Tensor(expensive_tensor) = &expensive else { unreachable!() };
+    let f = c.cond(&y.sin(), &expensive);
+    let want = |c: &[f64], x: &[f64], y: &[f64]| -> Vec<f64> {
+        izip!(c, x, y).map(|(c, x, y)| c * y.sin() + (1.0 - c) * x.exp().sin().exp()).collect()
+    };
+    // the blend by default, `NaN` for an infinite dead branch
+    assert!(!prune_dead_branches());
+    let (inf, _) = Expression::tensor(vec![f64::INFINITY; 3], false);
+    let g = c.cond(&y, &inf);
+    assert!(g.values().iter().all(|v| v.is_nan()));
+    set_prune_dead_branches(true);
+    before_update();
+    y_ref.assign(vec![1.0, 2.0, 3.0]);
+    assert_eq_vec!(g.values(), vec![1.0, 2.0, 3.0]);
+    forward_log();
+    // the condition is all `1` and frozen: the dead branch is never evaluated
+    for step in 1..=3 {
+        let (xs, ys) = (vec![0.1 * step as f64; 3], vec![step as f64; 3]);
+        before_update();
+        x_ref.assign(xs.clone());
+        y_ref.assign(ys.clone());
+        assert_eq_vec!(f.values(), want(&[1.0; 3], &xs, &ys));
+        assert!(!forward_log().contains(&expensive_tensor.key()));
+    }
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&y_ref).unwrap(), vec![3.0_f64.cos(); 3]);
+    assert_eq_vec!(grads.get(&x_ref).unwrap(), vec![0.0; 3]);
+    // updating the condition evaluates both branches, with the update of `x` missed while pruned
+    before_update();
+    c_ref.assign(vec![1.0, 0.0, 1.0]);
+    assert_eq_vec!(f.values(), want(&[1.0, 0.0, 1.0], &[0.3; 3], &[3.0; 3]));
+    assert!(forward_log().contains(&expensive_tensor.key()));
+    // pruned again, then unfrozen
+    before_update();
+    c_ref.assign(vec![1.0; 3]);
+    x_ref.assign(vec![0.7; 3]);
+    assert_eq_vec!(f.values(), want(&[1.0; 3], &[0.3; 3], &[3.0; 3]));
+    assert!(!forward_log().contains(&expensive_tensor.key()));
+    before_update();
+    c_ref.set_requires_grad(true);
+    assert_eq_vec!(f.values(), want(&[1.0; 3], &[0.7; 3], &[3.0; 3]));
+    assert!(forward_log().contains(&expensive_tensor.key()));
+    let grads = f.backward();
+    assert_eq_vec!(grads.get(&c_ref).unwrap(), vec![3.0_f64.sin() - 0.7_f64.exp().sin().exp(); 3]);
+    // a skipped subtree read directly is up to date
+    c_ref.set_requires_grad(false);
+    before_update();
+    x_ref.assign(vec![0.5; 3]);
+    f.values();
+    assert!(!forward_log().contains(&expensive_tensor.key()));
+    before_update();
+    assert_eq_vec!(expensive.values(), vec![0.5_f64.exp().sin().exp(); 3]);
+    // disabled, both branches are evaluated
+    set_prune_dead_branches(false);
+    before_update();
+    x_ref.assign(vec![0.6; 3]);
+    assert_eq_vec!(f.values(), want(&[1.0; 3], &[0.6; 3], &[3.0; 3]));
+    assert!(forward_log().contains(&expensive_tensor.key()));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn lazy_build() {
+    use super::{is_lazy_build, lazy_build};
+    use std::sync::{atomic::{AtomicUsize, Ordering::Relaxed}, Arc};
+    // the instrumented element-wise math
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+    const LEN: usize = 64;
+    const LAYERS: usize = 100;
+    let (x, x_ref) = Expression::tensor((0..LEN).map(|i| 0.1 * i as f64).collect(), true);
+    let (w, w_ref) = Expression::tensor(vec![0.9], true);
+    let build = || {
+        let mut y = x.clone();
+        for i in 0..LAYERS {
+            let counted = y.map_custom(
+                Arc::new(|x| { CALLS.fetch_add(1, Relaxed); x.tanh() }),
+                Arc::new(|_, res, grad| grad * (1.0 - res * res)),
+                "counted_tanh",
+            );
+            let shifted = counted.mul(&w).add(&Expression::Const(0.01 * i as f64));
+            y = shifted.ge(&Expression::Const(0.5)).cond(&shifted, &shifted.sin());
+        }
+        y
+    };
+    CALLS.store(0, Relaxed);
+    let eager = build();
+    assert_eq!(CALLS.load(Relaxed), LEN * LAYERS);
+    // no element-wise math at the construction, only the lengths
+    CALLS.store(0, Relaxed);
+    let lazy = lazy_build(|| {
+        assert!(is_lazy_build());
+        build()
+    });
+    assert!(!is_lazy_build());
+    assert_eq!(CALLS.load(Relaxed), 0);
+    let stats = lazy.memory_stats();
+    assert_eq!(stats.released_nodes, stats.nodes - 2);
+    assert_eq!(stats.retained_bytes, (LEN + 1) * size_of::<f64>());
+    // the first evaluation matches the eager construction exactly
+    assert_eq!(lazy.values(), eager.values());
+    assert_eq!(CALLS.load(Relaxed), LEN * LAYERS);
+    let (eager_grads, lazy_grads) = (eager.backward(), lazy.backward());
+    assert_eq!(lazy_grads.get(&x_ref).unwrap().0, eager_grads.get(&x_ref).unwrap().0);
+    assert_eq!(lazy_grads.get(&w_ref).unwrap().0, eager_grads.get(&w_ref).unwrap().0);
+    before_update();
+    w_ref.assign(vec![1.1]);
+    assert_eq!(lazy.values(), eager.values());
+    // the other ops compute their lazy inputs at the construction
+    let sum = lazy_build(|| build().sum());
+    assert_eq!(sum.values(), eager.sum().values());
+    // the lengths are still checked
+    let (short, _) = Expression::tensor(vec![1.0; LEN - 1], false);
+    assert!(lazy_build(|| x.sin().try_add(&short)).is_err());
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn tensor_stats() {
+    use super::TensorStats;
+    let (x, x_ref) = Expression::tensor(vec![3.0, -4.0, f64::NAN, f64::INFINITY, 1.0, f64::NAN], false);
+    let y = x.mul(&Expression::Const(2.0));
+    let Expression::Tensor(y_tensor) = &y else { unreachable!() };
+    let x_tensor = x_ref.tensor();
+    let stats = x_tensor.stats();
+    assert_eq!(stats, TensorStats { min: -4.0, max: 3.0, mean: 0.0, l2: 26.0_f64.sqrt(), n_nan: 2, n_inf: 1 });
+    assert_eq!(y_tensor.stats().n_nan, 2);
+    assert_eq!(x_tensor.count_where(|x| x > 0.0), 3);
+    assert_eq!(x_tensor.count_where(f64::is_nan), 2);
+    // cached by version: a write without `mark_changed` is not seen
+    x_tensor.values().write().unwrap()[1] = 10.0;
+    assert_eq!(x_tensor.stats(), stats);
+    before_update();
+    x_tensor.mark_changed();
+    assert_eq!(x_tensor.stats().max, 10.0);
+    // invalidated by the updates and the recomputes
+    before_update();
+    x_ref.assign(vec![-1.0, 2.0]);
+    let stats = x_tensor.stats();
+    assert_eq!((stats.min, stats.max, stats.mean, stats.n_nan), (-1.0, 2.0, 0.5, 0));
+    assert_eq!(y_tensor.stats().n_nan, 2);
+    y.value();
+    assert_eq!(y_tensor.stats(), TensorStats { min: -2.0, max: 4.0, mean: 1.0, l2: 20.0_f64.sqrt(), n_nan: 0, n_inf: 0 });
+    // without any finite value
+    let (z, _) = Expression::tensor(vec![f64::NAN, f64::NEG_INFINITY], false);
+    let Expression::Tensor(z_tensor) = &z else { unreachable!() };
+    let stats = z_tensor.stats();
+    assert!(stats.min.is_nan() && stats.max.is_nan() && stats.mean.is_nan());
+    assert_eq!((stats.l2, stats.n_nan, stats.n_inf), (0.0, 1, 1));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn io_tables() {
+    use super::{io, Error};
+    let (x, _) = Expression::tensor(vec![0.0, 0.5, 1.0], false);
+    let y = x.mul(&Expression::Const(2.0)).sub(&Expression::Const(0.25));
+    let (z, _) = Expression::tensor(vec![f64::NAN, f64::INFINITY, -f64::INFINITY], false);
+    let columns = [("x", &x), ("y, \"2x\"", &y), ("vdd", &Expression::Const(1.8)), ("z", &z)];
+    let mut csv = Vec::new();
+    io::write_csv_to(&mut csv, &columns).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "x,\"y, \"\"2x\"\"\",vdd,z\n0.0,-0.25,1.8,NaN\n0.5,0.75,1.8,inf\n1.0,1.75,1.8,-inf\n",
+    );
+    // round trip, bit by bit
+    let mut raw = Vec::new();
+    io::write_raw_to(&mut raw, &columns).unwrap();
+    let table = io::read_raw_from(raw.as_slice()).unwrap();
+    let names: Vec<_> = table.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["x", "y, \"2x\"", "vdd", "z"]);
+    for ((_, values), (_, expr)) in table.iter().zip(&columns) {
+        let expected = match expr {
+            Expression::Const(c) => vec![*c; 3],
+            _ => expr.values(),
+        };
+        assert_eq!(values.iter().map(|x| x.to_bits()).collect::<Vec<_>>(), expected.iter().map(|x| x.to_bits()).collect::<Vec<_>>());
+    }
+    // constants only is one row, no column is no row
+    let mut csv = Vec::new();
+    io::write_csv_to(&mut csv, &[("a", &Expression::Const(1.0))]).unwrap();
+    assert_eq!(csv, b"a\n1.0\n");
+    let mut raw = Vec::new();
+    io::write_raw_to(&mut raw, &[]).unwrap();
+    assert_eq!(io::read_raw_from(raw.as_slice()).unwrap(), vec![]);
+    // length mismatch, a length-1 tensor is not broadcast
+    let (w, _) = Expression::tensor(vec![1.0], false);
+    assert_eq!(
+        io::write_csv_to(Vec::new(), &[("x", &x), ("w", &w)]),
+        Err(Error::LengthMismatch { expected: 3, got: 1, op: "column `w`".to_owned() }),
+    );
+    // malformed tables
+    let mut raw = Vec::new();
+    io::write_raw_to(&mut raw, &columns).unwrap();
+    let invalid = |bytes: &[u8]| matches!(io::read_raw_from(bytes), Err(Error::InvalidRaw(_)));
+    assert!(invalid(b""));
+    assert!(invalid(b"GRAX\x01\0\0\0"));
+    let mut bad_version = raw.clone();
+    bad_version[4] = 2;
+    assert!(invalid(&bad_version));
+    assert!(invalid(&raw[..raw.len() - 1]));
+    assert!(invalid(&[raw.as_slice(), &[0]].concat()));
+    let mut huge_rows = raw.clone();
+    huge_rows[12..20].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(invalid(&huge_rows));
+    let mut bad_name = raw.clone();
+    bad_name[24] = 0xff;
+    assert!(invalid(&bad_name));
+    // through the files
+    let dir = std::env::temp_dir();
+    let (csv_path, raw_path) = (dir.join(format!("gspice-io-{}.csv", std::process::id())), dir.join(format!("gspice-io-{}.raw", std::process::id())));
+    io::write_csv(&csv_path, &columns).unwrap();
+    io::write_raw(&raw_path, &columns).unwrap();
+    assert!(std::fs::read_to_string(&csv_path).unwrap().starts_with("x,"));
+    assert_eq!(io::read_raw(&raw_path).unwrap().len(), 4);
+    std::fs::remove_file(csv_path).unwrap();
+    std::fs::remove_file(&raw_path).unwrap();
+    assert!(matches!(io::read_raw(&raw_path), Err(Error::Io(_))));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn optimizer_checkpoint() {
+    use super::{optimizer::{Adam, Checkpoint, Optimizer, ParameterMap}, Bound, Error};
+    use std::collections::HashMap;
+    // built again from scratch on resume, as after a restart
+    let build = || {
+        let (x, x_ref) = Expression::tensor(vec![0.0, -1.0, 5.0], true);
+        let (y, y_ref) = Expression::tensor(vec![2.0], true);
+        let loss = x.mul(&y).sub(&Expression::constant(3.0)).sqr().sum().add(&y.sqr().sum());
+        let params: ParameterMap = [("x", x_ref.clone()), ("y", y_ref.clone())].into_iter().collect();
+        (loss, Adam::new(vec![x_ref, y_ref], 0.05), params)
+    };
+    let step = |loss: &Expression, adam: &mut Adam| {
+        let l = loss.value().to_tensor().unwrap()[0];
+        adam.step(&loss.backward());
+        l
+    };
+    let (loss, mut adam, params) = build();
+    for _ in 0..5 {
+        step(&loss, &mut adam);
+    }
+    let extra = HashMap::from([("best".to_owned(), 0.5)]);
+    let mut file = Vec::new();
+    Checkpoint::save_to(&mut file, &adam, &params, 5, &extra).unwrap();
+    let saved_y = params.get("y").unwrap().tensor().read().clone();
+    let uninterrupted: Vec<f64> = (0..10).map(|_| step(&loss, &mut adam)).collect();
+    // resume
+    let (loss, mut adam, params) = build();
+    let checkpoint = Checkpoint::load_from(file.as_slice()).unwrap();
+    assert_eq!((checkpoint.iter(), checkpoint.extra()), (5, &extra));
+    checkpoint.restore(&mut adam, &params, true).unwrap();
+    let resumed: Vec<f64> = (0..10).map(|_| step(&loss, &mut adam)).collect();
+    assert_eq!(resumed, uninterrupted);
+    // mismatches, nothing restored when strict
+    let (z, z_ref) = Expression::tensor(vec![1.0; 2], true);
+    let (x, x_ref) = Expression::tensor(vec![0.0; 4], true);
+    let mut other = Adam::new(vec![x_ref.clone(), z_ref.clone()], 0.05);
+    let others: ParameterMap = [("x", x_ref), ("z", z_ref)].into_iter().collect();
+    assert_eq!(
+        checkpoint.restore(&mut other, &others, true),
+        Err(Error::CheckpointMismatch { missing: vec!["z".to_owned()], extra: vec!["y".to_owned()], len_changed: vec![("x".to_owned(), 3, 4)] }),
+    );
+    // partial, only the matching parameters
+    let (y, y_ref) = Expression::tensor(vec![0.0], true);
+    let partial: ParameterMap = [("y", y_ref), ("z", others.get("z").unwrap().clone())].into_iter().collect();
+    checkpoint.restore(&mut other, &partial, false).unwrap();
+    assert_eq!(x.values(), vec![0.0; 4]);
+    assert_eq!(z.values(), vec![1.0; 2]);
+    assert_eq!(y.values(), saved_y);
+    // a saved value out of the strict bounds, nothing restored
+    let (x, x_ref) = Expression::tensor(vec![9.0; 3], true);
+    let (y, y_ref) = Expression::tensor(vec![-100.0], true);
+    y_ref.set_bounds(Bound::Unbounded, Bound::Scalar(saved_y[0] - 1.0));
+    y_ref.set_strict_bounds(true);
+    let mut bounded = Adam::new(vec![x_ref.clone(), y_ref.clone()], 0.05);
+    let bounded_params: ParameterMap = [("x", x_ref), ("y", y_ref)].into_iter().collect();
+    assert_eq!(checkpoint.restore(&mut bounded, &bounded_params, true), Err(Error::OutOfBounds { index: 0, op: "restore".to_owned() }));
+    assert_eq!((x.values(), y.values()), (vec![9.0; 3], vec![-100.0]));
+    // malformed checkpoints
+    let invalid = |bytes: &[u8]| matches!(Checkpoint::load_from(bytes), Err(Error::InvalidCheckpoint(_)));
+    assert!(invalid(b""));
+    assert!(invalid(&[b"GSNP", &file[4..]].concat()));
+    let mut bad_version = file.clone();
+    bad_version[4] = 2;
+    assert!(invalid(&bad_version));
+    assert!(invalid(&file[..file.len() - 1]));
+    // through a file
+    let path = std::env::temp_dir().join(format!("gspice-checkpoint-{}.ckpt", std::process::id()));
+    Checkpoint::save(&path, &adam, &params, 15, &HashMap::new()).unwrap();
+    assert_eq!(Checkpoint::load(&path).unwrap().iter(), 15);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(Checkpoint::load(&path), Err(Error::Io(_))));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn integrate() {
+    use super::{Error, Quadrature};
+    let integral = |expr: &Expression| expr.value().to_tensor().unwrap()[0];
+    // denser near the start
+    let grid = |n: usize, end: f64| -> Vec<f64> { (0..n).map(|i| end * (i as f64 / (n - 1) as f64).powf(1.5)).collect() };
+    for n in [101, 100] {
+        let times = grid(n, std::f64::consts::PI);
+        let (x, _) = Expression::tensor(times.iter().map(|t| t.sin()).collect(), false);
+        assert_eq_vec!([integral(&x.integrate(&times, Quadrature::Trapezoid))], [2.0], 1e-3);
+        assert_eq_vec!([integral(&x.integrate(&times, Quadrature::Simpson))], [2.0], 1e-6);
+        // ∫₀² 2t + 1 = 6, ∫₀² 3t² - 2t + 1 = 6
+        let times = grid(n, 2.0);
+        let (linear, _) = Expression::tensor(times.iter().map(|t| 2.0 * t + 1.0).collect(), false);
+        let (quadratic, _) = Expression::tensor(times.iter().map(|t| 3.0 * t * t - 2.0 * t + 1.0).collect(), false);
+        assert_eq_vec!([integral(&linear.integrate(&times, Quadrature::Trapezoid))], [6.0], 1e-12);
+        assert_eq_vec!([integral(&linear.integrate(&times, Quadrature::Simpson))], [6.0], 1e-12);
+        if n % 2 == 1 {
+            assert_eq_vec!([integral(&quadratic.integrate(&times, Quadrature::Simpson))], [6.0], 1e-12);
+        }
+    }
+    // an even count ends with a trapezoid
+    let times = [0.0, 0.1, 0.3, 0.4, 0.8, 1.0];
+    let samples = [1.0, -2.0, 0.5, 3.0, 2.0, -1.0];
+    let (x, x_ref) = Expression::tensor(samples.to_vec(), true);
+    let (head, _) = Expression::tensor(samples[..5].to_vec(), false);
+    assert_eq_vec!(
+        [integral(&x.integrate(&times, Quadrature::Simpson))],
+        [integral(&head.integrate(&times[..5], Quadrature::Simpson)) + 0.5 * 0.2 * (2.0 - 1.0)],
+        1e-12
+    );
+    // the gradient of `∫ x²` by the finite differences
+    for rule in [Quadrature::Trapezoid, Quadrature::Simpson] {
+        let y = x.sqr().integrate(&times, rule);
+        let grads = y.backward();
+        let want: Vec<f64> = (0..samples.len()).map(|j| finite_difference(|v| {
+            let mut samples = samples.to_vec();
+            samples[j] = v;
+            integral(&Expression::tensor(samples, false).0.sqr().integrate(&times, rule))
+        }, samples[j])).collect();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), want, 1e-6);
+    }
+    // recompute
+    let y = x.integrate(&times, Quadrature::Trapezoid);
+    before_update();
+    x_ref.assign(vec![2.0; 6]);
+    assert_eq_vec!([integral(&y)], [2.0], 1e-12);
+    // a constant over the whole grid
+    assert_eq!(Expression::constant(3.0).integrate(&times, Quadrature::Simpson).scalar(), Some(3.0));
+    assert!(matches!(x.try_integrate(&[0.0, 0.2, 0.1, 0.3, 0.4, 0.5], Quadrature::Trapezoid), Err(Error::InvalidGrid(_))));
+    assert!(matches!(x.try_integrate(&[0.0], Quadrature::Trapezoid), Err(Error::InvalidGrid(_))));
+    assert!(matches!(x.try_integrate(&[0.0, f64::NAN], Quadrature::Trapezoid), Err(Error::InvalidGrid(_))));
+    assert!(matches!(x.try_integrate(&times[..4], Quadrature::Simpson), Err(Error::LengthMismatch { expected: 4, got: 6, .. })));
+}
+
+#[test]
+#[serial]
+#[rustfmt::skip]
+fn window_reduce() {
+    use super::{Error, WindowKind, WindowTail};
+    let values = |expr: &Expression| expr.value().to_tensor().unwrap();
+    let samples = vec![1.0, 3.0, 2.0, 5.0, 4.0, 0.0, 6.0, 1.0, 2.0, 7.0];
+    let (x, x_ref) = Expression::tensor(samples.clone(), true);
+    assert_eq!(values(&x.window_reduce(3, 3, WindowKind::Max, WindowTail::Drop)), [3.0, 5.0, 6.0]);
+    assert_eq!(values(&x.window_reduce(3, 3, WindowKind::Max, WindowTail::Keep)), [3.0, 5.0, 6.0, 7.0]);
+    assert_eq!(values(&x.window_reduce(3, 3, WindowKind::Min, WindowTail::Keep)), [1.0, 0.0, 1.0, 7.0]);
+    assert_eq_vec!(values(&x.window_reduce(4, 4, WindowKind::Mean, WindowTail::Keep)), [2.75, 2.75, 4.5], 1e-12);
+    // overlapping, the last full window reaches the end, nothing to keep
+    assert_eq!(values(&x.window_reduce(4, 2, WindowKind::Max, WindowTail::Keep)), [5.0, 5.0, 6.0, 7.0]);
+    // skipping samples, the tail is the single sample 9
+    assert_eq!(values(&x.window_reduce(2, 3, WindowKind::Min, WindowTail::Keep)), [1.0, 4.0, 1.0, 7.0]);
+    // the full windows against the brute force
+    let waveform: Vec<f64> = (0..200).map(|i| (i as f64 * 0.37).sin() + (i as f64 * 0.05).cos()).collect();
+    let (w, _) = Expression::tensor(waveform.clone(), false);
+    for (window, stride) in [(1, 1), (5, 1), (7, 3), (16, 16), (3, 8), (200, 1)] {
+        let windows: Vec<&[f64]> = (0..).step_by(stride).take_while(|start| start + window <= waveform.len()).map(|start| &waveform[start..start + window]).collect();
+        let max: Vec<f64> = windows.iter().map(|w| w.iter().copied().fold(f64::NEG_INFINITY, f64::max)).collect();
+        let min: Vec<f64> = windows.iter().map(|w| w.iter().copied().fold(f64::INFINITY, f64::min)).collect();
+        let mean: Vec<f64> = windows.iter().map(|w| w.iter().sum::<f64>() / window as f64).collect();
+        assert_eq!(values(&w.window_reduce(window, stride, WindowKind::Max, WindowTail::Drop)), max);
+        assert_eq!(values(&w.window_reduce(window, stride, WindowKind::Min, WindowTail::Drop)), min);
+        assert_eq_vec!(values(&w.window_reduce(window, stride, WindowKind::Mean, WindowTail::Drop)), mean, 1e-12);
+    }
+    // the first of the tied extrema gets the gradient
+    let (t, t_ref) = Expression::tensor(vec![2.0, 2.0, 1.0, 3.0, 3.0, 0.0], true);
+    let grads = t.window_reduce(3, 1, WindowKind::Max, WindowTail::Drop).sum().backward();
+    assert_grad!(grads.get(&t_ref), vec![1.0, 0.0, 0.0, 3.0, 0.0, 0.0]);
+    let grads = t.window_reduce(3, 1, WindowKind::Min, WindowTail::Drop).sum().backward();
+    assert_grad!(grads.get(&t_ref), vec![0.0, 0.0, 3.0, 0.0, 0.0, 1.0]);
+    let grads = t.window_reduce(3, 2, WindowKind::Mean, WindowTail::Keep).sum().backward();
+    assert_eq_vec!(grads.get(&t_ref).unwrap(), [1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 1.0 / 3.0, 5.0 / 6.0, 0.5], 1e-12);
+    // by the finite differences, without ties
+    for kind in [WindowKind::Max, WindowKind::Min, WindowKind::Mean] {
+        let f = |samples: Vec<f64>| values(&Expression::tensor(samples, false).0.sqr().window_reduce(4, 3, kind, WindowTail::Keep).sum())[0];
+        let grads = x.sqr().window_reduce(4, 3, kind, WindowTail::Keep).sum().backward();
+        let want: Vec<f64> = (0..samples.len()).map(|j| finite_difference(|v| {
+            let mut samples = samples.clone();
+            samples[j] = v;
+            f(samples)
+        }, samples[j])).collect();
+        assert_eq_vec!(grads.get(&x_ref).unwrap(), want, 1e-6);
+    }
+    // the length follows the input
+    let y = x.window_reduce(3, 3, WindowKind::Max, WindowTail::Drop);
+    before_update();
+    x_ref.assign(vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    assert_eq!(values(&y), [2.0, 5.0]);
+    assert_eq!(Expression::constant(2.0).window_reduce(3, 1, WindowKind::Mean, WindowTail::Drop).scalar(), Some(2.0));
+    assert!(matches!(x.try_window_reduce(0, 1, WindowKind::Max, WindowTail::Drop), Err(Error::InvalidWindow(_))));
+    assert!(matches!(x.try_window_reduce(2, 0, WindowKind::Max, WindowTail::Drop), Err(Error::InvalidWindow(_))));
+    assert_eq!(x.try_window_reduce(8, 1, WindowKind::Max, WindowTail::Drop).unwrap_err(), Error::EmptyTensor);
+    assert_eq!(values(&x.window_reduce(8, 1, WindowKind::Max, WindowTail::Keep)), [6.0]);
+}
+
+#[test]
+#[serial]
+fn anomaly_detection() {
+    use super::{set_anomaly_detection, take_anomaly_report};
+    _ = take_anomaly_report();
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let f = x.log().mul(&Expression::constant(2.0));
+    assert!(take_anomaly_report().is_none());
+
+    set_anomaly_detection(true);
+    before_update();
+    x_ref.assign(vec![1.0, -2.0, 3.0]);
+    f.value();
+    set_anomaly_detection(false);
+    let report = take_anomaly_report().expect("no anomaly detected");
+    assert_eq!(report.op, "Log");
+    assert_eq!(report.index, 1);
+    assert!(report.value.is_nan());
+    assert_eq_vec!(&report.inputs, &[-2.0]);
+    assert!(
+        report.subtree.starts_with("Log(Tensor"),
+        "{}",
+        report.subtree
+    );
+    // only the first detection is recorded
+    assert!(take_anomaly_report().is_none());
+
+    // disabled
+    before_update();
+    x_ref.assign(vec![-1.0, -2.0, 3.0]);
+    f.value();
+    _ = x.sqrt();
+    assert!(take_anomaly_report().is_none());
+
+    // a broadcast length-1 input is reported at index 0
+    let (y, y_ref) = Expression::tensor(vec![1.0], true);
+    let g = y.div(&x);
+    set_anomaly_detection(true);
+    before_update();
+    x_ref.assign(vec![1.0, 0.0, 3.0]);
+    y_ref.assign(vec![2.0]);
+    g.value();
+    set_anomaly_detection(false);
+    let report = take_anomaly_report().expect("no anomaly detected");
+    assert_eq!(report.index, 1);
+    assert_eq_vec!(&report.inputs, &[2.0, 0.0]);
+}
+
+#[test]
+#[serial]
+fn fallible_api() {
+    use super::{BinaryOp, Error};
+    // LengthMismatch
+    let (x, x_ref) = Expression::tensor(vec![1.0, 2.0, 3.0], true);
+    let (y, _) = Expression::tensor(vec![1.0, 2.0], true);
+    assert_eq!(
+        x.try_add(&y).unwrap_err(),
+        Error::LengthMismatch {
+            expected: 3,
+            got: 2,
+            op: "Add".to_owned()
+        }
+    );
+    assert!(x.try_add(&Expression::constant(1.0)).is_ok());
+    assert!(matches!(
+        x_ref.try_update(&[1.0]),
+        Err(Error::LengthMismatch {
+            expected: 3,
+            got: 1,
+            ..
+        })
+    ));
+    assert_tensor!(&x, vec![1.0, 2.0, 3.0]);
+
+    // EmptyTensor
+    let (empty, _) = Expression::tensor(vec![], true);
+    assert_eq!(empty.sin().try_backward().unwrap_err(), Error::EmptyTensor);
+
+    // NonDifferentiable
+    assert_eq!(
+        x.ceil().add(&x).try_backward().unwrap_err(),
+        Error::NonDifferentiable {
+            op: "Ceil".to_owned()
+        }
+    );
+    assert!(x.sin().add(&x).try_backward().is_ok());
+    // the ceil nodes without gradient to propagate are accepted
+    let (frozen, _) = Expression::tensor(vec![1.5, 2.5, 3.5], false);
+    assert!(frozen.ceil().add(&x).try_backward().is_ok());
+    assert!(x
+        .ceil()
+        .mul(&Expression::constant(0.0))
+        .add(&x)
+        .try_backward()
+        .is_ok());
+    assert_eq!(
+        Expression::constant(2.0)
+            .try_binary_op(&x, BinaryOp::Sub)
+            .unwrap()
+            .try_backward()
+            .unwrap()
+            .get(&x_ref)
+            .unwrap()
+            .0,
+        vec![-1.0; 3]
+    );
+
+    // PoisonedLock, poisoned by another thread
+    let f = x.mul(&Expression::constant(2.0));
+    let x_clone = x.clone();
+    let result = std::thread::spawn(move || {
+        std::panic::catch_unwind(|| {
+            if let Expression::Tensor(tensor) = &x_clone {
+                let _write = tensor.values().write().unwrap();
+                panic!("poison the lock");
+            }
+        })
+    })
+    .join()
+    .unwrap();
+    assert!(result.is_err());
+    // the infallible API recovers the data
+    assert_eq_vec!(x.value().to_tensor().unwrap(), vec![1.0, 2.0, 3.0]);
+    let grads = f.backward();
+    assert_grad!(grads.get(&x_ref), vec![2.0, 2.0, 2.0]);
+    // the fallible API reports the poison once
+    assert_eq!(f.try_backward().unwrap_err(), Error::PoisonedLock);
+    assert!(f.try_backward().is_ok());
+    if let Expression::Tensor(tensor) = &x {
+        _ = std::panic::catch_unwind(|| {
+            let _write = tensor.values().write().unwrap();
+            panic!("poison the lock");
+        });
+    }
+    assert_eq!(x_ref.try_update(&[1.0, 1.0, 1.0]), Err(Error::PoisonedLock));
+    before_update();
+    assert_eq!(x_ref.try_update(&[1.0, 1.0, 1.0]), Ok(()));
+    assert_eq_vec!(f.value().to_tensor().unwrap(), vec![4.0, 6.0, 8.0]);
+}
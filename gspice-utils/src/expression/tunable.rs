@@ -0,0 +1,112 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::{
+    op::{MaxMethod, Reduce, RobustLoss, Transition},
+    Error, Expression, Op, TensorRef,
+};
+
+/// Handle of a [tunable constant](Expression::tunable_constant)
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ConstHandle(TensorRef);
+
+impl ConstHandle {
+    /// Need [`before_update`](super::before_update) before calling this
+    ///
+    /// Need [`Expression::value`] after calling this
+    #[inline]
+    pub fn set(&self, value: f64) {
+        self.0.assign(vec![value]);
+    }
+    #[inline]
+    pub fn get(&self) -> f64 {
+        self.0 .0.read()[0]
+    }
+    /// The underlying length-1 tensor
+    #[inline]
+    pub fn tensor_ref(&self) -> &TensorRef {
+        &self.0
+    }
+}
+
+impl Op {
+    /// The scalar parameters held by the op itself rather than as inputs,
+    /// they are fixed at build time
+    pub(super) fn captured_consts(&self) -> Vec<f64> {
+        match self {
+            Op::Powf(_, n) => vec![*n],
+            Op::Diode(_, diode) => diode.params().to_vec(),
+            Op::Transition(_, Transition::SmoothStepRange { x0, x1 }) => vec![*x0, *x1],
+            Op::Transition(_, Transition::GaussPulse { center, sigma }) => vec![*center, *sigma],
+            Op::UnaryParam(_, param, _) => vec![*param],
+            Op::DivEps(_, _, eps) => vec![*eps],
+            Op::RobustLoss(_, _, RobustLoss::Huber(delta)) => vec![*delta],
+            Op::Shift(_, _, fill) => vec![*fill],
+            Op::Ddt(_, dt) => vec![*dt],
+            Op::Ema(_, alpha, _) => vec![*alpha],
+            Op::Idt(_, dt, initial) => vec![*dt, *initial],
+            Op::CrossTime(_, cross) => vec![cross.threshold],
+            Op::Reduce(_, Reduce::Max(MaxMethod::Smooth(temperature))) => vec![*temperature],
+            Op::CountGe(_, count) => vec![count.threshold],
+            Op::SoftHistogram(_, histogram) => vec![histogram.sigma],
+            Op::Reduce(_, Reduce::Quantile { q, bandwidth }) => {
+                [*q].into_iter().chain(*bandwidth).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Expression {
+    /// A constant that can be changed later without rebuilding the graph,
+    /// e.g., the nominal temperature to be swept
+    ///
+    /// A length-1 tensor without gradient, broadcasted like a constant
+    /// and recomputed through like any other leaf
+    #[inline]
+    pub fn tunable_constant(value: f64) -> (Self, ConstHandle) {
+        let (expr, tensor_ref) = Self::tensor(vec![value], false);
+        (expr, ConstHandle(tensor_ref))
+    }
+    /// Rebuild the graph with every constant input equal to `value`
+    /// replaced by a [tunable constant](Expression::tunable_constant)
+    ///
+    /// [`Error::CapturedConst`] when any op holds `value` as its own parameter
+    /// (e.g., the exponent of [`powf`](Expression::powf)), which cannot be retrofitted,
+    /// keyed by the path from the root as [`Expression::diff`]
+    pub fn promote_const_to_parameter(&self, value: f64) -> Result<(Self, ConstHandle), Error> {
+        let mut captured = BTreeMap::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![("$".to_owned(), self)];
+        while let Some((path, expr)) = stack.pop() {
+            let Self::Tensor(tensor) = expr else {
+                continue;
+            };
+            if !visited.insert(tensor.key()) {
+                continue;
+            }
+            if tensor.op().captured_consts().contains(&value) {
+                captured.insert(path.clone(), tensor.op().name());
+            }
+            stack.extend(
+                tensor
+                    .op()
+                    .inputs()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, input)| (format!("{path}.{i}"), input)),
+            );
+        }
+        if !captured.is_empty() {
+            return Err(Error::CapturedConst {
+                value: value.to_string(),
+                ops: captured,
+            });
+        }
+        let (tunable, handle) = Self::tunable_constant(value);
+        let promoted = self.rebuild(&|expr| match expr {
+            Self::Const(x) if *x == value => Some(tunable.clone()),
+            _ => None,
+        });
+        Ok((promoted, handle))
+    }
+}
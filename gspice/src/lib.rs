@@ -1,4 +1,30 @@
+pub mod prelude;
+
+/// The expression graph, defined once in `gspice-utils`, its main items are also re-exported here
 pub use gspice_utils::expression;
+pub use gspice_utils::expression::{
+    accuracy_mode, assert_policy, backward_many, before_update, eval_many, is_deterministic,
+    is_lazy_build, lazy_build, max_graph_depth, pairwise_limit, prune_dead_branches,
+    register_unary, set_accuracy_mode, set_anomaly_detection, set_assert_policy, set_deterministic,
+    set_max_graph_depth, set_pairwise_limit, set_prune_dead_branches, set_smooth_forward,
+    smooth_forward, take_anomaly_report, take_assert_violation, try_backward_many, AccuracyMode,
+    AnomalyReport, AssertPolicy, BinaryOp, Bound, CachedExpression, ComplexExpr, ConstHandle,
+    ConvPadding, CustomBinary, CustomBinaryBackward, CustomBinaryForward, CustomOp, CustomUnary,
+    CustomUnaryBackward, CustomUnaryForward, DiscreteBinaryOp, Edge, EmaState, EmaTracker, Error,
+    Expression, Grad, GradId, GradMethod, GradStore, GraphBuilder, GraphDiff, LogicNary,
+    LossBuilder, MaxMethod, MemoryStats, NodeDiff, Op, Pwl, PwlExtrapolation, Quadrature,
+    Retention, RobustLoss, ScalarTensor, SharpnessHandle, SmoothForward, SnapshotDiff,
+    SnapshotMismatch, Spline, SplineBoundary, Tensor, TensorRef, TensorStats, TiePolicy, Tolerance,
+    UnaryId, UnaryOp, Weighted, WindowKind, WindowTail,
+};
+
+pub use gspice_utils::expression::io;
+pub use gspice_utils::expression::optimizer as optim;
+pub use gspice_utils::expression::optimizer::log_summary_every;
+pub use gspice_utils::expression::profile;
+pub use gspice_utils::expression::sweep;
+#[cfg(feature = "rayon")]
+pub use gspice_utils::expression::sweep_par;
 
 pub fn add(left: usize, right: usize) -> usize {
     left + right
@@ -0,0 +1,32 @@
+//! The commonly used items in one import
+//!
+//! ```
+//! use gspice::prelude::*;
+//!
+//! let (x, x_ref) = Expression::tensor(vec![1.0, 2.0], true);
+//! let f = x.mul(&x).lt_sigmoid(&Expression::constant(2.0), 4.0);
+//! // the graph can be inspected from user code
+//! let Expression::Tensor(tensor) = &f else {
+//!     unreachable!()
+//! };
+//! let Op::DiscreteBinary(Expression::Tensor(lhs), _, CmpOp::Lt, CmpMethod::Sigmoid(_)) = tensor.op()
+//! else {
+//!     unreachable!()
+//! };
+//! assert!(matches!(lhs.op(), Op::Binary(_, _, BinaryOp::Mul)));
+//!
+//! let mut optimizer = Sgd::new(vec![x_ref], 0.1);
+//! f.value();
+//! optimizer.step(&f.backward());
+//! ```
+//!
+//! The comparisons' ops and gradient methods are also named [`CmpOp`] and [`CmpMethod`]
+
+pub use gspice_utils::expression::optimizer::{
+    Adam, Cosine, ExponentialDecay, Lbfgs, Optimizer, ReduceOnPlateau, Scheduler, Sgd,
+};
+pub use gspice_utils::expression::{
+    before_update, BinaryOp, DiscreteBinaryOp, DiscreteBinaryOp as CmpOp, Error, Expression, Grad,
+    GradId, GradMethod, GradMethod as CmpMethod, GradStore, Op, ScalarTensor, Tensor, TensorRef,
+    UnaryOp,
+};
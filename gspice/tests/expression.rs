@@ -0,0 +1,43 @@
+//! The expression graph is one definition shared by `gspice` and `gspice-utils`
+
+use gspice::{before_update, BinaryOp, Expression, Op, UnaryOp};
+
+#[test]
+fn same_definition_across_crates() {
+    let (x, x_ref) = Expression::tensor(vec![0.5, 1.5], true);
+    let f: gspice_utils::expression::Expression = x.mul(&x).sin();
+    let g: gspice::expression::Expression = f.clone();
+    let _: &gspice::prelude::Expression = &g;
+    let Expression::Tensor(tensor) = &g else {
+        panic!("expected a tensor");
+    };
+    let Op::Unary(Expression::Tensor(input), UnaryOp::Sin) = tensor.op() else {
+        panic!("expected sin, got {:?}", tensor.op());
+    };
+    assert!(matches!(input.op(), Op::Binary(_, _, BinaryOp::Mul)));
+
+    let grads = gspice_utils::expression::Expression::backward(&g);
+    let grad = grads.get(&x_ref).unwrap();
+    for (x, grad) in [0.5_f64, 1.5].iter().zip(grad.iter()) {
+        assert!((grad - 2.0 * x * (x * x).cos()).abs() < 1e-12);
+    }
+    before_update();
+    x_ref.assign(vec![1.0, 2.0]);
+    let values = g.value().to_tensor().unwrap();
+    assert!((values[0] - 1.0_f64.sin()).abs() < 1e-12);
+    assert!((values[1] - 4.0_f64.sin()).abs() < 1e-12);
+}
+
+#[test]
+fn fallible_surface_across_crates() {
+    let (x, _) = Expression::tensor(vec![1.0, 2.0, 3.0], false);
+    let (y, _) = Expression::tensor(vec![1.0, 2.0], false);
+    assert!(matches!(
+        x.try_add(&y),
+        Err(gspice::Error::LengthMismatch {
+            expected: 3,
+            got: 2,
+            ..
+        })
+    ));
+}
@@ -0,0 +1,15 @@
+#!/usr/bin/env sh
+# The pure-Rust crates build without pyo3, only the gspice-py bindings link against Python
+set -eu
+cd "$(dirname "$0")/.."
+status=0
+for crate in gspice gspice-utils; do
+    cargo build --quiet -p "$crate" --no-default-features
+    if cargo tree --quiet -p "$crate" --no-default-features --edges normal,build --prefix none \
+        | grep -q '^pyo3 '; then
+        echo "error: $crate depends on pyo3" >&2
+        status=1
+    fi
+done
+[ "$status" -eq 0 ] && echo "ok: no pyo3 in the pure-Rust crates"
+exit "$status"